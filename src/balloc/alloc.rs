@@ -8,9 +8,10 @@ use crate::{
     block_group::BlockGroup,
     error::{Error, ErrorKind, Result},
     fs::BlockGroupRef,
+    logging::ext4_log,
     superblock::Superblock,
+    types::Pblk,
 };
-use log::*;
 use super::{checksum::*, helpers::*};
 
 /// 块分配器状态
@@ -50,8 +51,11 @@ impl BlockAllocator {
         &mut self,
         bdev: &mut BlockDev<D>,
         sb: &mut Superblock,
-        goal: u64,
-    ) -> Result<u64> {
+        goal: impl Into<Pblk>,
+    ) -> Result<Pblk> {
+        sb.check_writable()?;
+        let goal = goal.into();
+
         // 计算目标块组
         let bg_id = get_bgid_of_block(sb, goal);
         let idx_in_bg = addr_to_idx_bg(sb, goal);
@@ -83,6 +87,9 @@ impl BlockAllocator {
             };
 
             if free_blocks > 0 {
+                if let Some(observer) = bdev.observer() {
+                    observer.on_alloc_retry(bgid);
+                }
                 // 计算此块组的起始索引
                 let first_in_bg = get_block_of_bgid(sb, bgid);
                 let idx_in_bg = addr_to_idx_bg(sb, first_in_bg);
@@ -107,17 +114,27 @@ impl BlockAllocator {
         sb: &mut Superblock,
         bgid: u32,
         mut idx_in_bg: u32,
-    ) -> Result<Option<u64>> {
-        // 获取此块组的块数
-        let blk_in_bg = sb.blocks_in_group_cnt(bgid);
+    ) -> Result<Option<Pblk>> {
+        // 获取此块组的块数（受挂载时的分配上限约束）
+        let blk_in_bg = alloc_limit_in_group(sb, bgid, sb.blocks_in_group_cnt(bgid));
 
         // 计算此块组的第一个有效索引
         let first_in_bg = get_block_of_bgid(sb, bgid);
         let first_in_bg_index = addr_to_idx_bg(sb, first_in_bg);
 
-        if idx_in_bg < first_in_bg_index {
+        if idx_in_bg < first_in_bg_index || idx_in_bg >= blk_in_bg {
             idx_in_bg = first_in_bg_index;
         }
+        if idx_in_bg >= blk_in_bg {
+            return Ok(None);
+        }
+
+        // 位图和块组描述符在块组锁内一起更新
+        let group_locks = sb.group_locks();
+        let _group = group_locks.lock(bgid);
+
+        // BLOCK_UNINIT 的块组先生成位图
+        super::uninit::init_block_bitmap(bdev, sb, bgid)?;
 
         // 第一步：获取位图地址和块组描述符副本
         let (bmp_blk_addr, bg_copy) = {
@@ -128,20 +145,16 @@ impl BlockAllocator {
         };
 
         // 第二步：操作位图
+        let mut new_csum = 0;
         let alloc_opt = {
+            check_bitmap_cached(bdev, sb, bmp_blk_addr, |bitmap_data| check_bitmap_csum(sb, &bg_copy, bitmap_data, bmp_blk_addr))?;
             let mut bitmap_block = Block::get(bdev, bmp_blk_addr)?;
 
             bitmap_block.with_data_mut(|bitmap_data| {
-                // 验证位图校验和
-                if !verify_bitmap_csum(sb, &bg_copy, bitmap_data) {
-                    // 记录警告但继续
-                }
-
                 // 1. 检查目标位置是否空闲
                 if !bitmap::test_bit(bitmap_data, idx_in_bg) {
                     set_bit(bitmap_data, idx_in_bg)?;
-                    let mut bg_for_csum = bg_copy;
-                    set_bitmap_csum(sb, &mut bg_for_csum, bitmap_data);
+                    new_csum = bitmap_csum(sb, bitmap_data);
                     return Ok::<_, Error>(Some(idx_in_bg));
                 }
 
@@ -154,8 +167,7 @@ impl BlockAllocator {
                 for tmp_idx in (idx_in_bg + 1)..end_idx {
                     if !bitmap::test_bit(bitmap_data, tmp_idx) {
                         set_bit(bitmap_data, tmp_idx)?;
-                        let mut bg_for_csum = bg_copy;
-                        set_bitmap_csum(sb, &mut bg_for_csum, bitmap_data);
+                        new_csum = bitmap_csum(sb, bitmap_data);
                         return Ok::<_, Error>(Some(tmp_idx));
                     }
                 }
@@ -163,8 +175,7 @@ impl BlockAllocator {
                 // 3. 在整个块组中查找
                 if let Some(rel_blk_idx) = find_first_zero(bitmap_data, idx_in_bg, blk_in_bg) {
                     set_bit(bitmap_data, rel_blk_idx)?;
-                    let mut bg_for_csum = bg_copy;
-                    set_bitmap_csum(sb, &mut bg_for_csum, bitmap_data);
+                    new_csum = bitmap_csum(sb, bitmap_data);
                     return Ok::<_, Error>(Some(rel_blk_idx));
                 }
 
@@ -178,10 +189,10 @@ impl BlockAllocator {
 
             // 🔧 验证分配的块号
             let device_total = bdev.total_blocks();
-            if alloc >= device_total {
-                log::error!(
+            if alloc.0 >= device_total {
+                ext4_log!(Balloc, Error,
                     "[try_alloc_in_group] INVALID block allocated: {:#x} (exceeds device total {}), idx={}, bgid={}",
-                    alloc, device_total, idx, bgid
+                    alloc.0, device_total, idx, bgid
                 );
                 return Err(Error::new(
                     ErrorKind::Corrupted,
@@ -189,15 +200,16 @@ impl BlockAllocator {
                 ));
             }
 
-            log::info!(
+            ext4_log!(Balloc, hot,
                 "[try_alloc_in_group] Allocated block: {:#x} (idx={}, bgid={})",
-                alloc, idx, bgid
+                alloc.0, idx, bgid
             );
 
             // 第三步：更新块组描述符
             {
                 let mut bg_ref = BlockGroupRef::get(bdev, sb, bgid)?;
                 bg_ref.dec_free_blocks(1)?;
+                bg_ref.set_block_bitmap_csum(new_csum)?;
                 // bg_ref 在此处自动释放并写回
             }
 
@@ -252,12 +264,27 @@ impl Default for BlockAllocator {
 pub fn try_alloc_block<D: BlockDevice>(
     bdev: &mut BlockDev<D>,
     sb: &mut Superblock,
-    baddr: u64,
+    baddr: impl Into<Pblk>,
 ) -> Result<bool> {
+    sb.check_writable()?;
+    let baddr = baddr.into();
+
+    // 超出挂载时的分配上限，视为不可用
+    if baddr.0 >= sb.alloc_limit() {
+        return Ok(false);
+    }
+
     // 计算块组和索引
     let block_group = get_bgid_of_block(sb, baddr);
     let index_in_group = addr_to_idx_bg(sb, baddr);
 
+    // 位图和块组描述符在块组锁内一起更新
+    let group_locks = sb.group_locks();
+    let _group = group_locks.lock(block_group);
+
+    // BLOCK_UNINIT 的块组先生成位图
+    super::uninit::init_block_bitmap(bdev, sb, block_group)?;
+
     // 第一步：获取位图地址和块组描述符副本
     let (bmp_blk_addr, bg_copy) = {
         let mut bg_ref = BlockGroupRef::get(bdev, sb, block_group)?;
@@ -267,23 +294,19 @@ pub fn try_alloc_block<D: BlockDevice>(
     };
 
     // 第二步：操作位图
+    let mut new_csum = 0;
     let is_free = {
+        check_bitmap_cached(bdev, sb, bmp_blk_addr, |bitmap_data| check_bitmap_csum(sb, &bg_copy, bitmap_data, bmp_blk_addr))?;
         let mut bitmap_block = Block::get(bdev, bmp_blk_addr)?;
 
         bitmap_block.with_data_mut(|bitmap_data| {
-            // 验证位图校验和
-            if !verify_bitmap_csum(sb, &bg_copy, bitmap_data) {
-                // 记录警告但继续
-            }
-
             // 检查块是否空闲
             let free = !bitmap::test_bit(bitmap_data, index_in_group);
 
             // 如果空闲，分配它
             if free {
                 set_bit(bitmap_data, index_in_group)?;
-                let mut bg_for_csum = bg_copy;
-                set_bitmap_csum(sb, &mut bg_for_csum, bitmap_data);
+                new_csum = bitmap_csum(sb, bitmap_data);
             }
 
             Ok::<_, Error>(free)
@@ -299,6 +322,7 @@ pub fn try_alloc_block<D: BlockDevice>(
     {
         let mut bg_ref = BlockGroupRef::get(bdev, sb, block_group)?;
         bg_ref.dec_free_blocks(1)?;
+        bg_ref.set_block_bitmap_csum(new_csum)?;
         // bg_ref 在此处自动释放并写回
     }
 
@@ -328,9 +352,9 @@ pub fn try_alloc_block<D: BlockDevice>(
 pub fn alloc_block<D: BlockDevice>(
     bdev: &mut BlockDev<D>,
     sb: &mut Superblock,
-) -> Result<u64> {
+) -> Result<Pblk> {
     let mut allocator = BlockAllocator::new();
-    let goal = sb.first_data_block() as u64;
+    let goal = Pblk(sb.first_data_block() as u64);
     allocator.alloc_block(bdev, sb, goal)
 }
 
@@ -349,13 +373,16 @@ pub fn alloc_block<D: BlockDevice>(
 ///
 /// # 注意
 ///
-/// 实际分配数可能小于 max_count（块组空间不足）
+/// 块组内没有 max_count 个连续空闲块时，分配其中最长的一段，
+/// 实际分配数可能小于 max_count
 pub fn alloc_blocks_in_group<D: BlockDevice>(
     bdev: &mut BlockDev<D>,
     sb: &mut Superblock,
-    goal: u64,
+    goal: impl Into<Pblk>,
     max_count: u32,
-) -> Result<(u64, u32)> {
+) -> Result<(Pblk, u32)> {
+    sb.check_writable()?;
+    let goal = goal.into();
     if max_count == 0 {
         return Err(Error::new(
             ErrorKind::InvalidInput,
@@ -372,6 +399,13 @@ pub fn alloc_blocks_in_group<D: BlockDevice>(
     let bgid = get_bgid_of_block(sb, goal);
     let idx_in_bg = addr_to_idx_bg(sb, goal);
 
+    // 位图和块组描述符在块组锁内一起更新
+    let group_locks = sb.group_locks();
+    let _group = group_locks.lock(bgid);
+
+    // BLOCK_UNINIT 的块组先生成位图
+    super::uninit::init_block_bitmap(bdev, sb, bgid)?;
+
     // 第一步：获取位图和块组信息
     let (bitmap_addr, bg_copy, blocks_in_bg) = {
         let mut bg_ref = BlockGroupRef::get(bdev, sb, bgid)?;
@@ -387,56 +421,42 @@ pub fn alloc_blocks_in_group<D: BlockDevice>(
 
         let bmp = bg_ref.block_bitmap()?;
         let bg_data = bg_ref.get_block_group_copy()?;
-        let blk_cnt = sb.blocks_in_group_cnt(bgid);
+        let blk_cnt = alloc_limit_in_group(sb, bgid, sb.blocks_in_group_cnt(bgid));
         (bmp, bg_data, blk_cnt)
     };
 
+    if idx_in_bg >= blocks_in_bg {
+        return Err(Error::new(
+            ErrorKind::NoSpace,
+            "Goal is beyond the allocation limit",
+        ));
+    }
+
     // 第二步：在位图中查找连续空闲块
+    let mut new_csum = 0;
     let (start_idx, alloc_count) = {
+        check_bitmap_cached(bdev, sb, bitmap_addr, |bitmap_data| check_bitmap_csum(sb, &bg_copy, bitmap_data, bitmap_addr))?;
         let mut bitmap_block = Block::get(bdev, bitmap_addr)?;
 
         bitmap_block.with_data_mut(|bitmap_data| {
-            // 验证校验和
-            if !verify_bitmap_csum(sb, &bg_copy, bitmap_data) {
-                // 警告但继续
-            }
-
-            // 查找连续空闲位
-            let result = bitmap::find_consecutive_zeros(
-                bitmap_data,
-                idx_in_bg,
-                blocks_in_bg,
-                max_count,
-            );
-
-            if let Some(start) = result {
-                // 实际分配的块数（可能小于请求的数量）
-                // 我们需要计算找到了多少连续空闲块
-                let mut count = 0u32;
-                for i in start..blocks_in_bg {
-                    if count >= max_count {
-                        break;
-                    }
-                    if !bitmap::test_bit(bitmap_data, i) {
-                        count += 1;
-                    } else {
-                        break;
+            // 从 goal 开始查找连续空闲位；没有足够长的段时取块组内最长的一段，
+            // 调用方对剩余部分再次分配
+            let mut result =
+                bitmap::find_longest_zeros(bitmap_data, idx_in_bg, blocks_in_bg, max_count);
+            if result.is_none_or(|(_, count)| count < max_count) {
+                if let Some(before) = bitmap::find_longest_zeros(bitmap_data, 0, idx_in_bg, max_count) {
+                    if result.is_none_or(|(_, count)| before.1 > count) {
+                        result = Some(before);
                     }
                 }
+            }
 
-                if count == 0 {
-                    return Err(Error::new(
-                        ErrorKind::NoSpace,
-                        "No consecutive blocks found",
-                    ));
-                }
-
+            if let Some((start, count)) = result {
                 // 设置位图位
                 bitmap::set_bits(bitmap_data, start, count)?;
 
                 // 更新校验和
-                let mut bg_for_csum = bg_copy;
-                set_bitmap_csum(sb, &mut bg_for_csum, bitmap_data);
+                new_csum = bitmap_csum(sb, bitmap_data);
 
                 Ok::<_, Error>((start, count))
             } else {
@@ -452,6 +472,7 @@ pub fn alloc_blocks_in_group<D: BlockDevice>(
     {
         let mut bg_ref = BlockGroupRef::get(bdev, sb, bgid)?;
         bg_ref.dec_free_blocks(alloc_count)?;
+        bg_ref.set_block_bitmap_csum(new_csum)?;
     }
 
     // 第四步：更新 superblock
@@ -493,14 +514,16 @@ pub fn alloc_blocks_in_group<D: BlockDevice>(
 pub fn alloc_blocks<D: BlockDevice>(
     bdev: &mut BlockDev<D>,
     sb: &mut Superblock,
-    goal: u64,
+    goal: impl Into<Pblk>,
     max_count: u32,
-) -> Result<(u64, u32)> {
+) -> Result<(Pblk, u32)> {
+    sb.check_writable()?;
+    let goal = goal.into();
     let device_total = bdev.total_blocks();
 
-    info!(
+    ext4_log!(Balloc, hot,
         "[BALLOC] Requesting {} blocks, goal={:#x}, device_total={}",
-        max_count, goal, device_total
+        max_count, goal.0, device_total
     );
 
     // 首先尝试在 goal 所在的块组中分配
@@ -509,10 +532,10 @@ pub fn alloc_blocks<D: BlockDevice>(
     // 如果成功，直接返回
     if let Ok((start_block, count)) = result {
         // 验证分配的块是否在设备范围内
-        if start_block + count as u64 > device_total {
-            error!(
+        if start_block.0 + count as u64 > device_total {
+            ext4_log!(Balloc, Error,
                 "[BALLOC] Allocated blocks OUT OF RANGE! start={:#x}, count={}, device_total={}",
-                start_block, count, device_total
+                start_block.0, count, device_total
             );
             return Err(Error::new(
                 ErrorKind::Corrupted,
@@ -520,9 +543,9 @@ pub fn alloc_blocks<D: BlockDevice>(
             ));
         }
 
-        info!(
+        ext4_log!(Balloc, hot,
             "[BALLOC] Allocated {} blocks: start={:#x}, end={:#x}",
-            count, start_block, start_block + count as u64 - 1
+            count, start_block.0, start_block.0 + count as u64 - 1
         );
         return Ok((start_block, count));
     }
@@ -541,16 +564,19 @@ pub fn alloc_blocks<D: BlockDevice>(
 
         // 计算该块组的第一个数据块作为新的 goal
         let blocks_per_group = sb.blocks_per_group();
-        let bg_first_block = first_data_block + (bgid as u64 * blocks_per_group as u64);
+        let bg_first_block = Pblk(first_data_block + (bgid as u64 * blocks_per_group as u64));
 
         // 尝试在这个块组中分配
+        if let Some(observer) = bdev.observer() {
+            observer.on_alloc_retry(bgid);
+        }
         match alloc_blocks_in_group(bdev, sb, bg_first_block, max_count) {
             Ok((start_block, count)) => {
                 // 验证分配的块是否在设备范围内
-                if start_block + count as u64 > device_total {
-                    error!(
+                if start_block.0 + count as u64 > device_total {
+                    ext4_log!(Balloc, Error,
                         "[BALLOC] Allocated blocks OUT OF RANGE (fallback)! start={:#x}, count={}, device_total={}",
-                        start_block, count, device_total
+                        start_block.0, count, device_total
                     );
                     return Err(Error::new(
                         ErrorKind::Corrupted,
@@ -558,9 +584,9 @@ pub fn alloc_blocks<D: BlockDevice>(
                     ));
                 }
 
-                info!(
+                ext4_log!(Balloc, hot,
                     "[BALLOC] Allocated {} blocks (fallback to bg {}): start={:#x}",
-                    count, bgid, start_block
+                    count, bgid, start_block.0
                 );
                 return Ok((start_block, count));
             }
@@ -9,6 +9,7 @@ use crate::{
     error::{Error, ErrorKind, Result},
     fs::BlockGroupRef,
     superblock::Superblock,
+    types::Pblk,
 };
 
 use super::{checksum::*, helpers::*};
@@ -33,11 +34,20 @@ use super::{checksum::*, helpers::*};
 pub fn free_block<D: BlockDevice>(
     bdev: &mut BlockDev<D>,
     sb: &mut Superblock,
-    baddr: u64,
+    baddr: impl Into<Pblk>,
 ) -> Result<()> {
+    sb.check_writable()?;
+    let baddr = baddr.into();
     let bg_id = get_bgid_of_block(sb, baddr);
     let index_in_group = addr_to_idx_bg(sb, baddr);
 
+    // 位图和块组描述符在块组锁内一起更新
+    let group_locks = sb.group_locks();
+    let _group = group_locks.lock(bg_id);
+
+    // BLOCK_UNINIT 的块组先生成位图
+    super::uninit::init_block_bitmap(bdev, sb, bg_id)?;
+
     // 第一步：获取位图地址和块组描述符副本
     let (bitmap_block_addr, bg_copy) = {
         let mut bg_ref = BlockGroupRef::get(bdev, sb, bg_id)?;
@@ -47,21 +57,17 @@ pub fn free_block<D: BlockDevice>(
     };
 
     // 第二步：操作位图
+    let mut new_csum = 0;
     {
+        check_bitmap_cached(bdev, sb, bitmap_block_addr, |bitmap_data| check_bitmap_csum(sb, &bg_copy, bitmap_data, bitmap_block_addr))?;
         let mut bitmap_block = Block::get(bdev, bitmap_block_addr)?;
 
         bitmap_block.with_data_mut(|bitmap_data| {
-            // 验证位图校验和（如果启用）
-            if !verify_bitmap_csum(sb, &bg_copy, bitmap_data) {
-                // 记录警告但继续操作
-            }
-
             // 清除位图中的位
             clear_bit(bitmap_data, index_in_group)?;
 
             // 更新位图校验和
-            let mut bg_for_csum = bg_copy;
-            set_bitmap_csum(sb, &mut bg_for_csum, bitmap_data);
+            new_csum = bitmap_csum(sb, bitmap_data);
 
             Ok::<_, Error>(())
         })??;
@@ -72,6 +78,7 @@ pub fn free_block<D: BlockDevice>(
     {
         let mut bg_ref = BlockGroupRef::get(bdev, sb, bg_id)?;
         bg_ref.inc_free_blocks(1)?;
+        bg_ref.set_block_bitmap_csum(new_csum)?;
         // bg_ref 在此处自动释放并写回
     }
 
@@ -105,9 +112,11 @@ pub fn free_block<D: BlockDevice>(
 pub fn free_blocks<D: BlockDevice>(
     bdev: &mut BlockDev<D>,
     sb: &mut Superblock,
-    first: u64,
+    first: impl Into<Pblk>,
     count: u32,
 ) -> Result<()> {
+    sb.check_writable()?;
+    let first = first.into();
     if count == 0 {
         return Ok(());
     }
@@ -117,7 +126,7 @@ pub fn free_blocks<D: BlockDevice>(
 
     // 计算第一个和最后一个块组
     let bg_first = get_bgid_of_block(sb, first);
-    let bg_last = get_bgid_of_block(sb, first + count as u64 - 1);
+    let bg_last = get_bgid_of_block(sb, first + (count as u64 - 1));
 
     // 逐块组释放
     for bg_id in bg_first..=bg_last {
@@ -133,6 +142,13 @@ pub fn free_blocks<D: BlockDevice>(
             free_cnt = remaining;
         }
 
+        // 位图和块组描述符在块组锁内一起更新
+        let group_locks = sb.group_locks();
+        let _group = group_locks.lock(bg_id);
+
+        // BLOCK_UNINIT 的块组先生成位图
+        super::uninit::init_block_bitmap(bdev, sb, bg_id)?;
+
         // 第一步：获取位图地址和块组描述符副本
         let (bitmap_blk, bg_copy) = {
             let mut bg_ref = BlockGroupRef::get(bdev, sb, bg_id)?;
@@ -142,21 +158,17 @@ pub fn free_blocks<D: BlockDevice>(
         };
 
         // 第二步：操作位图
+        let mut new_csum = 0;
         {
+            check_bitmap_cached(bdev, sb, bitmap_blk, |bitmap_data| check_bitmap_csum(sb, &bg_copy, bitmap_data, bitmap_blk))?;
             let mut bitmap_block = Block::get(bdev, bitmap_blk)?;
 
             bitmap_block.with_data_mut(|bitmap_data| {
-                // 验证位图校验和（如果启用）
-                if !verify_bitmap_csum(sb, &bg_copy, bitmap_data) {
-                    // 记录警告但继续操作
-                }
-
                 // 清除位图中的多个位
                 clear_bits(bitmap_data, idx_in_bg_first, free_cnt)?;
 
                 // 更新位图校验和
-                let mut bg_for_csum = bg_copy;
-                set_bitmap_csum(sb, &mut bg_for_csum, bitmap_data);
+                new_csum = bitmap_csum(sb, bitmap_data);
 
                 Ok::<_, Error>(())
             })??;
@@ -167,6 +179,7 @@ pub fn free_blocks<D: BlockDevice>(
         {
             let mut bg_ref = BlockGroupRef::get(bdev, sb, bg_id)?;
             bg_ref.inc_free_blocks(free_cnt)?;
+            bg_ref.set_block_bitmap_csum(new_csum)?;
             // bg_ref 在此处自动释放并写回
         }
 
@@ -8,6 +8,7 @@ use crate::{
     error::Result,
     fs::{BlockGroupRef, InodeRef},
     superblock::Superblock,
+    types::Pblk,
 };
 
 use super::{alloc::*, free::*};
@@ -30,7 +31,7 @@ pub fn free_block_with_inode<D: BlockDevice>(
     bdev: &mut BlockDev<D>,
     sb: &mut Superblock,
     inode_ref: &mut InodeRef<D>,
-    baddr: u64,
+    baddr: impl Into<Pblk>,
 ) -> Result<()> {
     // 先释放块
     free_block(bdev, sb, baddr)?;
@@ -60,7 +61,7 @@ pub fn free_blocks_with_inode<D: BlockDevice>(
     bdev: &mut BlockDev<D>,
     sb: &mut Superblock,
     inode_ref: &mut InodeRef<D>,
-    first: u64,
+    first: impl Into<Pblk>,
     count: u32,
 ) -> Result<()> {
     // 先释放块
@@ -92,8 +93,8 @@ pub fn alloc_block_with_inode<D: BlockDevice>(
     bdev: &mut BlockDev<D>,
     sb: &mut Superblock,
     inode_ref: &mut InodeRef<D>,
-    goal: u64,
-) -> Result<u64> {
+    goal: impl Into<Pblk>,
+) -> Result<Pblk> {
     // 分配块
     let baddr = allocator.alloc_block(bdev, sb, goal)?;
 
@@ -119,7 +120,7 @@ pub fn try_alloc_block_with_inode<D: BlockDevice>(
     bdev: &mut BlockDev<D>,
     sb: &mut Superblock,
     inode_ref: &mut InodeRef<D>,
-    baddr: u64,
+    baddr: impl Into<Pblk>,
 ) -> Result<bool> {
     // 尝试分配块
     let allocated = try_alloc_block(bdev, sb, baddr)?;
@@ -1,6 +1,6 @@
 //! 块分配辅助函数
 
-use crate::superblock::Superblock;
+use crate::{superblock::Superblock, types::Pblk};
 
 /// 从块地址计算块组 ID
 ///
@@ -14,7 +14,8 @@ use crate::superblock::Superblock;
 /// # 返回
 ///
 /// 块组索引
-pub fn get_bgid_of_block(sb: &Superblock, mut baddr: u64) -> u32 {
+pub fn get_bgid_of_block(sb: &Superblock, baddr: impl Into<Pblk>) -> u32 {
+    let mut baddr = baddr.into().0;
     if sb.first_data_block() != 0 && baddr != 0 {
         baddr -= 1;
     }
@@ -34,7 +35,7 @@ pub fn get_bgid_of_block(sb: &Superblock, mut baddr: u64) -> u32 {
 /// # 返回
 ///
 /// 块地址
-pub fn get_block_of_bgid(sb: &Superblock, bgid: u32) -> u64 {
+pub fn get_block_of_bgid(sb: &Superblock, bgid: u32) -> Pblk {
     let mut baddr = 0u64;
 
     if sb.first_data_block() != 0 {
@@ -42,7 +43,7 @@ pub fn get_block_of_bgid(sb: &Superblock, bgid: u32) -> u64 {
     }
 
     baddr += bgid as u64 * sb.blocks_per_group() as u64;
-    baddr
+    Pblk(baddr)
 }
 
 /// 从块组内索引和块组 ID 计算绝对块地址
@@ -58,7 +59,7 @@ pub fn get_block_of_bgid(sb: &Superblock, bgid: u32) -> u64 {
 /// # 返回
 ///
 /// 绝对块地址
-pub fn bg_idx_to_addr(sb: &Superblock, idx_in_bg: u32, bgid: u32) -> u64 {
+pub fn bg_idx_to_addr(sb: &Superblock, idx_in_bg: u32, bgid: u32) -> Pblk {
     let bg_first = get_block_of_bgid(sb, bgid);
     bg_first + idx_in_bg as u64
 }
@@ -75,7 +76,8 @@ pub fn bg_idx_to_addr(sb: &Superblock, idx_in_bg: u32, bgid: u32) -> u64 {
 /// # 返回
 ///
 /// 块组内索引
-pub fn addr_to_idx_bg(sb: &Superblock, mut baddr: u64) -> u32 {
+pub fn addr_to_idx_bg(sb: &Superblock, baddr: impl Into<Pblk>) -> u32 {
+    let mut baddr = baddr.into().0;
     if sb.first_data_block() != 0 && baddr != 0 {
         baddr -= 1;
     }
@@ -83,6 +85,24 @@ pub fn addr_to_idx_bg(sb: &Superblock, mut baddr: u64) -> u32 {
     (baddr % sb.blocks_per_group() as u64) as u32
 }
 
+/// 计算块组内允许分配的块索引上界（不含）
+///
+/// 受 [`Superblock::alloc_limit`] 限制：块组完全位于上限之外时返回 0
+///
+/// # 参数
+///
+/// * `sb` - superblock 引用
+/// * `bgid` - 块组 ID
+/// * `blocks_in_bg` - 块组内的块数
+pub fn alloc_limit_in_group(sb: &Superblock, bgid: u32, blocks_in_bg: u32) -> u32 {
+    let bg_first = get_block_of_bgid(sb, bgid);
+    let limit = sb.alloc_limit();
+    if limit <= bg_first.0 {
+        return 0;
+    }
+    (limit - bg_first.0).min(blocks_in_bg as u64) as u32
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -110,9 +130,9 @@ mod tests {
         sb.blocks_per_group = 8192u32.to_le();
         let superblock = Superblock::new(sb);
 
-        assert_eq!(get_block_of_bgid(&superblock, 0), 0);
-        assert_eq!(get_block_of_bgid(&superblock, 1), 8192);
-        assert_eq!(get_block_of_bgid(&superblock, 2), 16384);
+        assert_eq!(get_block_of_bgid(&superblock, 0), Pblk(0));
+        assert_eq!(get_block_of_bgid(&superblock, 1), Pblk(8192));
+        assert_eq!(get_block_of_bgid(&superblock, 2), Pblk(16384));
     }
 
     #[test]
@@ -128,7 +148,7 @@ mod tests {
         let idx_in_bg = 100u32;
 
         let addr = bg_idx_to_addr(&superblock, idx_in_bg, bgid);
-        assert_eq!(addr, 8192 + 100);
+        assert_eq!(addr, Pblk(8192 + 100));
 
         let recovered_idx = addr_to_idx_bg(&superblock, addr);
         assert_eq!(recovered_idx, idx_in_bg);
@@ -136,4 +156,28 @@ mod tests {
         let recovered_bgid = get_bgid_of_block(&superblock, addr);
         assert_eq!(recovered_bgid, bgid);
     }
+
+    #[test]
+    fn test_alloc_limit_in_group() {
+        let sb = ext4_sblock {
+            magic: EXT4_SUPERBLOCK_MAGIC.to_le(),
+            first_data_block: 0u32.to_le(),
+            blocks_per_group: 8192u32.to_le(),
+            blocks_count_lo: 32768u32.to_le(),
+            ..Default::default()
+        };
+        let mut superblock = Superblock::new(sb);
+
+        assert_eq!(alloc_limit_in_group(&superblock, 3, 8192), 8192);
+
+        superblock.set_max_blocks(Some(10000));
+        assert_eq!(superblock.alloc_limit(), 10000);
+        assert_eq!(alloc_limit_in_group(&superblock, 0, 8192), 8192);
+        assert_eq!(alloc_limit_in_group(&superblock, 1, 8192), 10000 - 8192);
+        assert_eq!(alloc_limit_in_group(&superblock, 2, 8192), 0);
+
+        // 上限大于总块数时以总块数为准
+        superblock.set_max_blocks(Some(1 << 40));
+        assert_eq!(superblock.alloc_limit(), 32768);
+    }
 }
@@ -7,9 +7,13 @@ pub mod checksum;
 pub mod free;
 pub mod alloc;
 pub mod fs_integration;
+pub mod reserve;
+pub mod uninit;
 
 pub use helpers::*;
 pub use checksum::*;
 pub use free::*;
 pub use alloc::*;
 pub use fs_integration::*;
+pub use reserve::*;
+pub use uninit::*;
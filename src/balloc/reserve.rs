@@ -0,0 +1,69 @@
+//! 延迟分配的块预留
+//!
+//! 延迟分配的写入不会立即分配物理块，只在 superblock 的运行时计数中
+//! 预留相应数量的块，保证之后刷新时有足够的空闲块。
+//! 预留计数不写入磁盘，也不影响位图。
+//!
+//! # 注意
+//!
+//! 普通（非延迟）分配不检查预留，因此预留只保证预留时刻的可用空间；
+//! 刷新时仍可能返回 `NoSpace`。
+//!
+//! 为特权属主保留的块（`s_r_blocks_count`）由 [`check_free_blocks`] 在
+//! 分配前按文件属主检查。
+
+use crate::{
+    error::{Error, ErrorKind, Result},
+    superblock::Superblock,
+};
+
+/// 未被预留的空闲块数
+///
+/// 与 `Ext4FileSystem::stats` 相同，分配上限之外的块从空闲数中扣除
+pub fn unreserved_free_blocks(sb: &Superblock) -> u64 {
+    let beyond_limit = sb.blocks_count() - sb.alloc_limit();
+    sb.free_blocks_count()
+        .saturating_sub(beyond_limit)
+        .saturating_sub(sb.reserved_blocks())
+}
+
+/// 检查属主为 `uid`/`gid` 的文件能否再分配 `count` 个块
+///
+/// 非特权属主（见 [`Superblock::can_use_reserved`]）不能使用
+/// [`Superblock::root_reserved_blocks`] 个保留块；特权属主不受限制，
+/// 空闲块不足时由分配器返回错误。
+///
+/// # 错误
+///
+/// - `ErrorKind::NoSpace` - 扣除保留块后的空闲块不足
+pub fn check_free_blocks(sb: &Superblock, count: u64, uid: u32, gid: u32) -> Result<()> {
+    if sb.can_use_reserved(uid, gid) {
+        return Ok(());
+    }
+    if unreserved_free_blocks(sb) < count.saturating_add(sb.root_reserved_blocks()) {
+        return Err(Error::new(
+            ErrorKind::NoSpace,
+            "Not enough free blocks outside the reserved pool",
+        ));
+    }
+    Ok(())
+}
+
+/// 预留 `count` 个块
+///
+/// # 错误
+///
+/// - `ErrorKind::NoSpace` - 未被预留的空闲块不足
+pub fn reserve_blocks(sb: &mut Superblock, count: u64) -> Result<()> {
+    if unreserved_free_blocks(sb) < count {
+        return Err(Error::new(ErrorKind::NoSpace, "Not enough free blocks to reserve"));
+    }
+    sb.set_reserved_blocks(sb.reserved_blocks() + count);
+    Ok(())
+}
+
+/// 释放 `count` 个预留块（实际分配前或丢弃数据时调用）
+pub fn release_reserved_blocks(sb: &mut Superblock, count: u64) {
+    debug_assert!(sb.reserved_blocks() >= count, "releasing more blocks than reserved");
+    sb.set_reserved_blocks(sb.reserved_blocks().saturating_sub(count));
+}
@@ -0,0 +1,162 @@
+//! 未初始化块组（`EXT4_BG_BLOCK_UNINIT`）的块位图
+//!
+//! 启用 `uninit_bg`（`GDT_CSUM`）或 `metadata_csum` 时，mke2fs 不为空的块组写位图，
+//! 只在描述符中打上 `BLOCK_UNINIT` 标志，这样的块组除元数据外全部空闲。
+//! 分配器第一次在这样的块组中分配或释放块时才按元数据布局生成位图并清除标志，
+//! 挂载和其他只读操作不需要读取这些位图。
+
+use crate::{
+    bitmap,
+    block::{Block, BlockDev, BlockDevice},
+    block_group::BlockGroup,
+    consts::*,
+    error::Result,
+    logging::ext4_log,
+    superblock::Superblock,
+};
+
+use super::{checksum::set_bitmap_csum, helpers::get_block_of_bgid};
+
+/// 块组 `bgid` 带 `BLOCK_UNINIT` 标志时生成并写入它的块位图
+///
+/// 位图中标记 superblock 备份、GDT、保留 GDT 块，以及位于本块组内的
+/// 任意块组的位图和 inode 表（`flex_bg` 时可能属于其他块组），
+/// 块组末尾之后的填充位也置 1。随后清除标志并按位图更新空闲块数。
+///
+/// 返回是否进行了初始化
+pub fn init_block_bitmap<D: BlockDevice>(
+    bdev: &mut BlockDev<D>,
+    sb: &Superblock,
+    bgid: u32,
+) -> Result<bool> {
+    let mut bg = BlockGroup::load(bdev, sb, bgid)?;
+    if !bg.has_flag(EXT4_BG_BLOCK_UNINIT) {
+        return Ok(false);
+    }
+
+    let block_size = sb.block_size();
+    let start = get_block_of_bgid(sb, bgid).0;
+    let count = sb.blocks_in_group_cnt(bgid);
+    let end = start + count as u64;
+    let mut bitmap = alloc::vec![0u8; block_size as usize];
+    bitmap::set_bits(&mut bitmap, 0, sb.num_base_meta_clusters(bgid))?;
+
+    let itable_blocks = (sb.inodes_per_group() * sb.inode_size() as u32).div_ceil(block_size) as u64;
+    for group in 0..sb.block_group_count() {
+        let loaded;
+        let other = if group == bgid {
+            &bg
+        } else {
+            loaded = BlockGroup::load(bdev, sb, group)?;
+            &loaded
+        };
+        let ranges = [
+            (other.get_block_bitmap(sb), 1),
+            (other.get_inode_bitmap(sb), 1),
+            (other.get_inode_table_first_block(sb), itable_blocks),
+        ];
+        for (first, len) in ranges {
+            let from = first.max(start);
+            let to = (first + len).min(end);
+            if from < to {
+                bitmap::set_bits(&mut bitmap, (from - start) as u32, (to - from) as u32)?;
+            }
+        }
+    }
+    bitmap::set_bits(&mut bitmap, count, block_size * 8 - count)?;
+
+    // 位图块从未写过，不需要读出旧内容
+    let bitmap_addr = bg.get_block_bitmap(sb);
+    Block::get_noread(bdev, bitmap_addr)?.with_data_mut(|data| data.copy_from_slice(&bitmap))?;
+
+    bg.clear_flag(EXT4_BG_BLOCK_UNINIT);
+    bg.set_free_blocks_count(sb, bitmap::count_zeros(&bitmap, 0, count));
+    set_bitmap_csum(sb, bg.inner_mut(), &bitmap);
+    bg.update_checksum(sb);
+    bg.write(bdev, sb)?;
+    ext4_log!(Balloc, Debug, "[BALLOC] Initialized block bitmap of uninit group {bgid}");
+    Ok(true)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{error::Result, types::{ext4_group_desc, ext4_sblock}};
+
+    struct MemDevice {
+        storage: alloc::vec::Vec<u8>,
+    }
+
+    impl BlockDevice for MemDevice {
+        fn block_size(&self) -> u32 {
+            4096
+        }
+
+        fn sector_size(&self) -> u32 {
+            512
+        }
+
+        fn total_blocks(&self) -> u64 {
+            (self.storage.len() / 4096) as u64
+        }
+
+        fn read_blocks(&mut self, lba: u64, count: u32, buf: &mut [u8]) -> Result<usize> {
+            let start = lba as usize * 512;
+            let len = count as usize * 512;
+            buf[..len].copy_from_slice(&self.storage[start..start + len]);
+            Ok(len)
+        }
+
+        fn write_blocks(&mut self, lba: u64, count: u32, buf: &[u8]) -> Result<usize> {
+            let start = lba as usize * 512;
+            let len = count as usize * 512;
+            self.storage[start..start + len].copy_from_slice(&buf[..len]);
+            Ok(len)
+        }
+    }
+
+    #[test]
+    fn test_init_block_bitmap() {
+        // 两个 64 块的块组；块组 1 有 superblock 备份和 1 个 GDT 块，
+        // 它的位图在块组 1 内，inode 表（2 块）放在块组 0（类似 flex_bg）
+        let sb = Superblock::new(ext4_sblock {
+            log_block_size: 2u32.to_le(),
+            log_cluster_size: 2u32.to_le(),
+            blocks_per_group: 64u32.to_le(),
+            inodes_per_group: 32u32.to_le(),
+            inode_size: 256u16.to_le(),
+            blocks_count_lo: 128u32.to_le(),
+            inodes_count: 64u32.to_le(),
+            feature_ro_compat: EXT4_FEATURE_RO_COMPAT_SPARSE_SUPER.to_le(),
+            ..Default::default()
+        });
+        let mut bdev = BlockDev::new(MemDevice { storage: alloc::vec![0u8; 128 * 4096] }).unwrap();
+
+        let desc = |block_bitmap: u32, inode_bitmap: u32, itable: u32, flags: u16| {
+            let mut bg = BlockGroup::new(0, ext4_group_desc::default());
+            bg.set_block_bitmap(&sb, block_bitmap as u64);
+            bg.set_inode_bitmap(&sb, inode_bitmap as u64);
+            bg.set_inode_table_first_block(&sb, itable as u64);
+            bg.set_flag(flags);
+            *bg.inner()
+        };
+        crate::block_group::write_block_group_desc(&mut bdev, &sb, 0, &desc(2, 3, 4, 0)).unwrap();
+        crate::block_group::write_block_group_desc(&mut bdev, &sb, 1, &desc(66, 67, 6, EXT4_BG_BLOCK_UNINIT))
+            .unwrap();
+
+        assert!(!init_block_bitmap(&mut bdev, &sb, 0).unwrap());
+        assert!(init_block_bitmap(&mut bdev, &sb, 1).unwrap());
+        assert!(!init_block_bitmap(&mut bdev, &sb, 1).unwrap());
+
+        let bg = BlockGroup::load(&mut bdev, &sb, 1).unwrap();
+        assert!(!bg.has_flag(EXT4_BG_BLOCK_UNINIT));
+        // superblock 备份、GDT 和两个位图
+        assert_eq!(bg.get_free_blocks_count(&sb), 60);
+
+        let bitmap = Block::get(&mut bdev, 66u64).unwrap().with_data(|data| data.to_vec()).unwrap();
+        assert_eq!(bitmap::count_ones(&bitmap, 0, 64), 4);
+        assert!((0..4).all(|i| bitmap::test_bit(&bitmap, i)));
+        // 块组末尾之后的填充位
+        assert_eq!(bitmap::count_zeros(&bitmap, 64, 4096 * 8), 0);
+    }
+}
@@ -256,6 +256,40 @@ pub fn find_consecutive_zeros(bitmap: &[u8], start: u32, end: u32, count: u32) -
     None
 }
 
+/// 查找位图中最长的连续空闲段，最多 `max_count` 位
+///
+/// 找到长度达到 `max_count` 的段时立即返回；否则返回 `[start, end)` 中最长的段，
+/// 长度相同时取靠前的。
+///
+/// # 返回
+///
+/// `(起始索引, 长度)`，没有任何空闲位时返回 None
+pub fn find_longest_zeros(bitmap: &[u8], start: u32, end: u32, max_count: u32) -> Option<(u32, u32)> {
+    let end = end.min((bitmap.len() * 8) as u32);
+    let mut best: Option<(u32, u32)> = None;
+    let mut run_start = start;
+    let mut run_len = 0u32;
+
+    for i in start..end {
+        if test_bit(bitmap, i) {
+            run_len = 0;
+            continue;
+        }
+        if run_len == 0 {
+            run_start = i;
+        }
+        run_len += 1;
+        if best.is_none_or(|(_, len)| run_len > len) {
+            best = Some((run_start, run_len));
+        }
+        if run_len >= max_count {
+            break;
+        }
+    }
+
+    best
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -374,6 +408,26 @@ mod tests {
         assert_eq!(find_consecutive_zeros(&bitmap, 0, 32, 5), Some(8));
     }
 
+    #[test]
+    fn test_find_longest_zeros() {
+        let mut bitmap = [0xffu8; 4];
+        assert_eq!(find_longest_zeros(&bitmap, 0, 32, 4), None);
+
+        // 空闲段：2-3、10-14、20-22
+        clear_bits(&mut bitmap, 2, 2).unwrap();
+        clear_bits(&mut bitmap, 10, 5).unwrap();
+        clear_bits(&mut bitmap, 20, 3).unwrap();
+
+        // 没有足够长的段时返回最长的
+        assert_eq!(find_longest_zeros(&bitmap, 0, 32, 8), Some((10, 5)));
+        // 第一个满足长度的段，截断到 max_count
+        assert_eq!(find_longest_zeros(&bitmap, 0, 32, 2), Some((2, 2)));
+        assert_eq!(find_longest_zeros(&bitmap, 0, 32, 3), Some((10, 3)));
+        // 只在 [start, end) 内查找
+        assert_eq!(find_longest_zeros(&bitmap, 12, 32, 8), Some((12, 3)));
+        assert_eq!(find_longest_zeros(&bitmap, 0, 12, 8), Some((2, 2)));
+    }
+
     #[test]
     fn test_out_of_range() {
         let mut bitmap = [0u8; 4]; // 32 bits
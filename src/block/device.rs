@@ -1,8 +1,11 @@
 //! 块设备核心类型
 
 use crate::error::{Error, ErrorKind, Result};
+use crate::types::{ByteOff, Pblk};
 use alloc::vec;
 
+use super::{heatmap::WriteHeatMap, observer::FsObserver};
+
 /// 块设备接口
 ///
 /// 实现此 trait 以提供底层块设备访问。
@@ -44,7 +47,10 @@ pub trait BlockDevice {
     /// 逻辑块大小（通常 4096）
     fn block_size(&self) -> u32;
 
-    /// 物理扇区大小（通常 512）
+    /// 物理扇区大小（通常 512，4Kn 设备为 4096）
+    ///
+    /// 读写都以扇区为单位；文件系统块小于扇区或分区偏移不对齐时，
+    /// [`BlockDev`] 按扇区读-改-写，设备实现不需要处理不对齐的访问
     fn sector_size(&self) -> u32;
 
     /// 总块数
@@ -52,6 +58,9 @@ pub trait BlockDevice {
 
     /// 读取扇区
     ///
+    /// 文件系统会把物理连续的多个块合并成一次调用（`count` 可能覆盖
+    /// 数百个块），支持 DMA 的后端可以直接发出单个大请求。
+    ///
     /// # 参数
     ///
     /// * `lba` - 逻辑块地址（以扇区为单位）
@@ -65,6 +74,8 @@ pub trait BlockDevice {
 
     /// 写入扇区
     ///
+    /// 与 [`read_blocks`](Self::read_blocks) 相同，`count` 可能覆盖多个连续块
+    ///
     /// # 参数
     ///
     /// * `lba` - 逻辑块地址（以扇区为单位）
@@ -81,6 +92,19 @@ pub trait BlockDevice {
         Ok(())
     }
 
+    /// 写屏障
+    ///
+    /// 返回时，调用前已完成的所有 [`write_blocks`](Self::write_blocks) 都必须先于
+    /// 调用后发出的写入落盘。文件系统在日志的描述符/数据块与提交块之间、
+    /// 检查点写回与推进日志起点之间、以及卸载时写 superblock 之前调用它。
+    ///
+    /// 带易失性写缓存的后端必须实现此语义（例如发出 FLUSH 命令或 `fsync`）。
+    /// 默认实现调用 [`flush`](Self::flush)；能只保证顺序而不等待持久化的后端
+    /// （如支持有序标签队列的设备）可以覆盖为更轻量的实现。
+    fn flush_barrier(&mut self) -> Result<()> {
+        self.flush()
+    }
+
     /// 是否只读
     fn is_read_only(&self) -> bool {
         false
@@ -151,6 +175,8 @@ pub trait BlockDevice {
 pub struct BlockDev<D: BlockDevice> {
     /// 底层设备
     device: D,
+    /// 逻辑块大小（字节），初始为设备块大小，见 [`set_block_size`](Self::set_block_size)
+    block_size: u32,
     /// 分区偏移（字节）
     partition_offset: u64,
     /// 分区大小（字节）
@@ -167,6 +193,14 @@ pub struct BlockDev<D: BlockDevice> {
     ref_count: u32,
     /// 块缓存（可选）
     pub(super) bcache: Option<crate::cache::BlockCache>,
+    /// 写入热度图（可选）
+    heatmap: Option<WriteHeatMap>,
+    /// 观测者（可选），见 [`set_observer`](Self::set_observer)
+    observer: Option<&'static dyn FsObserver>,
+    /// 块组描述符和位图缓存
+    pub(super) meta: crate::cache::MetaCache,
+    /// 只读模式，见 [`set_read_only`](Self::set_read_only)
+    read_only: bool,
 }
 
 impl<D: BlockDevice> BlockDev<D> {
@@ -175,11 +209,11 @@ impl<D: BlockDevice> BlockDev<D> {
         let block_size = device.block_size();
         let sector_size = device.sector_size();
 
-        // 验证块大小是扇区大小的整数倍
-        if block_size % sector_size != 0 {
+        // 块大小与扇区大小不必整除，未对齐的访问由 device_read/device_write 处理
+        if block_size == 0 || sector_size == 0 {
             return Err(Error::new(
                 ErrorKind::InvalidInput,
-                "Block size must be a multiple of sector size",
+                "Block size and sector size must be non-zero",
             ));
         }
 
@@ -188,6 +222,7 @@ impl<D: BlockDevice> BlockDev<D> {
 
         Ok(Self {
             device,
+            block_size,
             partition_offset: 0,
             partition_size,
             read_count: 0,
@@ -196,6 +231,10 @@ impl<D: BlockDevice> BlockDev<D> {
             physical_write_count: 0,
             ref_count: 0,
             bcache: None,
+            heatmap: None,
+            observer: None,
+            meta: crate::cache::MetaCache::new(),
+            read_only: false,
         })
     }
 
@@ -263,7 +302,7 @@ impl<D: BlockDevice> BlockDev<D> {
 
     /// 获取逻辑块大小
     pub fn block_size(&self) -> u32 {
-        self.device.block_size()
+        self.block_size
     }
 
     /// 获取物理扇区大小
@@ -271,9 +310,9 @@ impl<D: BlockDevice> BlockDev<D> {
         self.device.sector_size()
     }
 
-    /// 获取总块数
+    /// 获取总块数（逻辑块）
     pub fn total_blocks(&self) -> u64 {
-        self.device.total_blocks()
+        self.partition_size / self.block_size as u64
     }
 
     /// 获取逻辑读取次数（包括缓存命中）
@@ -311,6 +350,47 @@ impl<D: BlockDevice> BlockDev<D> {
         hits as f64 / self.read_count as f64
     }
 
+    /// 启用写入热度图
+    ///
+    /// 之后每个实际写到设备上的块都按区域计数，见 [`WriteHeatMap`]。
+    /// 已启用时会重新开始计数。
+    ///
+    /// # 参数
+    ///
+    /// * `first_block` - 第一个区域的起始逻辑块
+    /// * `blocks_per_region` - 每个区域的逻辑块数
+    /// * `sample_every` - 采样间隔（每多少个块记录一次）
+    pub fn enable_write_heatmap(&mut self, first_block: u64, blocks_per_region: u64, sample_every: u32) {
+        self.heatmap = Some(WriteHeatMap::new(first_block, blocks_per_region, sample_every));
+    }
+
+    /// 停用写入热度图并返回已收集的数据
+    pub fn disable_write_heatmap(&mut self) -> Option<WriteHeatMap> {
+        self.heatmap.take()
+    }
+
+    /// 获取写入热度图，未启用时返回 `None`
+    pub fn write_heatmap(&self) -> Option<&WriteHeatMap> {
+        self.heatmap.as_ref()
+    }
+
+    /// 获取可变的写入热度图（用于清零等）
+    pub fn write_heatmap_mut(&mut self) -> Option<&mut WriteHeatMap> {
+        self.heatmap.as_mut()
+    }
+
+    /// 设置观测者，`None` 取消
+    ///
+    /// 之后的设备读写、块缓存命中/未命中等事件回调到 `observer`，见 [`FsObserver`]
+    pub fn set_observer(&mut self, observer: Option<&'static dyn FsObserver>) {
+        self.observer = observer;
+    }
+
+    /// 当前的观测者
+    pub fn observer(&self) -> Option<&'static dyn FsObserver> {
+        self.observer
+    }
+
     /// 设置分区偏移和大小
     ///
     /// # 参数
@@ -320,6 +400,36 @@ impl<D: BlockDevice> BlockDev<D> {
     pub fn set_partition(&mut self, offset: u64, size: u64) {
         self.partition_offset = offset;
         self.partition_size = size;
+        self.meta.clear();
+    }
+
+    /// 设置逻辑块大小
+    ///
+    /// 挂载时把逻辑块大小切换为文件系统的块大小，使 1 KiB / 2 KiB 块的文件系统
+    /// 可以位于块大小为 4096 的设备上（反之亦然）。块大小可以小于扇区大小
+    /// （4Kn 设备上的 1K 块文件系统），此时写入按扇区读-改-写。
+    /// 切换前写回缓存中的脏块，然后清空块缓存和元数据缓存。
+    ///
+    /// # 错误
+    ///
+    /// - `ErrorKind::InvalidInput` - 块大小不是 1024..=65536 之间的 2 的幂
+    /// - 写回脏块失败时返回相应的错误
+    pub fn set_block_size(&mut self, block_size: u32) -> Result<()> {
+        if block_size == self.block_size {
+            return Ok(());
+        }
+        if !block_size.is_power_of_two() || !(1024..=65536).contains(&block_size) {
+            return Err(Error::new(ErrorKind::InvalidInput, "Unsupported block size"));
+        }
+
+        self.write_back_dirty()?;
+        self.meta.clear();
+        if let Some(cache) = &mut self.bcache {
+            cache.unpin_all();
+            cache.set_block_size(block_size as usize);
+        }
+        self.block_size = block_size;
+        Ok(())
     }
 
     /// 获取分区偏移
@@ -332,18 +442,84 @@ impl<D: BlockDevice> BlockDev<D> {
         self.partition_size
     }
 
+    /// 设置只读模式
+    ///
+    /// 打开后写入块（[`write_block`](Self::write_block)、
+    /// [`Block::with_data_mut`](super::Block::with_data_mut) 等）返回
+    /// `ErrorKind::ReadOnlyFs`，缓存中不会出现脏块。只读挂载时由文件系统设置
+    pub fn set_read_only(&mut self, read_only: bool) {
+        self.read_only = read_only;
+    }
+
+    /// 是否处于只读模式（包括底层设备本身只读）
+    pub fn is_read_only(&self) -> bool {
+        self.read_only || self.device.is_read_only()
+    }
+
+    /// 只读模式下返回 `ErrorKind::ReadOnlyFs`
+    pub(super) fn check_writable(&self) -> Result<()> {
+        if self.is_read_only() {
+            return Err(Error::new(ErrorKind::ReadOnlyFs, "Block device is read-only"));
+        }
+        Ok(())
+    }
+
     // 内部辅助方法
 
-    /// 将逻辑块地址转换为物理扇区地址
-    pub(super) fn logical_to_physical(&self, lba: u64) -> u64 {
-        let block_size = self.device.block_size() as u64;
+    /// 逻辑块范围 `[lba, lba + count)` 对应的扇区范围
+    ///
+    /// 返回（起始扇区, 扇区数, 起始字节在首个扇区内的偏移）。
+    /// 块大小小于扇区大小（例如 4Kn 设备上的 1K 块文件系统），
+    /// 或者分区偏移不是扇区对齐时，范围的首尾可能落在扇区中间
+    fn sector_span(&self, lba: u64, count: u32) -> (u64, u32, usize) {
         let sector_size = self.device.sector_size() as u64;
-        (lba * block_size + self.partition_offset) / sector_size
+        let start = lba * self.block_size as u64 + self.partition_offset;
+        let end = start + count as u64 * self.block_size as u64;
+        let first = start / sector_size;
+        let sectors = end.div_ceil(sector_size) - first;
+        (first, sectors as u32, (start - first * sector_size) as usize)
+    }
+
+    /// 从设备读取 `count` 个逻辑块到 `buf`
+    ///
+    /// 首尾都与扇区对齐时直接读取，否则读取覆盖范围的整扇区后复制所需部分
+    pub(super) fn device_read(&mut self, lba: u64, count: u32, buf: &mut [u8]) -> Result<usize> {
+        let len = count as usize * self.block_size as usize;
+        let sector_size = self.device.sector_size() as usize;
+        let (first, sectors, head) = self.sector_span(lba, count);
+        if head == 0 && len % sector_size == 0 {
+            return self.device.read_blocks(first, sectors, &mut buf[..len]);
+        }
+
+        let mut temp = alloc::vec![0u8; sectors as usize * sector_size];
+        self.device.read_blocks(first, sectors, &mut temp)?;
+        buf[..len].copy_from_slice(&temp[head..head + len]);
+        Ok(len)
     }
 
-    /// 每个逻辑块包含的物理扇区数
-    pub(super) fn sectors_per_block(&self) -> u32 {
-        self.device.block_size() / self.device.sector_size()
+    /// 把 `buf` 中的 `count` 个逻辑块写入设备
+    ///
+    /// 首尾都与扇区对齐时直接写入；否则先读出首尾两个不完整的扇区
+    /// （读-改-写），合并新数据后写回整扇区，扇区中其余块的内容保持不变
+    pub(super) fn device_write(&mut self, lba: u64, count: u32, buf: &[u8]) -> Result<usize> {
+        let len = count as usize * self.block_size as usize;
+        let sector_size = self.device.sector_size() as usize;
+        let (first, sectors, head) = self.sector_span(lba, count);
+        if head == 0 && len % sector_size == 0 {
+            return self.device.write_blocks(first, sectors, &buf[..len]);
+        }
+
+        let mut temp = alloc::vec![0u8; sectors as usize * sector_size];
+        if head != 0 || len < sector_size {
+            self.device.read_blocks(first, 1, &mut temp[..sector_size])?;
+        }
+        let tail = temp.len() - sector_size;
+        if sectors > 1 && (head + len) % sector_size != 0 {
+            self.device.read_blocks(first + sectors as u64 - 1, 1, &mut temp[tail..])?;
+        }
+        temp[head..head + len].copy_from_slice(&buf[..len]);
+        self.device.write_blocks(first, sectors, &temp)?;
+        Ok(len)
     }
 
     /// issue：当前这些计数的追踪并不准确
@@ -367,11 +543,38 @@ impl<D: BlockDevice> BlockDev<D> {
         self.physical_write_count += 1;
     }
 
+    /// 记录从 `lba` 开始的 `count` 个逻辑块被写入设备（热度图、观测者）
+    pub(super) fn record_device_write(&mut self, lba: u64, count: u64) {
+        if let Some(map) = &mut self.heatmap {
+            map.record(lba, count);
+        }
+        if let Some(observer) = self.observer {
+            observer.on_block_write(lba, count as u32);
+        }
+    }
+
+    /// 记录从 `lba` 开始的 `count` 个逻辑块从设备读取（物理读计数、观测者）
+    pub(super) fn record_device_read(&mut self, lba: u64, count: u64) {
+        self.inc_physical_read_count();
+        if let Some(observer) = self.observer {
+            observer.on_block_read(lba, count as u32);
+        }
+    }
+
+    /// 通知观测者一次块缓存访问
+    pub(super) fn observe_cache(&self, lba: u64, hit: bool) {
+        match self.observer {
+            Some(observer) if hit => observer.on_cache_hit(lba),
+            Some(observer) => observer.on_cache_miss(lba),
+            None => {}
+        }
+    }
+
     /// 刷新指定逻辑块地址的缓存
     ///
     /// # 参数
     ///
-    /// * `lba` - 逻辑块地址
+    /// * `lba` - 物理块号（文件系统块）
     ///
     /// # 返回
     ///
@@ -380,13 +583,8 @@ impl<D: BlockDevice> BlockDev<D> {
     /// # 错误
     ///
     /// 如果块不在缓存中或写入失败，返回错误
-    pub fn flush_lba(&mut self, lba: u64) -> Result<()> {
-        // 先获取必要参数，避免借用冲突
-        // TODO: 需要进一步考虑如何重构以避免使用蹩脚、低效的方式绕开引用检查，类似的代码还有多处
-        let sector_size = self.device.sector_size();
-        let partition_offset = self.partition_offset;
-        let block_size = self.block_size();
-
+    pub fn flush_lba(&mut self, lba: impl Into<Pblk>) -> Result<()> {
+        let lba = lba.into().0;
         if let Some(cache) = &mut self.bcache {
             // 使用新架构：Cache提供数据，BlockDev负责I/O
             // 获取数据并复制到临时buffer
@@ -399,10 +597,8 @@ impl<D: BlockDevice> BlockDev<D> {
             // 释放cache借用，进行I/O
             drop(cache);
 
-            // 计算物理地址并写入
-            let pba = (lba * block_size as u64 + partition_offset) / sector_size as u64;
-            let count = (block_size as usize + sector_size as usize - 1) / sector_size as usize;
-            self.device_mut().write_blocks(pba, count as u32, &data)?;
+            self.device_write(lba, 1, &data)?;
+            self.record_device_write(lba, 1);
 
             // 重新借用cache并标记为clean
             if let Some(cache) = &mut self.bcache {
@@ -427,11 +623,6 @@ impl<D: BlockDevice> BlockDev<D> {
     ///
     /// 返回实际flush的块数量
     pub fn flush_some_dirty_blocks(&mut self, count: usize) -> Result<usize> {
-        // 先获取必要参数，避免借用冲突
-        let sector_size = self.device.sector_size();
-        let partition_offset = self.partition_offset;
-        let block_size = self.block_size();
-
         let to_flush = if let Some(cache) = &mut self.bcache {
             let dirty_blocks = cache.get_dirty_blocks();
             dirty_blocks.into_iter().take(count).collect::<alloc::vec::Vec<_>>()
@@ -456,9 +647,8 @@ impl<D: BlockDevice> BlockDev<D> {
                 };
 
                 // 进行I/O（此时没有cache借用）
-                let pba = (lba * block_size as u64 + partition_offset) / sector_size as u64;
-                let count = (block_size as usize + sector_size as usize - 1) / sector_size as usize;
-                self.device_mut().write_blocks(pba, count as u32, &data)?;
+                self.device_write(lba, 1, &data)?;
+                self.record_device_write(lba, 1);
 
                 // 标记clean
                 if let Some(cache) = &mut self.bcache {
@@ -485,15 +675,16 @@ impl<D: BlockDevice> BlockDev<D> {
     ///
     /// # 参数
     ///
-    /// * `lba` - 起始逻辑块地址
+    /// * `lba` - 起始物理块号（文件系统块）
     /// * `count` - 要读取的块数
     /// * `buf` - 目标缓冲区
     ///
     /// # 返回
     ///
     /// 成功返回读取的字节数
-    pub fn read_blocks_direct(&mut self, lba: u64, count: u32, buf: &mut [u8]) -> Result<usize> {
-        let block_size = self.device.block_size();
+    pub fn read_blocks_direct(&mut self, lba: impl Into<Pblk>, count: u32, buf: &mut [u8]) -> Result<usize> {
+        let lba = lba.into().0;
+        let block_size = self.block_size();
         let required_size = count as usize * block_size as usize;
 
         if buf.len() < required_size {
@@ -503,15 +694,10 @@ impl<D: BlockDevice> BlockDev<D> {
             ));
         }
 
-        // 转换为物理扇区地址
-        let pba = self.logical_to_physical(lba);
-        let sectors_per_block = self.sectors_per_block();
-        let sector_count = count * sectors_per_block;
-
         // 直接从设备读取
         self.inc_read_count();
-        self.inc_physical_read_count();
-        self.device.read_blocks(pba, sector_count, buf)
+        self.record_device_read(lba, count as u64);
+        self.device_read(lba, count, buf)
     }
 
     /// 直接写入块（绕过缓存）
@@ -525,15 +711,17 @@ impl<D: BlockDevice> BlockDev<D> {
     ///
     /// # 参数
     ///
-    /// * `lba` - 起始逻辑块地址
+    /// * `lba` - 起始物理块号（文件系统块）
     /// * `count` - 要写入的块数
     /// * `buf` - 源数据缓冲区
     ///
     /// # 返回
     ///
     /// 成功返回写入的字节数
-    pub fn write_blocks_direct(&mut self, lba: u64, count: u32, buf: &[u8]) -> Result<usize> {
-        let block_size = self.device.block_size();
+    pub fn write_blocks_direct(&mut self, lba: impl Into<Pblk>, count: u32, buf: &[u8]) -> Result<usize> {
+        self.check_writable()?;
+        let lba = lba.into().0;
+        let block_size = self.block_size();
         let required_size = count as usize * block_size as usize;
 
         if buf.len() < required_size {
@@ -543,15 +731,13 @@ impl<D: BlockDevice> BlockDev<D> {
             ));
         }
 
-        // 转换为物理扇区地址
-        let pba = self.logical_to_physical(lba);
-        let sectors_per_block = self.sectors_per_block();
-        let sector_count = count * sectors_per_block;
-
         // 直接写入设备
         self.inc_write_count();
         self.inc_physical_write_count();
-        self.device.write_blocks(pba, sector_count, buf)
+        let written = self.device_write(lba, count, buf)?;
+        self.record_device_write(lba, count as u64);
+        self.meta.invalidate_range(lba, count as u64);
+        Ok(written)
     }
 
     /// 直接读取字节（绕过缓存）
@@ -566,9 +752,10 @@ impl<D: BlockDevice> BlockDev<D> {
     /// # 返回
     ///
     /// 成功返回读取的字节数
-    pub fn read_bytes_direct(&mut self, offset: u64, buf: &mut [u8]) -> Result<usize> {
+    pub fn read_bytes_direct(&mut self, offset: impl Into<ByteOff>, buf: &mut [u8]) -> Result<usize> {
+        let offset = offset.into().0;
         let len = buf.len();
-        let block_size = self.device.block_size() as u64;
+        let block_size = self.block_size() as u64;
 
         // 计算起始块和块内偏移
         let start_block = offset / block_size;
@@ -602,9 +789,11 @@ impl<D: BlockDevice> BlockDev<D> {
     /// # 返回
     ///
     /// 成功返回写入的字节数
-    pub fn write_bytes_direct(&mut self, offset: u64, buf: &[u8]) -> Result<usize> {
+    pub fn write_bytes_direct(&mut self, offset: impl Into<ByteOff>, buf: &[u8]) -> Result<usize> {
+        self.check_writable()?;
+        let offset = offset.into().0;
         let len = buf.len();
-        let block_size = self.device.block_size() as u64;
+        let block_size = self.block_size() as u64;
 
         let start_block = offset / block_size;
         let block_offset = (offset % block_size) as usize;
@@ -637,7 +826,40 @@ impl<D: BlockDevice> BlockDev<D> {
     ///
     /// 如果启用了缓存，返回 Some(CacheStats)，否则返回 None
     pub fn cache_stats(&self) -> Option<crate::cache::CacheStats> {
-        self.bcache.as_ref().map(|cache| cache.stats())
+        self.bcache.as_ref().map(|cache| {
+            let mut stats = cache.stats();
+            self.meta.fill_stats(&mut stats);
+            stats
+        })
+    }
+
+    /// 缓存中脏块的块号（最久未使用的在前），未启用缓存时为空
+    pub(crate) fn dirty_blocks(&self) -> alloc::vec::Vec<u64> {
+        self.bcache.as_ref().map_or_else(alloc::vec::Vec::new, |cache| cache.get_dirty_blocks())
+    }
+
+    /// 块组描述符和位图缓存
+    pub(crate) fn meta_cache(&mut self) -> &mut crate::cache::MetaCache {
+        &mut self.meta
+    }
+
+    /// 清空块组描述符和位图缓存
+    ///
+    /// 事务回滚、重新挂载或绕过 `BlockDev` 修改了设备内容之后调用，
+    /// 之后的访问重新从块缓存读取描述符并重新校验位图
+    pub fn invalidate_meta_cache(&mut self) {
+        self.meta.clear();
+    }
+
+    /// 丢弃块缓存（包括脏块）和元数据缓存，不写回设备
+    ///
+    /// 用于模拟掉电：之后的读取重新从设备读取。固定的块同时被取消固定
+    pub fn discard_cache(&mut self) {
+        self.meta.clear();
+        if let Some(cache) = &mut self.bcache {
+            cache.clear();
+            cache.unpin_all();
+        }
     }
 
     /// 检查是否启用了缓存
@@ -649,12 +871,14 @@ impl<D: BlockDevice> BlockDev<D> {
     ///
     /// # 参数
     ///
-    /// * `lba` - 逻辑块地址
+    /// * `lba` - 物理块号（文件系统块）
     ///
     /// # 返回
     ///
     /// 成功返回 Ok(())
-    pub fn invalidate_cache_block(&mut self, lba: u64) -> Result<()> {
+    pub fn invalidate_cache_block(&mut self, lba: impl Into<Pblk>) -> Result<()> {
+        let lba = lba.into().0;
+        self.meta.invalidate_range(lba, 1);
         if let Some(cache) = &mut self.bcache {
             cache.invalidate_buffer(lba)?;
         }
@@ -672,12 +896,156 @@ impl<D: BlockDevice> BlockDev<D> {
     ///
     /// 成功返回失效的块数量
     pub fn invalidate_cache_range(&mut self, from: u64, count: u32) -> Result<usize> {
+        self.meta.invalidate_range(from, count as u64);
         if let Some(cache) = &mut self.bcache {
             return cache.invalidate_range(from, count);
         }
         Ok(0)
     }
 
+    /// 固定缓存块并读入缓存，之后它不会被淘汰
+    ///
+    /// 用于超级块、块组描述符、位图、journal 超级块等反复访问的元数据，
+    /// 避免大量数据写入把它们挤出缓存。未启用缓存时没有效果。
+    ///
+    /// # 错误
+    ///
+    /// - `ErrorKind::NoSpace` - 固定的块已达到缓存容量的一半
+    pub fn pin_cache_block(&mut self, lba: impl Into<Pblk>) -> Result<()> {
+        let lba = lba.into().0;
+        let Some(cache) = &mut self.bcache else {
+            return Ok(());
+        };
+        cache.pin(lba)?;
+        if !cache.contains(lba) {
+            let mut buf = vec![0u8; self.block_size() as usize];
+            if let Err(e) = self.read_block(lba, &mut buf) {
+                self.unpin_cache_block(lba);
+                return Err(e);
+            }
+        }
+        Ok(())
+    }
+
+    /// 解除一次 [`pin_cache_block`](Self::pin_cache_block) 的固定
+    pub fn unpin_cache_block(&mut self, lba: impl Into<Pblk>) {
+        if let Some(cache) = &mut self.bcache {
+            cache.unpin(lba.into().0);
+        }
+    }
+
+    /// 解除所有缓存块的固定
+    pub fn unpin_all_cache_blocks(&mut self) {
+        if let Some(cache) = &mut self.bcache {
+            cache.unpin_all();
+        }
+    }
+
+    /// 获取预读配置
+    ///
+    /// 未启用缓存时返回 `None`
+    pub fn readahead(&self) -> Option<crate::cache::ReadaheadConfig> {
+        self.bcache.as_ref().map(|cache| cache.readahead())
+    }
+
+    /// 设置预读配置
+    ///
+    /// 未启用缓存时没有效果（预读的块需要缓存来存放）。默认禁用预读
+    ///
+    /// # 示例
+    ///
+    /// ```rust,ignore
+    /// // SD 卡上顺序读大文件：每次缺失额外读取 32 块
+    /// block_dev.set_readahead(ReadaheadConfig { window: 32, trigger: 2 });
+    /// ```
+    pub fn set_readahead(&mut self, config: crate::cache::ReadaheadConfig) {
+        if let Some(cache) = &mut self.bcache {
+            cache.set_readahead(config);
+        }
+    }
+
+    /// 获取缓存淘汰策略
+    ///
+    /// 未启用缓存时返回 `None`
+    pub fn cache_policy(&self) -> Option<crate::cache::CachePolicy> {
+        self.bcache.as_ref().map(|cache| cache.policy())
+    }
+
+    /// 设置缓存淘汰策略
+    ///
+    /// 未启用缓存时没有效果
+    pub fn set_cache_policy(&mut self, policy: crate::cache::CachePolicy) {
+        if let Some(cache) = &mut self.bcache {
+            cache.set_policy(policy);
+        }
+    }
+
+    /// 获取脏块写回阈值
+    ///
+    /// 未启用缓存时返回 `None`
+    pub fn writeback_config(&self) -> Option<crate::cache::WritebackConfig> {
+        self.bcache.as_ref().map(|cache| cache.writeback())
+    }
+
+    /// 设置脏块写回阈值
+    ///
+    /// 未启用缓存时没有效果
+    pub fn set_writeback_config(&mut self, config: crate::cache::WritebackConfig) {
+        if let Some(cache) = &mut self.bcache {
+            cache.set_writeback(config);
+        }
+    }
+
+    /// 脏块超过高水位时写回最久未使用的脏块，直到回到低水位
+    ///
+    /// 每次弄脏缓存块之后调用
+    pub(super) fn writeback_over_high_water(&mut self) -> Result<()> {
+        let excess = self.bcache.as_ref().map_or(0, |cache| cache.excess_dirty());
+        if excess > 0 {
+            log::debug!("[BlockDev] Dirty blocks over high water, writing back {excess}");
+            self.flush_some_dirty_blocks(excess)?;
+        }
+        Ok(())
+    }
+
+    /// 调整缓存容量（块数）
+    ///
+    /// 未启用缓存时创建缓存。缩小时驱逐干净块，脏块比新容量多时
+    /// 先把脏块写回设备，因此宿主系统内存紧张时可以随时调用。
+    ///
+    /// # 错误
+    ///
+    /// - `ErrorKind::InvalidInput` - `capacity` 为 0
+    /// - 写回脏块失败时返回设备错误
+    ///
+    /// # 示例
+    ///
+    /// ```rust,ignore
+    /// // 内存紧张：缓存缩小到 64 块
+    /// fs.block_device_mut().set_cache_capacity(64)?;
+    /// ```
+    pub fn set_cache_capacity(&mut self, capacity: usize) -> Result<()> {
+        let block_size = self.block_size() as usize;
+        let cache = match &mut self.bcache {
+            Some(cache) => cache,
+            None => {
+                if capacity == 0 {
+                    return Err(Error::new(ErrorKind::InvalidInput, "Cache capacity must be non-zero"));
+                }
+                self.bcache = Some(crate::cache::BlockCache::new(capacity, block_size));
+                return Ok(());
+            }
+        };
+
+        match cache.set_capacity(capacity) {
+            Err(e) if e.kind() == ErrorKind::NoSpace => {
+                self.write_back_dirty()?;
+                self.bcache.as_mut().unwrap().set_capacity(capacity)
+            }
+            result => result,
+        }
+    }
+
     // ===== 写回模式控制 =====
 
     /// 启用缓存写回模式
@@ -0,0 +1,268 @@
+//! 镜像文件块设备（`std` 特性）
+//!
+//! [`FileBlockDevice`] 把 `std::fs::File` 作为块设备，用于在宿主机上读写 ext4 镜像。
+//! 启用 `mmap` 特性后可以用 [`enable_mmap`](FileBlockDevice::enable_mmap) 把镜像映射到内存，
+//! 读写变成内存复制，由操作系统负责写回。
+
+use super::BlockDevice;
+use crate::error::{Error, ErrorKind, Result};
+use std::{
+    fs::{File, OpenOptions},
+    io::{Read, Seek, SeekFrom, Write},
+    path::Path,
+};
+
+/// 块大小
+const BLOCK_SIZE: u32 = 4096;
+/// 扇区大小
+const SECTOR_SIZE: u32 = 512;
+
+/// 镜像的内存映射
+#[cfg(feature = "mmap")]
+enum Mapping {
+    ReadOnly(memmap2::Mmap),
+    ReadWrite(memmap2::MmapMut),
+}
+
+#[cfg(feature = "mmap")]
+impl Mapping {
+    fn bytes(&self) -> &[u8] {
+        match self {
+            Mapping::ReadOnly(map) => map,
+            Mapping::ReadWrite(map) => map,
+        }
+    }
+}
+
+/// 镜像文件块设备
+///
+/// 块大小 4096、扇区大小 512；文件末尾不足一个块的部分不可访问。
+///
+/// # 示例
+///
+/// ```rust,ignore
+/// use lwext4_core::{BlockDev, Ext4FileSystem, FileBlockDevice};
+///
+/// let dev = FileBlockDevice::open("rootfs.ext4")?;
+/// let mut fs = Ext4FileSystem::mount(BlockDev::new_with_cache(dev, 1024)?)?;
+/// println!("{:?}", fs.read_dir("/")?);
+/// ```
+pub struct FileBlockDevice {
+    file: File,
+    total_blocks: u64,
+    read_only: bool,
+    #[cfg(feature = "mmap")]
+    map: Option<Mapping>,
+}
+
+impl FileBlockDevice {
+    /// 以读写方式打开镜像文件
+    ///
+    /// # 错误
+    ///
+    /// - `ErrorKind::NotFound` / `ErrorKind::PermissionDenied` - 无法打开文件
+    /// - `ErrorKind::Io` - 读取文件大小失败
+    pub fn open(path: impl AsRef<Path>) -> Result<Self> {
+        let file = OpenOptions::new()
+            .read(true)
+            .write(true)
+            .open(path)
+            .map_err(|e| io_error(e, "Failed to open image file"))?;
+        Self::from_file(file, false)
+    }
+
+    /// 以只读方式打开镜像文件，挂载时文件系统自动以只读方式挂载
+    pub fn open_read_only(path: impl AsRef<Path>) -> Result<Self> {
+        let file = File::open(path).map_err(|e| io_error(e, "Failed to open image file"))?;
+        Self::from_file(file, true)
+    }
+
+    /// 创建 `size` 字节的镜像文件（稀疏文件），已存在时截断
+    pub fn create(path: impl AsRef<Path>, size: u64) -> Result<Self> {
+        let file = OpenOptions::new()
+            .read(true)
+            .write(true)
+            .create(true)
+            .truncate(true)
+            .open(path)
+            .map_err(|e| io_error(e, "Failed to create image file"))?;
+        file.set_len(size).map_err(|e| io_error(e, "Failed to set image size"))?;
+        Self::from_file(file, false)
+    }
+
+    /// 使用已经打开的文件，`read_only` 为真时拒绝写入
+    pub fn from_file(file: File, read_only: bool) -> Result<Self> {
+        let len = file.metadata().map_err(|e| io_error(e, "Failed to read image size"))?.len();
+        Ok(Self {
+            file,
+            total_blocks: len / BLOCK_SIZE as u64,
+            read_only,
+            #[cfg(feature = "mmap")]
+            map: None,
+        })
+    }
+
+    /// 把镜像映射到内存，之后的读写直接访问映射
+    ///
+    /// # 错误
+    ///
+    /// - `ErrorKind::Io` - 映射失败
+    #[cfg(feature = "mmap")]
+    pub fn enable_mmap(&mut self) -> Result<()> {
+        // SAFETY: 映射期间文件由本设备独占持有；其他进程同时修改镜像文件时，
+        // 与普通读写一样会看到不一致的内容，但不会破坏内存安全以外的约定
+        let map = unsafe {
+            if self.read_only {
+                memmap2::Mmap::map(&self.file).map(Mapping::ReadOnly)
+            } else {
+                memmap2::MmapMut::map_mut(&self.file).map(Mapping::ReadWrite)
+            }
+        };
+        self.map = Some(map.map_err(|e| io_error(e, "Failed to map image file"))?);
+        Ok(())
+    }
+
+    /// 底层文件
+    pub fn file(&self) -> &File {
+        &self.file
+    }
+
+    /// 取出底层文件
+    pub fn into_file(self) -> File {
+        self.file
+    }
+
+    /// 扇区范围对应的字节偏移和长度，越界时返回错误
+    fn byte_range(&self, lba: u64, count: u32, buf_len: usize) -> Result<(u64, usize)> {
+        let len = count as usize * SECTOR_SIZE as usize;
+        if buf_len < len {
+            return Err(Error::new(ErrorKind::InvalidInput, "Buffer too small"));
+        }
+        let end = self.total_blocks * BLOCK_SIZE as u64;
+        let offset = lba.checked_mul(SECTOR_SIZE as u64).filter(|&offset| offset + len as u64 <= end);
+        let offset = offset.ok_or(Error::new(ErrorKind::InvalidInput, "Access beyond end of device"))?;
+        Ok((offset, len))
+    }
+}
+
+impl BlockDevice for FileBlockDevice {
+    fn block_size(&self) -> u32 {
+        BLOCK_SIZE
+    }
+
+    fn sector_size(&self) -> u32 {
+        SECTOR_SIZE
+    }
+
+    fn total_blocks(&self) -> u64 {
+        self.total_blocks
+    }
+
+    fn read_blocks(&mut self, lba: u64, count: u32, buf: &mut [u8]) -> Result<usize> {
+        let (offset, len) = self.byte_range(lba, count, buf.len())?;
+
+        #[cfg(feature = "mmap")]
+        if let Some(map) = &self.map {
+            let start = offset as usize;
+            buf[..len].copy_from_slice(&map.bytes()[start..start + len]);
+            return Ok(len);
+        }
+
+        self.file.seek(SeekFrom::Start(offset)).map_err(|e| io_error(e, "Image seek failed"))?;
+        self.file.read_exact(&mut buf[..len]).map_err(|e| io_error(e, "Image read failed"))?;
+        Ok(len)
+    }
+
+    fn write_blocks(&mut self, lba: u64, count: u32, buf: &[u8]) -> Result<usize> {
+        if self.read_only {
+            return Err(Error::new(ErrorKind::ReadOnlyFs, "Device is read-only"));
+        }
+        let (offset, len) = self.byte_range(lba, count, buf.len())?;
+
+        #[cfg(feature = "mmap")]
+        if let Some(Mapping::ReadWrite(map)) = &mut self.map {
+            let start = offset as usize;
+            map[start..start + len].copy_from_slice(&buf[..len]);
+            return Ok(len);
+        }
+
+        self.file.seek(SeekFrom::Start(offset)).map_err(|e| io_error(e, "Image seek failed"))?;
+        self.file.write_all(&buf[..len]).map_err(|e| io_error(e, "Image write failed"))?;
+        Ok(len)
+    }
+
+    /// 写回映射的脏页并等待文件数据落盘
+    fn flush(&mut self) -> Result<()> {
+        if self.read_only {
+            return Ok(());
+        }
+        #[cfg(feature = "mmap")]
+        if let Some(Mapping::ReadWrite(map)) = &self.map {
+            map.flush().map_err(|e| io_error(e, "Image mmap flush failed"))?;
+        }
+        self.file.sync_data().map_err(|e| io_error(e, "Image sync failed"))
+    }
+
+    fn is_read_only(&self) -> bool {
+        self.read_only
+    }
+}
+
+/// 把 `std::io::Error` 转换为本 crate 的错误
+fn io_error(err: std::io::Error, message: &'static str) -> Error {
+    log::debug!("[FileBlockDevice] {message}: {err}");
+    let kind = match err.kind() {
+        std::io::ErrorKind::NotFound => ErrorKind::NotFound,
+        std::io::ErrorKind::PermissionDenied => ErrorKind::PermissionDenied,
+        std::io::ErrorKind::WouldBlock => ErrorKind::WouldBlock,
+        _ => ErrorKind::Io,
+    };
+    Error::new(kind, message)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn temp_image(name: &str) -> std::path::PathBuf {
+        std::env::temp_dir().join(format!("lwext4_core_{}_{name}", std::process::id()))
+    }
+
+    #[test]
+    fn test_file_device_read_write() {
+        let path = temp_image("rw.img");
+        let mut dev = FileBlockDevice::create(&path, 4 * 4096 + 100).unwrap();
+        assert_eq!(dev.total_blocks(), 4);
+        dev.write_blocks(8, 8, &[0x5a; 4096]).unwrap();
+        dev.flush().unwrap();
+        assert!(dev.write_blocks(32, 1, &[0u8; 512]).is_err());
+        drop(dev);
+
+        let mut dev = FileBlockDevice::open_read_only(&path).unwrap();
+        let mut buf = [0u8; 1024];
+        dev.read_blocks(15, 2, &mut buf).unwrap();
+        assert!(buf[..512].iter().all(|&b| b == 0x5a));
+        assert!(buf[512..].iter().all(|&b| b == 0));
+        assert_eq!(dev.write_blocks(0, 1, &[0u8; 512]).unwrap_err().kind(), ErrorKind::ReadOnlyFs);
+
+        assert_eq!(FileBlockDevice::open(temp_image("missing.img")).err().unwrap().kind(), ErrorKind::NotFound);
+        std::fs::remove_file(path).unwrap();
+    }
+
+    #[cfg(feature = "mmap")]
+    #[test]
+    fn test_file_device_mmap() {
+        let path = temp_image("mmap.img");
+        let mut dev = FileBlockDevice::create(&path, 2 * 4096).unwrap();
+        dev.enable_mmap().unwrap();
+        dev.write_blocks(1, 1, &[7u8; 512]).unwrap();
+        let mut buf = [0u8; 512];
+        dev.read_blocks(1, 1, &mut buf).unwrap();
+        assert_eq!(buf, [7u8; 512]);
+        dev.flush().unwrap();
+        drop(dev);
+
+        assert_eq!(std::fs::read(&path).unwrap()[512], 7);
+        std::fs::remove_file(path).unwrap();
+    }
+}
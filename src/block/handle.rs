@@ -3,7 +3,9 @@
 //! 对应 lwext4 的 `ext4_block` API
 
 use crate::error::{Error, ErrorKind, Result};
+use crate::types::Pblk;
 use crate::block::{BlockDevice, BlockDev};
+use crate::cache::{BlockCache, CacheBuffer};
 
 /// 块句柄
 ///
@@ -52,6 +54,8 @@ pub struct Block<'a, D: BlockDevice> {
     block_dev: &'a mut BlockDev<D>,
     /// 逻辑块地址
     lba: u64,
+    /// 是否是元数据块（决定分段 LRU 下的缓存优先级）
+    metadata: bool,
     /// 是否持有缓存块引用（需要在 drop 时释放）
     held: bool,
     /// 本地数据副本（仅在无缓存时使用）
@@ -67,7 +71,7 @@ impl<'a, D: BlockDevice> Block<'a, D> {
     ///
     /// # 缓存路径
     ///
-    /// 1. 调用 `cache.alloc(lba)` 在缓存中分配块
+    /// 1. 调用 `cache.alloc_metadata(lba)` 在缓存中分配块
     ///    - 如果块已存在：返回现有块的可变引用
     ///    - 如果块不存在：分配新槽位（可能驱逐 LRU 块）
     /// 2. 如果是新分配的块，从磁盘读取数据到缓存块
@@ -81,49 +85,55 @@ impl<'a, D: BlockDevice> Block<'a, D> {
     /// # 参数
     ///
     /// * `block_dev` - 块设备
-    /// * `lba` - 逻辑块地址
-    pub fn get(block_dev: &'a mut BlockDev<D>, lba: u64) -> Result<Self> {
-        let block_size = block_dev.block_size() as usize;
+    /// * `lba` - 物理块号（文件系统块）
+    pub fn get(block_dev: &'a mut BlockDev<D>, lba: impl Into<Pblk>) -> Result<Self> {
+        Self::get_with(block_dev, lba.into().0, true)
+    }
+
+    /// 获取文件数据块（读取数据）
+    ///
+    /// 与 [`get`](Self::get) 相同，但块按数据块缓存：分段 LRU 下从试用段开始，
+    /// 命中也不晋升。文件数据的部分块读-改-写应使用它，而不是自行
+    /// `read_block` 到临时缓冲区再 `write_block` 回去。
+    pub fn get_data(block_dev: &'a mut BlockDev<D>, lba: impl Into<Pblk>) -> Result<Self> {
+        Self::get_with(block_dev, lba.into().0, false)
+    }
 
-        // 先获取需要的值，避免借用冲突
-        let pba = block_dev.logical_to_physical(lba);
-        let count = block_dev.sectors_per_block();
+    fn get_with(block_dev: &'a mut BlockDev<D>, lba: u64, metadata: bool) -> Result<Self> {
+        let block_size = block_dev.block_size() as usize;
 
-        if let Some(cache) = &mut block_dev.bcache {
+        if block_dev.bcache.is_some() {
             // 有缓存：在缓存中分配块
-            // 使用主动flush机制：如果alloc失败（NoSpace），先flush一些脏块再重试
-            let (_cache_buf, is_new) = match cache.alloc(lba) {
-                Ok(result) => result,
-                Err(e) if e.kind() == crate::error::ErrorKind::NoSpace => {
-                    // Cache满且都是脏块 - 主动flush后重试
-                    let flush_count = cache.capacity() / 4;
-                    drop(cache); // 释放借用
-                    // prepare for contest replace warn with info
-                    log::info!("[Block::get] Cache full with dirty blocks, flushing {} blocks", flush_count);
-
-                    // Flush 25%的cache容量
-                    block_dev.flush_some_dirty_blocks(flush_count)?;
-
-                    // 重试alloc
-                    block_dev.bcache.as_mut().unwrap().alloc(lba)?
+            let needs_read = Self::with_slot(block_dev, lba, metadata, "Block::get", |cache_buf, is_new| {
+                // 其他路径通过 get_noread 预留了该块但尚未写入，其中的数据不能当作磁盘内容
+                if !is_new && cache_buf.is_initializing() {
+                    return Err(Error::new(ErrorKind::Busy, "Block is being initialized"));
                 }
-                Err(e) => return Err(e),
-            };
+                Ok(is_new || !cache_buf.is_uptodate())
+            })??;
 
-            if is_new {
+            block_dev.observe_cache(lba, !needs_read);
+            if needs_read {
                 // 新分配的块，需要从磁盘读取
                 // ⚠️ 解决借用冲突：先读取到临时缓冲区，然后重新获取 cache 引用填充数据
                 // 第一次 alloc 的引用在调用 device_mut() 前必须结束，否则会有借用冲突
 
-                // 先读取数据到临时缓冲区
-                block_dev.inc_physical_read_count();
+                // 先读取数据到临时缓冲区（顺序访问时附带预读）
                 let mut temp_buf = alloc::vec![0u8; block_size];
-                block_dev.device_mut().read_blocks(pba, count, &mut temp_buf)?;
+                if let Err(e) = block_dev.read_miss(lba, &mut temp_buf) {
+                    // 读取失败（包括 WouldBlock）时移除半初始化的缓存块，
+                    // 否则重试时会把未读入的数据当作有效内容
+                    if let Some(cache) = &mut block_dev.bcache {
+                        cache.invalidate_buffer(lba)?;
+                    }
+                    return Err(e);
+                }
 
-                // 重新获取缓存块引用并填充数据
-                let (cache_buf, _) = block_dev.bcache.as_mut().unwrap().alloc(lba)?;
-                cache_buf.data.copy_from_slice(&temp_buf);
-                cache_buf.mark_uptodate();
+                // 重新获取缓存块引用并填充数据（预读可能已经把新槽位驱逐）
+                Self::with_slot(block_dev, lba, metadata, "Block::get", |cache_buf, _| {
+                    cache_buf.data.copy_from_slice(&temp_buf);
+                    cache_buf.mark_uptodate();
+                })?;
             }
 
             // Block 持有 &mut BlockDev，保证缓存块不被其他操作访问
@@ -131,6 +141,7 @@ impl<'a, D: BlockDevice> Block<'a, D> {
             Ok(Self {
                 block_dev,
                 lba,
+                metadata,
                 held: true,
                 local_data: None,
                 local_dirty: false,
@@ -143,6 +154,7 @@ impl<'a, D: BlockDevice> Block<'a, D> {
             Ok(Self {
                 block_dev,
                 lba,
+                metadata,
                 held: false,
                 local_data: Some(data),
                 local_dirty: false,
@@ -161,10 +173,12 @@ impl<'a, D: BlockDevice> Block<'a, D> {
     ///
     /// # 缓存路径
     ///
-    /// 1. 调用 `cache.alloc(lba)` 在缓存中分配块
+    /// 1. 调用 `cache.alloc_metadata(lba)` 在缓存中分配块
     /// 2. 如果是新块，**不从磁盘读取**
-    /// 3. 标记为 `uptodate`（因为调用者会立即覆盖）
-    /// 4. Block 持有 `&mut BlockDev`，drop 时释放，lru crate 自动管理
+    /// 3. 新块标记为 `INITIALIZING`：在第一次 `with_data_mut` 之前，
+    ///    `with_data` 返回错误，`Block::get` 返回 `Busy`，缓存也不会驱逐它
+    /// 4. 第一次 `with_data_mut` 之后标记为 `uptodate`（调用者应覆盖整个块）
+    /// 5. 没有写入就 drop 时从缓存中移除该块，之后的读取重新访问磁盘
     ///
     /// # 无缓存路径
     ///
@@ -173,33 +187,37 @@ impl<'a, D: BlockDevice> Block<'a, D> {
     /// # 参数
     ///
     /// * `block_dev` - 块设备
-    /// * `lba` - 逻辑块地址
-    pub fn get_noread(block_dev: &'a mut BlockDev<D>, lba: u64) -> Result<Self> {
+    /// * `lba` - 物理块号（文件系统块）
+    pub fn get_noread(block_dev: &'a mut BlockDev<D>, lba: impl Into<Pblk>) -> Result<Self> {
+        Self::get_noread_with(block_dev, lba.into().0, true)
+    }
+
+    /// 获取文件数据块（不读取数据）
+    ///
+    /// 与 [`get_noread`](Self::get_noread) 相同，缓存优先级同 [`get_data`](Self::get_data)
+    pub fn get_data_noread(block_dev: &'a mut BlockDev<D>, lba: impl Into<Pblk>) -> Result<Self> {
+        Self::get_noread_with(block_dev, lba.into().0, false)
+    }
+
+    fn get_noread_with(block_dev: &'a mut BlockDev<D>, lba: u64, metadata: bool) -> Result<Self> {
         let block_size = block_dev.block_size() as usize;
 
-        if let Some(cache) = &mut block_dev.bcache {
+        if block_dev.bcache.is_some() {
             // 有缓存：在缓存中分配块，但不读取磁盘
-            // 使用主动flush机制
-            let (cache_buf, _is_new) = match cache.alloc(lba) {
-                Ok(result) => result,
-                Err(e) if e.kind() == crate::error::ErrorKind::NoSpace => {
-                    let flush_count = cache.capacity() / 4;
-                    drop(cache); // 释放借用
-                    // prepare for contest replace warn with info       
-                    log::info!("[Block::get_noread] Cache full, flushing {} blocks", flush_count);
-                    block_dev.flush_some_dirty_blocks(flush_count)?;
-                    block_dev.bcache.as_mut().unwrap().alloc(lba)?
+            Self::with_slot(block_dev, lba, metadata, "Block::get_noread", |cache_buf, is_new| {
+                // 已在缓存中的块数据有效，保持原状态；新块在写入前处于初始化状态
+                if is_new {
+                    cache_buf.mark_initializing();
+                } else if cache_buf.is_initializing() {
+                    return Err(Error::new(ErrorKind::Busy, "Block is being initialized"));
                 }
-                Err(e) => return Err(e),
-            };
-
-            // 不管是新块还是已存在，都标记为 uptodate
-            // 因为调用者会立即覆盖整个块
-            cache_buf.mark_uptodate();
+                Ok(())
+            })??;
 
             Ok(Self {
                 block_dev,
                 lba,
+                metadata,
                 held: true,
                 local_data: None,
                 local_dirty: false,
@@ -211,6 +229,7 @@ impl<'a, D: BlockDevice> Block<'a, D> {
             Ok(Self {
                 block_dev,
                 lba,
+                metadata,
                 held: false,
                 local_data: Some(data),
                 local_dirty: false,
@@ -218,6 +237,32 @@ impl<'a, D: BlockDevice> Block<'a, D> {
         }
     }
 
+    /// 在缓存中分配 `lba` 的槽位并交给 `f` 访问
+    ///
+    /// 使用主动flush机制：缓存满且都是脏块（NoSpace）时，先写回 25% 容量的脏块再重试。
+    /// 调用者保证已启用缓存。
+    fn with_slot<R>(
+        block_dev: &mut BlockDev<D>,
+        lba: u64,
+        metadata: bool,
+        caller: &str,
+        f: impl FnOnce(&mut CacheBuffer, bool) -> R,
+    ) -> Result<R> {
+        let cache = block_dev.bcache.as_mut().unwrap();
+        let (cache_buf, is_new) = match alloc_slot(cache, lba, metadata) {
+            Ok(result) => result,
+            Err(e) if e.kind() == ErrorKind::NoSpace => {
+                let flush_count = cache.capacity() / 4;
+                // prepare for contest replace warn with info
+                log::info!("[{caller}] Cache full with dirty blocks, flushing {flush_count} blocks");
+                block_dev.flush_some_dirty_blocks(flush_count)?;
+                alloc_slot(block_dev.bcache.as_mut().unwrap(), lba, metadata)?
+            }
+            Err(e) => return Err(e),
+        };
+        Ok(f(cache_buf, is_new))
+    }
+
     /// 获取逻辑块地址
     pub fn lba(&self) -> u64 {
         self.lba
@@ -238,24 +283,15 @@ impl<'a, D: BlockDevice> Block<'a, D> {
     where
         F: FnOnce(&[u8]) -> R,
     {
-        if let Some(cache) = &mut self.block_dev.bcache {
+        if self.block_dev.bcache.is_some() {
             // 有缓存：临时获取缓存块引用
-            // 使用主动flush机制
-            let (cache_buf, _) = match cache.alloc(self.lba) {
-                Ok(result) => result,
-                Err(e) if e.kind() == crate::error::ErrorKind::NoSpace => {
-                    let flush_count = cache.capacity() / 4;
-                    drop(cache); // 释放借用
-                    // prepare for contest replace warn with info
-                    log::info!("[Block::with_data] Cache full, flushing {} blocks", flush_count);
-                    self.block_dev.flush_some_dirty_blocks(flush_count)?;
-                    self.block_dev.bcache.as_mut().unwrap().alloc(self.lba)?
+            Self::with_slot(self.block_dev, self.lba, self.metadata, "Block::with_data", |cache_buf, _| {
+                if cache_buf.is_initializing() {
+                    return Err(Error::new(ErrorKind::InvalidState, "Block read before initialization"));
                 }
-                Err(e) => return Err(e),
-            };
-            let result = f(&cache_buf.data);
-            // ✅ lru crate 自动管理生命周期，无需手动 free
-            Ok(result)
+                // ✅ lru crate 自动管理生命周期，无需手动 free
+                Ok(f(&cache_buf.data))
+            })?
         } else if let Some(data) = &self.local_data {
             // 无缓存：使用本地副本
             Ok(f(data))
@@ -280,35 +316,31 @@ impl<'a, D: BlockDevice> Block<'a, D> {
     where
         F: FnOnce(&mut [u8]) -> R,
     {
-        if let Some(cache) = &mut self.block_dev.bcache {
+        self.block_dev.check_writable()?;
+        if self.block_dev.bcache.is_some() {
             // 有缓存：临时获取缓存块可变引用
-            // 使用主动flush机制
-            let (cache_buf, _) = match cache.alloc(self.lba) {
-                Ok(result) => result,
-                Err(e) if e.kind() == crate::error::ErrorKind::NoSpace => {
-                    let flush_count = cache.capacity() / 4;
-                    drop(cache); // 释放借用
-                    // prepare for contest replace warn with info
-                    log::info!("[Block::with_data_mut] Cache full, flushing {} blocks", flush_count);
-                    self.block_dev.flush_some_dirty_blocks(flush_count)?;
-                    self.block_dev.bcache.as_mut().unwrap().alloc(self.lba)?
-                }
-                Err(e) => return Err(e),
-            };
-            let result = f(&mut cache_buf.data);
-            // 标记为脏
-            cache_buf.mark_dirty();
+            let result = Self::with_slot(self.block_dev, self.lba, self.metadata, "Block::with_data_mut", |cache_buf, _| {
+                let result = f(&mut cache_buf.data);
+                // 标记为脏；get_noread 的块在第一次写入后完成初始化
+                cache_buf.mark_dirty();
+                cache_buf.mark_uptodate();
+                result
+            })?;
             // 将块加入脏列表（需要重新借用cache，因为可能经过了drop）
             if let Some(cache) = &mut self.block_dev.bcache {
                 cache.mark_dirty(self.lba)?;
             }
+            // 块组描述符块被修改，缓存的描述符失效（位图的校验状态保留）
+            self.block_dev.meta.invalidate_descs(self.lba);
             // ✅ lru crate 自动管理生命周期，无需手动 free
-            // 脏块会在 dirty_set 中跟踪，flush 时会写回磁盘
+            // 脏块会在 dirty_set 中跟踪，flush 或超过写回高水位时写回磁盘
+            self.block_dev.writeback_over_high_water()?;
             Ok(result)
         } else if let Some(data) = &mut self.local_data {
             // 无缓存：修改本地副本并标记为脏
             let result = f(data);
             self.local_dirty = true;
+            self.block_dev.meta.invalidate_descs(self.lba);
             Ok(result)
         } else {
             Err(Error::new(ErrorKind::InvalidInput, "Block not initialized"))
@@ -327,6 +359,14 @@ impl<'a, D: BlockDevice> Block<'a, D> {
     /// 实际的释放逻辑
     fn do_release(&mut self) -> Result<()> {
         if self.held {
+            // get_noread 的块没有写入就释放：移除未初始化的缓存块，
+            // 避免之后的读取把它当作有效数据
+            if let Some(cache) = &mut self.block_dev.bcache {
+                if cache.is_initializing(self.lba) {
+                    log::debug!("[Block] LBA={:#x} released before initialization, dropping from cache", self.lba);
+                    cache.invalidate_buffer(self.lba)?;
+                }
+            }
             // 有缓存：lru crate 自动管理生命周期
             // Block 不再需要显式释放引用计数
             // 当 Block drop 时，对 BlockDev 的可变引用被释放，
@@ -343,6 +383,15 @@ impl<'a, D: BlockDevice> Block<'a, D> {
     }
 }
 
+/// 按块的类型在缓存中分配槽位
+fn alloc_slot(cache: &mut BlockCache, lba: u64, metadata: bool) -> Result<(&mut CacheBuffer, bool)> {
+    if metadata {
+        cache.alloc_metadata(lba)
+    } else {
+        cache.alloc_data(lba)
+    }
+}
+
 /// 实现 Drop trait，自动释放块
 impl<'a, D: BlockDevice> Drop for Block<'a, D> {
     fn drop(&mut self) {
@@ -362,6 +411,11 @@ mod tests {
         sector_size: u32,
         total_blocks: u64,
         storage: alloc::vec::Vec<u8>,
+        /// 下一次读取返回 WouldBlock
+        would_block: bool,
+        /// 每次写屏障时已经写入设备的次数
+        barriers: alloc::vec::Vec<usize>,
+        writes: usize,
     }
 
     impl MockDevice {
@@ -374,6 +428,9 @@ mod tests {
                 sector_size,
                 total_blocks,
                 storage,
+                would_block: false,
+                barriers: alloc::vec::Vec::new(),
+                writes: 0,
             }
         }
     }
@@ -392,6 +449,9 @@ mod tests {
         }
 
         fn read_blocks(&mut self, lba: u64, count: u32, buf: &mut [u8]) -> Result<usize> {
+            if core::mem::take(&mut self.would_block) {
+                return Err(crate::error::Error::new(crate::error::ErrorKind::WouldBlock, "device busy"));
+            }
             let start = (lba * self.sector_size as u64) as usize;
             let len = (count * self.sector_size) as usize;
             buf[..len].copy_from_slice(&self.storage[start..start + len]);
@@ -402,8 +462,14 @@ mod tests {
             let start = (lba * self.sector_size as u64) as usize;
             let len = (count * self.sector_size) as usize;
             self.storage[start..start + len].copy_from_slice(&buf[..len]);
+            self.writes += 1;
             Ok(len)
         }
+
+        fn flush_barrier(&mut self) -> Result<()> {
+            self.barriers.push(self.writes);
+            Ok(())
+        }
     }
 
     #[test]
@@ -468,6 +534,22 @@ mod tests {
         }
     }
 
+    #[test]
+    fn test_block_read_only() {
+        let device = MockDevice::new(100);
+        let mut block_dev = BlockDev::new_with_cache(device, 8).unwrap();
+        block_dev.set_read_only(true);
+
+        let mut block = Block::get(&mut block_dev, 0).unwrap();
+        let err = block.with_data_mut(|data| data[0] = 0x42).unwrap_err();
+        assert_eq!(err.kind(), ErrorKind::ReadOnlyFs);
+        block.with_data(|data| assert_eq!(data[0], 0)).unwrap();
+        drop(block);
+
+        assert_eq!(block_dev.write_block(1, &[0u8; 4096]).unwrap_err().kind(), ErrorKind::ReadOnlyFs);
+        assert_eq!(block_dev.cache_stats().unwrap().dirty_blocks, 0);
+    }
+
     #[test]
     fn test_block_modify_without_cache() {
         let device = MockDevice::new(100);
@@ -515,6 +597,25 @@ mod tests {
         }).unwrap();
     }
 
+    #[test]
+    fn test_block_get_noread_released_uninitialized() {
+        let mut device = MockDevice::new(100);
+        device.storage[10 * 4096] = 0x42;
+        let mut block_dev = BlockDev::new_with_cache(device, 8).unwrap();
+
+        {
+            let mut block = Block::get_noread(&mut block_dev, 10).unwrap();
+            // 写入前读取是错误的
+            let err = block.with_data(|data| data[0]).unwrap_err();
+            assert_eq!(err.kind(), ErrorKind::InvalidState);
+            // 没有写入就释放
+        }
+
+        // 之后的读取必须访问磁盘，而不是返回未初始化的缓存块
+        let mut block = Block::get(&mut block_dev, 10).unwrap();
+        assert_eq!(block.with_data(|data| data[0]).unwrap(), 0x42);
+    }
+
     #[test]
     fn test_block_sequential_access() {
         let device = MockDevice::new(100);
@@ -553,4 +654,221 @@ mod tests {
         // 验证可以再次获取
         let _block = Block::get(&mut block_dev, 0).unwrap();
     }
+
+    #[test]
+    fn test_multi_block_read_sees_cached_data() {
+        let device = MockDevice::new(100);
+        let mut block_dev = BlockDev::new_with_cache(device, 8).unwrap();
+
+        {
+            let mut block = Block::get(&mut block_dev, 11).unwrap();
+            block.with_data_mut(|data| data[0] = 0x77).unwrap();
+        }
+
+        let mut buf = alloc::vec![0xEEu8; 4096 * 4];
+        block_dev.read_blocks(10, 4, &mut buf).unwrap();
+        assert_eq!(buf[0], 0);
+        assert_eq!(buf[4096], 0x77);
+        assert_eq!(buf[4096 * 3], 0);
+    }
+
+    #[test]
+    fn test_multi_block_read_after_partial_write() {
+        let mut device = MockDevice::new(100);
+        device.storage[31 * 4096..32 * 4096].fill(0xEE);
+        let mut block_dev = BlockDev::new_with_cache(device, 8).unwrap();
+
+        // 只写入块的前半部分：缓存块是脏的，但没有标记 uptodate
+        {
+            let cache = block_dev.bcache.as_mut().unwrap();
+            let (buf, _) = cache.alloc(31).unwrap();
+            buf.data[..2048].fill(0x33);
+            cache.mark_dirty(31).unwrap();
+        }
+
+        let mut buf = alloc::vec![0u8; 4096 * 3];
+        block_dev.read_blocks(30, 3, &mut buf).unwrap();
+        assert_eq!(buf[4096], 0x33);
+        assert_eq!(buf[4096 + 2047], 0x33);
+
+        let mut single = alloc::vec![0u8; 4096];
+        block_dev.read_block(31, &mut single).unwrap();
+        assert_eq!(single[0], 0x33);
+        assert_eq!(block_dev.cache_stats().unwrap().dirty_blocks, 1);
+    }
+
+    #[test]
+    fn test_multi_block_write_updates_cache() {
+        let device = MockDevice::new(100);
+        let mut block_dev = BlockDev::new_with_cache(device, 8).unwrap();
+
+        {
+            let mut block = Block::get(&mut block_dev, 21).unwrap();
+            block.with_data_mut(|data| data[0] = 0x11).unwrap();
+        }
+
+        let data = alloc::vec![0x42u8; 4096 * 3];
+        block_dev.write_blocks(20, 3, &data).unwrap();
+
+        // 缓存副本已更新且不再是脏块
+        let mut block = Block::get(&mut block_dev, 21).unwrap();
+        block.with_data(|d| assert_eq!(d[0], 0x42)).unwrap();
+        drop(block);
+        assert_eq!(block_dev.cache_stats().unwrap().dirty_blocks, 0);
+
+        let mut raw = alloc::vec![0u8; 4096];
+        block_dev.read_blocks_direct(22, 1, &mut raw).unwrap();
+        assert_eq!(raw[0], 0x42);
+    }
+
+    #[test]
+    fn test_block_get_retry_after_would_block() {
+        let mut device = MockDevice::new(100);
+        device.storage[5 * 4096] = 0x42;
+        let mut block_dev = BlockDev::new_with_cache(device, 8).unwrap();
+
+        block_dev.device_mut().would_block = true;
+        let err = Block::get(&mut block_dev, 5).err().unwrap();
+        assert!(err.is_would_block());
+
+        // 重试时必须重新读取设备，而不是返回半初始化的缓存块
+        let mut block = Block::get(&mut block_dev, 5).unwrap();
+        assert_eq!(block.with_data(|data| data[0]).unwrap(), 0x42);
+    }
+
+    #[test]
+    fn test_barrier_writes_back_dirty_blocks_first() {
+        let device = MockDevice::new(100);
+        let mut block_dev = BlockDev::new_with_cache(device, 8).unwrap();
+
+        for lba in [3, 7] {
+            let mut block = Block::get(&mut block_dev, lba).unwrap();
+            block.with_data_mut(|data| data[0] = 0x5a).unwrap();
+        }
+        block_dev.barrier().unwrap();
+
+        // 屏障发出时两个脏块都已写到设备
+        assert_eq!(block_dev.device().barriers, [2]);
+        assert_eq!(block_dev.cache_stats().unwrap().dirty_blocks, 0);
+        assert_eq!(block_dev.device().storage[7 * 4096], 0x5a);
+    }
+
+    #[test]
+    fn test_block_get_data_not_promoted() {
+        let device = MockDevice::new(100);
+        let mut block_dev = BlockDev::new_with_cache(device, 4).unwrap();
+        block_dev.set_cache_policy(crate::cache::CachePolicy::SegmentedLru);
+
+        Block::get(&mut block_dev, 0).unwrap();
+        // 反复访问的数据块不晋升，之后的顺序读取可以淘汰它
+        for _ in 0..3 {
+            let mut block = Block::get_data(&mut block_dev, 10).unwrap();
+            block.with_data(|data| data[0]).unwrap();
+        }
+        for lba in 20..30 {
+            Block::get_data(&mut block_dev, lba).unwrap();
+        }
+
+        let cache = block_dev.bcache.as_ref().unwrap();
+        assert!(cache.contains(0));
+        assert!(!cache.contains(10));
+    }
+
+    #[test]
+    fn test_block_observer_counts() {
+        static OBSERVER: crate::block::CountingObserver = crate::block::CountingObserver::new();
+
+        let device = MockDevice::new(100);
+        let mut block_dev = BlockDev::new_with_cache(device, 8).unwrap();
+        block_dev.set_observer(Some(&OBSERVER));
+
+        Block::get(&mut block_dev, 5).unwrap();
+        let mut block = Block::get(&mut block_dev, 5).unwrap();
+        block.with_data_mut(|data| data[0] = 1).unwrap();
+        drop(block);
+        block_dev.flush().unwrap();
+
+        let counts = OBSERVER.snapshot();
+        assert_eq!((counts.cache_misses, counts.cache_hits), (1, 1));
+        assert_eq!((counts.device_reads, counts.blocks_read), (1, 1));
+        assert_eq!((counts.device_writes, counts.blocks_written), (1, 1));
+    }
+
+    #[test]
+    fn test_block_set_block_size() {
+        let device = MockDevice::new(4);
+        let mut block_dev = BlockDev::new_with_cache(device, 8).unwrap();
+
+        // 切换前的脏块先写回
+        let mut block = Block::get(&mut block_dev, 1).unwrap();
+        block.with_data_mut(|data| data[0] = 0x11).unwrap();
+        drop(block);
+
+        assert_eq!(block_dev.set_block_size(3072).unwrap_err().kind(), crate::error::ErrorKind::InvalidInput);
+        assert_eq!(block_dev.set_block_size(512).unwrap_err().kind(), crate::error::ErrorKind::InvalidInput);
+        block_dev.set_block_size(1024).unwrap();
+        assert_eq!(block_dev.block_size(), 1024);
+        assert_eq!(block_dev.total_blocks(), 16);
+        assert_eq!(block_dev.device().storage[4096], 0x11);
+
+        // 1 KiB 逻辑块 5 位于设备块 1 的第二个 1 KiB
+        let mut block = Block::get(&mut block_dev, 5).unwrap();
+        block.with_data_mut(|data| {
+            assert_eq!(data.len(), 1024);
+            data[0] = 0x22;
+        }).unwrap();
+        drop(block);
+        block_dev.flush().unwrap();
+        assert_eq!(block_dev.device().storage[5 * 1024], 0x22);
+        assert_eq!(block_dev.device().storage[4096], 0x11);
+    }
+
+    #[test]
+    fn test_block_sector_larger_than_block() {
+        // 4Kn 设备上的 1K 块：写入按扇区读-改-写，同一扇区中的其他块保持不变
+        let mut device = MockDevice::new(4);
+        device.sector_size = 4096;
+        device.storage.iter_mut().for_each(|b| *b = 0xee);
+        let mut block_dev = BlockDev::new(device).unwrap();
+        block_dev.set_block_size(1024).unwrap();
+
+        block_dev.write_block(1, &[0x11; 1024]).unwrap();
+        // 跨扇区的多块写入：首尾都落在扇区中间
+        block_dev.write_blocks(3, 3, &[0x22; 3072]).unwrap();
+
+        let storage = &block_dev.device().storage;
+        assert!(storage[..1024].iter().all(|&b| b == 0xee));
+        assert!(storage[1024..2048].iter().all(|&b| b == 0x11));
+        assert!(storage[2048..3072].iter().all(|&b| b == 0xee));
+        assert!(storage[3072..6144].iter().all(|&b| b == 0x22));
+        assert!(storage[6144..].iter().all(|&b| b == 0xee));
+
+        let mut buf = [0u8; 2048];
+        block_dev.read_blocks_direct(1, 2, &mut buf).unwrap();
+        assert!(buf[..1024].iter().all(|&b| b == 0x11));
+        assert!(buf[1024..].iter().all(|&b| b == 0xee));
+        let mut buf = [0u8; 1024];
+        block_dev.read_block(5, &mut buf).unwrap();
+        assert!(buf.iter().all(|&b| b == 0x22));
+    }
+
+    #[test]
+    fn test_block_unaligned_partition_offset() {
+        // 分区偏移不是扇区对齐
+        let mut device = MockDevice::new(4);
+        device.sector_size = 4096;
+        let mut block_dev = BlockDev::new_partition_with_cache(device, 1024, 3 * 4096, 8).unwrap();
+        block_dev.write_block(0, &[0x33; 4096]).unwrap();
+        block_dev.flush().unwrap();
+
+        let storage = &block_dev.device().storage;
+        assert!(storage[..1024].iter().all(|&b| b == 0));
+        assert!(storage[1024..5120].iter().all(|&b| b == 0x33));
+        assert!(storage[5120..].iter().all(|&b| b == 0));
+
+        let mut buf = [0u8; 4096];
+        block_dev.invalidate_cache_block(0).unwrap();
+        block_dev.read_block(0, &mut buf).unwrap();
+        assert_eq!(buf, [0x33; 4096]);
+    }
 }
@@ -0,0 +1,107 @@
+//! 写入热度图
+//!
+//! 按区域（通常为一个块组）统计实际写到设备上的块数，用于分析闪存磨损：
+//! 哪些区域（日志、inode 表、位图）被频繁写入，从而调整布局或日志大小。
+//!
+//! 计数只保存在内存中，不写入磁盘。为了降低开销可以采样：
+//! 每 `sample_every` 个被写入的块只记录一次，估算值需要乘回采样间隔。
+
+use alloc::vec::Vec;
+
+/// 按区域统计的物理写入计数
+#[derive(Debug, Clone)]
+pub struct WriteHeatMap {
+    /// 第一个区域的起始块号（ext4 中为 `first_data_block`）
+    first_block: u64,
+    /// 每个区域的块数
+    blocks_per_region: u64,
+    /// 采样间隔（1 表示记录每个块）
+    sample_every: u32,
+    /// 距离下一次采样还需写入的块数
+    until_sample: u32,
+    /// 各区域的采样计数，按需增长
+    counts: Vec<u64>,
+}
+
+impl WriteHeatMap {
+    /// 创建热度图
+    ///
+    /// `blocks_per_region` 和 `sample_every` 为 0 时按 1 处理；
+    /// `first_block` 之前的块计入区域 0
+    pub fn new(first_block: u64, blocks_per_region: u64, sample_every: u32) -> Self {
+        let sample_every = sample_every.max(1);
+        Self {
+            first_block,
+            blocks_per_region: blocks_per_region.max(1),
+            sample_every,
+            until_sample: sample_every,
+            counts: Vec::new(),
+        }
+    }
+
+    /// 记录从 `block` 开始的 `count` 个块被写入设备
+    pub fn record(&mut self, block: u64, count: u64) {
+        for b in block..block + count {
+            self.until_sample -= 1;
+            if self.until_sample > 0 {
+                continue;
+            }
+            self.until_sample = self.sample_every;
+
+            let region = (b.saturating_sub(self.first_block) / self.blocks_per_region) as usize;
+            if region >= self.counts.len() {
+                self.counts.resize(region + 1, 0);
+            }
+            self.counts[region] += 1;
+        }
+    }
+
+    /// 每个区域的块数
+    pub fn blocks_per_region(&self) -> u64 {
+        self.blocks_per_region
+    }
+
+    /// 采样间隔
+    pub fn sample_every(&self) -> u32 {
+        self.sample_every
+    }
+
+    /// 各区域的采样计数（下标为区域号，末尾未写入的区域不出现）
+    pub fn samples(&self) -> &[u64] {
+        &self.counts
+    }
+
+    /// 区域 `region` 被写入块数的估算值（采样计数 × 采样间隔）
+    pub fn estimated_writes(&self, region: usize) -> u64 {
+        self.counts.get(region).copied().unwrap_or(0) * self.sample_every as u64
+    }
+
+    /// 清零所有计数
+    pub fn reset(&mut self) {
+        self.counts.clear();
+        self.until_sample = self.sample_every;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_heatmap_regions_and_sampling() {
+        let mut map = WriteHeatMap::new(1, 8, 1);
+        map.record(0, 1); // 引导块计入区域 0
+        map.record(1, 3);
+        map.record(17, 2);
+        assert_eq!(map.samples(), &[4, 0, 2]);
+
+        let mut sampled = WriteHeatMap::new(0, 8, 4);
+        sampled.record(0, 10);
+        assert_eq!(sampled.samples(), &[2]);
+        assert_eq!(sampled.estimated_writes(0), 8);
+        assert_eq!(sampled.estimated_writes(5), 0);
+
+        sampled.reset();
+        assert!(sampled.samples().is_empty());
+    }
+}
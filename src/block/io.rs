@@ -2,9 +2,41 @@
 
 use super::{BlockDev, BlockDevice};
 use crate::error::{Error, ErrorKind, Result};
+use crate::types::{ByteOff, Pblk};
 use alloc::vec;
 
 impl<D: BlockDevice> BlockDev<D> {
+    /// 缓存未命中时从设备读取块
+    ///
+    /// 读取 `lba` 到 `buf`。如果缓存检测到顺序访问，会用一次多块设备读取
+    /// 取回后续的若干块，并把它们作为干净块填入缓存。
+    ///
+    /// 调用者负责把 `buf` 中的数据放入 `lba` 对应的缓存块。
+    pub(super) fn read_miss(&mut self, lba: u64, buf: &mut [u8]) -> Result<()> {
+        let block_size = self.block_size() as usize;
+
+        let end = self.partition_size() / block_size as u64;
+        let blocks = match &mut self.bcache {
+            Some(cache) => cache.readahead_count(lba, end.saturating_sub(lba)),
+            None => 1,
+        };
+
+        self.record_device_read(lba, blocks.max(1) as u64);
+        if blocks <= 1 {
+            self.device_read(lba, 1, &mut buf[..block_size])?;
+            return Ok(());
+        }
+
+        let mut temp = vec![0u8; blocks as usize * block_size];
+        self.device_read(lba, blocks, &mut temp)?;
+        buf[..block_size].copy_from_slice(&temp[..block_size]);
+
+        if let Some(cache) = &mut self.bcache {
+            cache.fill_readahead(lba + 1, &temp[block_size..]);
+        }
+        Ok(())
+    }
+
     /// 读取单个逻辑块
     ///
     /// 从指定逻辑块地址读取一个完整的块到缓冲区。
@@ -12,14 +44,15 @@ impl<D: BlockDevice> BlockDev<D> {
     ///
     /// # 参数
     ///
-    /// * `lba` - 逻辑块地址
+    /// * `lba` - 物理块号（文件系统块）
     /// * `buf` - 目标缓冲区（大小至少为 block_size）
     ///
     /// # 返回
     ///
     /// 成功返回读取的字节数
-    pub fn read_block(&mut self, lba: u64, buf: &mut [u8]) -> Result<usize> {
-        let block_size = self.device().block_size();
+    pub fn read_block(&mut self, lba: impl Into<Pblk>, buf: &mut [u8]) -> Result<usize> {
+        let lba = lba.into().0;
+        let block_size = self.block_size();
 
         if buf.len() < block_size as usize {
             return Err(Error::new(
@@ -37,7 +70,9 @@ impl<D: BlockDevice> BlockDev<D> {
                 Ok(data) => {
                     // 缓存命中
                     buf[..data.len()].copy_from_slice(data);
-                    return Ok(data.len());
+                    let len = data.len();
+                    self.observe_cache(lba, true);
+                    return Ok(len);
                 }
                 Err(_) => true, // 缓存未命中
             }
@@ -46,10 +81,9 @@ impl<D: BlockDevice> BlockDev<D> {
         };
 
         if cache_miss {
-            // 缓存未命中 - 从设备读取到用户缓冲区
-            let pba = self.logical_to_physical(lba);
-            let count = self.sectors_per_block();
-            self.device_mut().read_blocks(pba, count, buf)?;
+            // 缓存未命中 - 从设备读取到用户缓冲区（顺序访问时附带预读）
+            self.observe_cache(lba, false);
+            self.read_miss(lba, &mut buf[..block_size as usize])?;
 
             // 将数据填充到缓存
             if let Some(cache) = &mut self.bcache {
@@ -78,9 +112,8 @@ impl<D: BlockDevice> BlockDev<D> {
         }
 
         // 无缓存 - 直接从设备读取
-        let pba = self.logical_to_physical(lba);
-        let count = self.sectors_per_block();
-        self.device_mut().read_blocks(pba, count, buf)
+        self.record_device_read(lba, 1);
+        self.device_read(lba, 1, buf)
     }
 
     /// 写入单个逻辑块
@@ -90,14 +123,16 @@ impl<D: BlockDevice> BlockDev<D> {
     ///
     /// # 参数
     ///
-    /// * `lba` - 逻辑块地址
+    /// * `lba` - 物理块号（文件系统块）
     /// * `buf` - 源数据缓冲区（大小至少为 block_size）
     ///
     /// # 返回
     ///
     /// 成功返回写入的字节数
-    pub fn write_block(&mut self, lba: u64, buf: &[u8]) -> Result<usize> {
-        let block_size = self.device().block_size();
+    pub fn write_block(&mut self, lba: impl Into<Pblk>, buf: &[u8]) -> Result<usize> {
+        self.check_writable()?;
+        let lba = lba.into().0;
+        let block_size = self.block_size();
 
         if buf.len() < block_size as usize {
             return Err(Error::new(
@@ -107,6 +142,7 @@ impl<D: BlockDevice> BlockDev<D> {
         }
 
         self.inc_write_count();
+        self.meta.invalidate_range(lba, 1);
 
         // 如果启用了缓存，写入缓存
         if let Some(cache) = &mut self.bcache {
@@ -114,6 +150,7 @@ impl<D: BlockDevice> BlockDev<D> {
             match cache.write_block(lba, buf) {
                 Ok(n) => {
                     // 块已在缓存中，写入成功
+                    self.writeback_over_high_water()?;
                     return Ok(n);
                 }
                 Err(_) => {
@@ -146,15 +183,136 @@ impl<D: BlockDevice> BlockDev<D> {
 
                     // ✅ lru crate 自动管理生命周期，无需手动 free
 
+                    self.writeback_over_high_water()?;
                     return Ok(buf.len());
                 }
             }
         }
 
         // 无缓存 - 直接写入设备
-        let pba = self.logical_to_physical(lba);
-        let count = self.sectors_per_block();
-        self.device_mut().write_blocks(pba, count, buf)
+        let written = self.device_write(lba, 1, buf)?;
+        self.record_device_write(lba, 1);
+        Ok(written)
+    }
+
+    /// 读取连续的多个逻辑块
+    ///
+    /// 已缓存的块从缓存复制，其余连续的未缓存块合并为一次设备读取，
+    /// 便于 NVMe/virtio 等后端发出单个大 DMA 请求。
+    /// 合并读取的块不会填入缓存，避免大块顺序读冲掉元数据缓存；
+    /// 孤立的单个未缓存块仍走 [`read_block`](Self::read_block) 的缓存路径。
+    ///
+    /// # 参数
+    ///
+    /// * `lba` - 起始物理块号（文件系统块）
+    /// * `count` - 块数量
+    /// * `buf` - 目标缓冲区（大小至少为 count * block_size）
+    ///
+    /// # 返回
+    ///
+    /// 成功返回读取的字节数
+    pub fn read_blocks(&mut self, lba: impl Into<Pblk>, count: u32, buf: &mut [u8]) -> Result<usize> {
+        let lba = lba.into().0;
+        let block_size = self.block_size() as usize;
+        let total = count as usize * block_size;
+        if buf.len() < total {
+            return Err(Error::new(
+                ErrorKind::InvalidInput,
+                "Buffer too small for requested blocks",
+            ));
+        }
+
+        let mut i = 0u32;
+        while i < count {
+            // 已缓存的块（可能比磁盘新）直接从缓存复制
+            if let Some(cache) = &self.bcache {
+                if let Ok(data) = cache.read_block(lba + i as u64) {
+                    let off = i as usize * block_size;
+                    buf[off..off + block_size].copy_from_slice(data);
+                    self.inc_read_count();
+                    self.observe_cache(lba + i as u64, true);
+                    i += 1;
+                    continue;
+                }
+            }
+
+            // 收集连续的未缓存块
+            let start = i;
+            i += 1;
+            while i < count && !self.is_cached(lba + i as u64) {
+                i += 1;
+            }
+
+            let run = i - start;
+            let off = start as usize * block_size;
+            if run == 1 {
+                // 单块走常规缓存路径，顺序的小读取仍可触发预读
+                self.read_block(lba + start as u64, &mut buf[off..off + block_size])?;
+                continue;
+            }
+
+            self.inc_read_count();
+            self.record_device_read(lba + start as u64, run as u64);
+            for i in start..start + run {
+                self.observe_cache(lba + i as u64, false);
+            }
+            self.device_read(lba + start as u64, run, &mut buf[off..off + run as usize * block_size])?;
+        }
+
+        Ok(total)
+    }
+
+    /// 写入连续的多个逻辑块
+    ///
+    /// 单个块或写回模式下逐块写入缓存（保持延迟写语义）；否则整段数据
+    /// 通过一次设备写入落盘，并同步更新已缓存的副本。
+    ///
+    /// # 参数
+    ///
+    /// * `lba` - 起始物理块号（文件系统块）
+    /// * `count` - 块数量
+    /// * `buf` - 源数据缓冲区（大小至少为 count * block_size）
+    ///
+    /// # 返回
+    ///
+    /// 成功返回写入的字节数
+    pub fn write_blocks(&mut self, lba: impl Into<Pblk>, count: u32, buf: &[u8]) -> Result<usize> {
+        self.check_writable()?;
+        let lba = lba.into().0;
+        let block_size = self.block_size() as usize;
+        let total = count as usize * block_size;
+        if buf.len() < total {
+            return Err(Error::new(
+                ErrorKind::InvalidInput,
+                "Buffer too small for requested blocks",
+            ));
+        }
+
+        if count == 1 || self.is_write_back_enabled() {
+            for (i, chunk) in buf[..total].chunks_exact(block_size).enumerate() {
+                self.write_block(lba + i as u64, chunk)?;
+            }
+            return Ok(total);
+        }
+
+        self.inc_write_count();
+        self.inc_physical_write_count();
+        self.device_write(lba, count, &buf[..total])?;
+        self.record_device_write(lba, count as u64);
+        self.meta.invalidate_range(lba, count as u64);
+
+        if let Some(cache) = &mut self.bcache {
+            for (i, chunk) in buf[..total].chunks_exact(block_size).enumerate() {
+                cache.update_clean(lba + i as u64, chunk);
+            }
+        }
+
+        Ok(total)
+    }
+
+    /// 块是否在缓存中且数据有效（uptodate 或脏）
+    fn is_cached(&self, lba: u64) -> bool {
+        self.bcache.as_ref().is_some_and(|cache| cache.contains(lba))
     }
 
     /// 读取字节
@@ -176,9 +334,10 @@ impl<D: BlockDevice> BlockDev<D> {
     /// let mut buf = vec![0u8; 100];
     /// block_dev.read_bytes(1024, &mut buf)?;
     /// ```
-    pub fn read_bytes(&mut self, offset: u64, buf: &mut [u8]) -> Result<usize> {
+    pub fn read_bytes(&mut self, offset: impl Into<ByteOff>, buf: &mut [u8]) -> Result<usize> {
+        let offset = offset.into().0;
         let len = buf.len();
-        let block_size = self.device().block_size() as u64;
+        let block_size = self.block_size() as u64;
 
         // 计算起始块和块内偏移
         let start_block = offset / block_size;
@@ -223,9 +382,11 @@ impl<D: BlockDevice> BlockDev<D> {
     /// let data = b"Hello, ext4!";
     /// block_dev.write_bytes(1024, data)?;
     /// ```
-    pub fn write_bytes(&mut self, offset: u64, buf: &[u8]) -> Result<usize> {
+    pub fn write_bytes(&mut self, offset: impl Into<ByteOff>, buf: &[u8]) -> Result<usize> {
+        self.check_writable()?;
+        let offset = offset.into().0;
         let len = buf.len();
-        let block_size = self.device().block_size() as u64;
+        let block_size = self.block_size() as u64;
 
         let start_block = offset / block_size;
         let block_offset = (offset % block_size) as usize;
@@ -263,19 +424,31 @@ impl<D: BlockDevice> BlockDev<D> {
     ///
     /// 如果启用了缓存，先刷新所有脏块到设备，然后调用设备的 flush。
     /// 这是两层刷新：缓存层和硬件层。
+    pub fn flush(&mut self) -> Result<()> {
+        self.write_back_dirty()?;
+
+        // 第二层：调用设备的硬件刷新（如 fsync）
+        self.device_mut().flush()
+    }
+
+    /// 写屏障
+    ///
+    /// 先把缓存中的脏块写到设备，再调用设备的
+    /// [`flush_barrier`](super::BlockDevice::flush_barrier)：屏障之前的写入
+    /// 一定先于之后的写入落盘。
+    pub fn barrier(&mut self) -> Result<()> {
+        self.write_back_dirty()?;
+        self.device_mut().flush_barrier()
+    }
+
     /// 刷新所有缓存的脏块到磁盘
     ///
     /// 这是架构重构后的新实现：
     /// - BlockCache提供脏块列表和数据
     /// - BlockDev负责实际的I/O操作
     /// - 职责清晰，无借用冲突
-    pub fn flush(&mut self) -> Result<()> {
+    pub(super) fn write_back_dirty(&mut self) -> Result<()> {
         // 第一层：刷新缓存中的脏块
-        // 先获取必要的参数（避免借用冲突）
-        let sector_size = self.device().sector_size();
-        let partition_offset = self.partition_offset();
-        let block_size = self.block_size();
-
         let dirty_blocks = if let Some(cache) = &mut self.bcache {
             cache.get_dirty_blocks()
         } else {
@@ -300,9 +473,8 @@ impl<D: BlockDevice> BlockDev<D> {
                 };
 
                 // 进行I/O操作（此时没有cache借用）
-                let pba = (lba * block_size as u64 + partition_offset) / sector_size as u64;
-                let count = (block_size as usize + sector_size as usize - 1) / sector_size as usize;
-                self.device_mut().write_blocks(pba, count as u32, &data)?;
+                self.device_write(lba, 1, &data)?;
+                self.record_device_write(lba, 1);
 
                 // 标记为clean
                 if let Some(cache) = &mut self.bcache {
@@ -313,7 +485,6 @@ impl<D: BlockDevice> BlockDev<D> {
             log::debug!("[BlockDev] Flushed {} blocks successfully", dirty_count);
         }
 
-        // 第二层：调用设备的硬件刷新（如 fsync）
-        self.device_mut().flush()
+        Ok(())
     }
 }
@@ -43,6 +43,7 @@ pub trait DeviceLock: Send {
 /// 用于单线程环境或已知不需要并发保护的场景
 /// TODO:有待完善,当前项目并没有充分考虑并发访问的情况，比如多个线程同时访问同一个块设备，或者多个线程同时访问同一个块。
 /// 也许在更高层直接对整个fs进行加锁，而不是对单个块设备进行加锁，这样做更加简单
+#[derive(Default)]
 pub struct NoLock;
 
 impl DeviceLock for NoLock {
@@ -65,12 +66,12 @@ mod std_locks {
     /// Mutex 锁实现
     impl DeviceLock for Mutex<()> {
         fn lock(&self) -> Result<()> {
-            let _ = self.lock().map_err(|_| {
+            drop(self.lock().map_err(|_| {
                 crate::error::Error::new(
                     crate::error::ErrorKind::Io,
                     "Failed to acquire mutex lock",
                 )
-            })?;
+            })?);
             Ok(())
         }
 
@@ -84,12 +85,12 @@ mod std_locks {
     /// RwLock 写锁实现
     impl DeviceLock for RwLock<()> {
         fn lock(&self) -> Result<()> {
-            let _ = self.write().map_err(|_| {
+            drop(self.write().map_err(|_| {
                 crate::error::Error::new(
                     crate::error::ErrorKind::Io,
                     "Failed to acquire write lock",
                 )
-            })?;
+            })?);
             Ok(())
         }
 
@@ -0,0 +1,182 @@
+//! 内存块设备
+//!
+//! 以 `Vec<u8>` 为存储的 RAM 盘，只依赖 `alloc`。用于测试、示例，
+//! 以及在内存中构建或检查镜像（例如把整个镜像读入内存后挂载）。
+
+use super::BlockDevice;
+use crate::error::{Error, ErrorKind, Result};
+use alloc::vec::Vec;
+
+/// 默认块大小
+const DEFAULT_BLOCK_SIZE: u32 = 4096;
+/// 默认扇区大小
+const DEFAULT_SECTOR_SIZE: u32 = 512;
+
+/// 内存块设备（RAM 盘）
+///
+/// # 示例
+///
+/// ```rust,ignore
+/// use lwext4_core::{BlockDev, Ext4FileSystem, MemBlockDevice};
+///
+/// let image = std::fs::read("rootfs.ext4")?;
+/// let dev = MemBlockDevice::from_vec(image)?;
+/// let mut fs = Ext4FileSystem::mount(BlockDev::new_with_cache(dev, 256)?)?;
+/// fs.write_file("/etc/hostname", b"ramdisk")?;
+///
+/// let image = fs.unmount()?.device().as_bytes().to_vec();
+/// ```
+#[derive(Debug, Clone)]
+pub struct MemBlockDevice {
+    data: Vec<u8>,
+    block_size: u32,
+    sector_size: u32,
+    read_only: bool,
+}
+
+impl MemBlockDevice {
+    /// 创建 `total_blocks` 个 4K 块的全零设备，扇区大小 512
+    pub fn new(total_blocks: u64) -> Self {
+        let len = total_blocks as usize * DEFAULT_BLOCK_SIZE as usize;
+        Self {
+            data: alloc::vec![0u8; len],
+            block_size: DEFAULT_BLOCK_SIZE,
+            sector_size: DEFAULT_SECTOR_SIZE,
+            read_only: false,
+        }
+    }
+
+    /// 使用已有的镜像数据创建设备，块大小 4096、扇区大小 512
+    ///
+    /// # 错误
+    ///
+    /// - `ErrorKind::InvalidInput` - 数据长度不是块大小的整数倍
+    pub fn from_vec(data: Vec<u8>) -> Result<Self> {
+        Self::with_geometry(data, DEFAULT_BLOCK_SIZE, DEFAULT_SECTOR_SIZE)
+    }
+
+    /// 使用指定的块大小和扇区大小创建设备
+    ///
+    /// # 错误
+    ///
+    /// - `ErrorKind::InvalidInput` - 扇区大小为 0、块大小不是扇区大小的整数倍，
+    ///   或数据长度不是块大小的整数倍
+    pub fn with_geometry(data: Vec<u8>, block_size: u32, sector_size: u32) -> Result<Self> {
+        if sector_size == 0 || block_size == 0 || block_size % sector_size != 0 {
+            return Err(Error::new(ErrorKind::InvalidInput, "Block size must be a multiple of sector size"));
+        }
+        if data.len() % block_size as usize != 0 {
+            return Err(Error::new(ErrorKind::InvalidInput, "Image size must be a multiple of block size"));
+        }
+        Ok(Self { data, block_size, sector_size, read_only: false })
+    }
+
+    /// 设置只读，只读设备上的写入返回 `ErrorKind::ReadOnlyFs`
+    pub fn set_read_only(&mut self, read_only: bool) {
+        self.read_only = read_only;
+    }
+
+    /// 设备内容
+    pub fn as_bytes(&self) -> &[u8] {
+        &self.data
+    }
+
+    /// 设备内容的可变引用，直接修改不经过块缓存
+    pub fn as_bytes_mut(&mut self) -> &mut [u8] {
+        &mut self.data
+    }
+
+    /// 取出设备内容
+    pub fn into_vec(self) -> Vec<u8> {
+        self.data
+    }
+
+    /// 扇区范围对应的字节范围，越界时返回错误
+    fn byte_range(&self, lba: u64, count: u32, buf_len: usize) -> Result<core::ops::Range<usize>> {
+        let ss = self.sector_size as u64;
+        let len = count as u64 * ss;
+        if (buf_len as u64) < len {
+            return Err(Error::new(ErrorKind::InvalidInput, "Buffer too small"));
+        }
+        let start = lba.checked_mul(ss).filter(|&start| start + len <= self.data.len() as u64);
+        let start = start.ok_or(Error::new(ErrorKind::InvalidInput, "Access beyond end of device"))?;
+        Ok(start as usize..(start + len) as usize)
+    }
+}
+
+impl BlockDevice for MemBlockDevice {
+    fn block_size(&self) -> u32 {
+        self.block_size
+    }
+
+    fn sector_size(&self) -> u32 {
+        self.sector_size
+    }
+
+    fn total_blocks(&self) -> u64 {
+        (self.data.len() / self.block_size as usize) as u64
+    }
+
+    fn read_blocks(&mut self, lba: u64, count: u32, buf: &mut [u8]) -> Result<usize> {
+        let range = self.byte_range(lba, count, buf.len())?;
+        let len = range.len();
+        buf[..len].copy_from_slice(&self.data[range]);
+        Ok(len)
+    }
+
+    fn write_blocks(&mut self, lba: u64, count: u32, buf: &[u8]) -> Result<usize> {
+        if self.read_only {
+            return Err(Error::new(ErrorKind::ReadOnlyFs, "Device is read-only"));
+        }
+        let range = self.byte_range(lba, count, buf.len())?;
+        let len = range.len();
+        self.data[range].copy_from_slice(&buf[..len]);
+        Ok(len)
+    }
+
+    fn is_read_only(&self) -> bool {
+        self.read_only
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::block::BlockDev;
+
+    #[test]
+    fn test_mem_device_read_write() {
+        let mut dev = MemBlockDevice::new(4);
+        assert_eq!(dev.total_blocks(), 4);
+        dev.write_blocks(8, 8, &[0xab; 4096]).unwrap();
+
+        let mut buf = [0u8; 1024];
+        dev.read_blocks(15, 2, &mut buf).unwrap();
+        assert!(buf[..512].iter().all(|&b| b == 0xab));
+        assert!(buf[512..].iter().all(|&b| b == 0));
+        assert_eq!(dev.as_bytes()[4096], 0xab);
+
+        // 越界
+        assert!(dev.read_blocks(31, 2, &mut buf).is_err());
+        assert!(dev.read_blocks(u64::MAX, 1, &mut buf).is_err());
+    }
+
+    #[test]
+    fn test_mem_device_geometry() {
+        assert!(MemBlockDevice::from_vec(alloc::vec![0u8; 4096 + 512]).is_err());
+        assert!(MemBlockDevice::with_geometry(alloc::vec![0u8; 4096], 1000, 512).is_err());
+        let dev = MemBlockDevice::with_geometry(alloc::vec![0u8; 8192], 1024, 512).unwrap();
+        assert_eq!(dev.total_blocks(), 8);
+    }
+
+    #[test]
+    fn test_mem_device_read_only() {
+        let mut dev = MemBlockDevice::new(1);
+        dev.set_read_only(true);
+        let err = dev.write_blocks(0, 1, &[1u8; 512]).unwrap_err();
+        assert_eq!(err.kind(), ErrorKind::ReadOnlyFs);
+
+        let block_dev = BlockDev::new(dev).unwrap();
+        assert!(block_dev.is_read_only());
+    }
+}
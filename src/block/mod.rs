@@ -7,14 +7,38 @@
 //! 但io.rs所有函数都需要提供vec buffer, 从vec buffer读入数据写到cache 或者从cache读出数据写到vec buffer
 
 //! block/handle 可以提供对某块cache的引用， 保证一致性 
-//! TODO:需要进一步评估io handle device实现的方法的冗余情况，也许同时提供了多个实现，但是其实实现的功能是类似的，也许可以合并。
-//! 另外，在模块外部调用这些方法时，有些地方使用了A实现，而有些地方使用了B实现，也许可以统一使用A实现，或者统一使用B实现。
+//! block/overlay 提供只读底层设备 + 写时复制覆盖层的组合设备
+//! block/heatmap 按区域统计实际写入设备的块数，用于闪存磨损分析
+//! block/mem 和 block/file 是内存 RAM 盘和镜像文件（`std` 特性）两个现成的设备实现
+//! block/partition 解析 MBR/GPT 分区表，并把一个分区作为独立的设备
+//!
+//! 文件系统代码访问块的约定：
+//! - 读取或修改单个块（元数据，以及文件数据的部分块读-改-写）使用 [`Block`]：
+//!   元数据用 `Block::get`/`get_noread`，文件数据用 `Block::get_data`/`get_data_noread`
+//! - 整段文件数据使用 io.rs 的 `read_blocks`/`write_blocks`，它们先查缓存、再合并设备 I/O，
+//!   与缓存保持一致
+//! - device.rs 的 `*_direct` 绕过缓存，只用于有意检查介质的场景（如 scrub），
+//!   不能用于读取可能有未写回修改的块
 
 mod device;
 mod io;
 mod handle;
 mod lock;
+mod overlay;
+mod heatmap;
+mod observer;
+mod mem;
+#[cfg(feature = "std")]
+mod file;
+pub mod partition;
 
 pub use device::{BlockDevice, BlockDev};
 pub use handle::Block;
 pub use lock::{DeviceLock, NoLock};
+pub use overlay::{MemoryOverlay, OverlayDevice, OverlayStore};
+pub use heatmap::WriteHeatMap;
+pub use observer::{CountingObserver, FsObserver, FsOp, NoopObserver, ObserverCounts, OpStats};
+pub use mem::MemBlockDevice;
+pub use partition::PartitionDevice;
+#[cfg(feature = "std")]
+pub use file::FileBlockDevice;
@@ -0,0 +1,366 @@
+//! 文件系统观测钩子
+//!
+//! 嵌入方实现 [`FsObserver`] 并通过 [`FsConfig::observer`](crate::fs::FsConfig::observer)
+//! 或 [`BlockDev::set_observer`](super::BlockDev::set_observer) 注册后，块设备读写、
+//! 块缓存命中/未命中、分配器换块组重试、日志提交和文件系统操作的耗时都会回调到它，
+//! 不需要修改本 crate 就能导出监控指标。
+//!
+//! 回调在持有文件系统的 `&mut` 时同步调用，应当尽快返回（例如只更新原子计数）。
+//! 观测者以 `&'static` 引用注册，通常是一个 `static` 变量。
+//! [`CountingObserver`] 是一个只做计数的简单实现。
+
+use core::{
+    sync::atomic::{AtomicU64, Ordering},
+    time::Duration,
+};
+
+/// 被观测的文件系统操作
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum FsOp {
+    /// 路径或目录项查找
+    Lookup,
+    /// 读取元数据（stat）
+    Stat,
+    /// 读取目录
+    ReadDir,
+    /// 读取文件数据
+    Read,
+    /// 写入文件数据
+    Write,
+    /// 创建文件、目录或符号链接
+    Create,
+    /// 删除文件或目录
+    Remove,
+    /// 重命名
+    Rename,
+    /// 截断
+    Truncate,
+    /// 单个 inode 的 fsync
+    Fsync,
+    /// 整个文件系统的提交（定时提交、`fsync`）
+    Commit,
+}
+
+impl FsOp {
+    /// 操作种类数
+    pub const COUNT: usize = 11;
+
+    /// 所有操作，顺序与 [`index`](Self::index) 一致
+    pub const ALL: [FsOp; Self::COUNT] = [
+        FsOp::Lookup,
+        FsOp::Stat,
+        FsOp::ReadDir,
+        FsOp::Read,
+        FsOp::Write,
+        FsOp::Create,
+        FsOp::Remove,
+        FsOp::Rename,
+        FsOp::Truncate,
+        FsOp::Fsync,
+        FsOp::Commit,
+    ];
+
+    /// 操作在 [`ALL`](Self::ALL) 中的下标
+    pub const fn index(self) -> usize {
+        self as usize
+    }
+
+    /// 操作名称（小写）
+    pub const fn name(self) -> &'static str {
+        match self {
+            FsOp::Lookup => "lookup",
+            FsOp::Stat => "stat",
+            FsOp::ReadDir => "readdir",
+            FsOp::Read => "read",
+            FsOp::Write => "write",
+            FsOp::Create => "create",
+            FsOp::Remove => "remove",
+            FsOp::Rename => "rename",
+            FsOp::Truncate => "truncate",
+            FsOp::Fsync => "fsync",
+            FsOp::Commit => "commit",
+        }
+    }
+}
+
+/// 文件系统观测者
+///
+/// 所有回调都有空的默认实现，只需覆盖关心的部分。
+///
+/// # 示例
+///
+/// ```rust,ignore
+/// static METRICS: CountingObserver = CountingObserver::new();
+///
+/// let config = FsConfig { observer: Some(&METRICS), ..FsConfig::default() };
+/// let mut fs = Ext4FileSystem::mount_with_config(bdev, config)?;
+/// fs.read_dir("/")?;
+/// println!("{:?}", METRICS.snapshot());
+/// ```
+pub trait FsObserver: Sync {
+    /// 当前时间，用于计算操作耗时
+    ///
+    /// 默认返回 `None`，此时 [`on_op`](Self::on_op) 收到的耗时为 `None`
+    fn now(&self) -> Option<Duration> {
+        None
+    }
+
+    /// 从设备读取了从 `lba` 开始的 `count` 个块（缓存未命中、预读、直接读取）
+    fn on_block_read(&self, _lba: u64, _count: u32) {}
+
+    /// 向设备写入了从 `lba` 开始的 `count` 个块
+    fn on_block_write(&self, _lba: u64, _count: u32) {}
+
+    /// 块缓存命中
+    fn on_cache_hit(&self, _lba: u64) {}
+
+    /// 块缓存未命中
+    fn on_cache_miss(&self, _lba: u64) {}
+
+    /// 块分配器在目标块组分配失败后，转到块组 `group` 重试
+    fn on_alloc_retry(&self, _group: u32) {}
+
+    /// 日志事务 `tid` 已提交，共写入 `blocks` 个日志块
+    fn on_journal_commit(&self, _tid: u64, _blocks: u32) {}
+
+    /// 一次文件系统操作结束
+    ///
+    /// 只报告最外层的操作：例如 `create_file` 内部的路径查找不会单独报告为
+    /// [`FsOp::Lookup`]
+    fn on_op(&self, _op: FsOp, _elapsed: Option<Duration>, _ok: bool) {}
+}
+
+impl core::fmt::Debug for dyn FsObserver {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        f.write_str("FsObserver")
+    }
+}
+
+/// 不做任何事的观测者
+#[derive(Debug, Clone, Copy, Default)]
+pub struct NoopObserver;
+
+impl FsObserver for NoopObserver {}
+
+/// 单种操作的统计
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct OpStats {
+    /// 次数
+    pub count: u64,
+    /// 失败次数
+    pub errors: u64,
+    /// 总耗时（只累计能取得时间的操作）
+    pub total_time: Duration,
+}
+
+/// [`CountingObserver`] 的计数快照
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct ObserverCounts {
+    /// 设备读取次数
+    pub device_reads: u64,
+    /// 从设备读取的块数
+    pub blocks_read: u64,
+    /// 设备写入次数
+    pub device_writes: u64,
+    /// 写入设备的块数
+    pub blocks_written: u64,
+    /// 块缓存命中次数
+    pub cache_hits: u64,
+    /// 块缓存未命中次数
+    pub cache_misses: u64,
+    /// 分配器重试次数
+    pub alloc_retries: u64,
+    /// 日志提交次数
+    pub journal_commits: u64,
+    /// 各操作的统计，按 [`FsOp::index`] 排列
+    pub ops: [OpStats; FsOp::COUNT],
+}
+
+impl ObserverCounts {
+    /// 某种操作的统计
+    pub fn op(&self, op: FsOp) -> OpStats {
+        self.ops[op.index()]
+    }
+}
+
+/// 只做计数的观测者
+///
+/// 所有计数都是原子变量，可以放在 `static` 中并在其他线程读取快照。
+/// 需要统计耗时时用 [`with_clock`](Self::with_clock) 提供时钟。
+#[derive(Debug)]
+pub struct CountingObserver {
+    clock: Option<fn() -> Option<Duration>>,
+    device_reads: AtomicU64,
+    blocks_read: AtomicU64,
+    device_writes: AtomicU64,
+    blocks_written: AtomicU64,
+    cache_hits: AtomicU64,
+    cache_misses: AtomicU64,
+    alloc_retries: AtomicU64,
+    journal_commits: AtomicU64,
+    op_counts: [AtomicU64; FsOp::COUNT],
+    op_errors: [AtomicU64; FsOp::COUNT],
+    /// 各操作的总耗时（纳秒）
+    op_nanos: [AtomicU64; FsOp::COUNT],
+}
+
+impl CountingObserver {
+    /// 创建不统计耗时的计数器
+    pub const fn new() -> Self {
+        Self {
+            clock: None,
+            device_reads: AtomicU64::new(0),
+            blocks_read: AtomicU64::new(0),
+            device_writes: AtomicU64::new(0),
+            blocks_written: AtomicU64::new(0),
+            cache_hits: AtomicU64::new(0),
+            cache_misses: AtomicU64::new(0),
+            alloc_retries: AtomicU64::new(0),
+            journal_commits: AtomicU64::new(0),
+            op_counts: [const { AtomicU64::new(0) }; FsOp::COUNT],
+            op_errors: [const { AtomicU64::new(0) }; FsOp::COUNT],
+            op_nanos: [const { AtomicU64::new(0) }; FsOp::COUNT],
+        }
+    }
+
+    /// 创建用 `clock` 计时的计数器，`clock` 通常是 [`SystemHal::now`](crate::fs::SystemHal::now)
+    pub const fn with_clock(clock: fn() -> Option<Duration>) -> Self {
+        let mut observer = Self::new();
+        observer.clock = Some(clock);
+        observer
+    }
+
+    /// 读取当前计数
+    pub fn snapshot(&self) -> ObserverCounts {
+        let load = |counter: &AtomicU64| counter.load(Ordering::Relaxed);
+        let mut ops = [OpStats::default(); FsOp::COUNT];
+        for (i, stats) in ops.iter_mut().enumerate() {
+            *stats = OpStats {
+                count: load(&self.op_counts[i]),
+                errors: load(&self.op_errors[i]),
+                total_time: Duration::from_nanos(load(&self.op_nanos[i])),
+            };
+        }
+
+        ObserverCounts {
+            device_reads: load(&self.device_reads),
+            blocks_read: load(&self.blocks_read),
+            device_writes: load(&self.device_writes),
+            blocks_written: load(&self.blocks_written),
+            cache_hits: load(&self.cache_hits),
+            cache_misses: load(&self.cache_misses),
+            alloc_retries: load(&self.alloc_retries),
+            journal_commits: load(&self.journal_commits),
+            ops,
+        }
+    }
+
+    /// 清零所有计数
+    pub fn reset(&self) {
+        let counters = [
+            &self.device_reads,
+            &self.blocks_read,
+            &self.device_writes,
+            &self.blocks_written,
+            &self.cache_hits,
+            &self.cache_misses,
+            &self.alloc_retries,
+            &self.journal_commits,
+        ];
+        let per_op = self.op_counts.iter().chain(&self.op_errors).chain(&self.op_nanos);
+        for counter in counters.into_iter().chain(per_op) {
+            counter.store(0, Ordering::Relaxed);
+        }
+    }
+}
+
+impl Default for CountingObserver {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl FsObserver for CountingObserver {
+    fn now(&self) -> Option<Duration> {
+        self.clock.and_then(|clock| clock())
+    }
+
+    fn on_block_read(&self, _lba: u64, count: u32) {
+        self.device_reads.fetch_add(1, Ordering::Relaxed);
+        self.blocks_read.fetch_add(count as u64, Ordering::Relaxed);
+    }
+
+    fn on_block_write(&self, _lba: u64, count: u32) {
+        self.device_writes.fetch_add(1, Ordering::Relaxed);
+        self.blocks_written.fetch_add(count as u64, Ordering::Relaxed);
+    }
+
+    fn on_cache_hit(&self, _lba: u64) {
+        self.cache_hits.fetch_add(1, Ordering::Relaxed);
+    }
+
+    fn on_cache_miss(&self, _lba: u64) {
+        self.cache_misses.fetch_add(1, Ordering::Relaxed);
+    }
+
+    fn on_alloc_retry(&self, _group: u32) {
+        self.alloc_retries.fetch_add(1, Ordering::Relaxed);
+    }
+
+    fn on_journal_commit(&self, _tid: u64, _blocks: u32) {
+        self.journal_commits.fetch_add(1, Ordering::Relaxed);
+    }
+
+    fn on_op(&self, op: FsOp, elapsed: Option<Duration>, ok: bool) {
+        let i = op.index();
+        self.op_counts[i].fetch_add(1, Ordering::Relaxed);
+        if !ok {
+            self.op_errors[i].fetch_add(1, Ordering::Relaxed);
+        }
+        if let Some(elapsed) = elapsed {
+            self.op_nanos[i].fetch_add(elapsed.as_nanos() as u64, Ordering::Relaxed);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_op_index() {
+        for (i, op) in FsOp::ALL.iter().enumerate() {
+            assert_eq!(op.index(), i);
+        }
+    }
+
+    #[test]
+    fn test_counting_observer() {
+        fn clock() -> Option<Duration> {
+            Some(Duration::from_millis(1))
+        }
+
+        let observer = CountingObserver::with_clock(clock);
+        observer.on_block_read(10, 4);
+        observer.on_block_write(20, 1);
+        observer.on_block_write(21, 2);
+        observer.on_cache_hit(10);
+        observer.on_cache_miss(11);
+        observer.on_alloc_retry(3);
+        observer.on_op(FsOp::Write, observer.now(), true);
+        observer.on_op(FsOp::Write, Some(Duration::from_micros(5)), false);
+
+        let counts = observer.snapshot();
+        assert_eq!((counts.device_reads, counts.blocks_read), (1, 4));
+        assert_eq!((counts.device_writes, counts.blocks_written), (2, 3));
+        assert_eq!((counts.cache_hits, counts.cache_misses, counts.alloc_retries), (1, 1, 1));
+        assert_eq!(
+            counts.op(FsOp::Write),
+            OpStats { count: 2, errors: 1, total_time: Duration::from_micros(1005) }
+        );
+        assert_eq!(counts.op(FsOp::Read), OpStats::default());
+
+        observer.reset();
+        assert_eq!(observer.snapshot(), ObserverCounts::default());
+    }
+}
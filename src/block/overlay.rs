@@ -0,0 +1,367 @@
+//! 写时复制的覆盖层设备
+//!
+//! 用于 ROM 上的出厂镜像：底层设备只读，所有修改按块写入覆盖层，
+//! 读取时优先返回覆盖层中的数据。文件系统可以像普通设备一样挂载并写入，
+//! 而底层镜像保持不变，丢弃覆盖层即可回到原始状态。
+
+use super::BlockDevice;
+use crate::error::{Error, ErrorKind, Result};
+use alloc::{
+    boxed::Box,
+    collections::BTreeMap,
+    vec::Vec,
+};
+
+/// 覆盖层存储接口
+///
+/// 以设备块（`BlockDevice::block_size()`）为单位保存被修改过的块。
+/// 默认提供内存实现 [`MemoryOverlay`]，也可以实现此 trait 把修改
+/// 持久化到另一块可写设备或文件中。
+pub trait OverlayStore {
+    /// 读取覆盖层中的块
+    ///
+    /// 块存在时复制到 `buf` 并返回 `true`，不存在返回 `false`
+    fn read(&mut self, block: u64, buf: &mut [u8]) -> Result<bool>;
+
+    /// 写入（或替换）覆盖层中的块，`data` 长度恰好为一个块
+    fn write(&mut self, block: u64, data: &[u8]) -> Result<()>;
+
+    /// 块是否已在覆盖层中
+    fn contains(&self, block: u64) -> bool;
+
+    /// 覆盖层中所有块的块号（升序）
+    fn blocks(&self) -> Vec<u64>;
+
+    /// 丢弃所有修改
+    fn clear(&mut self) -> Result<()>;
+
+    /// 刷新覆盖层
+    fn flush(&mut self) -> Result<()> {
+        Ok(())
+    }
+}
+
+/// 内存覆盖层
+#[derive(Debug, Default)]
+pub struct MemoryOverlay {
+    blocks: BTreeMap<u64, Box<[u8]>>,
+}
+
+impl MemoryOverlay {
+    /// 创建空的内存覆盖层
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// 覆盖层中的块数
+    pub fn len(&self) -> usize {
+        self.blocks.len()
+    }
+
+    /// 覆盖层是否为空
+    pub fn is_empty(&self) -> bool {
+        self.blocks.is_empty()
+    }
+}
+
+impl OverlayStore for MemoryOverlay {
+    fn read(&mut self, block: u64, buf: &mut [u8]) -> Result<bool> {
+        match self.blocks.get(&block) {
+            Some(data) => {
+                buf[..data.len()].copy_from_slice(data);
+                Ok(true)
+            }
+            None => Ok(false),
+        }
+    }
+
+    fn write(&mut self, block: u64, data: &[u8]) -> Result<()> {
+        match self.blocks.get_mut(&block) {
+            Some(existing) => existing.copy_from_slice(data),
+            None => {
+                self.blocks.insert(block, data.into());
+            }
+        }
+        Ok(())
+    }
+
+    fn contains(&self, block: u64) -> bool {
+        self.blocks.contains_key(&block)
+    }
+
+    fn blocks(&self) -> Vec<u64> {
+        self.blocks.keys().copied().collect()
+    }
+
+    fn clear(&mut self) -> Result<()> {
+        self.blocks.clear();
+        Ok(())
+    }
+}
+
+/// 只读底层设备 + 可写覆盖层
+///
+/// 实现了 [`BlockDevice`]，可以直接交给 `BlockDev` 挂载。
+/// 写入永远不会到达底层设备；不足一个块的写入会先从覆盖层或底层
+/// 设备读出整块再合并。
+///
+/// # 示例
+///
+/// ```rust,ignore
+/// use lwext4_core::{BlockDev, Ext4FileSystem, OverlayDevice};
+///
+/// let dev = OverlayDevice::new(rom_device);
+/// let mut fs = Ext4FileSystem::mount(BlockDev::new(dev)?)?;
+/// fs.write_file("/etc/config", b"variant-b")?;
+///
+/// // 检查修改了哪些块，然后丢弃，回到原始镜像
+/// ```
+pub struct OverlayDevice<B: BlockDevice, S: OverlayStore = MemoryOverlay> {
+    base: B,
+    store: S,
+    /// 部分块读写使用的临时缓冲区
+    scratch: Vec<u8>,
+}
+
+impl<B: BlockDevice> OverlayDevice<B, MemoryOverlay> {
+    /// 使用内存覆盖层创建
+    pub fn new(base: B) -> Self {
+        Self::with_store(base, MemoryOverlay::new())
+    }
+}
+
+impl<B: BlockDevice, S: OverlayStore> OverlayDevice<B, S> {
+    /// 使用指定的覆盖层存储创建
+    pub fn with_store(base: B, store: S) -> Self {
+        let block_size = base.block_size() as usize;
+        Self {
+            base,
+            store,
+            scratch: alloc::vec![0u8; block_size],
+        }
+    }
+
+    /// 底层只读设备
+    pub fn base(&self) -> &B {
+        &self.base
+    }
+
+    /// 覆盖层存储
+    pub fn store(&self) -> &S {
+        &self.store
+    }
+
+    /// 被修改过的块号（升序）
+    pub fn modified_blocks(&self) -> Vec<u64> {
+        self.store.blocks()
+    }
+
+    /// 丢弃所有修改，回到底层镜像的状态
+    ///
+    /// 调用前必须先卸载文件系统，否则块缓存中的数据会与设备不一致
+    pub fn discard(&mut self) -> Result<()> {
+        self.store.clear()
+    }
+
+    /// 拆分为底层设备和覆盖层存储
+    pub fn into_parts(self) -> (B, S) {
+        (self.base, self.store)
+    }
+
+    fn sectors_per_block(&self) -> u64 {
+        (self.base.block_size() / self.base.sector_size()) as u64
+    }
+
+    /// 把块的当前内容（覆盖层优先）读入 scratch
+    fn load_block(&mut self, block: u64) -> Result<()> {
+        if self.store.read(block, &mut self.scratch)? {
+            return Ok(());
+        }
+        let spb = self.sectors_per_block();
+        self.base.read_blocks(block * spb, spb as u32, &mut self.scratch)?;
+        Ok(())
+    }
+}
+
+impl<B: BlockDevice, S: OverlayStore> BlockDevice for OverlayDevice<B, S> {
+    fn block_size(&self) -> u32 {
+        self.base.block_size()
+    }
+
+    fn sector_size(&self) -> u32 {
+        self.base.sector_size()
+    }
+
+    fn total_blocks(&self) -> u64 {
+        self.base.total_blocks()
+    }
+
+    fn read_blocks(&mut self, lba: u64, count: u32, buf: &mut [u8]) -> Result<usize> {
+        let ss = self.sector_size() as usize;
+        let spb = self.sectors_per_block();
+        let len = count as usize * ss;
+        if buf.len() < len {
+            return Err(Error::new(ErrorKind::InvalidInput, "Buffer too small for read"));
+        }
+
+        let end = lba + count as u64;
+        let mut sector = lba;
+        while sector < end {
+            let block = sector / spb;
+            let off = (sector - lba) as usize * ss;
+
+            if self.store.contains(block) {
+                self.load_block(block)?;
+                let block_end = ((block + 1) * spb).min(end);
+                let skip = (sector - block * spb) as usize * ss;
+                let n = (block_end - sector) as usize * ss;
+                buf[off..off + n].copy_from_slice(&self.scratch[skip..skip + n]);
+                sector = block_end;
+                continue;
+            }
+
+            // 合并连续的未修改块，一次从底层设备读取
+            let mut run_end = ((block + 1) * spb).min(end);
+            while run_end < end && !self.store.contains(run_end / spb) {
+                run_end = ((run_end / spb + 1) * spb).min(end);
+            }
+            let n = (run_end - sector) as usize * ss;
+            self.base.read_blocks(sector, (run_end - sector) as u32, &mut buf[off..off + n])?;
+            sector = run_end;
+        }
+
+        Ok(len)
+    }
+
+    fn write_blocks(&mut self, lba: u64, count: u32, buf: &[u8]) -> Result<usize> {
+        let ss = self.sector_size() as usize;
+        let spb = self.sectors_per_block();
+        let bs = self.block_size() as usize;
+        let len = count as usize * ss;
+        if buf.len() < len {
+            return Err(Error::new(ErrorKind::InvalidInput, "Buffer too small for write"));
+        }
+
+        let end = lba + count as u64;
+        let mut sector = lba;
+        while sector < end {
+            let block = sector / spb;
+            let block_start = block * spb;
+            let block_end = ((block + 1) * spb).min(end);
+            let off = (sector - lba) as usize * ss;
+
+            if sector == block_start && block_end == block_start + spb {
+                self.store.write(block, &buf[off..off + bs])?;
+            } else {
+                // 部分块写入：读出整块再合并
+                self.load_block(block)?;
+                let skip = (sector - block_start) as usize * ss;
+                let n = (block_end - sector) as usize * ss;
+                self.scratch[skip..skip + n].copy_from_slice(&buf[off..off + n]);
+                let Self { store, scratch, .. } = self;
+                store.write(block, scratch)?;
+            }
+            sector = block_end;
+        }
+
+        Ok(len)
+    }
+
+    fn flush(&mut self) -> Result<()> {
+        self.store.flush()
+    }
+
+    fn open(&mut self) -> Result<()> {
+        self.base.open()
+    }
+
+    fn close(&mut self) -> Result<()> {
+        self.base.close()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// 只读 ROM 设备，拒绝任何写入
+    struct RomDevice {
+        storage: Vec<u8>,
+    }
+
+    impl RomDevice {
+        fn new(total_blocks: u64) -> Self {
+            let mut storage = alloc::vec![0u8; total_blocks as usize * 4096];
+            for (i, b) in storage.iter_mut().enumerate() {
+                *b = (i / 4096) as u8;
+            }
+            Self { storage }
+        }
+    }
+
+    impl BlockDevice for RomDevice {
+        fn block_size(&self) -> u32 {
+            4096
+        }
+
+        fn sector_size(&self) -> u32 {
+            512
+        }
+
+        fn total_blocks(&self) -> u64 {
+            (self.storage.len() / 4096) as u64
+        }
+
+        fn read_blocks(&mut self, lba: u64, count: u32, buf: &mut [u8]) -> Result<usize> {
+            let start = lba as usize * 512;
+            let len = count as usize * 512;
+            buf[..len].copy_from_slice(&self.storage[start..start + len]);
+            Ok(len)
+        }
+
+        fn write_blocks(&mut self, _lba: u64, _count: u32, _buf: &[u8]) -> Result<usize> {
+            Err(Error::new(ErrorKind::ReadOnlyFs, "ROM device is read-only"))
+        }
+
+        fn is_read_only(&self) -> bool {
+            true
+        }
+    }
+
+    #[test]
+    fn test_overlay_captures_writes() {
+        let mut dev = OverlayDevice::new(RomDevice::new(4));
+
+        // 整块写入块 1
+        dev.write_blocks(8, 8, &[0xAAu8; 4096]).unwrap();
+
+        // 跨越块 0/1/2 的读取：块 1 来自覆盖层，其余来自底层
+        let mut buf = alloc::vec![0u8; 3 * 4096];
+        dev.read_blocks(0, 24, &mut buf).unwrap();
+        assert!(buf[..4096].iter().all(|&b| b == 0));
+        assert!(buf[4096..8192].iter().all(|&b| b == 0xAA));
+        assert!(buf[8192..].iter().all(|&b| b == 2));
+
+        assert_eq!(dev.modified_blocks(), alloc::vec![1]);
+        assert!(dev.base().storage[4096..8192].iter().all(|&b| b == 1));
+    }
+
+    #[test]
+    fn test_overlay_partial_write_and_discard() {
+        let mut dev = OverlayDevice::new(RomDevice::new(4));
+
+        // 只写块 3 的第二个扇区
+        dev.write_blocks(25, 1, &[0x55u8; 512]).unwrap();
+
+        let mut buf = alloc::vec![0u8; 4096];
+        dev.read_blocks(24, 8, &mut buf).unwrap();
+        assert!(buf[..512].iter().all(|&b| b == 3));
+        assert!(buf[512..1024].iter().all(|&b| b == 0x55));
+        assert!(buf[1024..].iter().all(|&b| b == 3));
+
+        dev.discard().unwrap();
+        dev.read_blocks(24, 8, &mut buf).unwrap();
+        assert!(buf.iter().all(|&b| b == 3));
+        assert!(dev.modified_blocks().is_empty());
+    }
+}
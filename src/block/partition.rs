@@ -0,0 +1,472 @@
+//! 分区表解析和分区设备
+//!
+//! 大多数 SD 卡和磁盘镜像上，ext4 文件系统位于某个分区而不是 LBA 0。
+//! [`read_partitions`] 解析 MBR（含扩展分区中的逻辑分区）和 GPT 分区表，
+//! [`PartitionDevice`] 把原始设备的一段扇区作为独立的块设备，
+//! 可以直接交给 [`BlockDev`](super::BlockDev) 挂载。
+//!
+//! 只读取主 GPT 头和分区项数组，并校验它们的 CRC32；不解析备份 GPT。
+
+use super::BlockDevice;
+use crate::{
+    crc::crc32,
+    error::{Error, ErrorKind, Result},
+};
+use alloc::{string::String, vec, vec::Vec};
+
+/// MBR 引导签名（偏移 510）
+const MBR_SIGNATURE: [u8; 2] = [0x55, 0xaa];
+/// MBR 分区项数组的偏移
+const MBR_ENTRIES_OFFSET: usize = 446;
+/// GPT 保护分区的 MBR 类型
+const MBR_TYPE_GPT_PROTECTIVE: u8 = 0xee;
+/// Linux 文件系统的 MBR 类型
+const MBR_TYPE_LINUX: u8 = 0x83;
+/// 扩展分区的 MBR 类型（CHS、LBA、Linux 扩展分区）
+const MBR_TYPES_EXTENDED: [u8; 3] = [0x05, 0x0f, 0x85];
+/// 逻辑分区链的最大长度，防止损坏的 EBR 形成环
+const MAX_LOGICAL_PARTITIONS: u32 = 128;
+
+/// GPT 头签名
+const GPT_SIGNATURE: &[u8; 8] = b"EFI PART";
+/// GPT 分区项数组的最大字节数，防止损坏的头导致巨大的分配
+const GPT_MAX_ENTRIES_BYTES: usize = 1 << 20;
+/// Linux 文件系统数据分区的类型 GUID（0FC63DAF-8483-4772-8E79-3D69D8477DE4，磁盘字节序）
+const GPT_TYPE_LINUX_FS: [u8; 16] = [
+    0xaf, 0x3d, 0xc6, 0x0f, 0x83, 0x84, 0x72, 0x47, 0x8e, 0x79, 0x3d, 0x69, 0xd8, 0x47, 0x7d, 0xe4,
+];
+
+/// 分区类型
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum PartitionKind {
+    /// MBR 分区
+    Mbr {
+        /// 分区类型字节（Linux 为 0x83）
+        part_type: u8,
+    },
+    /// GPT 分区
+    Gpt {
+        /// 分区类型 GUID（磁盘字节序）
+        type_guid: [u8; 16],
+        /// 分区 GUID（磁盘字节序）
+        unique_guid: [u8; 16],
+        /// 分区名
+        name: String,
+    },
+}
+
+/// 分区表中的一个分区
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Partition {
+    /// 分区号，从 1 开始，与 Linux 的设备名（`sda1`）一致；MBR 逻辑分区从 5 开始
+    pub number: u32,
+    /// 起始扇区
+    pub start_lba: u64,
+    /// 扇区数
+    pub len_lba: u64,
+    /// 分区类型
+    pub kind: PartitionKind,
+}
+
+impl Partition {
+    /// 是否是 Linux 文件系统分区（MBR 类型 0x83 或 GPT Linux 文件系统数据分区）
+    pub fn is_linux(&self) -> bool {
+        match &self.kind {
+            PartitionKind::Mbr { part_type } => *part_type == MBR_TYPE_LINUX,
+            PartitionKind::Gpt { type_guid, .. } => *type_guid == GPT_TYPE_LINUX_FS,
+        }
+    }
+}
+
+/// 读取设备上的分区表
+///
+/// 扇区 0 没有 MBR 签名时（例如文件系统直接位于 LBA 0）返回空列表。
+/// 带有 GPT 保护分区时解析 GPT，否则按 MBR 解析，包括扩展分区中的逻辑分区。
+///
+/// # 错误
+///
+/// - `ErrorKind::Corrupted` - GPT 头或分区项数组的签名、大小或校验和无效
+/// - `ErrorKind::Io` - 设备读取失败
+pub fn read_partitions<D: BlockDevice>(dev: &mut D) -> Result<Vec<Partition>> {
+    let mut sector = vec![0u8; dev.sector_size() as usize];
+    dev.read_blocks(0, 1, &mut sector)?;
+    if sector[510..512] != MBR_SIGNATURE {
+        return Ok(Vec::new());
+    }
+
+    let entries = mbr_entries(&sector);
+    if entries.iter().any(|&(part_type, _, _)| part_type == MBR_TYPE_GPT_PROTECTIVE) {
+        return read_gpt(dev);
+    }
+
+    let mut partitions = Vec::new();
+    for (i, &(part_type, start, len)) in entries.iter().enumerate() {
+        if part_type == 0 || len == 0 {
+            continue;
+        }
+        if MBR_TYPES_EXTENDED.contains(&part_type) {
+            read_logical_partitions(dev, start, &mut partitions)?;
+        } else {
+            partitions.push(Partition {
+                number: i as u32 + 1,
+                start_lba: start,
+                len_lba: len,
+                kind: PartitionKind::Mbr { part_type },
+            });
+        }
+    }
+    Ok(partitions)
+}
+
+/// MBR/EBR 扇区中的 4 个分区项 `(类型, 起始扇区, 扇区数)`，起始扇区相对于该扇区所属的基准
+fn mbr_entries(sector: &[u8]) -> [(u8, u64, u64); 4] {
+    core::array::from_fn(|i| {
+        let entry = &sector[MBR_ENTRIES_OFFSET + i * 16..MBR_ENTRIES_OFFSET + (i + 1) * 16];
+        let start = u32::from_le_bytes(entry[8..12].try_into().unwrap());
+        let len = u32::from_le_bytes(entry[12..16].try_into().unwrap());
+        (entry[4], start as u64, len as u64)
+    })
+}
+
+/// 沿 EBR 链读取扩展分区中的逻辑分区
+///
+/// 每个 EBR 的第一项是逻辑分区（相对于该 EBR），第二项指向下一个 EBR（相对于扩展分区起点）
+fn read_logical_partitions<D: BlockDevice>(dev: &mut D, extended_start: u64, partitions: &mut Vec<Partition>) -> Result<()> {
+    let mut sector = vec![0u8; dev.sector_size() as usize];
+    let mut ebr = extended_start;
+
+    for number in 5..5 + MAX_LOGICAL_PARTITIONS {
+        dev.read_blocks(ebr, 1, &mut sector)?;
+        if sector[510..512] != MBR_SIGNATURE {
+            break;
+        }
+
+        let entries = mbr_entries(&sector);
+        let (part_type, start, len) = entries[0];
+        if part_type != 0 && len != 0 {
+            partitions.push(Partition {
+                number,
+                start_lba: ebr + start,
+                len_lba: len,
+                kind: PartitionKind::Mbr { part_type },
+            });
+        }
+
+        let (next_type, next_start, _) = entries[1];
+        if next_type == 0 || next_start == 0 {
+            break;
+        }
+        ebr = extended_start + next_start;
+    }
+    Ok(())
+}
+
+/// 读取主 GPT 头和分区项数组
+fn read_gpt<D: BlockDevice>(dev: &mut D) -> Result<Vec<Partition>> {
+    let ss = dev.sector_size() as usize;
+    let mut header = vec![0u8; ss];
+    dev.read_blocks(1, 1, &mut header)?;
+
+    let le32 = |buf: &[u8], off: usize| u32::from_le_bytes(buf[off..off + 4].try_into().unwrap());
+    let le64 = |buf: &[u8], off: usize| u64::from_le_bytes(buf[off..off + 8].try_into().unwrap());
+
+    if &header[..8] != GPT_SIGNATURE {
+        return Err(Error::new(ErrorKind::Corrupted, "Invalid GPT header signature"));
+    }
+    let header_size = le32(&header, 12) as usize;
+    if !(92..=ss).contains(&header_size) {
+        return Err(Error::new(ErrorKind::Corrupted, "Invalid GPT header size"));
+    }
+    let header_crc = le32(&header, 16);
+    header[16..20].fill(0);
+    if crc32(&header[..header_size]) != header_crc {
+        return Err(Error::new(ErrorKind::Corrupted, "GPT header checksum mismatch"));
+    }
+
+    let entries_lba = le64(&header, 72);
+    let entry_count = le32(&header, 80) as usize;
+    let entry_size = le32(&header, 84) as usize;
+    let entries_crc = le32(&header, 88);
+    let entries_len = entry_count.saturating_mul(entry_size);
+    if entry_size < 128 || entry_size % 8 != 0 || entries_len > GPT_MAX_ENTRIES_BYTES {
+        return Err(Error::new(ErrorKind::Corrupted, "Invalid GPT partition entry array"));
+    }
+
+    let sectors = entries_len.div_ceil(ss);
+    let mut entries = vec![0u8; sectors * ss];
+    dev.read_blocks(entries_lba, sectors as u32, &mut entries)?;
+    if crc32(&entries[..entries_len]) != entries_crc {
+        return Err(Error::new(ErrorKind::Corrupted, "GPT partition entries checksum mismatch"));
+    }
+
+    let mut partitions = Vec::new();
+    for (i, entry) in entries[..entries_len].chunks_exact(entry_size).enumerate() {
+        let type_guid: [u8; 16] = entry[..16].try_into().unwrap();
+        if type_guid == [0; 16] {
+            continue;
+        }
+        let (first, last) = (le64(entry, 32), le64(entry, 40));
+        if last < first {
+            return Err(Error::new(ErrorKind::Corrupted, "Invalid GPT partition range"));
+        }
+
+        let name_units = entry[56..128].chunks_exact(2).map(|c| u16::from_le_bytes([c[0], c[1]]));
+        let name = char::decode_utf16(name_units.take_while(|&u| u != 0))
+            .map(|c| c.unwrap_or(char::REPLACEMENT_CHARACTER))
+            .collect();
+
+        partitions.push(Partition {
+            number: i as u32 + 1,
+            start_lba: first,
+            len_lba: last - first + 1,
+            kind: PartitionKind::Gpt {
+                type_guid,
+                unique_guid: entry[16..32].try_into().unwrap(),
+                name,
+            },
+        });
+    }
+    Ok(partitions)
+}
+
+/// 分区设备
+///
+/// 把底层设备从 `offset_lba` 开始的 `len_lba` 个扇区作为独立的块设备，
+/// 扇区号在转发时加上偏移，越过分区末尾的访问返回错误。
+///
+/// # 示例
+///
+/// ```rust,ignore
+/// use lwext4_core::{BlockDev, Ext4FileSystem, PartitionDevice};
+///
+/// // SD 卡镜像：第 1 分区是 FAT 引导分区，rootfs 是第一个 Linux 分区
+/// let dev = PartitionDevice::first_linux(sdcard)?;
+/// let mut fs = Ext4FileSystem::mount(BlockDev::new_with_cache(dev, 256)?)?;
+/// ```
+pub struct PartitionDevice<D: BlockDevice> {
+    inner: D,
+    offset_lba: u64,
+    len_lba: u64,
+}
+
+impl<D: BlockDevice> PartitionDevice<D> {
+    /// 创建分区设备
+    ///
+    /// # 错误
+    ///
+    /// - `ErrorKind::InvalidInput` - 分区超出底层设备
+    pub fn new(inner: D, offset_lba: u64, len_lba: u64) -> Result<Self> {
+        let sectors_per_block = (inner.block_size() / inner.sector_size()) as u64;
+        let device_sectors = inner.total_blocks().saturating_mul(sectors_per_block);
+        if offset_lba.checked_add(len_lba).is_none_or(|end| end > device_sectors) {
+            return Err(Error::new(ErrorKind::InvalidInput, "Partition exceeds device"));
+        }
+        Ok(Self { inner, offset_lba, len_lba })
+    }
+
+    /// 打开分区表中编号为 `number` 的分区
+    ///
+    /// # 错误
+    ///
+    /// - `ErrorKind::NotFound` - 没有该分区
+    /// - 其余同 [`read_partitions`] 和 [`new`](Self::new)
+    pub fn by_number(mut inner: D, number: u32) -> Result<Self> {
+        let partition = read_partitions(&mut inner)?
+            .into_iter()
+            .find(|p| p.number == number)
+            .ok_or(Error::new(ErrorKind::NotFound, "Partition not found"))?;
+        Self::new(inner, partition.start_lba, partition.len_lba)
+    }
+
+    /// 打开分区表中第一个 Linux 文件系统分区，见 [`Partition::is_linux`]
+    ///
+    /// # 错误
+    ///
+    /// - `ErrorKind::NotFound` - 没有 Linux 分区
+    /// - 其余同 [`read_partitions`] 和 [`new`](Self::new)
+    pub fn first_linux(mut inner: D) -> Result<Self> {
+        let partition = read_partitions(&mut inner)?
+            .into_iter()
+            .find(Partition::is_linux)
+            .ok_or(Error::new(ErrorKind::NotFound, "No Linux partition found"))?;
+        Self::new(inner, partition.start_lba, partition.len_lba)
+    }
+
+    /// 分区起始扇区
+    pub fn offset_lba(&self) -> u64 {
+        self.offset_lba
+    }
+
+    /// 分区扇区数
+    pub fn len_lba(&self) -> u64 {
+        self.len_lba
+    }
+
+    /// 底层设备
+    pub fn inner(&self) -> &D {
+        &self.inner
+    }
+
+    /// 底层设备的可变引用
+    pub fn inner_mut(&mut self) -> &mut D {
+        &mut self.inner
+    }
+
+    /// 取出底层设备
+    pub fn into_inner(self) -> D {
+        self.inner
+    }
+
+    /// 检查访问范围并转换为底层设备的扇区号
+    fn translate(&self, lba: u64, count: u32) -> Result<u64> {
+        if lba.checked_add(count as u64).is_none_or(|end| end > self.len_lba) {
+            return Err(Error::new(ErrorKind::InvalidInput, "Access beyond end of partition"));
+        }
+        Ok(self.offset_lba + lba)
+    }
+}
+
+impl<D: BlockDevice> BlockDevice for PartitionDevice<D> {
+    fn block_size(&self) -> u32 {
+        self.inner.block_size()
+    }
+
+    fn sector_size(&self) -> u32 {
+        self.inner.sector_size()
+    }
+
+    /// 分区末尾不足一个块的扇区不计入
+    fn total_blocks(&self) -> u64 {
+        self.len_lba / (self.inner.block_size() / self.inner.sector_size()) as u64
+    }
+
+    fn read_blocks(&mut self, lba: u64, count: u32, buf: &mut [u8]) -> Result<usize> {
+        let lba = self.translate(lba, count)?;
+        self.inner.read_blocks(lba, count, buf)
+    }
+
+    fn write_blocks(&mut self, lba: u64, count: u32, buf: &[u8]) -> Result<usize> {
+        let lba = self.translate(lba, count)?;
+        self.inner.write_blocks(lba, count, buf)
+    }
+
+    fn flush(&mut self) -> Result<()> {
+        self.inner.flush()
+    }
+
+    fn flush_barrier(&mut self) -> Result<()> {
+        self.inner.flush_barrier()
+    }
+
+    fn is_read_only(&self) -> bool {
+        self.inner.is_read_only()
+    }
+
+    fn open(&mut self) -> Result<()> {
+        self.inner.open()
+    }
+
+    fn close(&mut self) -> Result<()> {
+        self.inner.close()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::block::MemBlockDevice;
+
+    /// 写入一个 MBR/EBR 分区项
+    fn set_mbr_entry(sector: &mut [u8], i: usize, part_type: u8, start: u32, len: u32) {
+        let entry = &mut sector[MBR_ENTRIES_OFFSET + i * 16..MBR_ENTRIES_OFFSET + (i + 1) * 16];
+        entry[4] = part_type;
+        entry[8..12].copy_from_slice(&start.to_le_bytes());
+        entry[12..16].copy_from_slice(&len.to_le_bytes());
+        sector[510..512].copy_from_slice(&MBR_SIGNATURE);
+    }
+
+    #[test]
+    fn test_mbr_with_logical_partitions() {
+        let mut dev = MemBlockDevice::new(64);
+        let bytes = dev.as_bytes_mut();
+        set_mbr_entry(&mut bytes[..512], 0, 0x0c, 8, 64);
+        set_mbr_entry(&mut bytes[..512], 1, MBR_TYPE_LINUX, 72, 128);
+        set_mbr_entry(&mut bytes[..512], 2, 0x05, 200, 200);
+        // 扩展分区：两个逻辑分区
+        set_mbr_entry(&mut bytes[200 * 512..201 * 512], 0, MBR_TYPE_LINUX, 8, 40);
+        set_mbr_entry(&mut bytes[200 * 512..201 * 512], 1, 0x05, 100, 60);
+        set_mbr_entry(&mut bytes[300 * 512..301 * 512], 0, 0x82, 8, 50);
+
+        let partitions = read_partitions(&mut dev).unwrap();
+        let summary: Vec<_> = partitions.iter().map(|p| (p.number, p.start_lba, p.len_lba, p.is_linux())).collect();
+        assert_eq!(
+            summary,
+            [(1, 8, 64, false), (2, 72, 128, true), (5, 208, 40, true), (6, 308, 50, false)]
+        );
+    }
+
+    #[test]
+    fn test_gpt() {
+        let mut dev = MemBlockDevice::new(64);
+        let bytes = dev.as_bytes_mut();
+        set_mbr_entry(&mut bytes[..512], 0, MBR_TYPE_GPT_PROTECTIVE, 1, 511);
+
+        // 分区项数组：LBA 2 开始，4 项
+        let entries = &mut bytes[2 * 512..2 * 512 + 4 * 128];
+        entries[..16].copy_from_slice(&[0x28, 0x73, 0x2a, 0xc1, 0x1f, 0xf8, 0xd2, 0x11, 0xba, 0x4b, 0, 0xa0, 0xc9, 0x3e, 0xc9, 0x3b]);
+        entries[32..40].copy_from_slice(&34u64.to_le_bytes());
+        entries[40..48].copy_from_slice(&99u64.to_le_bytes());
+        entries[256..272].copy_from_slice(&GPT_TYPE_LINUX_FS);
+        entries[256 + 32..256 + 40].copy_from_slice(&100u64.to_le_bytes());
+        entries[256 + 40..256 + 48].copy_from_slice(&499u64.to_le_bytes());
+        for (i, c) in "rootfs".encode_utf16().enumerate() {
+            entries[256 + 56 + i * 2..256 + 58 + i * 2].copy_from_slice(&c.to_le_bytes());
+        }
+        let entries_crc = crc32(entries);
+
+        let header = &mut bytes[512..1024];
+        header[..8].copy_from_slice(GPT_SIGNATURE);
+        header[12..16].copy_from_slice(&92u32.to_le_bytes());
+        header[72..80].copy_from_slice(&2u64.to_le_bytes());
+        header[80..84].copy_from_slice(&4u32.to_le_bytes());
+        header[84..88].copy_from_slice(&128u32.to_le_bytes());
+        header[88..92].copy_from_slice(&entries_crc.to_le_bytes());
+        let header_crc = crc32(&header[..92]);
+        header[16..20].copy_from_slice(&header_crc.to_le_bytes());
+
+        let partitions = read_partitions(&mut dev).unwrap();
+        assert_eq!(partitions.len(), 2);
+        assert_eq!((partitions[0].number, partitions[0].start_lba, partitions[0].len_lba), (1, 34, 66));
+        assert!(!partitions[0].is_linux());
+        assert_eq!((partitions[1].number, partitions[1].start_lba, partitions[1].len_lba), (3, 100, 400));
+        assert!(partitions[1].is_linux());
+        assert!(matches!(&partitions[1].kind, PartitionKind::Gpt { name, .. } if name == "rootfs"));
+
+        let part = PartitionDevice::first_linux(dev).unwrap();
+        assert_eq!((part.offset_lba(), part.len_lba(), part.total_blocks()), (100, 400, 50));
+
+        // 分区项被破坏
+        let mut dev = part.into_inner();
+        dev.as_bytes_mut()[2 * 512 + 40] ^= 1;
+        assert_eq!(read_partitions(&mut dev).unwrap_err().kind(), ErrorKind::Corrupted);
+    }
+
+    #[test]
+    fn test_partition_device_io() {
+        let dev = MemBlockDevice::new(4);
+        assert!(PartitionDevice::new(MemBlockDevice::new(4), 16, 17).is_err());
+        let mut part = PartitionDevice::new(dev, 16, 16).unwrap();
+        assert_eq!(part.total_blocks(), 2);
+
+        part.write_blocks(8, 1, &[0xcd; 512]).unwrap();
+        assert_eq!(part.inner().as_bytes()[24 * 512], 0xcd);
+        let mut buf = [0u8; 1024];
+        assert!(part.read_blocks(15, 2, &mut buf).is_err());
+        assert!(part.read_blocks(u64::MAX, 1, &mut buf).is_err());
+
+        // 没有分区表
+        let mut raw = part.into_inner();
+        assert!(read_partitions(&mut raw).unwrap().is_empty());
+        assert_eq!(PartitionDevice::by_number(raw, 1).err().unwrap().kind(), ErrorKind::NotFound);
+    }
+}
@@ -1,6 +1,11 @@
 //! 块组描述符校验和计算
 //!
-//! 对应 lwext4 的 `ext4_bg_crc16()` 功能
+//! 对应 lwext4 的 `ext4_bg_crc16()` 和 `ext4_fs_bg_checksum()` 功能
+
+use crate::{consts::*, superblock::Superblock};
+
+/// 描述符中 `bg_checksum` 字段的偏移
+const BG_CHECKSUM_OFFSET: usize = 0x1E;
 
 /// CRC-16 查找表
 static CRC16_TABLE: [u16; 256] = [
@@ -56,6 +61,77 @@ pub fn bg_crc16(mut crc: u16, buffer: &[u8]) -> u16 {
     crc
 }
 
+/// 计算块组描述符的校验和
+///
+/// 对应 lwext4 的 `ext4_fs_bg_checksum()`
+///
+/// - `metadata_csum`：CRC32C 的低 16 位，种子为 [`Superblock::csum_seed`]
+/// - `GDT_CSUM`：CRC16，依次覆盖 UUID、块组号和描述符
+///
+/// 两者都覆盖 `desc` 中除 `bg_checksum` 字段外的部分。
+/// 都未启用时返回 0。
+///
+/// # 参数
+///
+/// * `sb` - superblock 引用
+/// * `group` - 块组编号
+/// * `desc` - 描述符的原始字节（长度为描述符大小）
+pub fn compute_checksum(sb: &Superblock, group: u32, desc: &[u8]) -> u16 {
+    let group_bytes = group.to_le_bytes();
+    let after = BG_CHECKSUM_OFFSET + 2;
+
+    if sb.has_metadata_csum() {
+        let mut csum = crate::crc::crc32c_append(sb.csum_seed(), &group_bytes);
+        csum = crate::crc::crc32c_append(csum, &desc[..BG_CHECKSUM_OFFSET]);
+        csum = crate::crc::crc32c_append(csum, &[0; 2]);
+        if desc.len() > after {
+            csum = crate::crc::crc32c_append(csum, &desc[after..]);
+        }
+        return (csum & 0xFFFF) as u16;
+    }
+
+    if sb.has_ro_compat_feature(EXT4_FEATURE_RO_COMPAT_GDT_CSUM) {
+        let mut crc = bg_crc16(!0, sb.uuid());
+        crc = bg_crc16(crc, &group_bytes);
+        crc = bg_crc16(crc, &desc[..BG_CHECKSUM_OFFSET]);
+        // 旧格式的 CRC16 不覆盖 32 字节之后的部分，64 位描述符除外
+        if sb.has_incompat_feature(EXT4_FEATURE_INCOMPAT_64BIT) && desc.len() > after {
+            crc = bg_crc16(crc, &desc[after..]);
+        }
+        return crc;
+    }
+
+    0
+}
+
+/// 重新计算并写入块组描述符的校验和
+///
+/// 对应 lwext4 的 `ext4_fs_set_block_group_checksum()`
+///
+/// 未启用 `metadata_csum` 和 `GDT_CSUM` 时不做修改
+pub fn set_checksum(sb: &Superblock, group: u32, desc: &mut [u8]) {
+    if !sb.has_metadata_csum() && !sb.has_ro_compat_feature(EXT4_FEATURE_RO_COMPAT_GDT_CSUM) {
+        return;
+    }
+
+    let csum = compute_checksum(sb, group, desc);
+    desc[BG_CHECKSUM_OFFSET..BG_CHECKSUM_OFFSET + 2].copy_from_slice(&csum.to_le_bytes());
+}
+
+/// 验证块组描述符的校验和
+///
+/// 对应 lwext4 的 `ext4_fs_verify_bg_csum()`
+///
+/// 未启用 `metadata_csum` 和 `GDT_CSUM` 时总是返回 true
+pub fn verify_checksum(sb: &Superblock, group: u32, desc: &[u8]) -> bool {
+    if !sb.has_metadata_csum() && !sb.has_ro_compat_feature(EXT4_FEATURE_RO_COMPAT_GDT_CSUM) {
+        return true;
+    }
+
+    let stored = u16::from_le_bytes([desc[BG_CHECKSUM_OFFSET], desc[BG_CHECKSUM_OFFSET + 1]]);
+    stored == compute_checksum(sb, group, desc)
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -91,4 +167,35 @@ mod tests {
         // 相同输入应该得到相同输出
         assert_eq!(crc1, crc2);
     }
+
+    #[test]
+    fn test_desc_checksum() {
+        let mut sb_inner = crate::types::ext4_sblock {
+            uuid: [7; 16],
+            desc_size: 64u16.to_le(),
+            feature_incompat: EXT4_FEATURE_INCOMPAT_64BIT.to_le(),
+            ..Default::default()
+        };
+
+        let mut desc = [0x5au8; 64];
+        for ro_compat in [EXT4_FEATURE_RO_COMPAT_METADATA_CSUM, EXT4_FEATURE_RO_COMPAT_GDT_CSUM] {
+            sb_inner.feature_ro_compat = ro_compat.to_le();
+            let sb = Superblock::new(sb_inner);
+
+            set_checksum(&sb, 3, &mut desc);
+            assert!(verify_checksum(&sb, 3, &desc));
+            // 块组号参与校验和
+            assert!(!verify_checksum(&sb, 4, &desc));
+
+            desc[40] ^= 1;
+            assert!(!verify_checksum(&sb, 3, &desc));
+            desc[40] ^= 1;
+        }
+
+        // 都未启用时不修改校验和字段
+        sb_inner.feature_ro_compat = 0;
+        let before = desc;
+        set_checksum(&Superblock::new(sb_inner), 3, &mut desc);
+        assert_eq!(desc, before);
+    }
 }
@@ -0,0 +1,91 @@
+//! 块组锁
+//!
+//! 对应 Linux ext4 的 `blockgroup_lock`：固定数量的自旋锁，块组号取模后
+//! 选择其中一个。分配器修改块组的位图和块组描述符时持有对应的锁，
+//! 两者总是一起更新，不同块组的修改互不阻塞。
+//!
+//! 目前 [`BlockDev`](crate::block::BlockDev) 的读写仍然需要 `&mut`，
+//! 调用者还是被文件系统级的锁串行化；块组锁划定了块组内修改的边界，
+//! 块缓存支持共享访问后，不同块组的分配即可并行。
+//!
+//! 同一线程任何时候最多持有一个块组锁，不会出现锁顺序问题。
+
+use core::{
+    hint,
+    sync::atomic::{AtomicBool, Ordering},
+};
+
+/// 锁的数量（2 的幂），块组数更多时多个块组共用一个锁
+pub const GROUP_LOCK_COUNT: usize = 64;
+
+/// 按块组号索引的锁表
+pub struct GroupLockMap {
+    locks: [AtomicBool; GROUP_LOCK_COUNT],
+}
+
+/// 块组锁的 RAII 守卫，释放时解锁
+pub struct GroupLockGuard<'a> {
+    lock: &'a AtomicBool,
+}
+
+impl Drop for GroupLockGuard<'_> {
+    fn drop(&mut self) {
+        self.lock.store(false, Ordering::Release);
+    }
+}
+
+impl GroupLockMap {
+    /// 创建锁表，所有锁处于未锁定状态
+    pub fn new() -> Self {
+        Self { locks: core::array::from_fn(|_| AtomicBool::new(false)) }
+    }
+
+    fn slot(&self, bgid: u32) -> &AtomicBool {
+        &self.locks[bgid as usize % GROUP_LOCK_COUNT]
+    }
+
+    /// 锁住块组 `bgid`，已被其他线程持有时自旋等待
+    pub fn lock(&self, bgid: u32) -> GroupLockGuard<'_> {
+        loop {
+            if let Some(guard) = self.try_lock(bgid) {
+                return guard;
+            }
+            while self.slot(bgid).load(Ordering::Relaxed) {
+                hint::spin_loop();
+            }
+        }
+    }
+
+    /// 尝试锁住块组 `bgid`，已被持有时返回 `None`
+    pub fn try_lock(&self, bgid: u32) -> Option<GroupLockGuard<'_>> {
+        let lock = self.slot(bgid);
+        lock.compare_exchange(false, true, Ordering::Acquire, Ordering::Relaxed)
+            .ok()
+            .map(|_| GroupLockGuard { lock })
+    }
+}
+
+impl Default for GroupLockMap {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_group_lock() {
+        let locks = GroupLockMap::new();
+
+        let guard = locks.lock(3);
+        assert!(locks.try_lock(3).is_none());
+        // 取模后落在同一个锁上的块组也被锁住
+        assert!(locks.try_lock(3 + GROUP_LOCK_COUNT as u32).is_none());
+        assert!(locks.try_lock(4).is_some());
+
+        drop(guard);
+        assert!(locks.try_lock(3).is_some());
+    }
+}
@@ -6,7 +6,9 @@
 //! 考虑在更高层统一使用block_group_ref提供的接口，并修改block_group模块，使其为block_group_ref提供基础支持
 mod read;
 mod write;
+mod lock;
 pub mod checksum;
 
 pub use read::*;
 pub use write::*;
+pub use lock::{GroupLockGuard, GroupLockMap, GROUP_LOCK_COUNT};
@@ -1,13 +1,14 @@
 //! 块组描述符读取和查询操作
 
 use crate::{
-    block::{BlockDev, BlockDevice},
+    block::{Block, BlockDev, BlockDevice},
+    cache::GROUP_DESC_CACHE_BYTES,
     consts::*,
-    error::Result,
+    error::{Error, ErrorKind, Result},
     superblock::Superblock,
     types::ext4_group_desc,
 };
-use alloc::vec;
+use alloc::{vec, vec::Vec};
 
 /// 计算块组描述符的存储位置
 ///
@@ -25,8 +26,13 @@ use alloc::vec;
 /// # 实现说明
 ///
 /// 支持两种模式：
-/// - 传统模式：所有块组描述符连续存储在 first_data_block + 1 位置
-/// - META_BG 模式：块组描述符分散存储在各个 meta groups 中
+/// - 传统模式：所有块组描述符连续存储在 first_data_block + 1 开始的块中
+/// - META_BG 模式：从 `s_first_meta_bg` 个描述符块开始，每个 metagroup
+///   （一个描述符块能描述的块组）的描述符块存放在该 metagroup 第一个块组的
+///   superblock 之后（没有 superblock 时就在块组起始处）
+///
+/// 与内核 `descriptor_loc()` 相同，metagroup 第二个和最后一个块组中的
+/// 只是备份，这里总是返回主副本的位置。
 ///
 /// 此函数被以下模块使用：
 /// - `block_group/read.rs`: 读取块组描述符
@@ -44,61 +50,67 @@ pub fn get_block_group_desc_location(
     // 计算每个块可以容纳多少个描述符
     let desc_per_block = block_size / desc_size;
 
+    // 描述符所在的描述符块序号（同时也是 metagroup 编号）及块内偏移
+    let nr = group_num as u64 / desc_per_block;
+    let desc_offset_in_block = (group_num as u64 % desc_per_block) * desc_size;
+
     // 检查是否启用 META_BG 特性
     let has_meta_bg = sb.has_incompat_feature(EXT4_FEATURE_INCOMPAT_META_BG);
-    let first_meta_bg = u32::from_le(sb.inner().first_meta_bg);
-
-    let gdt_block: u64;
-    let desc_offset_in_block: u64;
-
-    if has_meta_bg {
-        // META_BG 模式
-        let metagroup = (group_num as u64) / desc_per_block;
-
-        // 检查是否在 META_BG 区域内
-        if metagroup < first_meta_bg as u64 {
-            // 在 first_meta_bg 之前，使用传统方式
-            gdt_block = first_data_block + 1;
-            desc_offset_in_block = (group_num as u64) * desc_size;
-        } else {
-            // 在 META_BG 区域内
-            // META_BG 模式下，每个 metagroup 的块组描述符存储在该 metagroup 的特定位置
-            // 描述符存储在 metagroup 中的第一个、第二个或最后一个块组
-
-            let first_group_in_metagroup = metagroup * desc_per_block;
-            let group_offset_in_metagroup = (group_num as u64) - first_group_in_metagroup;
-
-            // 计算 metagroup 的起始块号
-            let metagroup_start_block = first_group_in_metagroup * sb.blocks_per_group() as u64;
-
-            // META_BG 的 GDT 存储位置：
-            // - 第一个块组的描述符在 metagroup_start + 1
-            // - 第二个块组的描述符在 metagroup_start + blocks_per_group + 1
-            // - 最后一个块组的描述符在 metagroup_start + (desc_per_block - 1) * blocks_per_group + 1
-
-            let gdt_offset_blocks = if group_offset_in_metagroup == 0 {
-                1 // 第一个块组
-            } else if group_offset_in_metagroup == 1 {
-                sb.blocks_per_group() as u64 + 1 // 第二个块组
-            } else if group_offset_in_metagroup == desc_per_block - 1 {
-                (desc_per_block - 1) * sb.blocks_per_group() as u64 + 1 // 最后一个块组
-            } else {
-                // 其他块组的描述符存储在第一个块组的 GDT 中
-                1
-            };
-
-            gdt_block = metagroup_start_block + gdt_offset_blocks;
-            desc_offset_in_block = group_offset_in_metagroup * desc_size;
-        }
-    } else {
-        // 传统模式：所有块组描述符连续存储
-        gdt_block = first_data_block + 1 + ((group_num as u64) * desc_size) / block_size;
-        desc_offset_in_block = ((group_num as u64) * desc_size) % block_size;
+    let first_meta_bg = u32::from_le(sb.inner().first_meta_bg) as u64;
+
+    if !has_meta_bg || nr < first_meta_bg {
+        // 传统模式（或 META_BG 之前的部分）：描述符块紧跟在主 superblock 之后
+        return (first_data_block + 1 + nr, desc_offset_in_block);
+    }
+
+    // META_BG 模式：位于 metagroup 第一个块组中
+    let bg = nr * desc_per_block;
+    let mut gdt_block = first_data_block + bg * sb.blocks_per_group() as u64;
+    if sb.has_super_in_bg(bg as u32) {
+        gdt_block += 1;
+    }
+    // first_data_block 为 0 的 1K 块文件系统中，主 superblock 位于块 1
+    if block_size == 1024 && nr == 0 && first_data_block == 0 {
+        gdt_block += 1;
     }
 
     (gdt_block, desc_offset_in_block)
 }
 
+/// 计算第 `nr` 个块组描述符块的备份位置
+///
+/// 与 [`get_block_group_desc_location`] 对应：
+/// - 传统模式（或 META_BG 之前的部分）：每个带 superblock 备份的块组中，
+///   描述符块紧跟在备份 superblock 之后
+/// - META_BG 模式：metagroup 第二个和最后一个块组中各有一份，
+///   位于该块组的 superblock 备份之后（没有时就在块组起始处）
+///
+/// # 返回
+///
+/// 备份所在的块号，不含主副本
+pub fn get_block_group_desc_backup_locations(sb: &Superblock, nr: u32) -> Vec<u64> {
+    let first_data_block = sb.first_data_block() as u64;
+    let blocks_per_group = sb.blocks_per_group() as u64;
+    let group_start = |group: u32| first_data_block + group as u64 * blocks_per_group;
+
+    let has_meta_bg = sb.has_incompat_feature(EXT4_FEATURE_INCOMPAT_META_BG);
+    if !has_meta_bg || nr < u32::from_le(sb.inner().first_meta_bg) {
+        return sb
+            .backup_groups()
+            .into_iter()
+            .map(|group| group_start(group) + 1 + nr as u64)
+            .collect();
+    }
+
+    let desc_per_block = sb.block_size() / sb.group_desc_size() as u32;
+    let first = nr * desc_per_block;
+    [first + 1, first + desc_per_block - 1]
+        .into_iter()
+        .filter(|&group| group < sb.block_group_count())
+        .map(|group| group_start(group) + sb.has_super_in_bg(group) as u64)
+        .collect()
+}
+
 /// 读取块组描述符
 ///
 /// # 参数
@@ -118,28 +130,76 @@ pub fn get_block_group_desc_location(
 /// 支持两种模式：
 /// - 传统模式：所有块组描述符连续存储在 first_data_block + 1 位置
 /// - META_BG 模式：块组描述符分散存储在各个 meta groups 中
+///
+/// 描述符经过块设备的元数据缓存：未命中时同一描述符块上的描述符一起缓存，
+/// 该块被修改后自动失效
 pub fn read_block_group_desc<D: BlockDevice>(
     bdev: &mut BlockDev<D>,
     sb: &Superblock,
     group_num: u32,
 ) -> Result<ext4_group_desc> {
-    let block_size = sb.block_size() as u64;
+    if let Some(bytes) = bdev.meta_cache().get_desc(group_num) {
+        return Ok(desc_from_bytes(&bytes));
+    }
 
     // 使用统一的 GDT 定位函数
     let (gdt_block, desc_offset_in_block) = get_block_group_desc_location(sb, group_num);
 
-    // 计算最终的字节偏移
-    let desc_offset = gdt_block * block_size + desc_offset_in_block;
+    // 未命中时把整个描述符块上的描述符一起放入缓存
+    let desc_size = sb.group_desc_size();
+    let desc_len = desc_size.min(GROUP_DESC_CACHE_BYTES);
+    let first_group = group_num - (desc_offset_in_block as usize / desc_size) as u32;
+    let count = (sb.block_size() as usize / desc_size)
+        .min(sb.block_group_count().saturating_sub(first_group) as usize)
+        .max((group_num - first_group) as usize + 1);
+
+    let mut descs = vec![[0u8; GROUP_DESC_CACHE_BYTES]; count];
+    Block::get(bdev, gdt_block)?.with_data(|data| {
+        for (i, desc) in descs.iter_mut().enumerate() {
+            let off = i * desc_size;
+            desc[..desc_len].copy_from_slice(&data[off..off + desc_len]);
+        }
+    })?;
+    bdev.meta_cache().insert_descs(gdt_block, first_group, &descs);
 
-    // 读取块组描述符
-    let mut desc_buf = vec![0u8; core::mem::size_of::<ext4_group_desc>()];
-    bdev.read_bytes(desc_offset, &mut desc_buf)?;
+    Ok(desc_from_bytes(&descs[(group_num - first_group) as usize]))
+}
 
-    let desc = unsafe {
-        core::ptr::read_unaligned(desc_buf.as_ptr() as *const ext4_group_desc)
-    };
+/// 由缓存的描述符字节构造描述符（32 字节描述符的高位部分为 0）
+fn desc_from_bytes(bytes: &[u8; GROUP_DESC_CACHE_BYTES]) -> ext4_group_desc {
+    const _: () = assert!(core::mem::size_of::<ext4_group_desc>() <= GROUP_DESC_CACHE_BYTES);
+    unsafe { core::ptr::read_unaligned(bytes.as_ptr() as *const ext4_group_desc) }
+}
+
+/// 验证所有块组描述符的校验和
+///
+/// 对应内核挂载时的 `ext4_check_descriptors()` 中的校验和部分，
+/// 由 `FsConfig::verify_checksums` 打开
+///
+/// # 错误
+///
+/// - `ErrorKind::Corrupted` - 某个描述符的校验和不匹配，错误中带有描述符所在的块号
+pub(crate) fn check_block_group_descs<D: BlockDevice>(
+    bdev: &mut BlockDev<D>,
+    sb: &Superblock,
+) -> Result<()> {
+    let block_size = sb.block_size() as u64;
+    let mut desc_buf = vec![0u8; sb.group_desc_size()];
+
+    for group in 0..sb.block_group_count() {
+        let (gdt_block, desc_offset_in_block) = get_block_group_desc_location(sb, group);
+        bdev.read_bytes(gdt_block * block_size + desc_offset_in_block, &mut desc_buf)?;
+
+        if !super::checksum::verify_checksum(sb, group, &desc_buf) {
+            return Err(Error::with_block(
+                ErrorKind::Corrupted,
+                "Group descriptor checksum mismatch",
+                gdt_block,
+            ));
+        }
+    }
 
-    Ok(desc)
+    Ok(())
 }
 
 /// BlockGroup 包装器，提供高级操作
@@ -165,6 +225,11 @@ impl BlockGroup {
         Ok(Self { inner, group_num })
     }
 
+    /// 由描述符创建（例如扩容时新增的块组）
+    pub fn new(group_num: u32, inner: ext4_group_desc) -> Self {
+        Self { inner, group_num }
+    }
+
     /// 获取块组编号
     pub fn group_num(&self) -> u32 {
         self.group_num
@@ -357,4 +422,78 @@ mod tests {
         assert!(bg.has_flag(0x0004));
         assert!(!bg.has_flag(0x0008));
     }
+
+    fn location_sb(log_block_size: u32, first_data_block: u32, incompat: u32, first_meta_bg: u32) -> Superblock {
+        let sb_inner = crate::types::ext4_sblock {
+            log_block_size: log_block_size.to_le(),
+            first_data_block: first_data_block.to_le(),
+            blocks_per_group: (8192u32 << log_block_size).to_le(),
+            feature_incompat: incompat.to_le(),
+            feature_ro_compat: EXT4_FEATURE_RO_COMPAT_SPARSE_SUPER.to_le(),
+            first_meta_bg: first_meta_bg.to_le(),
+            desc_size: (EXT4_GROUP_DESC_SIZE_64 as u16).to_le(),
+            ..Default::default()
+        };
+        Superblock::new(sb_inner)
+    }
+
+    #[test]
+    fn test_desc_location_flat() {
+        // 4K 块、64 字节描述符：每块 64 个描述符
+        let sb = location_sb(2, 0, EXT4_FEATURE_INCOMPAT_64BIT, 0);
+        assert_eq!(get_block_group_desc_location(&sb, 0), (1, 0));
+        assert_eq!(get_block_group_desc_location(&sb, 70), (2, 6 * 64));
+
+        // 1K 块、32 字节描述符：描述符从块 2 开始
+        let sb = location_sb(0, 1, 0, 0);
+        assert_eq!(get_block_group_desc_location(&sb, 33), (3, 32));
+    }
+
+    #[test]
+    fn test_desc_location_meta_bg() {
+        let incompat = EXT4_FEATURE_INCOMPAT_64BIT | EXT4_FEATURE_INCOMPAT_META_BG;
+
+        // first_meta_bg 之前的部分仍然连续存放在主 superblock 之后
+        let sb = location_sb(2, 0, incompat, 2);
+        assert_eq!(get_block_group_desc_location(&sb, 10), (1, 10 * 64));
+        assert_eq!(get_block_group_desc_location(&sb, 100), (2, 36 * 64));
+        // metagroup 2 从块组 128 开始，该块组没有 superblock 备份
+        assert_eq!(get_block_group_desc_location(&sb, 129), (128 * 32768, 64));
+        // metagroup 中的所有块组都使用第一个块组中的主副本
+        assert_eq!(get_block_group_desc_location(&sb, 191), (128 * 32768, 63 * 64));
+
+        // 1K 块、32 字节描述符，全部使用 META_BG
+        let sb = location_sb(0, 1, EXT4_FEATURE_INCOMPAT_META_BG, 0);
+        assert_eq!(get_block_group_desc_location(&sb, 0), (2, 0));
+        assert_eq!(get_block_group_desc_location(&sb, 33), (1 + 32 * 8192, 32));
+
+        // first_data_block 为 0 的 1K 块文件系统：跳过块 1 中的 superblock
+        let sb = location_sb(0, 0, EXT4_FEATURE_INCOMPAT_META_BG, 0);
+        assert_eq!(get_block_group_desc_location(&sb, 5), (2, 5 * 32));
+    }
+
+    #[test]
+    fn test_desc_backup_locations() {
+        // 4K 块，10 个块组：稀疏组 1、3、5、7、9 中有备份
+        let mut sb = location_sb(2, 0, EXT4_FEATURE_INCOMPAT_64BIT, 0);
+        sb.inner_mut().blocks_count_lo = (10 * 32768u32).to_le();
+        assert_eq!(
+            get_block_group_desc_backup_locations(&sb, 0),
+            [1, 3, 5, 7, 9].map(|g| g * 32768 + 1)
+        );
+
+        // 1K 块、32 字节描述符：metagroup 1 为块组 32..63
+        let mut sb = location_sb(0, 1, EXT4_FEATURE_INCOMPAT_META_BG, 0);
+        sb.inner_mut().blocks_count_lo = (1 + 64 * 8192u32).to_le();
+        // 块组 33 没有 superblock 备份，块组 63 不是稀疏组
+        assert_eq!(
+            get_block_group_desc_backup_locations(&sb, 1),
+            [1 + 33 * 8192, 1 + 63 * 8192]
+        );
+        // metagroup 0 的备份在块组 1 和 31，块组 1 中有 superblock 备份
+        assert_eq!(
+            get_block_group_desc_backup_locations(&sb, 0),
+            [2 + 8192, 1 + 31 * 8192]
+        );
+    }
 }
@@ -9,7 +9,7 @@ use crate::{
 };
 use alloc::vec;
 
-use super::{BlockGroup, get_block_group_desc_location};
+use super::{BlockGroup, get_block_group_desc_backup_locations, get_block_group_desc_location};
 
 /// 写入块组描述符到块设备
 ///
@@ -37,12 +37,11 @@ pub fn write_block_group_desc<D: BlockDevice>(
     // 计算描述符的偏移
     let desc_offset = gdt_block * block_size + desc_offset_in_block;
 
-    // 将描述符转换为字节数组
+    // 将描述符转换为字节数组，只写 desc_size 字节，
+    // 32 字节描述符时不能覆盖紧随其后的下一个描述符
+    let desc_len = sb.group_desc_size().min(core::mem::size_of::<ext4_group_desc>());
     let desc_bytes = unsafe {
-        core::slice::from_raw_parts(
-            desc as *const ext4_group_desc as *const u8,
-            core::mem::size_of::<ext4_group_desc>(),
-        )
+        core::slice::from_raw_parts(desc as *const ext4_group_desc as *const u8, desc_len)
     };
 
     // 写入块组描述符
@@ -51,6 +50,36 @@ pub fn write_block_group_desc<D: BlockDevice>(
     Ok(())
 }
 
+/// 把主块组描述符表复制到所有备份位置
+///
+/// 备份位置见 [`get_block_group_desc_backup_locations`]。
+/// 只复制描述符块，不涉及保留的 GDT 块
+///
+/// # 参数
+///
+/// * `bdev` - 块设备引用
+/// * `sb` - superblock 引用
+pub fn write_block_group_desc_backups<D: BlockDevice>(
+    bdev: &mut BlockDev<D>,
+    sb: &Superblock,
+) -> Result<()> {
+    let block_size = sb.block_size() as u64;
+    let desc_per_block = sb.block_size() / sb.group_desc_size() as u32;
+    let desc_blocks = sb.block_group_count().div_ceil(desc_per_block);
+
+    let mut buf = vec![0u8; block_size as usize];
+    for nr in 0..desc_blocks {
+        let (gdt_block, _) = get_block_group_desc_location(sb, nr * desc_per_block);
+        bdev.read_bytes(gdt_block * block_size, &mut buf)?;
+
+        for backup_block in get_block_group_desc_backup_locations(sb, nr) {
+            bdev.write_bytes(backup_block * block_size, &buf)?;
+        }
+    }
+
+    Ok(())
+}
+
 impl BlockGroup {
     /// 获取内部块组描述符的可变引用
     pub(crate) fn inner_mut(&mut self) -> &mut ext4_group_desc {
@@ -206,6 +235,18 @@ impl BlockGroup {
         self.inner.flags = flags.to_le();
     }
 
+    /// 重新计算并设置描述符校验和
+    ///
+    /// 未启用 `metadata_csum` 和 `GDT_CSUM` 时设置为 0
+    pub fn update_checksum(&mut self, sb: &Superblock) {
+        let desc_len = sb.group_desc_size().min(core::mem::size_of::<ext4_group_desc>());
+        let desc_bytes = unsafe {
+            core::slice::from_raw_parts(&self.inner as *const ext4_group_desc as *const u8, desc_len)
+        };
+        let checksum = super::checksum::compute_checksum(sb, self.group_num, desc_bytes);
+        self.set_checksum(checksum);
+    }
+
     /// 将块组描述符写回块设备
     ///
     /// # 参数
@@ -0,0 +1,491 @@
+//! C API 兼容层 - 块设备注册
+//!
+//! 对应 lwext4 的 `ext4_blockdev.h` 和 `ext4_device_register()`。
+//!
+//! C 代码按 lwext4 的方式填写 [`ext4_blockdev_iface`]（函数指针和物理块参数）
+//! 与 [`ext4_blockdev`]，然后以设备名注册。Rust 侧用 [`CBlockDevice::lookup`]
+//! 取回设备，它把这些函数指针适配为 [`BlockDevice`]，可以直接交给
+//! [`BlockDev::new`](crate::block::BlockDev::new)：
+//!
+//! ```rust,ignore
+//! // C: ext4_device_register(&sd_bdev, "sda");
+//! let device = CBlockDevice::lookup("sda").ok_or(...)?;
+//! let fs = Ext4FileSystem::mount(BlockDev::new(device)?)?;
+//! ```
+//!
+//! 注册表只保存指针，结构体的内存由 C 代码持有，在注销前必须保持有效。
+
+#![allow(non_camel_case_types)]
+
+use core::{
+    cell::UnsafeCell,
+    ffi::{c_char, c_int, c_void, CStr},
+    ptr::NonNull,
+    sync::atomic::{AtomicBool, Ordering},
+};
+
+use crate::{
+    block::BlockDevice,
+    consts::*,
+    error::{Error, ErrorKind},
+    Result,
+};
+
+/// 设备名的最大长度（不含结尾的 NUL）
+pub const EXT4_DEV_NAME_MAX: usize = 32;
+
+/// 未设置 `lg_bsize` 时使用的逻辑块大小
+const DEFAULT_LG_BSIZE: u32 = 4096;
+
+/// 块设备操作函数
+pub type ext4_blockdev_fn = unsafe extern "C" fn(bdev: *mut ext4_blockdev) -> c_int;
+
+/// 块读取函数（`blk_id` 和 `blk_cnt` 以物理块为单位）
+pub type ext4_blockdev_bread_fn =
+    unsafe extern "C" fn(bdev: *mut ext4_blockdev, buf: *mut c_void, blk_id: u64, blk_cnt: u32) -> c_int;
+
+/// 块写入函数（`blk_id` 和 `blk_cnt` 以物理块为单位）
+pub type ext4_blockdev_bwrite_fn =
+    unsafe extern "C" fn(bdev: *mut ext4_blockdev, buf: *const c_void, blk_id: u64, blk_cnt: u32) -> c_int;
+
+/// 块设备接口，与 lwext4 的 `struct ext4_blockdev_iface` 布局相同
+///
+/// 函数返回 0 表示成功，其他值为 errno。`lock`/`unlock` 可以为空
+#[repr(C)]
+pub struct ext4_blockdev_iface {
+    /// 打开设备
+    pub open: Option<ext4_blockdev_fn>,
+    /// 读取物理块
+    pub bread: Option<ext4_blockdev_bread_fn>,
+    /// 写入物理块
+    pub bwrite: Option<ext4_blockdev_bwrite_fn>,
+    /// 关闭设备
+    pub close: Option<ext4_blockdev_fn>,
+    /// 加锁（可选）
+    pub lock: Option<ext4_blockdev_fn>,
+    /// 解锁（可选）
+    pub unlock: Option<ext4_blockdev_fn>,
+    /// 物理块大小（字节）
+    pub ph_bsize: u32,
+    /// 物理块数
+    pub ph_bcnt: u64,
+    /// 物理块缓冲区（本实现不使用）
+    pub ph_bbuf: *mut u8,
+    /// 打开计数
+    pub ph_refctr: u32,
+    /// 物理读次数
+    pub bread_ctr: u32,
+    /// 物理写次数
+    pub bwrite_ctr: u32,
+    /// 用户数据
+    pub p_user: *mut c_void,
+}
+
+/// 块设备，与 lwext4 的 `struct ext4_blockdev` 布局相同
+///
+/// `bc`、`fs`、`journal` 只为保持布局而保留，本实现不读写它们
+#[repr(C)]
+pub struct ext4_blockdev {
+    /// 设备接口
+    pub bdif: *mut ext4_blockdev_iface,
+    /// 分区起始偏移（字节）
+    pub part_offset: u64,
+    /// 分区大小（字节），0 表示整个设备
+    pub part_size: u64,
+    /// 块缓存（保留）
+    pub bc: *mut c_void,
+    /// 逻辑块大小，0 表示 4096
+    pub lg_bsize: u32,
+    /// 逻辑块数
+    pub lg_bcnt: u64,
+    /// 写回缓存标志（保留）
+    pub cache_write_back: u32,
+    /// 所属文件系统（保留）
+    pub fs: *mut c_void,
+    /// 日志（保留）
+    pub journal: *mut c_void,
+}
+
+/// 把注册的 C 块设备适配为 [`BlockDevice`]
+///
+/// 读写请求按物理块转发给 `bread`/`bwrite`，起始块加上 `part_offset`；
+/// [`open`](BlockDevice::open)/[`close`](BlockDevice::close) 按 `ph_refctr`
+/// 计数，只在第一次打开和最后一次关闭时调用 C 函数（与 lwext4 相同）
+pub struct CBlockDevice {
+    bd: NonNull<ext4_blockdev>,
+}
+
+impl CBlockDevice {
+    /// 从 C 结构体指针创建
+    ///
+    /// `bd` 或 `bd->bdif` 为空、缺少 `bread`/`bwrite`、物理块大小为 0 时返回 `None`
+    ///
+    /// # Safety
+    ///
+    /// `bd` 和 `bd->bdif` 必须指向有效的结构体，并且在返回值的整个生命周期内
+    /// 保持有效、不被其他代码同时修改
+    pub unsafe fn from_raw(bd: *mut ext4_blockdev) -> Option<Self> {
+        let bd = NonNull::new(bd)?;
+        // SAFETY: 调用者保证指针有效
+        let iface = unsafe { bd.as_ref().bdif.as_ref()? };
+        if iface.bread.is_none() || iface.bwrite.is_none() || iface.ph_bsize == 0 {
+            return None;
+        }
+        Some(Self { bd })
+    }
+
+    /// 按名称取回已注册的设备
+    ///
+    /// 注册时已检查过结构体，返回值在设备注销前有效
+    pub fn lookup(name: &str) -> Option<Self> {
+        let bd = REGISTRY.with(|slots| {
+            slots.iter().flatten().find(|slot| slot.name() == name.as_bytes()).map(|slot| slot.bd)
+        })?;
+        // SAFETY: ext4_device_register 的调用者保证结构体在注销前有效
+        unsafe { Self::from_raw(bd) }
+    }
+
+    /// 底层 C 结构体指针
+    pub fn as_raw(&self) -> *mut ext4_blockdev {
+        self.bd.as_ptr()
+    }
+
+    fn dev(&self) -> &ext4_blockdev {
+        // SAFETY: from_raw 的调用者保证指针有效
+        unsafe { self.bd.as_ref() }
+    }
+
+    fn iface(&self) -> &ext4_blockdev_iface {
+        // SAFETY: 同 dev()，from_raw 已检查非空
+        unsafe { &*self.dev().bdif }
+    }
+
+    fn iface_mut(&mut self) -> &mut ext4_blockdev_iface {
+        // SAFETY: 同 iface()
+        unsafe { &mut *self.dev().bdif }
+    }
+
+    /// 分区起始处的物理块号
+    fn part_start(&self) -> u64 {
+        self.dev().part_offset / self.iface().ph_bsize as u64
+    }
+
+    /// 调用可选的设备函数（为空时视为成功）
+    fn call(&mut self, f: Option<ext4_blockdev_fn>, message: &'static str) -> Result<()> {
+        match f {
+            // SAFETY: 函数指针由 C 代码提供，参数是它注册的结构体
+            Some(f) if unsafe { f(self.bd.as_ptr()) } != EOK => Err(Error::new(ErrorKind::Io, message)),
+            _ => Ok(()),
+        }
+    }
+
+    /// 检查请求的物理块范围和缓冲区长度，返回字节数
+    fn check_request(&self, lba: u64, count: u32, buf_len: usize) -> Result<usize> {
+        let bytes = count as usize * self.iface().ph_bsize as usize;
+        if buf_len < bytes {
+            return Err(Error::new(ErrorKind::InvalidInput, "buffer too small"));
+        }
+        let end = self.part_start() + lba + count as u64;
+        if end > self.iface().ph_bcnt {
+            return Err(Error::new(ErrorKind::InvalidInput, "request beyond end of device"));
+        }
+        Ok(bytes)
+    }
+}
+
+impl BlockDevice for CBlockDevice {
+    fn block_size(&self) -> u32 {
+        match self.dev().lg_bsize {
+            0 => DEFAULT_LG_BSIZE,
+            size => size,
+        }
+    }
+
+    fn sector_size(&self) -> u32 {
+        self.iface().ph_bsize
+    }
+
+    fn total_blocks(&self) -> u64 {
+        let part_size = match self.dev().part_size {
+            0 => self.iface().ph_bcnt * self.iface().ph_bsize as u64 - self.dev().part_offset,
+            size => size,
+        };
+        part_size / self.block_size() as u64
+    }
+
+    fn read_blocks(&mut self, lba: u64, count: u32, buf: &mut [u8]) -> Result<usize> {
+        let bytes = self.check_request(lba, count, buf.len())?;
+        let blk_id = self.part_start() + lba;
+        let bread = self.iface().bread.ok_or(Error::new(ErrorKind::Io, "bread not set"))?;
+        // SAFETY: 缓冲区长度已检查，至少 count 个物理块
+        if unsafe { bread(self.bd.as_ptr(), buf.as_mut_ptr().cast(), blk_id, count) } != EOK {
+            return Err(Error::new(ErrorKind::Io, "bread failed"));
+        }
+        let iface = self.iface_mut();
+        iface.bread_ctr = iface.bread_ctr.wrapping_add(1);
+        Ok(bytes)
+    }
+
+    fn write_blocks(&mut self, lba: u64, count: u32, buf: &[u8]) -> Result<usize> {
+        let bytes = self.check_request(lba, count, buf.len())?;
+        let blk_id = self.part_start() + lba;
+        let bwrite = self.iface().bwrite.ok_or(Error::new(ErrorKind::Io, "bwrite not set"))?;
+        // SAFETY: 同 read_blocks
+        if unsafe { bwrite(self.bd.as_ptr(), buf.as_ptr().cast(), blk_id, count) } != EOK {
+            return Err(Error::new(ErrorKind::Io, "bwrite failed"));
+        }
+        let iface = self.iface_mut();
+        iface.bwrite_ctr = iface.bwrite_ctr.wrapping_add(1);
+        Ok(bytes)
+    }
+
+    fn open(&mut self) -> Result<()> {
+        if self.iface().ph_refctr == 0 {
+            self.call(self.iface().open, "device open failed")?;
+        }
+        let iface = self.iface_mut();
+        iface.ph_refctr += 1;
+        Ok(())
+    }
+
+    fn close(&mut self) -> Result<()> {
+        match self.iface().ph_refctr {
+            0 => Ok(()),
+            1 => {
+                self.call(self.iface().close, "device close failed")?;
+                self.iface_mut().ph_refctr = 0;
+                Ok(())
+            }
+            _ => {
+                self.iface_mut().ph_refctr -= 1;
+                Ok(())
+            }
+        }
+    }
+}
+
+/// 注册表中的一项
+#[derive(Clone, Copy)]
+struct Slot {
+    name: [u8; EXT4_DEV_NAME_MAX],
+    name_len: usize,
+    bd: *mut ext4_blockdev,
+}
+
+impl Slot {
+    fn name(&self) -> &[u8] {
+        &self.name[..self.name_len]
+    }
+}
+
+/// 已注册设备表（对应 lwext4 的 `s_bdevices`）
+struct Registry {
+    busy: AtomicBool,
+    slots: UnsafeCell<[Option<Slot>; CONFIG_EXT4_BLOCKDEVS_COUNT]>,
+}
+
+// SAFETY: `slots` 只在 `busy` 由 false 置为 true 之后访问
+unsafe impl Sync for Registry {}
+
+impl Registry {
+    fn with<R>(&self, f: impl FnOnce(&mut [Option<Slot>; CONFIG_EXT4_BLOCKDEVS_COUNT]) -> R) -> R {
+        while self.busy.compare_exchange_weak(false, true, Ordering::Acquire, Ordering::Relaxed).is_err() {
+            core::hint::spin_loop();
+        }
+        // SAFETY: 持有 busy 标志期间独占访问
+        let result = f(unsafe { &mut *self.slots.get() });
+        self.busy.store(false, Ordering::Release);
+        result
+    }
+}
+
+static REGISTRY: Registry = Registry {
+    busy: AtomicBool::new(false),
+    slots: UnsafeCell::new([None; CONFIG_EXT4_BLOCKDEVS_COUNT]),
+};
+
+/// 读取 C 字符串形式的设备名
+///
+/// # Safety
+///
+/// `dev_name` 为空或指向 NUL 结尾的字符串
+unsafe fn dev_name<'a>(dev_name: *const c_char) -> Option<&'a [u8]> {
+    if dev_name.is_null() {
+        return None;
+    }
+    // SAFETY: 调用者保证是 NUL 结尾的字符串
+    let name = unsafe { CStr::from_ptr(dev_name) }.to_bytes();
+    (!name.is_empty() && name.len() <= EXT4_DEV_NAME_MAX).then_some(name)
+}
+
+/// C API: ext4_device_register
+///
+/// 以 `dev_name` 注册块设备。
+///
+/// 返回 `EOK`；参数无效时返回 `EINVAL`，名称已注册时返回 `EEXIST`，
+/// 注册表已满（[`CONFIG_EXT4_BLOCKDEVS_COUNT`]）时返回 `ENOSPC`。
+///
+/// # Safety
+///
+/// `bd` 和 `bd->bdif` 必须在注销前保持有效，`dev_name` 必须是 NUL 结尾的字符串
+#[no_mangle]
+pub unsafe extern "C" fn ext4_device_register(bd: *mut ext4_blockdev, dev_name: *const c_char) -> c_int {
+    // SAFETY: 调用者保证
+    let (Some(name), Some(_)) = (unsafe { self::dev_name(dev_name) }, unsafe { CBlockDevice::from_raw(bd) })
+    else {
+        return EINVAL;
+    };
+
+    REGISTRY.with(|slots| {
+        if slots.iter().flatten().any(|slot| slot.name() == name) {
+            return EEXIST;
+        }
+        let Some(free) = slots.iter_mut().find(|slot| slot.is_none()) else {
+            return ENOSPC;
+        };
+        let mut slot = Slot { name: [0; EXT4_DEV_NAME_MAX], name_len: name.len(), bd };
+        slot.name[..name.len()].copy_from_slice(name);
+        *free = Some(slot);
+        EOK
+    })
+}
+
+/// C API: ext4_device_unregister
+///
+/// 注销 `dev_name`。返回 `EOK`，未注册时返回 `ENOENT`
+///
+/// # Safety
+///
+/// `dev_name` 必须是 NUL 结尾的字符串
+#[no_mangle]
+pub unsafe extern "C" fn ext4_device_unregister(dev_name: *const c_char) -> c_int {
+    // SAFETY: 调用者保证
+    let Some(name) = (unsafe { self::dev_name(dev_name) }) else {
+        return EINVAL;
+    };
+
+    REGISTRY.with(|slots| {
+        match slots.iter_mut().find(|slot| slot.is_some_and(|slot| slot.name() == name)) {
+            Some(slot) => {
+                *slot = None;
+                EOK
+            }
+            None => ENOENT,
+        }
+    })
+}
+
+/// C API: ext4_device_unregister_all
+///
+/// 注销所有设备
+#[no_mangle]
+pub extern "C" fn ext4_device_unregister_all() -> c_int {
+    REGISTRY.with(|slots| slots.fill(None));
+    EOK
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use alloc::{boxed::Box, vec, vec::Vec};
+    use core::ptr;
+
+    fn registered(name: &str) -> *mut ext4_blockdev {
+        CBlockDevice::lookup(name).map_or(ptr::null_mut(), |dev| dev.as_raw())
+    }
+
+    const PH_BSIZE: u32 = 512;
+
+    unsafe extern "C" fn ram_bread(bdev: *mut ext4_blockdev, buf: *mut c_void, blk_id: u64, blk_cnt: u32) -> c_int {
+        let disk = unsafe { &*((*(*bdev).bdif).p_user as *const Vec<u8>) };
+        let start = blk_id as usize * PH_BSIZE as usize;
+        let len = blk_cnt as usize * PH_BSIZE as usize;
+        unsafe { ptr::copy_nonoverlapping(disk[start..].as_ptr(), buf.cast::<u8>(), len) };
+        EOK
+    }
+
+    unsafe extern "C" fn ram_bwrite(
+        bdev: *mut ext4_blockdev,
+        buf: *const c_void,
+        blk_id: u64,
+        blk_cnt: u32,
+    ) -> c_int {
+        let disk = unsafe { &mut *((*(*bdev).bdif).p_user as *mut Vec<u8>) };
+        let start = blk_id as usize * PH_BSIZE as usize;
+        let len = blk_cnt as usize * PH_BSIZE as usize;
+        unsafe { ptr::copy_nonoverlapping(buf.cast::<u8>(), disk[start..].as_mut_ptr(), len) };
+        EOK
+    }
+
+    /// 16 KiB 的内存盘，分区从第 2 个物理块开始
+    fn ram_device(disk: &mut Vec<u8>) -> (Box<ext4_blockdev_iface>, Box<ext4_blockdev>) {
+        let mut iface = Box::new(ext4_blockdev_iface {
+            open: None,
+            bread: Some(ram_bread),
+            bwrite: Some(ram_bwrite),
+            close: None,
+            lock: None,
+            unlock: None,
+            ph_bsize: PH_BSIZE,
+            ph_bcnt: (disk.len() / PH_BSIZE as usize) as u64,
+            ph_bbuf: ptr::null_mut(),
+            ph_refctr: 0,
+            bread_ctr: 0,
+            bwrite_ctr: 0,
+            p_user: (disk as *mut Vec<u8>).cast(),
+        });
+        let bd = Box::new(ext4_blockdev {
+            bdif: &mut *iface,
+            part_offset: 2 * PH_BSIZE as u64,
+            part_size: 0,
+            bc: ptr::null_mut(),
+            lg_bsize: 1024,
+            lg_bcnt: 0,
+            cache_write_back: 0,
+            fs: ptr::null_mut(),
+            journal: ptr::null_mut(),
+        });
+        (iface, bd)
+    }
+
+    #[test]
+    fn test_c_blockdev_read_write() {
+        let mut disk = vec![0u8; 16 * 1024];
+        let (_iface, mut bd) = ram_device(&mut disk);
+        {
+            let mut dev = unsafe { CBlockDevice::from_raw(&mut *bd) }.unwrap();
+
+            assert_eq!(dev.block_size(), 1024);
+            assert_eq!(dev.total_blocks(), 15);
+
+            let data = vec![0xabu8; 1024];
+            assert_eq!(dev.write_blocks(2, 2, &data).unwrap(), 1024);
+            let mut back = vec![0u8; 1024];
+            assert_eq!(dev.read_blocks(2, 2, &mut back).unwrap(), 1024);
+            assert_eq!(back, data);
+            assert!(dev.read_blocks(30, 2, &mut back).is_err());
+            assert_eq!((dev.iface().bread_ctr, dev.iface().bwrite_ctr), (1, 1));
+        }
+        // 分区偏移 2 个物理块
+        assert!(disk[4 * 512..6 * 512].iter().all(|&b| b == 0xab));
+    }
+
+    #[test]
+    fn test_device_register() {
+        let mut disk = vec![0u8; 16 * 1024];
+        let (_iface, mut bd) = ram_device(&mut disk);
+        let bd: *mut ext4_blockdev = &mut *bd;
+
+        unsafe {
+            assert_eq!(ext4_device_register(bd, c"ram0".as_ptr()), EOK);
+            assert_eq!(ext4_device_register(bd, c"ram0".as_ptr()), EEXIST);
+            assert_eq!(ext4_device_register(ptr::null_mut(), c"ram1".as_ptr()), EINVAL);
+            assert_eq!(registered("ram0"), bd);
+            assert!(registered("ram1").is_null());
+
+            assert_eq!(ext4_device_unregister(c"ram0".as_ptr()), EOK);
+            assert_eq!(ext4_device_unregister(c"ram0".as_ptr()), ENOENT);
+            assert!(registered("ram0").is_null());
+        }
+    }
+}
@@ -5,6 +5,7 @@
 //! 这些函数仅保留 C 风格的命名（`ext4_*`），内部实现全部使用 Rust 风格的方法。
 
 pub mod block;
+pub mod blockdev;
 
 // 可以根据需要添加其他模块
 // pub mod fs;
@@ -46,7 +46,7 @@ use crate::{
 };
 
 use super::buffer::CacheBuffer;
-use alloc::collections::BTreeSet;  // 使用BTreeSet因为no_std环境
+use alloc::collections::{BTreeMap, BTreeSet};  // 使用BTreeSet因为no_std环境
 use core::num::NonZeroUsize;
 use lru::LruCache;
 
@@ -54,6 +54,97 @@ use lru::LruCache;
 /// 增大到256以支持大量写操作（如apk add vim）
 pub const DEFAULT_CACHE_SIZE: usize = 256;
 
+/// 启用预读时建议的窗口大小（块数）
+pub const DEFAULT_READAHEAD_WINDOW: u32 = 8;
+
+/// 预读策略配置
+///
+/// 当检测到连续的顺序缺失时，一次设备读取会额外取回后续 `window` 个块。
+/// 这对 SD 卡等单次请求开销较大的设备效果明显。
+///
+/// 默认禁用，需要时通过 [`BlockCache::set_readahead`] 或
+/// `BlockDev::set_readahead` 显式开启。
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ReadaheadConfig {
+    /// 每次预读的额外块数，0 表示禁用预读
+    pub window: u32,
+    /// 触发预读前需要的连续顺序缺失次数
+    pub trigger: u32,
+}
+
+impl ReadaheadConfig {
+    /// 禁用预读的配置
+    pub const fn disabled() -> Self {
+        Self { window: 0, trigger: 0 }
+    }
+
+    /// 按 [`DEFAULT_READAHEAD_WINDOW`] 启用的顺序预读配置
+    pub const fn sequential() -> Self {
+        Self { window: DEFAULT_READAHEAD_WINDOW, trigger: 2 }
+    }
+
+    /// 是否启用预读
+    pub fn is_enabled(&self) -> bool {
+        self.window > 0
+    }
+}
+
+impl Default for ReadaheadConfig {
+    fn default() -> Self {
+        Self::disabled()
+    }
+}
+
+/// 脏块写回阈值
+///
+/// 脏块数超过容量的 `high_water`% 时，从最久未使用的脏块开始写回，
+/// 直到不超过 `low_water`%。缩短断电时丢失数据的窗口，
+/// 也避免缓存被脏块填满后才被迫同步写回。
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct WritebackConfig {
+    /// 高水位（容量的百分比），0 表示禁用阈值写回
+    pub high_water: u8,
+    /// 低水位（容量的百分比）
+    pub low_water: u8,
+}
+
+impl WritebackConfig {
+    /// 禁用阈值写回：脏块只在显式刷新或缓存满时写回
+    pub const fn disabled() -> Self {
+        Self { high_water: 0, low_water: 0 }
+    }
+
+    /// 是否启用阈值写回
+    pub fn is_enabled(&self) -> bool {
+        self.high_water > 0
+    }
+}
+
+impl Default for WritebackConfig {
+    fn default() -> Self {
+        Self { high_water: 80, low_water: 50 }
+    }
+}
+
+/// 分段 LRU 中保护段最多占缓存容量的百分比
+pub const SLRU_PROTECTED_PERCENT: usize = 75;
+
+/// 缓存淘汰策略
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum CachePolicy {
+    /// 纯 LRU：所有块按最近访问时间淘汰
+    #[default]
+    Lru,
+    /// 分段 LRU（2Q 的简化形式）
+    ///
+    /// 新读入的数据块先进入试用段，再次访问时晋升到保护段；经由
+    /// [`Block`](crate::block::Block) 访问的元数据块直接进入保护段。
+    /// 淘汰时先淘汰试用段中的块，大文件的顺序读写不会把 inode 表、
+    /// 位图和目录块挤出缓存。保护段超过容量的
+    /// [`SLRU_PROTECTED_PERCENT`]% 时，最久未使用的保护块降回试用段。
+    SegmentedLru,
+}
+
 /// 缓存统计信息
 #[derive(Debug, Clone, Default)]
 pub struct CacheStats {
@@ -67,6 +158,16 @@ pub struct CacheStats {
     pub writebacks: u64,
     /// 当前脏块数量
     pub dirty_blocks: usize,
+    /// 通过预读填充的块数
+    pub readahead_blocks: u64,
+    /// 块组描述符缓存命中次数
+    pub desc_hits: u64,
+    /// 块组描述符缓存未命中次数
+    pub desc_misses: u64,
+    /// 位图校验缓存命中次数（跳过了一次校验和计算）
+    pub bitmap_hits: u64,
+    /// 位图校验缓存未命中次数
+    pub bitmap_misses: u64,
 }
 
 impl CacheStats {
@@ -119,6 +220,27 @@ pub struct BlockCache {
 
     /// 统计信息
     stats: CacheStats,
+
+    /// 预读策略
+    readahead: ReadaheadConfig,
+
+    /// 下一个被视为顺序访问的缺失块
+    next_seq_lba: u64,
+
+    /// 当前连续顺序缺失次数
+    seq_misses: u32,
+
+    /// 淘汰策略
+    policy: CachePolicy,
+
+    /// 分段 LRU 保护段中的块
+    protected: BTreeSet<u64>,
+
+    /// 脏块写回阈值
+    writeback: WritebackConfig,
+
+    /// 被固定的块及其固定次数，固定的块永不驱逐
+    pinned: BTreeMap<u64, u32>,
 }
 
 impl BlockCache {
@@ -141,9 +263,29 @@ impl BlockCache {
             block_size,
             write_back_counter: 0,
             stats: CacheStats::default(),
+            readahead: ReadaheadConfig::default(),
+            next_seq_lba: u64::MAX,
+            seq_misses: 0,
+            policy: CachePolicy::Lru,
+            protected: BTreeSet::new(),
+            writeback: WritebackConfig::default(),
+            pinned: BTreeMap::new(),
         }
     }
 
+    /// 获取淘汰策略
+    pub fn policy(&self) -> CachePolicy {
+        self.policy
+    }
+
+    /// 设置淘汰策略
+    ///
+    /// 已缓存的块保留；切换到分段 LRU 时它们都从试用段开始
+    pub fn set_policy(&mut self, policy: CachePolicy) {
+        self.policy = policy;
+        self.protected.clear();
+    }
+
     /// 分配缓存块
     ///
     /// 对应 lwext4 的 `ext4_bcache_alloc`
@@ -179,6 +321,25 @@ impl BlockCache {
     /// TODO:如果多处想要引用同一逻辑块的cache，当前的实现无法满足并发只读的需求，只能做到串行访问。
     /// 因为cache的操作通过block handle进行，而block handle需要持有device的mut引用，同一时刻只能有一个block handle。
     pub fn alloc(&mut self, lba: u64) -> Result<(&mut CacheBuffer, bool)> {
+        self.alloc_with(lba, false, true)
+    }
+
+    /// 分配元数据块
+    ///
+    /// 与 [`alloc`](Self::alloc) 相同，但在分段 LRU 策略下块直接进入保护段
+    pub fn alloc_metadata(&mut self, lba: u64) -> Result<(&mut CacheBuffer, bool)> {
+        self.alloc_with(lba, true, true)
+    }
+
+    /// 分配文件数据块
+    ///
+    /// 与 [`alloc`](Self::alloc) 相同，但命中时不晋升到保护段：
+    /// 数据块的读-改-写不应把元数据挤出保护段
+    pub fn alloc_data(&mut self, lba: u64) -> Result<(&mut CacheBuffer, bool)> {
+        self.alloc_with(lba, false, false)
+    }
+
+    fn alloc_with(&mut self, lba: u64, metadata: bool, promote: bool) -> Result<(&mut CacheBuffer, bool)> {
         self.stats.total_accesses += 1;
 
         // lru crate 自动处理：
@@ -186,6 +347,10 @@ impl BlockCache {
         // - 如果不存在，contains检查后手动插入
         if self.cache.contains(&lba) {
             self.stats.hits += 1;
+            // 再次访问的块晋升到保护段
+            if promote {
+                self.protect(lba);
+            }
             // get_mut 会自动更新LRU顺序
             let buf = self.cache.get_mut(&lba).unwrap();
             log::trace!("[CACHE] alloc LBA={:#x} HIT (dirty={})", lba, buf.is_dirty());
@@ -215,36 +380,68 @@ impl BlockCache {
         // 创建新块并插入
         let buf = CacheBuffer::new(lba, self.block_size);
         self.cache.put(lba, buf);
+        if metadata {
+            self.protect(lba);
+        }
         log::debug!("[CACHE] alloc LBA={:#x} NEW block inserted", lba);
 
         // 返回新插入的块
         Ok((self.cache.get_mut(&lba).unwrap(), true))
     }
 
+    /// 分段 LRU 下把块放入保护段，必要时把最旧的保护块降回试用段
+    fn protect(&mut self, lba: u64) {
+        if self.policy != CachePolicy::SegmentedLru || !self.protected.insert(lba) {
+            return;
+        }
+
+        let limit = (self.capacity() * SLRU_PROTECTED_PERCENT / 100).max(1);
+        if self.protected.len() > limit {
+            // iter() 从新到旧遍历，rev() 之后最旧的在前
+            let oldest = self.cache.iter().rev().map(|(k, _)| *k).find(|k| *k != lba && self.protected.contains(k));
+            if let Some(oldest) = oldest {
+                self.protected.remove(&oldest);
+            }
+        }
+    }
+
+    /// 从缓存中移除块
+    fn remove(&mut self, lba: u64) -> Option<CacheBuffer> {
+        self.protected.remove(&lba);
+        self.cache.pop(&lba)
+    }
+
     /// 驱逐一个块为新块腾出空间
     ///
     /// # 策略
     ///
-    /// 1. 从LRU端开始查找第一个**非脏**块
+    /// 1. 从LRU端开始查找第一个**非脏**、未固定的块（分段 LRU 下先只看试用段）
     /// 2. 驱逐该块
     /// 3. 如果所有块都是脏的，返回CacheFull错误
     ///
     /// **重要**：绝不驱逐脏块！驱逐脏块会导致数据丢失和磁盘损坏。
     /// 调用者应该在调用alloc之前检查脏块比例，必要时主动flush。
     fn evict_for_new_block(&mut self) -> Result<()> {
-        // lru crate的iter()按照LRU到MRU顺序遍历
+        // lru crate的iter()按照MRU到LRU顺序遍历，rev()后最老的在前
         // 收集所有块的LBA
-        let keys: alloc::vec::Vec<u64> = self.cache.iter().map(|(k, _)| *k).collect();
+        let keys: alloc::vec::Vec<u64> = self.cache.iter().rev().map(|(k, _)| *k).collect();
 
         // 从LRU端（最老的）开始查找非脏块
-        // 注意：iter()已经是LRU到MRU顺序，不需要rev()
         // TODO：这里的算法或许可以进一步优化
-        for lba in keys.iter() {
-            if !self.dirty_set.contains(lba) {
-                // 找到非脏块，驱逐它
-                self.cache.pop(lba);
-                log::debug!("[CACHE] Evicted clean block LBA={:#x}", lba);
-                return Ok(());
+        let segmented = self.policy == CachePolicy::SegmentedLru;
+        for skip_protected in [segmented, false] {
+            for lba in keys.iter() {
+                if self.pinned.contains_key(lba) || (skip_protected && self.protected.contains(lba)) {
+                    continue;
+                }
+                // 正在初始化的块还被 get_noread 的持有者使用，同样不能驱逐
+                let initializing = self.cache.peek(lba).is_some_and(|buf| buf.is_initializing());
+                if !self.dirty_set.contains(lba) && !initializing {
+                    // 找到非脏块，驱逐它
+                    self.remove(*lba);
+                    log::debug!("[CACHE] Evicted clean block LBA={lba:#x}");
+                    return Ok(());
+                }
             }
         }
 
@@ -315,13 +512,21 @@ impl BlockCache {
     /// 成功返回块数据的切片，失败返回NotFound错误
     pub fn read_block(&self, lba: u64) -> Result<&[u8]> {
         if let Some(buf) = self.cache.peek(&lba) {
-            if buf.is_uptodate() {
+            if Self::has_valid_data(buf) {
                 return Ok(&buf.data);
             }
         }
         Err(Error::new(ErrorKind::NotFound, "Block not in cache"))
     }
 
+    /// 缓存块的数据能否代替磁盘内容
+    ///
+    /// 脏块即使没有标记 uptodate（例如分配后只写入了一部分）也比磁盘新，
+    /// 不能再从磁盘读取覆盖
+    fn has_valid_data(buf: &CacheBuffer) -> bool {
+        buf.is_uptodate() || buf.is_dirty()
+    }
+
     /// 写入缓存块数据
     ///
     /// 如果块在缓存中，写入数据并标记为脏
@@ -346,6 +551,36 @@ impl BlockCache {
         Err(Error::new(ErrorKind::NotFound, "Block not in cache"))
     }
 
+    /// 检查块是否在缓存中且数据有效（uptodate 或脏）
+    pub fn contains(&self, lba: u64) -> bool {
+        self.cache.peek(&lba).is_some_and(Self::has_valid_data)
+    }
+
+    /// 检查块是否在缓存中且正在初始化（`get_noread` 之后尚未写入）
+    pub fn is_initializing(&self, lba: u64) -> bool {
+        self.cache.peek(&lba).is_some_and(|buf| buf.is_initializing())
+    }
+
+    /// 用已写入磁盘的数据更新缓存副本
+    ///
+    /// 绕过缓存直接写设备后调用，保证缓存与磁盘一致。
+    /// 块不在缓存中时什么都不做；在缓存中时覆盖数据并标记为干净。
+    ///
+    /// # 返回
+    ///
+    /// 块是否在缓存中
+    pub fn update_clean(&mut self, lba: u64, data: &[u8]) -> bool {
+        if let Some(buf) = self.cache.peek_mut(&lba) {
+            let len = data.len().min(buf.data.len());
+            buf.data[..len].copy_from_slice(&data[..len]);
+            buf.mark_uptodate();
+            buf.clear_dirty();
+            self.dirty_set.remove(&lba);
+            return true;
+        }
+        false
+    }
+
     /// TODO: flush_lba flush_all 这两个方法已经不再使用，因为重构了device与cache的职责，
     /// 现在device负责实际的I/O操作，cache负责缓存管理。将来或许考虑删除这两个方法。
     /// 刷新单个块到磁盘
@@ -425,7 +660,7 @@ impl BlockCache {
     ///
     /// * `lba` - 逻辑块地址
     pub fn invalidate_buffer(&mut self, lba: u64) -> Result<()> {
-        self.cache.pop(&lba);
+        self.remove(lba);
         self.dirty_set.remove(&lba);
         Ok(())
     }
@@ -444,7 +679,7 @@ impl BlockCache {
         let mut invalidated = 0;
 
         for lba in from..(from + count as u64) {
-            if self.cache.pop(&lba).is_some() {
+            if self.remove(lba).is_some() {
                 invalidated += 1;
             }
             self.dirty_set.remove(&lba);
@@ -532,7 +767,7 @@ impl BlockCache {
     /// 返回的列表按LRU顺序排列（最老的在前）
     pub fn get_dirty_blocks(&self) -> alloc::vec::Vec<u64> {
         // 获取LRU顺序的所有块
-        let lru_order: alloc::vec::Vec<u64> = self.cache.iter().map(|(k, _)| *k).collect();
+        let lru_order: alloc::vec::Vec<u64> = self.cache.iter().rev().map(|(k, _)| *k).collect();
 
         // 过滤出脏块
         lru_order.into_iter()
@@ -557,11 +792,218 @@ impl BlockCache {
         Ok(())
     }
 
+    /// 获取写回阈值
+    pub fn writeback(&self) -> WritebackConfig {
+        self.writeback
+    }
+
+    /// 设置写回阈值
+    pub fn set_writeback(&mut self, config: WritebackConfig) {
+        self.writeback = config;
+    }
+
+    /// 超过高水位时，需要写回多少个脏块才能回到低水位；未超过时返回 0
+    pub fn excess_dirty(&self) -> usize {
+        if !self.writeback.is_enabled() {
+            return 0;
+        }
+        let capacity = self.capacity();
+        let high = capacity * self.writeback.high_water as usize / 100;
+        let low = capacity * self.writeback.low_water.min(self.writeback.high_water) as usize / 100;
+        if self.dirty_set.len() > high {
+            self.dirty_set.len() - low
+        } else {
+            0
+        }
+    }
+
+    /// 获取预读配置
+    pub fn readahead(&self) -> ReadaheadConfig {
+        self.readahead
+    }
+
+    /// 设置预读配置
+    pub fn set_readahead(&mut self, config: ReadaheadConfig) {
+        self.readahead = config;
+        self.seq_misses = 0;
+    }
+
+    /// 计算一次缓存缺失应读取的块数
+    ///
+    /// 在 `lba` 缺失、准备从设备读取时调用。根据最近的缺失位置判断
+    /// 是否处于顺序访问中，并返回本次应读取的块数（包括 `lba` 本身）。
+    ///
+    /// 预读范围遇到已缓存的块即停止，且不超过缓存容量的 1/4，
+    /// 避免把刚读入的块挤出缓存。
+    ///
+    /// # 参数
+    ///
+    /// * `lba` - 缺失的逻辑块地址
+    /// * `limit` - 允许读取的最大块数（通常受设备末尾限制）
+    ///
+    /// # 返回
+    ///
+    /// 需要读取的块数，至少为 1
+    pub fn readahead_count(&mut self, lba: u64, limit: u64) -> u32 {
+        if lba == self.next_seq_lba {
+            self.seq_misses = self.seq_misses.saturating_add(1);
+        } else {
+            self.seq_misses = 1;
+        }
+
+        let mut count = 1u32;
+        if self.readahead.is_enabled() && self.seq_misses >= self.readahead.trigger {
+            let max = (self.readahead.window as u64 + 1)
+                .min((self.capacity() / 4) as u64)
+                .min(limit)
+                .max(1) as u32;
+            while count < max && !self.cache.contains(&(lba + count as u64)) {
+                count += 1;
+            }
+        }
+
+        self.next_seq_lba = lba + count as u64;
+        count
+    }
+
+    /// 填充预读得到的块
+    ///
+    /// `data` 包含从 `start` 开始的连续若干块。已在缓存中的块不会被覆盖；
+    /// 缓存满且无法驱逐干净块时停止填充（预读是尽力而为的）。
+    ///
+    /// # 返回
+    ///
+    /// 实际填充的块数
+    pub fn fill_readahead(&mut self, start: u64, data: &[u8]) -> usize {
+        let mut filled = 0;
+        for (i, chunk) in data.chunks_exact(self.block_size).enumerate() {
+            let lba = start + i as u64;
+            if self.cache.contains(&lba) {
+                continue;
+            }
+            if self.cache.len() >= self.cache.cap().get() && self.evict_for_new_block().is_err() {
+                break;
+            }
+            let mut buf = CacheBuffer::new(lba, self.block_size);
+            buf.data.copy_from_slice(chunk);
+            buf.mark_uptodate();
+            self.cache.put(lba, buf);
+            filled += 1;
+        }
+        self.stats.readahead_blocks += filled as u64;
+        log::trace!("[CACHE] readahead filled {filled} blocks from LBA={start:#x}");
+        filled
+    }
+
+    /// 固定块，使其在缓存中永不被驱逐
+    ///
+    /// 固定按 LBA 记录、可以嵌套，每次 `pin` 对应一次 [`unpin`](Self::unpin)。
+    /// 块不在缓存中时也可以固定，之后读入缓存的块会一直保留；
+    /// [`invalidate_buffer`](Self::invalidate_buffer) 和 [`clear`](Self::clear)
+    /// 仍会丢弃块的数据，但不解除固定。
+    ///
+    /// # 错误
+    ///
+    /// - `ErrorKind::NoSpace` - 固定的块已达到容量的一半，
+    ///   再固定会让普通块没有足够的空间
+    pub fn pin(&mut self, lba: u64) -> Result<()> {
+        if let Some(count) = self.pinned.get_mut(&lba) {
+            *count += 1;
+            return Ok(());
+        }
+        if self.pinned.len() >= self.capacity() / 2 {
+            return Err(Error::new(ErrorKind::NoSpace, "Too many pinned cache blocks"));
+        }
+        self.pinned.insert(lba, 1);
+        Ok(())
+    }
+
+    /// 解除一次固定
+    ///
+    /// 固定次数归零后块恢复正常淘汰；块未被固定时没有效果
+    pub fn unpin(&mut self, lba: u64) {
+        if let Some(count) = self.pinned.get_mut(&lba) {
+            *count -= 1;
+            if *count == 0 {
+                self.pinned.remove(&lba);
+            }
+        }
+    }
+
+    /// 解除所有固定
+    pub fn unpin_all(&mut self) {
+        self.pinned.clear();
+    }
+
+    /// 块是否被固定
+    pub fn is_pinned(&self, lba: u64) -> bool {
+        self.pinned.contains_key(&lba)
+    }
+
+    /// 被固定的块数
+    pub fn pinned_count(&self) -> usize {
+        self.pinned.len()
+    }
+
     /// 调整缓存大小
     ///
     /// 如果新容量小于当前块数，会驱逐LRU块
+    ///
+    /// 警告：被驱逐的脏块会丢失，固定的块也可能被驱逐，
+    /// 动态调整请使用 [`set_capacity`](Self::set_capacity)
     pub fn resize(&mut self, new_capacity: NonZeroUsize) {
         self.cache.resize(new_capacity);
+        let cache = &self.cache;
+        self.protected.retain(|lba| cache.contains(lba));
+    }
+
+    /// 调整缓存容量，不丢失脏块
+    ///
+    /// 缩小时按淘汰策略驱逐干净块。宿主系统内存紧张时可以随时调用。
+    ///
+    /// # 错误
+    ///
+    /// - `ErrorKind::InvalidInput` - `capacity` 为 0
+    /// - `ErrorKind::NoSpace` - 脏块数超过新容量，需要先写回；
+    ///   此时容量不变（可能已经驱逐了部分干净块）
+    /// - `ErrorKind::InvalidInput` - 固定的块超过新容量的一半
+    pub fn set_capacity(&mut self, capacity: usize) -> Result<()> {
+        let new_capacity = NonZeroUsize::new(capacity)
+            .ok_or(Error::new(ErrorKind::InvalidInput, "Cache capacity must be non-zero"))?;
+        if self.pinned.len() > capacity / 2 {
+            return Err(Error::new(ErrorKind::InvalidInput, "Cache capacity too small for pinned blocks"));
+        }
+
+        while self.cache.len() > capacity {
+            self.evict_for_new_block()?;
+        }
+        self.cache.resize(new_capacity);
+
+        // 容量变小后保护段可能超出上限，降级最旧的保护块
+        let limit = (capacity * SLRU_PROTECTED_PERCENT / 100).max(1);
+        let mut excess = self.protected.len().saturating_sub(limit);
+        if excess > 0 {
+            let oldest: alloc::vec::Vec<u64> = self.cache.iter().rev().map(|(k, _)| *k).collect();
+            for lba in oldest {
+                if excess == 0 {
+                    break;
+                }
+                if self.protected.remove(&lba) {
+                    excess -= 1;
+                }
+            }
+        }
+        Ok(())
+    }
+
+    /// 修改块大小，同时清空缓存（不刷新脏块！）
+    ///
+    /// 容量、淘汰策略、预读和写回配置保持不变
+    pub fn set_block_size(&mut self, block_size: usize) {
+        self.clear();
+        self.block_size = block_size;
+        self.next_seq_lba = u64::MAX;
+        self.seq_misses = 0;
     }
 
     /// 清空缓存（不刷新脏块！）
@@ -570,6 +1012,7 @@ impl BlockCache {
     pub fn clear(&mut self) {
         self.cache.clear();
         self.dirty_set.clear();
+        self.protected.clear();
     }
 }
 
@@ -581,6 +1024,9 @@ impl core::fmt::Debug for BlockCache {
             .field("dirty_count", &self.dirty_set.len())
             .field("block_size", &self.block_size)
             .field("write_back_enabled", &self.is_write_back_enabled())
+            .field("policy", &self.policy)
+            .field("writeback", &self.writeback)
+            .field("pinned", &self.pinned.len())
             .field("stats", &self.stats)
             .finish()
     }
@@ -770,6 +1216,71 @@ mod tests {
         assert_eq!(cache.stats.hit_rate(), 0.5);
     }
 
+    #[test]
+    fn test_readahead_count_sequential() {
+        let mut cache = BlockCache::new(64, 4096);
+        cache.set_readahead(ReadaheadConfig::sequential());
+
+        // 第一次缺失不触发预读
+        assert_eq!(cache.readahead_count(10, u64::MAX), 1);
+        // 紧接着的顺序缺失触发预读
+        assert_eq!(cache.readahead_count(11, u64::MAX), DEFAULT_READAHEAD_WINDOW + 1);
+        // 随机访问重置检测
+        assert_eq!(cache.readahead_count(100, u64::MAX), 1);
+        // 受 limit 限制
+        assert_eq!(cache.readahead_count(101, 3), 3);
+    }
+
+    #[test]
+    fn test_readahead_stops_at_cached_block() {
+        let mut cache = BlockCache::new(64, 4096);
+        cache.set_readahead(ReadaheadConfig::sequential());
+        cache.alloc(23).unwrap();
+
+        cache.readahead_count(19, u64::MAX);
+        assert_eq!(cache.readahead_count(20, u64::MAX), 3);
+
+        cache.set_readahead(ReadaheadConfig::disabled());
+        cache.readahead_count(30, u64::MAX);
+        assert_eq!(cache.readahead_count(31, u64::MAX), 1);
+    }
+
+    #[test]
+    fn test_fill_readahead() {
+        let mut cache = BlockCache::new(8, 4096);
+        let (buf, _) = cache.alloc(2).unwrap();
+        buf.data[0] = 0x55;
+        cache.mark_dirty(2).unwrap();
+
+        let data = alloc::vec![0xAAu8; 4096 * 3];
+        assert_eq!(cache.fill_readahead(1, &data), 2);
+
+        assert_eq!(cache.read_block(1).unwrap()[0], 0xAA);
+        assert_eq!(cache.read_block(3).unwrap()[0], 0xAA);
+        // 已缓存的脏块不被覆盖
+        assert_eq!(cache.get_block_data(2).unwrap()[0], 0x55);
+        assert_eq!(cache.stats().readahead_blocks, 2);
+        assert_eq!(cache.dirty_count(), 1);
+    }
+
+    #[test]
+    fn test_update_clean() {
+        let mut cache = BlockCache::new(8, 4096);
+        cache.alloc(5).unwrap();
+        cache.mark_dirty(5).unwrap();
+        // 脏块的数据比磁盘新，即使没有标记 uptodate 也算有效
+        assert!(cache.contains(5));
+
+        let data = alloc::vec![0x11u8; 4096];
+        assert!(cache.update_clean(5, &data));
+        assert!(cache.contains(5));
+        assert_eq!(cache.dirty_count(), 0);
+        assert_eq!(cache.read_block(5).unwrap()[0], 0x11);
+
+        assert!(!cache.update_clean(6, &data));
+        assert!(!cache.contains(6));
+    }
+
     #[test]
     fn test_write_back_mode() {
         let mut cache = BlockCache::new(8, 4096);
@@ -792,4 +1303,107 @@ mod tests {
         assert_eq!(cache.write_back_counter(), 0);
         assert!(!cache.is_write_back_enabled());
     }
+
+    #[test]
+    fn test_segmented_lru_keeps_metadata() {
+        let mut cache = BlockCache::new(4, 4096);
+        cache.set_policy(CachePolicy::SegmentedLru);
+
+        cache.alloc_metadata(1).unwrap();
+        cache.alloc_metadata(2).unwrap();
+        // 顺序读取大量数据块
+        for lba in 100..110 {
+            cache.alloc(lba).unwrap();
+        }
+
+        // 元数据块留在保护段，数据块互相淘汰
+        assert!(cache.find_get(1).is_some());
+        assert!(cache.find_get(2).is_some());
+        assert_eq!(cache.len(), 4);
+
+        // 再次访问的数据块晋升，保护段超出上限时最旧的元数据块降级
+        cache.alloc(109).unwrap();
+        cache.alloc(108).unwrap();
+        for lba in 200..204 {
+            cache.alloc(lba).unwrap();
+        }
+        assert!(cache.find_get(1).is_none());
+        assert!(cache.find_get(109).is_some());
+    }
+
+    #[test]
+    fn test_set_capacity_keeps_dirty() {
+        let mut cache = BlockCache::new(8, 4096);
+        for lba in 0..6 {
+            cache.alloc(lba).unwrap();
+        }
+        cache.mark_dirty(0).unwrap();
+        cache.mark_dirty(1).unwrap();
+
+        cache.set_capacity(3).unwrap();
+        assert_eq!(cache.capacity(), 3);
+        assert_eq!(cache.len(), 3);
+        assert!(cache.get_block_data(0).is_some());
+        assert!(cache.get_block_data(1).is_some());
+
+        // 脏块比新容量多时需要先写回
+        assert_eq!(cache.set_capacity(1).unwrap_err().kind(), ErrorKind::NoSpace);
+        assert_eq!(cache.capacity(), 3);
+        assert_eq!(cache.set_capacity(0).unwrap_err().kind(), ErrorKind::InvalidInput);
+    }
+
+    #[test]
+    fn test_excess_dirty() {
+        let mut cache = BlockCache::new(10, 4096);
+        for lba in 0..8 {
+            cache.alloc(lba).unwrap();
+            cache.mark_dirty(lba).unwrap();
+        }
+        // 8 个脏块没有超过 80%
+        assert_eq!(cache.excess_dirty(), 0);
+
+        cache.alloc(8).unwrap();
+        cache.mark_dirty(8).unwrap();
+        // 写回到 50%
+        assert_eq!(cache.excess_dirty(), 4);
+
+        cache.set_writeback(WritebackConfig::disabled());
+        assert_eq!(cache.excess_dirty(), 0);
+    }
+
+    #[test]
+    fn test_pinned_blocks_not_evicted() {
+        let mut cache = BlockCache::new(4, 4096);
+        cache.alloc(1).unwrap().0.mark_uptodate();
+        cache.pin(1).unwrap();
+        cache.pin(1).unwrap();
+        // 尚未缓存的块也可以固定
+        cache.pin(2).unwrap();
+        assert!(cache.pin(3).is_err());
+
+        for lba in 100..110 {
+            cache.alloc(lba).unwrap();
+        }
+        assert!(cache.contains(1));
+
+        cache.alloc(2).unwrap().0.mark_uptodate();
+        for lba in 200..210 {
+            cache.alloc(lba).unwrap();
+        }
+        assert!(cache.contains(1));
+        assert!(cache.contains(2));
+        assert!(cache.set_capacity(3).is_err());
+
+        // 固定可以嵌套，次数归零后恢复正常淘汰
+        cache.unpin(1);
+        assert!(cache.is_pinned(1));
+        cache.unpin(1);
+        assert!(!cache.is_pinned(1));
+        for lba in 300..310 {
+            cache.alloc(lba).unwrap();
+        }
+        assert!(!cache.contains(1));
+        assert!(cache.contains(2));
+        assert_eq!(cache.pinned_count(), 1);
+    }
 }
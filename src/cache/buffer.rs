@@ -26,6 +26,8 @@ bitflags! {
         const FLUSH    = 0x04;
         /// 临时块（不缓存）
         const TMP      = 0x08;
+        /// 由 `get_noread` 分配、写入者尚未填充（数据无效）
+        const INITIALIZING = 0x10;
     }
 }
 
@@ -102,11 +104,25 @@ impl CacheBuffer {
         self.flags.contains(CacheFlags::DIRTY)
     }
 
-    /// 标记数据有效
+    /// 标记数据有效（同时结束初始化状态）
     pub fn mark_uptodate(&mut self) {
+        self.flags.remove(CacheFlags::INITIALIZING);
         self.flags.insert(CacheFlags::UPTODATE);
     }
 
+    /// 标记为正在初始化
+    ///
+    /// 数据在写入者填充之前无效，不能被其他路径当作磁盘内容读取
+    pub fn mark_initializing(&mut self) {
+        self.flags.remove(CacheFlags::UPTODATE);
+        self.flags.insert(CacheFlags::INITIALIZING);
+    }
+
+    /// 检查是否正在初始化
+    pub fn is_initializing(&self) -> bool {
+        self.flags.contains(CacheFlags::INITIALIZING)
+    }
+
     /// 检查数据是否有效
     pub fn is_uptodate(&self) -> bool {
         self.flags.contains(CacheFlags::UPTODATE)
@@ -184,6 +200,20 @@ mod tests {
         assert!(buf.flags.contains(CacheFlags::UPTODATE));
     }
 
+    #[test]
+    fn test_initializing_flag() {
+        let mut buf = CacheBuffer::new(100, 4096);
+
+        buf.mark_initializing();
+        assert!(buf.is_initializing());
+        assert!(!buf.is_uptodate());
+
+        // 写入者填充数据后结束初始化
+        buf.mark_uptodate();
+        assert!(!buf.is_initializing());
+        assert!(buf.is_uptodate());
+    }
+
     #[test]
     fn test_flush_flag() {
         let mut buf = CacheBuffer::new(100, 4096);
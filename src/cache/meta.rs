@@ -0,0 +1,189 @@
+//! 元数据缓存
+//!
+//! 块缓存之上的一层小缓存，保存已解析的块组描述符和已通过校验的位图块，
+//! 减少 `InodeRef::get` 和分配器对同一元数据的重复读取与校验。
+//!
+//! # 一致性
+//!
+//! 缓存挂在 [`BlockDev`](crate::block::BlockDev) 上，由块设备层维护：
+//! - 通过 `Block::with_data_mut` 修改描述符块时，丢弃该块上缓存的描述符
+//! - `write_block`、直接写入和缓存失效会同时丢弃描述符和位图的校验状态
+//! - 事务回滚时整个缓存被清空
+//!
+//! 分配器自己通过 `Block` 修改位图时保留校验状态（与内核的
+//! `buffer_verified` 相同），位图只在从外部写入后重新校验。
+
+use alloc::collections::{BTreeMap, BTreeSet};
+
+use super::CacheStats;
+
+/// 缓存的描述符字节数（64 位描述符的大小）
+pub(crate) const GROUP_DESC_CACHE_BYTES: usize = 64;
+
+/// 默认最多缓存的块组描述符数量
+const DEFAULT_DESC_CACHE_ENTRIES: usize = 1024;
+
+/// 默认最多记录的已校验位图块数量
+const DEFAULT_BITMAP_CACHE_ENTRIES: usize = 4096;
+
+/// 块组描述符和位图缓存
+#[derive(Debug)]
+pub(crate) struct MetaCache {
+    /// 块组号 -> (描述符所在块, 描述符字节)
+    descs: BTreeMap<u32, (u64, [u8; GROUP_DESC_CACHE_BYTES])>,
+    /// 缓存了描述符的块
+    desc_blocks: BTreeSet<u64>,
+    /// 已通过校验和检查的位图块
+    bitmaps: BTreeSet<u64>,
+    desc_hits: u64,
+    desc_misses: u64,
+    bitmap_hits: u64,
+    bitmap_misses: u64,
+}
+
+impl MetaCache {
+    pub(crate) fn new() -> Self {
+        Self {
+            descs: BTreeMap::new(),
+            desc_blocks: BTreeSet::new(),
+            bitmaps: BTreeSet::new(),
+            desc_hits: 0,
+            desc_misses: 0,
+            bitmap_hits: 0,
+            bitmap_misses: 0,
+        }
+    }
+
+    /// 查找块组描述符，同时记录命中/未命中
+    pub(crate) fn get_desc(&mut self, group: u32) -> Option<[u8; GROUP_DESC_CACHE_BYTES]> {
+        match self.descs.get(&group) {
+            Some((_, desc)) => {
+                self.desc_hits += 1;
+                Some(*desc)
+            }
+            None => {
+                self.desc_misses += 1;
+                None
+            }
+        }
+    }
+
+    /// 缓存描述符块 `lba` 上从 `first_group` 开始的一组描述符
+    pub(crate) fn insert_descs(&mut self, lba: u64, first_group: u32, descs: &[[u8; GROUP_DESC_CACHE_BYTES]]) {
+        if self.descs.len() + descs.len() > DEFAULT_DESC_CACHE_ENTRIES {
+            self.descs.clear();
+            self.desc_blocks.clear();
+        }
+        for (i, desc) in descs.iter().enumerate() {
+            self.descs.insert(first_group + i as u32, (lba, *desc));
+        }
+        self.desc_blocks.insert(lba);
+    }
+
+    /// 位图块是否已经校验过，同时记录命中/未命中
+    pub(crate) fn bitmap_verified(&mut self, lba: u64) -> bool {
+        if self.bitmaps.contains(&lba) {
+            self.bitmap_hits += 1;
+            true
+        } else {
+            self.bitmap_misses += 1;
+            false
+        }
+    }
+
+    /// 记录位图块已通过校验
+    pub(crate) fn mark_bitmap_verified(&mut self, lba: u64) {
+        if self.bitmaps.len() >= DEFAULT_BITMAP_CACHE_ENTRIES {
+            self.bitmaps.clear();
+        }
+        self.bitmaps.insert(lba);
+    }
+
+    /// 描述符块被修改：丢弃该块上缓存的描述符
+    pub(crate) fn invalidate_descs(&mut self, lba: u64) {
+        if self.desc_blocks.remove(&lba) {
+            self.descs.retain(|_, (block, _)| *block != lba);
+        }
+    }
+
+    /// 一段块被外部写入或失效：丢弃其中的描述符和位图校验状态
+    pub(crate) fn invalidate_range(&mut self, from: u64, count: u64) {
+        let end = from.saturating_add(count);
+        let blocks: alloc::vec::Vec<u64> = self.desc_blocks.range(from..end).copied().collect();
+        for lba in blocks {
+            self.invalidate_descs(lba);
+        }
+        if self.bitmaps.range(from..end).next().is_some() {
+            self.bitmaps.retain(|lba| *lba < from || *lba >= end);
+        }
+    }
+
+    /// 清空整个缓存（保留统计）
+    pub(crate) fn clear(&mut self) {
+        self.descs.clear();
+        self.desc_blocks.clear();
+        self.bitmaps.clear();
+    }
+
+    /// 把命中/未命中计数填入块缓存统计
+    pub(crate) fn fill_stats(&self, stats: &mut CacheStats) {
+        stats.desc_hits = self.desc_hits;
+        stats.desc_misses = self.desc_misses;
+        stats.bitmap_hits = self.bitmap_hits;
+        stats.bitmap_misses = self.bitmap_misses;
+    }
+}
+
+impl Default for MetaCache {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_desc_invalidation() {
+        let mut cache = MetaCache::new();
+        let descs = [[1u8; GROUP_DESC_CACHE_BYTES], [2u8; GROUP_DESC_CACHE_BYTES]];
+        cache.insert_descs(10, 0, &descs);
+        cache.insert_descs(11, 2, &descs[..1]);
+
+        assert_eq!(cache.get_desc(1).unwrap()[0], 2);
+        assert!(cache.get_desc(5).is_none());
+
+        // 修改块 10 只影响它上面的描述符
+        cache.invalidate_descs(10);
+        assert!(cache.get_desc(0).is_none());
+        assert!(cache.get_desc(2).is_some());
+
+        cache.invalidate_range(5, 10);
+        assert!(cache.get_desc(2).is_none());
+
+        let mut stats = CacheStats::default();
+        cache.fill_stats(&mut stats);
+        assert_eq!(stats.desc_hits, 2);
+        assert_eq!(stats.desc_misses, 3);
+    }
+
+    #[test]
+    fn test_bitmap_verified() {
+        let mut cache = MetaCache::new();
+        assert!(!cache.bitmap_verified(100));
+        cache.mark_bitmap_verified(100);
+        assert!(cache.bitmap_verified(100));
+
+        // 描述符块的修改不影响位图
+        cache.invalidate_descs(100);
+        assert!(cache.bitmap_verified(100));
+
+        cache.invalidate_range(100, 1);
+        assert!(!cache.bitmap_verified(100));
+
+        let mut stats = CacheStats::default();
+        cache.fill_stats(&mut stats);
+        assert_eq!((stats.bitmap_hits, stats.bitmap_misses), (2, 2));
+    }
+}
@@ -8,6 +8,10 @@
 //! - [`BlockCache`] - 块缓存管理器，使用 lru crate 提供 LRU 驱逐
 //! - [`CacheFlags`] - 缓存块状态标志
 //! - [`CacheStats`] - 缓存统计信息
+//! - `MetaCache` - 块组描述符和位图缓存
+//! - [`ReadaheadConfig`] - 顺序预读策略
+//! - [`CachePolicy`] - 淘汰策略（LRU 或分段 LRU）
+//! - [`WritebackConfig`] - 脏块写回阈值
 //!
 //! # 设计原理
 //!
@@ -93,6 +97,11 @@
 
 mod buffer;
 mod block_cache;
+mod meta;
 
 pub use buffer::{CacheBuffer, CacheFlags, EndWriteCallback};
-pub use block_cache::{BlockCache, CacheStats, DEFAULT_CACHE_SIZE};
+pub use block_cache::{
+    BlockCache, CachePolicy, CacheStats, ReadaheadConfig, DEFAULT_CACHE_SIZE, DEFAULT_READAHEAD_WINDOW,
+    SLRU_PROTECTED_PERCENT, WritebackConfig,
+};
+pub(crate) use meta::{MetaCache, GROUP_DESC_CACHE_BYTES};
@@ -41,6 +41,9 @@ pub const EXT4_EXTENT_MAGIC: u16 = 0xF30A;
 /// Root inode 编号
 pub const EXT4_ROOT_INODE: u32 = 2;
 
+/// Resize inode 编号，管理保留的 GDT 块
+pub const EXT4_RESIZE_INODE: u32 = 7;
+
 /// 块组描述符大小（传统）
 pub const EXT4_GROUP_DESC_SIZE: usize = 32;
 
@@ -53,6 +56,21 @@ pub const EXT4_MIN_BLOCK_GROUP_DESCRIPTOR_SIZE: usize = 32;
 /// 块组描述符最大大小
 pub const EXT4_MAX_BLOCK_GROUP_DESCRIPTOR_SIZE: usize = 1024;
 
+/// 块组描述符中 `bg_block_bitmap_csum_hi` 的结束偏移，描述符不小于它时该字段有效
+pub const EXT4_BG_BLOCK_BITMAP_CSUM_HI_END: usize = 0x3A;
+
+/// 块组描述符中 `bg_inode_bitmap_csum_hi` 的结束偏移，描述符不小于它时该字段有效
+pub const EXT4_BG_INODE_BITMAP_CSUM_HI_END: usize = 0x3C;
+
+/// 块组标志：inode 表和 inode 位图未初始化
+pub const EXT4_BG_INODE_UNINIT: u16 = 0x0001;
+
+/// 块组标志：块位图未初始化
+pub const EXT4_BG_BLOCK_UNINIT: u16 = 0x0002;
+
+/// 块组标志：inode 表已清零
+pub const EXT4_BG_INODE_ZEROED: u16 = 0x0004;
+
 /// Superblock 状态：有效/已挂载
 pub const EXT4_SUPER_STATE_VALID: u16 = 0x0001;
 
@@ -62,6 +80,12 @@ pub const EXT4_SUPER_STATE_ERROR: u16 = 0x0002;
 /// Superblock 状态：孤儿恢复中
 pub const EXT4_SUPER_STATE_ORPHAN: u16 = 0x0004;
 
+/// `s_max_mnt_count` 为 0 时挂载设置的默认值，与内核 `EXT4_DFL_MAX_MNT_COUNT` 相同
+pub const EXT4_DFL_MAX_MNT_COUNT: u16 = 20;
+
+/// superblock 错误记录的错误码：文件系统损坏，与内核 `EXT4_ERR_EFSCORRUPTED` 相同
+pub const EXT4_ERR_EFSCORRUPTED: u8 = 5;
+
 /// 校验和类型：CRC32C
 pub const EXT4_CHECKSUM_CRC32C: u8 = 1;
 
@@ -177,6 +201,15 @@ pub const EXT4_INODE_MODE_OTHER_WRITE: u16 = 0x0002;
 /// 其他用户执行权限
 pub const EXT4_INODE_MODE_OTHER_EXEC: u16 = 0x0001;
 
+/// 设置用户 ID（SUID）
+pub const EXT4_INODE_MODE_SETUID: u16 = 0x0800;
+
+/// 设置组 ID（SGID），目录设置时其中新建的文件继承目录的属组
+pub const EXT4_INODE_MODE_SETGID: u16 = 0x0400;
+
+/// 粘滞位
+pub const EXT4_INODE_MODE_STICKY: u16 = 0x0200;
+
 //=============================================================================
 // Inode 标志
 //=============================================================================
@@ -190,12 +223,21 @@ pub const EXT4_INODE_FLAG_HUGE_FILE: u32 = 0x00040000;
 /// 目录使用哈希树索引
 pub const EXT4_INODE_FLAG_INDEX: u32 = 0x00001000;
 
+/// 目录中的文件名大小写不敏感
+pub const EXT4_INODE_FLAG_CASEFOLD: u32 = 0x40000000;
+
+/// 使用 fscrypt 加密（文件内容、目录项名称或符号链接目标）
+pub const EXT4_INODE_FLAG_ENCRYPT: u32 = 0x00000800;
+
 /// 不可变文件
 pub const EXT4_INODE_FLAG_IMMUTABLE: u32 = 0x00000010;
 
 /// 仅追加
 pub const EXT4_INODE_FLAG_APPEND: u32 = 0x00000020;
 
+/// inode 存放一个扩展属性的值（EA_INODE）
+pub const EXT4_INODE_FLAG_EA_INODE: u32 = 0x00200000;
+
 //=============================================================================
 // 目录项类型
 //=============================================================================
@@ -255,6 +297,9 @@ pub const EXT4_FEATURE_COMPAT_DIR_INDEX: u32 = 0x0020;
 /// 兼容特性：延迟 inode 表初始化
 pub const EXT4_FEATURE_COMPAT_LAZY_BG: u32 = 0x0040;
 
+/// 兼容特性：稀疏超级块 v2（仅 s_backup_bgs 指定的两个块组有备份）
+pub const EXT4_FEATURE_COMPAT_SPARSE_SUPER2: u32 = 0x0200;
+
 /// 不兼容特性：压缩
 pub const EXT4_FEATURE_INCOMPAT_COMPRESSION: u32 = 0x0001;
 
@@ -300,6 +345,15 @@ pub const EXT4_FEATURE_INCOMPAT_INLINE_DATA: u32 = 0x8000;
 /// 不兼容特性：加密
 pub const EXT4_FEATURE_INCOMPAT_ENCRYPT: u32 = 0x10000;
 
+/// 不兼容特性：大小写不敏感目录
+pub const EXT4_FEATURE_INCOMPAT_CASEFOLD: u32 = 0x20000;
+
+/// 文件名编码：UTF-8（Unicode 12.1）
+pub const EXT4_ENC_UTF8_12_1: u16 = 1;
+
+/// 文件名编码标志：严格模式，拒绝无效的 UTF-8 文件名
+pub const EXT4_ENC_STRICT_MODE_FL: u16 = 0x0001;
+
 /// 只读兼容特性：稀疏超级块
 pub const EXT4_FEATURE_RO_COMPAT_SPARSE_SUPER: u32 = 0x0001;
 
@@ -339,6 +393,30 @@ pub const EXT4_FEATURE_RO_COMPAT_READONLY: u32 = 0x1000;
 /// 只读兼容特性：项目配额
 pub const EXT4_FEATURE_RO_COMPAT_PROJECT: u32 = 0x2000;
 
+/// 支持的不兼容特性，带有其余特性的镜像拒绝挂载
+pub const EXT4_FEATURE_INCOMPAT_SUPP: u32 = EXT4_FEATURE_INCOMPAT_FILETYPE
+    | EXT4_FEATURE_INCOMPAT_RECOVER
+    | EXT4_FEATURE_INCOMPAT_META_BG
+    | EXT4_FEATURE_INCOMPAT_EXTENTS
+    | EXT4_FEATURE_INCOMPAT_64BIT
+    | EXT4_FEATURE_INCOMPAT_FLEX_BG
+    | EXT4_FEATURE_INCOMPAT_EA_INODE
+    | EXT4_FEATURE_INCOMPAT_CSUM_SEED
+    | EXT4_FEATURE_INCOMPAT_LARGEDIR
+    | EXT4_FEATURE_INCOMPAT_ENCRYPT
+    | EXT4_FEATURE_INCOMPAT_CASEFOLD;
+
+/// 可以读写的只读兼容特性，其余特性（包括 `READONLY`）挂载为只读
+pub const EXT4_FEATURE_RO_COMPAT_SUPP: u32 = EXT4_FEATURE_RO_COMPAT_SPARSE_SUPER
+    | EXT4_FEATURE_RO_COMPAT_LARGE_FILE
+    | EXT4_FEATURE_RO_COMPAT_HUGE_FILE
+    | EXT4_FEATURE_RO_COMPAT_GDT_CSUM
+    | EXT4_FEATURE_RO_COMPAT_DIR_NLINK
+    | EXT4_FEATURE_RO_COMPAT_EXTRA_ISIZE
+    | EXT4_FEATURE_RO_COMPAT_QUOTA
+    | EXT4_FEATURE_RO_COMPAT_METADATA_CSUM
+    | EXT4_FEATURE_RO_COMPAT_PROJECT;
+
 //=============================================================================
 // 缓存和性能相关
 //=============================================================================
@@ -349,6 +427,9 @@ pub const CONFIG_BLOCK_DEV_CACHE_SIZE: u32 = 8;
 /// 最大缓存引用块数
 pub const CONFIG_MAX_CACHE_REF_BLOCKS: u32 = 256;
 
+/// C API 可注册的块设备数
+pub const CONFIG_EXT4_BLOCKDEVS_COUNT: usize = 2;
+
 //=============================================================================
 // 错误码（与 POSIX errno 兼容）
 //=============================================================================
@@ -356,30 +437,72 @@ pub const CONFIG_MAX_CACHE_REF_BLOCKS: u32 = 256;
 /// 成功
 pub const EOK: i32 = 0;
 
+/// 操作不允许
+pub const EPERM: i32 = 1;
+
 /// 没有此文件或目录
 pub const ENOENT: i32 = 2;
 
 /// I/O 错误
 pub const EIO: i32 = 5;
 
+/// 资源暂时不可用
+pub const EAGAIN: i32 = 11;
+
 /// 内存不足
 pub const ENOMEM: i32 = 12;
 
+/// 权限不足
+pub const EACCES: i32 = 13;
+
+/// 设备或资源忙
+pub const EBUSY: i32 = 16;
+
+/// 已存在
+pub const EEXIST: i32 = 17;
+
+/// 不是目录
+pub const ENOTDIR: i32 = 20;
+
 /// 是一个目录
 pub const EISDIR: i32 = 21;
 
 /// 无效参数
 pub const EINVAL: i32 = 22;
 
+/// 文件过大
+pub const EFBIG: i32 = 27;
+
 /// 设备上没有空间
 pub const ENOSPC: i32 = 28;
 
+/// 只读文件系统
+pub const EROFS: i32 = 30;
+
+/// 链接数过多
+pub const EMLINK: i32 = 31;
+
+/// 名称过长
+pub const ENAMETOOLONG: i32 = 36;
+
 /// 目录非空
 pub const ENOTEMPTY: i32 = 39;
 
+/// 符号链接层数过多
+pub const ELOOP: i32 = 40;
+
 /// 不支持的操作
 pub const ENOTSUP: i32 = 95;
 
+/// 文件系统损坏（Linux 以 `EFSCORRUPTED` 的名义使用）
+pub const EUCLEAN: i32 = 117;
+
+/// 超出磁盘配额
+pub const EDQUOT: i32 = 122;
+
+/// 缺少所需的密钥
+pub const ENOKEY: i32 = 126;
+
 //=============================================================================
 // 限制
 //=============================================================================
@@ -387,7 +510,9 @@ pub const ENOTSUP: i32 = 95;
 /// 最大路径长度
 pub const EXT4_PATH_MAX: usize = 4096;
 
-/// 最大符号链接深度
+/// 最大链接计数
+///
+/// 目录的链接计数超过它时按 `DIR_NLINK` 的方式记为 1
 pub const EXT4_LINK_MAX: u32 = 65000;
 
 /// 每个 inode 的最大 extent 数
@@ -403,6 +528,9 @@ pub const EXT4_XATTR_MAGIC: u32 = 0xEA020000;
 /// xattr 最大引用计数
 pub const EXT4_XATTR_REFCOUNT_MAX: u32 = 1024;
 
+/// xattr 值的最大长度（与 VFS 的 `XATTR_SIZE_MAX` 相同）
+pub const EXT4_XATTR_SIZE_MAX: usize = 65536;
+
 /// xattr 对齐（4字节对齐）
 pub const EXT4_XATTR_PAD_BITS: u32 = 2;
 pub const EXT4_XATTR_PAD: u32 = 1 << EXT4_XATTR_PAD_BITS;
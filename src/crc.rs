@@ -1,14 +1,87 @@
 //! CRC32C 校验和计算
 //!
 //! 为 ext4 元数据提供 CRC32C 校验和计算功能
-
-use crc32fast::Hasher;
+//!
+//! 与内核的 `ext4_chksum()` / `jbd2_chksum()` 一致，这里计算的是不做
+//! 首尾取反的原始 CRC32C（Castagnoli 多项式）：调用者传入 `~0` 作为初值，
+//! 结果直接写入磁盘，也可以作为下一段数据的初值继续累加。
+//!
+//! 另外提供内核 `crc32_be()` 对应的 [`crc32_be`]，只用于 JBD2 v1 事务校验和；
+//! 以及标准 CRC32（IEEE）[`crc32`]，只用于 GPT 分区表。
 
 /// CRC32 初始值（ext4 使用 0xFFFFFFFF，但内部会取反）
 pub const EXT4_CRC32_INIT: u32 = !0u32;
 
+/// CRC32C 多项式（反射形式）
+const CRC32C_POLY: u32 = 0x82F6_3B78;
+
+/// 按字节查表使用的 CRC32C 表
+static CRC32C_TABLE: [u32; 256] = make_table();
+
+const fn make_table() -> [u32; 256] {
+    let mut table = [0u32; 256];
+    let mut i = 0;
+    while i < 256 {
+        let mut crc = i as u32;
+        let mut bit = 0;
+        while bit < 8 {
+            crc = if crc & 1 != 0 { (crc >> 1) ^ CRC32C_POLY } else { crc >> 1 };
+            bit += 1;
+        }
+        table[i] = crc;
+        i += 1;
+    }
+    table
+}
+
+/// CRC32 多项式（非反射形式），用于 [`crc32_be`]
+const CRC32_BE_POLY: u32 = 0x04C1_1DB7;
+
+/// 按字节查表使用的大端 CRC32 表
+static CRC32_BE_TABLE: [u32; 256] = make_be_table();
+
+const fn make_be_table() -> [u32; 256] {
+    let mut table = [0u32; 256];
+    let mut i = 0;
+    while i < 256 {
+        let mut crc = (i as u32) << 24;
+        let mut bit = 0;
+        while bit < 8 {
+            crc = if crc & 0x8000_0000 != 0 { (crc << 1) ^ CRC32_BE_POLY } else { crc << 1 };
+            bit += 1;
+        }
+        table[i] = crc;
+        i += 1;
+    }
+    table
+}
+
+/// CRC32（IEEE）多项式（反射形式），用于 [`crc32`]
+const CRC32_POLY: u32 = 0xEDB8_8320;
+
+/// 按字节查表使用的 CRC32（IEEE）表
+static CRC32_TABLE: [u32; 256] = make_ieee_table();
+
+const fn make_ieee_table() -> [u32; 256] {
+    let mut table = [0u32; 256];
+    let mut i = 0;
+    while i < 256 {
+        let mut crc = i as u32;
+        let mut bit = 0;
+        while bit < 8 {
+            crc = if crc & 1 != 0 { (crc >> 1) ^ CRC32_POLY } else { crc >> 1 };
+            bit += 1;
+        }
+        table[i] = crc;
+        i += 1;
+    }
+    table
+}
+
 /// 计算 CRC32C 校验和（一次性计算）
 ///
+/// 等价于 `crc32c_append(EXT4_CRC32_INIT, data)`
+///
 /// # 参数
 /// * `data` - 要计算校验和的数据
 ///
@@ -16,7 +89,7 @@ pub const EXT4_CRC32_INIT: u32 = !0u32;
 /// CRC32C 值
 #[inline]
 pub fn crc32c(data: &[u8]) -> u32 {
-    crc32fast::hash(data)
+    crc32c_append(EXT4_CRC32_INIT, data)
 }
 
 /// 计算 CRC32C 校验和（追加模式）
@@ -29,9 +102,29 @@ pub fn crc32c(data: &[u8]) -> u32 {
 /// 更新后的 CRC32C 值
 #[inline]
 pub fn crc32c_append(crc: u32, data: &[u8]) -> u32 {
-    let mut hasher = Hasher::new_with_initial(crc);
-    hasher.update(data);
-    hasher.finalize()
+    data.iter().fold(crc, |crc, &b| {
+        CRC32C_TABLE[((crc ^ b as u32) & 0xff) as usize] ^ (crc >> 8)
+    })
+}
+
+/// 计算大端 CRC32 校验和（追加模式）
+///
+/// 对应内核的 `crc32_be()`，同样不做首尾取反。JBD2 的
+/// `JBD2_FEATURE_COMPAT_CHECKSUM`（v1）用它对整个事务的日志块累加校验和
+#[inline]
+pub fn crc32_be(crc: u32, data: &[u8]) -> u32 {
+    data.iter().fold(crc, |crc, &b| {
+        CRC32_BE_TABLE[((crc >> 24) ^ b as u32) as usize] ^ (crc << 8)
+    })
+}
+
+/// 计算标准 CRC32（IEEE 802.3，即 zlib 的 `crc32()`）
+///
+/// 与上面两个函数不同，这里做首尾取反，结果与 GPT 头中记录的值直接比较
+pub fn crc32(data: &[u8]) -> u32 {
+    !data.iter().fold(!0u32, |crc, &b| {
+        CRC32_TABLE[((crc ^ b as u32) & 0xff) as usize] ^ (crc >> 8)
+    })
 }
 
 #[cfg(test)]
@@ -59,4 +152,21 @@ mod tests {
 
         assert_eq!(crc_once, crc2);
     }
+
+    #[test]
+    fn test_crc32c_check_value() {
+        // 标准 CRC32C 检验值为 0xE3069283，这里不做末尾取反
+        assert_eq!(!crc32c(b"123456789"), 0xE306_9283);
+    }
+
+    #[test]
+    fn test_crc32_be_check_value() {
+        // CRC-32/MPEG-2（初值 ~0，不反射，不取反）的检验值
+        assert_eq!(crc32_be(!0, b"123456789"), 0x0376_E6E7);
+    }
+
+    #[test]
+    fn test_crc32_check_value() {
+        assert_eq!(crc32(b"123456789"), 0xCBF4_3926);
+    }
 }
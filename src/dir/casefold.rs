@@ -0,0 +1,125 @@
+//! 大小写不敏感目录（CASEFOLD）
+//!
+//! 启用 `INCOMPAT_CASEFOLD` 特性的文件系统中，带 `EXT4_CASEFOLD_FL` 标志的目录
+//! 按规范化后的文件名查找：`Readme.TXT` 与 `README.txt` 是同一个条目。
+//! 目录项中保存的仍是创建时的原始名称，只有比较和 HTree 哈希使用规范化形式。
+//!
+//! 规范化为 NFKD 分解后做 Unicode 大小写折叠（case folding）。
+//! 无法解码为 UTF-8 的目录项名称与内核一样按字节比较。
+//!
+//! 规范化需要 `casefold` cargo 特性；未启用时挂载带 CASEFOLD 特性的
+//! 文件系统返回 `Unsupported`，与没有 `CONFIG_UNICODE` 的内核一致。
+
+use crate::{
+    block::BlockDevice,
+    consts::*,
+    error::{Error, ErrorKind, Result},
+    fs::InodeRef,
+    superblock::Superblock,
+};
+use alloc::{borrow::Cow, string::String};
+
+/// 检查挂载时 superblock 中的文件名编码
+///
+/// # 错误
+///
+/// - `ErrorKind::Unsupported` - 未启用 `casefold` 特性，或编码/编码标志未知
+pub(crate) fn check_encoding(sb: &Superblock) -> Result<()> {
+    if !sb.has_incompat_feature(EXT4_FEATURE_INCOMPAT_CASEFOLD) {
+        return Ok(());
+    }
+    if !cfg!(feature = "casefold") {
+        return Err(Error::new(
+            ErrorKind::Unsupported,
+            "Casefold filesystem requires the casefold feature",
+        ));
+    }
+    if sb.encoding() != EXT4_ENC_UTF8_12_1 {
+        return Err(Error::new(ErrorKind::Unsupported, "Unknown filename encoding"));
+    }
+    if sb.encoding_flags() & !EXT4_ENC_STRICT_MODE_FL != 0 {
+        return Err(Error::new(ErrorKind::Unsupported, "Unknown filename encoding flags"));
+    }
+    Ok(())
+}
+
+/// 目录是否大小写不敏感
+///
+/// 需要文件系统启用 CASEFOLD 特性且目录带 `EXT4_CASEFOLD_FL` 标志
+pub fn is_casefolded<D: BlockDevice>(inode_ref: &mut InodeRef<D>) -> Result<bool> {
+    if !cfg!(feature = "casefold")
+        || !inode_ref.sb().has_incompat_feature(EXT4_FEATURE_INCOMPAT_CASEFOLD)
+    {
+        return Ok(false);
+    }
+    inode_ref.with_inode(|inode| u32::from_le(inode.flags) & EXT4_INODE_FLAG_CASEFOLD != 0)
+}
+
+/// 规范化文件名：NFKD 分解后做大小写折叠
+///
+/// `char::to_lowercase` 与 Unicode 大小写折叠只在少数字符上不同，
+/// 这些字符（`ß`、`ẞ`、`ς` 和 U+0345）单独处理；其余差异
+/// （如 `ϐ`、`ſ`）已经被 NFKD 分解消除
+///
+/// # 示例
+///
+/// ```rust,ignore
+/// assert_eq!(casefold("Straße.TXT"), casefold("STRASSE.txt"));
+/// ```
+#[cfg(feature = "casefold")]
+pub fn casefold(name: &str) -> String {
+    use unicode_normalization::UnicodeNormalization;
+
+    let mut folded = String::with_capacity(name.len());
+    for c in name.nfkd() {
+        match c {
+            'ß' | '\u{1e9e}' => folded.push_str("ss"),
+            'ς' => folded.push('σ'),
+            '\u{0345}' => folded.push('ι'),
+            _ => folded.extend(c.to_lowercase()),
+        }
+    }
+    // 小写映射可能产生需要再次分解的字符
+    folded.nfkd().collect()
+}
+
+/// 未启用 `casefold` 特性时不会挂载带 CASEFOLD 特性的文件系统，不会调用到这里
+#[cfg(not(feature = "casefold"))]
+pub(crate) fn casefold(name: &str) -> String {
+    String::from(name)
+}
+
+/// 计算 HTree 哈希时使用的名称
+///
+/// 大小写不敏感目录中使用规范化后的名称；无法解码为 UTF-8 时与内核一样使用原始字节
+pub(crate) fn hash_name(casefolded: bool, name: &[u8]) -> Cow<'_, [u8]> {
+    match core::str::from_utf8(name) {
+        Ok(s) if casefolded => Cow::Owned(casefold(s).into_bytes()),
+        _ => Cow::Borrowed(name),
+    }
+}
+
+#[cfg(all(test, feature = "casefold"))]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_casefold() {
+        assert_eq!(casefold("README.txt"), "readme.txt");
+        assert_eq!(casefold("Straße"), casefold("STRASSE"));
+        assert_eq!(casefold("ΣΊΣΥΦΟΣ"), casefold("σίσυφος"));
+        // 预组合字符与组合序列等价
+        assert_eq!(casefold("Caf\u{e9}"), casefold("CAFE\u{301}"));
+        // 兼容分解：连字和全角字符
+        assert_eq!(casefold("\u{fb01}le"), "file");
+        assert_eq!(casefold("\u{ff21}"), "a");
+    }
+
+    #[test]
+    fn test_hash_name() {
+        assert_eq!(&*hash_name(true, b"ABC"), b"abc");
+        assert_eq!(&*hash_name(false, b"ABC"), b"ABC");
+        // 无效的 UTF-8 按原始字节哈希
+        assert_eq!(&*hash_name(true, b"A\xff"), b"A\xff");
+    }
+}
@@ -3,11 +3,12 @@
 //! 对应 lwext4 的目录校验和相关功能
 
 use crate::{
-    block::BlockDevice,
+    block::{Block, BlockDevice},
     consts::*,
+    error::{Error, ErrorKind, Result},
     fs::InodeRef,
     superblock::Superblock,
-    types::ext4_dir_entry_tail,
+    types::{ext4_dir_entry_tail, ext4_dir_idx_tail},
 };
 
 /// 获取目录块的尾部（校验和结构）
@@ -138,51 +139,40 @@ pub fn get_tail_mut(dirent_block: &mut [u8], block_size: usize) -> Option<&mut e
 /// # 参数
 ///
 /// * `sb` - superblock 引用
-/// * `inode_ref` - inode 引用
+/// * `inode` - 目录的 inode 号
+/// * `generation` - 目录 inode 的 generation
 /// * `dirent` - 目录块数据（不含尾部）
 ///
 /// # 返回
 ///
 /// CRC32 校验和值
 #[cfg(feature = "metadata-csum")]
-pub fn calculate_csum<D: BlockDevice>(sb: &Superblock, inode_ref: &InodeRef<D>, dirent: &[u8]) -> u32 {
+pub fn calculate_csum(sb: &Superblock, inode: u32, generation: u32, dirent: &[u8]) -> u32 {
     if !sb.has_ro_compat_feature(EXT4_FEATURE_RO_COMPAT_METADATA_CSUM) {
         return 0;
     }
 
-    const EXT4_CRC32_INIT: u32 = 0xFFFFFFFF;
-
-    // 1. 先计算 UUID 的校验和
-    let mut csum = crate::crc::crc32c_append(EXT4_CRC32_INIT, sb.uuid());
-
-    // 2. 然后计算 inode 号的校验和
-    let ino_index = inode_ref.index().to_le_bytes();
-    csum = crate::crc::crc32c_append(csum, &ino_index);
-
-    // 3. 然后计算 inode generation 的校验和
-    let ino_gen = inode_ref.generation().to_le_bytes();
-    csum = crate::crc::crc32c_append(csum, &ino_gen);
-
-    // 4. 最后计算目录项数据的校验和（不包含尾部）
-    csum = crate::crc::crc32c_append(csum, dirent);
-
-    csum
+    crate::crc::crc32c_append(sb.inode_csum_seed(inode, generation), dirent)
 }
 
 /// 占位实现：当未启用 metadata-csum 功能时
 #[cfg(not(feature = "metadata-csum"))]
-pub fn calculate_csum<D: BlockDevice>(_sb: &Superblock, _inode_ref: &InodeRef<D>, _dirent: &[u8]) -> u32 {
+pub fn calculate_csum(_sb: &Superblock, _inode: u32, _generation: u32, _dirent: &[u8]) -> u32 {
     0
 }
 
 /// 验证目录块的校验和
 ///
-/// 对应 lwext4 的 `ext4_dir_csum_verify()`
+/// 对应 lwext4 的 `ext4_dir_csum_verify()` 和 `ext4_dir_dx_csum_verify()`
+///
+/// 叶子块校验块尾的 `ext4_dir_entry_tail`；HTree 索引块（dx root 和中间节点）
+/// 校验条目之后的 `ext4_dir_idx_tail`。两者都没有时视为校验失败。
 ///
 /// # 参数
 ///
 /// * `sb` - superblock 引用
-/// * `inode_ref` - inode 引用
+/// * `inode` - 目录的 inode 号
+/// * `generation` - 目录 inode 的 generation
 /// * `dirent_block` - 完整的目录块数据（包含尾部）
 /// * `block_size` - 块大小
 ///
@@ -190,9 +180,10 @@ pub fn calculate_csum<D: BlockDevice>(_sb: &Superblock, _inode_ref: &InodeRef<D>
 ///
 /// 校验和是否正确
 #[cfg(feature = "metadata-csum")]
-pub fn verify_csum<D: BlockDevice>(
+pub fn verify_csum(
     sb: &Superblock,
-    inode_ref: &InodeRef<D>,
+    inode: u32,
+    generation: u32,
     dirent_block: &[u8],
     block_size: usize,
 ) -> bool {
@@ -201,35 +192,146 @@ pub fn verify_csum<D: BlockDevice>(
         return true;
     }
 
-    // 获取尾部结构
-    let tail = match get_tail(dirent_block, block_size) {
-        Some(t) => t,
-        None => {
-            // 没有空间容纳校验和
-            return false;
-        }
-    };
-
-    // 计算校验和（不包含尾部）
-    let tail_offset = block_size - core::mem::size_of::<ext4_dir_entry_tail>();
-    let dirent_data = &dirent_block[..tail_offset];
-    let csum = calculate_csum(sb, inode_ref, dirent_data);
-
-    // 比较校验和
-    tail.checksum() == csum
+    verify_block_csum(sb.inode_csum_seed(inode, generation), dirent_block, block_size)
 }
 
 /// 占位实现：当未启用 metadata-csum 功能时，总是返回 true
 #[cfg(not(feature = "metadata-csum"))]
-pub fn verify_csum<D: BlockDevice>(
+pub fn verify_csum(
     _sb: &Superblock,
-    _inode_ref: &InodeRef<D>,
+    _inode: u32,
+    _generation: u32,
     _dirent_block: &[u8],
     _block_size: usize,
 ) -> bool {
     true
 }
 
+/// 用目录 inode 的校验和种子验证目录块
+///
+/// `seed` 见 [`Superblock::inode_csum_seed`]，叶子块和索引块的处理同 [`verify_csum`]
+pub fn verify_block_csum(seed: u32, dirent_block: &[u8], block_size: usize) -> bool {
+    // 叶子块：校验和保存在块尾
+    if let Some(tail) = get_tail(dirent_block, block_size) {
+        let tail_offset = block_size - core::mem::size_of::<ext4_dir_entry_tail>();
+        return tail.checksum() == crate::crc::crc32c_append(seed, &dirent_block[..tail_offset]);
+    }
+
+    // 索引块：校验和保存在最后一个可用条目之后
+    match dx_csum(seed, dirent_block, block_size) {
+        Some((tail_offset, csum)) => {
+            let stored = &dirent_block[tail_offset + 4..tail_offset + 8];
+            u32::from_le_bytes([stored[0], stored[1], stored[2], stored[3]]) == csum
+        }
+        // 没有空间容纳校验和
+        None => false,
+    }
+}
+
+/// 挂载时打开了 `FsConfig::verify_checksums` 时验证目录块
+///
+/// # 错误
+///
+/// - `ErrorKind::Corrupted` - 校验和不匹配，错误中带有块号
+pub(crate) fn check_block<D: BlockDevice>(inode_ref: &mut InodeRef<D>, block_addr: u64) -> Result<()> {
+    // 批量操作中推迟了校验和的目录，块上的校验和尚未更新
+    if inode_ref.sb().dir_csum_deferred(inode_ref.index()) {
+        return Ok(());
+    }
+    let Some(seed) = inode_ref.verify_csum_seed()? else {
+        return Ok(());
+    };
+
+    let block_size = inode_ref.sb().block_size() as usize;
+    let mut block = Block::get(inode_ref.bdev(), block_addr)?;
+    if !block.with_data(|data| verify_block_csum(seed, data, block_size))? {
+        return Err(Error::with_block(
+            ErrorKind::Corrupted,
+            "Directory block checksum mismatch",
+            block_addr,
+        ));
+    }
+    Ok(())
+}
+
+/// 获取 HTree 索引块中 count/limit 的偏移
+///
+/// 对应内核的 `get_dx_countlimit()`
+///
+/// 中间节点以覆盖整块的空目录项开头，count/limit 在偏移 8；
+/// dx root 以 `.`/`..` 和根信息开头，count/limit 在偏移 32。
+/// 不是索引块时返回 None。
+fn dx_countlimit_offset(block: &[u8], block_size: usize) -> Option<usize> {
+    if block_size < 32 || block.len() < block_size {
+        return None;
+    }
+
+    let rec_len = u16::from_le_bytes([block[4], block[5]]) as usize;
+    if rec_len == block_size {
+        return Some(8);
+    }
+    if rec_len != 12 {
+        return None;
+    }
+
+    // ".." 覆盖块的剩余部分，之后是根信息（reserved_zero 和 info_length）
+    let dotdot_len = u16::from_le_bytes([block[16], block[17]]) as usize;
+    let reserved_zero = u32::from_le_bytes([block[24], block[25], block[26], block[27]]);
+    let info_length = block[29];
+    if dotdot_len != block_size - 12 || reserved_zero != 0 || info_length != 8 {
+        return None;
+    }
+    Some(32)
+}
+
+/// 计算 HTree 索引块的校验和，返回 (ext4_dir_idx_tail 的偏移, 校验和)
+///
+/// 对应内核的 `ext4_dx_csum()`：覆盖已使用的条目和 `dt_reserved`，
+/// 校验和字段本身按 0 计算。limit 之后放不下尾部时返回 None。
+fn dx_csum(seed: u32, block: &[u8], block_size: usize) -> Option<(usize, u32)> {
+    let count_offset = dx_countlimit_offset(block, block_size)?;
+    let limit = u16::from_le_bytes([block[count_offset], block[count_offset + 1]]) as usize;
+    let count = u16::from_le_bytes([block[count_offset + 2], block[count_offset + 3]]) as usize;
+
+    let entry_size = core::mem::size_of::<crate::types::ext4_dir_idx_entry>();
+    let tail_offset = count_offset + limit * entry_size;
+    if count > limit || tail_offset + core::mem::size_of::<ext4_dir_idx_tail>() > block_size {
+        return None;
+    }
+
+    let mut csum = crate::crc::crc32c_append(seed, &block[..count_offset + count * entry_size]);
+    csum = crate::crc::crc32c_append(csum, &block[tail_offset..tail_offset + 4]);
+    csum = crate::crc::crc32c_append(csum, &[0; 4]);
+    Some((tail_offset, csum))
+}
+
+/// 设置目录叶子块的校验和
+///
+/// `seed` 为目录 inode 的校验和种子（见 [`Superblock::inode_csum_seed`]），
+/// 块尾没有有效的 `ext4_dir_entry_tail` 时不做任何修改
+pub fn set_leaf_csum(seed: u32, dirent_block: &mut [u8], block_size: usize) {
+    if get_tail(dirent_block, block_size).is_none() {
+        return;
+    }
+
+    let tail_offset = block_size - core::mem::size_of::<ext4_dir_entry_tail>();
+    let csum = crate::crc::crc32c_append(seed, &dirent_block[..tail_offset]);
+    if let Some(tail) = get_tail_mut(dirent_block, block_size) {
+        tail.set_checksum(csum);
+    }
+}
+
+/// 设置 HTree 索引块的校验和
+///
+/// 对应内核的 `ext4_dx_csum_set()`
+///
+/// `seed` 为目录 inode 的校验和种子，不是索引块或放不下尾部时不做任何修改
+pub fn set_dx_csum(seed: u32, block: &mut [u8], block_size: usize) {
+    if let Some((tail_offset, csum)) = dx_csum(seed, block, block_size) {
+        block[tail_offset + 4..tail_offset + 8].copy_from_slice(&csum.to_le_bytes());
+    }
+}
+
 /// 初始化目录项尾部
 ///
 /// 对应 lwext4 的 `ext4_dir_init_entry_tail()`
@@ -249,15 +351,19 @@ pub fn init_entry_tail(tail: &mut ext4_dir_entry_tail) {
 ///
 /// 对应 lwext4 的 `ext4_dir_set_csum()`
 ///
+/// 叶子块和 HTree 索引块都适用
+///
 /// # 参数
 ///
 /// * `sb` - superblock 引用
-/// * `inode_ref` - inode 引用
+/// * `inode` - 目录的 inode 号
+/// * `generation` - 目录 inode 的 generation
 /// * `dirent_block` - 完整的目录块数据（可变，包含尾部）
 /// * `block_size` - 块大小
-pub fn set_csum<D: BlockDevice>(
+pub fn set_csum(
     sb: &Superblock,
-    inode_ref: &InodeRef<D>,
+    inode: u32,
+    generation: u32,
     dirent_block: &mut [u8],
     block_size: usize,
 ) {
@@ -266,16 +372,11 @@ pub fn set_csum<D: BlockDevice>(
         return;
     }
 
-    // 获取可变尾部结构
-    let tail_offset = block_size - core::mem::size_of::<ext4_dir_entry_tail>();
-
-    // 先计算校验和（不包含尾部）
-    let dirent_data = &dirent_block[..tail_offset];
-    let csum = calculate_csum(sb, inode_ref, dirent_data);
-
-    // 然后获取可变尾部并设置校验和
-    if let Some(tail) = get_tail_mut(dirent_block, block_size) {
-        tail.set_checksum(csum);
+    let seed = sb.inode_csum_seed(inode, generation);
+    if get_tail(dirent_block, block_size).is_some() {
+        set_leaf_csum(seed, dirent_block, block_size);
+    } else {
+        set_dx_csum(seed, dirent_block, block_size);
     }
 }
 
@@ -334,4 +435,60 @@ mod tests {
             assert_eq!(core::ptr::addr_of!((*ptr).reserved_ft).read_unaligned(), EXT4_DIRENTRY_DIR_CSUM);
         }
     }
+
+    #[test]
+    fn test_leaf_csum() {
+        let block_size = 1024;
+        let mut block = vec![0u8; block_size];
+        block[4..6].copy_from_slice(&((block_size - 12) as u16).to_le_bytes());
+        let tail_offset = block_size - core::mem::size_of::<ext4_dir_entry_tail>();
+        init_entry_tail(unsafe { &mut *(block[tail_offset..].as_mut_ptr() as *mut ext4_dir_entry_tail) });
+
+        set_leaf_csum(0x1234, &mut block, block_size);
+        assert!(verify_block_csum(0x1234, &block, block_size));
+        assert!(!verify_block_csum(0x1235, &block, block_size));
+
+        block[8] ^= 1;
+        assert!(!verify_block_csum(0x1234, &block, block_size));
+    }
+
+    #[test]
+    fn test_dx_csum() {
+        let block_size = 1024;
+        let entry_size = core::mem::size_of::<crate::types::ext4_dir_idx_entry>();
+        let tail_size = core::mem::size_of::<ext4_dir_idx_tail>();
+
+        // 中间节点：覆盖整块的空目录项，count/limit 在偏移 8
+        let mut node = vec![0u8; block_size];
+        node[4..6].copy_from_slice(&(block_size as u16).to_le_bytes());
+        let limit = ((block_size - 8 - tail_size) / entry_size) as u16;
+        node[8..10].copy_from_slice(&limit.to_le_bytes());
+        node[10..12].copy_from_slice(&2u16.to_le_bytes());
+        assert_eq!(dx_countlimit_offset(&node, block_size), Some(8));
+
+        set_dx_csum(7, &mut node, block_size);
+        assert!(verify_block_csum(7, &node, block_size));
+        // 超出 count 的条目不参与校验和
+        node[8 + 3 * entry_size] = 0xff;
+        assert!(verify_block_csum(7, &node, block_size));
+        node[8 + entry_size] ^= 1;
+        assert!(!verify_block_csum(7, &node, block_size));
+
+        // dx root：`.`、`..` 和根信息之后，count/limit 在偏移 32
+        let mut root = vec![0u8; block_size];
+        root[4..6].copy_from_slice(&12u16.to_le_bytes());
+        root[16..18].copy_from_slice(&((block_size - 12) as u16).to_le_bytes());
+        root[29] = 8;
+        let limit = ((block_size - 32 - tail_size) / entry_size) as u16;
+        root[32..34].copy_from_slice(&limit.to_le_bytes());
+        root[34..36].copy_from_slice(&1u16.to_le_bytes());
+        assert_eq!(dx_countlimit_offset(&root, block_size), Some(32));
+
+        set_dx_csum(7, &mut root, block_size);
+        assert!(verify_block_csum(7, &root, block_size));
+
+        // limit 之后放不下尾部
+        root[32..34].copy_from_slice(&(limit + 1).to_le_bytes());
+        assert!(!verify_block_csum(7, &root, block_size));
+    }
 }
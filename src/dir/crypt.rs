@@ -0,0 +1,83 @@
+//! 加密目录中的文件名
+//!
+//! 带 `EXT4_ENCRYPT_FL` 标志的目录中，目录项名称是 fscrypt 加密后的密文，
+//! 可能包含 `/`、`\0` 和无效的 UTF-8。本库不解密，列目录时把密文编码为
+//! base64url（不带填充）字符串，查找时再解码回密文比较，因此
+//! [`read_dir`](super::read_dir) 返回的名称可以原样用于查找和删除。
+//!
+//! 编码后的名称只在本库内有意义，与内核的 no-key 名称格式不同。
+//! `.` 和 `..` 不加密，保持原样。
+
+use alloc::{string::String, vec::Vec};
+
+/// base64url 字母表（RFC 4648 §5）
+const BASE64URL: &[u8; 64] = b"ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz0123456789-_";
+
+/// 把密文名称编码为可打印的字符串
+pub fn encode_name(ciphertext: &[u8]) -> String {
+    let mut out = String::with_capacity(ciphertext.len().div_ceil(3) * 4);
+    for chunk in ciphertext.chunks(3) {
+        let mut v = 0u32;
+        for (i, &b) in chunk.iter().enumerate() {
+            v |= (b as u32) << (16 - 8 * i);
+        }
+        // n 字节输入产生 n + 1 个字符
+        for i in 0..=chunk.len() {
+            out.push(BASE64URL[(v >> (18 - 6 * i)) as usize & 0x3f] as char);
+        }
+    }
+    out
+}
+
+/// 把 [`encode_name`] 的结果解码回密文
+///
+/// 不是合法的编码时返回 `None`
+pub fn decode_name(name: &str) -> Option<Vec<u8>> {
+    let bytes = name.as_bytes();
+    if bytes.len() % 4 == 1 {
+        return None;
+    }
+
+    let mut out = Vec::with_capacity(bytes.len() * 3 / 4);
+    for chunk in bytes.chunks(4) {
+        let mut v = 0u32;
+        for (i, &c) in chunk.iter().enumerate() {
+            let digit = BASE64URL.iter().position(|&x| x == c)? as u32;
+            v |= digit << (18 - 6 * i);
+        }
+        for i in 0..chunk.len() - 1 {
+            out.push((v >> (16 - 8 * i)) as u8);
+        }
+    }
+
+    // 拒绝末尾多余的位，保证编码唯一
+    if encode_name(&out) != name {
+        return None;
+    }
+    Some(out)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_roundtrip() {
+        for len in 0..20 {
+            let data: Vec<u8> = (0..len).map(|i| (i * 37 + 200) as u8).collect();
+            let encoded = encode_name(&data);
+            assert!(!encoded.contains('/'));
+            assert_eq!(decode_name(&encoded), Some(data));
+        }
+        assert_eq!(encode_name(b"\xfb\xff"), "-_8");
+    }
+
+    #[test]
+    fn test_decode_invalid() {
+        assert_eq!(decode_name("A"), None);
+        assert_eq!(decode_name("a.b"), None);
+        // 末尾的位不为 0
+        assert_eq!(decode_name("AB"), None);
+        assert_eq!(decode_name("AA"), Some(alloc::vec![0]));
+    }
+}
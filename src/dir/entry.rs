@@ -7,8 +7,8 @@ use crate::{
     error::{Error, ErrorKind, Result},
     extent::ExtentTree,
     inode::Inode,
+    parse,
     superblock::Superblock,
-    types::ext4_dir_entry,
 };
 use alloc::{string::String, vec::Vec};
 
@@ -71,7 +71,7 @@ impl<'a, D: BlockDevice> DirIterator<'a, D> {
     ) -> Result<Self> {
         if !inode.is_dir() {
             return Err(Error::new(
-                ErrorKind::InvalidInput,
+                ErrorKind::NotADirectory,
                 "Inode is not a directory",
             ));
         }
@@ -137,49 +137,24 @@ impl<'a, D: BlockDevice> DirIterator<'a, D> {
                 continue;
             }
 
-            // 读取目录项
-            let entry_ptr = unsafe {
-                self.block_data[self.offset_in_block..].as_ptr() as *const ext4_dir_entry
-            };
-            let entry = unsafe { core::ptr::read_unaligned(entry_ptr) };
-
-            let inode = u32::from_le(entry.inode);
-            let rec_len = u16::from_le(entry.rec_len) as usize;
-            let name_len = entry.name_len as usize;
-
             // rec_len 为 0 表示目录结束
-            if rec_len == 0 {
+            if parse::read_u16(&self.block_data, self.offset_in_block + 4)? == 0 {
                 return Ok(None);
             }
 
-            // 移动到下一个目录项
-            self.offset_in_block += rec_len;
+            // 读取目录项
+            let entry = parse::dir_entry_at(&self.block_data, self.offset_in_block)?;
+            let inode = entry.inode;
 
-            // inode 为 0 表示已删除的目录项，跳过
-            if inode == 0 {
-                continue;
-            }
+            // 移动到下一个目录项
+            self.offset_in_block += entry.rec_len as usize;
 
-            // 检查名称长度是否有效
-            if name_len == 0 || name_len > EXT4_NAME_MAX {
+            // inode 为 0 表示已删除的目录项，名称为空的项也跳过
+            if inode == 0 || entry.name.is_empty() {
                 continue;
             }
 
-            // 读取文件名
-            // name 字段紧跟在 ext4_dir_entry 的固定部分之后（8 字节）
-            let name_offset = 8; // sizeof(inode) + sizeof(rec_len) + sizeof(name_len) + sizeof(file_type)
-            let name_start = self.offset_in_block - rec_len + name_offset;
-            let name_end = name_start + name_len;
-
-            if name_end > block_size {
-                return Err(Error::new(
-                    ErrorKind::Corrupted,
-                    "Directory entry name extends beyond block",
-                ));
-            }
-
-            let name_bytes = &self.block_data[name_start..name_end];
-            let name = String::from_utf8_lossy(name_bytes).into_owned();
+            let name = String::from_utf8_lossy(entry.name).into_owned();
 
             return Ok(Some(DirEntry {
                 inode,
@@ -264,9 +264,11 @@ pub fn htree_hash(
     // Initialize hash state
     let mut hash = [0x67452301_u32, 0xEFCDAB89, 0x98BADCFE, 0x10325476];
 
-    // Apply seed if provided
+    // Apply seed if provided（全零的种子视为未设置，与内核一致）
     if let Some(seed) = hash_seed {
-        hash.copy_from_slice(seed);
+        if seed.iter().any(|&w| w != 0) {
+            hash.copy_from_slice(seed);
+        }
     }
 
     let unsigned_char = matches!(
@@ -301,7 +303,8 @@ pub fn htree_hash(
                 half_md4(&mut hash, &data);
                 pos += 32;
             }
-            (hash[0], hash[1])
+            // 内核取 buf[1] 作为主哈希、buf[2] 作为次哈希
+            (hash[1], hash[2])
         }
 
         _ => {
@@ -312,6 +315,12 @@ pub fn htree_hash(
         }
     };
 
+    // 最低位保留给 64 位 readdir cookie，且不能与 EOF 标记冲突
+    let mut major = major & !1;
+    if major == EXT2_HTREE_EOF << 1 {
+        major = (EXT2_HTREE_EOF - 1) << 1;
+    }
+
     Ok((major, minor))
 }
 
@@ -327,6 +336,33 @@ mod tests {
         assert!(result.is_ok());
     }
 
+    #[test]
+    fn test_hash_matches_kernel() {
+        // 参考值来自 debugfs 的 dx_hash 命令
+        let seed = [0x83733f89, 0xf9466410, 0x2e8c27b4, 0x27f24aa0];
+        let name = b"new_entry_0";
+
+        assert_eq!(
+            htree_hash(name, Some(&seed), EXT2_HTREE_HALF_MD4).unwrap(),
+            (0x73b9d242, 0x99d4ea95)
+        );
+        assert_eq!(
+            htree_hash(name, Some(&seed), EXT2_HTREE_TEA).unwrap(),
+            (0x394ae430, 0xb8daa03d)
+        );
+        assert_eq!(htree_hash(name, Some(&seed), EXT2_HTREE_LEGACY).unwrap(), (0xa73a4608, 0));
+
+        // 无种子时使用默认初始值
+        assert_eq!(
+            htree_hash(b"example", None, EXT2_HTREE_HALF_MD4).unwrap(),
+            (0x8ea82f6c, 0xd14031f2)
+        );
+        assert_eq!(
+            htree_hash(b"example", Some(&[0; 4]), EXT2_HTREE_TEA).unwrap(),
+            (0xb299e020, 0xfbb2833b)
+        );
+    }
+
     #[test]
     fn test_hash_invalid_length() {
         // Empty name
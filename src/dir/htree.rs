@@ -10,16 +10,15 @@
 //! - HTree structure parsing
 //! - Binary search in index nodes
 //! - Leaf node lookup (with and without path tracking)
-//! - Leaf block splitting (`split_leaf_block`)
-//! - Index block splitting (`split_index_block`)
+//! - Leaf block splitting (`split_leaf_block`, feature `htree-write`)
+//! - Index block splitting and root growth (`split_index_block`, feature `htree-write`)
+//! - HTree initialization (`dx_init`) - in `write::dir_init`
+//! - Directory entry search (`find_entry`, follows hash collision continuation)
 //!
 //! ⚠️ **Partially Implemented**:
-//! - Directory entry search (read-only, depends on iterator)
 //! - Entry addition (integrated in write module, with splitting support)
 //!
 //! ❌ **Not Implemented**:
-//! - HTree initialization (`dx_init`) - in separate init module
-//! - Recursive index splitting (needed for very deep trees)
 //! - Parent inode reset
 //!
 //! # Dependency Status
@@ -39,11 +38,14 @@ use crate::{
     consts::*,
     error::{Error, ErrorKind, Result},
     fs::InodeRef,
+    parse,
     superblock::Superblock,
     types::{ext4_dir_idx_climit, ext4_dir_idx_entry, ext4_dir_idx_root},
 };
 use alloc::vec::Vec;
 
+use super::casefold::{hash_name, is_casefolded};
+use super::iterator::{find_in_block, NameMatcher};
 use super::hash::{htree_hash, EXT2_HTREE_HALF_MD4, EXT2_HTREE_LEGACY, EXT2_HTREE_TEA};
 
 /// HTree index block structure
@@ -102,6 +104,25 @@ pub struct IndexBlockInfo {
     pub entry_limit: u16,
 }
 
+/// 非根索引块的条目区偏移（fake entry 之后，entries[0].hash 被 climit 覆盖）
+const NODE_ENTRIES_OFFSET: usize = core::mem::size_of::<crate::types::ext4_fake_dir_entry>();
+
+/// 根索引块的条目区偏移（两个 dot entry 和根信息之后）
+const ROOT_ENTRIES_OFFSET: usize = 2 * core::mem::size_of::<crate::types::ext4_dir_idx_dot_en>()
+    + core::mem::size_of::<crate::types::ext4_dir_idx_rinfo>();
+
+/// 根块允许的最大 `indirect_levels`
+///
+/// 与内核一致：没有 `LARGEDIR` 特性时索引最多两层（根 + 一层中间节点），
+/// 有 `LARGEDIR` 时最多三层
+fn max_indirect_levels(sb: &Superblock) -> u8 {
+    if sb.has_incompat_feature(EXT4_FEATURE_INCOMPAT_LARGEDIR) {
+        2
+    } else {
+        1
+    }
+}
+
 /// Initialize hash info from root block
 ///
 /// 对应 lwext4 的 `ext4_dir_hinfo_init()`
@@ -124,6 +145,19 @@ pub fn init_hash_info<D: BlockDevice>(
     let has_unsigned_hash = inode_ref.sb().has_flag(EXT4_SUPERBLOCK_FLAGS_UNSIGNED_HASH);
     let has_metadata_csum = inode_ref.sb().has_ro_compat_feature(EXT4_FEATURE_RO_COMPAT_METADATA_CSUM);
     let seed = inode_ref.sb().hash_seed();
+    let max_levels = max_indirect_levels(inode_ref.sb());
+
+    // 加密且大小写不敏感的目录中，哈希由明文计算后保存在目录项里，无法从密文得出
+    if inode_ref.is_encrypted()? && is_casefolded(inode_ref)? {
+        return Err(Error::new(
+            ErrorKind::Unsupported,
+            "Hash of encrypted casefolded names is not computable",
+        ));
+    }
+    let matcher = NameMatcher::new(inode_ref, name)?;
+    let hash_input = matcher
+        .hash_input()
+        .ok_or(Error::new(ErrorKind::NotFound, "Not a valid encrypted name"))?;
 
     // Calculate entry space (needed for validation)
     let mut entry_space = block_size;
@@ -163,11 +197,11 @@ pub fn init_hash_info<D: BlockDevice>(
             ));
         }
 
-        // Check indirect levels (should be 0 or 1)
-        if root.info.indirect_levels() > 1 {
+        // Check indirect levels（LARGEDIR 时允许 2）
+        if root.info.indirect_levels() > max_levels {
             return Err(Error::new(
                 ErrorKind::Corrupted,
-                "HTree indirect levels > 1 not supported",
+                "HTree indirect levels exceed maximum",
             ));
         }
 
@@ -192,7 +226,8 @@ pub fn init_hash_info<D: BlockDevice>(
         }
 
         // Compute hash
-        let (hash, minor_hash) = htree_hash(name.as_bytes(), Some(&seed), hash_version)?;
+        // 大小写不敏感目录按规范化后的名称、加密目录按密文计算哈希
+        let (hash, minor_hash) = htree_hash(&hash_input, Some(&seed), hash_version)?;
 
         Ok(HTreeHashInfo {
             hash,
@@ -204,7 +239,7 @@ pub fn init_hash_info<D: BlockDevice>(
 }
 
 /// Calculate available entry space in index node
-fn calculate_entry_space(block_size: u32, sb: &Superblock) -> u32 {
+pub(crate) fn calculate_entry_space(block_size: u32, sb: &Superblock) -> u32 {
     let mut entry_space = block_size;
     entry_space -= 2 * core::mem::size_of::<crate::types::ext4_dir_idx_dot_en>() as u32;
     entry_space -= core::mem::size_of::<crate::types::ext4_dir_idx_rinfo>() as u32;
@@ -254,6 +289,7 @@ pub fn get_leaf_block<D: BlockDevice>(
     // Walk through the index tree
     loop {
         let physical_block = inode_ref.get_inode_dblk_idx(current_block_idx, false)?;
+        super::checksum::check_block(inode_ref, physical_block)?;
         let bdev = inode_ref.bdev();
         let mut block = Block::get(bdev, physical_block)?;
 
@@ -268,12 +304,8 @@ pub fn get_leaf_block<D: BlockDevice>(
                 let count = climit.count();
                 let limit = climit.limit();
 
-                // Entries start after climit
-                let entries_ptr = unsafe {
-                    ((&root.en as *const _ as *const u8)
-                        .add(core::mem::size_of::<ext4_dir_idx_climit>()))
-                        as *const ext4_dir_idx_entry
-                };
+                // climit 占据 entries[0] 的 hash 字段，entries[0].block 仍有效
+                let entries_ptr = &root.en as *const _ as *const ext4_dir_idx_entry;
                 let entries = unsafe {
                     core::slice::from_raw_parts(entries_ptr, count as usize)
                 };
@@ -293,7 +325,6 @@ pub fn get_leaf_block<D: BlockDevice>(
                 let entries_ptr = unsafe {
                     (data.as_ptr() as *const u8)
                         .add(core::mem::size_of::<crate::types::ext4_fake_dir_entry>())
-                        .add(core::mem::size_of::<ext4_dir_idx_climit>())
                         as *const ext4_dir_idx_entry
                 };
                 let entries = unsafe {
@@ -388,6 +419,7 @@ pub fn get_leaf_with_path<D: BlockDevice>(
     // Walk through the index tree, recording the path
     loop {
         let physical_block = inode_ref.get_inode_dblk_idx(current_block_idx, false)?;
+        super::checksum::check_block(inode_ref, physical_block)?;
         let bdev = inode_ref.bdev();
         let mut block = Block::get(bdev, physical_block)?;
 
@@ -402,11 +434,7 @@ pub fn get_leaf_with_path<D: BlockDevice>(
                 let count = climit.count();
                 let limit = climit.limit();
 
-                let entries_ptr = unsafe {
-                    ((&root.en as *const _ as *const u8)
-                        .add(core::mem::size_of::<ext4_dir_idx_climit>()))
-                        as *const ext4_dir_idx_entry
-                };
+                let entries_ptr = &root.en as *const _ as *const ext4_dir_idx_entry;
                 let entries = unsafe {
                     core::slice::from_raw_parts(entries_ptr, count as usize)
                 };
@@ -426,7 +454,6 @@ pub fn get_leaf_with_path<D: BlockDevice>(
                 let entries_ptr = unsafe {
                     (data.as_ptr() as *const u8)
                         .add(core::mem::size_of::<crate::types::ext4_fake_dir_entry>())
-                        .add(core::mem::size_of::<ext4_dir_idx_climit>())
                         as *const ext4_dir_idx_entry
                 };
                 let entries = unsafe {
@@ -445,7 +472,8 @@ pub fn get_leaf_with_path<D: BlockDevice>(
             }
 
             // Binary search for the right entry
-            let mut left = 0_usize;
+            // entries[0] 的 hash 被 climit 占用，视为最小哈希，从 1 开始查找
+            let mut left = 1_usize;
             let mut right = count as usize - 1;
             let mut result_idx = 0_usize;
 
@@ -467,16 +495,15 @@ pub fn get_leaf_with_path<D: BlockDevice>(
             Ok((entries[result_idx].block(), result_idx, count, limit))
         })??;
 
-        // Record this index block in the path (but only if not a leaf)
-        if current_level > 0 {
-            index_blocks.push(IndexBlockInfo {
-                logical_block: current_block_idx,
-                block_addr: physical_block,
-                position_idx,
-                entry_count: count,
-                entry_limit: limit,
-            });
-        }
+        // Record this index block in the path（包括 indirect_levels 为 0 时的根块，
+        // 它就是叶子块的父索引块）
+        index_blocks.push(IndexBlockInfo {
+            logical_block: current_block_idx,
+            block_addr: physical_block,
+            position_idx,
+            entry_count: count,
+            entry_limit: limit,
+        });
 
         drop(block);
 
@@ -498,6 +525,10 @@ pub fn get_leaf_with_path<D: BlockDevice>(
 ///
 /// 对应 lwext4 的 `ext4_dir_dx_find_entry()`
 ///
+/// 通过索引定位候选叶子块，只在该块内线性查找；
+/// 哈希冲突的条目被分裂到后续叶子块时（索引条目带 continuation 位），
+/// 继续查找下一个叶子块
+///
 /// # Parameters
 ///
 /// * `inode_ref` - Directory inode reference
@@ -506,30 +537,114 @@ pub fn get_leaf_with_path<D: BlockDevice>(
 /// # Returns
 ///
 /// `Some(inode_num)` if found, `None` if not found
-///
-/// # Note
-///
-/// This function only does HTree lookup to find the candidate leaf block.
-/// It then uses linear search within that block to find the actual entry.
-/// This is intentionally separated to maintain modularity with the iterator.
 pub fn find_entry<D: BlockDevice>(
     inode_ref: &mut InodeRef<D>,
     name: &str,
 ) -> Result<Option<u32>> {
-    // Initialize hash info
     let hash_info = init_hash_info(inode_ref, name)?;
+    let mut path = get_leaf_with_path(inode_ref, &hash_info)?;
+
+    loop {
+        if let Some(inode) = find_in_block(inode_ref, path.leaf_block, name)? {
+            return Ok(Some(inode));
+        }
+        if !next_leaf_block(inode_ref, &mut path, hash_info.hash)? {
+            return Ok(None);
+        }
+    }
+}
+
+/// 读取索引块中第 `idx` 个索引条目，返回 (hash, block)
+fn read_index_entry<D: BlockDevice>(
+    inode_ref: &mut InodeRef<D>,
+    info: &IndexBlockInfo,
+    idx: usize,
+) -> Result<(u32, u32)> {
+    let base = if info.logical_block == 0 { ROOT_ENTRIES_OFFSET } else { NODE_ENTRIES_OFFSET };
+    let offset = base + idx * core::mem::size_of::<ext4_dir_idx_entry>();
+    let bdev = inode_ref.bdev();
+    let mut block = Block::get(bdev, info.block_addr)?;
+    block.with_data(|data| {
+        if offset + core::mem::size_of::<ext4_dir_idx_entry>() > data.len() {
+            return Err(Error::new(ErrorKind::Corrupted, "HTree index entry beyond block"));
+        }
+        let entry = unsafe {
+            core::ptr::read_unaligned(data.as_ptr().add(offset) as *const ext4_dir_idx_entry)
+        };
+        Ok((entry.hash(), entry.block()))
+    })?
+}
+
+/// 下一个索引条目是否是 `hash` 的延续（冲突条目被分裂到了下一个叶子块）
+fn is_hash_continuation(next_hash: u32, hash: u32) -> bool {
+    next_hash & 1 != 0 && (next_hash & !1) == (hash & !1)
+}
+
+/// 读取索引块的 (count, limit)
+fn read_index_climit<D: BlockDevice>(
+    inode_ref: &mut InodeRef<D>,
+    block_addr: u64,
+    is_root: bool,
+) -> Result<(u16, u16)> {
+    let offset = if is_root { ROOT_ENTRIES_OFFSET } else { NODE_ENTRIES_OFFSET };
+    let bdev = inode_ref.bdev();
+    let mut block = Block::get(bdev, block_addr)?;
+    block.with_data(|data| {
+        let climit = unsafe {
+            core::ptr::read_unaligned(data.as_ptr().add(offset) as *const ext4_dir_idx_climit)
+        };
+        (climit.count(), climit.limit())
+    })
+}
 
-    // Find leaf block
-    let leaf_block = get_leaf_block(inode_ref, &hash_info)?;
+/// 将路径移动到下一个叶子块
+///
+/// 对应 lwext4 的 `ext4_dir_dx_next_block()`
+///
+/// 只有下一个索引条目的哈希（去掉最低位）等于 `hash` 且带有
+/// continuation 位时才移动，此时返回 `true`，否则返回 `false`
+pub(crate) fn next_leaf_block<D: BlockDevice>(
+    inode_ref: &mut InodeRef<D>,
+    path: &mut HTreePath,
+    hash: u32,
+) -> Result<bool> {
+    // 从最底层向上找第一个还有后续条目的索引块
+    let Some(level) = path
+        .index_blocks
+        .iter()
+        .rposition(|info| info.position_idx + 1 < info.entry_count as usize)
+    else {
+        return Ok(false);
+    };
 
-    // Linear search in the leaf block
-    // TODO: This should use DirIterator but positioned at specific block
-    // For now, we return Unsupported as this requires iterator enhancement
+    let position = path.index_blocks[level].position_idx + 1;
+    let (next_hash, mut child) = read_index_entry(inode_ref, &path.index_blocks[level], position)?;
+    if !is_hash_continuation(next_hash, hash) {
+        return Ok(false);
+    }
+    path.index_blocks[level].position_idx = position;
+
+    // 沿每一层的第一个条目向下走到叶子
+    for depth in level + 1..path.index_blocks.len() {
+        let block_addr = inode_ref.get_inode_dblk_idx(child, false)?;
+        super::checksum::check_block(inode_ref, block_addr)?;
+        let (count, limit) = read_index_climit(inode_ref, block_addr, false)?;
+        if count == 0 || count > limit {
+            return Err(Error::new(ErrorKind::Corrupted, "HTree invalid entry count"));
+        }
+        let info = IndexBlockInfo {
+            logical_block: child,
+            block_addr,
+            position_idx: 0,
+            entry_count: count,
+            entry_limit: limit,
+        };
+        child = read_index_entry(inode_ref, &info, 0)?.1;
+        path.index_blocks[depth] = info;
+    }
 
-    Err(Error::new(
-        ErrorKind::Unsupported,
-        "HTree find_entry requires positioned iterator (not yet implemented)",
-    ))
+    path.leaf_block = child;
+    Ok(true)
 }
 
 /// Check if directory uses HTree indexing
@@ -571,13 +686,15 @@ pub fn is_indexed<D: BlockDevice>(inode_ref: &mut InodeRef<D>) -> Result<bool> {
 // HTree Splitting Operations
 // =============================================================================
 
-use crate::types::{ext4_dir_en, ext4_dir_entry_tail, ext4_dir_idx_node, ext4_fake_dir_entry};
-use crate::balloc::BlockAllocator;
+#[cfg(feature = "htree-write")]
+use crate::types::{ext4_dir_entry_tail, ext4_fake_dir_entry};
+#[cfg(feature = "htree-write")]
 use super::checksum::{init_entry_tail, get_tail_mut};
 
 /// Directory entry with hash for sorting
 ///
 /// 对应 lwext4 的 `struct ext4_dx_sort_entry`
+#[cfg(feature = "htree-write")]
 #[derive(Clone)]
 struct DirEntrySortEntry {
     /// Hash value of the entry name
@@ -592,6 +709,7 @@ struct DirEntrySortEntry {
     name: [u8; 255],
 }
 
+#[cfg(feature = "htree-write")]
 impl DirEntrySortEntry {
     /// Calculate the aligned record length for this entry
     fn record_len(&self) -> u16 {
@@ -623,23 +741,23 @@ impl DirEntrySortEntry {
 /// # 参数
 ///
 /// * `inode_ref` - 目录 inode 引用
-/// * `sb` - 可变 superblock 引用（用于块分配）
 /// * `old_block_addr` - 旧块的物理地址
 /// * `hash_info` - 哈希信息
 ///
 /// # 返回
 ///
 /// (new_logical_block, split_hash)
+#[cfg(feature = "htree-write")]
 pub fn split_leaf_block<D: BlockDevice>(
     inode_ref: &mut InodeRef<D>,
-    sb: &mut Superblock,
     old_block_addr: u64,
     hash_info: &HTreeHashInfo,
 ) -> Result<(u32, u32)> {
     use super::hash::htree_hash;
 
-    let block_size = sb.block_size() as usize;
-    let has_csum = sb.has_ro_compat_feature(EXT4_FEATURE_RO_COMPAT_METADATA_CSUM);
+    let block_size = inode_ref.sb().block_size() as usize;
+    let has_csum = inode_ref.sb().has_ro_compat_feature(EXT4_FEATURE_RO_COMPAT_METADATA_CSUM);
+    let casefolded = is_casefolded(inode_ref)?;
 
     // 1. 读取旧块中所有目录项
     let mut entries = alloc::vec::Vec::new();
@@ -649,45 +767,27 @@ pub fn split_leaf_block<D: BlockDevice>(
         let mut block = Block::get(bdev, old_block_addr)?;
 
         block.with_data(|data| {
-            let mut offset = 0;
-
-            while offset < block_size {
-                if offset + 8 > block_size {
-                    break;
-                }
-
-                let de = unsafe { &*(data.as_ptr().add(offset) as *const ext4_dir_en) };
-                let rec_len = u16::from_le(de.rec_len) as usize;
-
-                if rec_len < 8 || offset + rec_len > block_size {
-                    break;
-                }
-
-                let inode = u32::from_le(de.inode);
-                if inode != 0 && de.name_len > 0 {
+            // 损坏的目录项会让部分条目丢失，直接报错
+            for de in parse::parse_dir_block(data.get(..block_size).unwrap_or(data))? {
+                if de.inode != 0 && !de.name.is_empty() {
                     // 计算哈希值
-                    let name_len = de.name_len as usize;
-                    let name_slice = &data[offset + 8..offset + 8 + name_len];
-
                     let (hash, _minor_hash) = htree_hash(
-                        name_slice,
+                        &hash_name(casefolded, de.name),
                         hash_info.seed.as_ref(),
                         hash_info.hash_version
                     )?;
 
                     let mut entry = DirEntrySortEntry {
                         hash,
-                        inode,
-                        name_len: de.name_len,
+                        inode: de.inode,
+                        name_len: de.name.len() as u8,
                         file_type: de.file_type,
                         name: [0; 255],
                     };
-                    entry.name[..name_len].copy_from_slice(name_slice);
+                    entry.name[..de.name.len()].copy_from_slice(de.name);
 
                     entries.push(entry);
                 }
-
-                offset += rec_len;
             }
 
             Ok::<(), Error>(())
@@ -735,32 +835,24 @@ pub fn split_leaf_block<D: BlockDevice>(
         split_hash = entries[split_idx].hash;
     }
 
-    // 4. 确保相同哈希的条目不被分开
+    // 4. 尽量不把相同哈希的条目分开；做不到时（右侧全部同哈希）
+    //    按内核的做法保留分割点，并在索引哈希上标记冲突延续
     let mut continued = false;
     if split_idx > 0 && split_hash == entries[split_idx - 1].hash {
-        // 需要跳过所有相同哈希的条目
-        while split_idx < entries.len() && entries[split_idx].hash == split_hash {
-            split_idx += 1;
+        let mut next = split_idx;
+        while next < entries.len() && entries[next].hash == split_hash {
+            next += 1;
         }
-        if split_idx < entries.len() {
-            split_hash = entries[split_idx].hash;
+        if next < entries.len() {
+            split_idx = next;
+            split_hash = entries[next].hash;
+        } else {
+            continued = true;
         }
-        continued = true;
     }
 
-    // 5. 分配新块
-    let mut allocator = BlockAllocator::new();
-    let goal = old_block_addr;
-
-    let new_block_addr = {
-        let bdev = inode_ref.bdev();
-        allocator.alloc_block(bdev, sb, goal)?
-    };
-    inode_ref.add_blocks(1)?;
-
-    // 计算新块的逻辑块号
-    let current_size = inode_ref.size()?;
-    let new_logical_block = (current_size / block_size as u64) as u32;
+    // 5. 在目录末尾分配新块（同时映射到 extent 树并更新 i_blocks）
+    let (new_logical_block, new_block_addr) = append_dir_block(inode_ref, block_size)?;
 
     // 6. 写入两个块
     write_sorted_entries(
@@ -793,7 +885,25 @@ pub fn split_leaf_block<D: BlockDevice>(
     Ok((new_logical_block, final_split_hash))
 }
 
+/// 在目录末尾追加一个块，返回 (逻辑块号, 物理块号)
+///
+/// 通过 extent 树分配，保证新块被映射且 i_blocks 得到更新；
+/// 调用者负责在写入内容后更新 inode 大小
+#[cfg(feature = "htree-write")]
+fn append_dir_block<D: BlockDevice>(
+    inode_ref: &mut InodeRef<D>,
+    block_size: usize,
+) -> Result<(u32, u64)> {
+    let new_logical_block = (inode_ref.size()? / block_size as u64) as u32;
+    let new_block_addr = inode_ref.get_inode_dblk_idx(new_logical_block, true)?;
+    if new_block_addr == 0 {
+        return Err(Error::new(ErrorKind::NoSpace, "Failed to allocate directory block"));
+    }
+    Ok((new_logical_block, new_block_addr))
+}
+
 /// Write sorted directory entries to a block
+#[cfg(feature = "htree-write")]
 fn write_sorted_entries<D: BlockDevice>(
     inode_ref: &mut InodeRef<D>,
     block_addr: u64,
@@ -811,7 +921,7 @@ fn write_sorted_entries<D: BlockDevice>(
     };
     let usable_size = block_size - tail_size;
 
-    let uuid = inode_ref.sb().inner().uuid;
+    let csum_seed = inode_ref.sb().csum_seed();
     let dir_inode = inode_ref.index();
     let inode_generation = inode_ref.generation()?;
 
@@ -821,6 +931,12 @@ fn write_sorted_entries<D: BlockDevice>(
     block.with_data_mut(|data| {
         data.fill(0);
 
+        if entries.is_empty() {
+            // 空块：一个覆盖整个可用空间的空目录项
+            let de = unsafe { &mut *(data.as_mut_ptr() as *mut ext4_dir_en) };
+            de.rec_len = (usable_size as u16).to_le();
+        }
+
         let mut offset = 0_usize;
         for (i, entry) in entries.iter().enumerate() {
             if offset >= usable_size {
@@ -861,7 +977,7 @@ fn write_sorted_entries<D: BlockDevice>(
 
             update_dir_block_checksum(
                 has_csum,
-                &uuid,
+                csum_seed,
                 dir_inode,
                 inode_generation,
                 data,
@@ -884,6 +1000,7 @@ fn write_sorted_entries<D: BlockDevice>(
 /// * `insert_position` - 插入位置（在 entries 数组中的索引）
 /// * `hash` - 哈希值
 /// * `logical_block` - 逻辑块号
+#[cfg(feature = "htree-write")]
 fn insert_index_entry<D: BlockDevice>(
     inode_ref: &mut InodeRef<D>,
     index_block_addr: u64,
@@ -891,7 +1008,7 @@ fn insert_index_entry<D: BlockDevice>(
     hash: u32,
     logical_block: u32,
 ) -> Result<()> {
-    let has_csum = inode_ref.sb().has_ro_compat_feature(EXT4_FEATURE_RO_COMPAT_METADATA_CSUM);
+    let csum_seed = index_csum_seed(inode_ref)?;
     let block_size = inode_ref.sb().block_size() as usize;
 
     let bdev = inode_ref.bdev();
@@ -950,30 +1067,40 @@ fn insert_index_entry<D: BlockDevice>(
         climit.count = (count + 1).to_le();
 
         // 更新校验和（如果需要）
-        if has_csum {
-            update_index_block_checksum(has_csum, data, block_size);
-        }
+        update_index_block_checksum(csum_seed, data, block_size);
     })?;
 
     Ok(())
 }
 
+/// 获取索引块校验和使用的 inode 种子，未启用 metadata_csum 时返回 None
+#[cfg(feature = "htree-write")]
+fn index_csum_seed<D: BlockDevice>(inode_ref: &mut InodeRef<D>) -> Result<Option<u32>> {
+    if !inode_ref.sb().has_ro_compat_feature(EXT4_FEATURE_RO_COMPAT_METADATA_CSUM) {
+        return Ok(None);
+    }
+    inode_ref.csum_seed().map(Some)
+}
+
 /// Update index block checksum
+///
+/// `csum_seed` 为 [`index_csum_seed`] 的结果，为 None 时不做任何修改
+#[cfg(feature = "htree-write")]
 fn update_index_block_checksum(
-    _has_csum: bool,
-    _data: &mut [u8],
-    _block_size: usize,
+    csum_seed: Option<u32>,
+    data: &mut [u8],
+    block_size: usize,
 ) {
-    // TODO: 实现索引块校验和
-    // 类似于 dir block checksum，但使用 ext4_dir_idx_tail
-    // 当前占位实现
+    if let Some(seed) = csum_seed {
+        super::checksum::set_dx_csum(seed, data, block_size);
+    }
 }
 
 /// Index block split result
 pub struct IndexSplitResult {
     /// New index block logical number
     pub new_logical_block: u32,
-    /// Split hash value
+    /// Split hash value（根块增高时为 0，无需在父块中插入条目）
     pub split_hash: u32,
     /// Whether this is a root split (tree grew taller)
     pub is_root_split: bool,
@@ -985,13 +1112,12 @@ pub struct IndexSplitResult {
 ///
 /// # 算法流程
 ///
-/// **Case A - 非 root 分裂（levels > 0）**：
-/// 1. 检查索引块是否已满
-/// 2. 分配新索引块
-/// 3. 1:1 分割索引条目
-/// 4. 在父级插入新条目
+/// **Case A - 非 root 分裂**：
+/// 1. 分配新索引块
+/// 2. 1:1 分割索引条目，右半部分移到新块
+/// 3. 返回右半部分第一个条目的哈希，由调用者插入父索引块
 ///
-/// **Case B - root 分裂（levels == 0）**：
+/// **Case B - root 分裂（树增高）**：
 /// 1. 所有条目移到新 child 块
 /// 2. root 只保留一个条目指向新 child
 /// 3. indirect_levels += 1
@@ -999,42 +1125,39 @@ pub struct IndexSplitResult {
 /// # 参数
 ///
 /// * `inode_ref` - 目录 inode 引用
-/// * `sb` - 可变 superblock 引用
 /// * `index_block_addr` - 索引块的物理地址
 /// * `is_root` - 是否是 root 块
-/// * `position_in_entries` - 当前插入位置在 entries 中的索引
+///
+/// # 错误
+///
+/// - `ErrorKind::InvalidInput` - 索引块未满
+/// - `ErrorKind::NoSpace` - 根块已满且树高已达上限（见 `LARGEDIR`）
 ///
 /// # 返回
 ///
 /// IndexSplitResult 包含新块信息和分割哈希值
+#[cfg(feature = "htree-write")]
 pub fn split_index_block<D: BlockDevice>(
     inode_ref: &mut InodeRef<D>,
-    sb: &mut Superblock,
     index_block_addr: u64,
     is_root: bool,
-    position_in_entries: usize,
 ) -> Result<IndexSplitResult> {
-    let block_size = sb.block_size() as usize;
-    let has_csum = sb.has_ro_compat_feature(EXT4_FEATURE_RO_COMPAT_METADATA_CSUM);
+    let block_size = inode_ref.sb().block_size() as usize;
+    let csum_seed = index_csum_seed(inode_ref)?;
+    let entries_offset = if is_root { ROOT_ENTRIES_OFFSET } else { NODE_ENTRIES_OFFSET };
 
-    // 1. 读取当前块的 count 和 limit
-    let (count, limit) = {
+    // 1. 读取当前块的 count、limit 和（根块的）树高
+    let (count, limit, levels) = {
         let bdev = inode_ref.bdev();
         let mut block = Block::get(bdev, index_block_addr)?;
 
         block.with_data(|data| {
-            let entries_offset = if is_root {
-                2 * core::mem::size_of::<crate::types::ext4_dir_idx_dot_en>()
-                    + core::mem::size_of::<crate::types::ext4_dir_idx_rinfo>()
-            } else {
-                core::mem::size_of::<ext4_fake_dir_entry>()
-            };
-
             let climit = unsafe {
                 &*(data.as_ptr().add(entries_offset) as *const ext4_dir_idx_climit)
             };
+            let root = unsafe { &*(data.as_ptr() as *const ext4_dir_idx_root) };
 
-            (u16::from_le(climit.count), u16::from_le(climit.limit))
+            (climit.count(), climit.limit(), root.info.indirect_levels())
         })?
     };
 
@@ -1046,32 +1169,23 @@ pub fn split_index_block<D: BlockDevice>(
             "Index block not full, no split needed"
         ));
     }
+    if is_root && levels >= max_indirect_levels(inode_ref.sb()) {
+        return Err(Error::new(ErrorKind::NoSpace, "Directory index is full"));
+    }
 
-    // 3. 分配新索引块
-    let mut allocator = BlockAllocator::new();
-    let goal = index_block_addr;
-
-    let new_block_addr = {
-        let bdev = inode_ref.bdev();
-        allocator.alloc_block(bdev, sb, goal)?
-    };
-    inode_ref.add_blocks(1)?;
-
-    // 计算新块的逻辑块号
-    let current_size = inode_ref.size()?;
-    let new_logical_block = (current_size / block_size as u64) as u32;
+    // 3. 在目录末尾分配新索引块
+    let (new_logical_block, new_block_addr) = append_dir_block(inode_ref, block_size)?;
 
     // 4. 执行分裂
-    if !is_root {
+    let split_hash = if !is_root {
         // Case A: 非 root 分裂
         split_non_root_index(
             inode_ref,
             index_block_addr,
             new_block_addr,
             count,
-            position_in_entries,
             block_size,
-            has_csum
+            csum_seed
         )?
     } else {
         // Case B: root 分裂
@@ -1082,18 +1196,15 @@ pub fn split_index_block<D: BlockDevice>(
             new_logical_block,
             count,
             block_size,
-            has_csum
-        )?
-    }
+            csum_seed
+        )?;
+        0
+    };
 
     // 5. 更新 inode size
     let new_size = (new_logical_block as u64 + 1) * block_size as u64;
     inode_ref.set_size(new_size)?;
 
-    // 6. 返回分割信息
-    // 分割哈希值需要从新块的第一个条目读取
-    let split_hash = read_first_entry_hash(inode_ref, new_block_addr, false)?;
-
     Ok(IndexSplitResult {
         new_logical_block,
         split_hash,
@@ -1101,21 +1212,61 @@ pub fn split_index_block<D: BlockDevice>(
     })
 }
 
+/// 初始化空的非根索引块并写入条目
+///
+/// `entries` 是从其他索引块复制出的原始条目（从 entries[0] 开始），
+/// 写入后 entries[0].hash 被新块的 climit 覆盖
+#[cfg(feature = "htree-write")]
+fn init_index_node(data: &mut [u8], entries: &[u8], block_size: usize, csum_seed: Option<u32>) {
+    let entry_size = core::mem::size_of::<ext4_dir_idx_entry>();
+
+    data.fill(0);
+
+    // 初始化 fake entry
+    let fake = unsafe { &mut *(data.as_mut_ptr() as *mut ext4_fake_dir_entry) };
+    fake.inode = 0;
+    fake.entry_len = (block_size as u16).to_le();
+    fake.name_len = 0;
+    fake.inode_type = 0;
+
+    // 写入条目
+    data[NODE_ENTRIES_OFFSET..NODE_ENTRIES_OFFSET + entries.len()].copy_from_slice(entries);
+
+    // 写入 climit
+    let tail_size = if csum_seed.is_some() {
+        core::mem::size_of::<crate::types::ext4_dir_idx_tail>()
+    } else {
+        0
+    };
+    let entry_space = block_size - NODE_ENTRIES_OFFSET - tail_size;
+    let max_entries = (entry_space / entry_size) as u16;
+
+    let climit = unsafe {
+        &mut *(data.as_mut_ptr().add(NODE_ENTRIES_OFFSET) as *mut ext4_dir_idx_climit)
+    };
+    climit.limit = max_entries.to_le();
+    climit.count = ((entries.len() / entry_size) as u16).to_le();
+
+    // 更新校验和
+    update_index_block_checksum(csum_seed, data, block_size);
+}
+
 /// Split a non-root index block
+///
+/// 返回移到新块的第一个条目的哈希
+#[cfg(feature = "htree-write")]
 fn split_non_root_index<D: BlockDevice>(
     inode_ref: &mut InodeRef<D>,
     old_block_addr: u64,
     new_block_addr: u64,
     count: u16,
-    position_in_entries: usize,
     block_size: usize,
-    has_csum: bool,
-) -> Result<()> {
+    csum_seed: Option<u32>,
+) -> Result<u32> {
     let count_left = count / 2;
     let count_right = count - count_left;
 
     let entry_size = core::mem::size_of::<ext4_dir_idx_entry>();
-    let entries_offset = core::mem::size_of::<ext4_fake_dir_entry>();
 
     // 读取右半部分条目
     let right_entries = {
@@ -1123,7 +1274,7 @@ fn split_non_root_index<D: BlockDevice>(
         let mut block = Block::get(bdev, old_block_addr)?;
 
         block.with_data(|data| {
-            let start = entries_offset + entry_size * count_left as usize;
+            let start = NODE_ENTRIES_OFFSET + entry_size * count_left as usize;
             let len = entry_size * count_right as usize;
             let mut entries = alloc::vec::Vec::with_capacity(len);
             entries.extend_from_slice(&data[start..start + len]);
@@ -1131,47 +1282,19 @@ fn split_non_root_index<D: BlockDevice>(
         })?
     };
 
+    // 右半部分第一个条目的哈希在新块中会被 climit 覆盖，先取出
+    let split_hash = u32::from_le_bytes([
+        right_entries[0],
+        right_entries[1],
+        right_entries[2],
+        right_entries[3],
+    ]);
+
     // 初始化新块
     {
         let bdev = inode_ref.bdev();
         let mut block = Block::get_noread(bdev, new_block_addr)?;
-
-        block.with_data_mut(|data| {
-            data.fill(0);
-
-            // 初始化 fake entry
-            let fake = unsafe { &mut *(data.as_mut_ptr() as *mut ext4_fake_dir_entry) };
-            fake.inode = 0;
-            fake.entry_len = (block_size as u16).to_le();
-            fake.name_len = 0;
-            fake.inode_type = 0;
-
-            // 写入 climit
-            let climit = unsafe {
-                &mut *(data.as_mut_ptr().add(entries_offset) as *mut ext4_dir_idx_climit)
-            };
-
-            let tail_size = if has_csum {
-                core::mem::size_of::<crate::types::ext4_dir_idx_tail>()
-            } else {
-                0
-            };
-            let entry_space = block_size - entries_offset - tail_size;
-            let max_entries = (entry_space / entry_size) as u16;
-
-            climit.limit = max_entries.to_le();
-            climit.count = count_right.to_le();
-
-            // 写入条目
-            data[entries_offset + core::mem::size_of::<ext4_dir_idx_climit>()..]
-                [..right_entries.len()]
-                .copy_from_slice(&right_entries);
-
-            // 更新校验和
-            if has_csum {
-                update_index_block_checksum(has_csum, data, block_size);
-            }
-        })?;
+        block.with_data_mut(|data| init_index_node(data, &right_entries, block_size, csum_seed))?;
     }
 
     // 更新旧块的 count
@@ -1181,23 +1304,19 @@ fn split_non_root_index<D: BlockDevice>(
 
         block.with_data_mut(|data| {
             let climit = unsafe {
-                &mut *(data.as_mut_ptr().add(entries_offset) as *mut ext4_dir_idx_climit)
+                &mut *(data.as_mut_ptr().add(NODE_ENTRIES_OFFSET) as *mut ext4_dir_idx_climit)
             };
             climit.count = count_left.to_le();
 
-            if has_csum {
-                update_index_block_checksum(has_csum, data, block_size);
-            }
+            update_index_block_checksum(csum_seed, data, block_size);
         })?;
     }
 
-    // TODO: 根据 position_in_entries 判断是否需要切换当前块指针
-    // 这需要在调用者处理
-
-    Ok(())
+    Ok(split_hash)
 }
 
 /// Split root index block (grow tree height)
+#[cfg(feature = "htree-write")]
 fn split_root_index<D: BlockDevice>(
     inode_ref: &mut InodeRef<D>,
     root_block_addr: u64,
@@ -1205,23 +1324,19 @@ fn split_root_index<D: BlockDevice>(
     new_child_logical: u32,
     count: u16,
     block_size: usize,
-    has_csum: bool,
+    csum_seed: Option<u32>,
 ) -> Result<()> {
     let entry_size = core::mem::size_of::<ext4_dir_idx_entry>();
-    let root_entries_offset = 2 * core::mem::size_of::<crate::types::ext4_dir_idx_dot_en>()
-        + core::mem::size_of::<crate::types::ext4_dir_idx_rinfo>();
-    let child_entries_offset = core::mem::size_of::<ext4_fake_dir_entry>();
 
-    // 读取所有条目
+    // 读取所有条目（entries[0].hash 位置是根块的 climit，写入新块后被覆盖）
     let all_entries = {
         let bdev = inode_ref.bdev();
         let mut block = Block::get(bdev, root_block_addr)?;
 
         block.with_data(|data| {
-            let start = root_entries_offset + core::mem::size_of::<ext4_dir_idx_climit>();
             let len = entry_size * count as usize;
             let mut entries = alloc::vec::Vec::with_capacity(len);
-            entries.extend_from_slice(&data[start..start + len]);
+            entries.extend_from_slice(&data[ROOT_ENTRIES_OFFSET..ROOT_ENTRIES_OFFSET + len]);
             entries
         })?
     };
@@ -1230,43 +1345,7 @@ fn split_root_index<D: BlockDevice>(
     {
         let bdev = inode_ref.bdev();
         let mut block = Block::get_noread(bdev, new_child_addr)?;
-
-        block.with_data_mut(|data| {
-            data.fill(0);
-
-            // 初始化 fake entry
-            let fake = unsafe { &mut *(data.as_mut_ptr() as *mut ext4_fake_dir_entry) };
-            fake.inode = 0;
-            fake.entry_len = (block_size as u16).to_le();
-            fake.name_len = 0;
-            fake.inode_type = 0;
-
-            // 写入 climit
-            let climit = unsafe {
-                &mut *(data.as_mut_ptr().add(child_entries_offset) as *mut ext4_dir_idx_climit)
-            };
-
-            let tail_size = if has_csum {
-                core::mem::size_of::<crate::types::ext4_dir_idx_tail>()
-            } else {
-                0
-            };
-            let entry_space = block_size - child_entries_offset - tail_size;
-            let max_entries = (entry_space / entry_size) as u16;
-
-            climit.limit = max_entries.to_le();
-            climit.count = count.to_le();
-
-            // 写入所有条目
-            data[child_entries_offset + core::mem::size_of::<ext4_dir_idx_climit>()..]
-                [..all_entries.len()]
-                .copy_from_slice(&all_entries);
-
-            // 更新校验和
-            if has_csum {
-                update_index_block_checksum(has_csum, data, block_size);
-            }
-        })?;
+        block.with_data_mut(|data| init_index_node(data, &all_entries, block_size, csum_seed))?;
     }
 
     // 更新 root 块
@@ -1275,64 +1354,28 @@ fn split_root_index<D: BlockDevice>(
         let mut block = Block::get(bdev, root_block_addr)?;
 
         block.with_data_mut(|data| {
-            // 更新 root info: indirect_levels = 1
-            let root_info_offset = 2 * core::mem::size_of::<crate::types::ext4_dir_idx_dot_en>();
-            let root_info = unsafe {
-                &mut *(data.as_mut_ptr().add(root_info_offset) as *mut crate::types::ext4_dir_idx_rinfo)
-            };
-            root_info.indirect_levels = 1;
+            // 更新 root info: indirect_levels += 1
+            let root = unsafe { &mut *(data.as_mut_ptr() as *mut ext4_dir_idx_root) };
+            root.info.indirect_levels += 1;
 
             // 更新 climit: count = 1
             let climit = unsafe {
-                &mut *(data.as_mut_ptr().add(root_entries_offset) as *mut ext4_dir_idx_climit)
+                &mut *(data.as_mut_ptr().add(ROOT_ENTRIES_OFFSET) as *mut ext4_dir_idx_climit)
             };
             climit.count = 1_u16.to_le();
 
-            // 写入唯一的条目，指向新 child
-            let entry = unsafe {
-                &mut *(data.as_mut_ptr().add(
-                    root_entries_offset + core::mem::size_of::<ext4_dir_idx_climit>()
-                ) as *mut ext4_dir_idx_entry)
-            };
-            entry.hash = 0_u32.to_le(); // Root entry hash is 0
-            entry.block = new_child_logical.to_le();
+            // entries[0].block 指向新 child（hash 字段即 climit）
+            let block_offset = ROOT_ENTRIES_OFFSET + 4;
+            data[block_offset..block_offset + 4].copy_from_slice(&new_child_logical.to_le_bytes());
 
             // 更新校验和
-            if has_csum {
-                update_index_block_checksum(has_csum, data, block_size);
-            }
+            update_index_block_checksum(csum_seed, data, block_size);
         })?;
     }
 
     Ok(())
 }
 
-/// Read the hash of the first entry in an index block
-fn read_first_entry_hash<D: BlockDevice>(
-    inode_ref: &mut InodeRef<D>,
-    block_addr: u64,
-    is_root: bool,
-) -> Result<u32> {
-    let bdev = inode_ref.bdev();
-    let mut block = Block::get(bdev, block_addr)?;
-
-    block.with_data(|data| {
-        let entries_offset = if is_root {
-            2 * core::mem::size_of::<crate::types::ext4_dir_idx_dot_en>()
-                + core::mem::size_of::<crate::types::ext4_dir_idx_rinfo>()
-        } else {
-            core::mem::size_of::<ext4_fake_dir_entry>()
-        };
-
-        let first_entry_offset = entries_offset + core::mem::size_of::<ext4_dir_idx_climit>();
-        let entry = unsafe {
-            &*(data.as_ptr().add(first_entry_offset) as *const ext4_dir_idx_entry)
-        };
-
-        u32::from_le(entry.hash)
-    })
-}
-
 // Functions requiring implementation:
 //
 // ❌ ext4_dir_dx_reset_parent_inode()
@@ -1340,10 +1383,7 @@ fn read_first_entry_hash<D: BlockDevice>(
 //    - Requires: transaction, directory entry modification
 //
 // ✅ split_leaf_block() - Implemented
-// ✅ split_index_block() - Implemented
-// ⏳ Integration into add_entry - Next step
-//
-// These will be integrated into add_entry after testing
+// ✅ split_index_block() - Implemented, used by add_entry
 
 #[cfg(test)]
 mod tests {
@@ -1362,4 +1402,31 @@ mod tests {
         let entries = base_space / core::mem::size_of::<ext4_dir_idx_entry>() as u32;
         assert_eq!(entries, 508);
     }
+
+    #[test]
+    fn test_hash_continuation() {
+        assert!(is_hash_continuation(0x1235, 0x1234));
+        assert!(is_hash_continuation(0x1235, 0x1235));
+        // 没有 continuation 位：冲突条目不会出现在下一个块
+        assert!(!is_hash_continuation(0x1234, 0x1234));
+        assert!(!is_hash_continuation(0x1237, 0x1234));
+    }
+
+    #[cfg(feature = "htree-write")]
+    #[test]
+    fn test_init_index_node_overlays_climit() {
+        // 两个条目：(hash 0x10, block 3)，(hash 0x20, block 4)
+        let entries = [0x10, 0, 0, 0, 3, 0, 0, 0, 0x20, 0, 0, 0, 4, 0, 0, 0];
+        let mut data = alloc::vec![0xffu8; 4096];
+        init_index_node(&mut data, &entries, 4096, None);
+
+        let climit = unsafe { &*(data.as_ptr().add(NODE_ENTRIES_OFFSET) as *const ext4_dir_idx_climit) };
+        assert_eq!(climit.count(), 2);
+        assert_eq!(climit.limit(), 511);
+
+        // entries[0].hash 被 climit 覆盖，block 和后续条目保持不变
+        let node_entries = &data[NODE_ENTRIES_OFFSET + 4..NODE_ENTRIES_OFFSET + 16];
+        assert_eq!(node_entries, &entries[4..]);
+        assert!(data[NODE_ENTRIES_OFFSET + 16..].iter().all(|&b| b == 0));
+    }
 }
@@ -15,11 +15,13 @@ use crate::{
     block::{Block, BlockDev, BlockDevice},
     consts::*,
     error::{Error, ErrorKind, Result},
-    fs::InodeRef,
+    fs::{FileType, InodeRef, InodeType},
+    logging::ext4_log,
+    parse,
     superblock::Superblock,
-    types::ext4_dir_entry,
 };
-use alloc::string::String;
+use super::{casefold::{casefold, is_casefolded}, crypt::{decode_name, encode_name}};
+use alloc::{borrow::Cow, string::String};
 
 /// 目录迭代器状态
 ///
@@ -40,6 +42,10 @@ pub struct DirIterator {
     total_size: u64,
     /// 是否已初始化
     initialized: bool,
+    /// 目录是否加密，加密目录中的名称按 [`encode_name`] 编码
+    encrypted: bool,
+    /// 最近一次验证过校验和的逻辑块号，见 `FsConfig::verify_checksums`
+    verified_block: Option<u32>,
 }
 
 impl DirIterator {
@@ -59,13 +65,14 @@ impl DirIterator {
 
         if !is_dir {
             return Err(Error::new(
-                ErrorKind::InvalidInput,
+                ErrorKind::NotADirectory,
                 "Inode is not a directory",
             ));
         }
 
         let total_size = inode_ref.size()?;
         let block_size = inode_ref.sb().block_size();
+        let encrypted = inode_ref.is_encrypted()?;
 
         Ok(Self {
             curr_off: pos,
@@ -73,6 +80,8 @@ impl DirIterator {
             offset_in_block: (pos % block_size as u64) as usize,
             total_size,
             initialized: false,
+            encrypted,
+            verified_block: None,
         })
     }
 
@@ -98,6 +107,18 @@ impl DirIterator {
         self.current_block_idx = new_block_idx;
         self.offset_in_block = (pos % block_size) as usize;
 
+        // 偏移可能来自目录修改之前（条目被合并或删除），
+        // 与内核一样从块首重新扫描，对齐到不早于 pos 的第一个条目
+        if self.offset_in_block != 0 {
+            let physical_block = inode_ref.get_inode_dblk_idx(self.current_block_idx, false)?;
+            let mut block = Block::get(inode_ref.bdev(), physical_block)?;
+            let target = self.offset_in_block;
+            let aligned = block.with_data(|data| align_to_entry(data, target))?;
+
+            self.offset_in_block = aligned;
+            self.curr_off = new_block_idx as u64 * block_size + aligned as u64;
+        }
+
         Ok(())
     }
 
@@ -168,6 +189,31 @@ impl DirIterator {
         }
     }
 
+    /// 获取当前块中的下一个目录项
+    ///
+    /// 与 [`next`](Self::next) 相同，但到达当前块末尾时返回 `None`
+    /// 而不进入下一个块，用于在 HTree 定位到的叶子块内查找
+    pub fn next_in_block<D: BlockDevice>(
+        &mut self,
+        inode_ref: &mut InodeRef<D>,
+    ) -> Result<Option<DirEntry>> {
+        let block_size = inode_ref.sb().block_size() as usize;
+
+        while !self.is_at_end() && self.offset_in_block < block_size {
+            let Some((entry, rec_len)) = self.read_current_entry(inode_ref)? else {
+                return Ok(None);
+            };
+            self.offset_in_block += rec_len as usize;
+            self.curr_off += rec_len as u64;
+
+            if entry.inode != 0 {
+                return Ok(Some(entry));
+            }
+        }
+
+        Ok(None)
+    }
+
     /// 读取当前位置的目录项
     ///
     /// 对应 lwext4 的 `ext4_dir_iterator_set()` 和目录项读取逻辑
@@ -178,7 +224,7 @@ impl DirIterator {
     /// - `Ok(None)` - 遇到 rec_len == 0（目录结束）
     /// - `Err(_)` - 格式错误或 I/O 错误
     fn read_current_entry<D: BlockDevice>(
-        &self,
+        &mut self,
         inode_ref: &mut InodeRef<D>,
     ) -> Result<Option<(DirEntry, u16)>> {
         let block_size = inode_ref.sb().block_size() as usize;
@@ -202,92 +248,66 @@ impl DirIterator {
         // 获取当前块的物理地址
         let physical_block = inode_ref.get_inode_dblk_idx(self.current_block_idx, false)?;
 
+        // 每个块只在第一次读取时验证校验和
+        if self.verified_block != Some(self.current_block_idx) {
+            super::checksum::check_block(inode_ref, physical_block)?;
+            self.verified_block = Some(self.current_block_idx);
+        }
+
         // 通过 Block handle 读取块
         let bdev = inode_ref.bdev();
         let mut block = Block::get(bdev, physical_block)?;
 
         block.with_data(|data| {
-            // 读取目录项头部
-            let entry_ptr = unsafe {
-                data.as_ptr().add(self.offset_in_block) as *const ext4_dir_entry
-            };
-            let entry_header = unsafe { core::ptr::read_unaligned(entry_ptr) };
-
-            let rec_len = u16::from_le(entry_header.rec_len);
-
             // rec_len 为 0 表示目录结束
-            if rec_len == 0 {
+            if parse::read_u16(data, self.offset_in_block + 4)? == 0 {
                 return Ok(None);
             }
 
-            // 检查 rec_len 是否越界
-            if self.offset_in_block + rec_len as usize > block_size {
-                return Err(Error::new(
-                    ErrorKind::Corrupted,
-                    "Directory entry rec_len extends beyond block",
-                ));
-            }
-
-            let name_len = entry_header.name_len as usize;
+            // 检查 rec_len 和 name_len 是否越界
+            let entry = parse::dir_entry_at(data.get(..block_size).unwrap_or(data), self.offset_in_block)?;
 
-            // 检查 name_len 是否合法（lwext4 的检查）
-            if name_len > rec_len as usize - 8 {
-                return Err(Error::new(
-                    ErrorKind::Corrupted,
-                    "Directory entry name_len too large",
-                ));
-            }
-
-            // 如果 inode 为 0，返回空项（已删除）
-            let inode = u32::from_le(entry_header.inode);
-            if inode == 0 {
-                return Ok(Some((
-                    DirEntry {
-                        inode: 0,
-                        name: String::new(),
-                        file_type: entry_header.file_type,
-                    },
-                    rec_len,
-                )));
-            }
-
-            // 读取文件名（紧跟在固定 8 字节头部之后）
-            if name_len == 0 || name_len > EXT4_NAME_MAX {
-                return Ok(Some((
-                    DirEntry {
-                        inode,
-                        name: String::new(),
-                        file_type: entry_header.file_type,
-                    },
-                    rec_len,
-                )));
-            }
-
-            let name_start = self.offset_in_block + 8;
-            let name_end = name_start + name_len;
-
-            if name_end > block_size {
-                return Err(Error::new(
-                    ErrorKind::Corrupted,
-                    "Directory entry name extends beyond block",
-                ));
-            }
-
-            let name_bytes = &data[name_start..name_end];
-            let name = String::from_utf8_lossy(name_bytes).into_owned();
+            // inode 为 0 的空项（已删除）和名称长度为 0 的项返回空名称
+            let name = if entry.inode == 0 || entry.name.is_empty() {
+                String::new()
+            } else if self.encrypted && entry.name != b"." && entry.name != b".." {
+                // `.` 和 `..` 不加密
+                encode_name(entry.name)
+            } else {
+                String::from_utf8_lossy(entry.name).into_owned()
+            };
 
             Ok(Some((
                 DirEntry {
-                    inode,
+                    inode: entry.inode,
                     name,
-                    file_type: entry_header.file_type,
+                    file_type: entry.file_type,
                 },
-                rec_len,
+                entry.rec_len,
             )))
         })?
     }
 }
 
+/// 计算块内不早于 `target` 的第一个目录项的偏移
+///
+/// 从块首按 rec_len 逐项前进；遇到损坏的 rec_len 时返回块大小，
+/// 即从下一个块继续
+fn align_to_entry(data: &[u8], target: usize) -> usize {
+    let mut off = 0;
+    while off < target {
+        if off + EXT4_DIR_ENTRY_MIN_LEN > data.len() {
+            return data.len();
+        }
+        let rec_len = u16::from_le_bytes([data[off + 4], data[off + 5]]) as usize;
+        if rec_len < EXT4_DIR_ENTRY_MIN_LEN || rec_len % 4 != 0 {
+            return data.len();
+        }
+        off += rec_len;
+    }
+    off.min(data.len())
+}
+
 /// 目录项
 ///
 /// 表示一个目录中的条目
@@ -316,6 +336,137 @@ impl DirEntry {
     pub fn is_symlink(&self) -> bool {
         self.file_type == EXT4_DE_SYMLINK
     }
+
+    /// 条目的文件类型，包括设备文件、FIFO 和 Socket
+    ///
+    /// 目录项没有记录类型时为 `FileType::Unknown`，见 [`fill_file_type`]
+    pub fn kind(&self) -> FileType {
+        FileType::from_de_type(self.file_type)
+    }
+}
+
+/// 在目录中查找的名称
+///
+/// 按目录的属性决定如何比较，要查找的名称只转换一次：
+/// - 加密目录：名称是 [`crypt::encode_name`](super::crypt::encode_name) 编码的密文
+/// - 大小写不敏感目录：预先规范化，逐项比较规范化后的名称
+/// - 其他目录：按字节比较
+pub(crate) struct NameMatcher<'a> {
+    name: &'a str,
+    encrypted: bool,
+    folded: Option<String>,
+}
+
+impl<'a> NameMatcher<'a> {
+    /// 为 `inode_ref` 指向的目录创建匹配器
+    pub(crate) fn new<D: BlockDevice>(inode_ref: &mut InodeRef<D>, name: &'a str) -> Result<Self> {
+        let encrypted = inode_ref.is_encrypted()?;
+        // 密文无法规范化，加密且大小写不敏感的目录只能按密文比较
+        let folded = (!encrypted && is_casefolded(inode_ref)?).then(|| casefold(name));
+        Ok(Self { name, encrypted, folded })
+    }
+
+    /// 与 [`DirEntry::name`] 比较
+    pub(crate) fn matches(&self, entry_name: &str) -> bool {
+        match &self.folded {
+            Some(folded) => casefold(entry_name) == *folded,
+            None => entry_name == self.name,
+        }
+    }
+
+    /// 与磁盘上目录项的原始名称比较
+    pub(crate) fn matches_raw(&self, raw: &[u8]) -> bool {
+        if self.encrypted {
+            return encode_name(raw) == self.name;
+        }
+        match core::str::from_utf8(raw) {
+            Ok(s) => self.matches(s),
+            // 无效的 UTF-8 与内核一样按字节比较
+            Err(_) => raw == self.name.as_bytes(),
+        }
+    }
+
+    /// 计算 HTree 哈希时使用的名称
+    ///
+    /// 加密目录中是密文；名称不是合法的编码时返回 `None`，此时不会有匹配的目录项
+    pub(crate) fn hash_input(&self) -> Option<Cow<'_, [u8]>> {
+        if self.encrypted {
+            return decode_name(self.name).map(Cow::Owned);
+        }
+        Some(match &self.folded {
+            Some(folded) => Cow::Borrowed(folded.as_bytes()),
+            None => Cow::Borrowed(self.name.as_bytes()),
+        })
+    }
+}
+
+/// 在目录的指定逻辑块内查找名称
+///
+/// # 参数
+///
+/// * `inode_ref` - 目录的 inode 引用
+/// * `lblk` - 目录内的逻辑块号
+/// * `name` - 要查找的文件名
+///
+/// # 返回
+///
+/// 找到的 inode 编号，不存在时返回 `None`
+pub fn find_in_block<D: BlockDevice>(
+    inode_ref: &mut InodeRef<D>,
+    lblk: u32,
+    name: &str,
+) -> Result<Option<u32>> {
+    let matcher = NameMatcher::new(inode_ref, name)?;
+    let block_size = inode_ref.sb().block_size() as u64;
+    let mut iter = DirIterator::new(inode_ref, lblk as u64 * block_size)?;
+
+    while let Some(entry) = iter.next_in_block(inode_ref)? {
+        if matcher.matches(&entry.name) {
+            return Ok(Some(entry.inode));
+        }
+    }
+
+    Ok(None)
+}
+
+/// 在目录中查找名称
+///
+/// 目录带 HTree 索引时只查找哈希对应的叶子块，否则线性扫描所有块。
+/// 索引损坏或使用不支持的格式时与内核一样退回线性扫描。
+/// `.` 和 `..` 位于 dx root 中，不在索引里，总是线性查找（它们是前两项）。
+/// 大小写不敏感目录中按规范化后的名称比较，见 [`casefold`](super::casefold)
+///
+/// # 参数
+///
+/// * `inode_ref` - 目录的 inode 引用
+/// * `name` - 要查找的文件名
+///
+/// # 返回
+///
+/// 找到的 inode 编号，不存在时返回 `None`
+pub fn find_entry<D: BlockDevice>(
+    inode_ref: &mut InodeRef<D>,
+    name: &str,
+) -> Result<Option<u32>> {
+    if name != "." && name != ".." && super::htree::is_indexed(inode_ref)? {
+        match super::htree::find_entry(inode_ref, name) {
+            Ok(found) => return Ok(found),
+            Err(e) if matches!(e.kind(), ErrorKind::Corrupted | ErrorKind::Unsupported) => {
+                ext4_log!(Dir, Warn, "[HTREE] index lookup failed ({:?}), falling back to linear scan", e);
+            }
+            Err(e) => return Err(e),
+        }
+    }
+
+    let matcher = NameMatcher::new(inode_ref, name)?;
+    let mut iter = DirIterator::new(inode_ref, 0)?;
+    while let Some(entry) = iter.next(inode_ref)? {
+        if matcher.matches(&entry.name) {
+            return Ok(Some(entry.inode));
+        }
+    }
+
+    Ok(None)
 }
 
 /// 便捷函数：读取目录中的所有条目
@@ -333,13 +484,88 @@ pub fn read_dir<D: BlockDevice>(
     let mut entries = alloc::vec::Vec::new();
     let mut iter = DirIterator::new(inode_ref, 0)?;
 
-    while let Some(entry) = iter.next(inode_ref)? {
+    while let Some(mut entry) = iter.next(inode_ref)? {
+        fill_file_type(inode_ref, &mut entry)?;
         entries.push(entry);
     }
 
     Ok(entries)
 }
 
+/// 补全目录项的文件类型
+///
+/// 没有 `FILETYPE` 特性的文件系统中目录项不记录类型（`EXT4_DE_UNKNOWN`），
+/// 此时读取条目指向的 inode，按其 mode 填入类型
+///
+/// # 参数
+///
+/// * `inode_ref` - 条目所在目录的 inode 引用
+/// * `entry` - 要补全的目录项
+pub fn fill_file_type<D: BlockDevice>(inode_ref: &mut InodeRef<D>, entry: &mut DirEntry) -> Result<()> {
+    if entry.file_type != EXT4_DE_UNKNOWN
+        || inode_ref.sb().has_incompat_feature(EXT4_FEATURE_INCOMPAT_FILETYPE)
+    {
+        return Ok(());
+    }
+    let (bdev, sb) = inode_ref.bdev_and_sb_mut();
+    let mode = InodeRef::get(bdev, sb, entry.inode)?.with_inode(|inode| u16::from_le(inode.mode))?;
+    entry.file_type = InodeType::from_mode(mode as u32).to_de_type();
+    Ok(())
+}
+
+/// 目录项排序方式
+///
+/// 磁盘上的目录项顺序取决于创建镜像的工具和操作历史，
+/// 同样内容的两个镜像可能给出不同顺序。需要可复现的结果时使用
+/// [`DirOrder::ByName`] 或 [`DirOrder::ByInode`]。
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum DirOrder {
+    /// 磁盘上的原始顺序（不排序）
+    #[default]
+    OnDisk,
+    /// 按文件名的字节序排序
+    ByName,
+    /// 按 inode 编号排序，编号相同（硬链接）时按文件名排序
+    ByInode,
+}
+
+/// 按指定方式对目录项排序
+///
+/// 排序结果只依赖目录项内容，与磁盘布局无关，因此是稳定的：
+/// 同一组条目无论来自哪个镜像，排序后的顺序都相同。
+pub fn sort_entries(entries: &mut [DirEntry], order: DirOrder) {
+    match order {
+        DirOrder::OnDisk => {}
+        DirOrder::ByName => {
+            entries.sort_unstable_by(|a, b| a.name.as_bytes().cmp(b.name.as_bytes()));
+        }
+        DirOrder::ByInode => {
+            entries.sort_unstable_by(|a, b| {
+                a.inode
+                    .cmp(&b.inode)
+                    .then_with(|| a.name.as_bytes().cmp(b.name.as_bytes()))
+            });
+        }
+    }
+}
+
+/// 便捷函数：按指定顺序读取目录中的所有条目
+///
+/// 读取全部条目后排序一次，见 [`sort_entries`]
+///
+/// # 参数
+///
+/// * `inode_ref` - 目录的 inode 引用
+/// * `order` - 排序方式
+pub fn read_dir_sorted<D: BlockDevice>(
+    inode_ref: &mut InodeRef<D>,
+    order: DirOrder,
+) -> Result<alloc::vec::Vec<DirEntry>> {
+    let mut entries = read_dir(inode_ref)?;
+    sort_entries(&mut entries, order);
+    Ok(entries)
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -366,4 +592,52 @@ mod tests {
         assert!(!entry.is_file());
         assert!(entry.is_symlink());
     }
+
+    fn entry(inode: u32, name: &str) -> DirEntry {
+        DirEntry {
+            inode,
+            name: name.into(),
+            file_type: EXT4_DE_REG_FILE,
+        }
+    }
+
+    #[test]
+    fn test_sort_entries() {
+        let mut entries = alloc::vec![
+            entry(12, "b"),
+            entry(2, ".."),
+            entry(11, "a"),
+            entry(12, "a2"),
+            entry(11, "."),
+        ];
+
+        sort_entries(&mut entries, DirOrder::OnDisk);
+        assert_eq!(entries[0].name, "b");
+
+        sort_entries(&mut entries, DirOrder::ByName);
+        let names: alloc::vec::Vec<_> = entries.iter().map(|e| e.name.as_str()).collect();
+        assert_eq!(names, [".", "..", "a", "a2", "b"]);
+
+        sort_entries(&mut entries, DirOrder::ByInode);
+        let names: alloc::vec::Vec<_> = entries.iter().map(|e| e.name.as_str()).collect();
+        assert_eq!(names, ["..", ".", "a", "a2", "b"]);
+    }
+
+    #[test]
+    fn test_align_to_entry() {
+        // 三个条目：[0, 12) [12, 40) [40, 64)
+        let mut data = [0u8; 64];
+        data[4..6].copy_from_slice(&12u16.to_le_bytes());
+        data[16..18].copy_from_slice(&28u16.to_le_bytes());
+        data[44..46].copy_from_slice(&24u16.to_le_bytes());
+
+        assert_eq!(align_to_entry(&data, 0), 0);
+        assert_eq!(align_to_entry(&data, 12), 12);
+        assert_eq!(align_to_entry(&data, 20), 40);
+        assert_eq!(align_to_entry(&data, 41), 64);
+
+        // 损坏的 rec_len 跳到块尾
+        data[16..18].copy_from_slice(&0u16.to_le_bytes());
+        assert_eq!(align_to_entry(&data, 20), 64);
+    }
 }
@@ -104,7 +104,7 @@ impl<'a, D: BlockDevice> PathLookup<'a, D> {
             // 确保当前 inode 是目录
             if !current_inode.is_dir() {
                 return Err(Error::new(
-                    ErrorKind::InvalidInput,
+                    ErrorKind::NotADirectory,
                     "Not a directory",
                 ));
             }
@@ -10,6 +10,8 @@
 //! - `hash` - HTree 哈希算法（✅ 新实现，完整支持所有哈希版本）
 //! - `htree` - HTree 索引功能（✅ 查找完成，写入部分完成）
 //! - `write` - 目录写操作（✅ 新实现，支持添加/删除条目）
+//! - `casefold` - 大小写不敏感目录的名称规范化与比较
+//! - `crypt` - 加密目录中密文名称的编码
 //! - `entry` - 旧的目录迭代器实现（⚠️ 已废弃，保留用于向后兼容）
 //! - `lookup` - 旧的路径查找实现（⚠️ 已废弃，保留用于向后兼容）
 //!
@@ -34,6 +36,8 @@ pub mod path_lookup;
 pub mod hash;
 pub mod htree;
 pub mod write;
+pub mod casefold;
+pub mod crypt;
 
 // 旧实现（向后兼容，已废弃）
 #[deprecated(since = "0.2.0", note = "Use `iterator` module instead")]
@@ -42,9 +46,9 @@ mod entry;
 mod lookup;
 
 // 重新导出常用类型（新实现）
-pub use iterator::{DirEntry, DirIterator, read_dir};
+pub use iterator::{DirEntry, DirIterator, DirOrder, fill_file_type, find_entry, find_in_block, read_dir, read_dir_sorted, sort_entries};
 pub use reader::DirReader;
-pub use path_lookup::{PathLookup, lookup_path, get_inode_ref_by_path};
+pub use path_lookup::{PathLookup, lookup_path, lookup_path_at, get_inode_ref_by_path};
 
 // 向后兼容：重新导出旧 API（使用类型别名避免冲突）
 #[allow(deprecated)]
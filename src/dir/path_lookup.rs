@@ -7,6 +7,7 @@
 //! 与旧的 `lookup.rs` 实现相比，新设计：
 //! 1. **使用 InodeRef** - 而不是 Inode::load() 加载拷贝
 //! 2. **使用新的 DirIterator** - 基于 Block handle 的迭代器
+//!    （目录带 HTree 索引时只查找哈希对应的叶子块）
 //! 3. **更好的错误处理** - 区分不同类型的错误
 
 use crate::{
@@ -16,9 +17,7 @@ use crate::{
     fs::InodeRef,
     superblock::Superblock,
 };
-use alloc::{string::{String, ToString}, vec::Vec};
-
-use super::iterator::DirIterator;
+use super::iterator::{find_entry, DirIterator};
 
 /// 路径查找器
 ///
@@ -54,38 +53,52 @@ impl<'a, D: BlockDevice> PathLookup<'a, D> {
     /// let inode_num = lookup.find_inode("/bin/ls")?;
     /// ```
     pub fn find_inode(&mut self, path: &str) -> Result<u32> {
+        self.lookup_path_at(EXT4_ROOT_INODE, path)
+    }
+
+    /// 从指定目录开始查找路径（*at() 语义）
+    ///
+    /// 对应 openat 等系统调用的路径解析：
+    /// - 绝对路径忽略 `base_ino`，从根目录开始
+    /// - 相对路径从 `base_ino` 开始
+    /// - `.` 被跳过，`..` 通过目录中的 `..` 条目回到父目录（根目录的父目录是自身）
+    /// - 连续的 `/` 视为一个
+    /// - 以 `/` 结尾的路径要求最终 inode 是目录
+    ///
+    /// # 参数
+    ///
+    /// * `base_ino` - 相对路径的起始目录 inode 编号
+    /// * `path` - 路径字符串
+    ///
+    /// # 错误
+    ///
+    /// - `ErrorKind::InvalidInput` - 路径为空，或中间组件/带尾部 `/` 的目标不是目录
+    /// - `ErrorKind::NotFound` - 路径组件不存在
+    ///
+    /// # 示例
+    ///
+    /// ```ignore
+    /// let etc = lookup.find_inode("/etc")?;
+    /// let passwd = lookup.lookup_path_at(etc, "../etc/./passwd")?;
+    /// ```
+    pub fn lookup_path_at(&mut self, base_ino: u32, path: &str) -> Result<u32> {
         if path.is_empty() {
             return Err(Error::new(ErrorKind::InvalidInput, "Empty path"));
         }
 
-        // 分割路径
-        let components: Vec<String> = path
-            .split('/')
-            .filter(|s| !s.is_empty())
-            .map(|s| s.to_string())
-            .collect();
-
-        if components.is_empty() {
-            // 只有 "/" 的情况
-            return Ok(EXT4_ROOT_INODE);
-        }
-
-        // 从根目录开始
-        let mut current_inode_num = EXT4_ROOT_INODE;
+        let mut current_inode_num = if path.starts_with('/') {
+            EXT4_ROOT_INODE
+        } else {
+            base_ino
+        };
 
-        for component in &components {
-            // 跳过空组件和 "."
+        for component in path.split('/') {
+            // 跳过空组件（连续或首尾的 '/'）和 "."
             if component.is_empty() || component == "." {
                 continue;
             }
-
-            // 处理 ".."
-            if component == ".." {
-                // TODO: 实现 ".." 处理（需要记录父目录或读取 ".." 条目）
-                return Err(Error::new(
-                    ErrorKind::Unsupported,
-                    ".. not yet supported",
-                ));
+            if component.len() > 255 {
+                return Err(Error::new(ErrorKind::NameTooLong, "Path component too long"));
             }
 
             // 获取当前 inode 的引用
@@ -94,23 +107,21 @@ impl<'a, D: BlockDevice> PathLookup<'a, D> {
             // 确保当前 inode 是目录
             if !current_inode_ref.is_dir()? {
                 return Err(Error::new(
-                    ErrorKind::InvalidInput,
+                    ErrorKind::NotADirectory,
                     "Not a directory",
                 ));
             }
 
-            // 在目录中查找下一个组件
-            let mut iter = DirIterator::new(&mut current_inode_ref, 0)?;
-            let mut found_inode = None;
+            // ".." 条目位于目录第一个块中，HTree 的叶子块里没有它，
+            // 因此不走哈希查找
+            let found = if component == ".." {
+                find_parent(&mut current_inode_ref)?
+            } else {
+                // 在目录中查找下一个组件（有 HTree 索引时只查找对应的叶子块）
+                find_entry(&mut current_inode_ref, component)?
+            };
 
-            while let Some(entry) = iter.next(&mut current_inode_ref)? {
-                if entry.name == component.as_str() {
-                    found_inode = Some(entry.inode);
-                    break;
-                }
-            }
-
-            match found_inode {
+            match found {
                 Some(inode_num) => {
                     current_inode_num = inode_num;
                 }
@@ -125,6 +136,17 @@ impl<'a, D: BlockDevice> PathLookup<'a, D> {
             // current_inode_ref 在此处自动释放（Drop）
         }
 
+        // "dir/"、"dir/." 等形式要求目标是目录
+        if requires_dir(path) {
+            let mut inode_ref = InodeRef::get(self.bdev, self.sb, current_inode_num)?;
+            if !inode_ref.is_dir()? {
+                return Err(Error::new(
+                    ErrorKind::NotADirectory,
+                    "Not a directory",
+                ));
+            }
+        }
+
         Ok(current_inode_num)
     }
 
@@ -164,6 +186,26 @@ pub fn lookup_path<D: BlockDevice>(
     lookup.find_inode(path)
 }
 
+/// 便捷函数：从指定目录开始查找路径（*at() 语义）
+///
+/// 见 [`PathLookup::lookup_path_at`]
+///
+/// # 参数
+///
+/// * `bdev` - 块设备引用
+/// * `sb` - superblock 引用（可变）
+/// * `base_ino` - 相对路径的起始目录 inode 编号
+/// * `path` - 路径字符串
+pub fn lookup_path_at<D: BlockDevice>(
+    bdev: &mut BlockDev<D>,
+    sb: &mut Superblock,
+    base_ino: u32,
+    path: &str,
+) -> Result<u32> {
+    let mut lookup = PathLookup::new(bdev, sb);
+    lookup.lookup_path_at(base_ino, path)
+}
+
 /// 便捷函数：根据路径获取 InodeRef
 ///
 /// # 参数
@@ -182,6 +224,22 @@ pub fn get_inode_ref_by_path<'a, D: BlockDevice>(
     InodeRef::get(bdev, sb, inode_num)
 }
 
+/// 读取目录的 ".." 条目
+fn find_parent<D: BlockDevice>(inode_ref: &mut InodeRef<D>) -> Result<Option<u32>> {
+    let mut iter = DirIterator::new(inode_ref, 0)?;
+    while let Some(entry) = iter.next(inode_ref)? {
+        if entry.name == ".." {
+            return Ok(Some(entry.inode));
+        }
+    }
+    Ok(None)
+}
+
+/// 路径是否要求最终目标是目录（以 `/`、`/.` 或 `/..` 结尾）
+fn requires_dir(path: &str) -> bool {
+    path.ends_with('/') || matches!(path.rsplit('/').next(), Some(".") | Some(".."))
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -191,4 +249,16 @@ mod tests {
         // 这些测试需要实际的块设备和 ext4 文件系统
         // 主要是验证 API 的设计和编译
     }
+
+    #[test]
+    fn test_requires_dir() {
+        assert!(requires_dir("/"));
+        assert!(requires_dir("a/b/"));
+        assert!(requires_dir("a//"));
+        assert!(requires_dir("."));
+        assert!(requires_dir("a/.."));
+        assert!(!requires_dir("a/b"));
+        assert!(!requires_dir("a/.b"));
+        assert!(!requires_dir("..."));
+    }
 }
@@ -8,6 +8,15 @@
 //! - **有状态**: 与 DirIterator 不同，DirReader 持有 InodeRef 引用和当前条目
 //! - **ArceOS 兼容**: 提供 current() + step() API，而不是 Iterator 风格
 //! - **延迟加载**: 在 new() 时加载第一个条目，在 step() 时加载下一个
+//! - **可恢复**: tell()/offset() 返回的偏移可以交给 seek() 或 new() 继续读取，
+//!   用于实现 getdents 而无需把整个目录读入内存
+//!
+//! ## 偏移格式
+//!
+//! 偏移是目录内的字节位置（逻辑块号 × 块大小 + 块内偏移）。调用者应将其视为
+//! 不透明的 cookie。目录在两次调用之间被修改时，seek() 会对齐到不早于该位置的
+//! 第一个条目，因此不会返回半个条目；但 HTree 叶子分裂会在块之间移动条目，
+//! 期间被移动的条目可能重复出现或被跳过。
 //!
 //! ## 与 DirIterator 的关系
 //!
@@ -43,6 +52,8 @@ pub struct DirReader<'a, 'b, D: BlockDevice> {
     inode_ref: &'a mut InodeRef<'b, D>,
     /// 当前目录项（缓存）
     current_entry: Option<DirEntry>,
+    /// 当前目录项的起始偏移
+    current_pos: u64,
 }
 
 impl<'a, 'b, D: BlockDevice> DirReader<'a, 'b, D> {
@@ -68,15 +79,18 @@ impl<'a, 'b, D: BlockDevice> DirReader<'a, 'b, D> {
     /// let mut reader = DirReader::new(&mut inode_ref, 1024)?;
     /// ```
     pub fn new(inode_ref: &'a mut InodeRef<'b, D>, offset: u64) -> Result<Self> {
-        let mut iter = DirIterator::new(inode_ref, offset)?;
+        let mut iter = DirIterator::new(inode_ref, 0)?;
+        iter.seek(inode_ref, offset)?;
 
         // 读取第一个条目
+        let current_pos = iter.current_offset();
         let current_entry = iter.next(inode_ref)?;
 
         Ok(Self {
             iter,
             inode_ref,
             current_entry,
+            current_pos,
         })
     }
 
@@ -124,6 +138,7 @@ impl<'a, 'b, D: BlockDevice> DirReader<'a, 'b, D> {
     /// ```
     pub fn step(&mut self) -> Result<()> {
         // 读取下一个条目
+        self.current_pos = self.iter.current_offset();
         self.current_entry = self.iter.next(self.inode_ref)?;
         Ok(())
     }
@@ -132,7 +147,8 @@ impl<'a, 'b, D: BlockDevice> DirReader<'a, 'b, D> {
     ///
     /// # 返回
     ///
-    /// 当前位置的字节偏移
+    /// 当前条目之后的位置，即下一次 step() 开始读取的位置。
+    /// 适合作为 getdents 中当前条目的 `d_off`
     ///
     /// # 示例
     ///
@@ -144,11 +160,35 @@ impl<'a, 'b, D: BlockDevice> DirReader<'a, 'b, D> {
         self.iter.current_offset()
     }
 
+    /// 获取当前条目的位置
+    ///
+    /// # 返回
+    ///
+    /// 当前条目的起始偏移（已到末尾时为末尾位置）。
+    /// 把它交给 seek() 会重新读到当前条目，适合在缓冲区已满、
+    /// 当前条目尚未返回给调用者时保存位置
+    ///
+    /// # 示例
+    ///
+    /// ```ignore
+    /// while let Some(entry) = reader.current() {
+    ///     if !emit(entry, reader.offset()) {
+    ///         return Ok(reader.tell()); // 下次从此条目继续
+    ///     }
+    ///     reader.step()?;
+    /// }
+    /// ```
+    pub fn tell(&self) -> u64 {
+        self.current_pos
+    }
+
     /// 定位到指定偏移
     ///
     /// # 参数
     ///
-    /// * `offset` - 目标位置（字节偏移）
+    /// * `offset` - 目标位置，通常来自 tell() 或 offset()
+    ///
+    /// 偏移不在条目边界上时（目录已被修改）对齐到其后的第一个条目
     ///
     /// # 返回
     ///
@@ -167,6 +207,7 @@ impl<'a, 'b, D: BlockDevice> DirReader<'a, 'b, D> {
         self.iter.seek(self.inode_ref, offset)?;
 
         // 重新加载当前条目
+        self.current_pos = self.iter.current_offset();
         self.current_entry = self.iter.next(self.inode_ref)?;
 
         Ok(())
@@ -7,15 +7,15 @@
 //! ## 功能
 //!
 //! - ✅ 普通目录添加条目（支持分配新块）
+//! - ✅ 单块线性目录写满时自动转换为 HTree 目录
 //! - ✅ HTree 目录添加条目（支持叶子块分裂）
 //! - ✅ HTree 叶子块分裂
+//! - ✅ HTree 索引块逐层分裂和根节点增高
 //! - ✅ 删除目录条目
 //! - ✅ 目录校验和更新
 //!
 //! ## 限制
 //!
-//! - ⚠️ HTree 索引块满时不支持递归分裂（部分实现）
-//! - ⚠️ HTree 根节点分裂未完全集成（功能已实现）
 //! - ❌ 不支持内联数据（inline data）目录
 //!
 //! ## 使用示例
@@ -33,9 +33,10 @@
 use crate::{
     block::{Block, BlockDev, BlockDevice},
     consts::*,
-    dir::{checksum, htree},
+    dir::{checksum, htree, iterator::NameMatcher},
     error::{Error, ErrorKind, Result},
     fs::InodeRef,
+    logging::ext4_log,
     superblock::Superblock,
     types::{ext4_dir_entry, ext4_dir_entry_tail},
 };
@@ -58,7 +59,6 @@ pub const EXT4_DE_SYMLINK: u8 = 7;
 /// # 参数
 ///
 /// * `inode_ref` - 目录 inode 引用
-/// * `sb` - 可变 superblock 引用（用于可能的块分配）
 /// * `name` - 条目名称
 /// * `child_inode` - 子 inode 编号
 /// * `file_type` - 文件类型（EXT4_DE_* 常量）
@@ -73,28 +73,31 @@ pub const EXT4_DE_SYMLINK: u8 = 7;
 /// - 对于 HTree 目录，如果叶子块满了会返回 NoSpace 错误
 pub fn add_entry<D: BlockDevice>(
     inode_ref: &mut InodeRef<D>,
-    sb: &mut Superblock,
     name: &str,
     child_inode: u32,
     file_type: u8,
 ) -> Result<()> {
+    inode_ref.sb().check_writable()?;
     // 检查名称长度
-    if name.is_empty() || name.len() > 255 {
-        return Err(Error::new(
-            ErrorKind::InvalidInput,
-            "Directory entry name too long or empty",
-        ));
+    if name.is_empty() {
+        return Err(Error::new(ErrorKind::InvalidInput, "Directory entry name is empty"));
+    }
+    if name.len() > 255 {
+        return Err(Error::new(ErrorKind::NameTooLong, "Directory entry name too long"));
     }
 
+    // 无法加密名称，不能在加密目录中创建条目
+    inode_ref.check_not_encrypted()?;
+
     // 检查是否是 HTree 索引目录
     let is_htree = htree::is_indexed(inode_ref)?;
 
     if is_htree {
         // HTree 目录
-        add_entry_htree(inode_ref, sb, name, child_inode, file_type)
+        add_entry_htree(inode_ref, name, child_inode, file_type)
     } else {
         // 普通目录
-        add_entry_linear(inode_ref, sb, name, child_inode, file_type)
+        add_entry_linear(inode_ref, name, child_inode, file_type)
     }
 }
 
@@ -103,7 +106,6 @@ pub fn add_entry<D: BlockDevice>(
 /// 对应 lwext4 的线性目录处理部分
 fn add_entry_linear<D: BlockDevice>(
     inode_ref: &mut InodeRef<D>,
-    sb: &mut Superblock,
     name: &str,
     child_inode: u32,
     file_type: u8,
@@ -120,10 +122,16 @@ fn add_entry_linear<D: BlockDevice>(
         let block_addr = match inode_ref.get_inode_dblk_idx(block_idx, false) {
             Ok(addr) => addr,
             Err(_) => {
+                // 唯一的块已满：与内核一样转换为 HTree 目录，而不是追加线性块
+                #[cfg(feature = "htree-write")]
+                if block_idx == 1 && can_index_dir(inode_ref.sb()) {
+                    make_indexed_dir(inode_ref)?;
+                    return add_entry_htree(inode_ref, name, child_inode, file_type);
+                }
+
                 // 没有更多块了，需要分配新块
                 return append_new_block(
                     inode_ref,
-                    sb,
                     name,
                     child_inode,
                     file_type,
@@ -134,9 +142,9 @@ fn add_entry_linear<D: BlockDevice>(
 
         {
             // 在获取 bdev 之前提取所有需要的数据（不保留引用）
-            let has_csum = inode_ref.sb().has_ro_compat_feature(EXT4_FEATURE_RO_COMPAT_METADATA_CSUM);
+            let has_csum = leaf_csum_now(inode_ref);
             let block_size = inode_ref.sb().block_size() as usize;
-            let uuid = inode_ref.sb().inner().uuid;
+            let csum_seed = inode_ref.sb().csum_seed();
             let inode_index = inode_ref.index();
             let inode_generation = inode_ref.generation()?;
 
@@ -157,7 +165,7 @@ fn add_entry_linear<D: BlockDevice>(
                     // 成功插入，更新校验和（如果需要）
                     update_dir_block_checksum(
                         has_csum,
-                        &uuid,
+                        csum_seed,
                         inode_index,
                         inode_generation,
                         data,
@@ -185,9 +193,9 @@ fn add_entry_linear<D: BlockDevice>(
 /// Handle leaf block split and retry insertion
 ///
 /// Called when the target leaf block is full
+#[cfg(feature = "htree-write")]
 fn handle_leaf_split<D: BlockDevice>(
     inode_ref: &mut InodeRef<D>,
-    sb: &mut Superblock,
     hash_info: &htree::HTreeHashInfo,
     path: &htree::HTreePath,
     old_block_addr: u64,
@@ -195,48 +203,42 @@ fn handle_leaf_split<D: BlockDevice>(
     child_inode: u32,
     file_type: u8,
 ) -> Result<()> {
+    // 父索引块必须已有空位（由 make_index_room 保证），否则分裂叶子块后
+    // 新块无法被索引
+    let parent_info = match path.index_blocks.last() {
+        Some(parent_info) if parent_info.entry_count < parent_info.entry_limit => parent_info,
+        Some(_) => {
+            return Err(Error::new(
+                ErrorKind::NoSpace,
+                "Parent index block is full",
+            ));
+        }
+        None => {
+            return Err(Error::new(
+                ErrorKind::Corrupted,
+                "HTree path has no index block",
+            ));
+        }
+    };
+
     // Split the leaf block
     let (new_logical_block, split_hash) = htree::split_leaf_block(
         inode_ref,
-        sb,
         old_block_addr,
         hash_info,
     )?;
 
-    // Insert index entry pointing to the new block
-    // The parent is the last index block in the path
-    if let Some(parent_info) = path.index_blocks.last() {
-        // Check if parent has space for the new entry
-        if parent_info.entry_count >= parent_info.entry_limit {
-            // Parent is full, need to split index block
-            // For now, return an error (TODO: implement recursive index split)
-            return Err(Error::new(
-                ErrorKind::NoSpace,
-                "Index block is full, recursive split not yet implemented",
-            ));
-        }
+    // Insert the new index entry at position_idx + 1
+    // (right after where we found the original leaf)
+    let insert_position = parent_info.position_idx + 1;
 
-        // Insert the new index entry at position_idx + 1
-        // (right after where we found the original leaf)
-        let insert_position = parent_info.position_idx + 1;
-
-        insert_index_entry_at(
-            inode_ref,
-            parent_info.block_addr,
-            insert_position,
-            split_hash,
-            new_logical_block,
-        )?;
-    } else {
-        // No parent in path means this is a root-only tree (indirect_levels == 0)
-        // In this case, we need to do a root split which grows the tree
-        // For now, return an error (TODO: implement root growth)
-        // issue: if this means inline data?
-        return Err(Error::new(
-            ErrorKind::NoSpace,
-            "Root split not yet implemented in add_entry",
-        ));
-    }
+    insert_index_entry_at(
+        inode_ref,
+        parent_info.block_addr,
+        insert_position,
+        split_hash,
+        new_logical_block,
+    )?;
 
     // Retry the insertion: decide which block to use based on hash
     let target_block = if hash_info.hash >= split_hash {
@@ -251,9 +253,9 @@ fn handle_leaf_split<D: BlockDevice>(
     let target_block_addr = inode_ref.get_inode_dblk_idx(target_block, false)?;
 
     // Prepare data for checksum
-    let has_csum = inode_ref.sb().has_ro_compat_feature(EXT4_FEATURE_RO_COMPAT_METADATA_CSUM);
+    let has_csum = leaf_csum_now(inode_ref);
     let block_size = inode_ref.sb().block_size() as usize;
-    let uuid = inode_ref.sb().inner().uuid;
+    let csum_seed = inode_ref.sb().csum_seed();
     let inode_index = inode_ref.index();
     let inode_generation = inode_ref.generation()?;
     let required_len = calculate_entry_len(name.len() as u8);
@@ -274,7 +276,7 @@ fn handle_leaf_split<D: BlockDevice>(
         if result {
             update_dir_block_checksum(
                 has_csum,
-                &uuid,
+                csum_seed,
                 inode_index,
                 inode_generation,
                 data,
@@ -297,9 +299,51 @@ fn handle_leaf_split<D: BlockDevice>(
     Ok(())
 }
 
+/// 确保叶子块的父索引块有空位
+///
+/// 从父索引块向上找到连续已满的最高一层：若为根块则增加树高，
+/// 否则把它一分为二并在其父索引块中插入新条目。每次只处理一层，
+/// 返回 `true` 表示做了分裂，调用者需要重新查找路径
+#[cfg(feature = "htree-write")]
+fn make_index_room<D: BlockDevice>(
+    inode_ref: &mut InodeRef<D>,
+    path: &htree::HTreePath,
+) -> Result<bool> {
+    let levels = &path.index_blocks;
+    let is_full = |info: &htree::IndexBlockInfo| info.entry_count >= info.entry_limit;
+
+    match levels.last() {
+        Some(parent) if is_full(parent) => {}
+        _ => return Ok(false),
+    }
+
+    let mut top = levels.len() - 1;
+    while top > 0 && is_full(&levels[top - 1]) {
+        top -= 1;
+    }
+
+    if top == 0 {
+        // 根块已满：所有条目移到新的中间节点，树高加一
+        htree::split_index_block(inode_ref, levels[0].block_addr, true)?;
+    } else {
+        let result = htree::split_index_block(inode_ref, levels[top].block_addr, false)?;
+        let parent = &levels[top - 1];
+        insert_index_entry_at(
+            inode_ref,
+            parent.block_addr,
+            parent.position_idx + 1,
+            result.split_hash,
+            result.new_logical_block,
+        )?;
+    }
+
+    Ok(true)
+}
+
 /// Insert an index entry into an index block at a specific position
 ///
 /// Wrapper around htree module's internal function
+#[cfg(feature = "htree-write")]
 fn insert_index_entry_at<D: BlockDevice>(
     inode_ref: &mut InodeRef<D>,
     index_block_addr: u64,
@@ -339,7 +383,8 @@ fn insert_index_entry_at<D: BlockDevice>(
 
         // Calculate insertion position
         let entry_size = core::mem::size_of::<crate::types::ext4_dir_idx_entry>();
-        let insert_offset = entries_offset + core::mem::size_of::<crate::types::ext4_dir_idx_climit>() + entry_size * insert_position;
+        // climit 占据 entries[0] 的 hash 字段，条目数组从 climit 处开始
+        let insert_offset = entries_offset + entry_size * insert_position;
         let old_entry_ptr = unsafe { data.as_ptr().add(insert_offset) };
         let new_entry_ptr = unsafe { data.as_mut_ptr().add(insert_offset + entry_size) };
 
@@ -379,12 +424,12 @@ fn insert_index_entry_at<D: BlockDevice>(
 ///
 /// 对应 lwext4 的 `ext4_dir_dx_add_entry()`
 ///
-/// 支持叶子块分裂。当叶子块满时自动分裂并重试插入。
-///
-/// ⚠️ **部分限制**：索引块满时不支持递归分裂（返回错误）
+/// 支持叶子块分裂。当叶子块满时自动分裂并重试插入；叶子块的父索引块
+/// 也满时逐层分裂索引块，根块满时增加树高（`indirect_levels` 最多到 1，
+/// 有 `LARGEDIR` 特性时到 2）。
+#[cfg_attr(not(feature = "htree-write"), allow(unused_variables))]
 fn add_entry_htree<D: BlockDevice>(
     inode_ref: &mut InodeRef<D>,
-    sb: &mut Superblock,
     name: &str,
     child_inode: u32,
     file_type: u8,
@@ -392,8 +437,26 @@ fn add_entry_htree<D: BlockDevice>(
     // 计算哈希值
     let hash_info = htree::init_hash_info(inode_ref, name)?;
 
+    loop {
+        if add_entry_htree_once(inode_ref, &hash_info, name, child_inode, file_type)? {
+            return Ok(());
+        }
+    }
+}
+
+/// 查找路径并尝试插入一次
+///
+/// 返回 `false` 表示分裂了一层索引块，需要重新查找路径再试
+#[cfg_attr(not(feature = "htree-write"), allow(unused_variables))]
+fn add_entry_htree_once<D: BlockDevice>(
+    inode_ref: &mut InodeRef<D>,
+    hash_info: &htree::HTreeHashInfo,
+    name: &str,
+    child_inode: u32,
+    file_type: u8,
+) -> Result<bool> {
     // 找到目标叶子块及其路径
-    let path = htree::get_leaf_with_path(inode_ref, &hash_info)?;
+    let path = htree::get_leaf_with_path(inode_ref, hash_info)?;
     let leaf_block_idx = path.leaf_block;
 
     // 获取物理块地址
@@ -403,9 +466,9 @@ fn add_entry_htree<D: BlockDevice>(
     let required_len = calculate_entry_len(name.len() as u8);
 
     // 在获取 bdev 之前提取所有需要的数据
-    let has_csum = inode_ref.sb().has_ro_compat_feature(EXT4_FEATURE_RO_COMPAT_METADATA_CSUM);
+    let has_csum = leaf_csum_now(inode_ref);
     let block_size = inode_ref.sb().block_size() as usize;
-    let uuid = inode_ref.sb().inner().uuid;
+    let csum_seed = inode_ref.sb().csum_seed();
     let inode_index = inode_ref.index();
     let inode_generation = inode_ref.generation()?;
 
@@ -426,7 +489,7 @@ fn add_entry_htree<D: BlockDevice>(
             // 成功插入，更新校验和
             update_dir_block_checksum(
                 has_csum,
-                &uuid,
+                csum_seed,
                 inode_index,
                 inode_generation,
                 data,
@@ -440,20 +503,32 @@ fn add_entry_htree<D: BlockDevice>(
     drop(block);
 
     if !insert_result {
-        // 叶子块满了，需要分裂
-        handle_leaf_split(
-            inode_ref,
-            sb,
-            &hash_info,
-            &path,
-            block_addr,
-            name,
-            child_inode,
-            file_type,
-        )?;
+        // 叶子块满了，需要分裂；先保证父索引块有空位
+        #[cfg(feature = "htree-write")]
+        {
+            if make_index_room(inode_ref, &path)? {
+                return Ok(false);
+            }
+
+            handle_leaf_split(
+                inode_ref,
+                hash_info,
+                &path,
+                block_addr,
+                name,
+                child_inode,
+                file_type,
+            )?;
+        }
+
+        #[cfg(not(feature = "htree-write"))]
+        return Err(Error::new(
+            ErrorKind::Unsupported,
+            "HTree leaf block is full and htree-write support is disabled",
+        ));
     }
 
-    Ok(())
+    Ok(true)
 }
 
 /// 在块中查找空闲空间并插入条目
@@ -471,7 +546,7 @@ fn find_and_insert_entry(
     let mut offset = 0;
     let mut entries_checked = 0;
 
-    log::trace!(
+    ext4_log!(Dir, Trace,
         "[find_and_insert_entry] START: name='{}', required_len={}, block_size={}",
         name,
         required_len,
@@ -513,7 +588,7 @@ fn find_and_insert_entry(
 
         // 检查是否有足够的空闲空间
         if free_space >= required_len {
-            log::trace!(
+            ext4_log!(Dir, Trace,
                 "[find_and_insert_entry] FOUND SPACE: offset={}, rec_len={}, actual_len={}, free_space={}, required_len={}, entry_inode={}, entries_checked={}",
                 offset,
                 rec_len,
@@ -552,7 +627,7 @@ fn find_and_insert_entry(
         offset += rec_len as usize;
     }
 
-    log::trace!(
+    ext4_log!(Dir, Trace,
         "[find_and_insert_entry] NO SPACE: name='{}', entries_checked={}, final_offset={}",
         name,
         entries_checked,
@@ -620,12 +695,11 @@ fn write_entry(
 
 /// 分配新的目录块并添加条目
 ///
-/// ⚠️ **重要**: 此函数需要可变的 Superblock 引用用于块分配
+/// 块分配通过 `inode_ref` 持有的 superblock 进行
 ///
 /// # 参数
 ///
 /// * `inode_ref` - 目录 inode 引用
-/// * `sb` - 可变 superblock 引用（用于块分配）
 /// * `name` - 条目名称
 /// * `child_inode` - 子 inode 编号
 /// * `file_type` - 文件类型
@@ -645,40 +719,34 @@ fn write_entry(
 /// 完整的实现需要支持 extent 树的插入和分裂操作
 pub fn append_new_block<D: BlockDevice>(
     inode_ref: &mut InodeRef<D>,
-    sb: &mut Superblock,
     name: &str,
     child_inode: u32,
     file_type: u8,
     required_len: u16,
 ) -> Result<()> {
-
-    let block_size = sb.block_size();
-    let has_csum = sb.has_ro_compat_feature(EXT4_FEATURE_RO_COMPAT_METADATA_CSUM);
+    inode_ref.sb().check_writable()?;
+    let block_size = inode_ref.sb().block_size();
+    let has_csum = inode_ref.sb().has_ro_compat_feature(EXT4_FEATURE_RO_COMPAT_METADATA_CSUM);
 
     // 计算下一个逻辑块号
     let current_size = inode_ref.size()?;
     let logical_block = (current_size / block_size as u64) as u32;
 
-    // 使用 extent::get_blocks() 分配新块并更新 extent tree
+    // 通过 get_inode_dblk_idx 分配新块（extent 或间接块映射）
     // 这会自动处理：
     // 1. 分配物理块
-    // 2. 更新 extent tree（添加新的 extent 或扩展现在 extent）
+    // 2. 更新 extent tree 或间接块指针
     // 3. 更新 inode 的 blocks 计数
-    use crate::extent::get_blocks;
-    use crate::balloc::BlockAllocator;
-
-    let mut allocator = BlockAllocator::new();
-
-    log::info!("[append_new_block] Allocating logical block {} for inode {}",
+    ext4_log!(Dir, hot, "[append_new_block] Allocating logical block {} for inode {}",
                logical_block, inode_ref.index());
 
-    let (new_block_addr, _count) = get_blocks(inode_ref, sb, &mut allocator, logical_block, 1, true)?;
+    let new_block_addr = inode_ref.get_inode_dblk_idx(logical_block, true)?;
 
-    log::info!("[append_new_block] Allocated physical block {} for logical block {}",
+    ext4_log!(Dir, hot, "[append_new_block] Allocated physical block {} for logical block {}",
                new_block_addr, logical_block);
 
     // 初始化新块
-    let uuid = sb.inner().uuid;
+    let csum_seed = inode_ref.sb().csum_seed();
     let dir_inode = inode_ref.index();
     let inode_generation = inode_ref.generation()?;
 
@@ -710,7 +778,7 @@ pub fn append_new_block<D: BlockDevice>(
             // 更新校验和
             update_dir_block_checksum(
                 has_csum,
-                &uuid,
+                csum_seed,
                 dir_inode,
                 inode_generation,
                 data,
@@ -728,38 +796,59 @@ pub fn append_new_block<D: BlockDevice>(
     Ok(())
 }
 
-/// 初始化新目录（创建 . 和 .. 条目）
+/// 初始化新目录
 ///
-/// 对应 lwext4 中创建新目录时调用 `ext4_dir_add_entry()` 两次
+/// 新目录的唯一初始化入口（`create_dir` 和 `create_in_dir` 共用），
+/// 对应 lwext4 中 `ext4_dir_add_entry()` 两次 / `ext4_dir_dx_init()`
 ///
 /// # 参数
 ///
-/// * `dir_inode_ref` - 目录自身的 inode 引用
+/// * `dir_inode_ref` - 目录自身的 inode 引用（大小必须为 0）
 /// * `parent_inode` - 父目录的 inode 编号
+/// * `indexed` - 是否希望创建 HTree 索引目录
+///
+/// # 实现说明
 ///
-/// # 前提条件
+/// 所需的块都在这里显式分配，不依赖调用者预先分配：
+/// - 线性目录：块 0 包含 `.`（12 字节）和占据剩余空间的 `..`
+/// - 索引目录：块 0 为 dx root，块 1 为空叶子块，并设置
+///   `EXT4_INODE_FLAG_INDEX`
 ///
-/// - 目录至少有一个块已分配
-/// - inode size >= block_size
+/// 只有在 `indexed` 为真、启用 `htree-write` 特性且文件系统支持
+/// `DIR_INDEX` 时才创建索引目录。索引块校验和尚未实现，因此启用
+/// `METADATA_CSUM` 时总是创建线性目录。
 ///
-/// # 实现说明
+/// # 错误
 ///
-/// 在目录的第一个块中创建：
-/// - `.` 条目（指向自己）
-/// - `..` 条目（指向父目录）
-/// issue: 默认block1已分配， 需要检查是否需要优化当前函数以移除默认条件， 或者将该逻辑分发到其他函数， 但要能够确保目录至少有一个块已分配
+/// - `ErrorKind::InvalidInput` - 目录已经有内容
+/// - `ErrorKind::NoSpace` - 无法分配目录块
 pub fn dir_init<D: BlockDevice>(
     dir_inode_ref: &mut InodeRef<D>,
     parent_inode: u32,
+    indexed: bool,
 ) -> Result<()> {
+    dir_inode_ref.sb().check_writable()?;
+    if dir_inode_ref.size()? != 0 {
+        return Err(Error::new(
+            ErrorKind::InvalidInput,
+            "Directory is already initialized",
+        ));
+    }
+
+    #[cfg(feature = "htree-write")]
+    if indexed && can_index_dir(dir_inode_ref.sb()) {
+        return dx_init(dir_inode_ref, parent_inode);
+    }
+    #[cfg(not(feature = "htree-write"))]
+    let _ = indexed;
+
     let block_size = dir_inode_ref.sb().block_size();
     let has_csum = dir_inode_ref.sb().has_ro_compat_feature(EXT4_FEATURE_RO_COMPAT_METADATA_CSUM);
 
-    // 获取或分配第一个块（新目录需要创建块）
-    let block_addr = dir_inode_ref.get_inode_dblk_idx(0, true)?;
+    let block_addr = alloc_dir_block(dir_inode_ref, 0)?;
 
     // 提取需要的数据
-    let uuid = dir_inode_ref.sb().inner().uuid;
+    let csum_seed = dir_inode_ref.sb().csum_seed();
     let dir_inode = dir_inode_ref.index();
     let inode_generation = dir_inode_ref.generation()?;
 
@@ -797,7 +886,7 @@ pub fn dir_init<D: BlockDevice>(
             // 更新校验和
             update_dir_block_checksum(
                 has_csum,
-                &uuid,
+                csum_seed,
                 dir_inode,
                 inode_generation,
                 data,
@@ -814,128 +903,158 @@ pub fn dir_init<D: BlockDevice>(
     Ok(())
 }
 
-/// 初始化 HTree 索引目录
+/// 分配目录的第 `lblk` 个逻辑块（映射到 extent 树并计入 i_blocks）
+fn alloc_dir_block<D: BlockDevice>(dir_inode_ref: &mut InodeRef<D>, lblk: u32) -> Result<u64> {
+    let block_addr = dir_inode_ref.get_inode_dblk_idx(lblk, true)?;
+    if block_addr == 0 {
+        return Err(Error::new(ErrorKind::NoSpace, "Failed to allocate directory block"));
+    }
+    Ok(block_addr)
+}
+
+/// 新建或转换的目录能否使用 HTree 布局
+#[cfg(feature = "htree-write")]
+fn can_index_dir(sb: &Superblock) -> bool {
+    sb.has_compat_feature(EXT4_FEATURE_COMPAT_DIR_INDEX)
+        && !sb.has_ro_compat_feature(EXT4_FEATURE_RO_COMPAT_METADATA_CSUM)
+}
+
+/// 初始化 HTree 索引目录（由 [`dir_init`] 调用）
 ///
 /// 对应 lwext4 的 `ext4_dir_dx_init()`
 ///
-/// # 参数
-///
-/// * `dir_inode_ref` - 目录自身的 inode 引用
-/// * `parent_inode` - 父目录的 inode 编号
-///
-/// # 前提条件
-///
-/// - 目录至少有一个块已分配
-/// - 文件系统支持 DIR_INDEX 特性
-///
-/// # 实现说明
-///
-/// 在块 0 创建 HTree 根节点结构，包括：
-/// - `.` 和 `..` 条目（作为 dot entries）
-/// - 根节点信息（hash 版本、间接层级等）
-/// - 索引条目数组
-///
-/// ⚠️ **简化实现**：不自动分配第一个叶子块（块 1）
-/// 叶子块应由调用者在创建目录后立即分配
-/// issue: 1.初始化逻辑不完整 2.这个函数还没有被实际应用到mkdir的逻辑中， 也就是根本还没有被调用过 3.简化实现， 默认block1已经分配， 亟待后续优化
-pub fn dx_init<D: BlockDevice>(
+/// 块 0 为 dx root：`.`/`..` dot entries、根信息、count/limit
+/// 以及指向块 1 的唯一索引条目；块 1 为只含一个空目录项的叶子块
+#[cfg(feature = "htree-write")]
+fn dx_init<D: BlockDevice>(
     dir_inode_ref: &mut InodeRef<D>,
     parent_inode: u32,
 ) -> Result<()> {
-
     let block_size = dir_inode_ref.sb().block_size();
-    let has_csum = dir_inode_ref.sb().has_ro_compat_feature(EXT4_FEATURE_RO_COMPAT_METADATA_CSUM);
-
-    // 获取或分配第一个块（根块）（新目录需要创建块）
-    let block_addr = dir_inode_ref.get_inode_dblk_idx(0, true)?;
-
-    // 提取需要的数据
-    let uuid = dir_inode_ref.sb().inner().uuid;
-    let dir_inode = dir_inode_ref.index();
-    let inode_generation = dir_inode_ref.generation()?;
     let hash_version = dir_inode_ref.sb().inner().def_hash_version;
+    let limit = htree::calculate_entry_space(block_size, dir_inode_ref.sb()) as u16;
+    let dir_inode = dir_inode_ref.index();
 
-    let bdev = dir_inode_ref.bdev();
-    let mut block = Block::get_noread(bdev, block_addr)?;
+    let root_addr = alloc_dir_block(dir_inode_ref, 0)?;
+    let leaf_addr = alloc_dir_block(dir_inode_ref, 1)?;
 
-    block.with_data_mut(|data| {
-        // 清零整个块
-        data.fill(0);
+    {
+        let mut block = Block::get_noread(dir_inode_ref.bdev(), root_addr)?;
+        block.with_data_mut(|data| write_dx_root(data, dir_inode, parent_inode, hash_version, limit))?;
+    }
 
-        // 1. 创建 . 和 .. 条目（作为特殊的 dot entries）
-        // . 条目：12 字节
-        write_entry(data, 0, ".", dir_inode, EXT4_DE_DIR, 12);
+    {
+        let mut block = Block::get_noread(dir_inode_ref.bdev(), leaf_addr)?;
+        block.with_data_mut(|data| {
+            data.fill(0);
+            write_entry(data, 0, "", 0, EXT4_DE_UNKNOWN, block_size as u16);
+        })?;
+    }
 
-        // .. 条目：占据到索引信息之前的空间
-        let dotdot_len = block_size - 12;
-        write_entry(data, 12, "..", parent_inode, EXT4_DE_DIR, dotdot_len as u16);
+    dir_inode_ref.with_inode_mut(|inode| {
+        let flags = u32::from_le(inode.flags);
+        inode.flags = (flags | EXT4_INODE_FLAG_INDEX).to_le();
+    })?;
+    dir_inode_ref.set_size(2 * block_size as u64)?;
 
-        // 2. 初始化 HTree 根信息
-        // 根信息位于 . 和 .. 之后
-        // 每个 dot entry 是 12 字节（见 lwext4）
-        let root_info_offset = 12 + 12;
+    Ok(())
+}
 
-        // hash_version (1 byte) at offset
-        data[root_info_offset] = hash_version;
-        // info_length (1 byte) = 8
-        data[root_info_offset + 1] = 8;
-        // indirect_levels (1 byte) = 0
-        data[root_info_offset + 2] = 0;
-        // unused (1 byte) = 0
-        data[root_info_offset + 3] = 0;
+/// 写入 dx root 块：`.`/`..`、根信息，以及指向块 1 的唯一索引条目
+#[cfg(feature = "htree-write")]
+fn write_dx_root(data: &mut [u8], dir_inode: u32, parent_inode: u32, hash_version: u8, limit: u16) {
+    use crate::types::{ext4_dir_idx_climit, ext4_dir_idx_root};
+
+    let block_size = data.len();
+    data.fill(0);
+
+    // 1. dot entries：".." 的 rec_len 覆盖块的剩余部分
+    write_entry(data, 0, ".", dir_inode, EXT4_DE_DIR, 12);
+    write_entry(data, 12, "..", parent_inode, EXT4_DE_DIR, (block_size - 12) as u16);
+
+    // 2. 根信息
+    let root = unsafe { &mut *(data.as_mut_ptr() as *mut ext4_dir_idx_root) };
+    root.info.reserved_zero = 0;
+    root.info.hash_version = hash_version;
+    root.info.info_length = 8;
+    root.info.indirect_levels = 0;
+    root.info.unused_flags = 0;
+
+    // 3. climit 覆盖 entries[0].hash，entries[0].block 紧随其后
+    let climit_offset = core::mem::size_of::<ext4_dir_idx_root>();
+    let climit = unsafe {
+        &mut *(data[climit_offset..].as_mut_ptr() as *mut ext4_dir_idx_climit)
+    };
+    climit.limit = limit.to_le();
+    climit.count = 1_u16.to_le();
+    data[climit_offset + 4..climit_offset + 8].copy_from_slice(&1_u32.to_le_bytes());
+}
 
-        // 3. 设置索引条目限制和计数
-        let entries_offset = root_info_offset + 8; // info_length = 8
+/// 将只有一个块的线性目录转换为 HTree 目录
+///
+/// 对应内核的 `make_indexed_dir()`
+///
+/// `.`/`..` 之后的所有目录项原样移到新分配的块 1，最后一项的 rec_len
+/// 延伸到块尾；块 0 改写为 dx root，唯一的索引条目指向块 1。
+/// 块 1 已满时由随后的 HTree 插入负责分裂。
+#[cfg(feature = "htree-write")]
+fn make_indexed_dir<D: BlockDevice>(inode_ref: &mut InodeRef<D>) -> Result<()> {
+    let block_size = inode_ref.sb().block_size() as usize;
+    let hash_version = inode_ref.sb().inner().def_hash_version;
+    let limit = htree::calculate_entry_space(block_size as u32, inode_ref.sb()) as u16;
+    let dir_inode = inode_ref.index();
 
-        // 计算可用空间
-        let entry_space = if has_csum {
-            block_size as usize - entries_offset - core::mem::size_of::<ext4_dir_entry_tail>()
-        } else {
-            block_size as usize - entries_offset
-        };
+    let root_addr = inode_ref.get_inode_dblk_idx(0, false)?;
+    let mut old = alloc::vec![0u8; block_size];
+    Block::get(inode_ref.bdev(), root_addr)?.with_data(|data| old.copy_from_slice(data))?;
 
-        // 每个索引条目 8 字节（hash(4) + block(4)）
-        let max_entries = (entry_space / 8) as u16;
-
-        // count_limit 结构（4 字节）：limit(2) + count(2)
-        let limit_offset = entries_offset;
-        // limit
-        data[limit_offset..limit_offset + 2].copy_from_slice(&max_entries.to_le_bytes());
-        // count = 1 (将有一个指向块 1 的索引条目，需要调用者后续添加)
-        data[limit_offset + 2..limit_offset + 4].copy_from_slice(&1_u16.to_le_bytes());
-
-        // 4. 添加第一个索引条目（hash=0, block=1）
-        // 注意：这假设块 1 会被分配，但我们不在这里分配它
-        let first_entry_offset = entries_offset + 4; // 跳过 count_limit
-        // hash (4 bytes) = 0
-        data[first_entry_offset..first_entry_offset + 4].copy_from_slice(&0_u32.to_le_bytes());
-        // block (4 bytes) = 1
-        data[first_entry_offset + 4..first_entry_offset + 8].copy_from_slice(&1_u32.to_le_bytes());
-
-        // 5. 如果需要校验和，初始化尾部
-        if has_csum {
-            let tail_offset = block_size as usize - core::mem::size_of::<ext4_dir_entry_tail>();
-            let tail = unsafe {
-                &mut *(data[tail_offset..].as_mut_ptr() as *mut ext4_dir_entry_tail)
-            };
-            checksum::init_entry_tail(tail);
+    // 定位 ".." 之后的第一个目录项
+    let dotdot = u16::from_le_bytes([old[4], old[5]]) as usize;
+    if dotdot < 12 || dotdot + 12 > block_size {
+        return Err(Error::new(ErrorKind::Corrupted, "Directory dot entries are invalid"));
+    }
+    let parent_inode = u32::from_le_bytes([old[dotdot], old[dotdot + 1], old[dotdot + 2], old[dotdot + 3]]);
+    let dotdot_len = u16::from_le_bytes([old[dotdot + 4], old[dotdot + 5]]) as usize;
+    let start = dotdot + dotdot_len;
+    if start > block_size {
+        return Err(Error::new(ErrorKind::Corrupted, "Directory entry rec_len extends beyond block"));
+    }
 
-            // 更新校验和
-            update_dir_block_checksum(
-                has_csum,
-                &uuid,
-                dir_inode,
-                inode_generation,
-                data,
-                block_size as usize,
-            );
-        }
-    })?;
+    let leaf_addr = alloc_dir_block(inode_ref, 1)?;
+    {
+        let mut block = Block::get_noread(inode_ref.bdev(), leaf_addr)?;
+        block.with_data_mut(|data| {
+            data.fill(0);
+            let len = block_size - start;
+            if len == 0 {
+                write_entry(data, 0, "", 0, EXT4_DE_UNKNOWN, block_size as u16);
+                return;
+            }
+            data[..len].copy_from_slice(&old[start..]);
+
+            // 找到最后一项，让它覆盖块的剩余部分
+            let mut offset = 0;
+            loop {
+                let rec_len = u16::from_le_bytes([data[offset + 4], data[offset + 5]]) as usize;
+                if rec_len == 0 || offset + rec_len >= len {
+                    break;
+                }
+                offset += rec_len;
+            }
+            data[offset + 4..offset + 6].copy_from_slice(&((block_size - offset) as u16).to_le_bytes());
+        })?;
+    }
 
-    drop(block);
+    {
+        let mut block = Block::get(inode_ref.bdev(), root_addr)?;
+        block.with_data_mut(|data| write_dx_root(data, dir_inode, parent_inode, hash_version, limit))?;
+    }
 
-    // 更新目录 inode 的 size（一个块）
-    dir_inode_ref.set_size(block_size as u64)?;
+    inode_ref.with_inode_mut(|inode| {
+        let flags = u32::from_le(inode.flags);
+        inode.flags = (flags | EXT4_INODE_FLAG_INDEX).to_le();
+    })?;
+    inode_ref.set_size(2 * block_size as u64)?;
 
     Ok(())
 }
@@ -947,12 +1066,22 @@ fn calculate_entry_len(name_len: u8) -> u16 {
     ((base_len + 7) & !7) as u16
 }
 
+/// 在已有叶子块中插入目录项后是否立即更新校验和
+///
+/// 批量操作期间只记录目录，结束时统一重算（见 [`Superblock::defer_dir_csum`]）
+fn leaf_csum_now<D: BlockDevice>(inode_ref: &mut InodeRef<D>) -> bool {
+    let dir = inode_ref.index();
+    inode_ref.sb().has_ro_compat_feature(EXT4_FEATURE_RO_COMPAT_METADATA_CSUM)
+        && !inode_ref.superblock_mut().defer_dir_csum(dir)
+}
+
 /// 更新目录块校验和（不需要 InodeRef 的版本）
 ///
-/// 这个版本接受提前提取的标量数据，避免与 bdev() 的可变借用冲突
+/// 这个版本接受提前提取的标量数据，避免与 bdev() 的可变借用冲突。
+/// `csum_seed` 为文件系统的校验和种子（[`Superblock::csum_seed`]）
 pub(super) fn update_dir_block_checksum(
     has_csum: bool,
-    uuid: &[u8; 16],
+    csum_seed: u32,
     inode_index: u32,
     inode_generation: u32,
     data: &mut [u8],
@@ -965,36 +1094,15 @@ pub(super) fn update_dir_block_checksum(
     // 手动计算校验和（不使用 InodeRef）
     #[cfg(feature = "metadata-csum")]
     {
-        const EXT4_CRC32_INIT: u32 = 0xFFFFFFFF;
-
-        // 计算尾部偏移量
-        let tail_offset = block_size - core::mem::size_of::<ext4_dir_entry_tail>();
-
-        // 1. 计算 UUID 的校验和
-        let mut csum = crate::crc::crc32c_append(EXT4_CRC32_INIT, uuid);
-
-        // 2. 计算 inode 号的校验和
-        let ino_index = inode_index.to_le_bytes();
-        csum = crate::crc::crc32c_append(csum, &ino_index);
-
-        // 3. 计算 inode generation 的校验和
-        let ino_gen = inode_generation.to_le_bytes();
-        csum = crate::crc::crc32c_append(csum, &ino_gen);
-
-        // 4. 计算目录项数据的校验和（不包含尾部）
-        let dirent_data = &data[..tail_offset];
-        csum = crate::crc::crc32c_append(csum, dirent_data);
-
-        // 5. 设置校验和到尾部
-        if let Some(tail) = checksum::get_tail_mut(data, block_size) {
-            tail.set_checksum(csum);
-        }
+        let mut seed = crate::crc::crc32c_append(csum_seed, &inode_index.to_le_bytes());
+        seed = crate::crc::crc32c_append(seed, &inode_generation.to_le_bytes());
+        checksum::set_leaf_csum(seed, data, block_size);
     }
 
     #[cfg(not(feature = "metadata-csum"))]
     {
         // 无操作
-        let _ = (uuid, inode_index, inode_generation, data, block_size);
+        let _ = (csum_seed, inode_index, inode_generation, data, block_size);
     }
 }
 
@@ -1002,6 +1110,10 @@ pub(super) fn update_dir_block_checksum(
 ///
 /// 对应 lwext4 的 `ext4_dir_remove_entry()`
 ///
+/// HTree 目录按名称哈希定位候选叶子块（包括哈希冲突的后续叶子块），
+/// 只在这些块中查找；索引无法解析时与查找路径一样退回线性扫描。
+/// 普通目录线性扫描所有块。
+///
 /// # 参数
 ///
 /// * `inode_ref` - 目录 inode 引用
@@ -1010,61 +1122,102 @@ pub(super) fn update_dir_block_checksum(
 /// # 返回
 ///
 /// 成功返回 Ok(())，条目不存在返回 NotFound 错误
-/// issue: 这里直接采用遍历所有逻辑块，然后从逻辑块中查找匹配目录项， 有待优化, 应该向lwext4的是实现， 使用上hashinfo
 pub fn remove_entry<D: BlockDevice>(
     inode_ref: &mut InodeRef<D>,
     name: &str,
 ) -> Result<()> {
-    // 遍历目录块查找条目
-    let mut block_idx = 0_u32;
-    loop {
-        let block_addr = match inode_ref.get_inode_dblk_idx(block_idx, false) {
-            Ok(addr) => addr,
-            Err(_) => {
-                // 遍历完所有块，没找到
-                return Err(Error::new(
-                    ErrorKind::NotFound,
-                    "Directory entry not found",
-                ));
-            }
-        };
-
-        // 在获取 bdev 之前提取所有需要的数据
-        let has_csum = inode_ref.sb().has_ro_compat_feature(EXT4_FEATURE_RO_COMPAT_METADATA_CSUM);
-        let block_size = inode_ref.sb().block_size() as usize;
-        let uuid = inode_ref.sb().inner().uuid;
-        let inode_index = inode_ref.index();
-        let inode_generation = inode_ref.generation()?;
-
-        let bdev = inode_ref.bdev();
-        let mut block = Block::get(bdev, block_addr)?;
-
-        let found = block.with_data_mut(|data| {
-            let result = remove_entry_from_block(data, name);
-
-            if result {
-                // 删除成功，更新校验和
-                update_dir_block_checksum(
-                    has_csum,
-                    &uuid,
-                    inode_index,
-                    inode_generation,
-                    data,
-                    block_size,
-                );
+    inode_ref.sb().check_writable()?;
+    // "." 和 ".." 总在块 0（HTree 目录的根块）中，不参与哈希索引
+    let found = if name == "." || name == ".." {
+        remove_entry_in_block(inode_ref, 0, name)?
+    } else if htree::is_indexed(inode_ref)? {
+        match remove_entry_htree(inode_ref, name) {
+            Ok(found) => found,
+            Err(e) if matches!(e.kind(), ErrorKind::Corrupted | ErrorKind::Unsupported) => {
+                ext4_log!(Dir, Warn, "[HTREE] index lookup failed ({e:?}), falling back to linear scan");
+                remove_entry_linear(inode_ref, name)?
             }
+            Err(e) => return Err(e),
+        }
+    } else {
+        remove_entry_linear(inode_ref, name)?
+    };
 
-            result
-        })?;
+    if found {
+        Ok(())
+    } else {
+        Err(Error::new(
+            ErrorKind::NotFound,
+            "Directory entry not found",
+        ))
+    }
+}
 
-        drop(block);
+/// 通过 HTree 索引找到候选叶子块并删除条目
+fn remove_entry_htree<D: BlockDevice>(inode_ref: &mut InodeRef<D>, name: &str) -> Result<bool> {
+    let hash_info = htree::init_hash_info(inode_ref, name)?;
+    let mut path = htree::get_leaf_with_path(inode_ref, &hash_info)?;
 
-        if found {
-            return Ok(());
+    loop {
+        if remove_entry_in_block(inode_ref, path.leaf_block, name)? {
+            return Ok(true);
+        }
+        if !htree::next_leaf_block(inode_ref, &mut path, hash_info.hash)? {
+            return Ok(false);
         }
+    }
+}
 
-        block_idx += 1;
+/// 遍历目录的所有块删除条目
+fn remove_entry_linear<D: BlockDevice>(inode_ref: &mut InodeRef<D>, name: &str) -> Result<bool> {
+    let block_size = inode_ref.sb().block_size() as u64;
+    let blocks = inode_ref.size()?.div_ceil(block_size) as u32;
+
+    for block_idx in 0..blocks {
+        if remove_entry_in_block(inode_ref, block_idx, name)? {
+            return Ok(true);
+        }
     }
+
+    Ok(false)
+}
+
+/// 在目录的第 `block_idx` 个逻辑块中删除条目并更新校验和
+fn remove_entry_in_block<D: BlockDevice>(
+    inode_ref: &mut InodeRef<D>,
+    block_idx: u32,
+    name: &str,
+) -> Result<bool> {
+    let matcher = NameMatcher::new(inode_ref, name)?;
+    let block_addr = inode_ref.get_inode_dblk_idx(block_idx, false)?;
+
+    // 在获取 bdev 之前提取所有需要的数据
+    let has_csum = inode_ref.sb().has_ro_compat_feature(EXT4_FEATURE_RO_COMPAT_METADATA_CSUM);
+    let block_size = inode_ref.sb().block_size() as usize;
+    let csum_seed = inode_ref.sb().csum_seed();
+    let inode_index = inode_ref.index();
+    let inode_generation = inode_ref.generation()?;
+
+    let bdev = inode_ref.bdev();
+    let mut block = Block::get(bdev, block_addr)?;
+
+    block.with_data_mut(|data| {
+        let result = remove_entry_from_block(data, &matcher);
+
+        if result {
+            // 删除成功，更新校验和
+            update_dir_block_checksum(
+                has_csum,
+                csum_seed,
+                inode_index,
+                inode_generation,
+                data,
+                block_size,
+            );
+        }
+
+        result
+    })
 }
 
 /// 从块中删除条目
@@ -1072,7 +1225,7 @@ pub fn remove_entry<D: BlockDevice>(
 /// # 返回
 ///
 /// 找到并删除返回 true，未找到返回 false
-fn remove_entry_from_block(data: &mut [u8], name: &str) -> bool {
+fn remove_entry_from_block(data: &mut [u8], matcher: &NameMatcher) -> bool {
     let mut prev_offset: Option<usize> = None;
     let mut offset = 0;
 
@@ -1100,7 +1253,7 @@ fn remove_entry_from_block(data: &mut [u8], name: &str) -> bool {
             if name_offset + entry_name_len <= data.len() {
                 let entry_name = &data[name_offset..name_offset + entry_name_len];
 
-                if entry_name == name.as_bytes() {
+                if matcher.matches_raw(entry_name) {
                     // 找到了，删除它
                     if let Some(prev_off) = prev_offset {
                         // 合并到前一个条目
@@ -1146,6 +1299,61 @@ mod tests {
         assert_eq!(calculate_entry_len(8), 24);
     }
 
+    #[cfg(feature = "htree-write")]
+    #[test]
+    fn test_write_dx_root_layout() {
+        let mut data = alloc::vec![0xffu8; 4096];
+        write_dx_root(&mut data, 12, 2, 1, 508);
+
+        // ".." 的 rec_len 覆盖块的剩余部分，parent 保存在其中
+        assert_eq!(u16::from_le_bytes([data[4], data[5]]), 12);
+        assert_eq!(u32::from_le_bytes([data[12], data[13], data[14], data[15]]), 2);
+        assert_eq!(u16::from_le_bytes([data[16], data[17]]), 4096 - 12);
+
+        // climit: limit 508, count 1, entries[0].block = 1
+        assert_eq!(data[28], 1);
+        assert_eq!(u16::from_le_bytes([data[32], data[33]]), 508);
+        assert_eq!(u16::from_le_bytes([data[34], data[35]]), 1);
+        assert_eq!(u32::from_le_bytes([data[36], data[37], data[38], data[39]]), 1);
+        assert!(data[40..].iter().all(|&b| b == 0));
+    }
+
+    #[cfg(feature = "htree-write")]
+    #[test]
+    fn test_remove_entry_htree() {
+        use crate::testing::image;
+
+        let mut fs = image::mount(image::image());
+        let dir = fs.create_dir("/", "dir", 0o755).unwrap();
+        let file = fs.create_file("/", "file", 0o644).unwrap();
+        let name = |i: u32| alloc::format!("entry-with-a-fairly-long-name-{i:05}");
+
+        // 约 85 个目录项一个块，600 个名字需要多个叶子块
+        const COUNT: u32 = 600;
+        for i in 0..COUNT {
+            fs.link_inode(dir, &name(i), file).unwrap();
+        }
+        assert!(htree::is_indexed(&mut fs.get_inode_ref(dir).unwrap()).unwrap());
+        let blocks = fs.metadata("/dir").unwrap().size / 4096;
+        assert!(blocks > 3, "only {blocks} directory blocks");
+
+        for i in (0..COUNT).filter(|i| i % 3 != 0) {
+            fs.remove_file("/dir", &name(i)).unwrap();
+        }
+
+        for i in 0..COUNT {
+            let found = fs.lookup_in_dir(dir, &name(i));
+            if i % 3 == 0 {
+                assert_eq!(found.unwrap(), file, "{}", name(i));
+            } else {
+                assert_eq!(found.unwrap_err().kind(), ErrorKind::NotFound, "{}", name(i));
+            }
+        }
+        assert_eq!(fs.remove_file("/dir", &name(1)).unwrap_err().kind(), ErrorKind::NotFound);
+        assert_eq!(fs.read_dir("/dir").unwrap().len() as u32, COUNT.div_ceil(3) + 2);
+        assert_eq!(fs.metadata("/file").unwrap().links_count as u32, COUNT.div_ceil(3) + 1);
+    }
+
     #[test]
     fn test_dir_entry_constants() {
         assert_eq!(EXT4_DE_REG_FILE, 1);
@@ -0,0 +1,200 @@
+//! 磁盘格式结构
+//!
+//! 集中列出 ext4 和 JBD2 的全部磁盘结构，供检查、制作镜像等工具直接解析
+//! 抓取到的块，而不需要挂载文件系统。
+//!
+//! 这些结构都是 `#[repr(C)]` 的纯数据类型，字段保存的是磁盘上的原始值：
+//! ext4 结构为小端序，JBD2 结构为大端序，读取时需要 `u32::from_le` /
+//! `u32::from_be`。启用 `serde` feature 后所有结构派生
+//! `Serialize` / `Deserialize`，序列化的同样是原始值。
+//!
+//! # ext4
+//!
+//! | 结构 | 大小（字节） | 位置 |
+//! |------|-------------|------|
+//! | [`ext4_sblock`] | 1024 | 设备偏移 1024，以及备份块组的第一个块 |
+//! | [`ext4_group_desc`] | 32 / 64 | superblock 之后的块组描述符表，64 位特性下为 64 字节 |
+//! | [`ext4_inode`] | 160 | inode 表，每项 `inode_size` 字节（超出部分为额外字段和 xattr） |
+//! | [`ext4_extent_header`] | 12 | `i_block` 开头及每个 extent 树块的开头 |
+//! | [`ext4_extent_idx`] | 12 | 索引节点中紧随头部的条目 |
+//! | [`ext4_extent`] | 12 | 叶子节点中紧随头部的条目 |
+//! | [`ext4_extent_tail`] | 4 | extent 树块中最后一个可用条目之后（metadata_csum） |
+//! | [`ext4_dir_entry`] | 8 + 名称 | 线性目录块，`rec_len` 链接 |
+//! | [`ext4_dir_entry_tail`] | 12 | 目录块末尾（metadata_csum） |
+//! | [`ext4_dir_idx_root`] | 32 | HTree 根块开头："."、".."、根信息 |
+//! | [`ext4_dir_idx_node`] | 8 | HTree 内部索引块开头（伪目录项） |
+//! | [`ext4_dir_idx_climit`] | 4 | 索引条目数组开头，占用第一项的 `hash` 位置 |
+//! | [`ext4_dir_idx_entry`] | 8 | 索引条目（哈希值 + 子块号） |
+//! | [`ext4_dir_idx_tail`] | 8 | 索引块条目之后（metadata_csum） |
+//! | [`ext4_xattr_header`] | 32 | 独立 xattr 块开头 |
+//! | [`ext4_xattr_ibody_header`] | 4 | inode 额外空间中 xattr 区域开头 |
+//! | [`ext4_xattr_entry`] | 16 + 名称 | xattr 头部之后，4 字节对齐 |
+//!
+//! # JBD2（需要 `journal` feature）
+//!
+//! | 结构 | 大小（字节） | 位置 |
+//! |------|-------------|------|
+//! | [`jbd_sb`] | 1024 | 日志的第一个块 |
+//! | [`jbd_bhdr`] | 12 | 每个日志元数据块（描述符、提交、撤销）的开头 |
+//! | [`jbd_block_tag3`] / [`jbd_block_tag`] | 16 / 12 | 描述符块中的块标签，CSUM_V3 使用前者 |
+//! | [`jbd_block_tail`] | 4 | 描述符块末尾（校验和特性） |
+//! | [`jbd_commit_header`] | 60 | 提交块 |
+//! | [`jbd_revoke_header`] | 16 | 撤销块开头，之后是被撤销的块号 |
+//! | [`jbd_revoke_tail`] | 4 | 撤销块末尾（校验和特性） |
+//!
+//! # 示例
+//!
+//! ```rust,ignore
+//! use lwext4_core::disk::{ext4_sblock, OnDisk};
+//!
+//! let sb = ext4_sblock::from_bytes(&image[1024..]).unwrap();
+//! assert_eq!(u16::from_le(sb.magic), 0xEF53);
+//! ```
+
+pub use crate::types::{
+    ext4_dir_entry, ext4_dir_entry_tail, ext4_dir_idx_climit, ext4_dir_idx_dot_en,
+    ext4_dir_idx_entry, ext4_dir_idx_node, ext4_dir_idx_rinfo, ext4_dir_idx_root,
+    ext4_dir_idx_tail, ext4_extent, ext4_extent_header, ext4_extent_idx, ext4_extent_tail,
+    ext4_fake_dir_entry, ext4_group_desc, ext4_inode, ext4_sblock, ext4_xattr_entry,
+    ext4_xattr_header, ext4_xattr_ibody_header,
+};
+
+#[cfg(feature = "journal")]
+pub use crate::journal::types::{
+    jbd_bhdr, jbd_block_tag, jbd_block_tag3, jbd_block_tail, jbd_commit_header,
+    jbd_revoke_header, jbd_revoke_tail, jbd_sb,
+};
+
+mod sealed {
+    pub trait Sealed {}
+}
+
+/// 可以直接从字节解析的磁盘结构
+///
+/// 只为本模块列出的结构实现：它们的字段全是整数，任意字节都是合法的值
+pub trait OnDisk: Copy + sealed::Sealed {
+    /// 结构的大小（字节）
+    const SIZE: usize = core::mem::size_of::<Self>();
+
+    /// 从 `bytes` 开头解析结构，长度不足时返回 `None`
+    fn from_bytes(bytes: &[u8]) -> Option<Self> {
+        if bytes.len() < Self::SIZE {
+            return None;
+        }
+        // SAFETY: 长度已检查；Self 只由整数组成，任意位模式都合法
+        Some(unsafe { core::ptr::read_unaligned(bytes.as_ptr() as *const Self) })
+    }
+}
+
+macro_rules! impl_on_disk {
+    ($($ty:ty),* $(,)?) => {
+        $(
+            impl sealed::Sealed for $ty {}
+            impl OnDisk for $ty {}
+        )*
+    };
+}
+
+impl_on_disk!(
+    ext4_sblock,
+    ext4_group_desc,
+    ext4_inode,
+    ext4_extent_header,
+    ext4_extent_idx,
+    ext4_extent,
+    ext4_extent_tail,
+    ext4_dir_entry,
+    ext4_dir_entry_tail,
+    ext4_dir_idx_root,
+    ext4_dir_idx_node,
+    ext4_dir_idx_climit,
+    ext4_dir_idx_entry,
+    ext4_dir_idx_dot_en,
+    ext4_dir_idx_rinfo,
+    ext4_dir_idx_tail,
+    ext4_fake_dir_entry,
+    ext4_xattr_header,
+    ext4_xattr_ibody_header,
+    ext4_xattr_entry,
+);
+
+#[cfg(feature = "journal")]
+impl_on_disk!(
+    jbd_sb,
+    jbd_bhdr,
+    jbd_block_tag3,
+    jbd_block_tag,
+    jbd_block_tail,
+    jbd_commit_header,
+    jbd_revoke_header,
+    jbd_revoke_tail,
+);
+
+/// 长度超过 32 的数组字段的 serde 实现（serde 只内置了 32 以内的数组）
+#[cfg(feature = "serde")]
+pub(crate) mod serde_array {
+    use alloc::vec::Vec;
+    use serde::{de::Error, Deserialize, Deserializer, Serialize, Serializer};
+
+    pub fn serialize<S, T, const N: usize>(array: &[T; N], serializer: S) -> Result<S::Ok, S::Error>
+    where
+        S: Serializer,
+        T: Serialize,
+    {
+        serializer.collect_seq(array)
+    }
+
+    pub fn deserialize<'de, D, T, const N: usize>(deserializer: D) -> Result<[T; N], D::Error>
+    where
+        D: Deserializer<'de>,
+        T: Deserialize<'de>,
+    {
+        let items = Vec::<T>::deserialize(deserializer)?;
+        let len = items.len();
+        items
+            .try_into()
+            .map_err(|_| D::Error::invalid_length(len, &"an array of the on-disk length"))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_sizes() {
+        assert_eq!(ext4_sblock::SIZE, 1024);
+        assert_eq!(ext4_group_desc::SIZE, 64);
+        assert_eq!(ext4_inode::SIZE, 160);
+        assert_eq!(ext4_extent_header::SIZE, 12);
+        assert_eq!(ext4_extent_idx::SIZE, 12);
+        assert_eq!(ext4_extent::SIZE, 12);
+        assert_eq!(ext4_dir_entry_tail::SIZE, 12);
+        assert_eq!(ext4_xattr_header::SIZE, 32);
+        assert_eq!(ext4_xattr_entry::SIZE, 16);
+    }
+
+    #[cfg(feature = "journal")]
+    #[test]
+    fn test_jbd_sizes() {
+        assert_eq!(jbd_sb::SIZE, 1024);
+        assert_eq!(jbd_bhdr::SIZE, 12);
+        assert_eq!(jbd_block_tag3::SIZE, 16);
+        assert_eq!(jbd_commit_header::SIZE, 60);
+    }
+
+    #[test]
+    fn test_from_bytes() {
+        let mut block = [0u8; 1024];
+        block[56..58].copy_from_slice(&0xEF53u16.to_le_bytes());
+        let sb = ext4_sblock::from_bytes(&block).unwrap();
+        assert_eq!(u16::from_le(sb.magic), 0xEF53);
+
+        assert!(ext4_sblock::from_bytes(&block[..1023]).is_none());
+
+        let ext = ext4_extent::from_bytes(&[1, 0, 0, 0, 8, 0, 0, 0, 100, 0, 0, 0]).unwrap();
+        assert_eq!(u32::from_le(ext.block), 1);
+        assert_eq!(u16::from_le(ext.len), 8);
+        assert_eq!(u32::from_le(ext.start_lo), 100);
+    }
+}
@@ -2,6 +2,7 @@
 //!
 //! 提供 ext4 文件系统操作的错误类型。
 
+use crate::consts::*;
 use core::fmt;
 
 /// ext4 操作错误
@@ -9,6 +10,7 @@ use core::fmt;
 pub struct Error {
     kind: ErrorKind,
     message: &'static str,
+    block: Option<u64>,
 }
 
 /// 错误类别
@@ -37,19 +39,81 @@ pub enum ErrorKind {
     InvalidState,
     /// 目录非空
     NotEmpty,
+    /// 非阻塞设备暂时无法完成请求，稍后重试
+    WouldBlock,
+    /// 超出磁盘配额（对应 `EDQUOT`）
+    QuotaExceeded,
+    /// 文件内容或名称已加密，没有密钥无法访问（对应 `ENOKEY`）
+    Encrypted,
+    /// 符号链接层数过多（对应 `ELOOP`）
+    SymlinkLoop,
+    /// 路径中间的组件或操作对象不是目录（对应 `ENOTDIR`）
+    NotADirectory,
+    /// 对目录执行了只适用于非目录的操作（对应 `EISDIR`）
+    IsADirectory,
+    /// 文件系统以只读方式挂载，或设备只读（对应 `EROFS`）
+    ReadOnlyFs,
+    /// 文件名超过 255 字节（对应 `ENAMETOOLONG`）
+    NameTooLong,
+    /// 超出最大文件大小（对应 `EFBIG`）
+    FileTooLarge,
+    /// 操作本身不允许，与权限无关（如硬链接目录，对应 `EPERM`）
+    NotPermitted,
+    /// 链接计数达到上限（对应 `EMLINK`）
+    TooManyLinks,
+}
+
+impl ErrorKind {
+    /// 对应的 POSIX errno
+    ///
+    /// 每个类别映射到固定的 errno（`Corrupted` 为 Linux 的 `EFSCORRUPTED`，
+    /// 即 `EUCLEAN`），VFS 层和 C API 可以直接返回
+    pub const fn to_errno(self) -> i32 {
+        match self {
+            ErrorKind::Io => EIO,
+            ErrorKind::InvalidInput => EINVAL,
+            ErrorKind::Corrupted => EUCLEAN,
+            ErrorKind::PermissionDenied => EACCES,
+            ErrorKind::NotFound => ENOENT,
+            ErrorKind::AlreadyExists => EEXIST,
+            ErrorKind::NoSpace => ENOSPC,
+            ErrorKind::Unsupported => ENOTSUP,
+            ErrorKind::Busy => EBUSY,
+            ErrorKind::InvalidState => EINVAL,
+            ErrorKind::NotEmpty => ENOTEMPTY,
+            ErrorKind::WouldBlock => EAGAIN,
+            ErrorKind::QuotaExceeded => EDQUOT,
+            ErrorKind::Encrypted => ENOKEY,
+            ErrorKind::SymlinkLoop => ELOOP,
+            ErrorKind::NotADirectory => ENOTDIR,
+            ErrorKind::IsADirectory => EISDIR,
+            ErrorKind::ReadOnlyFs => EROFS,
+            ErrorKind::NameTooLong => ENAMETOOLONG,
+            ErrorKind::FileTooLarge => EFBIG,
+            ErrorKind::NotPermitted => EPERM,
+            ErrorKind::TooManyLinks => EMLINK,
+        }
+    }
 }
 
 impl Error {
     /// 创建新错误
     pub const fn new(kind: ErrorKind, message: &'static str) -> Self {
-        Self { kind, message }
+        Self { kind, message, block: None }
+    }
+
+    /// 创建指明出错块地址的错误
+    ///
+    /// 用于校验和不匹配等与具体磁盘块相关的错误
+    pub const fn with_block(kind: ErrorKind, message: &'static str, block: u64) -> Self {
+        Self { kind, message, block: Some(block) }
     }
 
     /// 创建带原因的错误（简化版，忽略 cause）
     ///
     /// 注意：在 no_std 环境下，cause 参数会被忽略
     pub fn with_cause(kind: ErrorKind, message: &'static str, _cause: impl core::fmt::Debug) -> Self {
-        Self { kind, message }
+        Self { kind, message, block: None }
     }
 
     /// 获取错误类型
@@ -61,11 +125,74 @@ impl Error {
     pub const fn message(&self) -> &'static str {
         self.message
     }
+
+    /// 获取出错的块地址（如果有）
+    pub const fn block(&self) -> Option<u64> {
+        self.block
+    }
+
+    /// 对应的 POSIX errno，见 [`ErrorKind::to_errno`]
+    pub const fn errno(&self) -> i32 {
+        self.kind.to_errno()
+    }
+
+    /// 是否为可重试的 [`ErrorKind::WouldBlock`]
+    pub const fn is_would_block(&self) -> bool {
+        matches!(self.kind, ErrorKind::WouldBlock)
+    }
+}
+
+/// 在设备返回 `WouldBlock` 时重试操作
+///
+/// 执行 `op`，如果返回 [`ErrorKind::WouldBlock`] 则调用 `wait(attempt)`
+/// （用于轮询设备、让出 CPU 等）后重新执行，最多执行 `max_attempts` 次。
+/// 其他错误立即返回；超过重试次数时返回最后一次的 `WouldBlock` 错误。
+///
+/// # 可重试的操作
+///
+/// 块层保证设备返回 `WouldBlock` 时不留下半初始化的缓存块，
+/// 脏块在写回失败时保持为脏，因此以下操作可以整体安全重试：
+///
+/// - 只读操作：`read_at`、`read_dir`、`metadata`、`getxattr`、`listxattr`、
+///   `scrub`、路径查找
+/// - 刷新操作：`flush`、`sync`（已写回的块不会重复写入）
+///
+/// 修改元数据的多步操作（创建、写入、截断、删除、重命名、设置 xattr）
+/// **不能**在中途失败后直接重试：失败前的步骤可能已修改缓存中的位图、
+/// inode 或目录块。对这类操作，集成方应在设备层自行等待（阻塞直到完成），
+/// 或者只在 `op` 内部重试纯读取部分。
+///
+/// # 示例
+///
+/// ```rust,ignore
+/// use lwext4_core::error::retry_would_block;
+///
+/// let n = retry_would_block(16, |_| device_poll(), || fs.read_at_inode(ino, &mut buf, 0))?;
+/// ```
+pub fn retry_would_block<T, W, F>(max_attempts: u32, mut wait: W, mut op: F) -> Result<T>
+where
+    W: FnMut(u32),
+    F: FnMut() -> Result<T>,
+{
+    let mut attempt = 1;
+    loop {
+        match op() {
+            Err(e) if e.is_would_block() && attempt < max_attempts => {
+                wait(attempt);
+                attempt += 1;
+            }
+            result => return result,
+        }
+    }
 }
 
 impl fmt::Display for Error {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
-        write!(f, "{:?}: {}", self.kind, self.message)
+        write!(f, "{:?}: {}", self.kind, self.message)?;
+        if let Some(block) = self.block {
+            write!(f, " (block {block})")?;
+        }
+        Ok(())
     }
 }
 
@@ -73,6 +200,7 @@ impl fmt::Display for Error {
 impl std::error::Error for Error {}
 
 // Journal error conversion
+#[cfg(feature = "journal")]
 impl From<crate::journal::JournalError> for Error {
     fn from(err: crate::journal::JournalError) -> Self {
         use crate::journal::JournalError;
@@ -89,3 +217,62 @@ impl From<crate::journal::JournalError> for Error {
 
 /// Result 类型别名
 pub type Result<T> = core::result::Result<T, Error>;
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_retry_would_block() {
+        let mut calls = 0;
+        let mut waits = 0;
+        let result = retry_would_block(5, |_| waits += 1, || {
+            calls += 1;
+            if calls < 3 {
+                Err(Error::new(ErrorKind::WouldBlock, "device busy"))
+            } else {
+                Ok(calls)
+            }
+        });
+        assert_eq!(result, Ok(3));
+        assert_eq!(waits, 2);
+
+        // 超过重试次数
+        let mut calls = 0;
+        let result: Result<()> = retry_would_block(2, |_| {}, || {
+            calls += 1;
+            Err(Error::new(ErrorKind::WouldBlock, "device busy"))
+        });
+        assert!(result.unwrap_err().is_would_block());
+        assert_eq!(calls, 2);
+
+        // 其他错误不重试
+        let mut calls = 0;
+        let result: Result<()> = retry_would_block(5, |_| {}, || {
+            calls += 1;
+            Err(Error::new(ErrorKind::Io, "bad sector"))
+        });
+        assert_eq!(result.unwrap_err().kind(), ErrorKind::Io);
+        assert_eq!(calls, 1);
+    }
+
+    #[test]
+    fn test_error_block() {
+        let e = Error::with_block(ErrorKind::Corrupted, "Inode checksum mismatch", 1234);
+        assert_eq!(e.block(), Some(1234));
+        assert_eq!(alloc::format!("{e}"), "Corrupted: Inode checksum mismatch (block 1234)");
+        assert_eq!(Error::new(ErrorKind::Io, "bad sector").block(), None);
+    }
+
+    #[test]
+    fn test_errno() {
+        assert_eq!(Error::new(ErrorKind::NotFound, "missing").errno(), ENOENT);
+        assert_eq!(ErrorKind::NotADirectory.to_errno(), ENOTDIR);
+        assert_eq!(ErrorKind::IsADirectory.to_errno(), EISDIR);
+        assert_eq!(ErrorKind::ReadOnlyFs.to_errno(), EROFS);
+        assert_eq!(ErrorKind::QuotaExceeded.to_errno(), EDQUOT);
+        assert_eq!(ErrorKind::SymlinkLoop.to_errno(), ELOOP);
+        assert_eq!(ErrorKind::Corrupted.to_errno(), EUCLEAN);
+        assert_eq!(ErrorKind::TooManyLinks.to_errno(), EMLINK);
+    }
+}
@@ -5,7 +5,7 @@
 //! ## 校验和算法
 //!
 //! Extent 块的 CRC32C 校验和计算包括：
-//! 1. 文件系统 UUID
+//! 1. 文件系统的校验和种子（见 `Superblock::csum_seed`）
 //! 2. Inode 编号
 //! 3. Inode generation
 //! 4. Extent 块内容（header + entries，不包括 tail 的 checksum 字段）
@@ -16,7 +16,6 @@ use crate::{
     superblock::Superblock,
     types::{ext4_extent_header, ext4_extent_tail},
     BlockDevice,
-    crc::EXT4_CRC32_INIT,
 };
 
 /// 计算 extent tail 的偏移量
@@ -108,27 +107,24 @@ pub fn compute_checksum(
         return 0;
     }
 
-    // 1. 计算 fs uuid 的 CRC
-    let mut crc = crate::crc::crc32c_append(EXT4_CRC32_INIT, &sb.inner().uuid);
-
-    // 2. 计算 inode number 的 CRC
-    let inode_num_bytes = inode_num.to_le_bytes();
-    crc = crate::crc::crc32c_append(crc, &inode_num_bytes);
-
-    // 3. 计算 inode generation 的 CRC
-    let inode_gen_bytes = inode_gen.to_le_bytes();
-    crc = crate::crc::crc32c_append(crc, &inode_gen_bytes);
-
+    // 1-3. 种子包含 fs 校验和种子、inode number 和 inode generation
     // 4. 计算 extent 块的 CRC（到 tail 之前）
+    compute_checksum_with_seed(sb.inode_csum_seed(inode_num, inode_gen), block_data)
+}
+
+/// 用 inode 的校验和种子计算 extent 块的 CRC32C 校验和
+///
+/// `seed` 见 [`Superblock::inode_csum_seed`]
+pub(crate) fn compute_checksum_with_seed(seed: u32, block_data: &[u8]) -> u32 {
     let header_ptr = block_data.as_ptr() as *const ext4_extent_header;
     let header = unsafe { &*header_ptr };
     let tail_offset = extent_tail_offset(header);
 
     if tail_offset <= block_data.len() {
-        crc = crate::crc::crc32c_append(crc, &block_data[..tail_offset]);
+        crate::crc::crc32c_append(seed, &block_data[..tail_offset])
+    } else {
+        seed
     }
-
-    crc
 }
 
 /// 设置 extent 块的校验和
@@ -155,6 +151,24 @@ pub fn set_checksum(
     }
 }
 
+/// 修改非根 extent 块后重新计算块尾的校验和
+///
+/// `seed` 见 [`InodeRef::extent_csum_seed`]，为 None（未启用 `metadata_csum`）时不做修改
+pub(crate) fn update_checksum(seed: Option<u32>, block_data: &mut [u8]) {
+    let Some(seed) = seed else {
+        return;
+    };
+    let header = unsafe { &*(block_data.as_ptr() as *const ext4_extent_header) };
+    if extent_tail_offset(header) + core::mem::size_of::<ext4_extent_tail>() > block_data.len() {
+        return;
+    }
+
+    let checksum = compute_checksum_with_seed(seed, block_data);
+    unsafe {
+        get_extent_tail_mut(block_data).checksum = checksum.to_le();
+    }
+}
+
 /// 验证 extent 块的校验和
 ///
 /// # 参数
@@ -178,14 +192,25 @@ pub fn verify_checksum(
         return true;
     }
 
+    verify_checksum_with_seed(sb.inode_csum_seed(inode_num, inode_gen), block_data)
+}
+
+/// 用 inode 的校验和种子验证 extent 块的校验和
+///
+/// `seed` 见 [`Superblock::inode_csum_seed`]
+pub(crate) fn verify_checksum_with_seed(seed: u32, block_data: &[u8]) -> bool {
+    // 损坏的 max 可能让 tail 落在块外
+    let header = unsafe { &*(block_data.as_ptr() as *const ext4_extent_header) };
+    if extent_tail_offset(header) + core::mem::size_of::<ext4_extent_tail>() > block_data.len() {
+        return false;
+    }
+
     let stored = unsafe {
         let tail = get_extent_tail(block_data);
         u32::from_le(tail.checksum)
     };
 
-    let computed = compute_checksum(sb, inode_num, inode_gen, block_data);
-
-    stored == computed
+    stored == compute_checksum_with_seed(seed, block_data)
 }
 
 /// 为 inode 中的 extent 树设置校验和
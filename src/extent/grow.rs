@@ -8,8 +8,8 @@ use crate::{
     consts::*,
     error::Result,
     fs::InodeRef,
-    superblock::Superblock,
-    types::{ext4_extent, ext4_extent_header, ext4_extent_idx},
+    logging::ext4_log,
+    types::{ext4_extent, ext4_extent_header, ext4_extent_idx, Pblk},
 };
 
 use super::helpers::*;
@@ -29,7 +29,6 @@ use alloc::vec::Vec;
 /// # 参数
 ///
 /// * `inode_ref` - Inode 引用
-/// * `sb` - Superblock 引用
 /// * `allocator` - 块分配器
 ///
 /// # 返回
@@ -57,10 +56,9 @@ use alloc::vec::Vec;
 /// - `ErrorKind::InvalidInput` - 参数无效
 pub fn grow_tree_depth<D: BlockDevice>(
     inode_ref: &mut InodeRef<D>,
-    sb: &mut Superblock,
     allocator: &mut BlockAllocator,
-) -> Result<u64> {
-    let block_size = sb.block_size();
+) -> Result<Pblk> {
+    let block_size = inode_ref.sb().block_size();
 
     // 1. 读取当前根节点信息
     let (old_header, is_leaf) = inode_ref.with_inode(|inode| {
@@ -83,19 +81,22 @@ pub fn grow_tree_depth<D: BlockDevice>(
     let old_depth = old_header.depth();
     let new_depth = old_depth + 1;
 
-    log::debug!(
+    ext4_log!(Extent, Debug,
         "[GROW_TREE] Starting grow_tree_depth: old_depth={}, new_depth={}, is_leaf={}",
         old_depth, new_depth, is_leaf
     );
 
     // 2. 分配新的物理块
+    let (bdev, sb) = inode_ref.bdev_and_sb_mut();
     let new_block = allocator.alloc_block(
-        inode_ref.bdev(),
+        bdev,
         sb,
         0, // goal = 0 让 balloc 自己选择
     )?;
+    // 树节点块同样计入 i_blocks（释放时由 free_block_with_inode 扣除）
+    inode_ref.add_blocks(1)?;
 
-    log::debug!(
+    ext4_log!(Extent, Debug,
         "[GROW_TREE] Allocated new block: 0x{:x} (decimal: {})",
         new_block, new_block
     );
@@ -103,7 +104,7 @@ pub fn grow_tree_depth<D: BlockDevice>(
     // 3. 将当前根节点内容复制到新块
     if is_leaf {
         // 根节点是叶子，复制 extent 数组
-        log::debug!("[GROW_TREE] Copying extents to new block 0x{:x}", new_block);
+        ext4_log!(Extent, Debug, "[GROW_TREE] Copying extents to new block 0x{:x}", new_block);
         copy_extents_to_new_block(
             inode_ref,
             new_block,
@@ -112,7 +113,7 @@ pub fn grow_tree_depth<D: BlockDevice>(
         )?;
     } else {
         // 根节点是索引节点，复制 index 数组
-        log::debug!("[GROW_TREE] Copying indices to new block 0x{:x}", new_block);
+        ext4_log!(Extent, Debug, "[GROW_TREE] Copying indices to new block 0x{:x}", new_block);
         copy_indices_to_new_block(
             inode_ref,
             new_block,
@@ -124,7 +125,7 @@ pub fn grow_tree_depth<D: BlockDevice>(
 
     // 4. 在 inode 中创建新的根节点
     // 新根节点是索引节点，只包含一个 index 指向刚才分配的块
-    log::debug!(
+    ext4_log!(Extent, Debug,
         "[GROW_TREE] Creating new root in inode: depth={}, pointing to block 0x{:x}",
         new_depth, new_block
     );
@@ -137,15 +138,15 @@ pub fn grow_tree_depth<D: BlockDevice>(
     // 🔧 关键修复：强制写回 inode 到磁盘
     // grow_tree_depth 修改了 inode.blocks（extent 树的根节点），这是文件系统元数据的关键部分
     // 必须确保这个修改被立即持久化，否则后续读取可能读到旧的树结构，导致数据损坏
-    log::debug!(
+    ext4_log!(Extent, Debug,
         "[GROW_TREE] Force writeback inode after grow_tree_depth (critical for consistency)"
     );
     inode_ref.force_writeback().map_err(|e| {
-        log::error!("[GROW_TREE] Failed to force writeback after grow: {:?}", e);
+        ext4_log!(Extent, Error, "[GROW_TREE] Failed to force writeback after grow: {:?}", e);
         e
     })?;
 
-    log::debug!("[GROW_TREE] grow_tree_depth completed successfully with forced writeback");
+    ext4_log!(Extent, Debug, "[GROW_TREE] grow_tree_depth completed successfully with forced writeback");
 
     Ok(new_block)
 }
@@ -153,7 +154,7 @@ pub fn grow_tree_depth<D: BlockDevice>(
 /// 将 inode 中的 extent 数组复制到新分配的块
 fn copy_extents_to_new_block<D: BlockDevice>(
     inode_ref: &mut InodeRef<D>,
-    new_block: u64,
+    new_block: Pblk,
     block_size: u32,
     old_header: &ext4_extent_header,
 ) -> Result<()> {
@@ -192,6 +193,7 @@ fn copy_extents_to_new_block<D: BlockDevice>(
     };
 
     // 写入新块
+    let csum_seed = inode_ref.extent_csum_seed()?;
     {
         let mut block = Block::get(inode_ref.bdev(), new_block)?;
 
@@ -214,6 +216,8 @@ fn copy_extents_to_new_block<D: BlockDevice>(
                     *(data[offset..].as_mut_ptr() as *mut ext4_extent) = *extent;
                 }
             }
+
+            super::checksum::update_checksum(csum_seed, data);
         })?;
     } // block dropped here, marked dirty automatically
 
@@ -223,7 +227,7 @@ fn copy_extents_to_new_block<D: BlockDevice>(
 /// 将 inode 中的 index 数组复制到新分配的块
 fn copy_indices_to_new_block<D: BlockDevice>(
     inode_ref: &mut InodeRef<D>,
-    new_block: u64,
+    new_block: Pblk,
     block_size: u32,
     old_header: &ext4_extent_header,
     old_depth: u16,
@@ -263,6 +267,7 @@ fn copy_indices_to_new_block<D: BlockDevice>(
     };
 
     // 写入新块
+    let csum_seed = inode_ref.extent_csum_seed()?;
     {
         let mut block = Block::get(inode_ref.bdev(), new_block)?;
 
@@ -285,6 +290,8 @@ fn copy_indices_to_new_block<D: BlockDevice>(
                     *(data[offset..].as_mut_ptr() as *mut ext4_extent_idx) = *idx;
                 }
             }
+
+            super::checksum::update_checksum(csum_seed, data);
         })?;
     } // block dropped here, marked dirty automatically
 
@@ -297,7 +304,7 @@ fn copy_indices_to_new_block<D: BlockDevice>(
 fn create_new_root_in_inode<D: BlockDevice>(
     inode_ref: &mut InodeRef<D>,
     new_depth: u16,
-    child_block: u64,
+    child_block: Pblk,
 ) -> Result<()> {
     inode_ref.with_inode_mut(|inode| {
         let data = unsafe {
@@ -328,13 +335,13 @@ fn create_new_root_in_inode<D: BlockDevice>(
         ext4_idx_store_pblock(first_idx, child_block);
         first_idx.unused = 0u16.to_le();
 
-        log::debug!(
+        ext4_log!(Extent, Debug,
             "[GROW_TREE] Wrote index to root: block=0, child_block=0x{:x}, leaf_lo=0x{:x}, leaf_hi=0x{:x}",
             child_block, first_idx.leaf_lo, first_idx.leaf_hi
         );
 
         // 打印整个 inode.blocks 的前 28 字节（header 12 + index 12 + 额外 4）
-        log::debug!("[GROW_TREE] inode.blocks[0..28]: {:02x?}", &data[..28]);
+        ext4_log!(Extent, Debug, "[GROW_TREE] inode.blocks[0..28]: {:02x?}", &data[..28]);
     })?;
 
     inode_ref.mark_dirty();
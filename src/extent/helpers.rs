@@ -2,7 +2,10 @@
 //!
 //! 提供操作 extent header、extent、index 的辅助宏函数
 
-use crate::types::{ext4_extent, ext4_extent_header, ext4_extent_idx};
+use crate::{
+    logging::ext4_log,
+    types::{ext4_extent, ext4_extent_header, ext4_extent_idx, Pblk},
+};
 use core::mem::size_of;
 
 /// 获取 extent header 中的第一个 extent
@@ -197,10 +200,11 @@ pub unsafe fn EXT_HAS_FREE_INDEX(header: *const ext4_extent_header) -> bool {
 ///
 /// * `idx` - index 引用
 /// * `pblock` - 物理块号
-pub fn ext4_idx_store_pblock(idx: &mut ext4_extent_idx, pblock: u64) {
+pub fn ext4_idx_store_pblock(idx: &mut ext4_extent_idx, pblock: impl Into<Pblk>) {
+    let pblock = pblock.into().0;
     // 🔧 验证输入的块号是否超出 48-bit 限制
     if pblock > 0xFFFFFFFFFFFF {
-        log::error!(
+        ext4_log!(Extent, Error,
             "[ext4_idx_store_pblock] Invalid pblock: {:#x} (exceeds 48-bit limit)",
             pblock
         );
@@ -212,13 +216,13 @@ pub fn ext4_idx_store_pblock(idx: &mut ext4_extent_idx, pblock: u64) {
     // 🔧 验证写入结果
     let reconstructed = ext4_idx_pblock(idx);
     if reconstructed != pblock {
-        log::error!(
+        ext4_log!(Extent, Error,
             "[ext4_idx_store_pblock] Mismatch! input={:#x}, stored={:#x}, leaf_lo={:#x}, leaf_hi={:#x}",
             pblock, reconstructed, u32::from_le(idx.leaf_lo), u16::from_le(idx.leaf_hi)
         );
     }
 
-    log::trace!(
+    ext4_log!(Extent, Trace,
         "[ext4_idx_store_pblock] Stored pblock={:#x} -> leaf_lo={:#x}, leaf_hi={:#x}",
         pblock, u32::from_le(idx.leaf_lo), u16::from_le(idx.leaf_hi)
     );
@@ -242,7 +246,7 @@ pub fn ext4_idx_pblock(idx: &ext4_extent_idx) -> u64 {
 
     // 注意：leaf_hi 非零是正常情况，表示物理块号超过 32 位
     // ext4 支持最大 48 位物理块地址
-    log::trace!(
+    ext4_log!(Extent, Trace,
         "[ext4_idx_pblock] Read pblock={:#x} (leaf_lo={:#x}, leaf_hi={:#x})",
         pblock, lo as u32, hi as u16
     );
@@ -258,7 +262,8 @@ pub fn ext4_idx_pblock(idx: &ext4_extent_idx) -> u64 {
 ///
 /// * `extent` - extent 引用
 /// * `pblock` - 物理块号
-pub fn ext4_ext_store_pblock(extent: &mut ext4_extent, pblock: u64) {
+pub fn ext4_ext_store_pblock(extent: &mut ext4_extent, pblock: impl Into<Pblk>) {
+    let pblock = pblock.into().0;
     extent.start_lo = ((pblock & 0xFFFFFFFF) as u32).to_le();
     extent.start_hi = (((pblock >> 32) & 0xFFFF) as u16).to_le();
 }
@@ -280,7 +285,7 @@ pub fn ext4_ext_pblock(extent: &ext4_extent) -> u64 {
     let pblock = lo | (hi << 32);
 
     // 添加调试日志来追踪读取的 extent
-    log::trace!(
+    ext4_log!(Extent, Trace,
         "[EXTENT_READ] ext4_ext_pblock: start_lo=0x{:x}, start_hi=0x{:x}, logical={}, len={}, pblock=0x{:x}",
         extent.start_lo, extent.start_hi,
         u32::from_le(extent.block), u16::from_le(extent.len),
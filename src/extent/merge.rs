@@ -316,7 +316,7 @@ pub fn try_merge_and_insert<D: BlockDevice>(
         try_merge_and_insert_root(inode_ref, new_lblock, new_pblock, new_len, new_is_unwritten)
     } else {
         try_merge_and_insert_leaf_block(
-            inode_ref.bdev(),
+            inode_ref,
             block_addr,
             block_size,
             new_lblock,
@@ -378,7 +378,7 @@ fn try_merge_and_insert_root<D: BlockDevice>(
 
 /// 在叶子块中尝试合并并插入 extent
 fn try_merge_and_insert_leaf_block<D: BlockDevice>(
-    bdev: &mut crate::block::BlockDev<D>,
+    inode_ref: &mut InodeRef<D>,
     block_addr: u64,
     block_size: u32,
     new_lblock: u32,
@@ -389,6 +389,8 @@ fn try_merge_and_insert_leaf_block<D: BlockDevice>(
     use super::split::{read_extents_from_block, write_extents_to_block};
 
     // 读取当前的 extent 数组
+    let csum_seed = inode_ref.extent_csum_seed()?;
+    let bdev = inode_ref.bdev();
     let (mut extents, header) = read_extents_from_block(bdev, block_addr, block_size)?;
 
     // 检查是否有空间
@@ -422,7 +424,7 @@ fn try_merge_and_insert_leaf_block<D: BlockDevice>(
     // 写回
     let mut new_header = header;
     new_header.entries = (extents.len() as u16).to_le();
-    write_extents_to_block(bdev, block_addr, block_size, &new_header, &extents)?;
+    write_extents_to_block(bdev, block_addr, csum_seed, &new_header, &extents)?;
 
     Ok(true)
 }
@@ -88,3 +88,4 @@ pub use write::{
     get_blocks, remove_space, tree_init, ExtentPath, ExtentPathNode, ExtentNodeType,
     ExtentWriter,
 };
+pub(crate) use write::insert_extent_with_auto_split;
@@ -25,8 +25,7 @@ use crate::{
     block::{Block, BlockDevice},
     error::Result,
     fs::InodeRef,
-    superblock::Superblock,
-    types::{ext4_extent, ext4_extent_header},
+    types::{ext4_extent, ext4_extent_header, Lblk},
 };
 
 use super::{
@@ -114,7 +113,6 @@ struct LeafRemoveInfo {
 /// # 参数
 ///
 /// * `inode_ref` - Inode 引用
-/// * `sb` - Superblock 引用
 /// * `allocator` - 块分配器
 /// * `from` - 起始逻辑块号
 /// * `to` - 结束逻辑块号（包含，u32::MAX 表示到文件末尾）
@@ -137,12 +135,12 @@ struct LeafRemoveInfo {
 /// 这些优化功能可以在后续版本中添加。
 pub fn remove_space_multilevel<D: BlockDevice>(
     inode_ref: &mut InodeRef<D>,
-    sb: &mut Superblock,
     allocator: &mut BlockAllocator,
-    from: u32,
-    to: u32,
+    from: impl Into<Lblk>,
+    to: impl Into<Lblk>,
 ) -> Result<()> {
-    let block_size = sb.block_size();
+    let (Lblk(from), Lblk(to)) = (from.into(), to.into());
+    let block_size = inode_ref.sb().block_size();
 
     // 1. 遍历树，收集所有需要执行的删除操作
     let leaf_ops = collect_remove_operations(inode_ref, block_size, from, to)?;
@@ -151,7 +149,6 @@ pub fn remove_space_multilevel<D: BlockDevice>(
     for leaf_info in leaf_ops {
         execute_leaf_operations(
             inode_ref,
-            sb,
             allocator,
             &leaf_info,
             block_size,
@@ -458,25 +455,25 @@ fn traverse_node_for_remove<D: BlockDevice>(
 /// 执行叶子节点的删除操作
 fn execute_leaf_operations<D: BlockDevice>(
     inode_ref: &mut InodeRef<D>,
-    sb: &mut Superblock,
     allocator: &mut BlockAllocator,
     leaf_info: &LeafRemoveInfo,
     block_size: u32,
 ) -> Result<()> {
     // 1. 先释放所有物理块
     for op in &leaf_info.operations {
+        let (bdev, sb) = inode_ref.bdev_and_sb_mut();
         match op {
             RemoveOp::Delete { start_pblock, count, .. } => {
-                crate::balloc::free_blocks(inode_ref.bdev(), sb, *start_pblock, *count)?;
+                crate::balloc::free_blocks(bdev, sb, *start_pblock, *count)?;
             }
             RemoveOp::TruncateStart { free_pblock, free_count, .. } => {
-                crate::balloc::free_blocks(inode_ref.bdev(), sb, *free_pblock, *free_count)?;
+                crate::balloc::free_blocks(bdev, sb, *free_pblock, *free_count)?;
             }
             RemoveOp::TruncateEnd { free_pblock, free_count, .. } => {
-                crate::balloc::free_blocks(inode_ref.bdev(), sb, *free_pblock, *free_count)?;
+                crate::balloc::free_blocks(bdev, sb, *free_pblock, *free_count)?;
             }
             RemoveOp::SplitMiddle { free_pblock, free_count, .. } => {
-                crate::balloc::free_blocks(inode_ref.bdev(), sb, *free_pblock, *free_count)?;
+                crate::balloc::free_blocks(bdev, sb, *free_pblock, *free_count)?;
             }
         }
     }
@@ -485,10 +482,12 @@ fn execute_leaf_operations<D: BlockDevice>(
     if leaf_info.node_type == ExtentNodeType::Root {
         update_root_extents(inode_ref, &leaf_info.operations)?;
     } else {
+        let csum_seed = inode_ref.extent_csum_seed()?;
         update_leaf_block_extents(
             inode_ref.bdev(),
             leaf_info.block_addr,
             block_size,
+            csum_seed,
             &leaf_info.operations,
         )?;
     }
@@ -521,12 +520,16 @@ fn update_leaf_block_extents<D: BlockDevice>(
     bdev: &mut crate::block::BlockDev<D>,
     block_addr: u64,
     block_size: u32,
+    csum_seed: Option<u32>,
     operations: &[RemoveOp],
 ) -> Result<()> {
     let mut block = Block::get(bdev, block_addr)?;
 
-    block.with_data_mut(|data| {
-        update_extent_array(&mut data[0..block_size as usize], operations)
+    block.with_data_mut(|data| -> Result<()> {
+        let data = &mut data[0..block_size as usize];
+        update_extent_array(data, operations)?;
+        super::checksum::update_checksum(csum_seed, data);
+        Ok(())
     })??;
 
     // Block 会在 drop 时自动标记为 dirty
@@ -8,8 +8,8 @@ use crate::{
     consts::*,
     error::{Error, ErrorKind, Result},
     fs::InodeRef,
-    superblock::Superblock,
-    types::{ext4_extent, ext4_extent_header, ext4_extent_idx},
+    logging::ext4_log,
+    types::{ext4_extent, ext4_extent_header, ext4_extent_idx, Pblk},
 };
 
 use super::{
@@ -31,7 +31,6 @@ use alloc::vec::Vec;
 /// # 参数
 ///
 /// * `inode_ref` - Inode 引用
-/// * `sb` - Superblock 引用
 /// * `allocator` - 块分配器
 /// * `path` - Extent 路径（包含需要分裂的节点）
 /// * `at` - 需要分裂的节点在路径中的索引（0 = root）
@@ -55,7 +54,6 @@ use alloc::vec::Vec;
 /// - `ErrorKind::InvalidInput` - 参数无效
 pub fn split_extent_node<D: BlockDevice>(
     inode_ref: &mut InodeRef<D>,
-    sb: &mut Superblock,
     allocator: &mut BlockAllocator,
     path: &mut ExtentPath,
     at: usize,
@@ -87,17 +85,19 @@ pub fn split_extent_node<D: BlockDevice>(
     let split_at = entries / 2;
 
     // 分配新的物理块用于右节点
+    let (bdev, sb) = inode_ref.bdev_and_sb_mut();
     let new_block = allocator.alloc_block(
-        inode_ref.bdev(),
+        bdev,
         sb,
         0, // goal = 0 让 balloc 自己选择
     )?;
+    // 树节点块同样计入 i_blocks（释放时由 free_block_with_inode 扣除）
+    inode_ref.add_blocks(1)?;
 
     // 根据节点类型执行不同的分裂逻辑
     if is_leaf {
         split_leaf_node(
             inode_ref,
-            sb,
             allocator,
             path,
             at,
@@ -108,7 +108,6 @@ pub fn split_extent_node<D: BlockDevice>(
     } else {
         split_index_node(
             inode_ref,
-            sb,
             allocator,
             path,
             at,
@@ -126,15 +125,15 @@ pub fn split_extent_node<D: BlockDevice>(
 /// 将叶子节点的 extent 条目分裂到两个节点
 fn split_leaf_node<D: BlockDevice>(
     inode_ref: &mut InodeRef<D>,
-    sb: &mut Superblock,
     allocator: &mut BlockAllocator,
     path: &mut ExtentPath,
     at: usize,
-    new_block: u64,
+    new_block: Pblk,
     split_at: u16,
     _new_extent_logical_block: u32,
 ) -> Result<()> {
-    let block_size = sb.block_size();
+    let block_size = inode_ref.sb().block_size();
+    let csum_seed = inode_ref.extent_csum_seed()?;
     let node = &path.nodes[at];
     let depth = node.depth;
 
@@ -168,7 +167,7 @@ fn split_leaf_node<D: BlockDevice>(
     write_extents_to_block(
         inode_ref.bdev(),
         new_block,
-        block_size,
+        csum_seed,
         &new_header,
         &new_extents,
     )?;
@@ -190,7 +189,7 @@ fn split_leaf_node<D: BlockDevice>(
         write_extents_to_block(
             inode_ref.bdev(),
             node.block_addr,
-            block_size,
+            csum_seed,
             &updated_header,
             &kept_extents,
         )?;
@@ -209,7 +208,6 @@ fn split_leaf_node<D: BlockDevice>(
     // 在父节点中插入新索引
     insert_parent_index(
         inode_ref,
-        sb,
         allocator,
         path,
         at,
@@ -225,15 +223,15 @@ fn split_leaf_node<D: BlockDevice>(
 /// 将索引节点的 index 条目分裂到两个节点
 fn split_index_node<D: BlockDevice>(
     inode_ref: &mut InodeRef<D>,
-    sb: &mut Superblock,
     allocator: &mut BlockAllocator,
     path: &mut ExtentPath,
     at: usize,
-    new_block: u64,
+    new_block: Pblk,
     split_at: u16,
     _new_extent_logical_block: u32,
 ) -> Result<()> {
-    let block_size = sb.block_size();
+    let block_size = inode_ref.sb().block_size();
+    let csum_seed = inode_ref.extent_csum_seed()?;
     let node = &path.nodes[at];
     let depth = node.depth;
 
@@ -263,7 +261,7 @@ fn split_index_node<D: BlockDevice>(
     write_indices_to_block(
         inode_ref.bdev(),
         new_block,
-        block_size,
+        csum_seed,
         &new_header,
         &new_indices,
     )?;
@@ -285,7 +283,7 @@ fn split_index_node<D: BlockDevice>(
         write_indices_to_block(
             inode_ref.bdev(),
             node.block_addr,
-            block_size,
+            csum_seed,
             &updated_header,
             &kept_indices,
         )?;
@@ -304,7 +302,6 @@ fn split_index_node<D: BlockDevice>(
     // 在父节点中插入新索引
     insert_parent_index(
         inode_ref,
-        sb,
         allocator,
         path,
         at,
@@ -317,10 +314,13 @@ fn split_index_node<D: BlockDevice>(
 
 /// 在父节点中插入指向新节点的索引
 ///
+/// 父节点满时先递归分裂父节点；若子节点是根节点则先增加树深度。
+/// 分裂或增长之后 `path` 中的上层节点可能已经过时（例如根节点内容被移到了新块），
+/// 因此插入位置按深度和 `first_block` 从根重新定位，支持任意深度的树。
+///
 /// # 参数
 ///
 /// * `inode_ref` - Inode 引用
-/// * `sb` - Superblock 引用
 /// * `allocator` - 块分配器
 /// * `path` - Extent 路径
 /// * `child_at` - 子节点在路径中的位置
@@ -328,83 +328,97 @@ fn split_index_node<D: BlockDevice>(
 /// * `physical_block` - 新索引指向的物理块号
 fn insert_parent_index<D: BlockDevice>(
     inode_ref: &mut InodeRef<D>,
-    sb: &mut Superblock,
     allocator: &mut BlockAllocator,
     path: &mut ExtentPath,
     child_at: usize,
     first_block: u32,
-    physical_block: u64,
+    physical_block: Pblk,
 ) -> Result<()> {
-    // 如果child是根节点，需要增加树深度
-    let parent_at = if child_at == 0 {
-        // 调用 grow_tree_depth 增加树的深度
-        // grow_tree_depth 会将当前根节点移到新块，并创建新的根索引节点
-        // 新根节点包含一个指向旧根内容的索引（逻辑块0）
-        crate::extent::grow_tree_depth(inode_ref, sb, allocator)?;
-
-        // 🔧 BUG FIX: 不要直接返回！
-        // grow_tree_depth 只插入了指向原root内容的第一个索引
-        // 我们还需要在新root中插入第二个索引，指向分裂出的右半部分（physical_block）
-        // 新root就是parent_at=0
-        log::debug!(
+    let parent_depth = path.nodes[child_at].depth + 1;
+
+    if child_at == 0 {
+        // 子节点是根节点：grow_tree_depth 会将当前根节点移到新块，
+        // 并创建只含一个索引（指向原根内容）的新根，新根就是父节点
+        crate::extent::grow_tree_depth(inode_ref, allocator)?;
+
+        ext4_log!(Extent, Debug,
             "[insert_parent_index] After grow_tree_depth, inserting second index: first_block={}, physical_block={:#x}",
             first_block, physical_block
         );
-        0
     } else {
-        child_at - 1
-    };
+        let (_, parent_header) = locate_index_node(inode_ref, parent_depth, first_block)?;
+
+        if parent_header.entries_count() >= parent_header.max_entries() {
+            // 父节点也满了，需要先递归分裂父节点
+            split_extent_node(
+                inode_ref,
+                allocator,
+                path,
+                child_at - 1,
+                first_block,
+            )?;
+        }
+    }
 
-    // 检查父节点是否有空间
-    // 注意：如果刚执行了grow_tree_depth，需要重新读取root header
-    let (parent_entries, parent_max_entries) = if child_at == 0 {
-        // grow_tree_depth之后，root已经是新的索引节点了
-        // 需要从inode重新读取header
-        inode_ref.with_inode(|inode| {
-            let data = unsafe {
-                core::slice::from_raw_parts(
-                    inode.blocks.as_ptr() as *const u8,
-                    60,
-                )
-            };
-            let header = unsafe {
-                *(data.as_ptr() as *const ext4_extent_header)
-            };
-            (header.entries_count(), header.max_entries())
-        })?
-    } else {
-        let parent_node = &path.nodes[parent_at];
-        (parent_node.header.entries_count(), parent_node.header.max_entries())
-    };
+    // 重新定位覆盖 first_block 的父节点（可能是分裂出的右半部分或新根）
+    let (parent_block, _) = locate_index_node(inode_ref, parent_depth, first_block)?;
+    insert_index_to_node(inode_ref, parent_block, first_block, physical_block)
+}
 
-    if parent_entries >= parent_max_entries {
-        // 父节点也满了，需要先递归分裂父节点
-        // 这里我们使用 first_block 作为分裂点的提示
-        split_extent_node(
-            inode_ref,
-            sb,
-            allocator,
-            path,
-            parent_at,
-            first_block,
-        )?;
+/// 在索引数组中选择覆盖 `logical_block` 的索引
+///
+/// 返回最后一个起始块不大于 `logical_block` 的索引；`logical_block`
+/// 位于所有索引之前时返回第一个索引（与 lwext4 的 `ext4_ext_binsearch_idx()` 一致）
+fn select_index(indices: &[ext4_extent_idx], logical_block: u32) -> Option<&ext4_extent_idx> {
+    indices
+        .iter()
+        .take_while(|idx| idx.logical_block() <= logical_block)
+        .last()
+        .or(indices.first())
+}
 
-        // 分裂后，路径可能已经改变，需要重新查找正确的父节点
-        // 但是由于我们只是要插入索引，可以继续使用当前的路径
-        // （分裂会确保有足够的空间）
+/// 从根开始定位深度为 `depth`、覆盖 `logical_block` 的索引节点
+///
+/// # 返回
+///
+/// `(节点所在块, 节点 header)`，节点为 inode 中的根节点时块为 `None`
+fn locate_index_node<D: BlockDevice>(
+    inode_ref: &mut InodeRef<D>,
+    depth: u16,
+    logical_block: u32,
+) -> Result<(Option<u64>, ext4_extent_header)> {
+    let block_size = inode_ref.sb().block_size();
+    let (mut indices, mut header) = read_indices_from_inode(inode_ref)?;
+    let mut node_block = None;
+
+    if header.depth() < depth {
+        return Err(Error::new(
+            ErrorKind::Corrupted,
+            "Extent tree shallower than expected",
+        ));
     }
 
-    // 在父节点中插入新索引
-    insert_index_to_node(
-        inode_ref,
-        sb,
-        path,
-        parent_at,
-        first_block,
-        physical_block,
-    )?;
+    while header.depth() > depth {
+        let child = select_index(&indices, logical_block)
+            .map(ext4_idx_pblock)
+            .ok_or_else(|| Error::new(ErrorKind::Corrupted, "Empty extent index node"))?;
 
-    Ok(())
+        let (child_indices, child_header) =
+            read_indices_from_block(inode_ref.bdev(), child, block_size)?;
+
+        if !child_header.is_valid() || child_header.depth() + 1 != header.depth() {
+            return Err(Error::new(
+                ErrorKind::Corrupted,
+                "Extent index node depth mismatch",
+            ));
+        }
+
+        indices = child_indices;
+        header = child_header;
+        node_block = Some(child);
+    }
+
+    Ok((node_block, header))
 }
 
 /// 在指定节点中插入索引
@@ -412,27 +426,22 @@ fn insert_parent_index<D: BlockDevice>(
 /// # 参数
 ///
 /// * `inode_ref` - Inode 引用
-/// * `sb` - Superblock 引用
-/// * `path` - Extent 路径
-/// * `at` - 要插入索引的节点位置
+/// * `node_block` - 要插入索引的节点所在块，`None` 表示 inode 中的根节点
 /// * `first_block` - 新索引的逻辑块号
 /// * `physical_block` - 新索引指向的物理块号
 fn insert_index_to_node<D: BlockDevice>(
     inode_ref: &mut InodeRef<D>,
-    sb: &mut Superblock,
-    path: &mut ExtentPath,
-    at: usize,
+    node_block: Option<u64>,
     first_block: u32,
-    physical_block: u64,
+    physical_block: Pblk,
 ) -> Result<()> {
-    let block_size = sb.block_size();
-    let node = &path.nodes[at];
+    let block_size = inode_ref.sb().block_size();
+    let csum_seed = inode_ref.extent_csum_seed()?;
 
     // 读取当前节点的 index 数组
-    let (mut indices, mut header) = if node.node_type == ExtentNodeType::Root {
-        read_indices_from_inode(inode_ref)?
-    } else {
-        read_indices_from_block(inode_ref.bdev(), node.block_addr, block_size)?
+    let (mut indices, mut header) = match node_block {
+        None => read_indices_from_inode(inode_ref)?,
+        Some(block_addr) => read_indices_from_block(inode_ref.bdev(), block_addr, block_size)?,
     };
 
     let entries = header.entries_count();
@@ -468,16 +477,15 @@ fn insert_index_to_node<D: BlockDevice>(
     header.entries = (entries + 1).to_le();
 
     // 写回节点
-    if node.node_type == ExtentNodeType::Root {
-        write_indices_to_inode(inode_ref, &header, &indices)?;
-    } else {
-        write_indices_to_block(
+    match node_block {
+        None => write_indices_to_inode(inode_ref, &header, &indices)?,
+        Some(block_addr) => write_indices_to_block(
             inode_ref.bdev(),
-            node.block_addr,
-            block_size,
+            block_addr,
+            csum_seed,
             &header,
             &indices,
-        )?;
+        )?,
     }
 
     Ok(())
@@ -523,9 +531,10 @@ pub(super) fn read_extents_from_inode<D: BlockDevice>(
 /// 从块读取 extent 数组
 pub(super) fn read_extents_from_block<D: BlockDevice>(
     bdev: &mut crate::block::BlockDev<D>,
-    block_addr: u64,
+    block_addr: impl Into<Pblk>,
     _block_size: u32,
 ) -> Result<(Vec<ext4_extent>, ext4_extent_header)> {
+    let block_addr = block_addr.into();
     let mut block = Block::get(bdev, block_addr)?;
 
     block.with_data(|data| -> Result<(Vec<ext4_extent>, ext4_extent_header)> {
@@ -592,9 +601,10 @@ pub(super) fn read_indices_from_inode<D: BlockDevice>(
 /// 从块读取 index 数组
 pub(super) fn read_indices_from_block<D: BlockDevice>(
     bdev: &mut crate::block::BlockDev<D>,
-    block_addr: u64,
+    block_addr: impl Into<Pblk>,
     _block_size: u32,
 ) -> Result<(Vec<ext4_extent_idx>, ext4_extent_header)> {
+    let block_addr = block_addr.into();
     let mut block = Block::get(bdev, block_addr)?;
 
     block.with_data(|data| -> Result<(Vec<ext4_extent_idx>, ext4_extent_header)> {
@@ -665,11 +675,12 @@ pub(super) fn write_extents_to_inode<D: BlockDevice>(
 /// 写入 extent 数组到块
 pub(super) fn write_extents_to_block<D: BlockDevice>(
     bdev: &mut crate::block::BlockDev<D>,
-    block_addr: u64,
-    _block_size: u32,
+    block_addr: impl Into<Pblk>,
+    csum_seed: Option<u32>,
     header: &ext4_extent_header,
     extents: &[ext4_extent],
 ) -> Result<()> {
+    let block_addr = block_addr.into();
     {
         let mut block = Block::get(bdev, block_addr)?;
 
@@ -692,6 +703,8 @@ pub(super) fn write_extents_to_block<D: BlockDevice>(
                     *(data[offset..].as_mut_ptr() as *mut ext4_extent) = *extent;
                 }
             }
+
+            super::checksum::update_checksum(csum_seed, data);
         })?;
     } // block dropped here, marked dirty automatically
 
@@ -738,11 +751,12 @@ fn write_indices_to_inode<D: BlockDevice>(
 /// 写入 index 数组到块
 fn write_indices_to_block<D: BlockDevice>(
     bdev: &mut crate::block::BlockDev<D>,
-    block_addr: u64,
-    _block_size: u32,
+    block_addr: impl Into<Pblk>,
+    csum_seed: Option<u32>,
     header: &ext4_extent_header,
     indices: &[ext4_extent_idx],
 ) -> Result<()> {
+    let block_addr = block_addr.into();
     {
         let mut block = Block::get(bdev, block_addr)?;
 
@@ -765,6 +779,8 @@ fn write_indices_to_block<D: BlockDevice>(
                     *(data[offset..].as_mut_ptr() as *mut ext4_extent_idx) = *idx;
                 }
             }
+
+            super::checksum::update_checksum(csum_seed, data);
         })?;
     } // block dropped here, marked dirty automatically
 
@@ -780,4 +796,27 @@ mod tests {
         // 需要实际的块设备和 ext4 文件系统进行测试
         // 主要验证 API 编译和基本逻辑
     }
+
+    #[test]
+    fn test_select_index() {
+        let idx = |block: u32, pblk: u64| {
+            let mut idx = ext4_extent_idx {
+                block: block.to_le(),
+                leaf_lo: 0,
+                leaf_hi: 0,
+                unused: 0,
+            };
+            ext4_idx_store_pblock(&mut idx, pblk);
+            idx
+        };
+        let indices = [idx(10, 100), idx(50, 200), idx(90, 300)];
+
+        let pick = |logical| select_index(&indices, logical).map(ext4_idx_pblock);
+        assert_eq!(pick(0), Some(100)); // 位于所有索引之前时取第一个
+        assert_eq!(pick(10), Some(100));
+        assert_eq!(pick(49), Some(100));
+        assert_eq!(pick(50), Some(200));
+        assert_eq!(pick(u32::MAX), Some(300));
+        assert!(select_index(&[], 0).is_none());
+    }
 }
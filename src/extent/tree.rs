@@ -4,10 +4,38 @@ use crate::{
     block::{Block, BlockDev, BlockDevice},
     error::{Error, ErrorKind, Result},
     inode::Inode,
-    types::{ext4_extent, ext4_extent_header, ext4_extent_idx, ext4_inode},
+    logging::ext4_log,
+    parse,
+    types::{ext4_extent, ext4_extent_header, ext4_extent_idx, ext4_inode, Lblk, Pblk},
 };
-use log::*;
+use super::unwritten::{get_actual_len, is_unwritten};
 use alloc::vec;
+use alloc::vec::Vec;
+
+/// 单次设备读取合并的最大块数
+const MAX_READ_RUN: u32 = 256;
+
+/// 一段连续映射的逻辑块范围
+///
+/// 对应 extent 树叶子节点中的一个 `ext4_extent`，已经解码为主机字节序
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ExtentRange {
+    /// 起始逻辑块号
+    pub logical_start: u32,
+    /// 块数
+    pub len: u32,
+    /// 起始物理块号
+    pub physical_start: u64,
+    /// 是否为 unwritten extent（读取时应视为全零）
+    pub unwritten: bool,
+}
+
+impl ExtentRange {
+    /// 结束逻辑块号（不包含）
+    pub fn logical_end(&self) -> u64 {
+        self.logical_start as u64 + self.len as u64
+    }
+}
 
 /// Extent 树遍历器
 ///
@@ -16,6 +44,8 @@ pub struct ExtentTree<'a, D: BlockDevice> {
     bdev: &'a mut BlockDev<D>,
     block_size: u32,
     device_total_blocks: u64,
+    /// 非 None 时读取子节点后用该种子验证 extent 块校验和
+    csum_seed: Option<u32>,
 }
 
 impl<'a, D: BlockDevice> ExtentTree<'a, D> {
@@ -26,7 +56,39 @@ impl<'a, D: BlockDevice> ExtentTree<'a, D> {
             bdev,
             block_size,
             device_total_blocks,
+            csum_seed: None,
+        }
+    }
+
+    /// 读取子节点时验证 extent 块校验和
+    ///
+    /// `seed` 为 inode 的校验和种子（见 [`InodeRef::csum_seed`](crate::fs::InodeRef::csum_seed)），
+    /// 为 `None` 时不验证
+    pub fn with_csum_seed(mut self, seed: Option<u32>) -> Self {
+        self.csum_seed = seed;
+        self
+    }
+
+    /// 读取子节点并检查头部和校验和
+    fn read_child_node(&mut self, block_addr: u64) -> Result<Vec<u8>> {
+        let child_data = {
+            let mut block = Block::get(self.bdev, block_addr)?;
+            block.with_data(|data| data.to_vec())?
+        };
+
+        node_header(&child_data)?;
+
+        if let Some(seed) = self.csum_seed {
+            if !super::checksum::verify_checksum_with_seed(seed, &child_data) {
+                return Err(Error::with_block(
+                    ErrorKind::Corrupted,
+                    "Extent block checksum mismatch",
+                    block_addr,
+                ));
+            }
         }
+
+        Ok(child_data)
     }
 
 
@@ -69,16 +131,7 @@ impl<'a, D: BlockDevice> ExtentTree<'a, D> {
         };
 
         // 解析根节点的 extent header
-        let header = unsafe {
-            core::ptr::read_unaligned(root_data.as_ptr() as *const ext4_extent_header)
-        };
-
-        if !header.is_valid() {
-            return Err(Error::new(
-                ErrorKind::Corrupted,
-                "Invalid extent header magic",
-            ));
-        }
+        let header = node_header(root_data)?;
 
         // 从根节点开始查找
         self.find_extent_in_node(root_data, &header, logical_block)
@@ -128,10 +181,11 @@ impl<'a, D: BlockDevice> ExtentTree<'a, D> {
 
             let extent_start = extent.logical_block();
             let extent_len = extent.actual_len() as u32;
-            let extent_end = extent_start + extent_len;
+            // 用 u64 计算，损坏的 extent 可能越过 2^32
+            let extent_end = extent_start as u64 + extent_len as u64;
 
             // 检查逻辑块是否在这个 extent 范围内
-            if logical_block >= extent_start && logical_block < extent_end {
+            if logical_block >= extent_start && (logical_block as u64) < extent_end {
                 let offset_in_extent = logical_block - extent_start;
                 let extent_physical_base = extent.physical_block();
                 let physical_block = extent_physical_base + offset_in_extent as u64;
@@ -141,7 +195,7 @@ impl<'a, D: BlockDevice> ExtentTree<'a, D> {
                 let start_hi = u16::from_le(extent.start_hi);
 
                 // 记录详细日志
-                info!(
+                ext4_log!(Extent, hot,
                     "[EXTENT READ] logical={}, found in extent[{}]: range=[{}-{}], \
                      physical_base={:#x}, physical_result={:#x}, start_hi={:#x}, start_lo={:#x}",
                     logical_block, i, extent_start, extent_end - 1,
@@ -150,7 +204,7 @@ impl<'a, D: BlockDevice> ExtentTree<'a, D> {
 
                 // 🔧 边界检查：验证物理块号是否在设备范围内
                 if physical_block >= self.device_total_blocks {
-                    error!(
+                    ext4_log!(Extent, Error,
                         "[EXTENT READ] Physical block OUT OF BOUNDS! \
                          physical={:#x}, device_total={}, extent_base={:#x}, \
                          start_hi={:#x}, start_lo={:#x}, offset_in_extent={}",
@@ -212,36 +266,141 @@ impl<'a, D: BlockDevice> ExtentTree<'a, D> {
 
         if let Some(idx) = target_idx {
             // 读取子节点
-            let child_block = idx.leaf_block();
-            let mut block = Block::get(self.bdev, child_block)?;
-
-            // 复制子节点数据到独立的缓冲区
-            let child_data = block.with_data(|data| {
-                let mut buf = alloc::vec![0u8; data.len()];
-                buf.copy_from_slice(data);
-                buf
-            })?;
-
-            // 释放 block，这样我们就可以递归调用了
-            drop(block);
-
-            // 解析子节点的头部
-            let child_header = unsafe {
-                core::ptr::read_unaligned(child_data.as_ptr() as *const ext4_extent_header)
-            };
+            let child_data = self.read_child_node(idx.leaf_block())?;
+            let child_header = child_header(&child_data, header)?;
+
+            // 递归查找
+            self.find_extent_in_node(&child_data, &child_header, logical_block)
+        } else {
+            Ok(None)
+        }
+    }
+
+    /// 收集 extent 树中的所有映射范围（内部实现，在 with_inode 闭包内使用）
+    ///
+    /// 按逻辑块号升序返回所有叶子 extent，空洞不会出现在结果中。
+    ///
+    /// # 参数
+    ///
+    /// * `inode` - ext4_inode 引用（通常从 InodeRef::with_inode 闭包获得）
+    pub(crate) fn collect_ranges_internal(&mut self, inode: &ext4_inode) -> Result<Vec<ExtentRange>> {
+        let flags = u32::from_le(inode.flags);
+        if flags & 0x80000 == 0 {  // EXT4_EXTENTS_FL
+            return Err(Error::new(
+                ErrorKind::Unsupported,
+                "Inode does not use extents",
+            ));
+        }
+
+        let root_data = unsafe {
+            core::slice::from_raw_parts(inode.blocks.as_ptr() as *const u8, 60)
+        };
+        let header = node_header(root_data)?;
+
+        let mut ranges = Vec::new();
+        self.collect_ranges_in_node(root_data, &header, &mut ranges, None)?;
+        Ok(ranges)
+    }
+
+    /// 收集 extent 树中所有非根节点（索引节点和叶子节点）所在的物理块
+    ///
+    /// 按深度优先顺序返回，用于扫描/校验树本身占用的元数据块。
+    ///
+    /// # 参数
+    ///
+    /// * `inode` - ext4_inode 引用（通常从 InodeRef::with_inode 闭包获得）
+    pub(crate) fn collect_node_blocks_internal(&mut self, inode: &ext4_inode) -> Result<Vec<u64>> {
+        let flags = u32::from_le(inode.flags);
+        if flags & 0x80000 == 0 {  // EXT4_EXTENTS_FL
+            return Err(Error::new(
+                ErrorKind::Unsupported,
+                "Inode does not use extents",
+            ));
+        }
+
+        let root_data = unsafe {
+            core::slice::from_raw_parts(inode.blocks.as_ptr() as *const u8, 60)
+        };
+        let header = node_header(root_data)?;
 
-            if !child_header.is_valid() {
+        let mut ranges = Vec::new();
+        let mut nodes = Vec::new();
+        self.collect_ranges_in_node(root_data, &header, &mut ranges, Some(&mut nodes))?;
+        Ok(nodes)
+    }
+
+    /// 递归收集节点下的所有 extent
+    ///
+    /// `nodes` 不为 `None` 时同时记录访问过的子节点块号
+    fn collect_ranges_in_node(
+        &mut self,
+        node_data: &[u8],
+        header: &ext4_extent_header,
+        ranges: &mut Vec<ExtentRange>,
+        mut nodes: Option<&mut Vec<u64>>,
+    ) -> Result<()> {
+        let entries = header.entries_count() as usize;
+        let header_size = core::mem::size_of::<ext4_extent_header>();
+
+        if header.is_leaf() {
+            let extent_size = core::mem::size_of::<ext4_extent>();
+            for i in 0..entries {
+                let offset = header_size + i * extent_size;
+                if offset + extent_size > node_data.len() {
+                    return Err(Error::new(
+                        ErrorKind::Corrupted,
+                        "Extent node data too short",
+                    ));
+                }
+
+                let extent = unsafe {
+                    core::ptr::read_unaligned(
+                        node_data[offset..].as_ptr() as *const ext4_extent
+                    )
+                };
+
+                let len = get_actual_len(&extent) as u32;
+                if len == 0 {
+                    continue;
+                }
+
+                ranges.push(ExtentRange {
+                    logical_start: extent.logical_block(),
+                    len,
+                    physical_start: extent.physical_block(),
+                    unwritten: is_unwritten(&extent),
+                });
+            }
+            return Ok(());
+        }
+
+        let idx_size = core::mem::size_of::<ext4_extent_idx>();
+        for i in 0..entries {
+            let offset = header_size + i * idx_size;
+            if offset + idx_size > node_data.len() {
                 return Err(Error::new(
                     ErrorKind::Corrupted,
-                    "Invalid extent header in child node",
+                    "Extent index node data too short",
                 ));
             }
 
-            // 递归查找
-            self.find_extent_in_node(&child_data, &child_header, logical_block)
-        } else {
-            Ok(None)
+            let idx = unsafe {
+                core::ptr::read_unaligned(
+                    node_data[offset..].as_ptr() as *const ext4_extent_idx
+                )
+            };
+
+            if let Some(nodes) = nodes.as_deref_mut() {
+                nodes.push(idx.leaf_block());
+            }
+
+            let child_data = self.read_child_node(idx.leaf_block())?;
+            let child_header = child_header(&child_data, header)?;
+
+            self.collect_ranges_in_node(&child_data, &child_header, ranges, nodes.as_deref_mut())?;
         }
+
+        Ok(())
     }
 
     /// 将逻辑块号映射到物理块号
@@ -260,8 +419,13 @@ impl<'a, D: BlockDevice> ExtentTree<'a, D> {
     /// 此方法接受 `Inode` 包装类型，内部会访问其 `ext4_inode` 数据。
     /// 在单线程场景下安全使用。在需要保证数据一致性的场景，
     /// 应在 `InodeRef::with_inode` 闭包内使用 `map_block_internal`。
-    pub fn map_block(&mut self, inode: &Inode, logical_block: u32) -> Result<Option<u64>> {
-        self.map_block_internal(inode.inner(), logical_block)
+    pub fn map_block(
+        &mut self,
+        inode: &Inode,
+        logical_block: impl Into<Lblk>,
+    ) -> Result<Option<Pblk>> {
+        let Lblk(logical_block) = logical_block.into();
+        Ok(self.map_block_internal(inode.inner(), logical_block)?.map(Pblk))
     }
 
     /// 读取文件的某个逻辑块
@@ -312,6 +476,8 @@ impl<'a, D: BlockDevice> ExtentTree<'a, D> {
     ///
     /// 实际读取的字节数
     ///
+    /// 空洞和 unwritten extent 读出为全零，不产生设备 I/O。
+    ///
     /// # 使用场景
     ///
     /// 此方法设计为在 `InodeRef::with_inode` 闭包内使用，保证数据一致性。
@@ -338,28 +504,60 @@ impl<'a, D: BlockDevice> ExtentTree<'a, D> {
         let to_read = core::cmp::min(buf.len() as u64, remaining) as usize;
 
         let block_size = self.block_size as u64;
+        let last_block = ((offset + to_read as u64 - 1) / block_size) as u32;
         let mut bytes_read = 0;
+        let mut run_buf = Vec::new();
+
+        // 一次取出全部映射，空洞和 unwritten extent 直接填零，不访问设备
+        let ranges = self.collect_ranges_internal(inode)?;
+        let mut idx = 0;
 
         while bytes_read < to_read {
             let current_offset = offset + bytes_read as u64;
             let block_num = (current_offset / block_size) as u32;
             let block_offset = (current_offset % block_size) as usize;
 
-            // 计算本次读取的字节数
-            let bytes_in_block = core::cmp::min(
-                block_size as usize - block_offset,
-                to_read - bytes_read,
-            );
-
-            // 读取块
-            let mut block_buf = alloc::vec![0u8; block_size as usize];
-            self.read_block(inode, block_num, &mut block_buf)?;
-
-            // 复制数据到输出缓冲区
-            buf[bytes_read..bytes_read + bytes_in_block]
-                .copy_from_slice(&block_buf[block_offset..block_offset + bytes_in_block]);
+            while idx < ranges.len() && ranges[idx].logical_end() <= block_num as u64 {
+                idx += 1;
+            }
 
-            bytes_read += bytes_in_block;
+            let range = ranges.get(idx).filter(|r| r.logical_start <= block_num);
+            match range {
+                Some(r) if !r.unwritten => {
+                    // 同一 extent 内的块物理连续，一次设备读取完成
+                    let in_extent = block_num - r.logical_start;
+                    let run = (r.len - in_extent)
+                        .min(MAX_READ_RUN)
+                        .min(last_block - block_num + 1);
+                    let physical = r.physical_start + in_extent as u64;
+
+                    if physical + run as u64 > self.device_total_blocks {
+                        return Err(Error::new(
+                            ErrorKind::Corrupted,
+                            "Physical block address exceeds device size",
+                        ));
+                    }
+
+                    run_buf.resize(run as usize * block_size as usize, 0);
+                    self.bdev.read_blocks(physical, run, &mut run_buf)?;
+
+                    let n = core::cmp::min(run_buf.len() - block_offset, to_read - bytes_read);
+                    buf[bytes_read..bytes_read + n]
+                        .copy_from_slice(&run_buf[block_offset..block_offset + n]);
+                    bytes_read += n;
+                }
+                _ => {
+                    // 空洞或 unwritten extent：填零到下一个有数据的位置
+                    let zero_end = match (range, ranges.get(idx)) {
+                        (Some(r), _) => r.logical_end() * block_size,
+                        (None, Some(next)) => next.logical_start as u64 * block_size,
+                        (None, None) => u64::MAX,
+                    };
+                    let n = core::cmp::min(zero_end - current_offset, (to_read - bytes_read) as u64) as usize;
+                    buf[bytes_read..bytes_read + n].fill(0);
+                    bytes_read += n;
+                }
+            }
         }
 
         Ok(bytes_read)
@@ -391,6 +589,24 @@ impl<'a, D: BlockDevice> ExtentTree<'a, D> {
     }
 }
 
+/// 检查节点头部（见 [`parse::extent_header`]）后读出
+fn node_header(node: &[u8]) -> Result<ext4_extent_header> {
+    parse::extent_header(node)?;
+    // SAFETY: extent_header 已确认 node 至少有 12 字节
+    Ok(unsafe { core::ptr::read_unaligned(node.as_ptr() as *const ext4_extent_header) })
+}
+
+/// 读出子节点头部，并检查它的深度比父节点小 1
+///
+/// 深度逐层递减保证了递归一定终止，即使损坏的索引指回祖先节点
+fn child_header(node: &[u8], parent: &ext4_extent_header) -> Result<ext4_extent_header> {
+    let header = node_header(node)?;
+    if header.depth() + 1 != parent.depth() {
+        return Err(Error::new(ErrorKind::Corrupted, "unexpected extent depth"));
+    }
+    Ok(header)
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -416,6 +632,17 @@ mod tests {
         assert!(!header.is_leaf());
     }
 
+    #[test]
+    fn test_extent_range_logical_end() {
+        let range = ExtentRange {
+            logical_start: u32::MAX - 1,
+            len: 4,
+            physical_start: 100,
+            unwritten: false,
+        };
+        assert_eq!(range.logical_end(), u32::MAX as u64 + 3);
+    }
+
     #[test]
     fn test_extent_physical_block() {
         let mut extent = ext4_extent::default();
@@ -425,4 +652,84 @@ mod tests {
         let physical = extent.physical_block();
         assert_eq!(physical, 0x0000ABCD12345678u64);
     }
+
+    struct CountingDevice {
+        storage: Vec<u8>,
+        reads: u32,
+    }
+
+    impl BlockDevice for CountingDevice {
+        fn block_size(&self) -> u32 {
+            4096
+        }
+
+        fn sector_size(&self) -> u32 {
+            512
+        }
+
+        fn total_blocks(&self) -> u64 {
+            (self.storage.len() / 4096) as u64
+        }
+
+        fn read_blocks(&mut self, lba: u64, count: u32, buf: &mut [u8]) -> Result<usize> {
+            self.reads += 1;
+            let start = lba as usize * 512;
+            let len = count as usize * 512;
+            buf[..len].copy_from_slice(&self.storage[start..start + len]);
+            Ok(len)
+        }
+
+        fn write_blocks(&mut self, _lba: u64, count: u32, _buf: &[u8]) -> Result<usize> {
+            Ok(count as usize * 512)
+        }
+    }
+
+    #[test]
+    fn test_read_file_holes_without_io() {
+        let mut storage = alloc::vec![0u8; 32 * 4096];
+        storage[10 * 4096..11 * 4096].fill(0xAA);
+        storage[11 * 4096..12 * 4096].fill(0xBB);
+        storage[20 * 4096..21 * 4096].fill(0xCC); // unwritten extent 的陈旧数据
+        let mut bdev = BlockDev::new(CountingDevice { storage, reads: 0 }).unwrap();
+
+        // 布局：块 0 -> 10，块 1 空洞，块 2 unwritten -> 20，块 3 -> 11，块 4 空洞（文件末尾）
+        let mut inode = ext4_inode {
+            flags: 0x80000u32.to_le(),
+            size_lo: (5 * 4096u32).to_le(),
+            ..Default::default()
+        };
+        let header = ext4_extent_header {
+            magic: 0xF30Au16.to_le(),
+            entries: 3u16.to_le(),
+            max: 4u16.to_le(),
+            depth: 0,
+            generation: 0,
+        };
+        let extents = [(0u32, 1u16, 10u32), (2, 32768 + 1, 20), (3, 1, 11)];
+        unsafe {
+            let root = inode.blocks.as_mut_ptr() as *mut u8;
+            core::ptr::write_unaligned(root as *mut ext4_extent_header, header);
+            for (i, &(block, len, start)) in extents.iter().enumerate() {
+                let extent = ext4_extent {
+                    block: block.to_le(),
+                    len: len.to_le(),
+                    start_hi: 0,
+                    start_lo: start.to_le(),
+                };
+                core::ptr::write_unaligned(root.add(12 + i * 12) as *mut ext4_extent, extent);
+            }
+        }
+
+        let mut buf = alloc::vec![0xFFu8; 5 * 4096];
+        let mut tree = ExtentTree::new(&mut bdev, 4096);
+        assert_eq!(tree.read_file_internal(&inode, 0, &mut buf).unwrap(), 5 * 4096);
+
+        assert!(buf[..4096].iter().all(|&b| b == 0xAA));
+        assert!(buf[4096..3 * 4096].iter().all(|&b| b == 0));
+        assert!(buf[3 * 4096..4 * 4096].iter().all(|&b| b == 0xBB));
+        assert!(buf[4 * 4096..].iter().all(|&b| b == 0));
+
+        // 只有两个已写入的 extent 产生设备读取
+        assert_eq!(bdev.device().reads, 2);
+    }
 }
@@ -22,16 +22,17 @@
 //! - `mark_unwritten()` - 将 written 转为 unwritten
 //! - `split_extent_at()` - 在指定位置分裂 extent
 //! - `convert_to_initialized()` - 转换部分 unwritten extent 为 initialized
+//! - `set_extent_unwritten()` - 不分裂地设置整个 extent 的状态（任意深度）
 //! - `zero_unwritten_range()` - 零填充未写入区域
 
 use crate::{
     balloc::BlockAllocator,
-    block::BlockDevice,
+    block::{Block, BlockDevice},
+    consts::EXT4_FEATURE_RO_COMPAT_METADATA_CSUM,
     error::{Error, ErrorKind, Result},
-    extent::write::insert_extent_simple,
+    extent::{checksum::set_checksum, write::insert_extent_simple},
     fs::InodeRef,
-    superblock::Superblock,
-    types::ext4_extent,
+    types::{ext4_extent, ext4_extent_header, ext4_extent_idx, Pblk},
 };
 
 /// 已初始化 extent 的最大长度（2^15 = 32768）
@@ -110,7 +111,8 @@ pub fn get_actual_len(extent: &ext4_extent) -> u16 {
 ///
 /// * `extent` - extent 引用
 /// * `pblock` - 物理块号
-pub fn store_pblock(extent: &mut ext4_extent, pblock: u64) {
+pub fn store_pblock(extent: &mut ext4_extent, pblock: impl Into<Pblk>) {
+    let pblock = pblock.into().0;
     extent.start_lo = (pblock as u32).to_le();
     extent.start_hi = ((pblock >> 32) as u16).to_le();
 }
@@ -140,7 +142,6 @@ pub fn get_pblock(extent: &ext4_extent) -> u64 {
 /// # 参数
 ///
 /// * `inode_ref` - inode 引用
-/// * `sb` - superblock
 /// * `extent_idx` - 要分裂的 extent 在数组中的索引
 /// * `split` - 分裂点的逻辑块号
 /// * `split_flag` - 分裂标志（EXT4_EXT_MARK_UNWRIT1/2）
@@ -155,7 +156,6 @@ pub fn get_pblock(extent: &ext4_extent) -> u64 {
 /// 对于多层树，请使用 `unwritten_multilevel::split_extent_at_multilevel`。
 pub fn split_extent_at<D: BlockDevice>(
     inode_ref: &mut InodeRef<D>,
-    _sb: &mut Superblock,
     extent_idx: usize,
     split: u32,
     split_flag: u32,
@@ -285,7 +285,6 @@ pub fn split_extent_at<D: BlockDevice>(
 /// # 参数
 ///
 /// * `inode_ref` - inode 引用
-/// * `sb` - superblock
 /// * `extent_idx` - extent 在数组中的索引
 /// * `split` - 转换范围的起始逻辑块号
 /// * `blocks` - 转换的块数量
@@ -295,7 +294,6 @@ pub fn split_extent_at<D: BlockDevice>(
 /// 成功返回 Ok(())
 pub fn convert_to_initialized<D: BlockDevice>(
     inode_ref: &mut InodeRef<D>,
-    sb: &mut Superblock,
     extent_idx: usize,
     split: u32,
     blocks: u32,
@@ -334,14 +332,13 @@ pub fn convert_to_initialized<D: BlockDevice>(
 
     // Case 1: 转换范围在结尾 [unwritten][initialized]
     if split + blocks == ee_block + ee_len {
-        return split_extent_at(inode_ref, sb, extent_idx, split, EXT4_EXT_MARK_UNWRIT1);
+        return split_extent_at(inode_ref, extent_idx, split, EXT4_EXT_MARK_UNWRIT1);
     }
 
     // Case 2: 转换范围在开头 [initialized][unwritten]
     if ee_block == split {
         return split_extent_at(
             inode_ref,
-            sb,
             extent_idx,
             split + blocks,
             EXT4_EXT_MARK_UNWRIT2,
@@ -354,7 +351,6 @@ pub fn convert_to_initialized<D: BlockDevice>(
     // 第一次分裂：split + blocks 处，将后半部分标spport记为 unwritten
     split_extent_at(
         inode_ref,
-        sb,
         extent_idx,
         split + blocks,
         EXT4_EXT_MARK_UNWRIT1 | EXT4_EXT_MARK_UNWRIT2,
@@ -362,11 +358,119 @@ pub fn convert_to_initialized<D: BlockDevice>(
 
     // 第二次分裂：split 处，将前半部分标记为 unwritten
     // 注意：第一次分裂后，extent_idx 位置的 extent 仍然是我们要分裂的那个
-    split_extent_at(inode_ref, sb, extent_idx, split, EXT4_EXT_MARK_UNWRIT1)?;
+    split_extent_at(inode_ref, extent_idx, split, EXT4_EXT_MARK_UNWRIT1)?;
 
     Ok(())
 }
 
+/// 原地设置包含 `logical_block` 的整个 extent 的 unwritten 标记
+///
+/// 与 [`convert_to_initialized`] 不同，不分裂 extent，因此支持任意深度的树且不需要分配块。
+/// 转为已初始化后 extent 中的块读出磁盘上的实际内容，需要部分转换时由调用者
+/// 配合 [`remove_space`](super::remove_space) 把 extent 拆开。
+///
+/// # 返回
+///
+/// 状态被修改的 extent 的 `(起始逻辑块, 块数)`；`logical_block` 不在 extent 中
+/// 或 extent 已经是目标状态时返回 `None`
+pub fn set_extent_unwritten<D: BlockDevice>(
+    inode_ref: &mut InodeRef<D>,
+    logical_block: u32,
+    unwritten: bool,
+) -> Result<Option<(u32, u32)>> {
+    let header_size = core::mem::size_of::<ext4_extent_header>();
+    let entry_size = core::mem::size_of::<ext4_extent>();
+
+    let mut node = inode_ref.with_inode(|inode| {
+        let root = unsafe { core::slice::from_raw_parts(inode.blocks.as_ptr() as *const u8, 60) };
+        root.to_vec()
+    })?;
+    // 叶子所在的块，`None` 表示 inode 中的根节点
+    let mut leaf_block = None;
+
+    let header = loop {
+        let header = unsafe { core::ptr::read_unaligned(node.as_ptr() as *const ext4_extent_header) };
+        if !header.is_valid() {
+            return Err(Error::new(ErrorKind::Corrupted, "Invalid extent header magic"));
+        }
+        if header.is_leaf() {
+            break header;
+        }
+
+        // 最后一个起始块不大于 logical_block 的索引
+        let mut child = None;
+        for i in 0..header.entries_count() as usize {
+            let offset = header_size + i * entry_size;
+            if offset + entry_size > node.len() {
+                return Err(Error::new(ErrorKind::Corrupted, "Extent index node data too short"));
+            }
+            let idx = unsafe { core::ptr::read_unaligned(node[offset..].as_ptr() as *const ext4_extent_idx) };
+            if idx.logical_block() > logical_block {
+                break;
+            }
+            child = Some(idx.leaf_block());
+        }
+        let Some(child) = child else {
+            return Ok(None);
+        };
+        node = Block::get(inode_ref.bdev(), child)?.with_data(|data| data.to_vec())?;
+        leaf_block = Some(child);
+    };
+
+    let mut found = None;
+    for i in 0..header.entries_count() as usize {
+        let offset = header_size + i * entry_size;
+        if offset + entry_size > node.len() {
+            return Err(Error::new(ErrorKind::Corrupted, "Extent node data too short"));
+        }
+        let extent = unsafe { core::ptr::read_unaligned(node[offset..].as_ptr() as *const ext4_extent) };
+        let start = u32::from_le(extent.block);
+        let len = get_actual_len(&extent) as u32;
+        if logical_block >= start && logical_block < start + len {
+            found = (is_unwritten(&extent) != unwritten).then_some((offset, start, len));
+            break;
+        }
+    }
+    let Some((offset, start, len)) = found else {
+        return Ok(None);
+    };
+
+    let mark = |data: &mut [u8]| {
+        let ptr = data[offset..].as_mut_ptr() as *mut ext4_extent;
+        let mut extent = unsafe { core::ptr::read_unaligned(ptr) };
+        if unwritten {
+            mark_unwritten(&mut extent);
+        } else {
+            mark_initialized(&mut extent);
+        }
+        unsafe { core::ptr::write_unaligned(ptr, extent) };
+    };
+
+    match leaf_block {
+        None => {
+            inode_ref.with_inode_mut(|inode| {
+                let root = unsafe { core::slice::from_raw_parts_mut(inode.blocks.as_mut_ptr() as *mut u8, 60) };
+                mark(root);
+            })?;
+            inode_ref.mark_dirty()?;
+        }
+        Some(addr) => {
+            let ino = inode_ref.inode_num();
+            let generation = inode_ref.with_inode(|inode| u32::from_le(inode.generation))?;
+            let (bdev, sb) = inode_ref.bdev_and_sb_mut();
+            let csum = sb.has_ro_compat_feature(EXT4_FEATURE_RO_COMPAT_METADATA_CSUM);
+            Block::get(bdev, addr)?.with_data_mut(|data| {
+                mark(data);
+                if csum {
+                    set_checksum(sb, ino, generation, data);
+                }
+            })?;
+        }
+    }
+
+    Ok(Some((start, len)))
+}
+
 /// 零填充 unwritten extent 的指定范围
 ///
 /// 对应 lwext4 的 `ext4_ext_zero_unwritten_range()`
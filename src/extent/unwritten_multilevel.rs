@@ -17,8 +17,7 @@ use crate::{
     block::{Block, BlockDevice},
     error::{Error, ErrorKind, Result},
     fs::InodeRef,
-    superblock::Superblock,
-    types::{ext4_extent, ext4_extent_header},
+    types::{ext4_extent, ext4_extent_header, Lblk},
 };
 
 use super::{
@@ -41,7 +40,6 @@ use alloc::vec::Vec;
 ///
 /// * `writer` - ExtentWriter（包含 transaction）
 /// * `inode_ref` - Inode 引用
-/// * `sb` - Superblock 引用
 /// * `allocator` - 块分配器
 /// * `logical_block` - 要分裂的逻辑块号
 /// * `split_flag` - 分裂标志（UNWRIT1/UNWRIT2）
@@ -65,11 +63,13 @@ use alloc::vec::Vec;
 pub fn split_extent_at_multilevel<D: BlockDevice>(
     writer: &mut ExtentWriter<D>,
     inode_ref: &mut InodeRef<D>,
-    sb: &mut Superblock,
     allocator: &mut BlockAllocator,
-    logical_block: u32,
+    logical_block: impl Into<Lblk>,
     split_flag: u32,
 ) -> Result<()> {
+    let Lblk(logical_block) = logical_block.into();
+    let block_size = inode_ref.sb().block_size();
+
     // 1. 查找 extent 路径
     let mut path = writer.find_extent_path(inode_ref, logical_block)?;
 
@@ -87,7 +87,7 @@ pub fn split_extent_at_multilevel<D: BlockDevice>(
         inode_ref,
         leaf_block_addr,
         leaf_node_type,
-        sb.block_size(),
+        block_size,
         logical_block,
     )?;
 
@@ -107,7 +107,7 @@ pub fn split_extent_at_multilevel<D: BlockDevice>(
             inode_ref,
             leaf_block_addr,
             leaf_node_type,
-            sb.block_size(),
+            block_size,
             extent_idx,
             split_flag,
         );
@@ -122,7 +122,6 @@ pub fn split_extent_at_multilevel<D: BlockDevice>(
         let leaf_at = path.nodes.len() - 1;
         writer.split_extent_node(
             inode_ref,
-            sb,
             allocator,
             &mut path,
             leaf_at,
@@ -141,7 +140,7 @@ pub fn split_extent_at_multilevel<D: BlockDevice>(
             inode_ref,
             new_leaf.block_addr,
             new_leaf.node_type,
-            sb.block_size(),
+            block_size,
             logical_block,
             split_flag,
         );
@@ -152,7 +151,7 @@ pub fn split_extent_at_multilevel<D: BlockDevice>(
         inode_ref,
         leaf_block_addr,
         leaf_node_type,
-        sb.block_size(),
+        block_size,
         logical_block,
         split_flag,
     )
@@ -238,6 +237,7 @@ fn change_extent_status<D: BlockDevice>(
 
         inode_ref.mark_dirty();
     } else {
+        let csum_seed = inode_ref.extent_csum_seed()?;
         let mut block = Block::get(inode_ref.bdev(), block_addr)?;
 
         block.with_data_mut(|data| -> Result<()> {
@@ -255,6 +255,7 @@ fn change_extent_status<D: BlockDevice>(
                 mark_initialized(extent);
             }
 
+            super::checksum::update_checksum(csum_seed, data);
             Ok(())
         })??;
     }
@@ -334,10 +335,11 @@ fn split_extent_in_leaf<D: BlockDevice>(
     if node_type == ExtentNodeType::Root {
         write_extents_to_inode(inode_ref, &new_header, &extents)?;
     } else {
+        let csum_seed = inode_ref.extent_csum_seed()?;
         write_extents_to_block(
             inode_ref.bdev(),
             block_addr,
-            block_size,
+            csum_seed,
             &new_header,
             &extents,
         )?;
@@ -352,7 +354,6 @@ fn split_extent_in_leaf<D: BlockDevice>(
 ///
 /// * `writer` - ExtentWriter
 /// * `inode_ref` - Inode 引用
-/// * `sb` - Superblock 引用
 /// * `allocator` - 块分配器
 /// * `logical_block` - 逻辑块号
 /// * `count` - 要转换的块数量
@@ -363,11 +364,12 @@ fn split_extent_in_leaf<D: BlockDevice>(
 pub fn convert_to_initialized_multilevel<D: BlockDevice>(
     writer: &mut ExtentWriter<D>,
     inode_ref: &mut InodeRef<D>,
-    sb: &mut Superblock,
     allocator: &mut BlockAllocator,
-    logical_block: u32,
+    logical_block: impl Into<Lblk>,
     count: u32,
 ) -> Result<u32> {
+    let Lblk(logical_block) = logical_block.into();
+    let block_size = inode_ref.sb().block_size();
     let mut converted = 0u32;
     let mut current_block = logical_block;
     let end_block = logical_block + count;
@@ -386,7 +388,7 @@ pub fn convert_to_initialized_multilevel<D: BlockDevice>(
                 inode_ref,
                 leaf.block_addr,
                 leaf.node_type,
-                sb.block_size(),
+                block_size,
                 current_block,
             )?;
 
@@ -413,7 +415,7 @@ pub fn convert_to_initialized_multilevel<D: BlockDevice>(
                 inode_ref,
                 leaf.block_addr,
                 leaf.node_type,
-                sb.block_size(),
+                block_size,
                 extent_idx,
                 0, // mark as initialized
             )?;
@@ -425,7 +427,6 @@ pub fn convert_to_initialized_multilevel<D: BlockDevice>(
             split_extent_at_multilevel(
                 writer,
                 inode_ref,
-                sb,
                 allocator,
                 convert_end,
                 EXT4_EXT_MARK_UNWRIT2, // 第二部分保持 unwritten
@@ -438,7 +439,7 @@ pub fn convert_to_initialized_multilevel<D: BlockDevice>(
                 inode_ref,
                 new_leaf.block_addr,
                 new_leaf.node_type,
-                sb.block_size(),
+                block_size,
                 convert_start,
             )?;
 
@@ -446,7 +447,7 @@ pub fn convert_to_initialized_multilevel<D: BlockDevice>(
                 inode_ref,
                 new_leaf.block_addr,
                 new_leaf.node_type,
-                sb.block_size(),
+                block_size,
                 new_idx,
                 0, // initialized
             )?;
@@ -458,7 +459,6 @@ pub fn convert_to_initialized_multilevel<D: BlockDevice>(
             split_extent_at_multilevel(
                 writer,
                 inode_ref,
-                sb,
                 allocator,
                 convert_start,
                 EXT4_EXT_MARK_UNWRIT1, // 第一部分保持 unwritten
@@ -471,7 +471,7 @@ pub fn convert_to_initialized_multilevel<D: BlockDevice>(
                 inode_ref,
                 new_leaf.block_addr,
                 new_leaf.node_type,
-                sb.block_size(),
+                block_size,
                 convert_start,
             )?;
 
@@ -479,7 +479,7 @@ pub fn convert_to_initialized_multilevel<D: BlockDevice>(
                 inode_ref,
                 new_leaf.block_addr,
                 new_leaf.node_type,
-                sb.block_size(),
+                block_size,
                 new_idx,
                 0, // initialized
             )?;
@@ -492,7 +492,6 @@ pub fn convert_to_initialized_multilevel<D: BlockDevice>(
             split_extent_at_multilevel(
                 writer,
                 inode_ref,
-                sb,
                 allocator,
                 convert_start,
                 EXT4_EXT_MARK_UNWRIT1 | EXT4_EXT_MARK_UNWRIT2,
@@ -502,7 +501,6 @@ pub fn convert_to_initialized_multilevel<D: BlockDevice>(
             split_extent_at_multilevel(
                 writer,
                 inode_ref,
-                sb,
                 allocator,
                 convert_end,
                 EXT4_EXT_MARK_UNWRIT2,
@@ -515,7 +513,7 @@ pub fn convert_to_initialized_multilevel<D: BlockDevice>(
                 inode_ref,
                 new_leaf.block_addr,
                 new_leaf.node_type,
-                sb.block_size(),
+                block_size,
                 convert_start,
             )?;
 
@@ -523,7 +521,7 @@ pub fn convert_to_initialized_multilevel<D: BlockDevice>(
                 inode_ref,
                 new_leaf.block_addr,
                 new_leaf.node_type,
-                sb.block_size(),
+                block_size,
                 new_idx,
                 0, // initialized
             )?;
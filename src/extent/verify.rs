@@ -15,7 +15,7 @@ use crate::{
     extent::checksum::{compute_checksum, extent_tail_offset, get_extent_tail},
     fs::InodeRef,
     superblock::Superblock,
-    types::ext4_extent_header,
+    types::{ext4_extent_header, ext4_extent_tail},
     BlockDevice,
 };
 
@@ -83,8 +83,16 @@ pub fn check_extent_block(
     pblock: u64,
 ) -> Result<()> {
     // 解析 extent header
-    let header_ptr = block_data.as_ptr() as *const ext4_extent_header;
-    let header = unsafe { &*header_ptr };
+    if block_data.len() < core::mem::size_of::<ext4_extent_header>() {
+        return Err(Error::new(
+            ErrorKind::Corrupted,
+            "bad extent block: too short for header",
+        ));
+    }
+    // SAFETY: 长度已检查；ext4_extent_header 只由整数组成
+    let header = unsafe {
+        core::ptr::read_unaligned(block_data.as_ptr() as *const ext4_extent_header)
+    };
 
     // 1. 检查魔数
     let magic = u16::from_le(header.magic);
@@ -122,11 +130,19 @@ pub fn check_extent_block(
         ));
     }
 
+    // max 个条目必须放得进块（之后按 max 定位校验和尾部）
+    let tail_offset = extent_tail_offset(&header);
+    if tail_offset > block_data.len() {
+        return Err(Error::new(
+            ErrorKind::Corrupted,
+            "bad extent block: max entries exceed block",
+        ));
+    }
+
     // 5. 检查校验和（如果启用了 METADATA_CSUM）
     if sb.has_ro_compat_feature(crate::consts::EXT4_FEATURE_RO_COMPAT_METADATA_CSUM) {
         // 获取存储的校验和
-        let tail_offset = extent_tail_offset(header);
-        if tail_offset <= block_data.len() {
+        if tail_offset + core::mem::size_of::<ext4_extent_tail>() <= block_data.len() {
             let stored_checksum = unsafe {
                 let tail = get_extent_tail(block_data);
                 u32::from_le(tail.checksum)
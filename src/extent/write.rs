@@ -28,17 +28,17 @@
 
 use crate::{
     balloc::{self, BlockAllocator},
-    block::{Block, BlockDevice},
-    consts::*,
+    block::{Block, BlockDev, BlockDevice},
     error::{Error, ErrorKind, Result},
     fs::InodeRef,
-    superblock::Superblock,
+    logging::ext4_log,
     transaction::SimpleTransaction,
-    types::{ext4_extent, ext4_extent_header, ext4_extent_idx},
+    types::{ext4_extent, ext4_extent_header, ext4_extent_idx, Lblk, Pblk},
 };
-use log::*;
 use alloc::vec::Vec;
 
+use super::unwritten::{get_actual_len, EXT_INIT_MAX_LEN};
+
 //=============================================================================
 // Extent 树初始化
 //=============================================================================
@@ -124,61 +124,119 @@ pub fn tree_init<D: BlockDevice>(inode_ref: &mut InodeRef<D>) -> Result<()> {
 ///
 /// # 返回
 ///
-/// 下一个已分配的逻辑块号，如果没有则返回 u32::MAX
-/// TODO: 这个函数只处理了深度为0的情况，对于多层树总是返回 u32::MAX
-/// 虽然不会导致功能错误，但会影响连续分配的优化效果
+/// 下一个已分配的逻辑块号（大于 `logical_block` 的最小 extent 起始块），
+/// 如果没有则返回 u32::MAX。支持任意深度的 extent 树。
 fn find_next_allocated_block<D: BlockDevice>(
     inode_ref: &mut InodeRef<D>,
     logical_block: u32,
 ) -> Result<u32> {
     // 读取 extent 树根节点
-    let (root_data, depth) = inode_ref.with_inode(|inode| {
-        let root_data = unsafe {
+    let root_data = inode_ref.with_inode(|inode| {
+        unsafe {
             core::slice::from_raw_parts(
                 inode.blocks.as_ptr() as *const u8,
                 60, // 15 * 4
             ).to_vec()
-        };
+        }
+    })?;
 
-        let header = unsafe {
-            *(root_data.as_ptr() as *const ext4_extent_header)
-        };
+    next_allocated_block_in_tree(inode_ref.bdev(), root_data, logical_block)
+}
 
-        (root_data, u16::from_le(header.depth))
+/// 查找覆盖 `logical_block` 的叶子节点的逻辑上界
+///
+/// 即路径上右侧第一个子树的起始块（不包含），插入该叶子的 extent
+/// 不能越过这个位置；深度为 0 或右侧没有子树时返回 u32::MAX。
+fn find_leaf_upper_bound<D: BlockDevice>(
+    inode_ref: &mut InodeRef<D>,
+    logical_block: u32,
+) -> Result<u32> {
+    let root_data = inode_ref.with_inode(|inode| {
+        unsafe {
+            core::slice::from_raw_parts(
+                inode.blocks.as_ptr() as *const u8,
+                60, // 15 * 4
+            ).to_vec()
+        }
     })?;
 
-    // 如果深度为 0，直接在根节点查找
-    if depth == 0 {
-        let header = unsafe { *(root_data.as_ptr() as *const ext4_extent_header) };
-        let entries = u16::from_le(header.entries);
-        let header_size = core::mem::size_of::<ext4_extent_header>();
-        let extent_size = core::mem::size_of::<ext4_extent>();
+    next_entry_in_tree(inode_ref.bdev(), root_data, logical_block, false)
+}
 
-        let mut next_block = u32::MAX;
+/// 从给定根节点开始查找下一个已分配的逻辑块
+fn next_allocated_block_in_tree<D: BlockDevice>(
+    bdev: &mut BlockDev<D>,
+    root_data: Vec<u8>,
+    logical_block: u32,
+) -> Result<u32> {
+    next_entry_in_tree(bdev, root_data, logical_block, true)
+}
 
-        for i in 0..entries as usize {
-            let offset = header_size + i * extent_size;
-            if offset + extent_size > root_data.len() {
-                break;
-            }
+/// 沿覆盖 `logical_block` 的路径向下遍历，取路径上每个节点中
+/// 第一个起始块大于 `logical_block` 的条目的最小值：
+/// 叶子节点给出下一个 extent，上层索引节点给出右侧子树的起点。
+///
+/// `include_leaf` 为 false 时只看索引节点，得到所在叶子的上界。
+fn next_entry_in_tree<D: BlockDevice>(
+    bdev: &mut BlockDev<D>,
+    root_data: Vec<u8>,
+    logical_block: u32,
+    include_leaf: bool,
+) -> Result<u32> {
+    let header_size = core::mem::size_of::<ext4_extent_header>();
+    // ext4_extent 与 ext4_extent_idx 同为 12 字节，且首字段都是起始逻辑块
+    let entry_size = core::mem::size_of::<ext4_extent>();
 
-            let extent = unsafe {
-                *(root_data.as_ptr().add(offset) as *const ext4_extent)
-            };
+    let mut node = root_data;
+    let mut next_block = u32::MAX;
+    let mut expected_depth: Option<u16> = None;
 
-            let ee_block = u32::from_le(extent.block);
+    loop {
+        let header = unsafe {
+            core::ptr::read_unaligned(node.as_ptr() as *const ext4_extent_header)
+        };
+        let depth = header.depth();
+
+        if !header.is_valid() || expected_depth.is_some_and(|d| d != depth) {
+            return Err(Error::new(
+                ErrorKind::Corrupted,
+                "Invalid extent node while searching next allocated block",
+            ));
+        }
+
+        let entries = (header.entries_count() as usize)
+            .min((node.len() - header_size) / entry_size);
+        let entry_block = |i: usize| {
+            let offset = header_size + i * entry_size;
+            u32::from_le_bytes([node[offset], node[offset + 1], node[offset + 2], node[offset + 3]])
+        };
 
-            // 找到第一个大于 logical_block 的 extent
-            if ee_block > logical_block && ee_block < next_block {
-                next_block = ee_block;
+        // 本节点中第一个起始块大于 logical_block 的条目
+        if depth > 0 || include_leaf {
+            if let Some(i) = (0..entries).find(|&i| entry_block(i) > logical_block) {
+                next_block = next_block.min(entry_block(i));
             }
         }
 
-        return Ok(next_block);
-    }
+        if depth == 0 || entries == 0 {
+            return Ok(next_block);
+        }
+
+        // 选择覆盖 logical_block 的索引继续向下
+        let pos = (0..entries)
+            .rev()
+            .find(|&i| entry_block(i) <= logical_block)
+            .unwrap_or(0);
+        let idx = unsafe {
+            core::ptr::read_unaligned(
+                node[header_size + pos * entry_size..].as_ptr() as *const ext4_extent_idx,
+            )
+        };
+        let child = super::helpers::ext4_idx_pblock(&idx);
 
-    // TODO: 支持多层树
-    Ok(u32::MAX)
+        node = Block::get(bdev, child)?.with_data(|data| data.to_vec())?;
+        expected_depth = Some(depth - 1);
+    }
 }
 
 /// 计算块分配目标
@@ -280,19 +338,21 @@ fn find_goal<D: BlockDevice>(
 /// ```
 pub fn get_blocks<D: BlockDevice>(
     inode_ref: &mut InodeRef<D>,
-    sb: &mut Superblock,
     allocator: &mut BlockAllocator,
-    logical_block: u32,
+    logical_block: impl Into<Lblk>,
     max_blocks: u32,
     create: bool,
-) -> Result<(u64, u32)> {
+) -> Result<(Pblk, u32)> {
+    let Lblk(logical_block) = logical_block.into();
+
     // 1. 查找包含此逻辑块的 extent
     let extent_opt = find_extent_for_block(inode_ref, logical_block)?;
 
     if let Some(extent) = extent_opt {
         // 提取 extent 信息
         let ee_block = u32::from_le(extent.block);
-        let ee_len = u16::from_le(extent.len);
+        // unwritten extent 的长度字段带有标志位
+        let ee_len = get_actual_len(&extent);
         let ee_start_lo = u32::from_le(extent.start_lo);
         let ee_start_hi = u16::from_le(extent.start_hi);
 
@@ -309,14 +369,14 @@ pub fn get_blocks<D: BlockDevice>(
             let remaining = ee_len as u32 - offset;
             let allocated = remaining.min(max_blocks);
 
-            return Ok((physical_block, allocated));
+            return Ok((Pblk(physical_block), allocated));
         }
     }
 
     // 2. 没有找到包含此逻辑块的 extent
     if !create {
         // 不创建，返回 0
-        return Ok((0, 0));
+        return Ok((Pblk(0), 0));
     }
 
     // 3. 分配新块并使用 ExtentWriter 插入
@@ -325,7 +385,6 @@ pub fn get_blocks<D: BlockDevice>(
     // 而不是简化版的 insert_extent_simple
 
     // 3.1 计算可以分配多少块（不能超过下一个已分配的 extent）
-    // TODO: find_next_allocated_block 对多层树返回 u32::MAX，不影响正确性但影响连续分配优化
     let next_allocated = find_next_allocated_block(inode_ref, logical_block)?;
     let mut allocated_count = if next_allocated > logical_block {
         (next_allocated - logical_block).min(max_blocks)
@@ -337,9 +396,12 @@ pub fn get_blocks<D: BlockDevice>(
     // 🚀 性能优化：传入已经查找到的 extent_opt，避免在 find_goal 中重复查找
     let goal = find_goal(inode_ref, logical_block, Some(extent_opt))?;
 
-    // 3.3 分配物理块（支持批量分配）
-    let (physical_block, actual_allocated) = balloc::alloc_blocks(
-        inode_ref.bdev(),
+    // 3.3 分配物理块（支持批量分配），先检查保留块和配额
+    inode_ref.check_free_blocks(allocated_count as u64)?;
+    inode_ref.quota_check_blocks(allocated_count as u64)?;
+    let (bdev, sb) = inode_ref.bdev_and_sb_mut();
+    let (Pblk(physical_block), actual_allocated) = balloc::alloc_blocks(
+        bdev,
         sb,
         goal,
         allocated_count,
@@ -347,7 +409,7 @@ pub fn get_blocks<D: BlockDevice>(
     allocated_count = actual_allocated;
 
     // 🚀 性能优化：降低日志级别
-    debug!(
+    ext4_log!(Extent, Debug,
         "[EXTENT WRITE] Allocated blocks: logical={}, physical={:#x}, count={}, goal={:#x}",
         logical_block, physical_block, actual_allocated, goal
     );
@@ -362,7 +424,6 @@ pub fn get_blocks<D: BlockDevice>(
 
     let insert_result = insert_extent_with_auto_split(
         inode_ref,
-        sb,
         allocator,
         logical_block,
         physical_block,
@@ -376,22 +437,23 @@ pub fn get_blocks<D: BlockDevice>(
             inode_ref.add_blocks(allocated_count)?;
 
             // 🚀 性能优化：降低日志级别
-            debug!(
+            ext4_log!(Extent, Debug,
                 "[EXTENT WRITE] Successfully inserted extent: logical={}, physical={:#x} (hi={:#x}, lo={:#x}), count={}",
                 logical_block, physical_block,
                 (physical_block >> 32) as u16, physical_block as u32,
                 allocated_count
             );
-            Ok((physical_block, allocated_count))
+            Ok((Pblk(physical_block), allocated_count))
         }
         Err(e) => {
             // 插入失败，释放已分配的块
-            error!(
+            ext4_log!(Extent, Error,
                 "[EXTENT WRITE] Failed to insert extent: logical={}, physical={:#x}, error={:?}",
                 logical_block, physical_block, e
             );
+            let (bdev, sb) = inode_ref.bdev_and_sb_mut();
             let _ = balloc::free_blocks(
-                inode_ref.bdev(),
+                bdev,
                 sb,
                 physical_block,
                 allocated_count,
@@ -415,14 +477,12 @@ pub fn get_blocks<D: BlockDevice>(
 /// # 参数
 ///
 /// * `inode_ref` - Inode 引用
-/// * `sb` - Superblock
 /// * `allocator` - 块分配器
 /// * `logical_block` - 逻辑块号
 /// * `physical_block` - 物理块号
 /// * `length` - extent 长度（块数）
-fn insert_extent_with_auto_split<D: BlockDevice>(
+pub(crate) fn insert_extent_with_auto_split<D: BlockDevice>(
     inode_ref: &mut InodeRef<D>,
-    sb: &mut Superblock,
     allocator: &mut BlockAllocator,
     logical_block: u32,
     physical_block: u64,
@@ -440,83 +500,44 @@ fn insert_extent_with_auto_split<D: BlockDevice>(
         (entries >= max, depth, entries, max)
     })?;
 
-    log::debug!(
+    ext4_log!(Extent, Debug,
         "[EXTENT_INSERT] logical={}, physical=0x{:x}, len={}, is_full={}, depth={}, entries={}/{}",
         logical_block, physical_block, length, is_full, depth, entries, max
     );
 
-    // 2. 根据当前状态决定插入策略
+    // 2. extent 不能越过所在叶子的上界，越过的部分插入右侧的叶子
+    if depth > 0 {
+        let bound = find_leaf_upper_bound(inode_ref, logical_block)?;
+        if bound > logical_block && logical_block as u64 + length as u64 > bound as u64 {
+            let head = bound - logical_block;
+            insert_extent_with_auto_split(inode_ref, allocator, logical_block, physical_block, head)?;
+            return insert_extent_with_auto_split(
+                inode_ref,
+                allocator,
+                bound,
+                physical_block + head as u64,
+                length - head,
+            );
+        }
+    }
+
+    // 3. 根据当前状态决定插入策略
     if is_full {
         // 根节点满了，需要增加树深度
-        log::debug!("[EXTENT_INSERT] Root is FULL, calling grow_tree_depth (depth {} -> {})", depth, depth + 1);
-        let new_block = super::grow_tree_depth(inode_ref, sb, allocator)?;
-
-        // 关键修复：grow 后需要根据新深度确定如何插入
-        // - 如果原 depth = 0，grow 后 depth = 1，new_block 是叶子节点（depth=0）
-        // - 如果原 depth >= 1，grow 后 depth >= 2，new_block 是索引节点，需要继续遍历
-        let new_depth = depth + 1;
-
-        // 根据新深度确定目标叶子块
-        let leaf_block: u64 = (match new_depth {
-            1 => {
-                // depth 0->1: new_block 就是叶子节点
-                log::debug!("[EXTENT_INSERT] After grow (0->1), new_block 0x{:x} is leaf", new_block);
-                Ok(new_block)
-            }
-            2 => {
-                // depth 1->2: new_block 是索引节点，读取其第一个 index 指向的叶子块
-                log::debug!("[EXTENT_INSERT] After grow (1->2), new_block 0x{:x} is index node", new_block);
-
-                Block::get(inode_ref.bdev(), new_block)
-                    .and_then(|mut idx_block| {
-                        // with_data 返回 Result<Result<u64>>, 需要展开外层并返回内层
-                        match idx_block.with_data(|data| -> Result<u64> {
-                            let header = unsafe {
-                                *(data.as_ptr() as *const ext4_extent_header)
-                            };
-
-                            let depth_check = u16::from_le(header.depth);
-                            if depth_check != 1 {
-                                log::error!("[EXTENT_INSERT] Expected depth=1 in new index block, got {}", depth_check);
-                                return Err(Error::new(
-                                    ErrorKind::Corrupted,
-                                    "Expected depth=1 in new index block after grow",
-                                ));
-                            }
-
-                            // 读取第一个 index
-                            let header_size = core::mem::size_of::<ext4_extent_header>();
-                            let idx_ptr = unsafe {
-                                (data.as_ptr() as *const u8).add(header_size) as *const ext4_extent_idx
-                            };
-                            let idx = unsafe { &*idx_ptr };
-
-                            let leaf = super::helpers::ext4_idx_pblock(idx);
-                            log::debug!("[EXTENT_INSERT] Read first index from 0x{:x}: leaf=0x{:x}", new_block, leaf);
-                            Ok(leaf)
-                        }) {
-                            Ok(inner_result) => inner_result,  // 返回内层 Result<u64>
-                            Err(e) => Err(e),
-                        }
-                    })
-            }
-            _ => {
-                // TODO: depth > 2 需要递归遍历索引树找到叶子节点
-                // 当前实现仅支持 depth <= 2，这在绝大多数情况下足够
-                // (depth=2 可支持约 340*340*32K = 3.7TB 文件)
-                log::error!("[EXTENT_INSERT] Tree depth {} not supported after grow", new_depth);
-                Err(Error::new(
-                    ErrorKind::Unsupported,
-                    "Tree depth > 2 not supported after grow",
-                ))
-            }
-        })?;
+        ext4_log!(Extent, Debug, "[EXTENT_INSERT] Root is FULL, calling grow_tree_depth (depth {} -> {})", depth, depth + 1);
+        let Pblk(new_block) = super::grow_tree_depth(inode_ref, allocator)?;
 
-        log::debug!("[EXTENT_INSERT] After grow, inserting to leaf block 0x{:x}", leaf_block);
-        insert_extent_to_leaf_direct(inode_ref, sb, allocator, leaf_block, logical_block, physical_block, length)?;
+        // grow 后新根只有一个索引，沿索引树向下查找覆盖 logical_block 的叶子块：
+        // - 原 depth = 0 时 new_block 就是叶子节点
+        // - 原 depth >= 1 时 new_block 是索引节点，需要继续遍历（支持任意深度）
+        ext4_log!(Extent, Debug, "[EXTENT_INSERT] After grow, new_block 0x{:x} at depth {}", new_block, depth);
+        let leaf_block = find_target_leaf_block(inode_ref, logical_block)?;
+
+        ext4_log!(Extent, Debug, "[EXTENT_INSERT] After grow, inserting to leaf block 0x{:x}", leaf_block);
+        insert_extent_to_leaf_direct(inode_ref, allocator, leaf_block, logical_block, physical_block, length)?;
     } else if depth == 0 {
         // 深度为 0 且未满，直接插入到根节点（inode.blocks）
-        log::debug!("[EXTENT_INSERT] Depth=0 and not full, using insert_extent_simple");
+        ext4_log!(Extent, Debug, "[EXTENT_INSERT] Depth=0 and not full, using insert_extent_simple");
         let extent = ext4_extent {
             block: logical_block.to_le(),
             len: (length as u16).to_le(),
@@ -527,15 +548,15 @@ fn insert_extent_with_auto_split<D: BlockDevice>(
         insert_extent_simple(inode_ref, &extent)?;
     } else {
         // 深度 > 0 且未满，需要插入到叶子节点
-        log::debug!("[EXTENT_INSERT] Depth={} and not full, inserting to leaf", depth);
+        ext4_log!(Extent, Debug, "[EXTENT_INSERT] Depth={} and not full, inserting to leaf", depth);
 
         // 🔧 关键修复：根据 logical_block 查找正确的目标叶子块
         // 不能使用 read_first_leaf_block，因为它总是返回第一个索引
         // 必须遍历索引树找到包含 logical_block 的正确叶子
         let leaf_block = find_target_leaf_block(inode_ref, logical_block)?;
-        log::debug!("[EXTENT_INSERT] Found target leaf block for logical={}: 0x{:x}", logical_block, leaf_block);
+        ext4_log!(Extent, Debug, "[EXTENT_INSERT] Found target leaf block for logical={}: 0x{:x}", logical_block, leaf_block);
 
-        insert_extent_to_leaf_direct(inode_ref, sb, allocator, leaf_block, logical_block, physical_block, length)?;
+        insert_extent_to_leaf_direct(inode_ref, allocator, leaf_block, logical_block, physical_block, length)?;
     }
 
     Ok(())
@@ -580,7 +601,7 @@ fn find_target_leaf_block<D: BlockDevice>(
 
             let idx_block = u32::from_le(idx.block);
 
-            log::debug!(
+            ext4_log!(Extent, Debug,
                 "[FIND_TARGET_LEAF] Index[{}]: idx_block={}, comparing with logical={}",
                 i, idx_block, logical_block
             );
@@ -598,7 +619,7 @@ fn find_target_leaf_block<D: BlockDevice>(
 
         let child_block = super::helpers::ext4_idx_pblock(&idx);
 
-        log::debug!(
+        ext4_log!(Extent, Debug,
             "[FIND_TARGET_LEAF] Selected child_block=0x{:x} for logical={}",
             child_block, logical_block
         );
@@ -637,7 +658,7 @@ fn traverse_to_leaf<D: BlockDevice>(
             let node_depth = u16::from_le(header.depth);
             let entries = u16::from_le(header.entries);
 
-            log::debug!(
+            ext4_log!(Extent, Debug,
                 "[TRAVERSE_LEAF] At block=0x{:x}, depth={}, entries={}, searching for logical={}",
                 current_block, node_depth, entries, logical_block
             );
@@ -669,7 +690,7 @@ fn traverse_to_leaf<D: BlockDevice>(
 
             let child = super::helpers::ext4_idx_pblock(&idx);
 
-            log::debug!("[TRAVERSE_LEAF] Selected child=0x{:x}", child);
+            ext4_log!(Extent, Debug, "[TRAVERSE_LEAF] Selected child=0x{:x}", child);
 
             Ok(child)
         })??;
@@ -678,7 +699,7 @@ fn traverse_to_leaf<D: BlockDevice>(
         current_depth -= 1;
     }
 
-    log::debug!("[TRAVERSE_LEAF] Found leaf block: 0x{:x}", current_block);
+    ext4_log!(Extent, Debug, "[TRAVERSE_LEAF] Found leaf block: 0x{:x}", current_block);
     Ok(current_block)
 }
 
@@ -716,7 +737,7 @@ fn read_first_leaf_block<D: BlockDevice>(inode_ref: &mut InodeRef<D>) -> Result<
         // 使用辅助函数而不是手动组合
         let child_block = super::helpers::ext4_idx_pblock(idx);
 
-        log::debug!(
+        ext4_log!(Extent, Debug,
             "[READ_LEAF_BLOCK] root_depth={}, first_child=0x{:x}",
             depth, child_block
         );
@@ -747,7 +768,7 @@ fn read_first_leaf_block<D: BlockDevice>(inode_ref: &mut InodeRef<D>) -> Result<
 
             let node_depth = u16::from_le(header.depth);
             if node_depth != current_depth {
-                log::warn!(
+                ext4_log!(Extent, Warn,
                     "[READ_LEAF_BLOCK] Depth mismatch: expected={}, actual={}",
                     current_depth, node_depth
                 );
@@ -761,7 +782,7 @@ fn read_first_leaf_block<D: BlockDevice>(inode_ref: &mut InodeRef<D>) -> Result<
 
             let child = super::helpers::ext4_idx_pblock(idx);
 
-            log::debug!(
+            ext4_log!(Extent, Debug,
                 "[READ_LEAF_BLOCK] Traversing: block=0x{:x}, depth={} -> child=0x{:x}",
                 current_block, current_depth, child
             );
@@ -773,7 +794,7 @@ fn read_first_leaf_block<D: BlockDevice>(inode_ref: &mut InodeRef<D>) -> Result<
         current_depth -= 1;
     }
 
-    log::debug!("[READ_LEAF_BLOCK] Found leaf block: 0x{:x}", current_block);
+    ext4_log!(Extent, Debug, "[READ_LEAF_BLOCK] Found leaf block: 0x{:x}", current_block);
     Ok(current_block)
 }
 
@@ -785,7 +806,6 @@ fn read_first_leaf_block<D: BlockDevice>(inode_ref: &mut InodeRef<D>) -> Result<
 /// # 参数
 ///
 /// * `inode_ref` - Inode 引用
-/// * `sb` - Superblock 引用
 /// * `allocator` - 块分配器（用于分裂时分配新块）
 /// * `leaf_block` - 叶子块地址
 /// * `logical_block` - 要插入的逻辑块号
@@ -793,21 +813,22 @@ fn read_first_leaf_block<D: BlockDevice>(inode_ref: &mut InodeRef<D>) -> Result<
 /// * `length` - extent 长度
 fn insert_extent_to_leaf_direct<D: BlockDevice>(
     inode_ref: &mut InodeRef<D>,
-    sb: &mut Superblock,
     allocator: &mut BlockAllocator,
     leaf_block: u64,
     logical_block: u32,
     physical_block: u64,
     length: u32,
 ) -> Result<()> {
-    log::debug!(
+    ext4_log!(Extent, Debug,
         "[EXTENT_LEAF_DIRECT] Inserting to leaf block 0x{:x}: logical={}, physical=0x{:x}, len={}",
         leaf_block, logical_block, physical_block, length
     );
 
     // 首先尝试直接插入
+    let csum_seed = inode_ref.extent_csum_seed()?;
     let insert_result = try_insert_to_leaf_block(
         inode_ref.bdev(),
+        csum_seed,
         leaf_block,
         logical_block,
         physical_block,
@@ -816,42 +837,40 @@ fn insert_extent_to_leaf_direct<D: BlockDevice>(
 
     match insert_result {
         Ok(()) => {
-            log::debug!("[EXTENT_LEAF_DIRECT] Insert succeeded without split");
+            ext4_log!(Extent, Debug, "[EXTENT_LEAF_DIRECT] Insert succeeded without split");
             Ok(())
         }
         Err(e) if e.kind() == ErrorKind::NoSpace => {
-            log::debug!("[EXTENT_LEAF_DIRECT] Leaf is full, need to split");
+            ext4_log!(Extent, Debug, "[EXTENT_LEAF_DIRECT] Leaf is full, need to split");
 
             // 构建 ExtentPath 用于分裂
-            let mut path = build_extent_path_for_leaf(inode_ref, leaf_block)?;
+            let mut path = build_extent_path_for_leaf(inode_ref, leaf_block, logical_block)?;
 
             // 执行分裂（在 path 的最后一个节点，即叶子节点）
             let leaf_at = path.nodes.len() - 1;
-            log::debug!(
+            ext4_log!(Extent, Debug,
                 "[EXTENT_LEAF_DIRECT] Calling split_extent_node at depth={}, leaf_at={}",
                 path.nodes[leaf_at].depth, leaf_at
             );
 
             super::split_extent_node(
                 inode_ref,
-                sb,
                 allocator,
                 &mut path,
                 leaf_at,
                 logical_block,
             )?;
 
-            log::debug!("[EXTENT_LEAF_DIRECT] Split succeeded, retrying insert");
+            ext4_log!(Extent, Debug, "[EXTENT_LEAF_DIRECT] Split succeeded, retrying insert");
 
             // 分裂后，需要重新确定应该插入到哪个叶子节点
             // 可能是原来的 leaf_block，也可能是新分裂出来的块
             let new_leaf_block = determine_target_leaf_after_split(
                 inode_ref,
-                &path,
                 logical_block,
             )?;
 
-            log::debug!(
+            ext4_log!(Extent, Debug,
                 "[EXTENT_LEAF_DIRECT] Target leaf after split: 0x{:x}",
                 new_leaf_block
             );
@@ -859,13 +878,14 @@ fn insert_extent_to_leaf_direct<D: BlockDevice>(
             // 重试插入（分裂后必定有空间）
             try_insert_to_leaf_block(
                 inode_ref.bdev(),
+                csum_seed,
                 new_leaf_block,
                 logical_block,
                 physical_block,
                 length,
             )?;
 
-            log::debug!("[EXTENT_LEAF_DIRECT] Retry insert succeeded");
+            ext4_log!(Extent, Debug, "[EXTENT_LEAF_DIRECT] Retry insert succeeded");
             Ok(())
         }
         Err(e) => Err(e),
@@ -877,6 +897,7 @@ fn insert_extent_to_leaf_direct<D: BlockDevice>(
 /// 这是一个辅助函数，仅执行插入操作。如果块满，返回 NoSpace 错误。
 fn try_insert_to_leaf_block<D: BlockDevice>(
     bdev: &mut crate::block::BlockDev<D>,
+    csum_seed: Option<u32>,
     leaf_block: u64,
     logical_block: u32,
     physical_block: u64,
@@ -928,7 +949,7 @@ fn try_insert_to_leaf_block<D: BlockDevice>(
 
             // 🔧 关键修复：检查是否已存在相同的逻辑块
             if existing_block == logical_block {
-                log::error!(
+                ext4_log!(Extent, Error,
                     "[EXTENT_INSERT] DUPLICATE DETECTED: logical_block={} already exists at pos {}, \
                      existing_physical=0x{:x}, new_physical=0x{:x}",
                     logical_block, i, existing_physical, physical_block
@@ -941,11 +962,13 @@ fn try_insert_to_leaf_block<D: BlockDevice>(
 
             // 🔧 新增：检查是否可以与前一个 extent 合并
             // 条件：existing_extent 在 new_extent 之前，且物理和逻辑都连续
+            // 合并后的长度不能超过 EXT_INIT_MAX_LEN（同时排除了 unwritten extent）
             if existing_block + existing_len as u32 == logical_block &&
-               existing_physical + existing_len as u64 == physical_block {
+               existing_physical + existing_len as u64 == physical_block &&
+               existing_len as u32 + length <= EXT_INIT_MAX_LEN as u32 {
                 can_merge_with_prev = true;
                 prev_pos = Some(i);
-                log::debug!(
+                ext4_log!(Extent, Debug,
                     "[EXTENT_MERGE] Can merge with PREV extent at pos {}: \
                      prev_logical={}-{}, prev_physical=0x{:x}-0x{:x}, \
                      new_logical={}, new_physical=0x{:x}",
@@ -961,10 +984,11 @@ fn try_insert_to_leaf_block<D: BlockDevice>(
                 // 🔧 新增：检查是否可以与后一个 extent 合并
                 // 条件：new_extent 在 existing_extent 之前，且物理和逻辑都连续
                 if logical_block + length == existing_block &&
-                   physical_block + length as u64 == existing_physical {
+                   physical_block + length as u64 == existing_physical &&
+                   existing_len as u32 + length <= EXT_INIT_MAX_LEN as u32 {
                     can_merge_with_next = true;
                     next_pos = Some(i);
-                    log::debug!(
+                    ext4_log!(Extent, Debug,
                         "[EXTENT_MERGE] Can merge with NEXT extent at pos {}: \
                          new_logical={}, new_physical=0x{:x}, \
                          next_logical={}-{}, next_physical=0x{:x}-0x{:x}",
@@ -977,6 +1001,17 @@ fn try_insert_to_leaf_block<D: BlockDevice>(
             }
         }
 
+        // 桥接合并后超长时只与前一个 extent 合并
+        if let (Some(p), Some(n)) = (prev_pos, next_pos) {
+            let ext_len = |i: usize| unsafe {
+                let ext = &*(data[header_size + i * extent_size..].as_ptr() as *const ext4_extent);
+                u16::from_le(ext.len) as u32
+            };
+            if ext_len(p) + length + ext_len(n) > EXT_INIT_MAX_LEN as u32 {
+                can_merge_with_next = false;
+            }
+        }
+
         // 🔧 执行合并操作（如果可以合并）
         if can_merge_with_prev && can_merge_with_next {
             // Case 3: 桥接合并 - 新 extent 连接了 prev 和 next
@@ -1019,7 +1054,7 @@ fn try_insert_to_leaf_block<D: BlockDevice>(
             // 更新 header（entries 减 1，因为删除了 next extent）
             header.entries = (entries_count - 1).to_le();
 
-            log::info!(
+            ext4_log!(Extent, hot,
                 "[EXTENT_MERGE] BRIDGE MERGE: prev_pos={}, next_pos={}, \
                  merged_logical={}-{}, total_len={} (prev_len={} + new_len={} + next_len={})",
                 prev_idx, next_idx,
@@ -1047,7 +1082,7 @@ fn try_insert_to_leaf_block<D: BlockDevice>(
                 prev_ext.len = new_len.to_le();
             }
 
-            log::info!(
+            ext4_log!(Extent, hot,
                 "[EXTENT_MERGE] PREV MERGE: pos={}, extended_len={} -> {}, \
                  logical_range={}-{}",
                 prev_idx, prev_len, new_len,
@@ -1080,7 +1115,7 @@ fn try_insert_to_leaf_block<D: BlockDevice>(
                 next_ext.len = new_len.to_le();
             }
 
-            log::info!(
+            ext4_log!(Extent, hot,
                 "[EXTENT_MERGE] NEXT MERGE: pos={}, extended_len={} -> {}, \
                  logical_range={}-{}",
                 next_idx, next_len, new_len,
@@ -1117,7 +1152,7 @@ fn try_insert_to_leaf_block<D: BlockDevice>(
         new_extent.start_lo = (physical_block as u32).to_le();
         new_extent.start_hi = ((physical_block >> 32) as u16).to_le();
 
-        log::debug!(
+        ext4_log!(Extent, Debug,
             "[EXTENT_INSERT] Writing extent at pos {}: logical={}, physical=0x{:x}, len={}",
             insert_pos, logical_block, physical_block, length
         );
@@ -1125,7 +1160,7 @@ fn try_insert_to_leaf_block<D: BlockDevice>(
         // 更新 header
         header.entries = (entries_count + 1).to_le();
 
-        log::debug!(
+        ext4_log!(Extent, Debug,
             "[EXTENT_INSERT] Updated header: entries {} -> {}",
             entries_count, entries_count + 1
         );
@@ -1133,119 +1168,108 @@ fn try_insert_to_leaf_block<D: BlockDevice>(
         Ok(())
     })??;
 
+    block.with_data_mut(|data| super::checksum::update_checksum(csum_seed, data))?;
     Ok(())
 }
 
 /// 构建从根到指定叶子块的 ExtentPath
 ///
-/// 用于分裂操作前构建路径信息
+/// 用于分裂操作前构建路径信息。每一层选择覆盖 `logical_block` 的索引向下遍历，
+/// 支持任意深度的索引树；`leaf_block` 用于校验路径终点。
 fn build_extent_path_for_leaf<D: BlockDevice>(
     inode_ref: &mut InodeRef<D>,
     leaf_block: u64,
+    logical_block: u32,
 ) -> Result<ExtentPath> {
     // 读取根节点信息
-    let (root_header, max_depth) = inode_ref.with_inode(|inode| {
-        let header_ptr = inode.blocks.as_ptr() as *const ext4_extent_header;
-        let header = unsafe { &*header_ptr };
-        let depth = u16::from_le(header.depth);
-        (header.clone(), depth)
-    })?;
+    let (root_indices, root_header) = super::split::read_indices_from_inode(inode_ref)?;
+    let max_depth = root_header.depth();
 
     let mut path = ExtentPath::new(max_depth);
 
-    // 添加根节点
-    path.push(ExtentPathNode {
+    // 如果深度为 0，根节点就是叶子节点
+    if max_depth == 0 {
+        path.push(ExtentPathNode {
+            block_addr: 0, // 根节点在 inode 中
+            depth: 0,
+            header: root_header,
+            index_pos: 0,
+            node_type: ExtentNodeType::Root,
+        });
+        return Ok(path);
+    }
+
+    let block_size = inode_ref.sb().block_size();
+    let mut indices = root_indices;
+    let mut node = ExtentPathNode {
         block_addr: 0, // 根节点在 inode 中
         depth: max_depth,
         header: root_header,
         index_pos: 0,
         node_type: ExtentNodeType::Root,
-    });
-
-    // 如果深度为 0，根节点就是叶子节点
-    if max_depth == 0 {
-        return Ok(path);
-    }
+    };
 
-    // 对于深度 > 0，需要从根节点遍历到目标叶子节点
-    // 支持任意深度的索引树
-    if max_depth > 0 {
-        let mut current_block = leaf_block;
-        let mut current_depth = max_depth;
+    // 从根节点开始逐层向下，直到叶子节点
+    loop {
+        // 最后一个起始块不大于 logical_block 的索引，都大于时取第一个
+        if indices.is_empty() {
+            return Err(Error::new(ErrorKind::Corrupted, "Empty extent index node"));
+        }
+        node.index_pos = indices
+            .iter()
+            .rposition(|idx| idx.logical_block() <= logical_block)
+            .unwrap_or(0);
+        let child_block = super::helpers::ext4_idx_pblock(&indices[node.index_pos]);
+        let parent_depth = node.depth;
+        path.push(node);
+
+        // 读取子节点 header（叶子节点的条目按 index 解析无意义，但 header 相同）
+        let (child_indices, child_header) =
+            super::split::read_indices_from_block(inode_ref.bdev(), child_block, block_size)?;
+
+        let child_depth = child_header.depth();
+        if !child_header.is_valid() || child_depth + 1 != parent_depth {
+            ext4_log!(Extent, Warn,
+                "[BUILD_PATH] Depth mismatch: expected {}, got {} at block 0x{:x}",
+                parent_depth - 1, child_depth, child_block
+            );
+            return Err(Error::new(
+                ErrorKind::Corrupted,
+                "Extent node depth mismatch while building path",
+            ));
+        }
 
-        // 从根节点开始，逐层向下查找，直到叶子节点
-        // 注意：我们已知目标叶子块地址，需要构建到达它的路径
-        // 这里采用简化策略：直接读取每一层的节点
-        while current_depth > 0 {
-            // 读取当前层的节点 header
-            let mut block = Block::get(inode_ref.bdev(), current_block)?;
-            let node_header = block.with_data(|data| {
-                let header = unsafe {
-                    *(data.as_ptr() as *const ext4_extent_header)
-                };
-                header.clone()
-            })?;
+        ext4_log!(Extent, Debug,
+            "[BUILD_PATH] Added node: depth={}, block=0x{:x}",
+            child_depth, child_block
+        );
 
-            // 验证深度一致性
-            let node_depth = u16::from_le(node_header.depth);
-            if node_depth != current_depth - 1 {
-                log::warn!(
-                    "[BUILD_PATH] Depth mismatch: expected {}, got {} at block 0x{:x}",
-                    current_depth - 1, node_depth, current_block
+        if child_depth == 0 {
+            if child_block != leaf_block {
+                ext4_log!(Extent, Warn,
+                    "[BUILD_PATH] Path for logical {} ends at 0x{:x}, expected leaf 0x{:x}",
+                    logical_block, child_block, leaf_block
                 );
             }
-
-            // 添加节点到路径
-            let node_type = if current_depth == 1 {
-                ExtentNodeType::Leaf
-            } else {
-                ExtentNodeType::Index
-            };
-
             path.push(ExtentPathNode {
-                block_addr: current_block,
-                depth: node_depth,
-                header: node_header,
+                block_addr: child_block,
+                depth: 0,
+                header: child_header,
                 index_pos: 0,
-                node_type,
+                node_type: ExtentNodeType::Leaf,
             });
-
-            log::debug!(
-                "[BUILD_PATH] Added node: depth={}, block=0x{:x}, type={:?}",
-                node_depth, current_block, node_type
-            );
-
-            // 如果是叶子节点，完成路径构建
-            if current_depth == 1 {
-                break;
-            }
-
-            // 否则，读取第一个索引项，继续向下遍历
-            // 注意：这里的假设是我们要找的叶子块在某个索引项下
-            // 实际上，由于我们已知 leaf_block，应该在索引中查找它
-            // 但为了简化，我们暂时读取第一个索引
-            // TODO: 改进为在索引中查找匹配的 leaf_block
-            let first_idx = block.with_data(|data| {
-                let header_size = core::mem::size_of::<ext4_extent_header>();
-                unsafe {
-                    *(data[header_size..].as_ptr() as *const ext4_extent_idx)
-                }
-            })?;
-
-            current_block = super::helpers::ext4_idx_pblock(&first_idx);
-            current_depth -= 1;
-
-            log::debug!(
-                "[BUILD_PATH] Moving to next level: block=0x{:x}, depth={}",
-                current_block, current_depth
-            );
+            return Ok(path);
         }
 
-        return Ok(path);
+        indices = child_indices;
+        node = ExtentPathNode {
+            block_addr: child_block,
+            depth: child_depth,
+            header: child_header,
+            index_pos: 0,
+            node_type: ExtentNodeType::Index,
+        };
     }
-
-    // 深度为 0 时，根节点就是叶子，已在前面处理
-    Ok(path)
 }
 
 /// 分裂后确定目标叶子块
@@ -1253,13 +1277,13 @@ fn build_extent_path_for_leaf<D: BlockDevice>(
 /// 根据 logical_block，决定应该插入到原叶子还是新分裂的叶子
 fn determine_target_leaf_after_split<D: BlockDevice>(
     inode_ref: &mut InodeRef<D>,
-    path: &ExtentPath,
     logical_block: u32,
 ) -> Result<u64> {
-    // 对于深度 1 的简单情况，从根节点的索引中找到目标叶子
-    let depth = path.nodes[0].header.depth();
+    // 分裂可能使根节点增长深度，path 中记录的根 header 已过时，从 inode 重新读取
+    let (root_indices, root_header) = super::split::read_indices_from_inode(inode_ref)?;
+    let depth = root_header.depth();
 
-    log::debug!(
+    ext4_log!(Extent, Debug,
         "[DETERMINE_TARGET] Starting: depth={}, logical_block={}",
         depth, logical_block
     );
@@ -1271,9 +1295,9 @@ fn determine_target_leaf_after_split<D: BlockDevice>(
 
     // 第一层：从根节点（inode）读取索引
     if current_depth > 0 {
-        let (indices, _) = super::split::read_indices_from_inode(inode_ref)?;
+        let indices = root_indices;
 
-        log::debug!(
+        ext4_log!(Extent, Debug,
             "[DETERMINE_TARGET] Level {}: Read {} indices from inode",
             current_depth, indices.len()
         );
@@ -1284,7 +1308,7 @@ fn determine_target_leaf_after_split<D: BlockDevice>(
             let idx_block = u32::from_le(idx.block);
             let next_block = super::helpers::ext4_idx_pblock(idx);
 
-            log::debug!(
+            ext4_log!(Extent, Debug,
                 "[DETERMINE_TARGET] Index {}: idx_block={}, next_block=0x{:x}",
                 i, idx_block, next_block
             );
@@ -1300,7 +1324,7 @@ fn determine_target_leaf_after_split<D: BlockDevice>(
             current_block = Some(super::helpers::ext4_idx_pblock(idx));
             current_depth -= 1;
         } else {
-            log::error!("[DETERMINE_TARGET] No matching index found in root!");
+            ext4_log!(Extent, Error, "[DETERMINE_TARGET] No matching index found in root!");
             return Err(Error::new(
                 ErrorKind::Corrupted,
                 "No matching index found in root after split",
@@ -1319,7 +1343,7 @@ fn determine_target_leaf_after_split<D: BlockDevice>(
                 block_size,
             )?;
 
-            log::debug!(
+            ext4_log!(Extent, Debug,
                 "[DETERMINE_TARGET] Level {}: Read {} indices from block 0x{:x}",
                 current_depth, indices.len(), block_addr
             );
@@ -1330,7 +1354,7 @@ fn determine_target_leaf_after_split<D: BlockDevice>(
                 let idx_block = u32::from_le(idx.block);
                 let next_block = super::helpers::ext4_idx_pblock(idx);
 
-                log::debug!(
+                ext4_log!(Extent, Debug,
                     "[DETERMINE_TARGET] Index {}: idx_block={}, next_block=0x{:x}",
                     i, idx_block, next_block
                 );
@@ -1346,7 +1370,7 @@ fn determine_target_leaf_after_split<D: BlockDevice>(
                 current_block = Some(super::helpers::ext4_idx_pblock(idx));
                 current_depth -= 1;
             } else {
-                log::error!("[DETERMINE_TARGET] No matching index found at depth {}!", current_depth);
+                ext4_log!(Extent, Error, "[DETERMINE_TARGET] No matching index found at depth {}!", current_depth);
                 return Err(Error::new(
                     ErrorKind::Corrupted,
                     "No matching index found in index block after split",
@@ -1362,7 +1386,7 @@ fn determine_target_leaf_after_split<D: BlockDevice>(
 
     // 此时 current_block 应该指向目标叶子块
     if let Some(leaf_block) = current_block {
-        log::debug!(
+        ext4_log!(Extent, Debug,
             "[DETERMINE_TARGET] Final target: leaf_block=0x{:x}",
             leaf_block
         );
@@ -1382,7 +1406,6 @@ fn determine_target_leaf_after_split<D: BlockDevice>(
 #[allow(dead_code)]
 fn insert_extent_to_leaf<D: BlockDevice>(
     inode_ref: &mut InodeRef<D>,
-    sb: &mut Superblock,
     allocator: &mut BlockAllocator,
     logical_block: u32,
     physical_block: u64,
@@ -1422,7 +1445,7 @@ fn insert_extent_to_leaf<D: BlockDevice>(
         let leaf_hi = u16::from_le(idx.leaf_hi);
         let leaf_block = (leaf_hi as u64) << 32 | (leaf_lo as u64);
 
-        log::debug!(
+        ext4_log!(Extent, Debug,
             "[EXTENT_LEAF] Read index: leaf_lo=0x{:x}, leaf_hi=0x{:x}, leaf_block=0x{:x}, depth={}",
             leaf_lo, leaf_hi, leaf_block, depth
         );
@@ -1433,7 +1456,6 @@ fn insert_extent_to_leaf<D: BlockDevice>(
     // 使用统一的 insert_extent_to_leaf_direct（支持分裂）
     insert_extent_to_leaf_direct(
         inode_ref,
-        sb,
         allocator,
         leaf_block,
         logical_block,
@@ -1509,7 +1531,7 @@ pub(crate) fn insert_extent_simple<D: BlockDevice>(
                 // 不应该重复插入相同的逻辑块
                 let existing_physical = crate::extent::helpers::ext4_ext_pblock(&existing_extent);
                 let new_physical = crate::extent::helpers::ext4_ext_pblock(extent);
-                log::error!(
+                ext4_log!(Extent, Error,
                     "[EXTENT_INSERT_SIMPLE] DUPLICATE DETECTED: logical_block={} already exists at pos {}, \
                      existing_physical=0x{:x}, new_physical=0x{:x}",
                     new_block, i, existing_physical, new_physical
@@ -1595,7 +1617,7 @@ fn find_extent_for_block<D: BlockDevice>(
     let entries = u16::from_le(header.entries);
     let max = u16::from_le(header.max);
 
-    log::debug!(
+    ext4_log!(Extent, Debug,
         "[FIND_EXTENT] Searching for logical={}, root: depth={}, entries={}/{}, inode.blocks[0..28]={:02x?}",
         logical_block, depth, entries, max, &root_data[..28]
     );
@@ -1604,7 +1626,7 @@ fn find_extent_for_block<D: BlockDevice>(
     if depth == 0 {
         // 叶子节点：直接在根节点中查找
         let result = find_extent_in_leaf(&root_data, logical_block)?;
-        log::debug!(
+        ext4_log!(Extent, Debug,
             "[FIND_EXTENT] depth=0, result={:?}",
             result.as_ref().map(|e| (u32::from_le(e.block), u16::from_le(e.len)))
         );
@@ -1613,7 +1635,7 @@ fn find_extent_for_block<D: BlockDevice>(
 
     // 多层树：需要遍历索引节点
     let result = find_extent_in_multilevel_tree(inode_ref, &root_data, &header, logical_block)?;
-    log::debug!(
+    ext4_log!(Extent, Debug,
         "[FIND_EXTENT] depth={}, result={:?}",
         depth,
         result.as_ref().map(|e| (u32::from_le(e.block), u16::from_le(e.len)))
@@ -1633,14 +1655,14 @@ fn find_extent_in_multilevel_tree<D: BlockDevice>(
     let depth = u16::from_le(header.depth);
     let entries = u16::from_le(header.entries);
 
-    log::debug!(
+    ext4_log!(Extent, Debug,
         "[FIND_EXTENT_MULTI] depth={}, entries={}, searching for logical={}",
         depth, entries, logical_block
     );
 
     // 如果已经是叶子节点，直接查找
     if header.is_leaf() {
-        log::debug!("[FIND_EXTENT_MULTI] Node is leaf, searching in leaf");
+        ext4_log!(Extent, Debug, "[FIND_EXTENT_MULTI] Node is leaf, searching in leaf");
         return find_extent_in_leaf(node_data, logical_block);
     }
 
@@ -1670,7 +1692,7 @@ fn find_extent_in_multilevel_tree<D: BlockDevice>(
         let leaf_hi = u16::from_le(idx.leaf_hi);
         let child_block = (leaf_hi as u64) << 32 | (leaf_lo as u64);
 
-        log::debug!(
+        ext4_log!(Extent, Debug,
             "[FIND_EXTENT_MULTI] Index[{}]: idx_block={}, child_block=0x{:x}",
             i, idx_block, child_block
         );
@@ -1691,7 +1713,7 @@ fn find_extent_in_multilevel_tree<D: BlockDevice>(
             (leaf_hi as u64) << 32 | (leaf_lo as u64)
         };
 
-        log::debug!(
+        ext4_log!(Extent, Debug,
             "[FIND_EXTENT_MULTI] Selected index[{}], reading child block 0x{:x}",
             idx_num, child_block
         );
@@ -1721,7 +1743,7 @@ fn find_extent_in_multilevel_tree<D: BlockDevice>(
 
         let child_depth = u16::from_le(child_header.depth);
         let child_entries = u16::from_le(child_header.entries);
-        log::debug!(
+        ext4_log!(Extent, Debug,
             "[FIND_EXTENT_MULTI] Child node: depth={}, entries={}",
             child_depth, child_entries
         );
@@ -1729,7 +1751,7 @@ fn find_extent_in_multilevel_tree<D: BlockDevice>(
         // 递归查找
         find_extent_in_multilevel_tree(inode_ref, &child_data, &child_header, logical_block)
     } else {
-        log::debug!("[FIND_EXTENT_MULTI] No suitable index found, returning None");
+        ext4_log!(Extent, Debug, "[FIND_EXTENT_MULTI] No suitable index found, returning None");
         Ok(None)
     }
 }
@@ -1739,7 +1761,7 @@ fn find_extent_in_leaf(node_data: &[u8], logical_block: u32) -> Result<Option<ex
     let header = unsafe { *(node_data.as_ptr() as *const ext4_extent_header) };
     let entries = u16::from_le(header.entries);
 
-    log::debug!(
+    ext4_log!(Extent, Debug,
         "[FIND_EXTENT_LEAF] Searching in leaf: entries={}, logical={}",
         entries, logical_block
     );
@@ -1762,7 +1784,7 @@ fn find_extent_in_leaf(node_data: &[u8], logical_block: u32) -> Result<Option<ex
 
         // 检查逻辑块是否在这个 extent 范围内
         if logical_block >= ee_block && logical_block < ee_block + ee_len as u32 {
-            log::debug!(
+            ext4_log!(Extent, Debug,
                 "[FIND_EXTENT_LEAF] Found at entry[{}]: range=[{}-{}], physical=0x{:x}",
                 i, ee_block, ee_block + ee_len as u32 - 1,
                 crate::extent::helpers::ext4_ext_pblock(&extent)
@@ -1771,7 +1793,7 @@ fn find_extent_in_leaf(node_data: &[u8], logical_block: u32) -> Result<Option<ex
         }
     }
 
-    log::debug!("[FIND_EXTENT_LEAF] Not found in leaf");
+    ext4_log!(Extent, Debug, "[FIND_EXTENT_LEAF] Not found in leaf");
     Ok(None)
 }
 
@@ -2043,7 +2065,6 @@ impl<'a, D: BlockDevice> ExtentWriter<'a, D> {
     /// # 参数
     ///
     /// * `inode_ref` - Inode 引用
-    /// * `sb` - Superblock 引用
     /// * `allocator` - 块分配器
     /// * `logical_block` - 逻辑块起始位置
     /// * `physical_block` - 物理块起始位置
@@ -2068,7 +2089,6 @@ impl<'a, D: BlockDevice> ExtentWriter<'a, D> {
     pub fn insert_extent(
         &mut self,
         inode_ref: &mut InodeRef<D>,
-        sb: &mut crate::superblock::Superblock,
         allocator: &mut crate::balloc::BlockAllocator,
         logical_block: u32,
         physical_block: u64,
@@ -2090,7 +2110,7 @@ impl<'a, D: BlockDevice> ExtentWriter<'a, D> {
             // 节点满了，需要分裂
             if leaf.node_type == ExtentNodeType::Root {
                 // 根节点满了，需要增加树深度
-                self.grow_tree_depth(inode_ref, sb, allocator)?;
+                self.grow_tree_depth(inode_ref, allocator)?;
 
                 // 重新查找路径（树结构已改变）
                 path = self.find_extent_path(inode_ref, logical_block)?;
@@ -2099,7 +2119,6 @@ impl<'a, D: BlockDevice> ExtentWriter<'a, D> {
                 let leaf_at = path.nodes.len() - 1;
                 self.split_extent_node(
                     inode_ref,
-                    sb,
                     allocator,
                     &mut path,
                     leaf_at,
@@ -2125,8 +2144,10 @@ impl<'a, D: BlockDevice> ExtentWriter<'a, D> {
             self.insert_extent_to_inode(inode_ref, logical_block, physical_block, length)?;
         } else {
             // 插入到独立的 extent 块
+            let csum_seed = inode_ref.extent_csum_seed()?;
             self.insert_extent_to_block(
                 leaf.block_addr,
+                csum_seed,
                 logical_block,
                 physical_block,
                 length,
@@ -2230,6 +2251,7 @@ impl<'a, D: BlockDevice> ExtentWriter<'a, D> {
     fn insert_extent_to_block(
         &mut self,
         block_addr: u64,
+        csum_seed: Option<u32>,
         logical_block: u32,
         physical_block: u64,
         length: u32,
@@ -2307,6 +2329,7 @@ impl<'a, D: BlockDevice> ExtentWriter<'a, D> {
                 // 更新 header 中的 entry 计数
                 header.entries = (entries_count + 1).to_le();
 
+                super::checksum::update_checksum(csum_seed, data);
                 Ok(())
             })??;
         } // block 在这里被 drop，释放借用
@@ -2333,7 +2356,6 @@ impl<'a, D: BlockDevice> ExtentWriter<'a, D> {
     /// # 参数
     ///
     /// * `inode_ref` - Inode 引用
-    /// * `sb` - Superblock 引用
     /// * `allocator` - 块分配器
     /// * `path` - Extent 路径（包含需要分裂的节点）
     /// * `at` - 需要分裂的节点在路径中的索引
@@ -2351,7 +2373,6 @@ impl<'a, D: BlockDevice> ExtentWriter<'a, D> {
     pub fn split_extent_node(
         &mut self,
         inode_ref: &mut InodeRef<D>,
-        sb: &mut crate::superblock::Superblock,
         allocator: &mut crate::balloc::BlockAllocator,
         path: &mut ExtentPath,
         at: usize,
@@ -2359,7 +2380,6 @@ impl<'a, D: BlockDevice> ExtentWriter<'a, D> {
     ) -> Result<()> {
         crate::extent::split_extent_node(
             inode_ref,
-            sb,
             allocator,
             path,
             at,
@@ -2398,7 +2418,6 @@ impl<'a, D: BlockDevice> ExtentWriter<'a, D> {
     /// # 参数
     ///
     /// * `inode_ref` - Inode 引用
-    /// * `sb` - Superblock 引用
     /// * `allocator` - 块分配器
     ///
     /// # 返回
@@ -2422,10 +2441,9 @@ impl<'a, D: BlockDevice> ExtentWriter<'a, D> {
     pub fn grow_tree_depth(
         &mut self,
         inode_ref: &mut InodeRef<D>,
-        sb: &mut crate::superblock::Superblock,
         allocator: &mut crate::balloc::BlockAllocator,
-    ) -> Result<u64> {
-        crate::extent::grow_tree_depth(inode_ref, sb, allocator)
+    ) -> Result<Pblk> {
+        crate::extent::grow_tree_depth(inode_ref, allocator)
     }
 }
 
@@ -2442,7 +2460,6 @@ impl<'a, D: BlockDevice> ExtentWriter<'a, D> {
 /// # 参数
 ///
 /// * `inode_ref` - Inode 引用
-/// * `sb` - Superblock 引用
 /// * `from` - 起始逻辑块号
 /// * `to` - 结束逻辑块号（包含）
 ///
@@ -2462,17 +2479,18 @@ impl<'a, D: BlockDevice> ExtentWriter<'a, D> {
 ///
 /// ```rust,ignore
 /// // 删除逻辑块 10-19（共 10 个块）
-/// remove_space(&mut inode_ref, &mut sb, 10, 19)?;
+/// remove_space(&mut inode_ref, 10, 19)?;
 ///
 /// // 截断文件到 100 个块
-/// remove_space(&mut inode_ref, &mut sb, 100, u32::MAX)?;
+/// remove_space(&mut inode_ref, 100, u32::MAX)?;
 /// ```
 pub fn remove_space<D: BlockDevice>(
     inode_ref: &mut InodeRef<D>,
-    sb: &mut Superblock,
-    from: u32,
-    to: u32,
+    from: impl Into<Lblk>,
+    to: impl Into<Lblk>,
 ) -> Result<()> {
+    let (Lblk(from), Lblk(to)) = (from.into(), to.into());
+
     // 读取 extent 树深度
     let depth = inode_ref.with_inode(|inode| {
         let header_ptr = inode.blocks.as_ptr() as *const ext4_extent_header;
@@ -2487,12 +2505,11 @@ pub fn remove_space<D: BlockDevice>(
 
     if depth == 0 {
         // 深度 0 使用优化的简化版本
-        remove_space_simple(inode_ref, sb, from, to)?;
+        remove_space_simple(inode_ref, from, to)?;
     } else {
         // 多层树使用完整实现
         crate::extent::remove_space_multilevel(
             inode_ref,
-            sb,
             &mut allocator,
             from,
             to,
@@ -2509,7 +2526,6 @@ pub fn remove_space<D: BlockDevice>(
 /// # 参数
 ///
 /// * `inode_ref` - Inode 引用
-/// * `sb` - Superblock 引用
 /// * `from` - 起始逻辑块号
 /// * `to` - 结束逻辑块号
 ///
@@ -2524,7 +2540,6 @@ pub fn remove_space<D: BlockDevice>(
 /// 4. 更新 extent 数组
 fn remove_space_simple<D: BlockDevice>(
     inode_ref: &mut InodeRef<D>,
-    sb: &mut Superblock,
     from: u32,
     to: u32,
 ) -> Result<()> {
@@ -2574,7 +2589,6 @@ fn remove_space_simple<D: BlockDevice>(
     for modification in modifications.iter().rev() {
         apply_extent_removal(
             inode_ref,
-            sb,
             modification.index,
             modification.ee_block,
             modification.ee_len,
@@ -2602,7 +2616,6 @@ struct ExtentModification {
 /// # 参数
 ///
 /// * `inode_ref` - Inode 引用
-/// * `sb` - Superblock 引用
 /// * `extent_idx` - Extent 在数组中的索引
 /// * `ee_block` - Extent 的起始逻辑块
 /// * `ee_len` - Extent 的长度
@@ -2611,7 +2624,6 @@ struct ExtentModification {
 /// * `to` - 删除范围的结束逻辑块
 fn apply_extent_removal<D: BlockDevice>(
     inode_ref: &mut InodeRef<D>,
-    sb: &mut Superblock,
     extent_idx: usize,
     ee_block: u32,
     ee_len: u32,
@@ -2625,7 +2637,8 @@ fn apply_extent_removal<D: BlockDevice>(
     if from <= ee_block && to >= ee_end {
         // 删除整个 extent
         // 1. 释放物理块
-        balloc::free_blocks(inode_ref.bdev(), sb, ee_start, ee_len)?;
+        let (bdev, sb) = inode_ref.bdev_and_sb_mut();
+        balloc::free_blocks(bdev, sb, ee_start, ee_len)?;
 
         // 2. 从数组中移除 extent
         remove_extent_at_index(inode_ref, extent_idx)?;
@@ -2639,7 +2652,8 @@ fn apply_extent_removal<D: BlockDevice>(
         let new_start = ee_start + removed_len as u64;
 
         // 1. 释放被删除的块
-        balloc::free_blocks(inode_ref.bdev(), sb, ee_start, removed_len)?;
+        let (bdev, sb) = inode_ref.bdev_and_sb_mut();
+        balloc::free_blocks(bdev, sb, ee_start, removed_len)?;
 
         // 2. 更新 extent
         update_extent_at_index(inode_ref, extent_idx, new_block, new_len, new_start)?;
@@ -2652,7 +2666,8 @@ fn apply_extent_removal<D: BlockDevice>(
         let removed_start = ee_start + (from - ee_block) as u64;
 
         // 1. 释放被删除的块
-        balloc::free_blocks(inode_ref.bdev(), sb, removed_start, removed_len)?;
+        let (bdev, sb) = inode_ref.bdev_and_sb_mut();
+        balloc::free_blocks(bdev, sb, removed_start, removed_len)?;
 
         // 2. 更新 extent
         update_extent_at_index(inode_ref, extent_idx, ee_block, new_len, ee_start)?;
@@ -2669,7 +2684,8 @@ fn apply_extent_removal<D: BlockDevice>(
         let right_start = ee_start + (left_len + middle_len) as u64;
 
         // 1. 释放中间的块
-        balloc::free_blocks(inode_ref.bdev(), sb, middle_start, middle_len)?;
+        let (bdev, sb) = inode_ref.bdev_and_sb_mut();
+        balloc::free_blocks(bdev, sb, middle_start, middle_len)?;
 
         // 2. 更新左边的 extent
         update_extent_at_index(inode_ref, extent_idx, ee_block, left_len, ee_start)?;
@@ -2782,6 +2798,7 @@ fn update_extent_at_index<D: BlockDevice>(
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::consts::EXT4_EXTENT_MAGIC;
 
     #[test]
     fn test_extent_path_creation() {
@@ -2796,4 +2813,193 @@ mod tests {
         assert_eq!(node_type, ExtentNodeType::Leaf);
         assert_ne!(node_type, ExtentNodeType::Index);
     }
+
+    struct MemDevice {
+        storage: Vec<u8>,
+    }
+
+    impl BlockDevice for MemDevice {
+        fn block_size(&self) -> u32 {
+            4096
+        }
+
+        fn sector_size(&self) -> u32 {
+            512
+        }
+
+        fn total_blocks(&self) -> u64 {
+            (self.storage.len() / 4096) as u64
+        }
+
+        fn read_blocks(&mut self, lba: u64, count: u32, buf: &mut [u8]) -> Result<usize> {
+            let start = lba as usize * 512;
+            let len = count as usize * 512;
+            buf[..len].copy_from_slice(&self.storage[start..start + len]);
+            Ok(len)
+        }
+
+        fn write_blocks(&mut self, _lba: u64, count: u32, _buf: &[u8]) -> Result<usize> {
+            Ok(count as usize * 512)
+        }
+    }
+
+    /// 构造一个 extent 节点：`entries` 为 (起始逻辑块, 长度或子节点物理块)
+    fn make_node(size: usize, depth: u16, entries: &[(u32, u32)]) -> Vec<u8> {
+        let mut node = alloc::vec![0u8; size];
+        let header = ext4_extent_header {
+            magic: EXT4_EXTENT_MAGIC.to_le(),
+            entries: (entries.len() as u16).to_le(),
+            max: (((size - 12) / 12) as u16).to_le(),
+            depth: depth.to_le(),
+            generation: 0,
+        };
+        unsafe { core::ptr::write_unaligned(node.as_mut_ptr() as *mut ext4_extent_header, header) };
+        for (i, &(block, value)) in entries.iter().enumerate() {
+            let entry = &mut node[12 + i * 12..24 + i * 12];
+            entry[..4].copy_from_slice(&block.to_le_bytes());
+            if depth == 0 {
+                // ext4_extent: len 为 u16，物理块随意
+                entry[4..6].copy_from_slice(&(value as u16).to_le_bytes());
+                entry[8..12].copy_from_slice(&1000u32.to_le_bytes());
+            } else {
+                // ext4_extent_idx: leaf_lo
+                entry[4..8].copy_from_slice(&value.to_le_bytes());
+            }
+        }
+        node
+    }
+
+    fn mem_bdev(blocks: &[(usize, Vec<u8>)]) -> BlockDev<MemDevice> {
+        let mut storage = alloc::vec![0u8; 16 * 4096];
+        for (lba, data) in blocks {
+            storage[lba * 4096..(lba + 1) * 4096].copy_from_slice(data);
+        }
+        BlockDev::new(MemDevice { storage }).unwrap()
+    }
+
+    #[test]
+    fn test_next_allocated_block_depth0() {
+        let mut bdev = mem_bdev(&[]);
+        let root = make_node(60, 0, &[(0, 4), (10, 2), (40, 1)]);
+
+        let next = |bdev: &mut BlockDev<MemDevice>, lblk| {
+            next_allocated_block_in_tree(bdev, root.clone(), lblk).unwrap()
+        };
+        assert_eq!(next(&mut bdev, 0), 10);
+        assert_eq!(next(&mut bdev, 5), 10);
+        assert_eq!(next(&mut bdev, 10), 40);
+        assert_eq!(next(&mut bdev, 40), u32::MAX);
+    }
+
+    #[test]
+    fn test_next_allocated_block_depth1() {
+        // 根索引 -> 叶子 1 [0, 10]，叶子 2 [100, 150]
+        let mut bdev = mem_bdev(&[
+            (1, make_node(4096, 0, &[(0, 4), (10, 2)])),
+            (2, make_node(4096, 0, &[(100, 8), (150, 1)])),
+        ]);
+        let root = make_node(60, 1, &[(0, 1), (100, 2)]);
+
+        let next = |bdev: &mut BlockDev<MemDevice>, lblk| {
+            next_allocated_block_in_tree(bdev, root.clone(), lblk).unwrap()
+        };
+        assert_eq!(next(&mut bdev, 3), 10);
+        // 叶子 1 中没有更大的 extent，由根索引给出右侧叶子的起点
+        assert_eq!(next(&mut bdev, 20), 100);
+        assert_eq!(next(&mut bdev, 100), 150);
+        assert_eq!(next(&mut bdev, 200), u32::MAX);
+    }
+
+    #[test]
+    fn test_leaf_upper_bound() {
+        // 只看索引节点：叶子内的 extent 不影响上界
+        let mut bdev = mem_bdev(&[
+            (1, make_node(4096, 0, &[(0, 4), (10, 2)])),
+            (2, make_node(4096, 0, &[(100, 8), (150, 1)])),
+        ]);
+        let root = make_node(60, 1, &[(0, 1), (100, 2)]);
+
+        let bound = |bdev: &mut BlockDev<MemDevice>, lblk| {
+            next_entry_in_tree(bdev, root.clone(), lblk, false).unwrap()
+        };
+        assert_eq!(bound(&mut bdev, 3), 100);
+        assert_eq!(bound(&mut bdev, 99), 100);
+        assert_eq!(bound(&mut bdev, 100), u32::MAX);
+    }
+
+    #[test]
+    fn test_next_allocated_block_depth2() {
+        // 根索引 -> 索引块 1 [0 -> 叶 3, 50 -> 叶 4]，索引块 2 [500 -> 叶 5]
+        let mut bdev = mem_bdev(&[
+            (1, make_node(4096, 1, &[(0, 3), (50, 4)])),
+            (2, make_node(4096, 1, &[(500, 5)])),
+            (3, make_node(4096, 0, &[(0, 1), (20, 1)])),
+            (4, make_node(4096, 0, &[(50, 10), (80, 1)])),
+            (5, make_node(4096, 0, &[(500, 1), (700, 1)])),
+        ]);
+        let root = make_node(60, 2, &[(0, 1), (500, 2)]);
+
+        let next = |bdev: &mut BlockDev<MemDevice>, lblk| {
+            next_allocated_block_in_tree(bdev, root.clone(), lblk).unwrap()
+        };
+        assert_eq!(next(&mut bdev, 1), 20);
+        assert_eq!(next(&mut bdev, 30), 50);
+        assert_eq!(next(&mut bdev, 60), 80);
+        // 索引块 1 的最后一个叶子之后，由根索引给出下一个子树
+        assert_eq!(next(&mut bdev, 90), 500);
+        assert_eq!(next(&mut bdev, 600), 700);
+        assert_eq!(next(&mut bdev, 700), u32::MAX);
+    }
+
+    #[test]
+    fn test_next_allocated_block_depth_mismatch() {
+        // 索引块 1 指向自身，子节点深度与预期不符
+        let mut bdev = mem_bdev(&[(1, make_node(4096, 1, &[(0, 1)]))]);
+        let root = make_node(60, 2, &[(0, 1)]);
+
+        let err = next_allocated_block_in_tree(&mut bdev, root, 0).unwrap_err();
+        assert_eq!(err.kind(), ErrorKind::Corrupted);
+    }
+
+    #[cfg(feature = "metadata-csum")]
+    #[test]
+    fn test_extent_block_checksums() {
+        use crate::{fs::{Ext4FileSystem, FsConfig}, testing::image};
+
+        let mount = |dev| {
+            let config = FsConfig { verify_checksums: true, ..Default::default() };
+            Ext4FileSystem::mount_with_config(BlockDev::new_with_cache(dev, 64).unwrap(), config).unwrap()
+        };
+        let mut fs = mount(image::image_with_csum());
+        let ino = fs.create_file("/", "sparse", 0o644).unwrap();
+
+        // 每隔一块写一块，每块一个 extent：叶子分裂，根索引满后树再长一层
+        const EXTENTS: u64 = 1200;
+        let block = |i: u64| [(i % 251) as u8; 4096];
+        for i in 0..EXTENTS {
+            fs.write_at_inode(ino, &block(i), i * 2 * 4096).unwrap();
+        }
+        let depth = fs.get_inode_ref(ino).unwrap().with_inode(|inode| {
+            let header = unsafe { &*(inode.blocks.as_ptr() as *const ext4_extent_header) };
+            header.depth()
+        }).unwrap();
+        assert_eq!(depth, 2);
+
+        let check = |fs: &mut Ext4FileSystem<_>, count: u64| {
+            let mut buf = [0u8; 4096];
+            for i in 0..count {
+                fs.read_at_inode(ino, &mut buf, i * 2 * 4096).unwrap();
+                assert_eq!(buf, block(i), "block {i}");
+            }
+        };
+        let dev = fs.unmount().unwrap().device().clone();
+        let mut fs = mount(dev);
+        check(&mut fs, EXTENTS);
+
+        // 截断同样修改（并释放）非根 extent 块
+        fs.truncate_file(ino, 500 * 2 * 4096).unwrap();
+        let dev = fs.unmount().unwrap().device().clone();
+        let mut fs = mount(dev);
+        check(&mut fs, 500);
+    }
 }
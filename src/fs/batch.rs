@@ -0,0 +1,227 @@
+//! 批量创建：解包归档时把大量小操作合并为一次提交
+//!
+//! [`Ext4FileSystem::batch`] 执行闭包期间：
+//! - 不做定时提交和日志容量提交，闭包中的所有修改在结束时一起提交
+//! - 块缓存不做阈值写回，位图、inode 表和目录块留在缓存中，结束时一起写回
+//! - 在已有目录块中插入目录项时不重算块校验和，结束时每个修改过的目录重算一次
+//! - [`Batch`] 缓存解析过的父目录路径，同一目录下的连续创建不必重复查找路径
+//!
+//! 闭包返回错误时已经完成的操作不会撤销，校验和同样会重算并提交。
+
+use crate::{
+    block::{BlockDevice, FsOp},
+    consts::EXT4_ROOT_INODE,
+    dir::checksum,
+    error::{Error, ErrorKind, Result},
+    xattr,
+};
+use alloc::{
+    collections::{BTreeMap, BTreeSet},
+    string::String,
+};
+
+use super::{filesystem::Ext4FileSystem, inode_ref::InodeRef, types::InodeType};
+
+/// 批量操作句柄，见 [`Ext4FileSystem::batch`]
+///
+/// 路径参数与 [`Ext4FileSystem`] 的同名方法相同（绝对路径）。
+pub struct Batch<'a, D: BlockDevice> {
+    fs: &'a mut Ext4FileSystem<D>,
+    /// 本次批量操作中解析过或新建的目录（路径 -> inode 号）
+    dirs: BTreeMap<String, u32>,
+}
+
+impl<D: BlockDevice> Batch<'_, D> {
+    /// 创建普通文件，见 [`Ext4FileSystem::create_file`]
+    pub fn create_file(&mut self, parent_path: &str, name: &str, mode: u16) -> Result<u32> {
+        let parent = self.resolve_dir(parent_path)?;
+        self.fs.observe(FsOp::Create, |fs| {
+            fs.with_alloc_undo(|fs, undo| fs.create_file_in_steps(undo, parent, name, mode))
+        })
+    }
+
+    /// 创建目录，见 [`Ext4FileSystem::create_dir`]
+    ///
+    /// 新目录的路径记入缓存，之后在其中创建文件不需要查找
+    pub fn create_dir(&mut self, parent_path: &str, name: &str, mode: u16) -> Result<u32> {
+        let parent = self.resolve_dir(parent_path)?;
+        let ino = self.fs.observe(FsOp::Create, |fs| {
+            fs.with_alloc_undo(|fs, undo| fs.create_dir_in_steps(undo, parent, name, mode))
+        })?;
+        self.dirs.insert(child_path(parent_path, name), ino);
+        Ok(ino)
+    }
+
+    /// 依次创建路径上所有不存在的目录，见 [`Ext4FileSystem::create_dir_all`]
+    ///
+    /// 路径记入缓存，之后在其中创建文件不需要查找
+    pub fn create_dir_all(&mut self, path: &str, mode: u16) -> Result<u32> {
+        let path = path.trim_end_matches('/');
+        if let Some(&ino) = self.dirs.get(path) {
+            return Ok(ino);
+        }
+        let ino = self.fs.create_dir_all(path, mode)?;
+        self.dirs.insert(String::from(path), ino);
+        Ok(ino)
+    }
+
+    /// 创建符号链接，见 [`Ext4FileSystem::fsymlink`]
+    pub fn symlink(&mut self, target: &str, parent_path: &str, name: &str) -> Result<u32> {
+        let parent = self.resolve_dir(parent_path)?;
+        self.fs.observe(FsOp::Create, |fs| {
+            fs.with_alloc_undo(|fs, undo| fs.fsymlink_in_steps(undo, target, parent, name))
+        })
+    }
+
+    /// 创建设备文件、FIFO 或 Socket，见 [`Ext4FileSystem::mknod`]
+    pub fn mknod(&mut self, parent_path: &str, name: &str, node_type: InodeType, mode: u16, rdev: u64) -> Result<u32> {
+        let parent = self.resolve_dir(parent_path)?;
+        self.fs.mknod_in(parent, name, node_type, mode, rdev)
+    }
+
+    /// 为已有的 inode 创建硬链接，见 [`Ext4FileSystem::link_inode`]
+    pub fn link(&mut self, inode_num: u32, parent_path: &str, name: &str) -> Result<()> {
+        let parent = self.resolve_dir(parent_path)?;
+        self.fs.link_inode(parent, name, inode_num)
+    }
+
+    /// 查找路径对应的 inode 号，见 [`Ext4FileSystem::lookup_at`]
+    pub fn lookup(&mut self, path: &str) -> Result<u32> {
+        match self.dirs.get(path.trim_end_matches('/')) {
+            Some(&ino) => Ok(ino),
+            None => self.fs.lookup_at(EXT4_ROOT_INODE, path),
+        }
+    }
+
+    /// 修改 inode 的属性（权限、属主、时间戳等），见 [`Ext4FileSystem::with_inode_ref`]
+    ///
+    /// 不会清空路径缓存
+    pub fn with_inode_ref<R>(&mut self, inode_num: u32, f: impl FnOnce(&mut InodeRef<D>) -> Result<R>) -> Result<R> {
+        self.fs.with_inode_ref(inode_num, f)
+    }
+
+    /// 把 `buf` 全部写入文件的 `offset` 处，见 [`Ext4FileSystem::write_at_inode_batch`]
+    pub fn write(&mut self, inode_num: u32, buf: &[u8], offset: u64) -> Result<()> {
+        let mut done = 0;
+        while done < buf.len() {
+            let n = self.fs.write_at_inode_batch(inode_num, &buf[done..], offset + done as u64)?;
+            if n == 0 {
+                return Err(Error::new(ErrorKind::Io, "Short write in batch"));
+            }
+            done += n;
+        }
+        Ok(())
+    }
+
+    /// 设置扩展属性，见 [`Ext4FileSystem::setxattr`]
+    pub fn setxattr(&mut self, inode_num: u32, name: &str, value: &[u8]) -> Result<()> {
+        self.fs.with_inode_ref(inode_num, |inode_ref| xattr::set(inode_ref, name, value))
+    }
+
+    /// 访问文件系统，执行批量句柄没有提供的操作
+    ///
+    /// 这些操作可能重命名或删除目录，调用后清空路径缓存
+    pub fn fs(&mut self) -> &mut Ext4FileSystem<D> {
+        self.dirs.clear();
+        self.fs
+    }
+
+    fn resolve_dir(&mut self, path: &str) -> Result<u32> {
+        if let Some(&ino) = self.dirs.get(path) {
+            return Ok(ino);
+        }
+        let ino = self.fs.lookup_at(EXT4_ROOT_INODE, path)?;
+        self.dirs.insert(String::from(path), ino);
+        Ok(ino)
+    }
+}
+
+impl<D: BlockDevice> Ext4FileSystem<D> {
+    /// 批量执行创建操作，结束时提交一次
+    ///
+    /// 闭包中通过 [`Batch`] 创建的文件、目录、写入的数据和扩展属性共享一次提交，
+    /// 目录块校验和与位图的写回也推迟到结束时，适合解包 tar/cpio 这类
+    /// 大量小文件的场景。嵌套调用时只有最外层在结束时提交。
+    ///
+    /// # 示例
+    ///
+    /// ```rust,ignore
+    /// fs.batch(|tx| {
+    ///     let dir = tx.create_dir("/", "etc", 0o755)?;
+    ///     let ino = tx.create_file("/etc", "hostname", 0o644)?;
+    ///     tx.write(ino, b"device\n", 0)?;
+    ///     tx.setxattr(dir, "user.origin", b"rootfs.tar")?;
+    ///     Ok(())
+    /// })?;
+    /// ```
+    pub fn batch<R>(&mut self, f: impl FnOnce(&mut Batch<'_, D>) -> Result<R>) -> Result<R> {
+        self.superblock().check_writable()?;
+        if !self.commit.hold() {
+            return f(&mut Batch { fs: self, dirs: BTreeMap::new() });
+        }
+
+        self.superblock_mut().begin_defer_dir_csums();
+        let writeback = self.bdev.writeback_config();
+        if writeback.is_some() {
+            self.bdev.set_writeback_config(crate::cache::WritebackConfig::disabled());
+        }
+
+        let result = f(&mut Batch { fs: self, dirs: BTreeMap::new() });
+
+        let dirs = self.superblock_mut().take_deferred_dir_csums();
+        if let Some(config) = writeback {
+            self.bdev.set_writeback_config(config);
+        }
+        self.commit.release();
+        let finished = self.rewrite_dir_csums(dirs).and_then(|()| self.commit_batch());
+
+        let value = result?;
+        finished?;
+        Ok(value)
+    }
+
+    /// 重算目录中所有叶子块的校验和
+    ///
+    /// HTree 索引块的校验和在修改时已经更新
+    fn rewrite_dir_csums(&mut self, dirs: BTreeSet<u32>) -> Result<()> {
+        let block_size = self.superblock().block_size();
+        for dir in dirs {
+            self.with_inode_ref(dir, |inode_ref| {
+                let seed = inode_ref.csum_seed()?;
+                let blocks = inode_ref.size()?.div_ceil(block_size as u64).min(u32::MAX as u64) as u32;
+                for m in inode_ref.fiemap(0..blocks)? {
+                    for block in m.physical_block..m.physical_block + m.len as u64 {
+                        let mut block = crate::block::Block::get(inode_ref.bdev(), block)?;
+                        block.with_data_mut(|data| {
+                            if checksum::get_tail(data, block_size as usize).is_some() {
+                                checksum::set_leaf_csum(seed, data, block_size as usize);
+                            }
+                        })?;
+                    }
+                }
+                Ok(())
+            })?;
+        }
+        Ok(())
+    }
+}
+
+/// 父目录路径与名字拼接成的路径
+fn child_path(parent: &str, name: &str) -> String {
+    let mut path = String::from(parent.trim_end_matches('/'));
+    path.push('/');
+    path.push_str(name);
+    path
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_child_path() {
+        assert_eq!(child_path("/", "etc"), "/etc");
+        assert_eq!(child_path("/etc", "init.d"), "/etc/init.d");
+        assert_eq!(child_path("/etc/", "init.d"), "/etc/init.d");
+    }
+}
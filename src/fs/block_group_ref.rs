@@ -4,7 +4,7 @@
 
 use crate::{
     block::{Block, BlockDev, BlockDevice},
-    block_group::get_block_group_desc_location,
+    block_group::{checksum, get_block_group_desc_location},
     consts::*,
     error::Result,
     superblock::Superblock,
@@ -24,6 +24,11 @@ use crate::{
 /// 2. **性能**: 避免不必要的数据复制
 /// 3. **正确语义**: 修改直接作用于 cache，自动标记为脏
 ///
+/// 所有修改都经过 [`with_block_group_mut`](Self::with_block_group_mut)，
+/// 它在修改后自动重新计算描述符校验和（启用 `metadata_csum` 或 `GDT_CSUM` 时），
+/// 调用者无需自行维护。位图校验和通过 [`set_block_bitmap_csum`](Self::set_block_bitmap_csum)
+/// 和 [`set_inode_bitmap_csum`](Self::set_inode_bitmap_csum) 写入，同样会刷新描述符校验和。
+///
 /// # 生命周期
 ///
 /// - 创建时获取包含块组描述符的 block 句柄
@@ -110,16 +115,21 @@ impl<'a, D: BlockDevice> BlockGroupRef<'a, D> {
 
     /// 访问块组描述符数据（可写）
     ///
-    /// 通过闭包修改块组描述符数据，自动标记 block 为脏
+    /// 通过闭包修改块组描述符数据，自动标记 block 为脏并重新计算描述符校验和
     pub fn with_block_group_mut<F, R>(&mut self, f: F) -> Result<R>
     where
         F: FnOnce(&mut ext4_group_desc) -> R,
     {
+        let sb = self.sb;
+        let bgid = self.bgid;
+        let offset = self.offset_in_block;
         let result = self.block.with_data_mut(|data| {
             let desc = unsafe {
-                &mut *(data.as_mut_ptr().add(self.offset_in_block) as *mut ext4_group_desc)
+                &mut *(data.as_mut_ptr().add(offset) as *mut ext4_group_desc)
             };
-            f(desc)
+            let result = f(desc);
+            checksum::set_checksum(sb, bgid, &mut data[offset..offset + sb.group_desc_size()]);
+            result
         })?;
         self.dirty = true;
         Ok(result)
@@ -350,6 +360,47 @@ impl<'a, D: BlockDevice> BlockGroupRef<'a, D> {
         })
     }
 
+    /// 设置块位图校验和
+    ///
+    /// `csum` 为 [`balloc::bitmap_csum`](crate::balloc::bitmap_csum) 对修改后位图的计算结果。
+    /// 未启用 `metadata_csum` 时不做修改
+    pub fn set_block_bitmap_csum(&mut self, csum: u32) -> Result<()> {
+        let sb = self.sb;
+        if !sb.has_metadata_csum() {
+            return Ok(());
+        }
+        self.with_block_group_mut(|desc| {
+            desc.block_bitmap_csum_lo = (csum as u16).to_le();
+
+            if sb.group_desc_size() >= EXT4_BG_BLOCK_BITMAP_CSUM_HI_END {
+                desc.block_bitmap_csum_hi = ((csum >> 16) as u16).to_le();
+            }
+        })
+    }
+
+    /// 设置 inode 位图校验和
+    ///
+    /// `csum` 为 [`ialloc::bitmap_csum`](crate::ialloc::bitmap_csum) 对修改后位图的计算结果。
+    /// 未启用 `metadata_csum` 时不做修改
+    pub fn set_inode_bitmap_csum(&mut self, csum: u32) -> Result<()> {
+        let sb = self.sb;
+        if !sb.has_metadata_csum() {
+            return Ok(());
+        }
+        self.with_block_group_mut(|desc| {
+            desc.inode_bitmap_csum_lo = (csum as u16).to_le();
+
+            if sb.group_desc_size() >= EXT4_BG_INODE_BITMAP_CSUM_HI_END {
+                desc.inode_bitmap_csum_hi = ((csum >> 16) as u16).to_le();
+            }
+        })
+    }
+
+    /// 是否设置了 `flags` 中的任一标志（`EXT4_BG_*`）
+    pub fn has_flag(&mut self, flags: u16) -> Result<bool> {
+        self.with_block_group(|desc| u16::from_le(desc.flags) & flags != 0)
+    }
+
     /// 获取块组描述符的拷贝（用于需要长期持有的场景）
     ///
     /// 注意：返回的是数据副本，修改不会反映到磁盘
@@ -368,10 +419,76 @@ impl<'a, D: BlockDevice> Drop for BlockGroupRef<'a, D> {
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::{block_group::checksum::verify_checksum, types::ext4_sblock};
+
+    struct MemDevice {
+        storage: alloc::vec::Vec<u8>,
+    }
+
+    impl BlockDevice for MemDevice {
+        fn block_size(&self) -> u32 {
+            4096
+        }
+
+        fn sector_size(&self) -> u32 {
+            512
+        }
+
+        fn total_blocks(&self) -> u64 {
+            (self.storage.len() / 4096) as u64
+        }
+
+        fn read_blocks(&mut self, lba: u64, count: u32, buf: &mut [u8]) -> Result<usize> {
+            let start = lba as usize * 512;
+            let len = count as usize * 512;
+            buf[..len].copy_from_slice(&self.storage[start..start + len]);
+            Ok(len)
+        }
+
+        fn write_blocks(&mut self, lba: u64, count: u32, buf: &[u8]) -> Result<usize> {
+            let start = lba as usize * 512;
+            let len = count as usize * 512;
+            self.storage[start..start + len].copy_from_slice(&buf[..len]);
+            Ok(len)
+        }
+    }
 
     #[test]
     fn test_block_group_ref_api() {
         // 这些测试需要实际的块设备和 ext4 文件系统
         // 主要是验证 API 的设计和编译
     }
+
+    #[test]
+    fn test_mutation_refreshes_checksums() {
+        let sb = Superblock::new(ext4_sblock {
+            log_block_size: 2u32.to_le(),
+            blocks_per_group: 32768u32.to_le(),
+            inodes_per_group: 64u32.to_le(),
+            blocks_count_lo: 16u32.to_le(),
+            inodes_count: 64u32.to_le(),
+            desc_size: 64u16.to_le(),
+            uuid: [3; 16],
+            feature_incompat: EXT4_FEATURE_INCOMPAT_64BIT.to_le(),
+            feature_ro_compat: EXT4_FEATURE_RO_COMPAT_METADATA_CSUM.to_le(),
+            ..Default::default()
+        });
+        let mut bdev = BlockDev::new(MemDevice { storage: alloc::vec![0u8; 16 * 4096] }).unwrap();
+
+        {
+            let mut bg_ref = BlockGroupRef::get(&mut bdev, &sb, 0).unwrap();
+            bg_ref.set_free_blocks_count(10).unwrap();
+            bg_ref.dec_free_blocks(1).unwrap();
+            bg_ref.set_block_bitmap_csum(0x1234_5678).unwrap();
+            bg_ref.set_inode_bitmap_csum(0x9abc_def0).unwrap();
+        }
+
+        let desc = Block::get(&mut bdev, 1u64).unwrap().with_data(|data| data[..64].to_vec()).unwrap();
+        assert!(verify_checksum(&sb, 0, &desc));
+        // bg_block_bitmap_csum_lo/hi 和 bg_inode_bitmap_csum_lo/hi
+        assert_eq!(desc[0x18..0x1A], [0x78, 0x56]);
+        assert_eq!(desc[0x38..0x3A], [0x34, 0x12]);
+        assert_eq!(desc[0x1A..0x1C], [0xf0, 0xde]);
+        assert_eq!(desc[0x3A..0x3C], [0xbc, 0x9a]);
+    }
 }
@@ -0,0 +1,275 @@
+//! 定时提交
+//!
+//! 与内核的 `commit=` 挂载选项相同，脏数据最多在内存中停留一个提交间隔
+//! （默认 5 秒，见 [`DEFAULT_COMMIT_INTERVAL`]）。文件系统自身没有定时器，
+//! 由宿主系统的定时器周期性调用 [`Ext4FileSystem::on_timer_tick`]，
+//! 当前时间来自 [`SystemHal::now`]。
+//!
+//! 以下情况不等待定时器，立即提交：
+//! - [`Ext4FileSystem::fsync`] / [`File::fsync`](super::File::fsync)
+//! - 待提交的脏块数达到单个日志事务的容量（日志块数的 1/4），
+//!   继续累积会使下一次提交无法装入日志
+//!
+//! [`Ext4FileSystem::batch`] 执行期间不做定时提交和日志容量提交，结束时提交一次。
+//!
+//! 一次提交把延迟分配的数据、配额文件、脏缓存块和 superblock 写回设备；
+//! 启用日志时脏元数据块先作为一个事务提交到日志，见 [`journal`](super::journal)。
+
+use crate::{block::{BlockDevice, FsOp}, error::Result, logging};
+use core::time::Duration;
+use log::debug;
+
+use super::{filesystem::Ext4FileSystem, types::SystemHal};
+
+/// 默认提交间隔，与内核 `JBD2_DEFAULT_MAX_COMMIT_AGE` 相同
+pub const DEFAULT_COMMIT_INTERVAL: Duration = Duration::from_secs(5);
+
+/// 触发提交的原因（用于日志）
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum CommitReason {
+    Timer,
+    Fsync,
+    JournalSpace,
+    Batch,
+}
+
+/// 提交调度状态
+#[derive(Debug)]
+pub(super) struct CommitScheduler {
+    /// 提交间隔，`None` 表示不做定时提交
+    interval: Option<Duration>,
+    /// 上次提交（或第一次 tick）的时间，`None` 表示还没有时间基准
+    last_commit: Option<Duration>,
+    /// 单个日志事务的容量（块），`None` 表示没有日志
+    journal_capacity: Option<u64>,
+    /// 批量操作期间不做定时提交和日志容量提交，见 [`batch`](Ext4FileSystem::batch)
+    held: bool,
+}
+
+impl CommitScheduler {
+    pub(super) fn new(interval: Option<Duration>, journal_capacity: Option<u64>) -> Self {
+        Self { interval, last_commit: None, journal_capacity, held: false }
+    }
+
+    /// 暂停自动提交，已经暂停时返回 false
+    pub(super) fn hold(&mut self) -> bool {
+        !core::mem::replace(&mut self.held, true)
+    }
+
+    /// 恢复自动提交
+    pub(super) fn release(&mut self) {
+        self.held = false;
+    }
+
+    /// 到 `now` 时是否应该定时提交
+    ///
+    /// 第一次调用只记录时间基准，不触发提交
+    fn timer_due(&mut self, now: Duration) -> bool {
+        let Some(interval) = self.interval.filter(|_| !self.held) else {
+            return false;
+        };
+        match self.last_commit {
+            Some(last) => now.saturating_sub(last) >= interval,
+            None => {
+                self.last_commit = Some(now);
+                false
+            }
+        }
+    }
+
+    /// `dirty_blocks` 个待提交的块是否已经占满一个日志事务
+    fn journal_full(&self, dirty_blocks: u64) -> bool {
+        !self.held && self.journal_capacity.is_some_and(|cap| dirty_blocks >= cap)
+    }
+
+    /// 记录一次提交，`now` 为 `None`（时间未知）时保留原来的时间基准
+    fn committed(&mut self, now: Option<Duration>) {
+        if now.is_some() {
+            self.last_commit = now;
+        }
+    }
+}
+
+impl<D: BlockDevice> Ext4FileSystem<D> {
+    /// 定时器回调
+    ///
+    /// 由宿主系统的定时器周期性调用（周期应明显小于提交间隔，例如 1 秒）。
+    /// 距上次提交超过 [`commit_interval`](Self::commit_interval) 且有未提交的修改时提交。
+    ///
+    /// `H::now()` 返回 `None` 时无法计时，不做任何事；第一次调用只记录时间基准。
+    /// 每次调用都开始新的日志限流周期，见 [`logging`](crate::logging)。
+    ///
+    /// # 返回
+    ///
+    /// 本次调用是否执行了提交
+    ///
+    /// # 示例
+    ///
+    /// ```rust,ignore
+    /// // 在宿主系统的 1 秒定时器中
+    /// fs.on_timer_tick::<MyHal>()?;
+    /// ```
+    pub fn on_timer_tick<H: SystemHal>(&mut self) -> Result<bool> {
+        logging::new_period();
+        let Some(now) = H::now() else {
+            return Ok(false);
+        };
+        if !self.commit.timer_due(now) {
+            return Ok(false);
+        }
+
+        if self.uncommitted_blocks() == 0 {
+            // 没有需要提交的内容，重新开始计时
+            self.commit.committed(Some(now));
+            return Ok(false);
+        }
+
+        self.commit_now(CommitReason::Timer)?;
+        self.commit.committed(Some(now));
+        Ok(true)
+    }
+
+    /// 设置提交间隔，`None` 关闭定时提交
+    ///
+    /// 也可以通过 [`FsConfig::commit_interval`](super::FsConfig::commit_interval) 在挂载时设置
+    pub fn set_commit_interval(&mut self, interval: Option<Duration>) {
+        self.commit.interval = interval;
+    }
+
+    /// 当前的提交间隔
+    pub fn commit_interval(&self) -> Option<Duration> {
+        self.commit.interval
+    }
+
+    /// 立即提交所有未提交的修改
+    ///
+    /// 对应 `fsync(2)` / `syncfs(2)`：返回时延迟分配的数据、脏缓存块和
+    /// superblock 都已写回设备。
+    pub fn fsync(&mut self) -> Result<()> {
+        self.commit_now(CommitReason::Fsync)?;
+        self.commit.committed(None);
+        Ok(())
+    }
+
+    /// 写回最多 `max_blocks` 个最久未使用的脏缓存块，返回实际写回的块数
+    ///
+    /// 供宿主系统在后台任务中周期性调用，缩短脏块停留在内存中的时间；
+    /// 弄脏缓存块时超过高水位的自动写回见
+    /// [`WritebackConfig`](crate::cache::WritebackConfig)。
+    /// 只写回块缓存，不为延迟分配的数据分配块，也不写回页缓存和
+    /// superblock，完整的持久化仍需要 [`fsync`](Self::fsync)。
+    ///
+    /// # 示例
+    ///
+    /// ```rust,ignore
+    /// // 后台任务：每 500ms 写回一批
+    /// loop {
+    ///     sleep(Duration::from_millis(500));
+    ///     fs.lock().writeback(64)?;
+    /// }
+    /// ```
+    pub fn writeback(&mut self, max_blocks: usize) -> Result<usize> {
+        self.bdev.flush_some_dirty_blocks(max_blocks)
+    }
+
+    /// 待提交的脏块达到日志事务容量时立即提交
+    ///
+    /// 在每次批量写入之后调用
+    pub(super) fn commit_if_journal_full(&mut self) -> Result<()> {
+        if !self.commit.journal_full(self.uncommitted_blocks()) {
+            return Ok(());
+        }
+        self.commit_now(CommitReason::JournalSpace)?;
+        self.commit.committed(None);
+        Ok(())
+    }
+
+    /// 批量操作结束时提交，见 [`batch`](Self::batch)
+    pub(super) fn commit_batch(&mut self) -> Result<()> {
+        self.commit_now(CommitReason::Batch)?;
+        self.commit.committed(None);
+        Ok(())
+    }
+
+    /// 尚未写回设备的块数（脏缓存块 + 延迟分配的脏页）
+    fn uncommitted_blocks(&self) -> u64 {
+        let cached = self.bdev.cache_stats().map_or(0, |s| s.dirty_blocks as u64);
+        cached + self.delalloc_dirty_blocks()
+    }
+
+    fn commit_now(&mut self, reason: CommitReason) -> Result<()> {
+        if self.superblock().is_read_only() {
+            return Ok(());
+        }
+        self.observe(FsOp::Commit, |fs| {
+            debug!("[commit] {:?}: {} uncommitted blocks", reason, fs.uncommitted_blocks());
+            fs.flush_page_cache()?;
+            fs.flush_delalloc()?;
+            fs.sync_quota()?;
+            #[cfg(feature = "journal")]
+            fs.commit_journal()?;
+            // superblock 记录的计数必须在它描述的块落盘之后才落盘
+            fs.bdev.barrier()?;
+            fs.write_superblock()?;
+            fs.bdev.flush()
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_timer_due() {
+        let mut sched = CommitScheduler::new(Some(DEFAULT_COMMIT_INTERVAL), None);
+
+        // 第一次 tick 只记录时间基准
+        assert!(!sched.timer_due(Duration::from_secs(100)));
+        assert!(!sched.timer_due(Duration::from_secs(104)));
+        assert!(sched.timer_due(Duration::from_secs(105)));
+
+        sched.committed(Some(Duration::from_secs(105)));
+        assert!(!sched.timer_due(Duration::from_secs(106)));
+
+        // 时间未知的提交不改变时间基准
+        sched.committed(None);
+        assert!(sched.timer_due(Duration::from_secs(110)));
+
+        // 时钟回退不会触发提交
+        assert!(!sched.timer_due(Duration::from_secs(50)));
+    }
+
+    #[test]
+    fn test_timer_disabled() {
+        let mut sched = CommitScheduler::new(None, Some(100));
+        assert!(!sched.timer_due(Duration::ZERO));
+        assert!(!sched.timer_due(Duration::from_secs(3600)));
+    }
+
+    #[test]
+    fn test_journal_full() {
+        let sched = CommitScheduler::new(None, Some(256));
+        assert!(!sched.journal_full(255));
+        assert!(sched.journal_full(256));
+
+        // 没有日志时从不因空间触发
+        let sched = CommitScheduler::new(None, None);
+        assert!(!sched.journal_full(u64::MAX));
+    }
+
+    #[test]
+    fn test_hold() {
+        let mut sched = CommitScheduler::new(Some(DEFAULT_COMMIT_INTERVAL), Some(256));
+        assert!(!sched.timer_due(Duration::from_secs(100)));
+
+        assert!(sched.hold());
+        assert!(!sched.hold());
+        assert!(!sched.journal_full(1000));
+        assert!(!sched.timer_due(Duration::from_secs(200)));
+
+        sched.release();
+        assert!(sched.journal_full(1000));
+        assert!(sched.timer_due(Duration::from_secs(200)));
+    }
+}
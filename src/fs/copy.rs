@@ -0,0 +1,424 @@
+//! 文件复制
+//!
+//! - [`copy_between`]：从一个已挂载的镜像（通常是只读的更新镜像）向另一个文件系统复制文件，
+//!   并尽量保留元数据和扩展属性
+//! - [`Ext4FileSystem::copy_file_range`]：同一文件系统内两个文件之间复制字节区间
+//!
+//! 数据都按 extent 逐段传输，空洞保持为空洞。复制总是分配新块，不与源文件共享块。
+
+use crate::{
+    block::{BlockDevice, FsOp},
+    error::{Error, ErrorKind, Result},
+};
+use alloc::vec::Vec;
+
+use super::{filesystem::Ext4FileSystem, metadata::FileMetadata, types::FollowSymlink, MappingFlags};
+
+/// 单次传输的最大字节数
+///
+/// 复制时整个过程只使用一个这样大小的缓冲区
+pub const COPY_CHUNK_SIZE: usize = 256 * 1024;
+
+/// 在两个文件系统实例之间复制普通文件
+///
+/// 按源文件的 extent 逐段读取并写入目标文件，每次最多传输
+/// [`COPY_CHUNK_SIZE`] 字节。源文件中的空洞和 unwritten extent 不会被复制，
+/// 目标文件保持相同的稀疏布局。
+///
+/// 复制完成后保留以下元数据：
+/// - 权限位、uid、gid
+/// - atime、mtime、ctime
+/// - 所有扩展属性（需要 `xattr` feature）
+///
+/// 两端路径的最后一个组件都不跟随符号链接：源是符号链接时返回错误，
+/// 目标是符号链接时也返回错误，不会截断链接指向的文件。
+///
+/// # 参数
+///
+/// * `src_fs` - 源文件系统
+/// * `src_path` - 源文件路径（绝对路径）
+/// * `dst_fs` - 目标文件系统
+/// * `dst_path` - 目标文件路径（绝对路径），已存在时会被截断后覆盖
+///
+/// # 返回
+///
+/// 目标文件的大小（字节）
+///
+/// # 错误
+///
+/// - `ErrorKind::NotFound` - 源文件或目标父目录不存在
+/// - `ErrorKind::InvalidInput` - 源或已存在的目标不是普通文件（包括符号链接）
+///
+/// # 示例
+///
+/// ```rust,ignore
+/// let mut update = Ext4FileSystem::mount(update_bdev)?;
+/// let mut live = Ext4FileSystem::mount(live_bdev)?;
+/// copy_between(&mut update, "/bin/app", &mut live, "/bin/app")?;
+/// ```
+pub fn copy_between<S: BlockDevice, T: BlockDevice>(
+    src_fs: &mut Ext4FileSystem<S>,
+    src_path: &str,
+    dst_fs: &mut Ext4FileSystem<T>,
+    dst_path: &str,
+) -> Result<u64> {
+    // 1. 读取源文件元数据
+    let meta = src_fs.metadata_with(src_path, FollowSymlink::NoFollow)?;
+    let src_inode = meta.inode_num;
+    if !meta.is_file() {
+        return Err(Error::new(ErrorKind::InvalidInput, "Source is not a regular file"));
+    }
+    let size = meta.size;
+
+    // 2. 计算需要复制的字节范围（跳过空洞）
+    let block_size = src_fs.superblock().block_size() as u64;
+    let ranges = src_fs.with_inode_ref(src_inode, |inode_ref| {
+        if !inode_ref.has_extents()? {
+            // 间接块映射的文件：按整个文件处理，空洞会被读成零
+            return Ok(alloc::vec![(0, size)]);
+        }
+
+        let ranges = inode_ref
+            .extent_ranges()?
+            .into_iter()
+            .filter(|r| !r.unwritten)
+            .map(|r| {
+                let start = r.logical_start as u64 * block_size;
+                let end = (r.logical_end() * block_size).min(size);
+                (start, end)
+            })
+            .filter(|(start, end)| start < end)
+            .collect::<Vec<_>>();
+        Ok(ranges)
+    })?;
+
+    // 3. 准备目标文件
+    let dst_inode = match dst_fs.metadata_with(dst_path, FollowSymlink::NoFollow) {
+        Ok(dst_meta) => {
+            let ino = dst_meta.inode_num;
+            if !dst_meta.is_file() {
+                return Err(Error::new(
+                    ErrorKind::InvalidInput,
+                    "Destination exists and is not a regular file",
+                ));
+            }
+            dst_fs.truncate_file(ino, 0)?;
+            ino
+        }
+        Err(e) if e.kind() == ErrorKind::NotFound => {
+            let (parent, name) = split_parent(dst_path)?;
+            dst_fs.create_file(parent, name, meta.permissions)?
+        }
+        Err(e) => return Err(e),
+    };
+
+    // 4. 逐段复制数据
+    let mut buf = alloc::vec![0u8; COPY_CHUNK_SIZE.min(size as usize).max(1)];
+    for (start, end) in ranges {
+        let mut offset = start;
+        while offset < end {
+            let len = ((end - offset) as usize).min(buf.len());
+            let n = src_fs.read_at_inode(src_inode, &mut buf[..len], offset)?;
+            if n == 0 {
+                break;
+            }
+            dst_fs.write_at_inode_batch(dst_inode, &buf[..n], offset)?;
+            offset += n as u64;
+        }
+    }
+
+    // 末尾的空洞只需要扩展 i_size
+    dst_fs.truncate_file(dst_inode, size)?;
+
+    // 5. 保留元数据和扩展属性
+    copy_attrs(src_fs, src_path, &meta, dst_fs, dst_path, dst_inode)?;
+
+    Ok(size)
+}
+
+impl<D: BlockDevice> Ext4FileSystem<D> {
+    /// 把 `src_ino` 从 `src_off` 开始的 `len` 字节复制到 `dst_ino` 的 `dst_off`（`copy_file_range(2)`）
+    ///
+    /// 按源文件的 extent 逐段复制，调用者不需要提供缓冲区，内部每次最多传输
+    /// [`COPY_CHUNK_SIZE`] 字节。源文件的空洞和 unwritten extent 不分配块：
+    /// 目标对应位置原来是空洞时保持空洞，有数据时写零。目标的数据块总是新分配的，
+    /// 不与源文件共享。
+    ///
+    /// 复制在源文件末尾停止；目标文件大小至少扩展到复制区间的末尾，mtime 不变。
+    ///
+    /// # 返回
+    ///
+    /// 复制的字节数，`src_off` 位于源文件末尾之后时为 0
+    ///
+    /// # 错误
+    ///
+    /// - `ErrorKind::InvalidInput` - 源或目标不是普通文件，或者同一文件内的两个区间重叠
+    /// - `ErrorKind::ReadOnlyFs` - 只读挂载
+    /// - `ErrorKind::Encrypted` - 源或目标已加密
+    /// - `ErrorKind::NoSpace` - 空间不足，此前的部分已经复制
+    ///
+    /// # 示例
+    ///
+    /// ```rust,ignore
+    /// // 把镜像层的前 1 MiB 复制到新文件
+    /// let dst = fs.create_file("/", "layer.copy", 0o644)?;
+    /// let copied = fs.copy_file_range(src, 0, dst, 0, 1 << 20)?;
+    /// ```
+    pub fn copy_file_range(
+        &mut self,
+        src_ino: u32,
+        src_off: u64,
+        dst_ino: u32,
+        dst_off: u64,
+        len: u64,
+    ) -> Result<u64> {
+        self.observe(FsOp::Write, |fs| fs.copy_file_range_steps(src_ino, src_off, dst_ino, dst_off, len))
+    }
+
+    /// `copy_file_range` 的各个步骤
+    fn copy_file_range_steps(
+        &mut self,
+        src_ino: u32,
+        src_off: u64,
+        dst_ino: u32,
+        dst_off: u64,
+        len: u64,
+    ) -> Result<u64> {
+        self.check_regular_file(src_ino)?;
+        self.check_regular_file(dst_ino)?;
+        self.superblock().check_writable()?;
+        if src_ino == dst_ino && src_off < dst_off.saturating_add(len) && dst_off < src_off.saturating_add(len) {
+            return Err(Error::new(ErrorKind::InvalidInput, "Source and destination ranges overlap"));
+        }
+
+        // 句柄中尚未提交的大小和延迟分配的数据先落到 inode 上，映射才完整
+        self.commit_pending_size(src_ino)?;
+        self.commit_pending_size(dst_ino)?;
+        self.flush_delalloc_inode(src_ino)?;
+        self.flush_delalloc_inode(dst_ino)?;
+
+        let size = self.with_inode_ref(src_ino, |inode_ref| inode_ref.size())?;
+        let len = len.min(size.saturating_sub(src_off));
+        if len == 0 {
+            return Ok(0);
+        }
+        if dst_off.checked_add(len).is_none() {
+            return Err(Error::new(ErrorKind::FileTooLarge, "Destination range exceeds maximum file size"));
+        }
+
+        let end = src_off + len;
+        let data = self.data_ranges(src_ino, src_off, end)?;
+        let mut buf = alloc::vec![0u8; COPY_CHUNK_SIZE.min(len as usize)];
+        let mut pos = src_off;
+        for (start, stop) in data.into_iter().chain([(end, end)]) {
+            if start > pos {
+                self.zero_mapped(dst_ino, dst_off + (pos - src_off), start - pos, &mut buf)?;
+            }
+            pos = start;
+            while pos < stop {
+                let n = ((stop - pos) as usize).min(buf.len());
+                let n = self.read_at_inode(src_ino, &mut buf[..n], pos)?;
+                if n == 0 {
+                    break;
+                }
+                let n = self.write_at_inode_batch(dst_ino, &buf[..n], dst_off + (pos - src_off))?;
+                if n == 0 {
+                    return Err(Error::new(ErrorKind::Io, "Short write while copying"));
+                }
+                pos += n as u64;
+            }
+            pos = pos.max(stop);
+        }
+
+        // 末尾的空洞只需要扩展 i_size
+        let dst_end = dst_off + len;
+        if dst_end > self.with_inode_ref(dst_ino, |inode_ref| inode_ref.size())? {
+            self.truncate_file(dst_ino, dst_end)?;
+        }
+        Ok(len)
+    }
+
+    /// `[start, end)` 中有数据（已写入的 extent）的字节区间
+    pub(super) fn data_ranges(&mut self, ino: u32, start: u64, end: u64) -> Result<Vec<(u64, u64)>> {
+        let block_size = self.superblock().block_size() as u64;
+        let first = (start / block_size).min(u32::MAX as u64) as u32;
+        let last = end.div_ceil(block_size).min(u32::MAX as u64) as u32;
+        let mappings = self.with_inode_ref(ino, |inode_ref| inode_ref.fiemap(first..last))?;
+        Ok(mappings
+            .into_iter()
+            .filter(|m| !m.flags.contains(MappingFlags::UNWRITTEN))
+            .map(|m| ((m.logical_block as u64 * block_size).max(start), (m.logical_end() * block_size).min(end)))
+            .filter(|(start, end)| start < end)
+            .collect())
+    }
+
+    /// 把 `[offset, offset + len)` 中已有数据的部分写零，空洞保持不变
+    fn zero_mapped(&mut self, ino: u32, offset: u64, len: u64, buf: &mut [u8]) -> Result<()> {
+        for (start, end) in self.data_ranges(ino, offset, offset + len)? {
+            buf.fill(0);
+            let mut pos = start;
+            while pos < end {
+                let n = ((end - pos) as usize).min(buf.len());
+                let n = self.write_at_inode_batch(ino, &buf[..n], pos)?;
+                if n == 0 {
+                    return Err(Error::new(ErrorKind::Io, "Short write while copying"));
+                }
+                pos += n as u64;
+            }
+        }
+        Ok(())
+    }
+}
+
+/// 将源文件的元数据和扩展属性复制到目标 inode
+///
+/// 保留权限位、uid、gid、atime、mtime、ctime（纳秒精度），以及所有扩展属性。
+/// 未启用 xattr 支持时跳过扩展属性。
+pub(super) fn copy_attrs<S: BlockDevice, T: BlockDevice>(
+    src_fs: &mut Ext4FileSystem<S>,
+    src_path: &str,
+    meta: &FileMetadata,
+    dst_fs: &mut Ext4FileSystem<T>,
+    dst_path: &str,
+    dst_inode: u32,
+) -> Result<()> {
+    dst_fs.with_inode_ref(dst_inode, |inode_ref| {
+        inode_ref.set_mode(meta.permissions)?;
+        inode_ref.set_owner(meta.uid, meta.gid)?;
+        inode_ref.set_atime_ns(meta.atime, meta.atime_nsec)?;
+        inode_ref.set_mtime_ns(meta.mtime, meta.mtime_nsec)?;
+        inode_ref.set_ctime_ns(meta.ctime, meta.ctime_nsec)?;
+        inode_ref.mark_dirty()
+    })?;
+
+    let names = match src_fs.listxattr(src_path) {
+        Ok(names) => names,
+        Err(e) if e.kind() == ErrorKind::Unsupported => Vec::new(),
+        Err(e) => return Err(e),
+    };
+    for name in names {
+        let value = src_fs.getxattr(src_path, &name)?;
+        dst_fs.setxattr(dst_path, &name, &value)?;
+    }
+
+    Ok(())
+}
+
+/// 将路径拆分为父目录和最后一个组件
+///
+/// 末尾的 `/` 会被忽略，父目录为空时返回 `/`
+pub(super) fn split_parent(path: &str) -> Result<(&str, &str)> {
+    let trimmed = path.trim_end_matches('/');
+    match trimmed.rfind('/') {
+        Some(pos) if pos + 1 < trimmed.len() => {
+            let parent = if pos == 0 { "/" } else { &trimmed[..pos] };
+            Ok((parent, &trimmed[pos + 1..]))
+        }
+        _ => Err(Error::new(ErrorKind::InvalidInput, "Path has no file name component")),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{fs::ops, testing::image};
+
+    fn pattern(len: usize) -> Vec<u8> {
+        (0..len).map(|i| (i % 251) as u8).collect()
+    }
+
+    #[test]
+    fn test_copy_file_range() {
+        let mut fs = image::mount(image::image());
+        let data = pattern(10000);
+        ops::write(&mut fs, "/src", &data).unwrap();
+        let src = fs.lookup_at(crate::consts::EXT4_ROOT_INODE, "src").unwrap();
+        let dst = fs.create_file("/", "dst", 0o644).unwrap();
+
+        // 不对齐的偏移和部分区间，目标前面留下空洞
+        assert_eq!(fs.copy_file_range(src, 100, dst, 5000, 3000).unwrap(), 3000);
+        let out = ops::read(&mut fs, "/dst").unwrap();
+        assert_eq!(out.len(), 8000);
+        assert!(out[..5000].iter().all(|&b| b == 0));
+        assert_eq!(&out[5000..], &data[100..3100]);
+        assert_eq!(fs.data_ranges(dst, 0, 4096).unwrap(), []);
+
+        // 跨过源文件末尾：只复制到末尾
+        assert_eq!(fs.copy_file_range(src, 9000, dst, 0, 5000).unwrap(), 1000);
+        let out = ops::read(&mut fs, "/dst").unwrap();
+        assert_eq!(out.len(), 8000);
+        assert_eq!(&out[..1000], &data[9000..]);
+        assert!(out[1000..5000].iter().all(|&b| b == 0));
+
+        // 起点在末尾之后
+        assert_eq!(fs.copy_file_range(src, 10000, dst, 0, 10).unwrap(), 0);
+        assert_eq!(fs.copy_file_range(src, 20000, dst, 0, 10).unwrap(), 0);
+
+        // 源中的空洞覆盖目标已有的数据时写零
+        fs.truncate_file(src, 20000).unwrap();
+        assert_eq!(fs.copy_file_range(src, 12000, dst, 5000, 3000).unwrap(), 3000);
+        let out = ops::read(&mut fs, "/dst").unwrap();
+        assert!(out[5000..8000].iter().all(|&b| b == 0));
+        assert_eq!(&out[..1000], &data[9000..]);
+    }
+
+    #[test]
+    fn test_copy_file_range_same_file() {
+        let mut fs = image::mount(image::image());
+        let data = pattern(10000);
+        ops::write(&mut fs, "/file", &data).unwrap();
+        let ino = fs.lookup_at(crate::consts::EXT4_ROOT_INODE, "file").unwrap();
+
+        // 不重叠的区间，扩展文件
+        assert_eq!(fs.copy_file_range(ino, 0, ino, 12000, 5000).unwrap(), 5000);
+        let out = ops::read(&mut fs, "/file").unwrap();
+        assert_eq!(out.len(), 17000);
+        assert_eq!(&out[..10000], &data[..]);
+        assert!(out[10000..12000].iter().all(|&b| b == 0));
+        assert_eq!(&out[12000..], &data[..5000]);
+
+        // 重叠的区间
+        let err = fs.copy_file_range(ino, 0, ino, 100, 200).unwrap_err();
+        assert_eq!(err.kind(), ErrorKind::InvalidInput);
+        let err = fs.copy_file_range(ino, 100, ino, 0, 200).unwrap_err();
+        assert_eq!(err.kind(), ErrorKind::InvalidInput);
+        // 相邻的区间不算重叠
+        assert_eq!(fs.copy_file_range(ino, 0, ino, 100, 100).unwrap(), 100);
+        let out = ops::read(&mut fs, "/file").unwrap();
+        assert_eq!(&out[100..200], &data[..100]);
+        assert_eq!(&out[200..10000], &data[200..]);
+    }
+
+    #[test]
+    fn test_copy_between_does_not_follow_symlinks() {
+        let mut src = image::mount(image::image());
+        let mut dst = image::mount(image::image());
+        ops::write(&mut src, "/file", b"new").unwrap();
+        src.fsymlink("file", "/", "link").unwrap();
+        ops::write(&mut dst, "/target", b"old data").unwrap();
+        dst.fsymlink("target", "/", "link").unwrap();
+
+        assert_eq!(copy_between(&mut src, "/file", &mut dst, "/copy").unwrap(), 3);
+        assert_eq!(ops::read(&mut dst, "/copy").unwrap(), b"new");
+
+        // 源是符号链接
+        let err = copy_between(&mut src, "/link", &mut dst, "/copy2").unwrap_err();
+        assert_eq!(err.kind(), ErrorKind::InvalidInput);
+        assert!(!dst.exists("/copy2"));
+
+        // 目标是符号链接：链接指向的文件保持不变
+        let err = copy_between(&mut src, "/file", &mut dst, "/link").unwrap_err();
+        assert_eq!(err.kind(), ErrorKind::InvalidInput);
+        assert_eq!(ops::read(&mut dst, "/target").unwrap(), b"old data");
+        assert!(dst.symlink_metadata("/link").unwrap().is_symlink());
+    }
+
+    #[test]
+    fn test_split_parent() {
+        assert_eq!(split_parent("/a/b/c").unwrap(), ("/a/b", "c"));
+        assert_eq!(split_parent("/file").unwrap(), ("/", "file"));
+        assert_eq!(split_parent("/dir/sub/").unwrap(), ("/dir", "sub"));
+        assert!(split_parent("/").is_err());
+        assert!(split_parent("noslash").is_err());
+    }
+}
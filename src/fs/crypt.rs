@@ -0,0 +1,81 @@
+//! fscrypt 加密文件的只读处理
+//!
+//! 本库不实现解密，只识别加密的 inode：
+//!
+//! - 加密文件的数据、加密符号链接的目标无法读写，返回 `ErrorKind::Encrypted`
+//! - 加密目录可以列出和查找，名称以编码后的密文表示，见 [`crate::dir::crypt`]
+//! - 加密目录中的条目可以删除，但不能创建（无法加密名称）
+//!
+//! 加密策略保存在 inode 的加密上下文扩展属性中（索引 9，名称 `c`），
+//! 可以通过 [`encryption_context`](Ext4FileSystem::encryption_context) 读取。
+
+use crate::{
+    block::BlockDevice,
+    consts::{EXT4_ROOT_INODE, EXT4_XATTR_INDEX_ENCRYPTION},
+    error::{Error, ErrorKind, Result},
+    xattr,
+};
+use alloc::{vec, vec::Vec};
+
+use super::filesystem::Ext4FileSystem;
+
+/// fscrypt 加密上下文扩展属性的名称
+const ENCRYPTION_CONTEXT_NAME: &[u8] = b"c";
+
+/// 加密上下文的最大长度（v2 上下文为 40 字节）
+const ENCRYPTION_CONTEXT_MAX: usize = 64;
+
+impl<D: BlockDevice> Ext4FileSystem<D> {
+    /// 读取 inode 的 fscrypt 加密上下文
+    ///
+    /// 返回原始的上下文字节（版本、加密模式、标志、主密钥标识和 nonce），
+    /// 本库不解析其内容
+    ///
+    /// # 返回
+    ///
+    /// 未加密的 inode 返回 `None`
+    ///
+    /// # 错误
+    ///
+    /// - `ErrorKind::Corrupted` - inode 带加密标志但没有加密上下文
+    ///
+    /// # 示例
+    ///
+    /// ```rust,ignore
+    /// if let Some(ctx) = fs.encryption_context("/secret")? {
+    ///     println!("policy version {}", ctx[0]);
+    /// }
+    /// ```
+    pub fn encryption_context(&mut self, path: &str) -> Result<Option<Vec<u8>>> {
+        let inode_num = self.lookup_at(EXT4_ROOT_INODE, path)?;
+        let mut inode_ref = self.get_inode_ref(inode_num)?;
+        if !inode_ref.is_encrypted()? {
+            return Ok(None);
+        }
+
+        let mut buf = vec![0u8; ENCRYPTION_CONTEXT_MAX];
+        let len = xattr::get_indexed(
+            &mut inode_ref,
+            EXT4_XATTR_INDEX_ENCRYPTION,
+            ENCRYPTION_CONTEXT_NAME,
+            &mut buf,
+        )
+        .map_err(|e| match e.kind() {
+            ErrorKind::NotFound => {
+                Error::new(ErrorKind::Corrupted, "Encrypted inode has no encryption context")
+            }
+            _ => e,
+        })?;
+        buf.truncate(len);
+        Ok(Some(buf))
+    }
+
+    /// 确认 inode 的内容可以访问
+    ///
+    /// # 错误
+    ///
+    /// - `ErrorKind::Encrypted` - inode 已加密
+    pub(super) fn check_not_encrypted(&mut self, inode_num: u32) -> Result<()> {
+        self.get_inode_ref(inode_num)?.check_not_encrypted()
+    }
+}
@@ -0,0 +1,230 @@
+//! 文件碎片整理
+//!
+//! 与 e4defrag 的思路相同：为文件中逻辑相邻、物理分散的一组 extent 分配
+//! 一段新的连续空间，复制数据后把这段逻辑区间的 extent 换成指向新空间的
+//! extent，最后释放旧块。
+//!
+//! - 映射通过 [`InodeRef::fiemap`] 获取，空洞保持不变
+//! - 新空间以上一段的结尾为分配目标（goal），整理后的各段尽量首尾相接
+//! - unwritten extent 不含数据，保持原样，也不与相邻的 extent 合并
+//!
+//! 操作不经过日志；换 extent 的过程中出错（如设备错误）时，
+//! 文件的这一段可能丢失映射，需要用 e2fsck 检查。
+
+use crate::{
+    balloc::BlockAllocator,
+    block::BlockDevice,
+    error::{Error, ErrorKind, Result},
+    extent::{self, EXT_INIT_MAX_LEN},
+};
+use alloc::{vec, vec::Vec};
+
+use super::{filesystem::Ext4FileSystem, ExtentMapping, MappingFlags};
+
+/// [`defragment`](Ext4FileSystem::defragment) 的结果
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct DefragReport {
+    /// 整理前的 extent 数
+    pub extents_before: u32,
+    /// 整理后的 extent 数
+    pub extents_after: u32,
+    /// 搬移的数据块数
+    pub blocks_moved: u64,
+}
+
+impl<D: BlockDevice> Ext4FileSystem<D> {
+    /// 整理文件 `inode` 的碎片
+    ///
+    /// 把逻辑上相邻的 extent 按每组不超过 `target_extent_len` 块（同时受 extent
+    /// 最大长度和块组大小限制）搬到连续空间；相邻的组分配到首尾相接的空间时
+    /// 会合并成更长的 extent。只有新空间的段数少于原来的 extent 数时才会搬移，
+    /// 空闲空间不够连续的部分保持原样。
+    ///
+    /// 开始前会写回延迟分配的数据和该文件的缓存页。
+    ///
+    /// # 错误
+    ///
+    /// - `ErrorKind::InvalidInput` - `target_extent_len` 为 0
+    /// - `ErrorKind::Unsupported` - 不是使用 extent 映射的普通文件
+    /// - `ErrorKind::NoSpace` - 没有足够的空闲块容纳新空间
+    ///
+    /// # 示例
+    ///
+    /// ```rust,ignore
+    /// let report = fs.defragment(inode, 8192)?;
+    /// println!("{} -> {} extents", report.extents_before, report.extents_after);
+    /// ```
+    pub fn defragment(&mut self, inode: u32, target_extent_len: u32) -> Result<DefragReport> {
+        if target_extent_len == 0 {
+            return Err(Error::new(ErrorKind::InvalidInput, "Target extent length is zero"));
+        }
+        let target = target_extent_len
+            .min(EXT_INIT_MAX_LEN as u32)
+            .min(self.superblock().blocks_per_group());
+
+        let eligible = self.with_inode_ref(inode, |inode_ref| {
+            Ok(inode_ref.is_file()? && inode_ref.has_extents()?)
+        })?;
+        if !eligible {
+            return Err(Error::new(
+                ErrorKind::Unsupported,
+                "Defragmentation requires an extent-mapped regular file",
+            ));
+        }
+
+        // 延迟分配和页缓存中的数据先落盘，页缓存按物理块号写回，搬移后失效
+        self.flush_delalloc()?;
+        self.page_cache_evict(inode, 0, u32::MAX)?;
+        // 搬移后打开句柄共享的映射缓存失效
+        self.open_inodes.invalidate_extent(inode);
+
+        let mappings = self.with_inode_ref(inode, |inode_ref| inode_ref.fiemap(0..u32::MAX))?;
+        let mut report = DefragReport {
+            extents_before: mappings.len() as u32,
+            ..Default::default()
+        };
+
+        let mut goal = None;
+        for group in defrag_groups(&mappings, target) {
+            let goal_block = goal.unwrap_or(group[0].physical_block);
+            if let Some(end) = self.defrag_group(inode, group, goal_block)? {
+                report.blocks_moved += group.iter().map(|m| m.len as u64).sum::<u64>();
+                goal = Some(end);
+            }
+        }
+
+        report.extents_after =
+            self.with_inode_ref(inode, |inode_ref| inode_ref.fiemap(0..u32::MAX))?.len() as u32;
+        Ok(report)
+    }
+
+    /// 把一组逻辑相邻的 extent 搬到新的连续空间
+    ///
+    /// 返回新空间的结尾块号（下一组的分配目标）；新空间的段数不少于原 extent
+    /// 数时放弃搬移并返回 `None`
+    fn defrag_group(&mut self, inode: u32, group: &[ExtentMapping], goal: u64) -> Result<Option<u64>> {
+        let len: u32 = group.iter().map(|m| m.len).sum();
+        let runs = match self.alloc_runs(goal, len) {
+            Ok(runs) => runs,
+            Err(e) if e.kind() == ErrorKind::NoSpace => return Ok(None),
+            Err(e) => return Err(e),
+        };
+        if runs.len() >= group.len() {
+            self.free_runs(&runs)?;
+            return Ok(None);
+        }
+
+        if let Err(e) = self.copy_group_data(group, &runs) {
+            self.free_runs(&runs)?;
+            return Err(e);
+        }
+
+        // 旧 extent 整段删除（同时释放旧块），再按新空间插入 extent；
+        // 数据块总数不变，i_blocks 只随 extent 树节点的分裂而变化
+        let first = group[0].logical_block;
+        self.with_inode_ref(inode, |inode_ref| {
+            extent::remove_space(inode_ref, first, first + len - 1)?;
+
+            let mut allocator = BlockAllocator::new();
+            let mut lblk = first;
+            for &(start, count) in &runs {
+                extent::insert_extent_with_auto_split(inode_ref, &mut allocator, lblk, start, count)?;
+                lblk += count;
+            }
+            Ok(())
+        })?;
+
+        Ok(runs.last().map(|&(start, count)| start + count as u64))
+    }
+
+    /// 按逻辑顺序把一组 extent 的数据复制到新空间的各段
+    fn copy_group_data(&mut self, group: &[ExtentMapping], runs: &[(u64, u32)]) -> Result<()> {
+        let block_size = self.superblock().block_size() as usize;
+        let mut buf = vec![0u8; block_size];
+        let mut dst = runs.iter().flat_map(|&(start, count)| start..start + count as u64);
+
+        for m in group {
+            for src in m.physical_block..m.physical_block + m.len as u64 {
+                let dst = dst
+                    .next()
+                    .ok_or_else(|| Error::new(ErrorKind::InvalidState, "Defrag target too short"))?;
+                self.bdev.read_block(src, &mut buf)?;
+                self.bdev.write_block(dst, &buf)?;
+            }
+        }
+        Ok(())
+    }
+}
+
+/// 把映射分成需要整理的组
+///
+/// 每组由逻辑上首尾相接的已写入 extent 组成，总长度不超过 `target`；
+/// 只有一个 extent 或者已经物理连续的组不需要整理，不会返回
+fn defrag_groups(mappings: &[ExtentMapping], target: u32) -> Vec<&[ExtentMapping]> {
+    let mut groups = Vec::new();
+    let mut start = 0;
+    let mut len = 0u32;
+
+    for (i, m) in mappings.iter().enumerate() {
+        let joins = i > start
+            && !m.flags.contains(MappingFlags::UNWRITTEN)
+            && mappings[i - 1].logical_end() == m.logical_block as u64
+            && len + m.len <= target;
+        if !joins {
+            push_group(&mut groups, &mappings[start..i]);
+            start = i;
+            len = 0;
+        }
+        if m.flags.contains(MappingFlags::UNWRITTEN) {
+            // unwritten extent 单独成组，push_group 会跳过
+            push_group(&mut groups, &mappings[start..=i]);
+            start = i + 1;
+            continue;
+        }
+        len += m.len;
+    }
+    push_group(&mut groups, &mappings[start..]);
+    groups
+}
+
+fn push_group<'a>(groups: &mut Vec<&'a [ExtentMapping]>, group: &'a [ExtentMapping]) {
+    let contiguous = group
+        .windows(2)
+        .all(|w| w[0].physical_block + w[0].len as u64 == w[1].physical_block);
+    if group.len() > 1 && !contiguous {
+        groups.push(group);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn m(logical_block: u32, physical_block: u64, len: u32) -> ExtentMapping {
+        ExtentMapping { logical_block, physical_block, len, flags: MappingFlags::empty() }
+    }
+
+    #[test]
+    fn test_defrag_groups() {
+        let unwritten = ExtentMapping { flags: MappingFlags::UNWRITTEN, ..m(30, 900, 2) };
+        let mappings = [
+            m(0, 100, 4),
+            m(4, 200, 4),
+            m(8, 300, 4),
+            // 空洞
+            m(20, 400, 2),
+            m(22, 402, 8), // 与上一段物理连续
+            unwritten,
+            m(32, 500, 1),
+            m(33, 600, 1),
+        ];
+
+        let groups = defrag_groups(&mappings, 8);
+        assert_eq!(groups.len(), 2);
+        assert_eq!(groups[0], &mappings[0..2]);
+        assert_eq!(groups[1], &mappings[6..8]);
+
+        assert_eq!(defrag_groups(&mappings, 16)[0], &mappings[0..3]);
+        assert!(defrag_groups(&mappings, 1).is_empty());
+    }
+}
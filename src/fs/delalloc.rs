@@ -0,0 +1,396 @@
+//! 延迟分配（delalloc）
+//!
+//! 启用后，写入文件空洞的数据先以页（一个文件系统块）为单位缓存在内存中，
+//! 只在 superblock 中预留块数（见 [`balloc::reserve`](crate::balloc::reserve)），
+//! 到 [`Ext4FileSystem::flush`] 时再按逻辑块连续的区段一次分配整段物理块。
+//! 小块追加写因此也能得到连续的大 extent。
+//!
+//! 已经映射的块（包括 unwritten extent）仍然直接写入设备。
+//!
+//! # 限制
+//!
+//! - 只作用于使用 extent 的普通文件
+//! - `read_at_inode` / [`File::read`](super::File::read) 能读到缓存的数据，
+//!   但直接操作 `InodeRef`、fiemap、scrub 等底层接口看到的是磁盘状态，
+//!   使用前应先 `flush`
+//! - inode 大小在写入时立即更新，刷新前崩溃会留下空洞
+
+use crate::{balloc, block::BlockDevice, error::Result};
+use alloc::{boxed::Box, collections::BTreeMap, vec::Vec};
+
+use super::filesystem::{Ext4FileSystem, MAX_WRITE_RUN};
+
+/// 延迟分配状态：每个 inode 的脏页
+#[derive(Debug, Default)]
+pub(super) struct DelallocState {
+    inodes: BTreeMap<u32, DirtyInode>,
+}
+
+/// 单个 inode 的脏页和预留块数
+#[derive(Debug, Default)]
+struct DirtyInode {
+    /// 逻辑块号 → 块数据
+    pages: BTreeMap<u32, Box<[u8]>>,
+    /// 已预留的块数（脏页数 + 1 个 extent 树块）
+    reserved: u64,
+}
+
+impl DirtyInode {
+    /// 按逻辑块连续划分的区段 `(起始逻辑块, 块数)`
+    fn runs(&self) -> Vec<(u32, u32)> {
+        let mut runs: Vec<(u32, u32)> = Vec::new();
+        for &lblk in self.pages.keys() {
+            match runs.last_mut() {
+                Some((start, len)) if *start + *len == lblk => *len += 1,
+                _ => runs.push((lblk, 1)),
+            }
+        }
+        runs
+    }
+}
+
+impl DelallocState {
+    pub(super) fn new() -> Self {
+        Self::default()
+    }
+
+    /// 缓存中的脏页总数
+    pub(super) fn dirty_blocks(&self) -> u64 {
+        self.inodes.values().map(|d| d.pages.len() as u64).sum()
+    }
+}
+
+impl<D: BlockDevice> Ext4FileSystem<D> {
+    /// 启用或关闭延迟分配
+    ///
+    /// 关闭时先刷新所有缓存的数据。也可以通过 [`FsConfig::delalloc`](super::FsConfig::delalloc)
+    /// 在挂载时启用。
+    pub fn set_delalloc(&mut self, enabled: bool) -> Result<()> {
+        if enabled {
+            if self.delalloc.is_none() {
+                self.delalloc = Some(DelallocState::new());
+            }
+        } else if self.delalloc.is_some() {
+            self.flush_delalloc()?;
+            self.delalloc = None;
+        }
+        Ok(())
+    }
+
+    /// 是否启用了延迟分配
+    pub fn delalloc_enabled(&self) -> bool {
+        self.delalloc.is_some()
+    }
+
+    /// 尚未分配物理块的脏页数
+    pub fn delalloc_dirty_blocks(&self) -> u64 {
+        self.delalloc.as_ref().map_or(0, DelallocState::dirty_blocks)
+    }
+
+    /// 为所有缓存的脏页分配物理块并写入设备
+    ///
+    /// 每个 inode 按逻辑块连续的区段分配，尽量得到整段连续的物理块。
+    /// 失败时尚未写入的页保留在缓存中，可以稍后重试。
+    pub fn flush_delalloc(&mut self) -> Result<()> {
+        let inodes = match self.delalloc.as_mut() {
+            Some(state) => core::mem::take(&mut state.inodes),
+            None => return Ok(()),
+        };
+
+        let mut pending = inodes.into_iter();
+        while let Some((ino, mut dirty)) = pending.next() {
+            // 先归还预留，实际分配时才有空闲块可用
+            balloc::release_reserved_blocks(self.superblock_mut(), dirty.reserved);
+            dirty.reserved = 0;
+
+            if let Err(e) = self.flush_dirty_inode(ino, &mut dirty) {
+                // 未写入的页放回缓存并重新预留（不再检查空间，数据已被接受）
+                let mut rest: BTreeMap<u32, DirtyInode> = pending.collect();
+                if !dirty.pages.is_empty() {
+                    dirty.reserved = dirty.pages.len() as u64 + 1;
+                    rest.insert(ino, dirty);
+                }
+                let reserved: u64 = rest.values().map(|d| d.reserved).sum();
+                let sb = self.superblock_mut();
+                sb.set_reserved_blocks(sb.reserved_blocks() + reserved);
+                if let Some(state) = self.delalloc.as_mut() {
+                    state.inodes = rest;
+                }
+                return Err(e);
+            }
+        }
+
+        Ok(())
+    }
+
+    /// 只为 inode 的脏页分配物理块并写入设备，见 [`fsync_inode`](Self::fsync_inode)
+    ///
+    /// 失败时尚未写入的页保留在缓存中
+    pub(super) fn flush_delalloc_inode(&mut self, ino: u32) -> Result<()> {
+        let Some(mut dirty) = self.delalloc.as_mut().and_then(|state| state.inodes.remove(&ino)) else {
+            return Ok(());
+        };
+
+        balloc::release_reserved_blocks(self.superblock_mut(), dirty.reserved);
+        dirty.reserved = 0;
+
+        let result = self.flush_dirty_inode(ino, &mut dirty);
+        if !dirty.pages.is_empty() {
+            dirty.reserved = dirty.pages.len() as u64 + 1;
+            let sb = self.superblock_mut();
+            sb.set_reserved_blocks(sb.reserved_blocks() + dirty.reserved);
+            if let Some(state) = self.delalloc.as_mut() {
+                state.inodes.insert(ino, dirty);
+            }
+        }
+        result
+    }
+
+    fn flush_dirty_inode(&mut self, ino: u32, dirty: &mut DirtyInode) -> Result<()> {
+        let block_size = self.superblock().block_size() as usize;
+
+        self.with_inode_ref(ino, |inode_ref| {
+            let mut buf = Vec::with_capacity(MAX_WRITE_RUN as usize * block_size);
+
+            for (start, len) in dirty.runs() {
+                let mut done = 0u32;
+                while done < len {
+                    let lblk = start + done;
+                    let (pblk, count) = inode_ref.get_inode_dblk_run(lblk, len - done, true)?;
+
+                    let mut written = 0u32;
+                    while written < count {
+                        let run = (count - written).min(MAX_WRITE_RUN);
+                        buf.clear();
+                        for l in lblk + written..lblk + written + run {
+                            buf.extend_from_slice(&dirty.pages[&l]);
+                        }
+                        inode_ref.bdev_mut().write_blocks(pblk + written as u64, run, &buf)?;
+                        for l in lblk + written..lblk + written + run {
+                            dirty.pages.remove(&l);
+                        }
+                        written += run;
+                    }
+
+                    done += count;
+                }
+            }
+
+            Ok(())
+        })
+    }
+
+    /// 延迟分配写入
+    ///
+    /// 文件不适用延迟分配（非 extent 普通文件）时返回 `None`，
+    /// 由调用者走直接写入路径。`defer_size` 为真时不更新 i_size，
+    /// 返回值的含义见 [`write_at_inode_sized`](Self::write_at_inode_sized)
+    pub(super) fn delalloc_write(
+        &mut self,
+        ino: u32,
+        buf: &[u8],
+        offset: u64,
+        defer_size: bool,
+    ) -> Result<Option<(usize, bool)>> {
+        let block_size = self.superblock().block_size() as u64;
+        let end = offset + buf.len() as u64;
+        let first = (offset / block_size) as u32;
+        let last = ((end - 1) / block_size) as u32;
+
+        let (eligible, size, mapped) = self.with_inode_ref(ino, |inode_ref| {
+            let eligible = inode_ref.is_file()? && inode_ref.has_extents()?;
+            if !eligible {
+                return Ok((false, 0, Vec::new()));
+            }
+            let mapped = inode_ref
+                .fiemap(first..last + 1)?
+                .into_iter()
+                .map(|m| (m.logical_block, m.logical_end() as u32))
+                .collect::<Vec<_>>();
+            Ok((true, inode_ref.size()?, mapped))
+        })?;
+        if !eligible {
+            return Ok(None);
+        }
+
+        let mut lblk = first;
+        let mut extents = mapped.iter().peekable();
+        while lblk <= last {
+            // 已映射的区段直接写入（unwritten extent 也视为已映射，避免重复分配）
+            while extents.next_if(|&&(_, e)| e <= lblk).is_some() {}
+            let seg_end = match extents.peek() {
+                Some(&&(s, e)) if s <= lblk => {
+                    let seg_end = e.min(last + 1);
+                    let (from, to) = self.segment_bytes(lblk, seg_end, offset, end);
+                    self.write_at_inode_direct(ino, &buf[from..to], offset + from as u64, defer_size)?;
+                    seg_end
+                }
+                next => {
+                    let seg_end = next.map_or(last + 1, |&&(s, _)| s.min(last + 1));
+                    for l in lblk..seg_end {
+                        let (from, to) = self.segment_bytes(l, l + 1, offset, end);
+                        let in_block = ((offset + from as u64) % block_size) as usize;
+                        self.buffer_page(ino, l, in_block, &buf[from..to])?;
+                    }
+                    seg_end
+                }
+            };
+            lblk = seg_end;
+        }
+
+        if end > size && defer_size {
+            return Ok(Some((buf.len(), false)));
+        }
+        if end > size {
+            self.with_inode_ref(ino, |inode_ref| {
+                // 直接写入路径可能已经扩展过文件
+                if end > inode_ref.size()? {
+                    inode_ref.set_size(end)?;
+                    inode_ref.mark_dirty()?;
+                }
+                Ok(())
+            })?;
+        }
+
+        Ok(Some((buf.len(), true)))
+    }
+
+    /// 逻辑块区间 `[start, end)` 与写入范围 `[offset, write_end)` 的交集，
+    /// 返回相对于写入缓冲区的字节范围
+    fn segment_bytes(&self, start: u32, end: u32, offset: u64, write_end: u64) -> (usize, usize) {
+        let block_size = self.superblock().block_size() as u64;
+        let from = (start as u64 * block_size).max(offset);
+        let to = (end as u64 * block_size).min(write_end);
+        ((from - offset) as usize, (to - offset) as usize)
+    }
+
+    /// 把数据写入一个缓存页，必要时新建页并预留块
+    fn buffer_page(&mut self, ino: u32, lblk: u32, in_block: usize, data: &[u8]) -> Result<()> {
+        let block_size = self.superblock().block_size() as usize;
+
+        let (exists, pages) = match self.delalloc.as_ref().and_then(|s| s.inodes.get(&ino)) {
+            Some(dirty) => (dirty.pages.contains_key(&lblk), dirty.pages.len() as u64),
+            None => (false, 0),
+        };
+        let first_page = pages == 0;
+
+        if !exists {
+            // 缓存页刷新时才分配，现在按本 inode 的全部缓存页检查配额，
+            // 避免刷新时才失败
+            if self.superblock().quota().is_some() {
+                self.with_inode_ref(ino, |inode_ref| inode_ref.quota_check_blocks(pages + 1))?;
+            }
+
+            // 每个 inode 额外预留一个块，用于刷新时 extent 树的增长
+            let need = if first_page { 2 } else { 1 };
+            self.with_inode_ref(ino, |inode_ref| inode_ref.check_free_blocks(need))?;
+            balloc::reserve_blocks(self.superblock_mut(), need)?;
+
+            let state = self.delalloc.get_or_insert_with(DelallocState::new);
+            let dirty = state.inodes.entry(ino).or_default();
+            dirty.reserved += need;
+            dirty.pages.insert(lblk, alloc::vec![0u8; block_size].into_boxed_slice());
+        }
+
+        if let Some(page) = self
+            .delalloc
+            .as_mut()
+            .and_then(|s| s.inodes.get_mut(&ino))
+            .and_then(|d| d.pages.get_mut(&lblk))
+        {
+            page[in_block..in_block + data.len()].copy_from_slice(data);
+        }
+        Ok(())
+    }
+
+    /// 用缓存页覆盖刚从磁盘读出的数据
+    pub(super) fn delalloc_overlay_read(&self, ino: u32, offset: u64, buf: &mut [u8]) {
+        let dirty = match self.delalloc.as_ref().and_then(|s| s.inodes.get(&ino)) {
+            Some(dirty) if !buf.is_empty() => dirty,
+            _ => return,
+        };
+
+        let block_size = self.superblock().block_size() as u64;
+        let end = offset + buf.len() as u64;
+        let first = (offset / block_size) as u32;
+        let last = ((end - 1) / block_size) as u32;
+
+        for (&lblk, page) in dirty.pages.range(first..=last) {
+            let page_start = lblk as u64 * block_size;
+            let from = page_start.max(offset);
+            let to = (page_start + block_size).min(end);
+            buf[(from - offset) as usize..(to - offset) as usize]
+                .copy_from_slice(&page[(from - page_start) as usize..(to - page_start) as usize]);
+        }
+    }
+
+    /// 截断时丢弃新大小之后的缓存页，并清零末尾页中超出部分
+    pub(super) fn delalloc_truncate(&mut self, ino: u32, new_size: u64) {
+        let block_size = self.superblock().block_size() as u64;
+        let keep = new_size.div_ceil(block_size) as u32;
+
+        let released = {
+            let dirty = match self.delalloc.as_mut().and_then(|s| s.inodes.get_mut(&ino)) {
+                Some(dirty) => dirty,
+                None => return,
+            };
+
+            let dropped = dirty.pages.split_off(&keep).len() as u64;
+            let tail = (new_size % block_size) as usize;
+            if tail != 0 {
+                if let Some(page) = dirty.pages.get_mut(&(keep - 1)) {
+                    page[tail..].fill(0);
+                }
+            }
+
+            if dirty.pages.is_empty() {
+                dirty.reserved
+            } else {
+                dirty.reserved -= dropped;
+                dropped
+            }
+        };
+
+        self.release_if_empty(ino, released);
+    }
+
+    /// 丢弃 inode 的所有缓存页（inode 被释放时调用）
+    pub(super) fn delalloc_discard(&mut self, ino: u32) {
+        let released = match self.delalloc.as_mut().and_then(|s| s.inodes.get_mut(&ino)) {
+            Some(dirty) => {
+                dirty.pages.clear();
+                dirty.reserved
+            }
+            None => return,
+        };
+        self.release_if_empty(ino, released);
+    }
+
+    fn release_if_empty(&mut self, ino: u32, released: u64) {
+        balloc::release_reserved_blocks(self.superblock_mut(), released);
+        if let Some(state) = self.delalloc.as_mut() {
+            if state.inodes.get(&ino).is_some_and(|d| d.pages.is_empty()) {
+                state.inodes.remove(&ino);
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_dirty_inode_runs() {
+        let mut dirty = DirtyInode::default();
+        for lblk in [0u32, 1, 2, 5, 7, 8] {
+            dirty.pages.insert(lblk, alloc::vec![0u8; 16].into_boxed_slice());
+        }
+
+        assert_eq!(dirty.runs(), alloc::vec![(0, 3), (5, 1), (7, 2)]);
+
+        let mut state = DelallocState::new();
+        state.inodes.insert(12, dirty);
+        assert_eq!(state.dirty_blocks(), 6);
+    }
+}
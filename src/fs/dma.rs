@@ -0,0 +1,194 @@
+//! 零拷贝写入：把文件区间的物理块段交给宿主直接 DMA
+//!
+//! [`File::block_run_for_write`](super::File::block_run_for_write) 为区间分配块（区间内的
+//! unwritten extent 拆出后重新分配），并返回物理块段；宿主绕过文件系统把数据直接写入这些块，之后调用
+//! [`File::commit_written`](super::File::commit_written) 更新文件大小和 mtime。
+//!
+//! 两次调用之间块已经映射但 i_size 尚未覆盖它们，e2fsck 会认为文件大小不对，
+//! 不应在此期间卸载文件系统。
+//!
+//! 块缓存、页缓存和延迟分配缓存中该区间的内容在返回物理块段之前写回并丢弃，
+//! 宿主写入之后不会被缓存中的旧数据覆盖。
+
+use crate::{
+    block::BlockDevice,
+    error::{Error, ErrorKind, Result},
+    extent,
+};
+use alloc::{vec, vec::Vec};
+
+use super::{
+    filesystem::Ext4FileSystem,
+    types::SystemHal,
+    MappingFlags,
+};
+
+impl<D: BlockDevice> Ext4FileSystem<D> {
+    /// 为 `[offset, offset + len)` 分配块并返回覆盖它的物理块段 `(起始块号, 块数)`
+    ///
+    /// 块号以文件系统块为单位，相对于文件系统起始位置。区间两端不对齐的块中原来读出为零的部分
+    /// （空洞、unwritten extent）会被清零；宿主需要自己保留这两个块中区间之外的已有数据。
+    pub(super) fn block_runs_for_write(&mut self, inode_num: u32, offset: u64, len: u64) -> Result<Vec<(u64, u32)>> {
+        if len == 0 {
+            return Ok(Vec::new());
+        }
+        self.superblock().check_writable()?;
+        self.check_not_encrypted(inode_num)?;
+
+        let (first, end) = self.block_range(offset, len)?;
+        let block_size = self.superblock().block_size() as u64;
+
+        // 缓存中尚未落盘的数据先写回，宿主写入之后它们不能再覆盖设备上的内容
+        self.flush_delalloc_inode(inode_num)?;
+        self.page_cache_evict(inode_num, first, end)?;
+
+        let mappings = self.with_inode_ref(inode_num, |inode_ref| inode_ref.fiemap(first..end))?;
+        let written = |block: u32| {
+            mappings.iter().any(|m| {
+                !m.flags.contains(MappingFlags::UNWRITTEN)
+                    && m.logical_block <= block
+                    && (block as u64) < m.logical_end()
+            })
+        };
+        // 不对齐的首尾块只有部分被宿主覆盖，原来读出为零时需要清零
+        let mut zero_edges = Vec::new();
+        if offset % block_size != 0 && !written(first) {
+            zero_edges.push(first);
+        }
+        if (offset + len) % block_size != 0 && end - 1 != first && !written(end - 1) {
+            zero_edges.push(end - 1);
+        }
+
+        // 区间内的 unwritten 部分从 extent 中拆出并释放，下面重新分配；
+        // 区间之外的部分保持 unwritten，文件大小之外不会出现已初始化的块
+        for m in mappings.iter().filter(|m| m.flags.contains(MappingFlags::UNWRITTEN)) {
+            // fiemap 的结果已经裁剪到查询区间，extent 的实际范围由转换返回
+            let (from, to) = (m.logical_block, m.logical_end() as u32);
+            self.with_inode_ref(inode_num, |inode_ref| {
+                let (start, len) = extent::set_extent_unwritten(inode_ref, from, false)?
+                    .ok_or(Error::new(ErrorKind::Corrupted, "Unwritten extent not found in tree"))?;
+                // remove_space 释放块但不修改 i_blocks，重新分配时会再加上
+                extent::remove_space(inode_ref, from, to - 1)?;
+                inode_ref.sub_blocks(to - from)?;
+                if start < from {
+                    extent::set_extent_unwritten(inode_ref, start, true)?;
+                }
+                if to < start + len {
+                    extent::set_extent_unwritten(inode_ref, to, true)?;
+                }
+                Ok(())
+            })?;
+        }
+        self.open_inodes.invalidate_extent(inode_num);
+
+        let runs = self.with_inode_ref(inode_num, |inode_ref| {
+            let mut runs: Vec<(u64, u32)> = Vec::new();
+            let mut block = first;
+            while block < end {
+                let (physical, count) = inode_ref.get_inode_dblk_run(block, end - block, true)?;
+                if physical == 0 {
+                    return Err(Error::new(ErrorKind::NoSpace, "Failed to allocate block"));
+                }
+                let count = count.min(end - block);
+                match runs.last_mut() {
+                    Some((start, n)) if *start + *n as u64 == physical => *n += count,
+                    _ => runs.push((physical, count)),
+                }
+                block += count;
+            }
+            Ok(runs)
+        })?;
+
+        let zeros = vec![0u8; block_size as usize];
+        for &block in &zero_edges {
+            let physical = run_physical(&runs, block - first)
+                .ok_or(Error::new(ErrorKind::InvalidState, "Edge block outside allocated runs"))?;
+            self.bdev.write_block(physical, &zeros)?;
+        }
+
+        // 块缓存中的副本写回后丢弃，宿主直接写设备
+        for &(start, count) in &runs {
+            for block in start..start + count as u64 {
+                self.bdev.flush_lba(block)?;
+            }
+            self.bdev.invalidate_cache_range(start, count)?;
+        }
+
+        self.commit_if_journal_full()?;
+        Ok(runs)
+    }
+
+    /// 宿主写完 `[offset, offset + len)` 之后更新文件大小和 mtime/ctime
+    ///
+    /// `H::now()` 返回 `None` 时只更新文件大小
+    pub(super) fn commit_written_at_inode<H: SystemHal>(&mut self, inode_num: u32, offset: u64, len: u64) -> Result<()> {
+        if len == 0 {
+            return Ok(());
+        }
+        self.superblock().check_writable()?;
+
+        // 宿主写入期间读入缓存的是旧数据
+        let (first, end) = self.block_range(offset, len)?;
+        self.page_cache_evict(inode_num, first, end)?;
+        for m in self.with_inode_ref(inode_num, |inode_ref| inode_ref.fiemap(first..end))? {
+            self.bdev.invalidate_cache_range(m.physical_block, m.len)?;
+        }
+
+        let new_end = offset + len;
+        self.with_inode_ref(inode_num, |inode_ref| {
+            if new_end > inode_ref.size()? {
+                inode_ref.set_size(new_end)?;
+            }
+            if let Some(now) = H::now() {
+                inode_ref.set_mtime(now.as_secs() as u32)?;
+                inode_ref.set_ctime(now.as_secs() as u32)?;
+            }
+            inode_ref.mark_dirty()
+        })?;
+
+        // i_size 已经覆盖到 new_end，表中不超过它的待提交大小作废
+        if let Some(entry) = self.open_inodes.get_mut(inode_num) {
+            if entry.pending_size.is_some_and(|pending| pending <= new_end) {
+                entry.pending_size = None;
+            }
+        }
+        Ok(())
+    }
+
+    /// 字节区间覆盖的逻辑块区间 `[first, end)`
+    fn block_range(&self, offset: u64, len: u64) -> Result<(u32, u32)> {
+        let block_size = self.superblock().block_size() as u64;
+        let end = offset
+            .checked_add(len)
+            .map(|end| end.div_ceil(block_size))
+            .filter(|&end| end <= u32::MAX as u64)
+            .ok_or(Error::new(ErrorKind::FileTooLarge, "Range exceeds maximum file size"))?;
+        Ok(((offset / block_size) as u32, end as u32))
+    }
+}
+
+/// 物理块段中第 `index` 个块的物理块号
+fn run_physical(runs: &[(u64, u32)], mut index: u32) -> Option<u64> {
+    for &(start, count) in runs {
+        if index < count {
+            return Some(start + index as u64);
+        }
+        index -= count;
+    }
+    None
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_run_physical() {
+        let runs = [(100, 2), (500, 3)];
+        assert_eq!(run_physical(&runs, 0), Some(100));
+        assert_eq!(run_physical(&runs, 1), Some(101));
+        assert_eq!(run_physical(&runs, 2), Some(500));
+        assert_eq!(run_physical(&runs, 4), Some(502));
+        assert_eq!(run_physical(&runs, 5), None);
+    }
+}
@@ -0,0 +1,375 @@
+//! 操作前的空间预估
+//!
+//! 大批量导入或预分配开始之前，估算操作需要的数据块、元数据块
+//! （extent 树节点、目录块）、inode 和日志 credits，并与当前空闲资源比较，
+//! 让调用者在动手之前就能给出明确的失败原因，而不是做到一半遇到 ENOSPC。
+//!
+//! 所有数值都是上限估计：假设空闲空间没有严重碎片化，
+//! 目录和 extent 树按最坏情况增长。
+
+use crate::{
+    block::BlockDevice,
+    consts::*,
+    dir::htree,
+    error::{Error, ErrorKind, Result},
+    extent::EXT_INIT_MAX_LEN,
+    superblock::Superblock,
+};
+
+use super::{filesystem::Ext4FileSystem, inode_ref::InodeRef};
+
+/// inode 中 extent 根节点能容纳的条目数
+const EXTENTS_IN_INODE: u64 = 4;
+
+/// 新目录项（含名称和 8 字节头部）按 4 字节对齐后的额外开销上限
+const DIR_ENTRY_OVERHEAD: u64 = 12;
+
+/// 需要预估的操作
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SpaceEstimateRequest {
+    /// 在 `parent` 目录中创建普通文件并写入 `size` 字节
+    CreateFile {
+        /// 父目录 inode
+        parent: u32,
+        /// 文件大小（字节）
+        size: u64,
+    },
+    /// 在 `parent` 目录中创建空目录
+    CreateDir {
+        /// 父目录 inode
+        parent: u32,
+    },
+    /// 为已有文件的 `[offset, offset + len)` 分配块（已映射的块不再计入）
+    Fallocate {
+        /// 文件 inode
+        inode: u32,
+        /// 起始偏移（字节）
+        offset: u64,
+        /// 长度（字节）
+        len: u64,
+    },
+    /// 批量导入一棵目录树
+    ///
+    /// 日志 credits 按整批作为一个事务计算
+    Import {
+        /// 普通文件数
+        files: u64,
+        /// 目录数
+        dirs: u64,
+        /// 所有文件数据的总字节数
+        data_bytes: u64,
+        /// 所有文件名和目录名的总字节数
+        name_bytes: u64,
+    },
+}
+
+/// 空间预估结果
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct SpaceEstimate {
+    /// 需要的数据块数
+    pub data_blocks: u64,
+    /// 需要的元数据块数（extent 树节点 / 间接块、目录块）
+    pub metadata_blocks: u64,
+    /// 需要的 inode 数
+    pub inodes: u64,
+    /// 需要的日志 credits（事务中会修改的元数据块数）
+    pub journal_credits: u64,
+    /// 当前可分配的空闲块数（已扣除延迟分配预留）
+    pub free_blocks: u64,
+    /// 当前空闲 inode 数
+    pub free_inodes: u64,
+    /// 单个事务允许的最大 credits（日志块数的 1/4），没有日志时为 `None`
+    pub journal_capacity: Option<u64>,
+}
+
+impl SpaceEstimate {
+    /// 需要的总块数
+    pub fn total_blocks(&self) -> u64 {
+        self.data_blocks + self.metadata_blocks
+    }
+
+    /// 当前空闲资源是否足够
+    pub fn fits(&self) -> bool {
+        self.check().is_ok()
+    }
+
+    /// 检查当前空闲资源是否足够，不够时返回说明原因的错误
+    pub fn check(&self) -> Result<()> {
+        if self.total_blocks() > self.free_blocks {
+            return Err(Error::new(ErrorKind::NoSpace, "Not enough free blocks for operation"));
+        }
+        if self.inodes > self.free_inodes {
+            return Err(Error::new(ErrorKind::NoSpace, "Not enough free inodes for operation"));
+        }
+        if self.journal_capacity.is_some_and(|cap| self.journal_credits > cap) {
+            return Err(Error::new(ErrorKind::NoSpace, "Operation exceeds journal transaction capacity"));
+        }
+        Ok(())
+    }
+}
+
+impl<D: BlockDevice> Ext4FileSystem<D> {
+    /// 预估一个操作需要的空间
+    ///
+    /// 只读取必要的元数据（父目录 / 目标文件的 inode、日志 inode），不修改文件系统。
+    ///
+    /// # 示例
+    ///
+    /// ```rust,ignore
+    /// let est = fs.estimate(SpaceEstimateRequest::CreateFile { parent: dir, size: len })?;
+    /// est.check()?; // 空间不足时立即失败
+    /// ```
+    pub fn estimate(&mut self, op: SpaceEstimateRequest) -> Result<SpaceEstimate> {
+        let mut est = match op {
+            SpaceEstimateRequest::CreateFile { parent, size } => {
+                let data_blocks = size.div_ceil(self.superblock().block_size() as u64);
+                let dir_blocks = self.dir_entry_blocks(parent)?;
+                let sb = self.superblock();
+                let map_blocks = map_blocks(sb, data_blocks);
+                SpaceEstimate {
+                    data_blocks,
+                    metadata_blocks: map_blocks + dir_blocks,
+                    inodes: 1,
+                    journal_credits: alloc_credits(sb, data_blocks, map_blocks)
+                        + create_credits(dir_blocks),
+                    ..Default::default()
+                }
+            }
+            SpaceEstimateRequest::CreateDir { parent } => {
+                let dir_blocks = self.dir_entry_blocks(parent)?;
+                let new_dir_blocks = if self.index_new_dirs { 2 } else { 1 };
+                SpaceEstimate {
+                    metadata_blocks: dir_blocks + new_dir_blocks,
+                    inodes: 1,
+                    journal_credits: alloc_credits(self.superblock(), new_dir_blocks, 0)
+                        + new_dir_blocks
+                        + create_credits(dir_blocks),
+                    ..Default::default()
+                }
+            }
+            SpaceEstimateRequest::Fallocate { inode, offset, len } => {
+                let (data_blocks, map_blocks) =
+                    self.with_inode_ref(inode, |inode_ref| fallocate_blocks(inode_ref, offset, len))?;
+                SpaceEstimate {
+                    data_blocks,
+                    metadata_blocks: map_blocks,
+                    journal_credits: alloc_credits(self.superblock(), data_blocks, map_blocks) + 1,
+                    ..Default::default()
+                }
+            }
+            SpaceEstimateRequest::Import { files, dirs, data_bytes, name_bytes } => {
+                let sb = self.superblock();
+                let block_size = sb.block_size() as u64;
+                // 每个文件最后一个块可能不满
+                let data_blocks = data_bytes.div_ceil(block_size) + files;
+                // 所有文件的 extent 都集中在一个文件时树最高，以此作为上限
+                let map_blocks = map_blocks(sb, data_blocks);
+                let entries = files + dirs;
+                let entry_bytes = name_bytes + entries * DIR_ENTRY_OVERHEAD;
+                // 每个新目录至少一个块（索引目录另有根块），每个目录的最后一个块可能不满
+                let per_dir = if self.index_new_dirs { 2 } else { 1 };
+                let dir_blocks = entry_bytes.div_ceil(block_size) + dirs * per_dir + dirs + 1;
+                SpaceEstimate {
+                    data_blocks,
+                    metadata_blocks: map_blocks + dir_blocks,
+                    inodes: entries,
+                    journal_credits: alloc_credits(sb, data_blocks + dir_blocks, map_blocks)
+                        + dir_blocks
+                        + entries * create_credits(0),
+                    ..Default::default()
+                }
+            }
+        };
+
+        let sb = self.superblock();
+        est.free_blocks = crate::balloc::unreserved_free_blocks(sb);
+        est.free_inodes = sb.free_inodes_count() as u64;
+        est.journal_capacity = self.journal_capacity()?;
+        Ok(est)
+    }
+
+    /// 在 `parent` 中添加一个目录项最多需要的新块数
+    fn dir_entry_blocks(&mut self, parent: u32) -> Result<u64> {
+        self.with_inode_ref(parent, |inode_ref| {
+            if !inode_ref.is_dir()? {
+                return Err(Error::new(ErrorKind::NotADirectory, "Parent inode is not a directory"));
+            }
+            if !htree::is_indexed(inode_ref)? {
+                return Ok(1);
+            }
+            // 叶子分裂 + 每层索引块分裂（含根增高）
+            let levels = if inode_ref.sb().has_incompat_feature(EXT4_FEATURE_INCOMPAT_LARGEDIR) { 3 } else { 2 };
+            Ok(1 + levels)
+        })
+    }
+
+    /// 单个事务允许的最大 credits
+    ///
+    /// 与 jbd2 的默认值一致，为日志块数的 1/4
+    pub(super) fn journal_capacity(&mut self) -> Result<Option<u64>> {
+        let journal_inum = u32::from_le(self.superblock().inner().journal_inum);
+        if !self.superblock().has_compat_feature(EXT4_FEATURE_COMPAT_HAS_JOURNAL) || journal_inum == 0 {
+            return Ok(None);
+        }
+        let block_size = self.superblock().block_size() as u64;
+        let journal_blocks = self.with_inode_ref(journal_inum, |inode_ref| inode_ref.size())? / block_size;
+        Ok(Some(journal_blocks / 4))
+    }
+}
+
+/// 映射 `data_blocks` 个新块需要的元数据块（extent 树节点或间接块）
+fn map_blocks(sb: &Superblock, data_blocks: u64) -> u64 {
+    if sb.has_extents() {
+        extent_tree_blocks(data_extents(sb, data_blocks), sb.block_size())
+    } else {
+        indirect_blocks(data_blocks, sb.block_size())
+    }
+}
+
+/// `data_blocks` 个块最多拆成的 extent 数
+///
+/// 单个 extent 不超过 [`EXT_INIT_MAX_LEN`]，也不跨越块组
+/// （块组开头可能是位图和 inode 表），额外加 1 表示起点未对齐
+fn data_extents(sb: &Superblock, data_blocks: u64) -> u64 {
+    if data_blocks == 0 {
+        return 0;
+    }
+    let max_run = (EXT_INIT_MAX_LEN as u64).min(sb.blocks_per_group() as u64).max(1);
+    data_blocks.div_ceil(max_run) + 1
+}
+
+/// 存放 `extents` 个 extent 需要的树节点块数（不含 inode 中的根节点）
+fn extent_tree_blocks(extents: u64, block_size: u32) -> u64 {
+    // 节点块：12 字节头部 + 12 字节的条目
+    let per_node = ((block_size as u64 - 12) / 12).max(2);
+    let mut level = extents;
+    let mut nodes = 0;
+    while level > EXTENTS_IN_INODE {
+        level = level.div_ceil(per_node);
+        nodes += level;
+    }
+    nodes
+}
+
+/// 从逻辑块 0 开始映射 `data_blocks` 个块需要的间接块数
+fn indirect_blocks(data_blocks: u64, block_size: u32) -> u64 {
+    let ppb = block_size as u64 / 4;
+    let mut rem = data_blocks.saturating_sub(EXT4_INODE_DIRECT_BLOCKS as u64);
+    let mut blocks = 0;
+
+    // 一级、二级、三级间接块：顶层块 + 各层覆盖 rem 所需的块
+    let mut span = ppb;
+    for _ in 0..3 {
+        if rem == 0 {
+            break;
+        }
+        let covered = rem.min(span);
+        blocks += 1;
+        let mut sub = span / ppb;
+        while sub > 1 {
+            blocks += covered.div_ceil(sub);
+            sub /= ppb;
+        }
+        rem -= covered;
+        span = span.saturating_mul(ppb);
+    }
+    blocks
+}
+
+/// 分配块修改的元数据块：涉及块组的位图和描述符、新树节点、inode 和 superblock
+fn alloc_credits(sb: &Superblock, data_blocks: u64, map_blocks: u64) -> u64 {
+    let blocks = data_blocks + map_blocks;
+    if blocks == 0 {
+        return 0;
+    }
+    let groups = (blocks.div_ceil(sb.blocks_per_group() as u64) + 1).min(sb.block_group_count() as u64);
+    2 * groups + map_blocks + 2
+}
+
+/// 创建一个 inode 并链接到父目录修改的元数据块：
+/// inode 位图、块组描述符、inode 表块、父目录 inode 和修改的目录块
+fn create_credits(dir_blocks: u64) -> u64 {
+    3 + 1 + dir_blocks + 1
+}
+
+/// 预分配 `[offset, offset + len)` 需要的 (数据块, 元数据块)
+fn fallocate_blocks<D: BlockDevice>(inode_ref: &mut InodeRef<D>, offset: u64, len: u64) -> Result<(u64, u64)> {
+    let block_size = inode_ref.sb().block_size() as u64;
+    let start = offset / block_size;
+    let end = offset.saturating_add(len).div_ceil(block_size).min(u32::MAX as u64);
+    if start >= end {
+        return Ok((0, 0));
+    }
+
+    let mapped = inode_ref.fiemap(start as u32..end as u32)?;
+    let mapped_blocks: u64 = mapped.iter().map(|m| m.len as u64).sum();
+    let data_blocks = (end - start) - mapped_blocks;
+
+    if !inode_ref.has_extents()? {
+        // 保守估计：按映射到范围末尾所需的全部间接块计算
+        return Ok((data_blocks, indirect_blocks(end, block_size as u32)));
+    }
+
+    // 每个空洞单独估算 extent 数，新旧 extent 树大小之差即为新增节点
+    let mut holes = 0;
+    let mut extents = 0;
+    let mut pos = start;
+    for m in mapped.iter().map(|m| (m.logical_block as u64, m.logical_end())).chain([(end, end)]) {
+        if m.0 > pos {
+            holes += data_extents(inode_ref.sb(), m.0 - pos);
+        }
+        pos = pos.max(m.1);
+    }
+    for m in inode_ref.fiemap(0..u32::MAX)? {
+        extents += m.len.div_ceil(EXT_INIT_MAX_LEN as u32) as u64;
+    }
+    let block_size = block_size as u32;
+    let map_blocks = extent_tree_blocks(extents + holes, block_size) - extent_tree_blocks(extents, block_size);
+    Ok((data_blocks, map_blocks))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_extent_tree_blocks() {
+        // 4 个 extent 放在 inode 中
+        assert_eq!(extent_tree_blocks(4, 4096), 0);
+        // 一层叶子节点，每个 340 条
+        assert_eq!(extent_tree_blocks(5, 4096), 1);
+        assert_eq!(extent_tree_blocks(340 * 4, 4096), 4);
+        // 叶子节点超过 4 个时需要一层索引节点
+        assert_eq!(extent_tree_blocks(340 * 5, 4096), 5 + 1);
+    }
+
+    #[test]
+    fn test_indirect_blocks() {
+        assert_eq!(indirect_blocks(12, 4096), 0);
+        assert_eq!(indirect_blocks(13, 4096), 1);
+        assert_eq!(indirect_blocks(12 + 1024, 4096), 1);
+        // 二级间接块：顶层块 + 一个间接块
+        assert_eq!(indirect_blocks(12 + 1024 + 1, 4096), 1 + 2);
+        assert_eq!(indirect_blocks(12 + 1024 + 1024 * 3, 4096), 1 + 1 + 3);
+    }
+
+    #[test]
+    fn test_space_estimate_check() {
+        let est = SpaceEstimate {
+            data_blocks: 10,
+            metadata_blocks: 2,
+            inodes: 1,
+            journal_credits: 20,
+            free_blocks: 12,
+            free_inodes: 1,
+            journal_capacity: Some(20),
+        };
+        assert!(est.fits());
+
+        let no_blocks = SpaceEstimate { free_blocks: 11, ..est };
+        assert_eq!(no_blocks.check().unwrap_err().kind(), ErrorKind::NoSpace);
+        assert!(!SpaceEstimate { free_inodes: 0, ..est }.fits());
+        assert!(!SpaceEstimate { journal_credits: 21, ..est }.fits());
+        assert!(SpaceEstimate { journal_capacity: None, journal_credits: 1000, ..est }.fits());
+    }
+}
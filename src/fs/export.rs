@@ -0,0 +1,564 @@
+//! 把目录树导出为 tar 流
+//!
+//! 不需要在宿主系统上挂载镜像，也不需要 `std`，适合在恢复环境中备份或提取文件。
+//! 输出为 ustar 格式，超出 ustar 字段范围的内容使用 PAX 扩展头：
+//! - 超过 100 字节的路径和链接目标、超过 8 GiB 的文件、超出范围的 uid/gid/mtime
+//! - 扩展属性，按 `SCHILY.xattr.<name>` 记录（GNU tar 和 bsdtar 的格式）
+//! - 稀疏文件，按 GNU sparse 1.0 格式只保存有数据的区间
+//!
+//! 条目按目录深度优先、同一目录内按名称排序输出，同一镜像的导出结果是确定的。
+//! 多个硬链接中第一个遇到的保存数据，其余导出为硬链接条目。Socket 无法用 tar 表示，
+//! 会被跳过并计入 [`ExportSummary::skipped`]。
+//!
+//! ```rust,ignore
+//! use lwext4_core::export::{self, ExportOptions};
+//!
+//! let mut tar = Vec::new();
+//! let summary = export::export_tar(&mut fs, "/", &mut tar, &ExportOptions::default())?;
+//! ```
+
+use crate::{
+    block::BlockDevice,
+    dir::{sort_entries, DirOrder},
+    error::{Error, ErrorKind, Result},
+    xattr,
+};
+use alloc::{
+    collections::BTreeMap,
+    format,
+    string::{String, ToString},
+    vec,
+    vec::Vec,
+};
+
+use super::{
+    copy::COPY_CHUNK_SIZE,
+    filesystem::Ext4FileSystem,
+    metadata::{FileMetadata, FileType},
+    special::{major, minor},
+    types::FollowSymlink,
+};
+
+/// tar 块大小
+const TAR_BLOCK: usize = 512;
+
+/// ustar 头中 `name` 字段的长度
+const NAME_LEN: usize = 100;
+
+/// ustar 头中 `prefix` 字段的长度
+const PREFIX_LEN: usize = 155;
+
+/// tar 流的输出端，与 `std::io::Write::write_all` 相同
+///
+/// `Vec<u8>` 实现了此 trait；启用 `std` 特性时可以用 [`IoSink`] 包装任意 `std::io::Write`
+pub trait TarSink {
+    /// 写入全部数据
+    fn write_all(&mut self, data: &[u8]) -> Result<()>;
+}
+
+impl TarSink for Vec<u8> {
+    fn write_all(&mut self, data: &[u8]) -> Result<()> {
+        self.extend_from_slice(data);
+        Ok(())
+    }
+}
+
+/// 把 `std::io::Write` 用作 [`TarSink`]
+#[cfg(feature = "std")]
+pub struct IoSink<W>(pub W);
+
+#[cfg(feature = "std")]
+impl<W: std::io::Write> TarSink for IoSink<W> {
+    fn write_all(&mut self, data: &[u8]) -> Result<()> {
+        self.0
+            .write_all(data)
+            .map_err(|_| Error::new(ErrorKind::Io, "Failed to write tar stream"))
+    }
+}
+
+/// 导出选项
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ExportOptions {
+    /// 导出扩展属性
+    pub xattrs: bool,
+    /// 有空洞的文件使用 GNU sparse 格式，只保存有数据的区间；
+    /// 关闭时空洞按零写出
+    pub sparse: bool,
+}
+
+impl Default for ExportOptions {
+    fn default() -> Self {
+        Self { xattrs: true, sparse: true }
+    }
+}
+
+/// 导出结果统计
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct ExportSummary {
+    /// 写出的条目数（不含 PAX 扩展头）
+    pub entries: u64,
+    /// 写出的文件数据字节数（不含空洞和 tar 头）
+    pub data_bytes: u64,
+    /// 无法用 tar 表示而跳过的条目数（Socket）
+    pub skipped: u64,
+}
+
+/// 把 `path` 目录下的所有内容导出为 tar 流，写入 `sink`
+///
+/// 条目路径相对于 `path`，不包含 `path` 本身，目录以 `/` 结尾。
+/// 结束时写出两个全零块。路径最后的符号链接会被跟随，
+/// 目录树中的符号链接作为链接本身导出。
+///
+/// # 错误
+///
+/// - `ErrorKind::NotADirectory` - `path` 不是目录
+/// - `ErrorKind::PermissionDenied` - 目录树中有加密文件，其内容无法读取
+pub fn export_tar<D: BlockDevice, S: TarSink + ?Sized>(
+    fs: &mut Ext4FileSystem<D>,
+    path: &str,
+    sink: &mut S,
+    options: &ExportOptions,
+) -> Result<ExportSummary> {
+    let root = fs.lookup_with(path, FollowSymlink::Follow)?;
+    if !fs.get_inode_ref(root)?.is_dir()? {
+        return Err(Error::new(ErrorKind::NotADirectory, "Export root is not a directory"));
+    }
+
+    let mut exporter = Exporter {
+        fs,
+        sink,
+        options: *options,
+        links: BTreeMap::new(),
+        summary: ExportSummary::default(),
+        buf: Vec::new(),
+    };
+    let mut stack = vec![(String::new(), root)];
+    while let Some((prefix, dir)) = stack.pop() {
+        let mut entries = exporter.fs.read_dir_from_inode(dir)?;
+        sort_entries(&mut entries, DirOrder::ByName);
+        let mut subdirs = Vec::new();
+        for entry in entries {
+            if entry.name == "." || entry.name == ".." {
+                continue;
+            }
+            let path = format!("{prefix}{}", entry.name);
+            if exporter.export_entry(&path, entry.inode)? {
+                subdirs.push((path + "/", entry.inode));
+            }
+        }
+        stack.extend(subdirs.into_iter().rev());
+    }
+
+    exporter.sink.write_all(&[0u8; 2 * TAR_BLOCK])?;
+    Ok(exporter.summary)
+}
+
+/// 一次导出的状态
+struct Exporter<'a, D: BlockDevice, S: TarSink + ?Sized> {
+    fs: &'a mut Ext4FileSystem<D>,
+    sink: &'a mut S,
+    options: ExportOptions,
+    /// 已导出的多链接 inode（inode 号 -> 第一次导出的路径）
+    links: BTreeMap<u32, String>,
+    summary: ExportSummary,
+    /// 读取文件数据的缓冲区
+    buf: Vec<u8>,
+}
+
+impl<D: BlockDevice, S: TarSink + ?Sized> Exporter<'_, D, S> {
+    /// 导出一个条目，返回它是否是需要继续遍历的目录
+    fn export_entry(&mut self, path: &str, ino: u32) -> Result<bool> {
+        let meta = self.fs.with_inode_ref(ino, |inode_ref| FileMetadata::from_inode_ref(inode_ref))?;
+        let typeflag = match meta.file_type {
+            FileType::RegularFile => b'0',
+            FileType::Directory => b'5',
+            FileType::Symlink => b'2',
+            FileType::CharDevice => b'3',
+            FileType::BlockDevice => b'4',
+            FileType::Fifo => b'6',
+            FileType::Socket | FileType::Unknown => {
+                self.summary.skipped += 1;
+                return Ok(false);
+            }
+        };
+
+        let mut header = TarHeader::from_metadata(&meta, typeflag);
+        let mut pax = Vec::new();
+        let mut name = String::from(path);
+        let mut data = FileData::None;
+        match meta.file_type {
+            FileType::Directory => name.push('/'),
+            FileType::Symlink => header.linkname = self.fs.read_symlink(ino)?,
+            _ => {}
+        }
+        if let Some(first) = (!meta.is_dir() && meta.links_count > 1).then(|| self.links.get(&ino)).flatten() {
+            header.typeflag = b'1';
+            header.linkname = first.clone();
+        } else if meta.is_file() {
+            if meta.links_count > 1 {
+                self.links.insert(ino, name.clone());
+            }
+            data = self.file_data(ino, meta.size)?;
+        }
+        header.path = name;
+
+        if self.options.xattrs {
+            for (key, value) in self.xattrs(ino)? {
+                pax_record(&mut pax, &format!("SCHILY.xattr.{key}"), &value);
+            }
+        }
+        header.size = match &data {
+            FileData::None => 0,
+            FileData::Full(size) => *size,
+            FileData::Sparse { map, ranges } => map.len() as u64 + ranges.iter().map(|(s, e)| e - s).sum::<u64>(),
+        };
+        if let FileData::Sparse { .. } = data {
+            pax_record(&mut pax, "GNU.sparse.major", b"1");
+            pax_record(&mut pax, "GNU.sparse.minor", b"0");
+            pax_record(&mut pax, "GNU.sparse.name", header.path.as_bytes());
+            pax_record(&mut pax, "GNU.sparse.realsize", meta.size.to_string().as_bytes());
+            header.path = sparse_placeholder(&header.path);
+        }
+
+        let block = header.encode(&mut pax);
+        if !pax.is_empty() {
+            let pax_header = TarHeader {
+                path: format!("PaxHeaders/{}", base_name(&header.path)),
+                typeflag: b'x',
+                size: pax.len() as u64,
+                ..TarHeader::from_metadata(&meta, b'x')
+            };
+            self.sink.write_all(&pax_header.encode(&mut Vec::new()))?;
+            self.write_padded(&pax)?;
+        }
+        self.sink.write_all(&block)?;
+        self.summary.entries += 1;
+
+        match data {
+            FileData::None => {}
+            FileData::Full(size) => self.copy_data(ino, &[(0, size)])?,
+            FileData::Sparse { map, ranges } => {
+                self.sink.write_all(&map)?;
+                self.copy_data(ino, &ranges)?;
+            }
+        }
+        Ok(meta.is_dir())
+    }
+
+    /// 决定普通文件的数据如何写出
+    fn file_data(&mut self, ino: u32, size: u64) -> Result<FileData> {
+        self.fs.check_not_encrypted(ino)?;
+        self.fs.commit_pending_size(ino)?;
+        self.fs.flush_delalloc_inode(ino)?;
+        if !self.options.sparse || size == 0 {
+            return Ok(FileData::Full(size));
+        }
+        let ranges = merge_ranges(self.fs.data_ranges(ino, 0, size)?);
+        if ranges.first().is_some_and(|&(start, end)| start == 0 && end == size) {
+            return Ok(FileData::Full(size));
+        }
+        Ok(FileData::Sparse { map: sparse_map(&ranges, size), ranges })
+    }
+
+    /// 写出文件中 `ranges` 区间的数据，最后补齐到 tar 块边界
+    fn copy_data(&mut self, ino: u32, ranges: &[(u64, u64)]) -> Result<()> {
+        let mut total = 0u64;
+        for &(start, end) in ranges {
+            let mut pos = start;
+            while pos < end {
+                let n = ((end - pos) as usize).min(COPY_CHUNK_SIZE);
+                self.buf.resize(n, 0);
+                let n = self.fs.read_at_inode(ino, &mut self.buf[..n], pos)?;
+                if n == 0 {
+                    return Err(Error::new(ErrorKind::Io, "Short read while exporting"));
+                }
+                self.sink.write_all(&self.buf[..n])?;
+                pos += n as u64;
+            }
+            total += end - start;
+        }
+        self.summary.data_bytes += total;
+        self.pad(total)
+    }
+
+    /// inode 的所有扩展属性，未启用 `xattr` 特性时为空
+    fn xattrs(&mut self, ino: u32) -> Result<Vec<(String, Vec<u8>)>> {
+        self.fs.with_inode_ref(ino, |inode_ref| {
+            let entries = match xattr::iter(inode_ref) {
+                Ok(entries) => entries.collect::<Vec<_>>(),
+                Err(e) if e.kind() == ErrorKind::Unsupported => return Ok(Vec::new()),
+                Err(e) => return Err(e),
+            };
+            let mut attrs = Vec::with_capacity(entries.len());
+            for entry in entries {
+                let mut value = vec![0u8; entry.value_size as usize];
+                let len = xattr::get(inode_ref, &entry.name, &mut value)?;
+                value.truncate(len);
+                attrs.push((entry.name, value));
+            }
+            Ok(attrs)
+        })
+    }
+
+    fn write_padded(&mut self, data: &[u8]) -> Result<()> {
+        self.sink.write_all(data)?;
+        self.pad(data.len() as u64)
+    }
+
+    /// 写出 `len` 字节的数据之后补齐到 tar 块边界
+    fn pad(&mut self, len: u64) -> Result<()> {
+        let rem = (len % TAR_BLOCK as u64) as usize;
+        if rem != 0 {
+            self.sink.write_all(&[0u8; TAR_BLOCK][rem..])?;
+        }
+        Ok(())
+    }
+}
+
+/// 普通文件数据的写出方式
+enum FileData {
+    /// 没有数据（目录、链接、设备等）
+    None,
+    /// 按文件大小完整写出
+    Full(u64),
+    /// GNU sparse 1.0：先写稀疏映射，再写各个有数据的区间
+    Sparse { map: Vec<u8>, ranges: Vec<(u64, u64)> },
+}
+
+/// 一个 ustar 头的内容
+struct TarHeader {
+    path: String,
+    typeflag: u8,
+    mode: u16,
+    uid: u32,
+    gid: u32,
+    size: u64,
+    mtime: i64,
+    linkname: String,
+    dev: (u32, u32),
+}
+
+impl TarHeader {
+    fn from_metadata(meta: &FileMetadata, typeflag: u8) -> Self {
+        Self {
+            path: String::new(),
+            typeflag,
+            mode: meta.permissions & 0o7777,
+            uid: meta.uid,
+            gid: meta.gid,
+            size: 0,
+            mtime: meta.mtime,
+            linkname: String::new(),
+            dev: if meta.is_device() { (major(meta.rdev), minor(meta.rdev)) } else { (0, 0) },
+        }
+    }
+
+    /// 编码为 512 字节的 ustar 头，放不下的字段追加到 PAX 记录 `pax` 中
+    fn encode(&self, pax: &mut Vec<u8>) -> [u8; TAR_BLOCK] {
+        let mut block = [0u8; TAR_BLOCK];
+        match split_ustar_path(&self.path) {
+            Some((prefix, name)) => {
+                put_str(&mut block[0..100], name);
+                put_str(&mut block[345..500], prefix);
+            }
+            None => {
+                pax_record(pax, "path", self.path.as_bytes());
+                put_str(&mut block[0..100], &self.path);
+            }
+        }
+        put_octal(&mut block[100..108], self.mode as u64);
+        if !put_octal(&mut block[108..116], self.uid as u64) {
+            pax_record(pax, "uid", self.uid.to_string().as_bytes());
+        }
+        if !put_octal(&mut block[116..124], self.gid as u64) {
+            pax_record(pax, "gid", self.gid.to_string().as_bytes());
+        }
+        if !put_octal(&mut block[124..136], self.size) {
+            pax_record(pax, "size", self.size.to_string().as_bytes());
+        }
+        if self.mtime < 0 || !put_octal(&mut block[136..148], self.mtime as u64) {
+            pax_record(pax, "mtime", self.mtime.to_string().as_bytes());
+        }
+        block[156] = self.typeflag;
+        if self.linkname.len() > NAME_LEN {
+            pax_record(pax, "linkpath", self.linkname.as_bytes());
+        }
+        put_str(&mut block[157..257], &self.linkname);
+        block[257..263].copy_from_slice(b"ustar\0");
+        block[263..265].copy_from_slice(b"00");
+        if matches!(self.typeflag, b'3' | b'4') {
+            put_octal(&mut block[329..337], self.dev.0 as u64);
+            put_octal(&mut block[337..345], self.dev.1 as u64);
+        }
+
+        block[148..156].fill(b' ');
+        let sum: u32 = block.iter().map(|&b| b as u32).sum();
+        put_octal(&mut block[148..155], sum as u64);
+        block
+    }
+}
+
+/// 把路径拆成 ustar 的 `prefix` 和 `name` 字段，放不下时返回 `None`
+fn split_ustar_path(path: &str) -> Option<(&str, &str)> {
+    if path.len() <= NAME_LEN {
+        return Some(("", path));
+    }
+    // 目录名末尾的 `/` 属于 name，不能作为拆分点
+    let body = path.strip_suffix('/').unwrap_or(path);
+    path.match_indices('/')
+        .map(|(i, _)| i)
+        .filter(|&i| i < body.len())
+        .find(|&i| i <= PREFIX_LEN && path.len() - i - 1 <= NAME_LEN)
+        .map(|i| (&path[..i], &path[i + 1..]))
+}
+
+/// 把字符串写入定长字段，超出部分截断
+fn put_str(field: &mut [u8], s: &str) {
+    let n = s.len().min(field.len());
+    field[..n].copy_from_slice(&s.as_bytes()[..n]);
+}
+
+/// 把数值以八进制写入定长字段（末尾保留 NUL），放不下时写 0 并返回 `false`
+fn put_octal(field: &mut [u8], value: u64) -> bool {
+    let digits = field.len() - 1;
+    let fits = digits >= 22 || value < 1u64 << (3 * digits);
+    let value = if fits { value } else { 0 };
+    let text = format!("{value:0digits$o}");
+    field[..digits].copy_from_slice(text.as_bytes());
+    field[digits] = 0;
+    fits
+}
+
+/// 追加一条 PAX 记录 `"<len> <key>=<value>\n"`，`len` 包括它自身的位数
+fn pax_record(out: &mut Vec<u8>, key: &str, value: &[u8]) {
+    let body = key.len() + value.len() + 3;
+    let mut len = body + 1;
+    while len != body + len.to_string().len() {
+        len = body + len.to_string().len();
+    }
+    out.extend_from_slice(format!("{len} {key}=").as_bytes());
+    out.extend_from_slice(value);
+    out.push(b'\n');
+}
+
+/// 合并相邻的数据区间
+fn merge_ranges(ranges: Vec<(u64, u64)>) -> Vec<(u64, u64)> {
+    let mut merged: Vec<(u64, u64)> = Vec::with_capacity(ranges.len());
+    for (start, end) in ranges {
+        match merged.last_mut() {
+            Some(last) if last.1 >= start => last.1 = last.1.max(end),
+            _ => merged.push((start, end)),
+        }
+    }
+    merged
+}
+
+/// GNU sparse 1.0 的稀疏映射：区间数和各区间的偏移、长度，每项一行，补齐到 tar 块边界
+///
+/// 文件以空洞结尾时追加一个位于文件末尾的空区间
+fn sparse_map(ranges: &[(u64, u64)], size: u64) -> Vec<u8> {
+    let trailing_hole = ranges.last().is_none_or(|&(_, end)| end < size);
+    let mut map = format!("{}\n", ranges.len() + trailing_hole as usize);
+    for (start, end) in ranges {
+        map.push_str(&format!("{start}\n{}\n", end - start));
+    }
+    if trailing_hole {
+        map.push_str(&format!("{size}\n0\n"));
+    }
+    let mut map = map.into_bytes();
+    map.resize(map.len().div_ceil(TAR_BLOCK) * TAR_BLOCK, 0);
+    map
+}
+
+/// 稀疏文件在 ustar 头中使用的名称 `<dir>/GNUSparseFile.0/<name>`，真实名称记录在 PAX 中
+fn sparse_placeholder(path: &str) -> String {
+    match path.rsplit_once('/') {
+        Some((dir, name)) => format!("{dir}/GNUSparseFile.0/{name}"),
+        None => format!("GNUSparseFile.0/{path}"),
+    }
+}
+
+/// 路径的最后一个组件
+fn base_name(path: &str) -> &str {
+    let path = path.trim_end_matches('/');
+    path.rsplit_once('/').map_or(path, |(_, name)| name)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_pax_record() {
+        let mut out = Vec::new();
+        pax_record(&mut out, "path", b"a");
+        assert_eq!(out, b"9 path=a\n");
+
+        // 长度从 2 位变为 3 位时，长度本身也要计入
+        let mut out = Vec::new();
+        pax_record(&mut out, "path", &[b'x'; 91]);
+        assert_eq!(&out[..4], b"101 ");
+        assert_eq!(out.len(), 101);
+    }
+
+    #[test]
+    fn test_put_octal() {
+        let mut field = [0xFFu8; 8];
+        assert!(put_octal(&mut field, 0o644));
+        assert_eq!(&field, b"0000644\0");
+        assert!(put_octal(&mut field, 0o7777777));
+        assert!(!put_octal(&mut field, 0o10000000));
+        assert_eq!(&field, b"0000000\0");
+    }
+
+    #[test]
+    fn test_split_ustar_path() {
+        assert_eq!(split_ustar_path("etc/passwd"), Some(("", "etc/passwd")));
+
+        let long = format!("{}/{}", "d".repeat(120), "f".repeat(90));
+        let (prefix, name) = split_ustar_path(&long).unwrap();
+        assert_eq!((prefix.len(), name.len()), (120, 90));
+
+        assert_eq!(split_ustar_path(&"x".repeat(101)), None);
+        assert_eq!(split_ustar_path(&format!("{}/", "d".repeat(120))), None);
+    }
+
+    #[test]
+    fn test_sparse_map() {
+        let map = sparse_map(&[(0, 4096), (8192, 12288)], 16384);
+        assert_eq!(map.len(), TAR_BLOCK);
+        assert!(map.starts_with(b"3\n0\n4096\n8192\n4096\n16384\n0\n\0"));
+
+        let map = sparse_map(&[(4096, 8192)], 8192);
+        assert!(map.starts_with(b"1\n4096\n4096\n\0"));
+        assert!(sparse_map(&[], 100).starts_with(b"1\n100\n0\n\0"));
+    }
+
+    #[test]
+    fn test_merge_ranges() {
+        assert_eq!(merge_ranges(vec![(0, 10), (10, 20), (30, 40)]), [(0, 20), (30, 40)]);
+        assert_eq!(merge_ranges(vec![]), []);
+    }
+
+    #[test]
+    fn test_header_checksum() {
+        let header = TarHeader {
+            path: String::from("a"),
+            typeflag: b'0',
+            mode: 0o644,
+            uid: 0,
+            gid: 0,
+            size: 0,
+            mtime: 0,
+            linkname: String::new(),
+            dev: (0, 0),
+        };
+        let mut pax = Vec::new();
+        let block = header.encode(&mut pax);
+        assert!(pax.is_empty());
+        let mut copy = block;
+        copy[148..156].fill(b' ');
+        let sum: u32 = copy.iter().map(|&b| b as u32).sum();
+        assert_eq!(&block[148..156], format!("{sum:06o}\0 ").as_bytes());
+    }
+}
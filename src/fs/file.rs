@@ -1,13 +1,124 @@
 //! 文件句柄
 
 use crate::{
-    block::{BlockDev, BlockDevice},
+    block::BlockDevice,
     error::{Error, ErrorKind, Result},
     extent::ExtentTree,
-    superblock::Superblock,
 };
 
-use super::filesystem::Ext4FileSystem;
+use super::{filesystem::Ext4FileSystem, open_table::InodeHandle, types::SystemHal};
+
+/// 文件指针的移动方式，与 `std::io::SeekFrom` 相同
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SeekFrom {
+    /// 从文件开头算起
+    Start(u64),
+    /// 从文件末尾算起，可以为负
+    End(i64),
+    /// 从当前位置算起，可以为负
+    Current(i64),
+}
+
+/// 打开文件的方式，对应 `open(2)` 的 `O_*` 标志
+///
+/// 用法与 `std::fs::OpenOptions` 相同，交给
+/// [`Ext4FileSystem::open_with`] 使用。默认所有标志都不设置，新建文件的权限为 `0o644`。
+///
+/// 有效的组合：
+/// - `read`、`write`、`append` 至少设置一个（`append` 隐含可写）
+/// - `create`、`create_new`、`truncate` 需要可写
+/// - `truncate` 和 `append` 不能同时设置
+#[derive(Debug, Clone, Copy)]
+pub struct OpenOptions {
+    pub(super) read: bool,
+    pub(super) write: bool,
+    pub(super) append: bool,
+    pub(super) truncate: bool,
+    pub(super) create: bool,
+    pub(super) create_new: bool,
+    pub(super) mode: u16,
+}
+
+impl OpenOptions {
+    /// 创建所有标志都未设置的选项
+    pub fn new() -> Self {
+        Self {
+            read: false,
+            write: false,
+            append: false,
+            truncate: false,
+            create: false,
+            create_new: false,
+            mode: 0o644,
+        }
+    }
+
+    /// 可读（`O_RDONLY` / `O_RDWR`）
+    pub fn read(&mut self, read: bool) -> &mut Self {
+        self.read = read;
+        self
+    }
+
+    /// 可写（`O_WRONLY` / `O_RDWR`）
+    pub fn write(&mut self, write: bool) -> &mut Self {
+        self.write = write;
+        self
+    }
+
+    /// 每次写入都追加到文件末尾（`O_APPEND`）
+    pub fn append(&mut self, append: bool) -> &mut Self {
+        self.append = append;
+        self
+    }
+
+    /// 打开已存在的文件时截断为 0（`O_TRUNC`）
+    pub fn truncate(&mut self, truncate: bool) -> &mut Self {
+        self.truncate = truncate;
+        self
+    }
+
+    /// 文件不存在时创建（`O_CREAT`）
+    pub fn create(&mut self, create: bool) -> &mut Self {
+        self.create = create;
+        self
+    }
+
+    /// 总是新建文件，已存在时失败（`O_CREAT | O_EXCL`）
+    pub fn create_new(&mut self, create_new: bool) -> &mut Self {
+        self.create_new = create_new;
+        self
+    }
+
+    /// 新建文件的权限位
+    pub fn mode(&mut self, mode: u16) -> &mut Self {
+        self.mode = mode;
+        self
+    }
+
+    pub(super) fn writable(&self) -> bool {
+        self.write || self.append
+    }
+
+    /// 检查标志组合是否有效
+    pub(super) fn validate(&self) -> Result<()> {
+        if !self.read && !self.writable() {
+            return Err(Error::new(ErrorKind::InvalidInput, "No access mode specified"));
+        }
+        if (self.create || self.create_new || self.truncate) && !self.writable() {
+            return Err(Error::new(ErrorKind::InvalidInput, "Create or truncate requires write access"));
+        }
+        if self.truncate && self.append {
+            return Err(Error::new(ErrorKind::InvalidInput, "Truncate and append are exclusive"));
+        }
+        Ok(())
+    }
+}
+
+impl Default for OpenOptions {
+    fn default() -> Self {
+        Self::new()
+    }
+}
 
 /// 文件句柄
 ///
@@ -22,29 +133,55 @@ use super::filesystem::Ext4FileSystem;
 /// - **数据一致性**: 总是访问最新的 inode 数据
 /// - **内存效率**: 不复制 ~160 字节的 inode 结构
 /// - **与 lwext4 一致**: lwext4 的 ext4_file 也不持有 inode 数据
+///
+/// # 写入合并
+///
+/// 连续的小追加只写数据块，新的文件大小先记在打开 inode 表中；写入需要分配块时
+/// inode 本来就会被修改，此时顺带提交大小。其余情况在 [`sync`](Self::sync)、
+/// [`close`](Self::close) 或读取、`seek_data`/`seek_hole`、截断之前提交。
+/// 同一文件的所有句柄共享这个大小，但在提交之前通过 `metadata()` 看到的大小
+/// 可能是旧值。
+///
+/// # 打开 inode 表
+///
+/// 每个句柄持有一个 [`InodeHandle`]。文件在打开期间被删除时，inode 和数据块
+/// 保留到最后一个句柄 [`close`](Self::close) 时才释放；没有关闭就丢弃的句柄
+/// 在卸载文件系统时才释放其引用。
 pub struct File<D: BlockDevice> {
-    /// Inode 编号
-    inode_num: u32,
+    /// 打开 inode 表中的句柄
+    handle: InodeHandle,
     /// 当前文件偏移
     offset: u64,
     /// 块大小（缓存以提高性能）
     block_size: u32,
+    /// 是否允许读取
+    readable: bool,
+    /// 是否允许写入和截断
+    writable: bool,
+    /// 写入前移动到文件末尾
+    append: bool,
     _phantom: core::marker::PhantomData<D>,
 }
 
 impl<D: BlockDevice> File<D> {
     /// 创建新的文件句柄（内部使用）
-    pub(super) fn new(
-        _bdev: &mut BlockDev<D>,
-        sb: &Superblock,
-        inode_num: u32,
-    ) -> Result<Self> {
-        Ok(Self {
-            inode_num,
+    pub(super) fn new(handle: InodeHandle, block_size: u32) -> Self {
+        Self {
+            handle,
             offset: 0,
-            block_size: sb.block_size(),
+            block_size,
+            readable: true,
+            writable: true,
+            append: false,
             _phantom: core::marker::PhantomData,
-        })
+        }
+    }
+
+    /// 按打开方式设置访问权限（内部使用）
+    pub(super) fn set_access(&mut self, options: &OpenOptions) {
+        self.readable = options.read;
+        self.writable = options.writable();
+        self.append = options.append;
     }
 
     /// 读取文件内容
@@ -69,16 +206,13 @@ impl<D: BlockDevice> File<D> {
     /// println!("Read {} bytes", n);
     /// ```
     pub fn read(&mut self, fs: &mut Ext4FileSystem<D>, buf: &mut [u8]) -> Result<usize> {
-        // ✅ 使用 InodeRef 的辅助方法，保证数据一致性
-        let mut inode_ref = fs.get_inode_ref(self.inode_num)?;
-
-        // 检查 EOF
-        let file_size = inode_ref.size()?;
-        if self.offset >= file_size {
-            return Ok(0); // EOF
+        if !self.readable {
+            return Err(Error::new(ErrorKind::PermissionDenied, "File not opened for reading"));
         }
 
-        let n = inode_ref.read_extent_file(self.offset, buf)?;
+        // 经由文件系统读取，以便看到延迟分配尚未落盘的数据
+        self.sync(fs)?;
+        let n = fs.read_at_inode(self.inode_num(), buf, self.offset)?;
         self.offset += n as u64;
 
         Ok(n)
@@ -127,52 +261,139 @@ impl<D: BlockDevice> File<D> {
         Ok(buf)
     }
 
-    /// 移动文件指针
+    /// 移动文件指针，与 `std::io::Seek::seek` 相同
     ///
     /// # 参数
     ///
     /// * `fs` - 文件系统引用
-    /// * `pos` - 新的位置（字节偏移）
+    /// * `pos` - 目标位置，相对于文件开头、末尾或当前位置
     ///
     /// # 返回
     ///
-    /// 新的位置
+    /// 新的位置（相对于文件开头）
     ///
     /// # 注意
     ///
-    /// 允许 seek 到文件末尾之后，实际读取时会返回 EOF
-    pub fn seek(&mut self, fs: &mut Ext4FileSystem<D>, pos: u64) -> Result<u64> {
-        // 获取文件大小用于验证（可选）
-        let file_size = self.size(fs)?;
+    /// 允许 seek 到文件末尾之后，实际读取时会返回 EOF，写入则留下空洞
+    ///
+    /// # 错误
+    ///
+    /// - `ErrorKind::InvalidInput` - 目标位置为负数或溢出
+    ///
+    /// # 示例
+    ///
+    /// ```rust,ignore
+    /// let mut file = fs.open("/var/log/app.log")?;
+    /// file.seek(&mut fs, SeekFrom::End(-128))?; // 最后 128 字节
+    /// ```
+    pub fn seek(&mut self, fs: &mut Ext4FileSystem<D>, pos: SeekFrom) -> Result<u64> {
+        let (base, delta) = match pos {
+            SeekFrom::Start(offset) => {
+                self.offset = offset;
+                return Ok(offset);
+            }
+            SeekFrom::End(delta) => (self.size(fs)?, delta),
+            SeekFrom::Current(delta) => (self.offset, delta),
+        };
 
-        // 允许 seek 到文件大小，但警告超出范围
-        if pos > file_size {
-            // 不返回错误，允许 seek 超过文件末尾
-            // 读取时会返回 EOF
+        self.offset = base
+            .checked_add_signed(delta)
+            .ok_or(Error::new(ErrorKind::InvalidInput, "Invalid seek to a negative or overflowing position"))?;
+        Ok(self.offset)
+    }
+
+    /// 移动文件指针到 `offset` 处或之后的第一个数据位置（`SEEK_DATA`）
+    ///
+    /// unwritten extent 视为空洞。结果按块对齐，但不会小于 `offset`。
+    ///
+    /// # 错误
+    ///
+    /// - `ErrorKind::NotFound` - `offset` 超出文件末尾，或之后没有数据
+    pub fn seek_data(&mut self, fs: &mut Ext4FileSystem<D>, offset: u64) -> Result<u64> {
+        let (size, ranges) = self.data_ranges(fs)?;
+        if offset >= size {
+            return Err(Error::new(ErrorKind::NotFound, "Offset is beyond end of file"));
         }
 
+        let pos = ranges
+            .iter()
+            .find(|&&(_, end)| end > offset)
+            .map(|&(start, _)| start.max(offset))
+            .filter(|&pos| pos < size)
+            .ok_or(Error::new(ErrorKind::NotFound, "No data after offset"))?;
+
         self.offset = pos;
+        Ok(pos)
+    }
+
+    /// 移动文件指针到 `offset` 处或之后的第一个空洞位置（`SEEK_HOLE`）
+    ///
+    /// 文件末尾视为一个隐式空洞，因此没有空洞的文件返回文件大小。
+    ///
+    /// # 错误
+    ///
+    /// - `ErrorKind::NotFound` - `offset` 超出文件末尾
+    pub fn seek_hole(&mut self, fs: &mut Ext4FileSystem<D>, offset: u64) -> Result<u64> {
+        let (size, ranges) = self.data_ranges(fs)?;
+        if offset >= size {
+            return Err(Error::new(ErrorKind::NotFound, "Offset is beyond end of file"));
+        }
+
+        let mut pos = offset;
+        for &(start, end) in &ranges {
+            if start > pos {
+                break;
+            }
+            pos = pos.max(end);
+        }
+
+        self.offset = pos.min(size);
         Ok(self.offset)
     }
 
+    /// 文件大小和有数据的字节范围（按偏移升序）
+    fn data_ranges(&mut self, fs: &mut Ext4FileSystem<D>) -> Result<(u64, alloc::vec::Vec<(u64, u64)>)> {
+        use super::MappingFlags;
+
+        self.sync(fs)?;
+
+        let block_size = self.block_size as u64;
+        let mut inode_ref = fs.get_inode_ref(self.inode_num())?;
+        let size = inode_ref.size()?;
+
+        let ranges = inode_ref
+            .fiemap(0..u32::MAX)?
+            .into_iter()
+            .filter(|m| !m.flags.contains(MappingFlags::UNWRITTEN))
+            .map(|m| (m.logical_block as u64 * block_size, m.logical_end() * block_size))
+            .collect();
+        Ok((size, ranges))
+    }
+
     /// 获取当前文件指针位置
     pub fn position(&self) -> u64 {
         self.offset
     }
 
+    /// 获取当前文件指针位置，与 `std::io::Seek::stream_position` 相同
+    pub fn stream_position(&self) -> u64 {
+        self.position()
+    }
+
     /// 获取文件大小
     ///
     /// # 参数
     ///
     /// * `fs` - 文件系统引用
     pub fn size(&self, fs: &mut Ext4FileSystem<D>) -> Result<u64> {
-        let mut inode_ref = fs.get_inode_ref(self.inode_num)?;
-        inode_ref.size()
+        let size = fs.get_inode_ref(self.inode_num())?.size()?;
+        let pending = fs.open_inodes.get(self.inode_num()).and_then(|entry| entry.pending_size);
+        Ok(pending.map_or(size, |pending| pending.max(size)))
     }
 
     /// 获取 inode 编号
     pub fn inode_num(&self) -> u32 {
-        self.inode_num
+        self.handle.ino()
     }
 
     /// 重置文件指针到起始位置
@@ -184,7 +405,8 @@ impl<D: BlockDevice> File<D> {
 
     /// 写入数据到文件
     ///
-    /// 从当前位置写入数据，并更新文件位置
+    /// 从当前位置写入数据，并更新文件位置。以 append 方式打开时，
+    /// 先把位置移动到文件末尾
     ///
     /// # 参数
     ///
@@ -203,17 +425,32 @@ impl<D: BlockDevice> File<D> {
     /// println!("Wrote {} bytes", n);
     /// ```
     pub fn write(&mut self, fs: &mut Ext4FileSystem<D>, buf: &[u8]) -> Result<usize> {
+        if !self.writable {
+            return Err(Error::new(ErrorKind::PermissionDenied, "File not opened for writing"));
+        }
         if buf.is_empty() {
             return Ok(0);
         }
+        if self.append {
+            self.offset = self.size(fs)?;
+        }
 
         // 🚀 性能优化：使用批量写入接口，一次性处理所有数据
         // 相比单块写入，避免了多次 InodeRef 获取/释放
-        let write_len = fs.write_at_inode_batch(self.inode_num, buf, self.offset)?;
+        let (write_len, size_done) = fs.write_at_inode_sized(self.inode_num(), buf, self.offset, true)?;
 
         // 更新文件位置
         self.offset += write_len as u64;
 
+        // 记录尚未提交的文件大小
+        if let Some(entry) = fs.open_inodes.get_mut(self.inode_num()) {
+            if !size_done {
+                entry.pending_size = Some(entry.pending_size.map_or(self.offset, |p| p.max(self.offset)));
+            } else if entry.pending_size.is_some_and(|p| p <= self.offset) {
+                entry.pending_size = None;
+            }
+        }
+
         Ok(write_len)
     }
 
@@ -231,8 +468,15 @@ impl<D: BlockDevice> File<D> {
     /// file.truncate(&mut fs, 100)?; // 截断到 100 字节
     /// ```
     pub fn truncate(&mut self, fs: &mut Ext4FileSystem<D>, size: u64) -> Result<()> {
+        if !self.writable {
+            return Err(Error::new(ErrorKind::PermissionDenied, "File not opened for writing"));
+        }
+
+        // 未提交的大小在截断之前生效，截断会覆盖它
+        self.sync(fs)?;
+
         // 调用文件系统级别的 truncate
-        fs.truncate_file(self.inode_num, size)?;
+        fs.truncate_file(self.inode_num(), size)?;
 
         // 如果当前 offset 超过了新大小，调整到文件末尾
         if self.offset > size {
@@ -241,15 +485,201 @@ impl<D: BlockDevice> File<D> {
 
         Ok(())
     }
+
+    /// 设置文件大小，与 `std::fs::File::set_len` 相同
+    ///
+    /// 变小时释放多余的块，变大时留下空洞。与 [`truncate`](Self::truncate)
+    /// 不同，文件指针保持不变，之后在原位置写入会留下空洞。
+    pub fn set_len(&mut self, fs: &mut Ext4FileSystem<D>, size: u64) -> Result<()> {
+        let offset = self.offset;
+        self.truncate(fs, size)?;
+        self.offset = offset;
+        Ok(())
+    }
+
+    /// 为 `[offset, offset + len)` 分配块，返回宿主可以直接 DMA 写入的物理块段
+    ///
+    /// 每一段为 `(起始块号, 块数)`，块号以文件系统块为单位、相对于文件系统起始位置
+    /// （设备地址还要加上 [`BlockDev::partition_offset`](crate::BlockDev::partition_offset)）。
+    /// 空洞被分配，区间内的 unwritten extent 转为已初始化；缓存中该区间的数据先写回并丢弃。
+    ///
+    /// 段覆盖区间涉及的整块。两端不对齐的块中原来读出为零的部分会被清零，
+    /// 已有数据需要宿主自己保留（读-改-写）。宿主写完之后调用
+    /// [`commit_written`](Self::commit_written)；在此之前文件大小不变，不应卸载文件系统。
+    ///
+    /// # 错误
+    ///
+    /// - `ErrorKind::PermissionDenied` - 文件没有以可写方式打开
+    /// - `ErrorKind::ReadOnlyFs` - 只读挂载
+    /// - `ErrorKind::NoSpace` - 空间不足，已分配的块保留在文件中
+    /// - `ErrorKind::FileTooLarge` - 区间超出最大文件大小
+    ///
+    /// # 示例
+    ///
+    /// ```rust,ignore
+    /// let runs = file.block_run_for_write(&mut fs, 0, buf.len() as u64)?;
+    /// let mut pos = 0;
+    /// for (pba, blocks) in runs {
+    ///     let len = blocks as usize * block_size;
+    ///     dma_write(pba, &buf[pos..(pos + len).min(buf.len())]);
+    ///     pos += len;
+    /// }
+    /// file.commit_written::<MyHal>(&mut fs, 0, buf.len() as u64)?;
+    /// ```
+    pub fn block_run_for_write(&mut self, fs: &mut Ext4FileSystem<D>, offset: u64, len: u64) -> Result<alloc::vec::Vec<(u64, u32)>> {
+        if !self.writable {
+            return Err(Error::new(ErrorKind::PermissionDenied, "File not opened for writing"));
+        }
+        fs.block_runs_for_write(self.inode_num(), offset, len)
+    }
+
+    /// 宿主按 [`block_run_for_write`](Self::block_run_for_write) 写完数据之后，
+    /// 把文件大小扩展到 `offset + len` 并更新 mtime/ctime（时间来自 `H::now()`）
+    ///
+    /// 不移动文件指针
+    pub fn commit_written<H: SystemHal>(&mut self, fs: &mut Ext4FileSystem<D>, offset: u64, len: u64) -> Result<()> {
+        if !self.writable {
+            return Err(Error::new(ErrorKind::PermissionDenied, "File not opened for writing"));
+        }
+        fs.commit_written_at_inode::<H>(self.inode_num(), offset, len)
+    }
+
+    /// 把尚未提交的文件大小（所有句柄共享）写入 inode
+    ///
+    /// 只提交 inode 元数据，数据块和缓存的写回仍由
+    /// [`Ext4FileSystem::flush`] 负责
+    pub fn sync(&mut self, fs: &mut Ext4FileSystem<D>) -> Result<()> {
+        fs.commit_pending_size(self.inode_num())
+    }
+
+    /// 提交文件大小后立即提交文件系统中所有未提交的修改
+    ///
+    /// 见 [`Ext4FileSystem::fsync`]
+    pub fn fsync(&mut self, fs: &mut Ext4FileSystem<D>) -> Result<()> {
+        self.sync(fs)?;
+        fs.fsync()
+    }
+
+    /// 与 `std::fs::File::sync_all` 相同，见 [`fsync`](Self::fsync)
+    pub fn sync_all(&mut self, fs: &mut Ext4FileSystem<D>) -> Result<()> {
+        self.fsync(fs)
+    }
+
+    /// 与 `std::fs::File::sync_data` 相同：提交文件大小后只写回这个文件的数据和块映射
+    ///
+    /// 见 [`Ext4FileSystem::fsync_inode`]
+    pub fn sync_data(&mut self, fs: &mut Ext4FileSystem<D>) -> Result<()> {
+        self.sync(fs)?;
+        fs.fsync_inode(self.inode_num(), true)
+    }
+
+    /// 关闭文件，提交尚未写入 inode 的文件大小并释放打开 inode 表中的引用
+    ///
+    /// 文件已被删除且这是最后一个句柄时，释放 inode 和数据块
+    pub fn close(mut self, fs: &mut Ext4FileSystem<D>) -> Result<()> {
+        self.sync(fs)?;
+        fs.close_inode(self.handle)
+    }
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::testing::image;
+
+    /// 在测试镜像上新建 `/file` 并写入 `data`
+    fn open_with_data(fs: &mut Ext4FileSystem<crate::block::MemBlockDevice>, data: &[u8]) -> File<crate::block::MemBlockDevice> {
+        let mut file = fs
+            .open_with("/file", OpenOptions::new().read(true).write(true).create(true))
+            .unwrap();
+        assert_eq!(file.write(fs, data).unwrap(), data.len());
+        file
+    }
 
     #[test]
     fn test_file_api() {
         // 这些测试需要实际的块设备和 ext4 文件系统
         // 主要是验证 API 的设计和编译
     }
+
+    #[test]
+    fn test_seek() {
+        let mut fs = image::mount(image::image());
+        let mut file = open_with_data(&mut fs, &[0u8; 100]);
+
+        assert_eq!(file.seek(&mut fs, SeekFrom::Start(10)).unwrap(), 10);
+        assert_eq!(file.seek(&mut fs, SeekFrom::Current(5)).unwrap(), 15);
+        assert_eq!(file.seek(&mut fs, SeekFrom::Current(-15)).unwrap(), 0);
+        assert_eq!(file.seek(&mut fs, SeekFrom::End(-20)).unwrap(), 80);
+        assert_eq!(file.seek(&mut fs, SeekFrom::End(50)).unwrap(), 150);
+        assert_eq!(file.stream_position(), 150);
+        // 超过 i64::MAX 的起始位置也可以设置
+        assert_eq!(file.seek(&mut fs, SeekFrom::Start(u64::MAX)).unwrap(), u64::MAX);
+
+        // 负数结果
+        file.seek(&mut fs, SeekFrom::Start(10)).unwrap();
+        let err = file.seek(&mut fs, SeekFrom::Current(-11)).unwrap_err();
+        assert_eq!(err.kind(), ErrorKind::InvalidInput);
+        let err = file.seek(&mut fs, SeekFrom::End(-101)).unwrap_err();
+        assert_eq!(err.kind(), ErrorKind::InvalidInput);
+        let err = file.seek(&mut fs, SeekFrom::End(i64::MIN)).unwrap_err();
+        assert_eq!(err.kind(), ErrorKind::InvalidInput);
+        // 失败的 seek 不移动文件指针
+        assert_eq!(file.stream_position(), 10);
+
+        // u64 溢出
+        file.seek(&mut fs, SeekFrom::Start(u64::MAX - 1)).unwrap();
+        let err = file.seek(&mut fs, SeekFrom::Current(2)).unwrap_err();
+        assert_eq!(err.kind(), ErrorKind::InvalidInput);
+        let err = file.seek(&mut fs, SeekFrom::Current(i64::MAX)).unwrap_err();
+        assert_eq!(err.kind(), ErrorKind::InvalidInput);
+        assert_eq!(file.stream_position(), u64::MAX - 1);
+        assert_eq!(file.seek(&mut fs, SeekFrom::Current(1)).unwrap(), u64::MAX);
+    }
+
+    #[test]
+    fn test_set_len() {
+        let mut fs = image::mount(image::image());
+        let data: alloc::vec::Vec<u8> = (0..10000u32).map(|i| i as u8).collect();
+        let mut file = open_with_data(&mut fs, &data);
+
+        // 缩小：释放多余的块，文件指针不变
+        file.set_len(&mut fs, 5000).unwrap();
+        assert_eq!(file.size(&mut fs).unwrap(), 5000);
+        assert_eq!(file.stream_position(), 10000);
+        file.rewind();
+        assert_eq!(file.read_to_end(&mut fs).unwrap(), &data[..5000]);
+
+        // 变大：新的部分（包括原来被截掉的部分）读出为零
+        file.set_len(&mut fs, 12000).unwrap();
+        assert_eq!(file.size(&mut fs).unwrap(), 12000);
+        file.rewind();
+        let content = file.read_to_end(&mut fs).unwrap();
+        assert_eq!(&content[..5000], &data[..5000]);
+        assert!(content[5000..].iter().all(|&b| b == 0));
+
+        // 重新挂载之后内容不变
+        file.close(&mut fs).unwrap();
+        let dev = fs.unmount().unwrap().device().clone();
+        let mut fs = image::mount(dev);
+        let content = crate::fs::ops::read(&mut fs, "/file").unwrap();
+        assert_eq!(content.len(), 12000);
+        assert_eq!(&content[..5000], &data[..5000]);
+        assert!(content[5000..].iter().all(|&b| b == 0));
+    }
+
+    #[test]
+    fn test_open_options_validate() {
+        assert!(OpenOptions::new().read(true).validate().is_ok());
+        assert!(OpenOptions::new().append(true).create(true).validate().is_ok());
+        assert!(OpenOptions::new().write(true).create_new(true).truncate(true).validate().is_ok());
+
+        // 没有访问方式
+        assert!(OpenOptions::new().validate().is_err());
+        // 只读时不能创建或截断
+        assert!(OpenOptions::new().read(true).create(true).validate().is_err());
+        assert!(OpenOptions::new().read(true).truncate(true).validate().is_err());
+        // 截断和追加互斥
+        assert!(OpenOptions::new().write(true).append(true).truncate(true).validate().is_err());
+    }
 }
@@ -1,15 +1,22 @@
 //! Ext4 文件系统核心结构
 
 use crate::{
-    block::{BlockDev, BlockDevice},
-    dir::{lookup_path, read_dir, DirEntry},
+    block::{Block, BlockDev, BlockDevice, FsOp},
+    dir::{find_entry, lookup_path, lookup_path_at, read_dir, sort_entries, DirEntry, DirOrder},
     error::{Error, ErrorKind, Result},
-    inode::Inode,
+    ialloc::InodeAllocPolicy,
     superblock::Superblock,
+    types::Pblk,
 };
-use alloc::vec::Vec;
+use alloc::{collections::BTreeMap, vec::Vec};
 
-use super::{file::File, metadata::FileMetadata, inode_ref::InodeRef, block_group_ref::BlockGroupRef};
+use super::{file::{File, OpenOptions}, metadata::FileMetadata, inode_ref::InodeRef, block_group_ref::BlockGroupRef, types::{CreatePolicy, FollowSymlink, FsConfig, GroupWrites}, undo::AllocUndo, delalloc::DelallocState, pagecache::PageCache, commit::{CommitScheduler, DEFAULT_COMMIT_INTERVAL}, open_table::OpenInodeTable, fsck::MountState};
+
+/// 批量写入时单次设备写入合并的最大块数
+pub(super) const MAX_WRITE_RUN: u32 = 256;
+
+/// [`Ext4FileSystem::metadata`] 连续跟随符号链接的最大次数（同 Linux 的 `MAXSYMLINKS`）
+pub const MAX_SYMLINK_FOLLOW: u32 = 40;
 
 /// 文件系统统计信息
 #[derive(Debug, Clone)]
@@ -60,9 +67,39 @@ pub struct FileSystemStats {
 /// let metadata = fs.metadata("/etc/passwd")?;
 /// println!("File size: {} bytes", metadata.size);
 /// ```
+///
+/// # 非阻塞设备
+///
+/// 设备可以返回 [`ErrorKind::WouldBlock`]，错误会原样传递给调用者。
+/// 哪些操作可以整体重试见 [`retry_would_block`](crate::error::retry_would_block)。
 pub struct Ext4FileSystem<D: BlockDevice> {
     pub(crate) bdev: BlockDev<D>,
-    sb: Superblock,
+    pub(super) sb: Superblock,
+    /// 延迟分配状态，`None` 表示未启用
+    pub(super) delalloc: Option<DelallocState>,
+    /// 文件数据页缓存，`None` 表示未启用
+    pub(super) page_cache: Option<PageCache>,
+    /// 新目录是否创建为 HTree 索引目录，见 [`FsConfig::index_new_dirs`]
+    pub(super) index_new_dirs: bool,
+    /// inode 分配策略，见 [`FsConfig::inode_alloc`]
+    inode_alloc: InodeAllocPolicy,
+    /// 新 inode 的属主和权限策略，见 [`FsConfig::create_policy`]
+    create_policy: CreatePolicy,
+    /// 新 inode 是否使用 extent 映射，挂载时由 EXTENTS 特性决定
+    use_extents: bool,
+    /// 定时提交状态，见 [`on_timer_tick`](Self::on_timer_tick)
+    pub(super) commit: CommitScheduler,
+    /// [`pin_metadata`](Self::pin_metadata) 固定的块，`None` 表示未固定
+    pinned_metadata: Option<Vec<u64>>,
+    /// 正在执行的被观测操作的嵌套深度，见 [`observe`](Self::observe)
+    op_depth: u32,
+    /// 打开 inode 表，见 [`open_inode`](Self::open_inode)
+    pub(super) open_inodes: OpenInodeTable,
+    /// 挂载状态，见 [`needs_fsck`](Self::needs_fsck)
+    pub(super) mount_state: MountState,
+    /// 日志，`None` 表示没有日志或日志需要恢复，见 [`journal`](super::journal)
+    #[cfg(feature = "journal")]
+    pub(super) journal: Option<super::journal::FsJournal>,
 }
 
 impl<D: BlockDevice> Ext4FileSystem<D> {
@@ -76,14 +113,158 @@ impl<D: BlockDevice> Ext4FileSystem<D> {
     ///
     /// 成功返回文件系统实例
     ///
+    /// 没有 EXTENTS 特性的 ext2/ext3 镜像上，之后新建的 inode 使用间接块映射
+    /// 而不是 extent 树，镜像保持为 e2fsck 认可的 ext2/ext3 格式。
+    ///
+    /// 挂载前用 [`Superblock::check_features`] 检查特性：带有本实现不支持的
+    /// incompat 特性时拒绝挂载；只带有不支持的 ro_compat 特性，或者块设备只读时，
+    /// 以只读方式挂载，修改操作返回 `ErrorKind::ReadOnlyFs`，而不是去修改
+    /// 无法正确理解的结构。
+    ///
     /// # 错误
     ///
-    /// - `ErrorKind::Corrupted` - 无效的 superblock
+    /// - `ErrorKind::Corrupted` - 主 superblock 无效（魔数或校验和不正确），
+    ///   且没有可用的备份，见 [`Superblock::load_with_fallback`]
     /// - `ErrorKind::Io` - 设备读取失败
-    pub fn mount(mut bdev: BlockDev<D>) -> Result<Self> {
-        let sb = Superblock::load(&mut bdev)?;
+    /// - `ErrorKind::Unsupported` - 带有不支持的 incompat 特性（具体特性见
+    ///   `Superblock::load(&mut bdev)?.check_features()`），或者启用了 CASEFOLD
+    ///   特性，但未启用 `casefold` cargo 特性或文件名编码未知
+    /// - `ErrorKind::InvalidInput` - 文件系统块大小不在 1K..=64K 范围内
+    ///
+    /// 可写挂载时增加挂载计数并把文件系统标记为使用中，见 [`needs_fsck`](Self::needs_fsck)。
+    pub fn mount(bdev: BlockDev<D>) -> Result<Self> {
+        let mut fs = Self::load(bdev)?;
+        fs.record_mount(None, None)?;
+        Ok(fs)
+    }
+
+    /// 读取 superblock 并构造文件系统，不修改设备
+    fn load(mut bdev: BlockDev<D>) -> Result<Self> {
+        let mut sb = Superblock::load_with_fallback(&mut bdev)?;
+        // 之后的块号都以文件系统块为单位，与设备块大小无关
+        bdev.set_block_size(sb.block_size())?;
+        crate::dir::casefold::check_encoding(&sb)?;
+
+        let report = sb.check_features();
+        if !report.can_mount() {
+            log::error!("[MOUNT] {report}");
+            return Err(Error::new(ErrorKind::Unsupported, "Unsupported incompatible filesystem features"));
+        }
+        if !report.can_write() {
+            log::warn!("[MOUNT] {report}, mounting read-only");
+            sb.set_read_only(true);
+        }
+        if bdev.is_read_only() {
+            log::info!("[MOUNT] Block device is read-only, mounting read-only");
+            sb.set_read_only(true);
+        }
+        bdev.set_read_only(sb.is_read_only());
 
-        Ok(Self { bdev, sb })
+        // 没有 EXTENTS 特性的 ext2/ext3 镜像：新文件使用间接块映射，
+        // 否则 e2fsck 会把带 EXTENTS 标志的 inode 视为损坏
+        let use_extents = sb.has_extents();
+        if !use_extents {
+            log::info!("[MOUNT] No EXTENTS feature, new inodes use indirect block mapping");
+        }
+
+        let mut fs = Self {
+            bdev,
+            sb,
+            delalloc: None,
+            page_cache: None,
+            index_new_dirs: false,
+            inode_alloc: InodeAllocPolicy::FirstFree,
+            create_policy: CreatePolicy::default(),
+            use_extents,
+            commit: CommitScheduler::new(Some(DEFAULT_COMMIT_INTERVAL), None),
+            pinned_metadata: None,
+            op_depth: 0,
+            open_inodes: OpenInodeTable::new(),
+            mount_state: MountState::new(),
+            #[cfg(feature = "journal")]
+            journal: None,
+        };
+        // 日志容量需要读取日志 inode，只能在构造之后计算
+        fs.commit = CommitScheduler::new(Some(DEFAULT_COMMIT_INTERVAL), fs.journal_capacity()?);
+        #[cfg(feature = "journal")]
+        {
+            fs.journal = super::journal::FsJournal::load(&mut fs.bdev, &mut fs.sb)?;
+        }
+        fs.load_quota()?;
+        Ok(fs)
+    }
+
+    /// 使用指定配置挂载文件系统
+    ///
+    /// 目前会应用 [`FsConfig::max_blocks`]：分配器不会使用上限之外的块，
+    /// [`stats`](Self::stats) 按上限报告容量；以及 [`FsConfig::delalloc`]
+    /// 、[`FsConfig::page_cache_pages`]、[`FsConfig::cache_blocks`]、[`FsConfig::cache_policy`]
+    /// 、[`FsConfig::cache_writeback`]、[`FsConfig::pin_metadata`]、[`FsConfig::index_new_dirs`]
+    /// 、[`FsConfig::inode_alloc`]、[`FsConfig::commit_interval`]、[`FsConfig::verify_checksums`]
+    /// 、[`FsConfig::reserved_percent`]、[`FsConfig::read_only`]、[`FsConfig::observer`]
+    /// 、[`FsConfig::create_policy`]、[`FsConfig::clock`]、[`FsConfig::mount_point`]
+    /// 和 [`FsConfig::record_errors`]。
+    ///
+    /// # 参数
+    ///
+    /// * `bdev` - 块设备包装器
+    /// * `config` - 文件系统配置
+    ///
+    /// # 错误
+    ///
+    /// - `ErrorKind::InvalidInput` - `max_blocks` 或 `cache_blocks` 为 0，或 `reserved_percent` 超过 50
+    /// - `ErrorKind::Corrupted` - 打开了 `verify_checksums`，块组描述符的校验和不匹配
+    /// - 其余同 [`mount`](Self::mount)
+    ///
+    /// # 示例
+    ///
+    /// ```rust,ignore
+    /// // 在 8GB 稀疏镜像中构建最终要烧录到 4GB 分区的文件系统
+    /// let config = FsConfig { max_blocks: Some(4 * 1024 * 1024 * 1024 / 4096), ..Default::default() };
+    /// let mut fs = Ext4FileSystem::mount_with_config(bdev, config)?;
+    /// ```
+    pub fn mount_with_config(mut bdev: BlockDev<D>, config: FsConfig) -> Result<Self> {
+        if config.max_blocks == Some(0) {
+            return Err(Error::new(ErrorKind::InvalidInput, "max_blocks must be non-zero"));
+        }
+        if config.reserved_percent.is_some_and(|percent| percent > 50) {
+            return Err(Error::new(ErrorKind::InvalidInput, "reserved_percent must not exceed 50"));
+        }
+
+        if let Some(blocks) = config.cache_blocks {
+            bdev.set_cache_capacity(blocks)?;
+        }
+        bdev.set_cache_policy(config.cache_policy);
+        bdev.set_writeback_config(config.cache_writeback);
+        bdev.set_observer(config.observer);
+
+        let mut fs = Self::load(bdev)?;
+        if config.read_only {
+            fs.sb.set_read_only(true);
+            fs.bdev.set_read_only(true);
+        }
+        if config.verify_checksums {
+            crate::block_group::check_block_group_descs(&mut fs.bdev, &fs.sb)?;
+            fs.sb.set_verify_checksums(true);
+        }
+        fs.sb.set_max_blocks(config.max_blocks);
+        fs.sb.set_reserved_percent(config.reserved_percent);
+        if config.delalloc {
+            fs.delalloc = Some(DelallocState::new());
+        }
+        if config.page_cache_pages > 0 {
+            fs.page_cache = Some(PageCache::new(config.page_cache_pages));
+        }
+        fs.index_new_dirs = config.index_new_dirs;
+        fs.inode_alloc = config.inode_alloc;
+        fs.create_policy = config.create_policy;
+        fs.set_commit_interval(config.commit_interval);
+        if config.pin_metadata {
+            fs.pin_metadata()?;
+        }
+        fs.record_mount(config.clock, config.mount_point)?;
+        fs.mount_state.record_errors = config.record_errors;
+        Ok(fs)
     }
 
     /// 卸载文件系统
@@ -98,8 +279,9 @@ impl<D: BlockDevice> Ext4FileSystem<D> {
     ///
     /// - 此方法会消费 `self`，之后无法再使用该文件系统实例
     /// - 确保所有文件句柄已经关闭
-    /// - 自动写回 superblock
-    /// - 同步块设备缓存
+    /// - 自动写回 superblock，并更新 superblock 和块组描述符的备份
+    /// - 把文件系统标记为正常卸载，挂载期间检测到的错误会保留
+    /// - 写 superblock 之前发出写屏障，最后同步块设备缓存
     ///
     /// # 示例
     ///
@@ -114,17 +296,60 @@ impl<D: BlockDevice> Ext4FileSystem<D> {
     /// 如果不调用此方法，`Ext4FileSystem` 被 drop 时不会自动刷新数据。
     /// 建议显式调用此方法以确保数据完整性。
     pub fn unmount(mut self) -> Result<BlockDev<D>> {
-        // 1. 写回 superblock
-        self.sb.write(&mut self.bdev)?;
+        // 只读挂载：没有需要写回的内容
+        if self.sb.is_read_only() {
+            return Ok(self.bdev);
+        }
+
+        // 0. 提交仍然打开的文件的大小，释放已删除但仍然打开的 inode；
+        //    写回缓存的文件数据，为延迟分配的数据分配块并写入
+        self.release_open_inodes()?;
+        self.flush_page_cache()?;
+        self.flush_delalloc()?;
+        self.sync_quota()?;
+        #[cfg(feature = "journal")]
+        self.commit_journal()?;
+
+        // 1. 写屏障：数据和元数据先于 superblock 落盘
+        self.bdev.barrier()?;
 
-        // 2. 同步块设备（确保所有写操作完成）
-        // 注意：BlockDev 目前没有显式的 sync 方法，
-        // 但所有写操作都是同步的，所以数据已经在磁盘上
+        // 2. 恢复挂载前的状态（正常卸载），写回 superblock 及其备份
+        self.record_unmount();
+        self.write_metadata_backups()?;
 
-        // 3. 返回块设备的所有权
+        // 3. 同步块设备，返回时所有写入都已持久化
+        self.bdev.flush()?;
+
+        // 4. 返回块设备的所有权
         Ok(self.bdev)
     }
 
+    /// 是否以只读方式挂载
+    ///
+    /// 见 [`FsConfig::read_only`]
+    pub fn is_read_only(&self) -> bool {
+        self.sb.is_read_only()
+    }
+
+    /// 当前的新建 inode 策略，见 [`FsConfig::create_policy`]
+    pub fn create_policy(&self) -> CreatePolicy {
+        self.create_policy
+    }
+
+    /// 更换新建 inode 的属主和权限策略
+    ///
+    /// 只影响之后创建的文件，例如 VFS 层在每次创建前设置调用进程的 uid/gid 和 umask
+    ///
+    /// # 示例
+    ///
+    /// ```rust,ignore
+    /// fs.set_create_policy(CreatePolicy { uid: 1000, gid: 1000, umask: 0o022, ..Default::default() });
+    /// let ino = fs.create_file("/home/user", "notes.txt", 0o666)?; // 0o644, 1000:1000
+    /// ```
+    pub fn set_create_policy(&mut self, policy: CreatePolicy) {
+        self.create_policy = policy;
+    }
+
     /// 获取 superblock 引用
     pub fn superblock(&self) -> &Superblock {
         &self.sb
@@ -145,6 +370,36 @@ impl<D: BlockDevice> Ext4FileSystem<D> {
         &mut self.sb
     }
 
+    /// 同时获取块设备和 superblock 的可变引用，供直接调用分配器的模块使用
+    pub(super) fn bdev_and_sb_mut(&mut self) -> (&mut BlockDev<D>, &mut Superblock) {
+        (&mut self.bdev, &mut self.sb)
+    }
+
+    /// 执行 `f` 并把它作为一次 `op` 操作报告给观测者
+    ///
+    /// 嵌套调用只报告最外层的操作，没有观测者时直接执行 `f`。
+    /// 返回 `ErrorKind::Corrupted` 时在 superblock 中记录错误，见 [`error_state`](Self::error_state)
+    pub(super) fn observe<R>(&mut self, op: FsOp, f: impl FnOnce(&mut Self) -> Result<R>) -> Result<R> {
+        let result = match self.bdev.observer() {
+            Some(observer) if self.op_depth == 0 => {
+                let start = observer.now();
+                self.op_depth += 1;
+                let result = f(self);
+                self.op_depth -= 1;
+                let elapsed = start.zip(observer.now()).map(|(start, end)| end.saturating_sub(start));
+                observer.on_op(op, elapsed, result.is_ok());
+                result
+            }
+            _ => f(self),
+        };
+        if let Err(e) = &result {
+            if e.kind() == ErrorKind::Corrupted {
+                self.note_corruption(op, e);
+            }
+        }
+        result
+    }
+
     /// 获取文件系统统计信息
     ///
     /// # 返回
@@ -162,19 +417,17 @@ impl<D: BlockDevice> Ext4FileSystem<D> {
     pub fn stats(&self) -> Result<FileSystemStats> {
         let sb_inner = self.sb.inner();
 
+        // 设置了分配上限时，上限之外的块视为全部空闲并从空闲数中扣除
+        // （保守估计，不会高报可用空间）
+        // 延迟分配预留的块同样不计入空闲块
+        let limit = self.sb.alloc_limit();
+        let free = crate::balloc::unreserved_free_blocks(&self.sb);
+
         Ok(FileSystemStats {
             block_size: self.sb.block_size(),
-            blocks_total: u32::from_le(sb_inner.blocks_count_lo) as u64
-                | ((u32::from_le(sb_inner.blocks_count_hi) as u64) << 32),
-            blocks_free: u32::from_le(sb_inner.free_blocks_count_lo) as u64
-                | ((u32::from_le(sb_inner.free_blocks_count_hi) as u64) << 32),
-            blocks_available: {
-                let free = u32::from_le(sb_inner.free_blocks_count_lo) as u64
-                    | ((u32::from_le(sb_inner.free_blocks_count_hi) as u64) << 32);
-                let reserved = u32::from_le(sb_inner.r_blocks_count_lo) as u64
-                    | ((u32::from_le(sb_inner.r_blocks_count_hi) as u64) << 32);
-                free.saturating_sub(reserved)
-            },
+            blocks_total: limit,
+            blocks_free: free,
+            blocks_available: free.saturating_sub(self.sb.root_reserved_blocks()),
             inodes_total: u32::from_le(sb_inner.inodes_count),
             inodes_free: u32::from_le(sb_inner.free_inodes_count),
             filesystem_id: {
@@ -189,9 +442,133 @@ impl<D: BlockDevice> Ext4FileSystem<D> {
         })
     }
 
+    /// 启用按块组统计的写入热度图
+    ///
+    /// 之后每个实际写到设备上的块都计入其所在块组（缓存中尚未刷新的
+    /// 写入不计）。计数只在内存中，用于分析闪存磨损热点。
+    /// 已启用时会重新开始计数。
+    ///
+    /// # 参数
+    ///
+    /// * `sample_every` - 采样间隔，每写入多少个块记录一次（0 按 1 处理）
+    ///
+    /// # 示例
+    ///
+    /// ```rust,ignore
+    /// fs.enable_write_heatmap(16);
+    /// run_workload(&mut fs)?;
+    /// for g in fs.write_heatmap().unwrap() {
+    ///     println!("group {}: ~{} blocks", g.group, g.estimated_blocks);
+    /// }
+    /// ```
+    pub fn enable_write_heatmap(&mut self, sample_every: u32) {
+        // 热度图按设备块计数，设备块可能小于文件系统块
+        let scale = (self.sb.block_size() / self.bdev.block_size()).max(1) as u64;
+        let first_block = self.sb.first_data_block() as u64 * scale;
+        let blocks_per_group = self.sb.blocks_per_group() as u64 * scale;
+        self.bdev.enable_write_heatmap(first_block, blocks_per_group, sample_every);
+    }
+
+    /// 停用写入热度图
+    pub fn disable_write_heatmap(&mut self) {
+        self.bdev.disable_write_heatmap();
+    }
+
+    /// 清零写入热度图的计数
+    pub fn reset_write_heatmap(&mut self) {
+        if let Some(map) = self.bdev.write_heatmap_mut() {
+            map.reset();
+        }
+    }
+
+    /// 导出写入热度图
+    ///
+    /// 返回每个块组（包括未被写入的块组）的写入统计，
+    /// 未启用时返回 `None`
+    pub fn write_heatmap(&self) -> Option<Vec<GroupWrites>> {
+        let map = self.bdev.write_heatmap()?;
+        let groups = (self.sb.block_group_count() as usize).max(map.samples().len());
+        Some(
+            (0..groups)
+                .map(|g| GroupWrites {
+                    group: g as u32,
+                    samples: map.samples().get(g).copied().unwrap_or(0),
+                    estimated_blocks: map.estimated_writes(g),
+                })
+                .collect(),
+        )
+    }
+
+    /// 在块缓存中固定关键元数据块
+    ///
+    /// 固定 superblock、所有块组描述符块和 journal superblock 所在的块，
+    /// 大量数据写入时它们不会被淘汰，不必反复从设备读取。位图块数量随块组数增长，
+    /// 需要时可以用 [`BlockDev::pin_cache_block`] 单独固定。
+    ///
+    /// 固定的块最多占缓存的一半，超出的部分不再固定。在线扩容或缩小后会重新固定。
+    /// 未启用块缓存时没有效果。
+    ///
+    /// # 返回
+    ///
+    /// 固定的块数
+    pub fn pin_metadata(&mut self) -> Result<usize> {
+        self.unpin_metadata();
+        if !self.bdev.has_cache() {
+            return Ok(0);
+        }
+
+        let mut blocks = Vec::new();
+        blocks.push(crate::consts::EXT4_SUPERBLOCK_OFFSET / self.sb.block_size() as u64);
+        for group in 0..self.sb.block_group_count() {
+            // 同一个描述符块中的块组是连续的
+            let (block, _) = crate::block_group::get_block_group_desc_location(&self.sb, group);
+            if blocks.last() != Some(&block) {
+                blocks.push(block);
+            }
+        }
+        let journal_inum = u32::from_le(self.sb.inner().journal_inum);
+        if self.sb.has_compat_feature(crate::consts::EXT4_FEATURE_COMPAT_HAS_JOURNAL) && journal_inum != 0 {
+            let block = self.with_inode_ref(journal_inum, |inode_ref| inode_ref.get_inode_dblk_idx(0, false))?;
+            if block != 0 {
+                blocks.push(block);
+            }
+        }
+
+        let mut pinned = Vec::new();
+        for block in blocks {
+            match self.bdev.pin_cache_block(block) {
+                Ok(()) => pinned.push(block),
+                Err(e) if e.kind() == ErrorKind::NoSpace => break,
+                Err(e) => {
+                    self.pinned_metadata = Some(pinned);
+                    return Err(e);
+                }
+            }
+        }
+        let count = pinned.len();
+        self.pinned_metadata = Some(pinned);
+        Ok(count)
+    }
+
+    /// 解除 [`pin_metadata`](Self::pin_metadata) 的固定
+    pub fn unpin_metadata(&mut self) {
+        for block in self.pinned_metadata.take().unwrap_or_default() {
+            self.bdev.unpin_cache_block(block);
+        }
+    }
+
+    /// 块组描述符的位置变化后重新固定元数据块
+    pub(super) fn repin_metadata(&mut self) -> Result<()> {
+        if self.pinned_metadata.is_some() {
+            self.pin_metadata()?;
+        }
+        Ok(())
+    }
+
     /// 刷新所有缓存的脏数据到磁盘
     ///
-    /// 该方法会将块缓存中的所有脏块写回磁盘，并调用设备的硬件刷新。
+    /// 该方法会先为延迟分配的数据分配物理块，更新 superblock 和块组描述符
+    /// 的备份，再将块缓存中的所有脏块写回磁盘，并调用设备的硬件刷新。
     ///
     /// # 返回
     ///
@@ -203,9 +580,30 @@ impl<D: BlockDevice> Ext4FileSystem<D> {
     /// fs.flush()?; // 确保所有数据写入磁盘
     /// ```
     pub fn flush(&mut self) -> Result<()> {
+        if self.sb.is_read_only() {
+            return Ok(());
+        }
+        self.flush_page_cache()?;
+        self.flush_delalloc()?;
+        self.sync_quota()?;
+        self.write_metadata_backups()?;
         self.bdev.flush()
     }
 
+    /// 写回 superblock（只写主副本）
+    pub(super) fn write_superblock(&mut self) -> Result<()> {
+        self.sb.write(&mut self.bdev)
+    }
+
+    /// 写回 superblock，并把 superblock 和块组描述符表复制到各备份块组
+    ///
+    /// 分配和释放只更新主副本，备份在 [`flush`](Self::flush) 和
+    /// [`unmount`](Self::unmount) 时统一更新
+    pub(super) fn write_metadata_backups(&mut self) -> Result<()> {
+        self.sb.write_with_backups(&mut self.bdev)?;
+        crate::block_group::write_block_group_desc_backups(&mut self.bdev, &self.sb)
+    }
+
     /// 获取 inode 引用
     ///
     /// # 参数
@@ -271,19 +669,77 @@ impl<D: BlockDevice> Ext4FileSystem<D> {
     /// ```
     pub fn open(&mut self, path: &str) -> Result<File<D>> {
         let inode_num = lookup_path(&mut self.bdev, &mut self.sb, path)?;
+        self.check_regular_file(inode_num)?;
+        let handle = self.open_inode(inode_num)?;
+        Ok(File::new(handle, self.sb.block_size()))
+    }
+
+    /// 按 `options` 打开文件，对应 `open(2)` 的 `O_*` 标志
+    ///
+    /// 父目录只解析一次；文件不存在且设置了 `create` 时，在同一次调用中
+    /// 查找并创建，不会出现“查找时不存在、创建时已被他人创建”的情况。
+    /// 返回的句柄按打开方式限制读写，`append` 模式下每次写入都追加到文件末尾。
+    ///
+    /// # 错误
+    ///
+    /// - `ErrorKind::InvalidInput` - 标志组合无效（见 [`OpenOptions`]），或者目标不是普通文件
+    /// - `ErrorKind::NotFound` - 文件不存在且没有设置 `create`
+    /// - `ErrorKind::AlreadyExists` - 设置了 `create_new` 而文件已存在
+    ///
+    /// # 示例
+    ///
+    /// ```rust,ignore
+    /// // O_WRONLY | O_CREAT | O_APPEND
+    /// let mut log = fs.open_with("/var/log/app.log", OpenOptions::new().append(true).create(true))?;
+    /// log.write(&mut fs, b"started\n")?;
+    /// log.close(&mut fs)?;
+    /// ```
+    pub fn open_with(&mut self, path: &str, options: &OpenOptions) -> Result<File<D>> {
+        options.validate()?;
+        if options.writable() {
+            self.sb.check_writable()?;
+        }
+        let (parent_path, name) = super::copy::split_parent(path)?;
+        let parent_inode = lookup_path(&mut self.bdev, &mut self.sb, parent_path)?;
+
+        let (inode_num, created) = match self.lookup_in_dir(parent_inode, name) {
+            Ok(_) if options.create_new => {
+                return Err(Error::new(ErrorKind::AlreadyExists, "File already exists"));
+            }
+            Ok(inode_num) => (inode_num, false),
+            Err(e) if e.kind() == ErrorKind::NotFound && (options.create || options.create_new) => {
+                let inode_num = self.with_alloc_undo(|fs, undo| {
+                    fs.create_file_in_steps(undo, parent_inode, name, options.mode)
+                })?;
+                (inode_num, true)
+            }
+            Err(e) => return Err(e),
+        };
+
+        self.check_regular_file(inode_num)?;
+        if options.truncate && !created && self.get_inode_ref(inode_num)?.size()? > 0 {
+            self.truncate_file(inode_num, 0)?;
+        }
+
+        let mut file = File::new(self.open_inode(inode_num)?, self.sb.block_size());
+        file.set_access(options);
+        Ok(file)
+    }
 
-        // 检查是否是普通文件
+    /// 检查 inode 是可以打开的普通文件
+    pub(super) fn check_regular_file(&mut self, inode_num: u32) -> Result<()> {
         let mut inode_ref = InodeRef::get(&mut self.bdev, &mut self.sb, inode_num)?;
         if !inode_ref.is_file()? {
             return Err(Error::new(ErrorKind::InvalidInput, "Not a regular file"));
         }
-        drop(inode_ref); // 明确释放
-
-        File::new(&mut self.bdev, &self.sb, inode_num)
+        inode_ref.check_not_encrypted()
     }
 
     /// 读取目录内容
     ///
+    /// 文件系统没有 `filetype` 特性时，条目的类型从各自的 inode 读取，
+    /// 见 [`fill_file_type`](crate::dir::fill_file_type)
+    ///
     /// # 参数
     ///
     /// * `path` - 目录路径（绝对路径）
@@ -301,17 +757,101 @@ impl<D: BlockDevice> Ext4FileSystem<D> {
     /// }
     /// ```
     pub fn read_dir(&mut self, path: &str) -> Result<Vec<DirEntry>> {
-        let inode_num = lookup_path(&mut self.bdev, &mut self.sb, path)?;
-        let mut inode_ref = InodeRef::get(&mut self.bdev, &mut self.sb, inode_num)?;
+        self.observe(FsOp::ReadDir, |fs| {
+            let inode_num = lookup_path(&mut fs.bdev, &mut fs.sb, path)?;
+            let mut inode_ref = InodeRef::get(&mut fs.bdev, &mut fs.sb, inode_num)?;
+
+            if !inode_ref.is_dir()? {
+                return Err(Error::new(ErrorKind::NotADirectory, "Not a directory"));
+            }
+
+            read_dir(&mut inode_ref)
+        })
+    }
+
+    /// 按指定顺序读取目录内容
+    ///
+    /// 与 [`read_dir`](Self::read_dir) 相同，但返回的条目按 `order` 排序。
+    /// `DirOrder::ByName` 和 `DirOrder::ByInode` 的结果与磁盘布局无关，
+    /// 可用于需要稳定输出的场景（例如对比不同工具生成的镜像）。
+    ///
+    /// # 参数
+    ///
+    /// * `path` - 目录路径（绝对路径）
+    /// * `order` - 排序方式
+    ///
+    /// # 示例
+    ///
+    /// ```rust,ignore
+    /// for entry in fs.read_dir_sorted("/etc", DirOrder::ByName)? {
+    ///     println!("{}", entry.name);
+    /// }
+    /// ```
+    pub fn read_dir_sorted(&mut self, path: &str, order: DirOrder) -> Result<Vec<DirEntry>> {
+        let mut entries = self.read_dir(path)?;
+        sort_entries(&mut entries, order);
+        Ok(entries)
+    }
+
+    /// 读取目录内容和每个条目的元数据（类似 NFS 的 READDIRPLUS）
+    ///
+    /// 与对每个条目调用 [`get_inode_attr`](Self::get_inode_attr) 的结果相同，
+    /// 但先按 inode 所在的 inode 表块对条目分组，每个 inode 表块只访问一次，
+    /// 避免列目录时对 inode 表的随机读取。条目顺序与 [`read_dir`](Self::read_dir) 相同，
+    /// 符号链接不跟随。
+    ///
+    /// # 错误
+    ///
+    /// - `ErrorKind::InvalidInput` - 路径不是目录
+    /// - `ErrorKind::Corrupted` - 打开了校验和检查且某个 inode 的校验和不匹配
+    ///
+    /// # 示例
+    ///
+    /// ```rust,ignore
+    /// for (entry, meta) in fs.read_dir_plus("/etc")? {
+    ///     println!("{} {} bytes", entry.name, meta.size);
+    /// }
+    /// ```
+    pub fn read_dir_plus(&mut self, path: &str) -> Result<Vec<(DirEntry, FileMetadata)>> {
+        let entries = self.read_dir(path)?;
+        let inode_size = self.sb.inode_size() as usize;
+
+        // inode 表块 -> (条目下标, 块内偏移)
+        let mut by_block: BTreeMap<u64, Vec<(usize, usize)>> = BTreeMap::new();
+        for (i, entry) in entries.iter().enumerate() {
+            let (block, offset) = super::inode_ref::inode_location(&mut self.bdev, &self.sb, entry.inode)?;
+            by_block.entry(block).or_default().push((i, offset));
+        }
 
-        if !inode_ref.is_dir()? {
-            return Err(Error::new(ErrorKind::InvalidInput, "Not a directory"));
+        let mut metas: Vec<Option<FileMetadata>> = alloc::vec![None; entries.len()];
+        let verify = self.sb.verify_checksums();
+        for (block_addr, slots) in by_block {
+            let sb = &self.sb;
+            Block::get(&mut self.bdev, block_addr)?.with_data(|data| {
+                for (i, offset) in slots {
+                    let inode_num = entries[i].inode;
+                    let raw = &data[offset..offset + inode_size];
+                    if verify && !crate::inode::checksum::verify_checksum_raw(sb, inode_num, raw) {
+                        return Err(Error::with_block(ErrorKind::Corrupted, "Inode checksum mismatch", block_addr));
+                    }
+                    metas[i] = Some(FileMetadata::from_raw(sb, inode_num, raw));
+                }
+                Ok(())
+            })??;
         }
 
-        read_dir(&mut inode_ref)
+        let mut metas: Vec<FileMetadata> = metas.into_iter().flatten().collect();
+        for meta in &mut metas {
+            self.fix_overflowed_nlink(meta)?;
+        }
+        Ok(entries.into_iter().zip(metas).collect())
     }
 
-    /// 获取文件元数据
+    /// 获取文件元数据（stat）
+    ///
+    /// 与 `std::fs::metadata` 相同，路径的最后一个组件是符号链接时跟随它，
+    /// 返回链接目标的元数据；不跟随时使用 [`symlink_metadata`](Self::symlink_metadata)。
+    /// 相对的链接目标从链接所在目录开始解析。
     ///
     /// # 参数
     ///
@@ -321,6 +861,11 @@ impl<D: BlockDevice> Ext4FileSystem<D> {
     ///
     /// 文件元数据
     ///
+    /// # 错误
+    ///
+    /// - `ErrorKind::NotFound` - 路径或链接目标不存在
+    /// - `ErrorKind::SymlinkLoop` - 连续跟随超过 [`MAX_SYMLINK_FOLLOW`] 次符号链接
+    ///
     /// # 示例
     ///
     /// ```rust,ignore
@@ -329,39 +874,122 @@ impl<D: BlockDevice> Ext4FileSystem<D> {
     /// println!("UID: {}, GID: {}", metadata.uid, metadata.gid);
     /// ```
     pub fn metadata(&mut self, path: &str) -> Result<FileMetadata> {
-        let inode_num = lookup_path(&mut self.bdev, &mut self.sb, path)?;
-        let inode = Inode::load(&mut self.bdev, &self.sb, inode_num)?;
+        self.metadata_with(path, FollowSymlink::Follow)
+    }
 
-        Ok(FileMetadata::from_inode(&inode, inode_num))
+    /// 获取文件元数据，不跟随最后的符号链接（lstat）
+    ///
+    /// 路径指向符号链接时返回链接本身的元数据，对应 `std::fs::symlink_metadata`。
+    ///
+    /// # 示例
+    ///
+    /// ```rust,ignore
+    /// let meta = fs.symlink_metadata("/bin/sh")?;
+    /// assert!(meta.is_symlink());
+    /// ```
+    pub fn symlink_metadata(&mut self, path: &str) -> Result<FileMetadata> {
+        self.metadata_with(path, FollowSymlink::NoFollow)
+    }
+
+    /// 获取文件元数据，由 `follow` 决定是否跟随最后的符号链接
+    ///
+    /// [`metadata`](Self::metadata) 和 [`symlink_metadata`](Self::symlink_metadata)
+    /// 分别对应 `Follow` 和 `NoFollow`
+    pub fn metadata_with(&mut self, path: &str, follow: FollowSymlink) -> Result<FileMetadata> {
+        self.observe(FsOp::Stat, |fs| {
+            let inode_num = fs.lookup_with(path, follow)?;
+            fs.get_inode_attr(inode_num)
+        })
+    }
+
+    /// 查找路径，返回 inode 编号
+    ///
+    /// `follow` 为 `Follow` 时，最后一个组件是符号链接则跟随到最终目标，
+    /// 相对的链接目标从链接所在目录开始解析。
+    ///
+    /// # 错误
+    ///
+    /// - `ErrorKind::NotFound` - 路径或链接目标不存在
+    /// - `ErrorKind::SymlinkLoop` - 连续跟随超过 [`MAX_SYMLINK_FOLLOW`] 次符号链接
+    pub fn lookup_with(&mut self, path: &str, follow: FollowSymlink) -> Result<u32> {
+        match follow {
+            FollowSymlink::Follow => self.lookup_follow(path),
+            FollowSymlink::NoFollow => lookup_path(&mut self.bdev, &mut self.sb, path),
+        }
+    }
+
+    /// 查找路径，最后一个组件是符号链接时跟随到最终目标
+    fn lookup_follow(&mut self, path: &str) -> Result<u32> {
+        let mut dir = crate::consts::EXT4_ROOT_INODE;
+        let mut path = alloc::string::String::from(path);
+
+        for _ in 0..=MAX_SYMLINK_FOLLOW {
+            let inode_num = lookup_path_at(&mut self.bdev, &mut self.sb, dir, &path)?;
+            let is_symlink = InodeRef::get(&mut self.bdev, &mut self.sb, inode_num)?
+                .with_inode(|inode| inode.is_symlink())?;
+            if !is_symlink {
+                return Ok(inode_num);
+            }
+
+            // 相对目标从链接所在的目录开始解析
+            let parent = match path.trim_end_matches('/').rfind('/') {
+                Some(pos) => &path[..=pos],
+                None => ".",
+            };
+            dir = lookup_path_at(&mut self.bdev, &mut self.sb, dir, parent)?;
+            path = self.read_symlink(inode_num)?;
+        }
+
+        Err(Error::new(ErrorKind::SymlinkLoop, "Too many levels of symbolic links"))
+    }
+
+    /// 从指定目录开始解析路径，返回 inode 编号
+    ///
+    /// 供 VFS 层实现 openat 等 *at() 调用：相对路径从 `dir_ino` 开始解析，
+    /// 绝对路径从根目录开始。`.`、`..`、连续 `/` 和尾部 `/` 的处理见
+    /// [`PathLookup::lookup_path_at`](crate::dir::PathLookup::lookup_path_at)。
+    ///
+    /// # 示例
+    ///
+    /// ```rust,ignore
+    /// let etc = fs.lookup_at(EXT4_ROOT_INODE, "etc")?;
+    /// let passwd = fs.lookup_at(etc, "./passwd")?;
+    /// let meta = fs.get_inode_attr(passwd)?;
+    /// ```
+    pub fn lookup_at(&mut self, dir_ino: u32, path: &str) -> Result<u32> {
+        self.observe(FsOp::Lookup, |fs| lookup_path_at(&mut fs.bdev, &mut fs.sb, dir_ino, path))
     }
 
     /// 检查路径是否存在
     ///
+    /// 与 [`metadata`](Self::metadata) 相同，跟随最后的符号链接，
+    /// 目标不存在的符号链接返回 `false`
+    ///
     /// # 参数
     ///
     /// * `path` - 路径（绝对路径）
     pub fn exists(&mut self, path: &str) -> bool {
-        lookup_path(&mut self.bdev, &mut self.sb, path).is_ok()
+        self.lookup_with(path, FollowSymlink::Follow).is_ok()
     }
 
-    /// 检查路径是否是目录
+    /// 检查路径是否是目录，跟随最后的符号链接
     ///
     /// # 参数
     ///
     /// * `path` - 路径（绝对路径）
     pub fn is_dir(&mut self, path: &str) -> Result<bool> {
-        let inode_num = lookup_path(&mut self.bdev, &mut self.sb, path)?;
+        let inode_num = self.lookup_with(path, FollowSymlink::Follow)?;
         let mut inode_ref = InodeRef::get(&mut self.bdev, &mut self.sb, inode_num)?;
         inode_ref.is_dir()
     }
 
-    /// 检查路径是否是普通文件
+    /// 检查路径是否是普通文件，跟随最后的符号链接
     ///
     /// # 参数
     ///
     /// * `path` - 路径（绝对路径）
     pub fn is_file(&mut self, path: &str) -> Result<bool> {
-        let inode_num = lookup_path(&mut self.bdev, &mut self.sb, path)?;
+        let inode_num = self.lookup_with(path, FollowSymlink::Follow)?;
         let mut inode_ref = InodeRef::get(&mut self.bdev, &mut self.sb, inode_num)?;
         inode_ref.is_file()
     }
@@ -517,31 +1145,13 @@ impl<D: BlockDevice> Ext4FileSystem<D> {
     /// ```
     pub fn listxattr(&mut self, path: &str) -> Result<Vec<alloc::string::String>> {
         use crate::xattr;
-        use alloc::string::String;
 
         let inode_num = lookup_path(&mut self.bdev, &mut self.sb, path)?;
 
         // 获取 InodeRef 并直接使用新的 xattr API
         let mut inode_ref = InodeRef::get(&mut self.bdev, &mut self.sb, inode_num)?;
 
-        // 调用新的 xattr API（使用 InodeRef）
-        let mut buffer = alloc::vec![0u8; 4096]; // 4KB 缓冲区
-        let len = xattr::list(&mut inode_ref, &mut buffer)?;
-
-        // 解析结果（以 \0 分隔的字符串列表）
-        let mut result = Vec::new();
-        let mut start = 0;
-        for i in 0..len {
-            if buffer[i] == 0 {
-                if i > start {
-                    let name = String::from_utf8_lossy(&buffer[start..i]).into_owned();
-                    result.push(name);
-                }
-                start = i + 1;
-            }
-        }
-
-        Ok(result)
+        Ok(xattr::iter(&mut inode_ref)?.map(|entry| entry.name).collect())
     }
 
     /// 获取扩展属性的值
@@ -570,8 +1180,9 @@ impl<D: BlockDevice> Ext4FileSystem<D> {
         // 获取 InodeRef 并直接使用新的 xattr API
         let mut inode_ref = InodeRef::get(&mut self.bdev, &mut self.sb, inode_num)?;
 
-        // 调用新的 xattr API（使用 InodeRef）
-        let mut buffer = alloc::vec![0u8; 65536]; // 64KB 缓冲区（xattr 值最大 64KB）
+        // 先查询值的长度，按需分配缓冲区
+        let size = xattr::get_size(&mut inode_ref, name)?;
+        let mut buffer = alloc::vec![0u8; size];
         let len = xattr::get(&mut inode_ref, name, &mut buffer)?;
 
         buffer.truncate(len);
@@ -657,10 +1268,91 @@ impl<D: BlockDevice> Ext4FileSystem<D> {
 
         let mut allocator = InodeAllocator::new();
         let inode_num = allocator.alloc_inode(&mut self.bdev, &mut self.sb, is_dir)?;
+        self.init_new_inode(inode_num, is_dir)?;
 
         Ok(inode_num)
     }
 
+    /// 为父目录 `parent_inode` 中的新条目分配 inode
+    ///
+    /// 按 [`FsConfig::inode_alloc`] 选择起始块组：默认策略与
+    /// [`alloc_inode`](Self::alloc_inode) 相同；Orlov 策略见
+    /// [`find_group_orlov`](crate::ialloc::find_group_orlov)
+    pub(crate) fn alloc_inode_in_dir(&mut self, parent_inode: u32, is_dir: bool) -> Result<u32> {
+        use crate::ialloc::{find_group_orlov, InodeAllocator};
+
+        let mut allocator = InodeAllocator::new();
+        if self.inode_alloc == InodeAllocPolicy::Orlov {
+            let goal = find_group_orlov(&mut self.bdev, &mut self.sb, parent_inode, is_dir)?;
+            allocator.set_last_bg_id(goal);
+        }
+        let inode_num = allocator.alloc_inode(&mut self.bdev, &mut self.sb, is_dir)?;
+        self.init_new_inode(inode_num, is_dir)?;
+        Ok(inode_num)
+    }
+
+    /// 初始化新分配 inode 的额外空间并计入配额，失败时释放该 inode
+    fn init_new_inode(&mut self, inode_num: u32, is_dir: bool) -> Result<()> {
+        let result = self
+            .init_extra_isize(inode_num)
+            .and_then(|_| self.quota_charge_new_inode(inode_num));
+        if let Err(e) = result {
+            crate::ialloc::free_inode(&mut self.bdev, &mut self.sb, inode_num, is_dir)?;
+            return Err(e);
+        }
+        Ok(())
+    }
+
+    /// 按 [`FsConfig::create_policy`] 设置父目录 `parent_inode` 中新 inode 的权限位、属主和属组
+    ///
+    /// 在 inode 的类型和权限设置之后调用，属主变化时配额用量随之转移
+    pub(super) fn apply_create_policy(&mut self, inode_num: u32, parent_inode: u32) -> Result<()> {
+        let (parent_mode, parent_gid) = InodeRef::get(&mut self.bdev, &mut self.sb, parent_inode)?
+            .with_inode(|inode| {
+                let gid = u16::from_le(inode.gid) as u32 | (u16::from_le(inode.gid_high) as u32) << 16;
+                (u16::from_le(inode.mode), gid)
+            })?;
+        let policy = self.create_policy;
+        let mut inode_ref = InodeRef::get(&mut self.bdev, &mut self.sb, inode_num)?;
+        let mode = inode_ref.with_inode(|inode| u16::from_le(inode.mode))?;
+        let (mode, gid) = policy.apply(mode, parent_mode, parent_gid);
+        inode_ref.set_mode(mode)?;
+        inode_ref.set_owner(policy.uid, gid)?;
+        inode_ref.mark_dirty()
+    }
+
+    /// 清空 inode 的额外空间（残留的扩展属性和时间戳）并设置 `i_extra_isize`
+    ///
+    /// 与内核相同取 `s_want_extra_isize`，不足 32 字节时取 32，
+    /// 这样新 inode 总能保存纳秒时间戳、创建时间和项目 ID。
+    /// 128 字节的 inode 没有额外空间，只写入校验和。
+    ///
+    /// 从未使用过的 inode 没有有效的校验和，这里不验证，之后的访问照常验证
+    fn init_extra_isize(&mut self, inode_num: u32) -> Result<()> {
+        use crate::consts::EXT4_GOOD_OLD_INODE_SIZE;
+
+        let inode_size = self.sb.inode_size() as usize;
+        let want = u16::from_le(self.sb.inner().want_extra_isize) as usize;
+        let extra_isize = want.max(32).min(inode_size.saturating_sub(EXT4_GOOD_OLD_INODE_SIZE)) as u16;
+
+        let mut inode_ref = InodeRef::get_unverified(&mut self.bdev, &mut self.sb, inode_num)?;
+        inode_ref.with_inode_raw_data_mut(|data| {
+            if inode_size <= EXT4_GOOD_OLD_INODE_SIZE {
+                return;
+            }
+            data[EXT4_GOOD_OLD_INODE_SIZE..].fill(0);
+            data[EXT4_GOOD_OLD_INODE_SIZE..EXT4_GOOD_OLD_INODE_SIZE + 2]
+                .copy_from_slice(&extra_isize.to_le_bytes());
+        })?;
+        inode_ref.mark_dirty()
+    }
+
+    /// 释放即将删除的 inode 的扩展属性块和 EA inode 引用
+    fn release_xattrs(&mut self, inode_num: u32) -> Result<()> {
+        let mut inode_ref = InodeRef::get(&mut self.bdev, &mut self.sb, inode_num)?;
+        crate::xattr::release_all(&mut inode_ref)
+    }
+
     /// 释放一个 inode
     ///
     /// 对应 lwext4 的 `ext4_fs_free_inode()`
@@ -694,6 +1386,10 @@ impl<D: BlockDevice> Ext4FileSystem<D> {
     pub fn free_inode(&mut self, inode_num: u32, is_dir: bool) -> Result<()> {
         use crate::ialloc::free_inode;
 
+        self.delalloc_discard(inode_num);
+        self.page_cache_discard(inode_num);
+        self.quota_release_inode(inode_num)?;
+
         free_inode(&mut self.bdev, &mut self.sb, inode_num, is_dir)?;
 
         Ok(())
@@ -721,7 +1417,7 @@ impl<D: BlockDevice> Ext4FileSystem<D> {
         use crate::balloc::BlockAllocator;
 
         let mut allocator = BlockAllocator::new();
-        let block_addr = allocator.alloc_block(&mut self.bdev, &mut self.sb, goal)?;
+        let Pblk(block_addr) = allocator.alloc_block(&mut self.bdev, &mut self.sb, goal)?;
 
         Ok(block_addr)
     }
@@ -767,11 +1463,29 @@ impl<D: BlockDevice> Ext4FileSystem<D> {
     /// fs.truncate_file(inode_num, 1024)?; // 截断到 1KB
     /// ```
     pub fn truncate_file(&mut self, inode_num: u32, new_size: u64) -> Result<()> {
-        use crate::extent::remove_space;
+        self.observe(FsOp::Truncate, |fs| {
+            fs.check_not_encrypted(inode_num)?;
+            fs.truncate_inode(inode_num, new_size)
+        })
+    }
+
+    /// 截断 inode 到指定大小，不检查加密
+    ///
+    /// 删除文件和目录时释放数据块使用，加密的 inode 没有密钥也可以删除
+    pub(super) fn truncate_inode(&mut self, inode_num: u32, new_size: u64) -> Result<()> {
 
         // 先获取block_size，避免借用冲突
         let block_size = self.sb.block_size() as u64;
 
+        self.delalloc_truncate(inode_num, new_size);
+        // 截断给出了确定的大小，打开的句柄中尚未提交的大小作废
+        if let Some(entry) = self.open_inodes.get_mut(inode_num) {
+            entry.pending_size = None;
+            entry.extent = None;
+        }
+        // 末尾的部分块会在磁盘上清零，缓存页先写回再丢弃
+        self.page_cache_evict(inode_num, (new_size / block_size).min(u32::MAX as u64) as u32, u32::MAX)?;
+
         let mut inode_ref = InodeRef::get(&mut self.bdev, &mut self.sb, inode_num)?;
         let old_size = inode_ref.size()?;
 
@@ -833,40 +1547,20 @@ impl<D: BlockDevice> Ext4FileSystem<D> {
                 // 重新获取 inode_ref 用于查找物理块
                 let mut inode_ref = InodeRef::get(&mut self.bdev, &mut self.sb, inode_num)?;
 
-                // 使用 get_blocks 查找逻辑块对应的物理块（不分配新块）
-                use crate::extent::get_blocks;
-                use crate::balloc::BlockAllocator;
-
-                // get_blocks 需要 &mut Superblock，但 inode_ref 已经借用了 sb
-                // 使用 unsafe 获取另一个引用（与 remove_space 相同的模式）
-                let sb_ptr = inode_ref.superblock_mut() as *mut crate::superblock::Superblock;
-                let sb_ref = unsafe { &mut *sb_ptr };
-
-                let mut allocator = BlockAllocator::new();
-                let (physical_block, _count) = get_blocks(
-                    &mut inode_ref,
-                    sb_ref,
-                    &mut allocator,
-                    last_block_num,
-                    1,
-                    false, // 不分配新块，只查找
-                )?;
+                // 查找逻辑块对应的物理块（不分配新块），空洞视为 0
+                let physical_block = match inode_ref.get_inode_dblk_idx(last_block_num, false) {
+                    Ok(block) => block,
+                    Err(e) if e.kind() == ErrorKind::NotFound => 0,
+                    Err(e) => return Err(e),
+                };
 
                 // 释放 inode_ref 以便访问 self.bdev
                 drop(inode_ref);
 
                 if physical_block != 0 {
-                    // 该块存在，读取并清零部分数据后写回
-
-                    // 读取物理块
-                    let mut block_buf = alloc::vec![0u8; block_size as usize];
-                    self.bdev.read_block(physical_block, &mut block_buf)?;
-
-                    // 清零从 offset_in_block 到块末尾的部分
-                    block_buf[offset_in_block..].fill(0);
-
-                    // 写回物理块
-                    self.bdev.write_block(physical_block, &block_buf)?;
+                    // 该块存在，在缓存中清零从 offset_in_block 到块末尾的部分
+                    let mut block = Block::get_data(&mut self.bdev, physical_block)?;
+                    block.with_data_mut(|data| data[offset_in_block..].fill(0))?;
 
                     log::debug!(
                         "[TRUNCATE] Zeroed bytes [{}, {}) in block {} (physical block {})",
@@ -909,19 +1603,9 @@ impl<D: BlockDevice> Ext4FileSystem<D> {
                 // 重新获取 inode_ref 用于 remove_space
                 let mut inode_ref = InodeRef::get(&mut self.bdev, &mut self.sb, inode_num)?;
 
-                // remove_space 需要 &mut Superblock，但 inode_ref 已经借用了 sb
-                // 这里使用 unsafe 获取 sb 的另一个可变引用
-                //
-                // 安全性保证：
-                // - inode_ref.sb 和 sb_ref 指向同一个对象
-                // - remove_space 和 inode_ref 操作的 sb 字段不冲突
-                // - 在 inode_ref 的生命周期内使用 sb_ref
-                let sb_ptr = inode_ref.superblock_mut() as *mut crate::superblock::Superblock;
-                let sb_ref = unsafe { &mut *sb_ptr };
-
-                // 调用 remove_space 释放块
+                // 调用 remove_space 释放块（按 extent 或间接块映射分派）
                 // 注意：remove_space 的 to 参数是包含的（不是左闭右开）
-                remove_space(&mut inode_ref, sb_ref, first_block_to_remove, last_block_to_remove)?;
+                inode_ref.remove_space(first_block_to_remove, last_block_to_remove)?;
 
                 log::debug!(
                     "[TRUNCATE] Successfully freed {} blocks",
@@ -963,32 +1647,27 @@ impl<D: BlockDevice> Ext4FileSystem<D> {
 
     /// 添加目录项（内部辅助方法）
     ///
-    /// # 注意
-    ///
-    /// 由于借用检查器限制，这个方法暂时标记为 TODO
-    fn add_dir_entry(&mut self, dir_inode: u32, name: &str, child_inode: u32, file_type: u8) -> Result<()> {
+    /// 块分配所需的 superblock 通过 `inode_ref` 访问
+    pub(super) fn add_dir_entry(&mut self, dir_inode: u32, name: &str, child_inode: u32, file_type: u8) -> Result<()> {
         use crate::dir::write;
 
         let mut inode_ref = InodeRef::get(&mut self.bdev, &mut self.sb, dir_inode)?;
-
-        // 安全性说明：
-        // - dir::write::add_entry 需要 &mut Superblock 但 inode_ref 已持有 &mut sb
-        // - add_entry 只读取 superblock 的一些字段（block_size 等），不会修改
-        // - 使用 unsafe 指针绕过借用检查器，确保不会产生数据竞争
-        let sb_ptr = inode_ref.superblock_mut() as *mut Superblock;
-        let sb_ref = unsafe { &mut *sb_ptr };
-
-        write::add_entry(&mut inode_ref, sb_ref, name, child_inode, file_type)?;
+        write::add_entry(&mut inode_ref, name, child_inode, file_type)?;
 
         Ok(())
     }
 
+    /// 检查目录中还没有名为 `name` 的目录项（内部辅助方法）
+    fn check_name_free(&mut self, dir_inode: u32, name: &str) -> Result<()> {
+        match self.lookup_in_dir(dir_inode, name) {
+            Ok(_) => Err(Error::new(ErrorKind::AlreadyExists, "Entry already exists")),
+            Err(e) if e.kind() == ErrorKind::NotFound => Ok(()),
+            Err(e) => Err(e),
+        }
+    }
+
     /// 删除目录项（内部辅助方法）
-    ///
-    /// # 注意
-    ///
-    /// 使用 unsafe 指针绕过借用检查器的限制
-    fn remove_dir_entry(&mut self, dir_inode: u32, name: &str) -> Result<()> {
+    pub(super) fn remove_dir_entry(&mut self, dir_inode: u32, name: &str) -> Result<()> {
         use crate::dir::write;
 
         let mut inode_ref = InodeRef::get(&mut self.bdev, &mut self.sb, dir_inode)?;
@@ -1013,19 +1692,44 @@ impl<D: BlockDevice> Ext4FileSystem<D> {
     ///
     /// 新文件的 inode 编号
     ///
+    /// # 错误
+    ///
+    /// - `ErrorKind::AlreadyExists` - 父目录中已有同名的目录项
+    ///
     /// # 示例
     ///
     /// ```rust,ignore
     /// let inode_num = fs.create_file("/tmp", "test.txt", 0o644)?;
     /// ```
     pub fn create_file(&mut self, parent_path: &str, name: &str, mode: u16) -> Result<u32> {
-        use crate::{consts::*, dir::write::{self, EXT4_DE_REG_FILE}, extent::tree_init};
+        self.observe(FsOp::Create, |fs| fs.with_alloc_undo(|fs, undo| fs.create_file_steps(undo, parent_path, name, mode)))
+    }
+
+    /// `create_file` 的各个步骤，分配记录到 `undo` 中
+    fn create_file_steps(&mut self, undo: &mut AllocUndo, parent_path: &str, name: &str, mode: u16) -> Result<u32> {
+        let parent_inode = lookup_path(&mut self.bdev, &mut self.sb, parent_path)?;
+        self.create_file_in_steps(undo, parent_inode, name, mode)
+    }
+
+    /// 在已解析的父目录中创建普通文件，分配记录到 `undo` 中
+    pub(super) fn create_file_in_steps(
+        &mut self,
+        undo: &mut AllocUndo,
+        parent_inode: u32,
+        name: &str,
+        mode: u16,
+    ) -> Result<u32> {
+        use crate::{consts::*, dir::write::EXT4_DE_REG_FILE};
+
+        self.check_name_free(parent_inode, name)?;
 
         // 1. 分配新 inode
-        let inode_num = self.alloc_inode(false)?;
+        let inode_num = self.alloc_inode_in_dir(parent_inode, false)?;
+        undo.inode(inode_num, false);
 
         // 2. 初始化 inode
         {
+            let use_extents = self.use_extents;
             let mut inode_ref = InodeRef::get(&mut self.bdev, &mut self.sb, inode_num)?;
 
             // 设置文件模式（类型 + 权限）
@@ -1050,23 +1754,16 @@ impl<D: BlockDevice> Ext4FileSystem<D> {
                 inode.mtime = now.to_le();
             })?;
 
-            // 设置 EXTENTS 标志
-            inode_ref.with_inode_mut(|inode| {
-                let flags = u32::from_le(inode.flags);
-                inode.flags = (flags | EXT4_INODE_FLAG_EXTENTS).to_le();
-            })?;
-
-            // 初始化 extent 树
-            tree_init(&mut inode_ref)?;
+            // 初始化块映射（extent 树或间接块）
+            init_block_map(&mut inode_ref, use_extents)?;
 
             inode_ref.mark_dirty()?;
             // inode_ref drop 时自动写回
         }
 
-        // 3. 查找父目录并添加条目
-        let parent_inode = lookup_path(&mut self.bdev, &mut self.sb, parent_path)?;
+        self.apply_create_policy(inode_num, parent_inode)?;
 
-        // 4. 添加到父目录（通过辅助方法避免借用冲突）
+        // 3. 添加到父目录（通过辅助方法避免借用冲突）
         self.add_dir_entry(parent_inode, name, inode_num, EXT4_DE_REG_FILE)?;
 
         Ok(inode_num)
@@ -1084,22 +1781,72 @@ impl<D: BlockDevice> Ext4FileSystem<D> {
     ///
     /// 新目录的 inode 编号
     ///
+    /// # 错误
+    ///
+    /// - `ErrorKind::AlreadyExists` - 父目录中已有同名的目录项
+    /// - `ErrorKind::TooManyLinks` - 父目录是链接计数已达 [`EXT4_LINK_MAX`](crate::consts::EXT4_LINK_MAX)
+    ///   的线性目录；HTree 目录的链接计数改为 1 并设置 `DIR_NLINK` 特性
+    ///
     /// # 示例
     ///
     /// ```rust,ignore
     /// let inode_num = fs.create_dir("/tmp", "mydir", 0o755)?;
     /// ```
     pub fn create_dir(&mut self, parent_path: &str, name: &str, mode: u16) -> Result<u32> {
-        use crate::{consts::*, dir::write::{self, EXT4_DE_DIR}, extent::tree_init};
+        self.observe(FsOp::Create, |fs| fs.with_alloc_undo(|fs, undo| fs.create_dir_steps(undo, parent_path, name, mode)))
+    }
 
-        // 1. 分配新 inode
-        let inode_num = self.alloc_inode(true)?;
+    /// 初始化新目录的内容并将其链接计数设为 2
+    ///
+    /// 按 [`FsConfig::index_new_dirs`] 选择线性或 HTree 布局，见
+    /// [`dir_init`](crate::dir::write::dir_init)。
+    /// 与内核一样，大小写不敏感目录的子目录继承 `EXT4_CASEFOLD_FL` 标志
+    fn init_new_dir(&mut self, inode_num: u32, parent_inode: u32) -> Result<()> {
+        let indexed = self.index_new_dirs;
+        let casefolded = {
+            let mut parent_ref = InodeRef::get(&mut self.bdev, &mut self.sb, parent_inode)?;
+            crate::dir::casefold::is_casefolded(&mut parent_ref)?
+        };
+        let mut inode_ref = InodeRef::get(&mut self.bdev, &mut self.sb, inode_num)?;
+        crate::dir::write::dir_init(&mut inode_ref, parent_inode, indexed)?;
+        inode_ref.with_inode_mut(|inode| {
+            inode.links_count = 2u16.to_le();
+            if casefolded {
+                let flags = u32::from_le(inode.flags);
+                inode.flags = (flags | crate::consts::EXT4_INODE_FLAG_CASEFOLD).to_le();
+            }
+        })?;
+        inode_ref.mark_dirty()
+    }
 
-        // 2. 查找父目录 inode
+    /// `create_dir` 的各个步骤，分配记录到 `undo` 中
+    fn create_dir_steps(&mut self, undo: &mut AllocUndo, parent_path: &str, name: &str, mode: u16) -> Result<u32> {
         let parent_inode = lookup_path(&mut self.bdev, &mut self.sb, parent_path)?;
+        self.create_dir_in_steps(undo, parent_inode, name, mode)
+    }
+
+    /// 在已解析的父目录中创建目录，分配记录到 `undo` 中
+    pub(super) fn create_dir_in_steps(
+        &mut self,
+        undo: &mut AllocUndo,
+        parent_inode: u32,
+        name: &str,
+        mode: u16,
+    ) -> Result<u32> {
+        use crate::{consts::*, dir::write::EXT4_DE_DIR};
+
+        self.check_name_free(parent_inode, name)?;
+
+        // 1. 检查父目录还能增加子目录
+        InodeRef::get(&mut self.bdev, &mut self.sb, parent_inode)?.check_dir_link_max()?;
+
+        // 2. 分配新 inode
+        let inode_num = self.alloc_inode_in_dir(parent_inode, true)?;
+        undo.inode(inode_num, true);
 
         // 3. 初始化目录 inode
         {
+            let use_extents = self.use_extents;
             let mut inode_ref = InodeRef::get(&mut self.bdev, &mut self.sb, inode_num)?;
 
             // 设置目录模式（类型 + 权限）
@@ -1111,11 +1858,6 @@ impl<D: BlockDevice> Ext4FileSystem<D> {
             // 设置初始大小为 0（目录项会自动增长）
             inode_ref.set_size(0)?;
 
-            // 设置链接计数为 2（自己 + "." 条目）
-            inode_ref.with_inode_mut(|inode| {
-                inode.links_count = 2u16.to_le();
-            })?;
-
             // 设置时间戳
             let now = 0u32; // TODO: 获取当前时间
             inode_ref.with_inode_mut(|inode| {
@@ -1124,34 +1866,27 @@ impl<D: BlockDevice> Ext4FileSystem<D> {
                 inode.mtime = now.to_le();
             })?;
 
-            // 设置 EXTENTS 标志
-            inode_ref.with_inode_mut(|inode| {
-                let flags = u32::from_le(inode.flags);
-                inode.flags = (flags | EXT4_INODE_FLAG_EXTENTS).to_le();
-            })?;
-
-            // 初始化 extent 树
-            tree_init(&mut inode_ref)?;
+            // 初始化块映射（extent 树或间接块）
+            init_block_map(&mut inode_ref, use_extents)?;
 
             inode_ref.mark_dirty()?;
             // inode_ref drop 时自动写回
         }
 
-        // 4. 添加 "." 和 ".." 条目到新目录
-        self.add_dir_entry(inode_num, ".", inode_num, EXT4_DE_DIR)?;
-        self.add_dir_entry(inode_num, "..", parent_inode, EXT4_DE_DIR)?;
+        self.apply_create_policy(inode_num, parent_inode)?;
+
+        // 4. 初始化 "." 和 ".." 条目（链接计数为 2：自己 + "." 条目）
+        self.init_new_dir(inode_num, parent_inode)?;
 
         // 5. 添加到父目录
         self.add_dir_entry(parent_inode, name, inode_num, EXT4_DE_DIR)?;
+        undo.entry(parent_inode, name);
 
         // 6. 增加父目录的链接计数（因为新目录的 ".." 指向父目录）
         {
             let mut parent_inode_ref = InodeRef::get(&mut self.bdev, &mut self.sb, parent_inode)?;
 
-            parent_inode_ref.with_inode_mut(|inode| {
-                let links = u16::from_le(inode.links_count);
-                inode.links_count = (links + 1).to_le();
-            })?;
+            parent_inode_ref.inc_dir_links()?;
 
             parent_inode_ref.mark_dirty()?;
         }
@@ -1190,13 +1925,11 @@ impl<D: BlockDevice> Ext4FileSystem<D> {
     /// 硬链接与原文件共享相同的 inode 和数据块，修改任一文件都会影响另一个。
     /// 只有当所有硬链接都被删除后，文件数据才会被真正释放。
     pub fn flink(&mut self, src_path: &str, dst_dir: &str, dst_name: &str) -> Result<()> {
-        use crate::dir::write::EXT4_DE_REG_FILE;
-
         // 1. 查找源文件 inode
         let src_inode = lookup_path(&mut self.bdev, &mut self.sb, src_path)?;
 
         // 2. 验证源是普通文件（不能对目录创建硬链接）
-        {
+        let file_type = {
             let mut inode_ref = InodeRef::get(&mut self.bdev, &mut self.sb, src_inode)?;
             if !inode_ref.is_file()? {
                 return Err(Error::new(
@@ -1204,7 +1937,8 @@ impl<D: BlockDevice> Ext4FileSystem<D> {
                     "Cannot create hard link to non-regular file",
                 ));
             }
-        }
+            inode_ref.de_type()?
+        };
 
         // 3. 查找目标目录 inode
         let dst_dir_inode = lookup_path(&mut self.bdev, &mut self.sb, dst_dir)?;
@@ -1220,7 +1954,7 @@ impl<D: BlockDevice> Ext4FileSystem<D> {
         }
 
         // 5. 在目标目录添加新的目录项（指向相同的 inode）
-        self.add_dir_entry(dst_dir_inode, dst_name, src_inode, EXT4_DE_REG_FILE)?;
+        self.add_dir_entry(dst_dir_inode, dst_name, src_inode, file_type)?;
 
         Ok(())
     }
@@ -1252,16 +1986,32 @@ impl<D: BlockDevice> Ext4FileSystem<D> {
     /// fs.fsymlink("/etc/passwd", "/tmp", "link")?;
     /// ```
     pub fn fsymlink(&mut self, target: &str, link_dir: &str, link_name: &str) -> Result<u32> {
-        use crate::{consts::*, dir::write::EXT4_DE_SYMLINK, extent::tree_init};
+        self.observe(FsOp::Create, |fs| fs.with_alloc_undo(|fs, undo| fs.fsymlink_steps(undo, target, link_dir, link_name)))
+    }
+
+    /// `fsymlink` 的各个步骤，分配记录到 `undo` 中
+    fn fsymlink_steps(&mut self, undo: &mut AllocUndo, target: &str, link_dir: &str, link_name: &str) -> Result<u32> {
+        let dir_inode = lookup_path(&mut self.bdev, &mut self.sb, link_dir)?;
+        self.fsymlink_in_steps(undo, target, dir_inode, link_name)
+    }
+
+    /// 在已解析的目录中创建符号链接，分配记录到 `undo` 中
+    pub(super) fn fsymlink_in_steps(
+        &mut self,
+        undo: &mut AllocUndo,
+        target: &str,
+        dir_inode: u32,
+        link_name: &str,
+    ) -> Result<u32> {
+        use crate::{consts::*, dir::write::EXT4_DE_SYMLINK};
 
         // 1. 分配新 inode
-        let inode_num = self.alloc_inode(false)?;
-
-        // 提取 block_size（避免借用冲突）
-        let block_size = self.sb.block_size();
+        let inode_num = self.alloc_inode_in_dir(dir_inode, false)?;
+        undo.inode(inode_num, false);
 
         // 2. 初始化符号链接 inode
         {
+            let use_extents = self.use_extents;
             let mut inode_ref = InodeRef::get(&mut self.bdev, &mut self.sb, inode_num)?;
 
             // 设置符号链接类型和权限
@@ -1297,14 +2047,8 @@ impl<D: BlockDevice> Ext4FileSystem<D> {
                 })?;
             } else {
                 // 慢速符号链接：需要分配块存储
-                // 设置 EXTENTS 标志
-                inode_ref.with_inode_mut(|inode| {
-                    let flags = u32::from_le(inode.flags);
-                    inode.flags = (flags | EXT4_INODE_FLAG_EXTENTS).to_le();
-                })?;
-
-                // 初始化 extent 树
-                tree_init(&mut inode_ref)?;
+                // 初始化块映射（extent 树或间接块）
+                init_block_map(&mut inode_ref, use_extents)?;
 
                 // 分配块并写入目标路径
                 let block_addr = inode_ref.get_inode_dblk_idx(0, true)?;
@@ -1318,13 +2062,16 @@ impl<D: BlockDevice> Ext4FileSystem<D> {
                 drop(inode_ref);
 
                 // 写入目标路径到块
-                let mut block_buf = alloc::vec![0u8; block_size as usize];
-                block_buf[..target_bytes.len()].copy_from_slice(target_bytes);
-                self.bdev.write_block(block_addr, &block_buf)?;
+                let mut block = Block::get_data_noread(&mut self.bdev, block_addr)?;
+                block.with_data_mut(|data| {
+                    data.fill(0);
+                    data[..target_bytes.len()].copy_from_slice(target_bytes);
+                })?;
+                drop(block);
 
                 // 重新获取 inode_ref 以便继续（实际上已经不需要了）
                 // return 会退出，所以这里直接返回
-                let dir_inode = lookup_path(&mut self.bdev, &mut self.sb, link_dir)?;
+                self.apply_create_policy(inode_num, dir_inode)?;
                 self.add_dir_entry(dir_inode, link_name, inode_num, EXT4_DE_SYMLINK)?;
                 return Ok(inode_num);
             }
@@ -1332,8 +2079,9 @@ impl<D: BlockDevice> Ext4FileSystem<D> {
             inode_ref.mark_dirty()?;
         }
 
+        self.apply_create_policy(inode_num, dir_inode)?;
+
         // 3. 在目录中添加符号链接条目
-        let dir_inode = lookup_path(&mut self.bdev, &mut self.sb, link_dir)?;
         self.add_dir_entry(dir_inode, link_name, inode_num, EXT4_DE_SYMLINK)?;
 
         Ok(inode_num)
@@ -1361,13 +2109,14 @@ impl<D: BlockDevice> Ext4FileSystem<D> {
     /// println!("Link points to: {}", target);
     /// ```
     pub fn readlink(&mut self, link_path: &str) -> Result<alloc::string::String> {
-        use crate::consts::*;
-
         // 1. 查找符号链接 inode
         let inode_num = lookup_path(&mut self.bdev, &mut self.sb, link_path)?;
+        self.read_symlink(inode_num)
+    }
 
-        // 提取 block_size（避免借用冲突）
-        let block_size = self.sb.block_size();
+    /// 读取符号链接 inode 的目标路径
+    pub(super) fn read_symlink(&mut self, inode_num: u32) -> Result<alloc::string::String> {
+        use crate::consts::*;
 
         let mut inode_ref = InodeRef::get(&mut self.bdev, &mut self.sb, inode_num)?;
 
@@ -1376,6 +2125,7 @@ impl<D: BlockDevice> Ext4FileSystem<D> {
         if (mode & EXT4_INODE_MODE_TYPE_MASK) != EXT4_INODE_MODE_SOFTLINK {
             return Err(Error::new(ErrorKind::InvalidInput, "Not a symlink"));
         }
+        inode_ref.check_not_encrypted()?;
 
         let size = inode_ref.size()? as usize;
         if size == 0 {
@@ -1401,9 +2151,8 @@ impl<D: BlockDevice> Ext4FileSystem<D> {
             // drop inode_ref，然后读块
             drop(inode_ref);
 
-            let mut block_buf = alloc::vec![0u8; block_size as usize];
-            self.bdev.read_block(block_addr, &mut block_buf)?;
-            block_buf[..size].to_vec()
+            let mut block = Block::get_data(&mut self.bdev, block_addr)?;
+            block.with_data(|data| data[..size].to_vec())?
         };
 
         alloc::string::String::from_utf8(target_bytes)
@@ -1423,6 +2172,11 @@ impl<D: BlockDevice> Ext4FileSystem<D> {
     /// fs.remove_file("/tmp", "test.txt")?;
     /// ```
     pub fn remove_file(&mut self, parent_path: &str, name: &str) -> Result<()> {
+        self.observe(FsOp::Remove, |fs| fs.remove_file_steps(parent_path, name))
+    }
+
+    /// `remove_file` 的各个步骤
+    fn remove_file_steps(&mut self, parent_path: &str, name: &str) -> Result<()> {
         use crate::consts::{EXT4_INODE_MODE_TYPE_MASK, EXT4_INODE_MODE_SOFTLINK};
 
         // 1. 查找父目录
@@ -1442,7 +2196,7 @@ impl<D: BlockDevice> Ext4FileSystem<D> {
             let is_dir = inode_ref.is_dir()?;
             if is_dir {
                 return Err(Error::new(
-                    ErrorKind::InvalidInput,
+                    ErrorKind::IsADirectory,
                     "Cannot remove directory with remove_file (use remove_dir)",
                 ));
             }
@@ -1474,12 +2228,14 @@ impl<D: BlockDevice> Ext4FileSystem<D> {
             (links == 0, is_fast)
         };
 
-        // 6. 如果链接计数为 0，释放 inode 和数据块
-        if should_free {
+        // 6. 如果链接计数为 0，释放 inode 和数据块；仍然打开时推迟到最后一次关闭
+        if should_free && !self.defer_unlinked(file_inode) {
+            self.release_xattrs(file_inode)?;
+
             // 快速符号链接没有数据块，跳过截断
             if !is_fast_symlink {
                 // 先截断文件以释放所有数据块
-                self.truncate_file(file_inode, 0)?;
+                self.truncate_inode(file_inode, 0)?;
             }
 
             // 释放 inode
@@ -1506,6 +2262,11 @@ impl<D: BlockDevice> Ext4FileSystem<D> {
     /// fs.remove_dir("/tmp", "mydir")?;
     /// ```
     pub fn remove_dir(&mut self, parent_path: &str, name: &str) -> Result<()> {
+        self.observe(FsOp::Remove, |fs| fs.remove_dir_steps(parent_path, name))
+    }
+
+    /// `remove_dir` 的各个步骤
+    fn remove_dir_steps(&mut self, parent_path: &str, name: &str) -> Result<()> {
         use crate::dir::iterator::DirIterator;
 
         // 1. 查找父目录
@@ -1524,7 +2285,7 @@ impl<D: BlockDevice> Ext4FileSystem<D> {
             let mut inode_ref = InodeRef::get(&mut self.bdev, &mut self.sb, dir_inode)?;
             if !inode_ref.is_dir()? {
                 return Err(Error::new(
-                    ErrorKind::InvalidInput,
+                    ErrorKind::NotADirectory,
                     "Not a directory",
                 ));
             }
@@ -1564,17 +2325,16 @@ impl<D: BlockDevice> Ext4FileSystem<D> {
             let mut parent_inode_ref = InodeRef::get(&mut self.bdev, &mut self.sb, parent_inode)?;
 
             // 减少父目录的链接计数（因为删除了指向父目录的 ".." 条目）
-            parent_inode_ref.with_inode_mut(|inode| {
-                let links = u16::from_le(inode.links_count);
-                inode.links_count = (links.saturating_sub(1)).to_le();
-            })?;
+            parent_inode_ref.dec_dir_links()?;
 
             parent_inode_ref.mark_dirty()?;
         }
 
-        // 6. 释放目录 inode 和数据块
+        // 6. 释放目录 inode、扩展属性和数据块；与内核的 clear_nlink() 一样先清零链接计数
+        InodeRef::get(&mut self.bdev, &mut self.sb, dir_inode)?.with_inode_mut(|inode| inode.links_count = 0)?;
+        self.release_xattrs(dir_inode)?;
         // 先截断以释放数据块
-        self.truncate_file(dir_inode, 0)?;
+        self.truncate_inode(dir_inode, 0)?;
 
         // 释放 inode
         self.free_inode(dir_inode, true)?;
@@ -1591,6 +2351,11 @@ impl<D: BlockDevice> Ext4FileSystem<D> {
     /// * `new_parent_path` - 新的父目录路径
     /// * `new_name` - 新名称
     ///
+    /// # 错误
+    ///
+    /// - `ErrorKind::InvalidInput` - 目录将被移动到自己的子树中
+    /// - `ErrorKind::TooManyLinks` - 新父目录是链接计数已达上限的线性目录
+    ///
     /// # 示例
     ///
     /// ```rust,ignore
@@ -1604,7 +2369,18 @@ impl<D: BlockDevice> Ext4FileSystem<D> {
         new_parent_path: &str,
         new_name: &str,
     ) -> Result<()> {
-        use crate::dir::write::{EXT4_DE_DIR, EXT4_DE_REG_FILE};
+        self.observe(FsOp::Rename, |fs| fs.rename_steps(old_parent_path, old_name, new_parent_path, new_name))
+    }
+
+    /// `rename` 的各个步骤
+    fn rename_steps(
+        &mut self,
+        old_parent_path: &str,
+        old_name: &str,
+        new_parent_path: &str,
+        new_name: &str,
+    ) -> Result<()> {
+        use crate::dir::write::EXT4_DE_DIR;
 
         // 1. 查找旧父目录
         let old_parent_inode = lookup_path(&mut self.bdev, &mut self.sb, old_parent_path)?;
@@ -1623,15 +2399,17 @@ impl<D: BlockDevice> Ext4FileSystem<D> {
         // 4. 获取文件类型
         let (is_dir, file_type) = {
             let mut inode_ref = InodeRef::get(&mut self.bdev, &mut self.sb, target_inode)?;
-            let is_dir = inode_ref.is_dir()?;
-            let file_type = if is_dir {
-                EXT4_DE_DIR
-            } else {
-                EXT4_DE_REG_FILE
-            };
-            (is_dir, file_type)
+            (inode_ref.is_dir()?, inode_ref.de_type()?)
         };
 
+        // 目录不能移动到自己的子树中，移动到其他目录时新父目录还要能增加子目录
+        if is_dir {
+            self.check_rename_loop(target_inode, new_parent_inode)?;
+            if old_parent_inode != new_parent_inode {
+                InodeRef::get(&mut self.bdev, &mut self.sb, new_parent_inode)?.check_dir_link_max()?;
+            }
+        }
+
         // 5. 在新父目录添加条目
         self.add_dir_entry(new_parent_inode, new_name, target_inode, file_type)?;
 
@@ -1640,10 +2418,7 @@ impl<D: BlockDevice> Ext4FileSystem<D> {
             let mut new_parent_inode_ref =
                 InodeRef::get(&mut self.bdev, &mut self.sb, new_parent_inode)?;
 
-            new_parent_inode_ref.with_inode_mut(|inode| {
-                let links = u16::from_le(inode.links_count);
-                inode.links_count = (links + 1).to_le();
-            })?;
+            new_parent_inode_ref.inc_dir_links()?;
             new_parent_inode_ref.mark_dirty()?;
         }
 
@@ -1655,10 +2430,7 @@ impl<D: BlockDevice> Ext4FileSystem<D> {
             let mut old_parent_inode_ref =
                 InodeRef::get(&mut self.bdev, &mut self.sb, old_parent_inode)?;
 
-            old_parent_inode_ref.with_inode_mut(|inode| {
-                let links = u16::from_le(inode.links_count);
-                inode.links_count = (links.saturating_sub(1)).to_le();
-            })?;
+            old_parent_inode_ref.dec_dir_links()?;
             old_parent_inode_ref.mark_dirty()?;
         }
 
@@ -1726,8 +2498,23 @@ impl<D: BlockDevice> Ext4FileSystem<D> {
     /// println!("Read {} bytes", n);
     /// ```
     pub fn read_at_inode(&mut self, inode_num: u32, buf: &mut [u8], offset: u64) -> Result<usize> {
+        self.observe(FsOp::Read, |fs| fs.read_at_inode_steps(inode_num, buf, offset))
+    }
+
+    /// `read_at_inode` 的各个步骤
+    fn read_at_inode_steps(&mut self, inode_num: u32, buf: &mut [u8], offset: u64) -> Result<usize> {
+        // 页缓存命中时不必查询 extent 树
+        if self.page_cache.is_some() {
+            self.check_not_encrypted(inode_num)?;
+            if let Some(n) = self.page_cache_read(inode_num, buf, offset)? {
+                self.delalloc_overlay_read(inode_num, offset, &mut buf[..n]);
+                return Ok(n);
+            }
+        }
+
         // ✅ 使用 InodeRef 的辅助方法，保证数据一致性
         let mut inode_ref = InodeRef::get(&mut self.bdev, &mut self.sb, inode_num)?;
+        inode_ref.check_not_encrypted()?;
 
         // 检查 EOF
         let file_size = inode_ref.size()?;
@@ -1735,7 +2522,12 @@ impl<D: BlockDevice> Ext4FileSystem<D> {
             return Ok(0); // EOF
         }
 
-        inode_ref.read_extent_file(offset, buf)
+        let n = inode_ref.read_extent_file(offset, buf)?;
+        drop(inode_ref);
+
+        // 延迟分配的数据尚未落盘，用缓存页覆盖
+        self.delalloc_overlay_read(inode_num, offset, &mut buf[..n]);
+        Ok(n)
     }
 
     /// 向指定 inode 的指定偏移量写入数据
@@ -1762,9 +2554,16 @@ impl<D: BlockDevice> Ext4FileSystem<D> {
     /// println!("Wrote {} bytes", n);
     /// ```
     pub fn write_at_inode(&mut self, inode_num: u32, buf: &[u8], offset: u64) -> Result<usize> {
+        self.observe(FsOp::Write, |fs| fs.write_at_inode_steps(inode_num, buf, offset))
+    }
+
+    /// `write_at_inode` 的各个步骤
+    fn write_at_inode_steps(&mut self, inode_num: u32, buf: &[u8], offset: u64) -> Result<usize> {
         if buf.is_empty() {
             return Ok(0);
         }
+        self.sb.check_writable()?;
+        self.check_not_encrypted(inode_num)?;
 
         let block_size = self.sb.block_size() as u64;
         let logical_block = (offset / block_size) as u32;
@@ -1774,6 +2573,15 @@ impl<D: BlockDevice> Ext4FileSystem<D> {
         let remaining_in_block = block_size as usize - offset_in_block;
         let write_len = buf.len().min(remaining_in_block);
 
+        if let Some((n, _)) = self.page_cache_write(inode_num, &buf[..write_len], offset)? {
+            return Ok(n);
+        }
+        if self.delalloc.is_some() {
+            if let Some((n, _)) = self.delalloc_write(inode_num, &buf[..write_len], offset, false)? {
+                return Ok(n);
+            }
+        }
+
         // 🚀 性能优化：只获取一次 InodeRef，避免重复的 inode 块查找
         let mut inode_ref = InodeRef::get(&mut self.bdev, &mut self.sb, inode_num)?;
 
@@ -1789,26 +2597,28 @@ impl<D: BlockDevice> Ext4FileSystem<D> {
                 "Failed to allocate block for write",
             ));
         }
+        #[cfg(feature = "journal")]
+        if let Some(journal) = self.journal.as_mut() {
+            journal.add_data_blocks(physical_block, 1);
+        }
 
         // 通过 InodeRef 访问 bdev（避免释放 InodeRef）
         let bdev = inode_ref.bdev_mut();
 
         // 🚀 性能优化：全块写入时跳过读取
-        let mut block_buf = alloc::vec![0u8; block_size as usize];
         let is_full_block_write = offset_in_block == 0 && write_len == block_size as usize;
-
-        if !is_full_block_write {
+        let mut block = if is_full_block_write {
+            Block::get_data_noread(bdev, physical_block)?
+        } else {
             // 部分块写入：需要先读取
-            bdev.read_block(physical_block, &mut block_buf)?;
-        }
-        // 全块写入：跳过读取，直接覆盖（block_buf 已初始化为 0）
-
-        // 在块内写入数据
-        block_buf[offset_in_block..offset_in_block + write_len]
-            .copy_from_slice(&buf[..write_len]);
+            Block::get_data(bdev, physical_block)?
+        };
 
-        // 写回块
-        bdev.write_block(physical_block, &block_buf)?;
+        // 直接在缓存块内写入数据
+        block.with_data_mut(|data| {
+            data[offset_in_block..offset_in_block + write_len].copy_from_slice(&buf[..write_len]);
+        })?;
+        drop(block);
 
         // 更新文件大小（如果写入超过了文件末尾）
         let new_end = offset + write_len as u64;
@@ -1842,9 +2652,63 @@ impl<D: BlockDevice> Ext4FileSystem<D> {
     /// - 100000块写入：write_at_inode_batch只需要1次InodeRef获取
     ///
     /// 预期性能提升：2-3倍
+    ///
+    /// 未映射的区域按剩余写入量一次分配整段，物理上连续的整块合并为
+    /// 一次设备写入（每次最多 `MAX_WRITE_RUN` 块）
+    ///
+    /// 启用延迟分配时，写入空洞的数据先缓存，到 [`flush`](Self::flush) 时才分配
     pub fn write_at_inode_batch(&mut self, inode_num: u32, buf: &[u8], offset: u64) -> Result<usize> {
+        self.write_at_inode_sized(inode_num, buf, offset, false).map(|(n, _)| n)
+    }
+
+    /// 写入数据，可选地推迟 i_size 更新
+    ///
+    /// `defer_size` 为真时，只有本次写入本来就会修改 inode（分配了块）时
+    /// 才顺带更新 i_size，否则由调用者记住新大小稍后提交，
+    /// 避免连续小追加反复弄脏 inode 表块（见 [`File`](super::File)）
+    ///
+    /// # 返回
+    ///
+    /// 写入的字节数，以及 i_size 是否已经覆盖本次写入的末尾
+    pub(super) fn write_at_inode_sized(
+        &mut self,
+        inode_num: u32,
+        buf: &[u8],
+        offset: u64,
+        defer_size: bool,
+    ) -> Result<(usize, bool)> {
         if buf.is_empty() {
-            return Ok(0);
+            return Ok((0, true));
+        }
+        // 页缓存和延迟分配只写内存，必须在这里拒绝
+        self.sb.check_writable()?;
+        self.check_not_encrypted(inode_num)?;
+
+        let mut written = self.page_cache_write(inode_num, buf, offset)?;
+        if written.is_none() && self.delalloc.is_some() {
+            written = self.delalloc_write(inode_num, buf, offset, defer_size)?;
+        }
+        let written = match written {
+            Some(written) => written,
+            None => self.write_at_inode_direct(inode_num, buf, offset, defer_size)?,
+        };
+
+        self.commit_if_journal_full()?;
+        Ok(written)
+    }
+
+    /// 立即分配并写入设备（不经过延迟分配）
+    ///
+    /// `defer_size` 的含义见 [`write_at_inode_sized`](Self::write_at_inode_sized)
+    pub(super) fn write_at_inode_direct(
+        &mut self,
+        inode_num: u32,
+        buf: &[u8],
+        offset: u64,
+        defer_size: bool,
+    ) -> Result<(usize, bool)> {
+        if buf.is_empty() {
+            return Ok((0, true));
         }
 
         let block_size = self.sb.block_size() as u64;
@@ -1852,55 +2716,96 @@ impl<D: BlockDevice> Ext4FileSystem<D> {
         // 🚀 关键优化：只获取一次 InodeRef，处理所有块
         let mut inode_ref = InodeRef::get(&mut self.bdev, &mut self.sb, inode_num)?;
         let current_size = inode_ref.size()?;
+        // 打开的 inode 共享映射缓存，另一个句柄刚写过的 extent 不必重新查找
+        let shared = self.open_inodes.get_mut(inode_num);
+        if let Some(extent) = shared.as_ref().and_then(|entry| entry.extent) {
+            inode_ref.set_block_map_cache(Some(extent));
+        }
 
         let mut bytes_written = 0;
         let mut current_offset = offset;
 
-        // 🚀 性能优化：复用块缓冲区，避免循环内的重复分配
-        let mut block_buf = alloc::vec![0u8; block_size as usize];
-
         while bytes_written < buf.len() {
             let logical_block = (current_offset / block_size) as u32;
             let offset_in_block = (current_offset % block_size) as usize;
             let remaining_in_block = block_size as usize - offset_in_block;
             let write_len = (buf.len() - bytes_written).min(remaining_in_block);
 
+            // 优化：全块写入时跳过读取
+            let is_full_block = offset_in_block == 0 && write_len == block_size as usize;
+
+            if is_full_block {
+                // 按剩余写入量一次映射/分配整段（包括末尾的部分块），
+                // 大块追加只产生少量 extent 插入
+                let remaining = (buf.len() - bytes_written) as u64;
+                let full_blocks = (remaining / block_size) as u32;
+                let spanned = remaining.div_ceil(block_size).min(u32::MAX as u64) as u32;
+                let (physical_block, count) =
+                    inode_ref.get_inode_dblk_run(logical_block, spanned, true)?;
+
+                // 登记到运行中的日志事务，提交时按数据模式在 commit block 之前写回
+                #[cfg(feature = "journal")]
+                if let Some(journal) = self.journal.as_mut() {
+                    journal.add_data_blocks(physical_block, count);
+                }
+
+                // 只有整块部分直接写设备，末尾的部分块走下面的读-改-写路径
+                let run_blocks = count.min(full_blocks);
+                let mut done = 0u32;
+                while done < run_blocks {
+                    let run = (run_blocks - done).min(MAX_WRITE_RUN);
+                    let start = bytes_written + done as usize * block_size as usize;
+                    let len = run as usize * block_size as usize;
+                    inode_ref.bdev_mut().write_blocks(
+                        physical_block + done as u64,
+                        run,
+                        &buf[start..start + len],
+                    )?;
+                    done += run;
+                }
+
+                let len = run_blocks as usize * block_size as usize;
+                bytes_written += len;
+                current_offset += len as u64;
+                continue;
+            }
+
             // 获取或分配物理块
             let physical_block = inode_ref.get_inode_dblk_idx(logical_block, true)?;
             if physical_block == 0 {
                 return Err(Error::new(ErrorKind::NoSpace, "Failed to allocate block"));
             }
-
-            // 通过 InodeRef 访问 bdev
-            let bdev = inode_ref.bdev_mut();
-
-            // 优化：全块写入时跳过读取
-            let is_full_block = offset_in_block == 0 && write_len == block_size as usize;
-
-            if !is_full_block {
-                bdev.read_block(physical_block, &mut block_buf)?;
+            #[cfg(feature = "journal")]
+            if let Some(journal) = self.journal.as_mut() {
+                journal.add_data_blocks(physical_block, 1);
             }
-            // 全块写入时不需要读取，直接覆盖（block_buf会被完全覆盖）
 
-            // 写入数据
-            block_buf[offset_in_block..offset_in_block + write_len]
-                .copy_from_slice(&buf[bytes_written..bytes_written + write_len]);
-
-            // 写回块
-            bdev.write_block(physical_block, &block_buf)?;
+            // 部分块写入：直接在缓存块内修改
+            let data = &buf[bytes_written..bytes_written + write_len];
+            let mut block = Block::get_data(inode_ref.bdev_mut(), physical_block)?;
+            block.with_data_mut(|block_data| {
+                block_data[offset_in_block..offset_in_block + write_len].copy_from_slice(data);
+            })?;
+            drop(block);
 
             bytes_written += write_len;
             current_offset += write_len as u64;
         }
 
-        // 更新文件大小
+        if let Some(entry) = shared {
+            entry.extent = inode_ref.block_map_cache();
+        }
+
+        // 更新文件大小（推迟时只在 inode 已因分配变脏时顺带更新）
         let new_end = offset + bytes_written as u64;
-        if new_end > current_size {
+        let mut size_done = new_end <= current_size;
+        if !size_done && (!defer_size || inode_ref.is_dirty()) {
             inode_ref.set_size(new_end)?;
             inode_ref.mark_dirty()?;
+            size_done = true;
         }
 
-        Ok(bytes_written)
+        Ok((bytes_written, size_done))
     }
 
     /// 获取 inode 的属性（元数据）
@@ -1921,50 +2826,32 @@ impl<D: BlockDevice> Ext4FileSystem<D> {
     /// println!("Mode: {:o}", attr.mode);
     /// ```
     pub fn get_inode_attr(&mut self, inode_num: u32) -> Result<FileMetadata> {
-        let mut inode_ref = InodeRef::get(&mut self.bdev, &mut self.sb, inode_num)?;
-
-        let mode = inode_ref.with_inode(|inode| u16::from_le(inode.mode))?;
-        let size = inode_ref.size()?;
-        let links_count = inode_ref.with_inode(|inode| u16::from_le(inode.links_count))?;
-
-        let (uid, gid) = inode_ref.with_inode(|inode| {
-            let uid = (u16::from_le(inode.uid) as u32) | ((u16::from_le(inode.uid_high) as u32) << 16);
-            let gid = (u16::from_le(inode.gid) as u32) | ((u16::from_le(inode.gid_high) as u32) << 16);
-            (uid, gid)
-        })?;
-
-        let (atime, mtime, ctime) = inode_ref.with_inode(|inode| {
-            (
-                u32::from_le(inode.atime) as i64,
-                u32::from_le(inode.mtime) as i64,
-                u32::from_le(inode.ctime) as i64,
-            )
-        })?;
+        self.observe(FsOp::Stat, |fs| {
+            let mut meta = {
+                let mut inode_ref = InodeRef::get(&mut fs.bdev, &mut fs.sb, inode_num)?;
+                FileMetadata::from_inode_ref(&mut inode_ref)?
+            };
+            fs.fix_overflowed_nlink(&mut meta)?;
+            Ok(meta)
+        })
+    }
 
-        use crate::consts::*;
-        let file_type = match mode & EXT4_INODE_MODE_TYPE_MASK {
-            EXT4_INODE_MODE_FILE => super::metadata::FileType::RegularFile,
-            EXT4_INODE_MODE_DIRECTORY => super::metadata::FileType::Directory,
-            EXT4_INODE_MODE_SOFTLINK => super::metadata::FileType::Symlink,
-            _ => super::metadata::FileType::Unknown,
-        };
+    /// 链接计数溢出（记为 1）的目录通过扫描目录得到真实的链接计数
+    fn fix_overflowed_nlink(&mut self, meta: &mut FileMetadata) -> Result<()> {
+        if !meta.is_dir() || meta.links_count != 1 {
+            return Ok(());
+        }
 
-        // 读取块数（使用 blocks_count_with_sb 以正确处理 HUGE_FILE）
-        let blocks_count = inode_ref.blocks_count()?;
-
-        Ok(FileMetadata {
-            inode_num,
-            file_type,
-            size,
-            permissions: mode & 0o7777,
-            links_count,
-            uid,
-            gid,
-            atime,
-            mtime,
-            ctime,
-            blocks_count,
-        })
+        let mut subdirs = 0;
+        for entry in self.read_dir_from_inode(meta.inode_num)? {
+            if entry.name == "." || entry.name == ".." {
+                continue;
+            }
+            // 没有 FILETYPE 特性时 read_dir 已经从 inode 补全了类型
+            subdirs += entry.is_dir() as u32;
+        }
+        meta.nlink = 2 + subdirs;
+        Ok(())
     }
 
     /// 在指定目录 inode 中查找子项
@@ -1989,29 +2876,20 @@ impl<D: BlockDevice> Ext4FileSystem<D> {
     /// let child_inode = fs.lookup_in_dir(parent_inode, "file.txt")?;
     /// ```
     pub fn lookup_in_dir(&mut self, parent_inode: u32, name: &str) -> Result<u32> {
-        // 读取目录条目
-        let entries = {
-            let mut inode_ref = InodeRef::get(&mut self.bdev, &mut self.sb, parent_inode)?;
+        self.observe(FsOp::Lookup, |fs| {
+            let mut inode_ref = InodeRef::get(&mut fs.bdev, &mut fs.sb, parent_inode)?;
             if !inode_ref.is_dir()? {
                 return Err(Error::new(
-                    ErrorKind::InvalidInput,
+                    ErrorKind::NotADirectory,
                     "Parent inode is not a directory",
                 ));
             }
-            read_dir(&mut inode_ref)?
-        };
-
-        // 查找匹配的条目
-        for entry in entries {
-            if entry.name == name {
-                return Ok(entry.inode);
-            }
-        }
 
-        Err(Error::new(
-            ErrorKind::NotFound,
-            "Entry not found in directory",
-        ))
+            find_entry(&mut inode_ref, name)?.ok_or(Error::new(
+                ErrorKind::NotFound,
+                "Entry not found in directory",
+            ))
+        })
     }
 
     /// 在指定目录 inode 中创建新条目
@@ -2050,6 +2928,17 @@ impl<D: BlockDevice> Ext4FileSystem<D> {
         name: &str,
         file_type: u8,
         mode: u16,
+    ) -> Result<u32> {
+        self.observe(FsOp::Create, |fs| fs.create_in_dir_steps(parent_inode, name, file_type, mode))
+    }
+
+    /// `create_in_dir` 的各个步骤
+    fn create_in_dir_steps(
+        &mut self,
+        parent_inode: u32,
+        name: &str,
+        file_type: u8,
+        mode: u16,
     ) -> Result<u32> {
         use crate::consts::*;
         use crate::dir::write::{EXT4_DE_DIR, EXT4_DE_REG_FILE, EXT4_DE_SYMLINK};
@@ -2059,7 +2948,7 @@ impl<D: BlockDevice> Ext4FileSystem<D> {
             let mut inode_ref = InodeRef::get(&mut self.bdev, &mut self.sb, parent_inode)?;
             if !inode_ref.is_dir()? {
                 return Err(Error::new(
-                    ErrorKind::InvalidInput,
+                    ErrorKind::NotADirectory,
                     "Parent inode is not a directory",
                 ));
             }
@@ -2074,14 +2963,16 @@ impl<D: BlockDevice> Ext4FileSystem<D> {
         }
 
         let is_dir = file_type == EXT4_DE_DIR;
+        if is_dir {
+            InodeRef::get(&mut self.bdev, &mut self.sb, parent_inode)?.check_dir_link_max()?;
+        }
 
         // 分配新 inode
-        let new_inode = self.alloc_inode(is_dir)?;
+        let new_inode = self.alloc_inode_in_dir(parent_inode, is_dir)?;
 
         // 初始化 inode
         {
-            use crate::extent::tree_init;
-
+            let use_extents = self.use_extents;
             // 设置文件类型和权限
             let inode_mode = match file_type {
                 EXT4_DE_REG_FILE => EXT4_INODE_MODE_FILE,
@@ -2090,19 +2981,6 @@ impl<D: BlockDevice> Ext4FileSystem<D> {
                 _ => EXT4_INODE_MODE_FILE, // 默认为普通文件
             };
 
-            // 读取 superblock 的 extra_isize 配置（在创建 inode_ref 之前）
-            let inode_size = self.sb.inode_size();
-            let extra_isize = if inode_size > EXT4_GOOD_OLD_INODE_SIZE as u16 {
-                let want_extra_isize = u16::from_le(self.sb.inner().want_extra_isize);
-                if want_extra_isize > 0 {
-                    want_extra_isize
-                } else {
-                    32u16  // 默认值
-                }
-            } else {
-                0u16
-            };
-
             let mut inode_ref = InodeRef::get(&mut self.bdev, &mut self.sb, new_inode)?;
 
             inode_ref.with_inode_mut(|inode| {
@@ -2114,35 +2992,34 @@ impl<D: BlockDevice> Ext4FileSystem<D> {
                 inode.atime = now.to_le();
                 inode.mtime = now.to_le();
                 inode.ctime = now.to_le();
-
-                // 设置 extra_isize
-                if extra_isize > 0 {
-                    inode.extra_isize = extra_isize.to_le();
-                }
-            })?;
-
-            // 设置 EXTENTS 标志（启用 extent 格式）
-            inode_ref.with_inode_mut(|inode| {
-                let flags = u32::from_le(inode.flags);
-                inode.flags = (flags | EXT4_INODE_FLAG_EXTENTS).to_le();
             })?;
 
             inode_ref.set_size(0)?;
 
-            // 初始化 extent 树
-            tree_init(&mut inode_ref)?;
+            // 初始化块映射（extent 树或间接块）
+            init_block_map(&mut inode_ref, use_extents)?;
 
             inode_ref.mark_dirty()?;
 
-            // 如果是目录，初始化目录结构
-            if is_dir {
-                crate::dir::write::dir_init(&mut inode_ref, parent_inode)?;
-            }
+        }
+
+        self.apply_create_policy(new_inode, parent_inode)?;
+
+        // 如果是目录，初始化目录结构
+        if is_dir {
+            self.init_new_dir(new_inode, parent_inode)?;
         }
 
         // 在父目录中添加条目
         self.add_dir_entry(parent_inode, name, new_inode, file_type)?;
 
+        // 新目录的 ".." 指向父目录，增加父目录的链接计数
+        if is_dir {
+            let mut parent_inode_ref = InodeRef::get(&mut self.bdev, &mut self.sb, parent_inode)?;
+            parent_inode_ref.inc_dir_links()?;
+            parent_inode_ref.mark_dirty()?;
+        }
+
         Ok(new_inode)
     }
 
@@ -2169,15 +3046,17 @@ impl<D: BlockDevice> Ext4FileSystem<D> {
     /// }
     /// ```
     pub fn read_dir_from_inode(&mut self, dir_inode: u32) -> Result<Vec<DirEntry>> {
-        let mut inode_ref = InodeRef::get(&mut self.bdev, &mut self.sb, dir_inode)?;
-        if !inode_ref.is_dir()? {
-            return Err(Error::new(
-                ErrorKind::InvalidInput,
-                "Inode is not a directory",
-            ));
-        }
+        self.observe(FsOp::ReadDir, |fs| {
+            let mut inode_ref = InodeRef::get(&mut fs.bdev, &mut fs.sb, dir_inode)?;
+            if !inode_ref.is_dir()? {
+                return Err(Error::new(
+                    ErrorKind::NotADirectory,
+                    "Inode is not a directory",
+                ));
+            }
 
-        read_dir(&mut inode_ref)
+            read_dir(&mut inode_ref)
+        })
     }
 
     /// 从指定目录 inode 中删除条目
@@ -2221,12 +3100,17 @@ impl<D: BlockDevice> Ext4FileSystem<D> {
     /// }
     /// ```
     pub fn unlink_from_dir(&mut self, parent_inode: u32, name: &str) -> Result<u32> {
+        self.observe(FsOp::Remove, |fs| fs.unlink_from_dir_steps(parent_inode, name))
+    }
+
+    /// `unlink_from_dir` 的各个步骤
+    fn unlink_from_dir_steps(&mut self, parent_inode: u32, name: &str) -> Result<u32> {
         // 验证父 inode 是目录
         {
             let mut inode_ref = InodeRef::get(&mut self.bdev, &mut self.sb, parent_inode)?;
             if !inode_ref.is_dir()? {
                 return Err(Error::new(
-                    ErrorKind::InvalidInput,
+                    ErrorKind::NotADirectory,
                     "Parent inode is not a directory",
                 ));
             }
@@ -2263,7 +3147,8 @@ impl<D: BlockDevice> Ext4FileSystem<D> {
     /// # 错误
     ///
     /// - `ErrorKind::NotFound` - 源条目不存在
-    /// - `ErrorKind::InvalidInput` - inode 不是目录
+    /// - `ErrorKind::InvalidInput` - inode 不是目录，或目录将被移动到自己的子树中
+    /// - `ErrorKind::TooManyLinks` - 目标目录是链接计数已达上限的线性目录
     ///
     /// # 示例
     ///
@@ -2281,7 +3166,18 @@ impl<D: BlockDevice> Ext4FileSystem<D> {
         dst_dir_ino: u32,
         dst_name: &str,
     ) -> Result<()> {
-        use crate::dir::write::{EXT4_DE_DIR, EXT4_DE_REG_FILE};
+        self.observe(FsOp::Rename, |fs| fs.rename_inode_steps(src_dir_ino, src_name, dst_dir_ino, dst_name))
+    }
+
+    /// `rename_inode` 的各个步骤
+    fn rename_inode_steps(
+        &mut self,
+        src_dir_ino: u32,
+        src_name: &str,
+        dst_dir_ino: u32,
+        dst_name: &str,
+    ) -> Result<()> {
+        use crate::dir::write::EXT4_DE_DIR;
 
         // 1. 查找目标 inode
         let target_inode = self.lookup_in_dir(src_dir_ino, src_name)?;
@@ -2289,15 +3185,18 @@ impl<D: BlockDevice> Ext4FileSystem<D> {
         // 2. 获取目标的文件类型
         let (is_dir, file_type) = {
             let mut inode_ref = InodeRef::get(&mut self.bdev, &mut self.sb, target_inode)?;
-            let is_dir = inode_ref.is_dir()?;
-            let file_type = if is_dir {
-                EXT4_DE_DIR
-            } else {
-                EXT4_DE_REG_FILE
-            };
-            (is_dir, file_type)
+            (inode_ref.is_dir()?, inode_ref.de_type()?)
         };
 
+        // 目录不能移动到自己的子树中（必须在删除已存在的目标之前检查）；
+        // 移动到其他目录且不替换已有目录时，目标目录还要能增加子目录
+        if is_dir {
+            self.check_rename_loop(target_inode, dst_dir_ino)?;
+            if src_dir_ino != dst_dir_ino && self.lookup_in_dir(dst_dir_ino, dst_name).is_err() {
+                InodeRef::get(&mut self.bdev, &mut self.sb, dst_dir_ino)?.check_dir_link_max()?;
+            }
+        }
+
         // 3. 如果目标名字已存在，先完整删除（POSIX 语义）
         //    注意：必须完整删除，包括释放 inode 和数据块
         //    否则会导致文件系统元数据损坏
@@ -2337,13 +3236,15 @@ impl<D: BlockDevice> Ext4FileSystem<D> {
                     (old_is_dir, new_links)
                 }; // old_inode_ref 在这里被释放
 
+                // 被替换的文件仍然打开时，最后一次关闭时释放
+                if new_links == 0 {
+                    self.defer_unlinked(old_target_inode);
+                }
+
                 // 如果是目录，还需要减少父目录的链接计数
                 if old_is_dir {
                     let mut dst_parent_ref = InodeRef::get(&mut self.bdev, &mut self.sb, dst_dir_ino)?;
-                    dst_parent_ref.with_inode_mut(|inode| {
-                        let links = u16::from_le(inode.links_count);
-                        inode.links_count = links.saturating_sub(1).to_le();
-                    })?;
+                    dst_parent_ref.dec_dir_links()?;
                     dst_parent_ref.mark_dirty()?;
                 }
 
@@ -2363,10 +3264,7 @@ impl<D: BlockDevice> Ext4FileSystem<D> {
             let mut dst_parent_inode_ref =
                 InodeRef::get(&mut self.bdev, &mut self.sb, dst_dir_ino)?;
 
-            dst_parent_inode_ref.with_inode_mut(|inode| {
-                let links = u16::from_le(inode.links_count);
-                inode.links_count = (links + 1).to_le();
-            })?;
+            dst_parent_inode_ref.inc_dir_links()?;
             dst_parent_inode_ref.mark_dirty()?;
         }
 
@@ -2378,10 +3276,7 @@ impl<D: BlockDevice> Ext4FileSystem<D> {
             let mut src_parent_inode_ref =
                 InodeRef::get(&mut self.bdev, &mut self.sb, src_dir_ino)?;
 
-            src_parent_inode_ref.with_inode_mut(|inode| {
-                let links = u16::from_le(inode.links_count);
-                inode.links_count = (links.saturating_sub(1)).to_le();
-            })?;
+            src_parent_inode_ref.dec_dir_links()?;
             src_parent_inode_ref.mark_dirty()?;
         }
 
@@ -2397,6 +3292,35 @@ impl<D: BlockDevice> Ext4FileSystem<D> {
         Ok(())
     }
 
+    /// 检查 `dir` 是否是 `dst_parent` 本身或其祖先
+    ///
+    /// 从 `dst_parent` 沿 ".." 向上走到根目录；目录不能移动到自己的子树中。
+    ///
+    /// # 错误
+    ///
+    /// - `ErrorKind::InvalidInput` - `dir` 是 `dst_parent` 的祖先
+    /// - `ErrorKind::Corrupted` - ".." 链过长（目录树中存在环）
+    fn check_rename_loop(&mut self, dir: u32, dst_parent: u32) -> Result<()> {
+        let mut current = dst_parent;
+        for _ in 0..self.sb.inodes_count() {
+            if current == dir {
+                return Err(Error::new(
+                    ErrorKind::InvalidInput,
+                    "Cannot move a directory into its own subdirectory",
+                ));
+            }
+            if current == crate::consts::EXT4_ROOT_INODE {
+                return Ok(());
+            }
+            let parent = self.lookup_in_dir(current, "..")?;
+            if parent == current {
+                return Ok(());
+            }
+            current = parent;
+        }
+        Err(Error::new(ErrorKind::Corrupted, "Directory '..' chain forms a loop"))
+    }
+
     /// 创建硬链接 (VFS 风格)
     ///
     /// 在指定目录中创建指向已存在 inode 的新目录条目
@@ -2430,14 +3354,12 @@ impl<D: BlockDevice> Ext4FileSystem<D> {
         name: &str,
         child_ino: u32,
     ) -> Result<()> {
-        use crate::dir::write::EXT4_DE_REG_FILE;
-
         // 1. 验证 dir_ino 是目录
         {
             let mut dir_inode_ref = InodeRef::get(&mut self.bdev, &mut self.sb, dir_ino)?;
             if !dir_inode_ref.is_dir()? {
                 return Err(Error::new(
-                    ErrorKind::InvalidInput,
+                    ErrorKind::NotADirectory,
                     "dir_ino is not a directory",
                 ));
             }
@@ -2450,27 +3372,13 @@ impl<D: BlockDevice> Ext4FileSystem<D> {
 
             if is_dir {
                 return Err(Error::new(
-                    ErrorKind::InvalidInput,
+                    ErrorKind::NotPermitted,
                     "Cannot create hard link to directory",
                 ));
             }
 
             // 获取文件类型
-            child_inode_ref.with_inode(|inode| {
-                let mode = u16::from_le(inode.mode);
-                let type_bits = mode & crate::consts::EXT4_INODE_MODE_TYPE_MASK;
-
-                // 根据 mode 确定目录条目类型
-                match type_bits {
-                    crate::consts::EXT4_INODE_MODE_FILE => crate::dir::write::EXT4_DE_REG_FILE,
-                    crate::consts::EXT4_INODE_MODE_SOFTLINK => crate::dir::write::EXT4_DE_SYMLINK,
-                    crate::consts::EXT4_INODE_MODE_CHARDEV => crate::dir::write::EXT4_DE_CHRDEV,
-                    crate::consts::EXT4_INODE_MODE_BLOCKDEV => crate::dir::write::EXT4_DE_BLKDEV,
-                    crate::consts::EXT4_INODE_MODE_FIFO => crate::dir::write::EXT4_DE_FIFO,
-                    crate::consts::EXT4_INODE_MODE_SOCKET => crate::dir::write::EXT4_DE_SOCK,
-                    _ => EXT4_DE_REG_FILE, // 默认为普通文件
-                }
-            })?
+            child_inode_ref.de_type()?
         };
 
         // 3. 在目录中添加条目
@@ -2491,23 +3399,33 @@ impl<D: BlockDevice> Ext4FileSystem<D> {
 
     /// Deferred deletion: 当VFS层释放最后一个对inode的引用时调用
     /// 如果 i_nlink == 0，则释放inode的所有资源
+    ///
+    /// inode 仍在打开 inode 表中时（见 [`open_inode`](Self::open_inode)）只做标记，
+    /// 最后一个句柄关闭时再释放
     pub fn drop_inode(&mut self, ino: u32) -> Result<()> {
-        let (nlink, is_dir) = {
+        let (nlink, is_dir, is_fast_symlink) = {
             let mut inode_ref = InodeRef::get(&mut self.bdev, &mut self.sb, ino)?;
             let nlink = inode_ref.with_inode(|inode| {
                 u16::from_le(inode.links_count)
             })?;
             let is_dir = inode_ref.is_dir()?;
-            (nlink, is_dir)
+            let is_fast_symlink = inode_ref.with_inode(|inode| inode.is_symlink())? && inode_ref.size()? < 60;
+            (nlink, is_dir, is_fast_symlink)
         };
 
+        if nlink == 0 && self.defer_unlinked(ino) {
+            return Ok(());
+        }
+
         if nlink == 0 {
             log::info!("[DROP_INODE] inode {} has nlink=0, freeing resources", ino);
 
-            // 释放数据块
-            let mut inode_ref = InodeRef::get(&mut self.bdev, &mut self.sb, ino)?;
-            inode_ref.set_size(0)?;
-            drop(inode_ref);
+            self.release_xattrs(ino)?;
+
+            // 释放数据块（快速符号链接没有数据块）
+            if !is_fast_symlink {
+                self.truncate_inode(ino, 0)?;
+            }
 
             // 释放inode号
             self.free_inode(ino, is_dir)?;
@@ -2519,13 +3437,204 @@ impl<D: BlockDevice> Ext4FileSystem<D> {
     }
 }
 
+/// 初始化新 inode 的块映射
+///
+/// `use_extents` 为真时设置 EXTENTS 标志并初始化 extent 树；否则（ext2/ext3 镜像）
+/// 清除 EXTENTS 标志并清零 `i_block`，数据块按间接块映射分配
+fn init_block_map<D: BlockDevice>(inode_ref: &mut InodeRef<D>, use_extents: bool) -> Result<()> {
+    use crate::consts::{EXT4_INODE_BLOCKS, EXT4_INODE_FLAG_EXTENTS};
+
+    if use_extents {
+        inode_ref.with_inode_mut(|inode| {
+            let flags = u32::from_le(inode.flags);
+            inode.flags = (flags | EXT4_INODE_FLAG_EXTENTS).to_le();
+        })?;
+        return crate::extent::tree_init(inode_ref);
+    }
+
+    inode_ref.with_inode_mut(|inode| {
+        let flags = u32::from_le(inode.flags);
+        inode.flags = (flags & !EXT4_INODE_FLAG_EXTENTS).to_le();
+        inode.blocks = [0; EXT4_INODE_BLOCKS];
+    })?;
+    inode_ref.mark_dirty()
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::testing::image;
+    use alloc::string::String;
 
     #[test]
     fn test_filesystem_api() {
         // 这些测试需要实际的块设备和 ext4 文件系统
         // 主要是验证 API 的设计和编译
     }
+
+    /// 目录项中记录的文件类型
+    fn entry_type(fs: &mut Ext4FileSystem<crate::block::MemBlockDevice>, dir: &str, name: &str) -> u8 {
+        fs.read_dir(dir).unwrap().into_iter().find(|e| e.name == name).unwrap().file_type
+    }
+
+    #[test]
+    fn test_rename_and_link_keep_file_type() {
+        use crate::dir::write::{EXT4_DE_FIFO, EXT4_DE_SYMLINK};
+
+        let mut fs = image::mount(image::image());
+        fs.create_dir("/", "dir", 0o755).unwrap();
+        let dir = fs.lookup_at(crate::consts::EXT4_ROOT_INODE, "dir").unwrap();
+        let fifo = fs.mknod("/", "fifo", crate::fs::InodeType::Fifo, 0o600, 0).unwrap();
+        fs.fsymlink("target", "/", "link").unwrap();
+
+        fs.rename("/", "fifo", "/dir", "fifo2").unwrap();
+        assert_eq!(entry_type(&mut fs, "/dir", "fifo2"), EXT4_DE_FIFO);
+        fs.rename_inode(crate::consts::EXT4_ROOT_INODE, "link", dir, "link2").unwrap();
+        assert_eq!(entry_type(&mut fs, "/dir", "link2"), EXT4_DE_SYMLINK);
+
+        fs.link_inode(crate::consts::EXT4_ROOT_INODE, "fifo3", fifo).unwrap();
+        assert_eq!(entry_type(&mut fs, "/", "fifo3"), EXT4_DE_FIFO);
+    }
+
+    /// 目录树的快照：每个目录的 (路径, 排序后的目录项, 链接计数)
+    fn tree(fs: &mut Ext4FileSystem<crate::block::MemBlockDevice>, dirs: &[&str]) -> Vec<(String, Vec<(String, u32)>, u16)> {
+        dirs.iter()
+            .map(|&dir| {
+                let mut entries: Vec<_> = fs.read_dir(dir).unwrap().into_iter().map(|e| (e.name, e.inode)).collect();
+                entries.sort();
+                (String::from(dir), entries, fs.metadata(dir).unwrap().links_count)
+            })
+            .collect()
+    }
+
+    #[test]
+    fn test_rename_into_descendant() {
+        let mut fs = image::mount(image::image());
+        crate::fs::ops::create_dir_all(&mut fs, "/a/b/c").unwrap();
+        let dirs = ["/", "/a", "/a/b", "/a/b/c"];
+        let before = tree(&mut fs, &dirs);
+        let a = fs.lookup_at(crate::consts::EXT4_ROOT_INODE, "a").unwrap();
+        let c = fs.lookup_at(crate::consts::EXT4_ROOT_INODE, "a/b/c").unwrap();
+
+        // 移动到子孙目录和自己之下
+        assert_eq!(fs.rename("/", "a", "/a/b/c", "a").unwrap_err().kind(), ErrorKind::InvalidInput);
+        assert_eq!(fs.rename("/", "a", "/a", "a").unwrap_err().kind(), ErrorKind::InvalidInput);
+        let err = fs.rename_inode(crate::consts::EXT4_ROOT_INODE, "a", c, "a").unwrap_err();
+        assert_eq!(err.kind(), ErrorKind::InvalidInput);
+        // 替换子孙目录中已存在的目录也不行，被替换的目录保留
+        fs.create_dir("/a/b/c", "a", 0o755).unwrap();
+        let before_replace = tree(&mut fs, &["/", "/a", "/a/b", "/a/b/c", "/a/b/c/a"]);
+        assert_eq!(fs.rename("/", "a", "/a/b/c", "a").unwrap_err().kind(), ErrorKind::InvalidInput);
+        assert_eq!(tree(&mut fs, &["/", "/a", "/a/b", "/a/b/c", "/a/b/c/a"]), before_replace);
+        fs.remove_dir("/a/b/c", "a").unwrap();
+
+        assert_eq!(tree(&mut fs, &dirs), before);
+        assert_eq!(fs.lookup_at(a, "..").unwrap(), crate::consts::EXT4_ROOT_INODE);
+
+        // 反方向（移动到祖先目录）可以
+        fs.rename("/a/b", "c", "/", "c").unwrap();
+        assert_eq!(fs.lookup_at(c, "..").unwrap(), crate::consts::EXT4_ROOT_INODE);
+    }
+
+    #[test]
+    fn test_follow_symlink() {
+        let mut fs = image::mount(image::image());
+        crate::fs::ops::write(&mut fs, "/file", b"data").unwrap();
+        fs.create_dir("/", "dir", 0o755).unwrap();
+        fs.fsymlink("file", "/", "to_file").unwrap();
+        fs.fsymlink("/dir", "/", "to_dir").unwrap();
+        fs.fsymlink("missing", "/", "dangling").unwrap();
+
+        let file = fs.lookup_with("/file", FollowSymlink::NoFollow).unwrap();
+        assert_eq!(fs.lookup_with("/to_file", FollowSymlink::Follow).unwrap(), file);
+        assert_ne!(fs.lookup_with("/to_file", FollowSymlink::NoFollow).unwrap(), file);
+
+        assert!(fs.metadata("/to_file").unwrap().is_file());
+        assert!(fs.symlink_metadata("/to_file").unwrap().is_symlink());
+        assert!(fs.metadata_with("/to_dir", FollowSymlink::Follow).unwrap().is_dir());
+        assert!(fs.metadata_with("/to_dir", FollowSymlink::NoFollow).unwrap().is_symlink());
+
+        // exists、is_dir、is_file 与 metadata 一样跟随
+        assert!(fs.exists("/to_file"));
+        assert!(fs.is_file("/to_file").unwrap());
+        assert!(!fs.is_dir("/to_file").unwrap());
+        assert!(fs.is_dir("/to_dir").unwrap());
+        assert!(!fs.exists("/dangling"));
+        assert!(fs.symlink_metadata("/dangling").is_ok());
+        assert_eq!(fs.metadata("/dangling").unwrap_err().kind(), ErrorKind::NotFound);
+    }
+
+    #[test]
+    fn test_read_dir_plus() {
+        use crate::consts::EXT4_ROOT_INODE;
+        use crate::fs::{makedev, FileType, InodeType};
+
+        let mut fs = image::mount(image::image());
+        let d = fs.create_dir("/", "d", 0o755).unwrap();
+        crate::fs::ops::write(&mut fs, "/d/file", &[7u8; 5000]).unwrap();
+        let file = fs.lookup_with("/d/file", FollowSymlink::NoFollow).unwrap();
+        fs.link_inode(d, "hard", file).unwrap();
+        fs.create_dir("/d", "sub", 0o700).unwrap();
+        fs.fsymlink("file", "/d", "link").unwrap();
+        fs.mknod("/d", "fifo", InodeType::Fifo, 0o600, 0).unwrap();
+        fs.mknod("/d", "chr", InodeType::CharacterDevice, 0o666, makedev(1, 3)).unwrap();
+        fs.mknod("/d", "blk", InodeType::BlockDevice, 0o660, makedev(8, 1)).unwrap();
+
+        let plus = fs.read_dir_plus("/d").unwrap();
+        let names: Vec<String> = plus.iter().map(|(e, _)| e.name.clone()).collect();
+        let plain: Vec<String> = fs.read_dir("/d").unwrap().into_iter().map(|e| e.name).collect();
+        assert_eq!(names, plain);
+
+        for (entry, meta) in &plus {
+            assert_eq!(meta.inode_num, entry.inode);
+            // 不跟随符号链接，与 lstat 的结果逐字段相同
+            let path = alloc::format!("/d/{}", entry.name);
+            let expected = fs.symlink_metadata(&path).unwrap();
+            assert_eq!(alloc::format!("{meta:?}"), alloc::format!("{expected:?}"), "{}", entry.name);
+        }
+
+        let get = |name: &str| &plus.iter().find(|(e, _)| e.name == name).unwrap().1;
+        assert_eq!(get("file").file_type, FileType::RegularFile);
+        assert_eq!(get("file").size, 5000);
+        assert_eq!(get("hard").links_count, 2);
+        assert_eq!(get("sub").file_type, FileType::Directory);
+        assert_eq!(get("sub").permissions, 0o700);
+        assert_eq!(get("link").file_type, FileType::Symlink);
+        assert_eq!(get("link").symlink_target_len, Some(4));
+        assert_eq!(get("fifo").file_type, FileType::Fifo);
+        assert_eq!(get("chr").file_type, FileType::CharDevice);
+        assert_eq!(get("chr").rdev, makedev(1, 3));
+        assert_eq!(get("blk").file_type, FileType::BlockDevice);
+        assert_eq!(get("blk").rdev, makedev(8, 1));
+        assert_eq!(get(".").nlink, 3);
+        assert_eq!(get("..").inode_num, EXT4_ROOT_INODE);
+    }
+
+    #[test]
+    fn test_write_fragmented_free_space() {
+        let mut fs = image::mount(image::image());
+        let a = fs.create_file("/", "a", 0o644).unwrap();
+        let b = fs.create_file("/", "b", 0o644).unwrap();
+
+        // 交替给两个文件追加一块，直到写满；删除 b 后空闲空间只剩单块的空洞
+        let block = [0u8; 4096];
+        let mut offset = 0u64;
+        loop {
+            if fs.write_at_inode(a, &block, offset).is_err() || fs.write_at_inode(b, &block, offset).is_err() {
+                break;
+            }
+            offset += 4096;
+        }
+        fs.remove_file("/", "b").unwrap();
+        let free = fs.superblock().free_blocks_count();
+        assert!(free > 200, "only {free} free blocks");
+
+        let c = fs.create_file("/", "c", 0o644).unwrap();
+        let data: Vec<u8> = (0..200 * 4096u32).map(|i| (i / 4096 + i % 251) as u8).collect();
+        assert_eq!(fs.write_at_inode_batch(c, &data, 0).unwrap(), data.len());
+
+        let remounted = fs.unmount().unwrap().device().clone();
+        let mut fs = image::mount(remounted);
+        assert_eq!(crate::fs::ops::read(&mut fs, "/c").unwrap(), data);
+    }
 }
@@ -0,0 +1,187 @@
+//! 挂载计数、检查间隔和错误记录
+//!
+//! 与内核相同，可写挂载时：
+//! - `s_mnt_count` 加一，`s_max_mnt_count` 为 0 时设为 [`EXT4_DFL_MAX_MNT_COUNT`]
+//! - 记录挂载时间（需要 [`FsConfig::clock`](super::FsConfig::clock)）和挂载点
+//!   （[`FsConfig::mount_point`](super::FsConfig::mount_point)）
+//! - 清除 `EXT4_SUPER_STATE_VALID` 并立即写回 superblock，挂载期间崩溃后
+//!   e2fsck 能发现文件系统没有正常卸载
+//!
+//! [`Ext4FileSystem::unmount`] 恢复挂载前的状态。操作返回 `ErrorKind::Corrupted` 时
+//! 设置 `EXT4_SUPER_STATE_ERROR`；打开 [`FsConfig::record_errors`](super::FsConfig::record_errors)
+//! 时还记录错误的时间、操作和块号（见 [`Ext4FileSystem::error_state`]），随下一次提交或卸载写回。
+//!
+//! [`Ext4FileSystem::needs_fsck`] 按 e2fsck 的规则判断是否应该检查，由宿主安排。
+
+use crate::{
+    block::{BlockDevice, FsOp},
+    consts::*,
+    error::{Error, Result},
+    superblock::{ErrorRecord, ErrorState},
+};
+use core::time::Duration;
+
+use super::filesystem::Ext4FileSystem;
+
+/// 需要检查文件系统的原因，见 [`Ext4FileSystem::fsck_reason`]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum FsckReason {
+    /// 上次没有正常卸载
+    NotClean,
+    /// 记录了错误
+    Errors,
+    /// 挂载次数达到 `s_max_mnt_count`
+    MountCount,
+    /// 距上次检查超过 `s_checkinterval`
+    CheckInterval,
+}
+
+/// 本次挂载的状态
+#[derive(Debug)]
+pub(super) struct MountState {
+    /// 挂载前 superblock 的状态标志，之后检测到的错误也记在这里，卸载时写回
+    state: u16,
+    /// 见 [`FsConfig::clock`](super::FsConfig::clock)
+    clock: Option<fn() -> Option<Duration>>,
+    /// 挂载时是否修改并写回了 superblock（可写挂载）
+    recorded: bool,
+    /// 见 [`FsConfig::record_errors`](super::FsConfig::record_errors)
+    pub(super) record_errors: bool,
+}
+
+impl MountState {
+    pub(super) fn new() -> Self {
+        Self { state: EXT4_SUPER_STATE_VALID, clock: None, recorded: false, record_errors: false }
+    }
+}
+
+impl<D: BlockDevice> Ext4FileSystem<D> {
+    /// 记录一次挂载，挂载的最后一步
+    pub(super) fn record_mount(
+        &mut self,
+        clock: Option<fn() -> Option<Duration>>,
+        mount_point: Option<&str>,
+    ) -> Result<()> {
+        self.mount_state = MountState { state: self.superblock().state(), clock, ..MountState::new() };
+        if let Some(reason) = self.fsck_reason() {
+            log::warn!("[MOUNT] Filesystem should be checked: {reason:?}");
+        }
+        if self.superblock().is_read_only() {
+            return Ok(());
+        }
+
+        let now = clock.and_then(|clock| clock());
+        let sb = self.superblock_mut();
+        if sb.max_mount_count() == 0 {
+            sb.set_max_mount_count(EXT4_DFL_MAX_MNT_COUNT as i16);
+        }
+        sb.inc_mount_count();
+        if let Some(now) = now {
+            sb.set_mount_time(now.as_secs());
+        }
+        if let Some(path) = mount_point {
+            sb.set_last_mounted(path);
+        }
+        // 日志事务提交时元数据已随写屏障写回原位置，崩溃后仍可能需要 e2fsck，
+        // 与没有日志时的内核一样在挂载期间清除 VALID
+        sb.clear_state_flags(EXT4_SUPER_STATE_VALID);
+        self.write_superblock()?;
+        self.bdev.flush()?;
+        self.mount_state.recorded = true;
+        Ok(())
+    }
+
+    /// 卸载时恢复挂载前的状态标志，保留挂载期间记录的错误
+    pub(super) fn record_unmount(&mut self) {
+        if self.mount_state.recorded {
+            let state = self.mount_state.state;
+            self.superblock_mut().set_state(state);
+        }
+    }
+
+    /// `op` 返回 `ErrorKind::Corrupted` 时记录错误
+    pub(super) fn note_corruption(&mut self, op: FsOp, error: &Error) {
+        self.mount_state.state |= EXT4_SUPER_STATE_ERROR;
+        if self.superblock().is_read_only() {
+            return;
+        }
+        self.superblock_mut().set_state_flags(EXT4_SUPER_STATE_ERROR);
+        if self.mount_state.record_errors {
+            let now = self.mount_state.clock.and_then(|clock| clock()).map_or(0, |now| now.as_secs());
+            let record = ErrorRecord::new(op.name(), now, error.block().unwrap_or(0), EXT4_ERR_EFSCORRUPTED);
+            self.superblock_mut().record_error(&record);
+        }
+    }
+
+    /// superblock 中记录的错误，包括以前的挂载记录的错误
+    ///
+    /// 记录需要打开 [`FsConfig::record_errors`](super::FsConfig::record_errors)；
+    /// 内核和 e2fsck 记录的错误也会读出
+    pub fn error_state(&self) -> ErrorState {
+        self.superblock().error_state()
+    }
+
+    /// 是否应该用 e2fsck 检查文件系统，见 [`fsck_reason`](Self::fsck_reason)
+    pub fn needs_fsck(&self) -> bool {
+        self.fsck_reason().is_some()
+    }
+
+    /// 需要检查文件系统的原因，不需要时返回 `None`
+    ///
+    /// 挂载前文件系统没有正常卸载或记录了错误、挂载以来检测到损坏、挂载次数达到
+    /// `s_max_mnt_count`，或者距上次检查超过 `s_checkinterval` 时需要检查。
+    /// 检查间隔需要 [`FsConfig::clock`](super::FsConfig::clock)，没有时钟时不按时间判断。
+    pub fn fsck_reason(&self) -> Option<FsckReason> {
+        let sb = self.superblock();
+        let now = self.mount_state.clock.and_then(|clock| clock()).map(|now| now.as_secs());
+        fsck_reason(
+            self.mount_state.state,
+            sb.mount_count(),
+            sb.max_mount_count(),
+            sb.last_check_time(),
+            sb.check_interval(),
+            now,
+        )
+    }
+}
+
+/// e2fsck 判断是否需要检查的规则
+fn fsck_reason(
+    state: u16,
+    mount_count: u16,
+    max_mount_count: i16,
+    last_check: u64,
+    check_interval: u32,
+    now: Option<u64>,
+) -> Option<FsckReason> {
+    if state & EXT4_SUPER_STATE_VALID == 0 {
+        Some(FsckReason::NotClean)
+    } else if state & EXT4_SUPER_STATE_ERROR != 0 {
+        Some(FsckReason::Errors)
+    } else if max_mount_count > 0 && mount_count >= max_mount_count as u16 {
+        Some(FsckReason::MountCount)
+    } else if check_interval != 0 && now.is_some_and(|now| now >= last_check + check_interval as u64) {
+        Some(FsckReason::CheckInterval)
+    } else {
+        None
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_fsck_reason() {
+        let valid = EXT4_SUPER_STATE_VALID;
+        assert_eq!(fsck_reason(valid, 3, -1, 0, 0, Some(100)), None);
+        assert_eq!(fsck_reason(0, 0, -1, 0, 0, None), Some(FsckReason::NotClean));
+        assert_eq!(fsck_reason(valid | EXT4_SUPER_STATE_ERROR, 0, -1, 0, 0, None), Some(FsckReason::Errors));
+        assert_eq!(fsck_reason(valid, 20, 20, 0, 0, None), Some(FsckReason::MountCount));
+        assert_eq!(fsck_reason(valid, 19, 20, 0, 0, None), None);
+        assert_eq!(fsck_reason(valid, 0, -1, 1000, 500, Some(1500)), Some(FsckReason::CheckInterval));
+        assert_eq!(fsck_reason(valid, 0, -1, 1000, 500, Some(1499)), None);
+        // 没有时钟时不按时间判断
+        assert_eq!(fsck_reason(valid, 0, -1, 1000, 500, None), None);
+    }
+}
@@ -0,0 +1,174 @@
+//! 单个 inode 的 fsync
+//!
+//! [`Ext4FileSystem::fsync`] 写回整个块缓存，缓存很大时会长时间占用设备。
+//! [`Ext4FileSystem::fsync_inode`] 只写回与一个 inode 相关的块：
+//!
+//! 1. 该 inode 的页缓存脏页和延迟分配的数据（此时才分配物理块）
+//! 2. 块缓存中落在该 inode 数据块上的脏块
+//! 3. 写屏障之后，块缓存中的映射元数据：extent 树节点或间接块，以及 inode 所在的 inode 表块
+//!
+//! 与 `fsync(2)` 相同，不包括指向该 inode 的目录项，新建的文件还需要对父目录调用一次。
+//! 分配时修改的位图、块组描述符和 superblock 计数留给下一次全局提交，崩溃后由 e2fsck
+//! 按 inode 重建。
+//! 运行中的日志事务同样留给下一次全局提交：它记录的是所有 inode 的元数据，不能只提交其中一部分。
+
+use crate::{block::{BlockDevice, FsOp}, error::Result, types::ext4_inode};
+use alloc::{collections::BTreeSet, vec, vec::Vec};
+use core::{mem::offset_of, ops::Range};
+
+use super::{filesystem::Ext4FileSystem, inode_ref::inode_location};
+
+/// `fdatasync` 必须持久化的 inode 字段：大小、块数、标志（决定块映射的解释方式）和块映射
+const DATASYNC_FIELDS: [Range<usize>; 5] = [
+    offset_of!(ext4_inode, size_lo)..offset_of!(ext4_inode, atime),
+    offset_of!(ext4_inode, blocks_count_lo)..offset_of!(ext4_inode, osd1),
+    offset_of!(ext4_inode, blocks)..offset_of!(ext4_inode, generation),
+    offset_of!(ext4_inode, size_hi)..offset_of!(ext4_inode, obso_faddr),
+    offset_of!(ext4_inode, blocks_high)..offset_of!(ext4_inode, file_acl_high),
+];
+
+/// 与一个 inode 相关的块
+#[derive(Debug, Default)]
+struct InodeBlocks {
+    /// 数据块区间 `(起始物理块, 块数)`，按起始块排序
+    data: Vec<(u64, u64)>,
+    /// 映射元数据块（extent 树节点或间接块）
+    meta: BTreeSet<u64>,
+}
+
+impl InodeBlocks {
+    /// `pblk` 是否是该 inode 的数据块
+    fn contains_data(&self, pblk: u64) -> bool {
+        let i = self.data.partition_point(|&(start, _)| start <= pblk);
+        i > 0 && pblk < self.data[i - 1].0 + self.data[i - 1].1
+    }
+}
+
+impl<D: BlockDevice> Ext4FileSystem<D> {
+    /// 只提交与一个 inode 相关的修改
+    ///
+    /// 对应 `fsync(2)` / `fdatasync(2)`：写回该 inode 的数据、extent 树节点（或间接块）
+    /// 和 inode 表块，其他文件的脏块留在缓存中，而不是像 [`fsync`](Self::fsync)
+    /// 那样写回整个块缓存。数据先于映射元数据落盘，两者之间有写屏障。
+    ///
+    /// `datasync` 为真时（`fdatasync`），如果 inode 的大小和块映射与设备上的一致
+    /// （只修改了时间戳等属性），不写 inode 表块。
+    ///
+    /// 未启用 `indirect` feature 时无法遍历间接块映射的文件，这类文件退回到
+    /// [`fsync`](Self::fsync)。
+    ///
+    /// # 示例
+    ///
+    /// ```rust,ignore
+    /// fs.write_at_inode(ino, b"record", offset)?;
+    /// fs.fsync_inode(ino, true)?; // 只等待这个文件的数据落盘
+    /// ```
+    pub fn fsync_inode(&mut self, inode_num: u32, datasync: bool) -> Result<()> {
+        if self.superblock().is_read_only() {
+            return Ok(());
+        }
+        self.observe(FsOp::Fsync, |fs| fs.fsync_inode_steps(inode_num, datasync))
+    }
+
+    /// `fsync_inode` 的各个步骤
+    fn fsync_inode_steps(&mut self, inode_num: u32, datasync: bool) -> Result<()> {
+        self.flush_page_cache_inode(inode_num)?;
+        self.flush_delalloc_inode(inode_num)?;
+
+        let Some(mut blocks) = self.inode_blocks(inode_num)? else {
+            return self.fsync();
+        };
+        let dirty = self.bdev.dirty_blocks();
+
+        for &lba in dirty.iter().filter(|&&lba| blocks.contains_data(lba)) {
+            self.bdev.flush_lba(lba)?;
+        }
+        // 页缓存和延迟分配的数据不经过块缓存，同样需要先于元数据落盘
+        self.bdev.device_mut().flush_barrier()?;
+
+        let (bdev, sb) = self.bdev_and_sb_mut();
+        let (inode_block, offset) = inode_location(bdev, sb, inode_num)?;
+        if dirty.contains(&inode_block) && !(datasync && self.inode_datasync_clean(inode_block, offset)?) {
+            blocks.meta.insert(inode_block);
+        }
+        for &lba in dirty.iter().filter(|&lba| blocks.meta.contains(lba)) {
+            self.bdev.flush_lba(lba)?;
+        }
+        self.bdev.device_mut().flush()
+    }
+
+    /// 收集 inode 的数据块和映射元数据块
+    ///
+    /// 间接块映射的文件在未启用 `indirect` feature 时返回 `None`
+    fn inode_blocks(&mut self, inode_num: u32) -> Result<Option<InodeBlocks>> {
+        self.with_inode_ref(inode_num, |inode_ref| {
+            // 快速符号链接、设备文件等没有数据块，i_block 中不是块指针
+            if inode_ref.blocks_count()? == 0 {
+                return Ok(Some(InodeBlocks::default()));
+            }
+
+            if inode_ref.has_extents()? {
+                let data = inode_ref
+                    .extent_ranges()?
+                    .iter()
+                    .map(|r| (r.physical_start, r.len as u64))
+                    .collect::<Vec<_>>();
+                let mut blocks = InodeBlocks { data, meta: inode_ref.extent_node_blocks()?.into_iter().collect() };
+                blocks.data.sort_unstable();
+                return Ok(Some(blocks));
+            }
+
+            #[cfg(feature = "indirect")]
+            {
+                let (data, meta) = crate::indirect::collect_blocks(inode_ref)?;
+                let mut data: Vec<(u64, u64)> = data.into_iter().map(|pblk| (pblk, 1)).collect();
+                data.sort_unstable();
+                Ok(Some(InodeBlocks { data, meta: meta.into_iter().collect() }))
+            }
+            #[cfg(not(feature = "indirect"))]
+            {
+                Ok(None)
+            }
+        })
+    }
+
+    /// 缓存中的 inode 与设备上的是否只在 `fdatasync` 不关心的字段上不同
+    fn inode_datasync_clean(&mut self, inode_block: u64, offset: usize) -> Result<bool> {
+        let block_size = self.superblock().block_size() as usize;
+        let mut cached = vec![0u8; block_size];
+        let mut on_disk = vec![0u8; block_size];
+        self.bdev.read_block(inode_block, &mut cached)?;
+        self.bdev.read_blocks_direct(inode_block, 1, &mut on_disk)?;
+
+        Ok(DATASYNC_FIELDS.iter().all(|field| {
+            let range = offset + field.start..offset + field.end;
+            cached[range.clone()] == on_disk[range]
+        }))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_contains_data() {
+        let blocks = InodeBlocks { data: vec![(100, 8), (200, 1)], meta: BTreeSet::new() };
+        assert!(!blocks.contains_data(99));
+        assert!(blocks.contains_data(100));
+        assert!(blocks.contains_data(107));
+        assert!(!blocks.contains_data(108));
+        assert!(blocks.contains_data(200));
+        assert!(!blocks.contains_data(201));
+        assert!(!InodeBlocks::default().contains_data(0));
+    }
+
+    #[test]
+    fn test_datasync_fields() {
+        assert_eq!(DATASYNC_FIELDS[0], 4..8);
+        assert_eq!(DATASYNC_FIELDS[1], 28..36);
+        assert_eq!(DATASYNC_FIELDS[2], 40..100);
+        assert_eq!(DATASYNC_FIELDS[3], 108..112);
+        assert_eq!(DATASYNC_FIELDS[4], 116..118);
+    }
+}
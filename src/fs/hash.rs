@@ -0,0 +1,107 @@
+//! 按 extent 映射流式计算文件内容的摘要
+//!
+//! 哈希算法由调用者通过 [`FileHasher`] 提供，文件系统只负责按顺序送入文件内容：
+//! 有数据的区间从设备读取，空洞和 unwritten extent 直接送入零，不发起设备读。
+//! 用于校验固件等大文件的完整性，调用者不必自己处理稀疏文件。
+
+use crate::{
+    block::{BlockDevice, FsOp},
+    error::Result,
+};
+use alloc::vec;
+
+use super::{copy::COPY_CHUNK_SIZE, filesystem::Ext4FileSystem};
+
+/// 文件内容摘要算法
+///
+/// [`Ext4FileSystem::hash_file`] 按文件偏移顺序调用 `update`，每次的数据长度不固定。
+/// 任何 `FnMut(&[u8])` 闭包也实现了此 trait。
+///
+/// # 示例
+///
+/// ```ignore
+/// let mut crc = 0u32;
+/// fs.hash_file(ino, &mut |data: &[u8]| crc = crc32c_update(crc, data))?;
+/// ```
+pub trait FileHasher {
+    /// 送入下一段文件内容
+    fn update(&mut self, data: &[u8]);
+}
+
+impl<F: FnMut(&[u8])> FileHasher for F {
+    fn update(&mut self, data: &[u8]) {
+        self(data)
+    }
+}
+
+impl<D: BlockDevice> Ext4FileSystem<D> {
+    /// 把普通文件的全部内容按顺序送入 `hasher`，返回送入的字节数（即文件大小）
+    ///
+    /// 空洞按零送入。延迟分配缓存中该文件的数据会先写回，
+    /// 以便从 extent 映射中区分数据和空洞。
+    pub fn hash_file<H: FileHasher + ?Sized>(&mut self, inode_num: u32, hasher: &mut H) -> Result<u64> {
+        self.observe(FsOp::Read, |fs| fs.hash_file_steps(inode_num, hasher))
+    }
+
+    /// `hash_file` 的各个步骤
+    fn hash_file_steps<H: FileHasher + ?Sized>(&mut self, inode_num: u32, hasher: &mut H) -> Result<u64> {
+        self.check_regular_file(inode_num)?;
+        self.check_not_encrypted(inode_num)?;
+        self.commit_pending_size(inode_num)?;
+        self.flush_delalloc_inode(inode_num)?;
+
+        let size = self.with_inode_ref(inode_num, |inode_ref| inode_ref.size())?;
+        if size == 0 {
+            return Ok(0);
+        }
+        let data = self.data_ranges(inode_num, 0, size)?;
+        let mut buf = vec![0u8; COPY_CHUNK_SIZE.min(size as usize)];
+        let mut pos = 0;
+        for (start, stop) in data.into_iter().chain([(size, size)]) {
+            if start > pos {
+                feed_zeros(hasher, start - pos, &mut buf);
+            }
+            pos = start;
+            while pos < stop {
+                let n = ((stop - pos) as usize).min(buf.len());
+                let n = self.read_at_inode(inode_num, &mut buf[..n], pos)?;
+                if n == 0 {
+                    break;
+                }
+                hasher.update(&buf[..n]);
+                pos += n as u64;
+            }
+            // 文件在 extent 末尾之前结束时剩余部分按零处理
+            if pos < stop {
+                feed_zeros(hasher, stop - pos, &mut buf);
+            }
+            pos = pos.max(stop);
+        }
+        Ok(size)
+    }
+}
+
+/// 以 `buf` 大小为单位送入 `len` 个零字节
+fn feed_zeros<H: FileHasher + ?Sized>(hasher: &mut H, mut len: u64, buf: &mut [u8]) {
+    buf.fill(0);
+    while len > 0 {
+        let n = (len as usize).min(buf.len());
+        hasher.update(&buf[..n]);
+        len -= n as u64;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use alloc::vec::Vec;
+
+    #[test]
+    fn test_feed_zeros() {
+        let mut chunks = Vec::new();
+        let mut buf = [0xAAu8; 4];
+        feed_zeros(&mut |data: &[u8]| chunks.push(data.to_vec()), 10, &mut buf);
+        assert_eq!(chunks, [vec![0u8; 4], vec![0u8; 4], vec![0u8; 2]]);
+        feed_zeros(&mut |_: &[u8]| panic!("no data expected"), 0, &mut buf);
+    }
+}
@@ -0,0 +1,614 @@
+//! 从 tar 流填充文件系统，[`export`](super::export) 的逆操作
+//!
+//! 在 [`Ext4FileSystem::batch`] 中创建目录、文件、符号链接、硬链接、设备文件和扩展属性，
+//! 整个导入只提交一次，可以完全在 Rust 中构建固件镜像。支持的格式：
+//! - ustar 以及 PAX 扩展头（`path`、`linkpath`、`size`、`uid`、`gid`、`mtime`、
+//!   `SCHILY.xattr.<name>`），全局 PAX 头被忽略
+//! - GNU 长文件名（`L`/`K`）和 GNU sparse 1.0 格式的稀疏文件
+//!
+//! 条目路径中的 `..` 会被拒绝，不会写到目标目录之外。归档中没有列出的父目录以 0o755 创建。
+//!
+//! ```rust,ignore
+//! use lwext4_core::import::{self, ImportOptions};
+//!
+//! let summary = import::import_tar(&mut fs, "/", &mut &tar[..], &ImportOptions::default())?;
+//! ```
+
+use crate::{
+    block::BlockDevice,
+    error::{Error, ErrorKind, Result},
+};
+use alloc::{string::String, vec, vec::Vec};
+
+use super::{
+    batch::Batch,
+    copy::COPY_CHUNK_SIZE,
+    filesystem::Ext4FileSystem,
+    special::makedev,
+    types::InodeType,
+};
+
+/// tar 块大小
+const TAR_BLOCK: usize = 512;
+
+/// 归档中没有列出的父目录的权限
+const DIR_MODE: u16 = 0o755;
+
+/// tar 流的输入端，与 `std::io::Read::read` 相同
+///
+/// 返回读取的字节数，0 表示流结束。`&[u8]` 实现了此 trait；
+/// 启用 `std` 特性时可以用 [`IoSource`] 包装任意 `std::io::Read`
+pub trait TarSource {
+    /// 读取数据到 `buf`
+    fn read(&mut self, buf: &mut [u8]) -> Result<usize>;
+}
+
+impl TarSource for &[u8] {
+    fn read(&mut self, buf: &mut [u8]) -> Result<usize> {
+        let n = buf.len().min(self.len());
+        let (head, tail) = self.split_at(n);
+        buf[..n].copy_from_slice(head);
+        *self = tail;
+        Ok(n)
+    }
+}
+
+/// 把 `std::io::Read` 用作 [`TarSource`]
+#[cfg(feature = "std")]
+pub struct IoSource<R>(pub R);
+
+#[cfg(feature = "std")]
+impl<R: std::io::Read> TarSource for IoSource<R> {
+    fn read(&mut self, buf: &mut [u8]) -> Result<usize> {
+        self.0
+            .read(buf)
+            .map_err(|_| Error::new(ErrorKind::Io, "Failed to read tar stream"))
+    }
+}
+
+/// 导入选项
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ImportOptions {
+    /// 导入扩展属性
+    pub xattrs: bool,
+    /// 使用归档中的属主和属组；关闭时按 [`CreatePolicy`](super::CreatePolicy) 设置
+    pub ownership: bool,
+}
+
+impl Default for ImportOptions {
+    fn default() -> Self {
+        Self { xattrs: true, ownership: true }
+    }
+}
+
+/// 导入结果统计
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct ImportSummary {
+    /// 创建的条目数（包括硬链接，不含自动创建的父目录）
+    pub entries: u64,
+    /// 写入的文件数据字节数（不含空洞）
+    pub data_bytes: u64,
+    /// 不支持而跳过的条目数（如 GNU sparse 0.x 格式的稀疏文件和卷标）
+    pub skipped: u64,
+}
+
+/// 把 tar 流中的内容导入 `path` 目录
+///
+/// 条目的权限和修改时间取自归档。表示目标目录本身的条目（如 `./`）被忽略，
+/// 已经存在的目录只更新属性。
+///
+/// # 错误
+///
+/// - `ErrorKind::Corrupted` - tar 头的校验和不匹配或字段无法解析
+/// - `ErrorKind::InvalidInput` - 条目路径包含 `..`、不是 UTF-8，或流在条目中间结束
+/// - `ErrorKind::AlreadyExists` - 非目录条目已经存在
+/// - `ErrorKind::NotFound` - 硬链接指向的文件不在目标目录中
+///
+/// 出错时已经导入的条目会保留
+pub fn import_tar<D: BlockDevice, R: TarSource + ?Sized>(
+    fs: &mut Ext4FileSystem<D>,
+    path: &str,
+    source: &mut R,
+    options: &ImportOptions,
+) -> Result<ImportSummary> {
+    let dest = String::from(path.trim_end_matches('/'));
+    fs.batch(|tx| {
+        tx.lookup(if dest.is_empty() { "/" } else { &dest })?;
+        let mut importer = Importer {
+            tx,
+            source,
+            dest,
+            options: *options,
+            summary: ImportSummary::default(),
+            buf: Vec::new(),
+        };
+        importer.run()?;
+        Ok(importer.summary)
+    })
+}
+
+/// 下一个条目的扩展信息（PAX 扩展头或 GNU 长文件名）
+#[derive(Default)]
+struct PendingAttrs {
+    path: Option<String>,
+    linkpath: Option<String>,
+    size: Option<u64>,
+    uid: Option<u32>,
+    gid: Option<u32>,
+    mtime: Option<(i64, u32)>,
+    xattrs: Vec<(String, Vec<u8>)>,
+    sparse_name: Option<String>,
+    sparse_realsize: Option<u64>,
+    sparse_v1: bool,
+    /// GNU sparse 0.x 格式的记录，这种格式不支持
+    sparse_legacy: bool,
+}
+
+/// 从 ustar 头解析出的条目
+struct Entry {
+    path: String,
+    linkpath: String,
+    typeflag: u8,
+    mode: u16,
+    uid: u32,
+    gid: u32,
+    size: u64,
+    mtime: (i64, u32),
+    rdev: u64,
+}
+
+/// 一次导入的状态
+struct Importer<'a, 'b, D: BlockDevice, R: TarSource + ?Sized> {
+    tx: &'a mut Batch<'b, D>,
+    source: &'a mut R,
+    /// 目标目录（不带末尾的 `/`，根目录为空字符串）
+    dest: String,
+    options: ImportOptions,
+    summary: ImportSummary,
+    /// 读取文件数据的缓冲区
+    buf: Vec<u8>,
+}
+
+impl<D: BlockDevice, R: TarSource + ?Sized> Importer<'_, '_, D, R> {
+    fn run(&mut self) -> Result<()> {
+        let mut pending = PendingAttrs::default();
+        let mut block = [0u8; TAR_BLOCK];
+        loop {
+            // 流在条目之间结束或遇到全零块都视为归档结束
+            if !self.read_block(&mut block)? || block.iter().all(|&b| b == 0) {
+                return Ok(());
+            }
+            let entry = parse_header(&block, &pending)?;
+            match entry.typeflag {
+                b'x' => {
+                    let data = self.read_data(entry.size)?;
+                    parse_pax(&data, &mut pending)?;
+                }
+                b'L' | b'K' => {
+                    let data = self.read_data(entry.size)?;
+                    let end = data.iter().position(|&b| b == 0).unwrap_or(data.len());
+                    let name = utf8(data[..end].to_vec())?;
+                    match entry.typeflag {
+                        b'L' => pending.path = Some(name),
+                        _ => pending.linkpath = Some(name),
+                    }
+                }
+                b'g' => self.skip(entry.size)?,
+                _ => {
+                    let attrs = core::mem::take(&mut pending);
+                    self.import_entry(entry, attrs)?;
+                }
+            }
+        }
+    }
+
+    fn import_entry(&mut self, mut entry: Entry, attrs: PendingAttrs) -> Result<()> {
+        if attrs.sparse_legacy && !attrs.sparse_v1 {
+            self.summary.skipped += 1;
+            return self.skip(entry.size);
+        }
+        if attrs.sparse_v1 {
+            entry.path = attrs.sparse_name.clone().unwrap_or(entry.path);
+        }
+        let Some(rel) = normalize(&entry.path)? else {
+            return self.skip(entry.size);
+        };
+        let full = self.join(&rel);
+        let (parent, name) = match full.rsplit_once('/') {
+            Some(("", name)) => ("/", name),
+            Some((parent, name)) => (parent, name),
+            None => ("/", full.as_str()),
+        };
+        if entry.typeflag != b'5' {
+            self.tx.create_dir_all(parent, DIR_MODE)?;
+        }
+
+        let ino = match entry.typeflag {
+            b'0' | b'\0' | b'7' => {
+                let ino = self.tx.create_file(parent, name, entry.mode)?;
+                match attrs.sparse_realsize.filter(|_| attrs.sparse_v1) {
+                    Some(realsize) => self.write_sparse(ino, entry.size, realsize)?,
+                    None => self.write_data(ino, 0, entry.size)?,
+                }
+                ino
+            }
+            b'5' => self.tx.create_dir_all(&full, entry.mode)?,
+            b'2' => self.tx.symlink(&entry.linkpath, parent, name)?,
+            b'1' => {
+                let target = normalize(&entry.linkpath)?
+                    .ok_or(Error::new(ErrorKind::InvalidInput, "Hard link to the destination directory"))?;
+                let target = self.tx.lookup(&self.join(&target))?;
+                self.tx.link(target, parent, name)?;
+                self.skip(entry.size)?;
+                self.summary.entries += 1;
+                return Ok(());
+            }
+            b'3' | b'4' | b'6' => {
+                let node_type = match entry.typeflag {
+                    b'3' => InodeType::CharacterDevice,
+                    b'4' => InodeType::BlockDevice,
+                    _ => InodeType::Fifo,
+                };
+                self.tx.mknod(parent, name, node_type, entry.mode, entry.rdev)?
+            }
+            _ => {
+                self.summary.skipped += 1;
+                return self.skip(entry.size);
+            }
+        };
+
+        let ownership = self.options.ownership.then_some((entry.uid, entry.gid));
+        self.tx.with_inode_ref(ino, |inode_ref| {
+            inode_ref.set_mode(entry.mode)?;
+            if let Some((uid, gid)) = ownership {
+                inode_ref.set_owner(uid, gid)?;
+            }
+            inode_ref.set_mtime_ns(entry.mtime.0, entry.mtime.1)
+        })?;
+        if self.options.xattrs {
+            for (key, value) in &attrs.xattrs {
+                self.tx.setxattr(ino, key, value)?;
+            }
+        }
+        self.summary.entries += 1;
+        Ok(())
+    }
+
+    /// 目标目录中的绝对路径
+    fn join(&self, rel: &str) -> String {
+        let mut path = self.dest.clone();
+        path.push('/');
+        path.push_str(rel);
+        path
+    }
+
+    /// 从流中读取 `len` 字节写入文件的 `offset` 处，不处理块对齐
+    fn copy_to_file(&mut self, ino: u32, offset: u64, len: u64) -> Result<()> {
+        let mut done = 0;
+        while done < len {
+            let n = ((len - done) as usize).min(COPY_CHUNK_SIZE);
+            self.buf.resize(n, 0);
+            let mut buf = core::mem::take(&mut self.buf);
+            let result = self.read_exact(&mut buf[..n]).and_then(|()| self.tx.write(ino, &buf[..n], offset + done));
+            self.buf = buf;
+            result?;
+            done += n as u64;
+        }
+        self.summary.data_bytes += len;
+        Ok(())
+    }
+
+    /// 写入普通文件的 `len` 字节数据并跳过块对齐的填充
+    fn write_data(&mut self, ino: u32, offset: u64, len: u64) -> Result<()> {
+        self.copy_to_file(ino, offset, len)?;
+        self.skip_padding(len)
+    }
+
+    /// 写入 GNU sparse 1.0 格式的数据：开头是稀疏映射，之后依次是各个有数据的区间
+    fn write_sparse(&mut self, ino: u32, size: u64, realsize: u64) -> Result<()> {
+        let (ranges, map_len) = self.read_sparse_map()?;
+        let data_len: u64 = ranges.iter().map(|&(_, len)| len).sum();
+        if map_len + data_len != size {
+            return Err(Error::new(ErrorKind::Corrupted, "Sparse map does not match entry size"));
+        }
+        for (offset, len) in ranges {
+            self.copy_to_file(ino, offset, len)?;
+        }
+        self.skip_padding(size)?;
+        self.tx.with_inode_ref(ino, |inode_ref| {
+            if inode_ref.size()? < realsize {
+                inode_ref.set_size(realsize)?;
+            }
+            Ok(())
+        })
+    }
+
+    /// 读取稀疏映射，返回 (偏移, 长度) 列表和映射占用的字节数（块对齐）
+    fn read_sparse_map(&mut self) -> Result<(Vec<(u64, u64)>, u64)> {
+        let mut block = [0u8; TAR_BLOCK];
+        let mut text = Vec::new();
+        let mut numbers = Vec::new();
+        let mut map_len = 0;
+        let mut count = None;
+        while count.is_none_or(|n: usize| numbers.len() < 1 + 2 * n) {
+            if !self.read_block(&mut block)? {
+                return Err(Error::new(ErrorKind::InvalidInput, "Unexpected end of tar stream"));
+            }
+            map_len += TAR_BLOCK as u64;
+            for &b in &block {
+                if b != b'\n' {
+                    text.push(b);
+                    continue;
+                }
+                numbers.push(parse_decimal(&text)?);
+                text.clear();
+                if numbers.len() == 1 {
+                    count = Some(numbers[0] as usize);
+                }
+                if count.is_some_and(|n| numbers.len() == 1 + 2 * n) {
+                    break;
+                }
+            }
+        }
+        let ranges = numbers[1..].chunks(2).map(|pair| (pair[0], pair[1])).collect();
+        Ok((ranges, map_len))
+    }
+
+    /// 读取一个 tar 块，流在块开始处结束时返回 `false`
+    fn read_block(&mut self, block: &mut [u8; TAR_BLOCK]) -> Result<bool> {
+        let n = self.source.read(block)?;
+        if n == 0 {
+            return Ok(false);
+        }
+        self.read_exact(&mut block[n..])?;
+        Ok(true)
+    }
+
+    fn read_exact(&mut self, mut buf: &mut [u8]) -> Result<()> {
+        while !buf.is_empty() {
+            let n = self.source.read(buf)?;
+            if n == 0 {
+                return Err(Error::new(ErrorKind::InvalidInput, "Unexpected end of tar stream"));
+            }
+            buf = &mut buf[n..];
+        }
+        Ok(())
+    }
+
+    /// 读取条目的全部数据（PAX 头、长文件名）
+    fn read_data(&mut self, len: u64) -> Result<Vec<u8>> {
+        if len > COPY_CHUNK_SIZE as u64 {
+            return Err(Error::new(ErrorKind::Unsupported, "Tar extended header too large"));
+        }
+        let mut data = vec![0u8; len as usize];
+        self.read_exact(&mut data)?;
+        self.skip_padding(len)?;
+        Ok(data)
+    }
+
+    /// 跳过 `len` 字节的条目数据及其填充
+    fn skip(&mut self, len: u64) -> Result<()> {
+        let mut left = len.next_multiple_of(TAR_BLOCK as u64);
+        let mut block = [0u8; TAR_BLOCK];
+        while left > 0 {
+            self.read_exact(&mut block)?;
+            left -= TAR_BLOCK as u64;
+        }
+        Ok(())
+    }
+
+    /// 跳过 `len` 字节数据之后到块边界的填充
+    fn skip_padding(&mut self, len: u64) -> Result<()> {
+        let rem = (len % TAR_BLOCK as u64) as usize;
+        if rem != 0 {
+            let mut pad = [0u8; TAR_BLOCK];
+            self.read_exact(&mut pad[rem..])?;
+        }
+        Ok(())
+    }
+}
+
+/// 解析 ustar 头，`pending` 中的扩展信息优先
+fn parse_header(block: &[u8; TAR_BLOCK], pending: &PendingAttrs) -> Result<Entry> {
+    let stored = parse_octal(&block[148..156])?;
+    let sum: u64 = block
+        .iter()
+        .enumerate()
+        .map(|(i, &b)| if (148..156).contains(&i) { b' ' as u64 } else { b as u64 })
+        .sum();
+    if stored != sum {
+        return Err(Error::new(ErrorKind::Corrupted, "Tar header checksum mismatch"));
+    }
+
+    let mut path = field_str(&block[0..100])?;
+    if &block[257..262] == b"ustar" {
+        let prefix = field_str(&block[345..500])?;
+        if !prefix.is_empty() {
+            path = alloc::format!("{prefix}/{path}");
+        }
+    }
+    let linkpath = field_str(&block[157..257])?;
+    Ok(Entry {
+        path: pending.path.clone().unwrap_or(path),
+        linkpath: pending.linkpath.clone().unwrap_or(linkpath),
+        typeflag: block[156],
+        mode: (parse_octal(&block[100..108])? & 0o7777) as u16,
+        uid: pending.uid.unwrap_or(parse_octal(&block[108..116])? as u32),
+        gid: pending.gid.unwrap_or(parse_octal(&block[116..124])? as u32),
+        size: pending.size.map_or_else(|| parse_octal(&block[124..136]), Ok)?,
+        mtime: pending.mtime.map_or_else(|| parse_octal(&block[136..148]).map(|t| (t as i64, 0)), Ok)?,
+        rdev: makedev(parse_octal(&block[329..337])? as u32, parse_octal(&block[337..345])? as u32),
+    })
+}
+
+/// 解析 PAX 扩展头的记录
+fn parse_pax(mut data: &[u8], pending: &mut PendingAttrs) -> Result<()> {
+    const BAD: Error = Error::new(ErrorKind::Corrupted, "Malformed PAX record");
+    while !data.is_empty() {
+        let space = data.iter().position(|&b| b == b' ').ok_or(BAD)?;
+        let len = parse_decimal(&data[..space])? as usize;
+        if len <= space || len > data.len() || data[len - 1] != b'\n' {
+            return Err(BAD);
+        }
+        let record = &data[space + 1..len - 1];
+        data = &data[len..];
+        let eq = record.iter().position(|&b| b == b'=').ok_or(BAD)?;
+        let (key, value) = (&record[..eq], &record[eq + 1..]);
+        let text = || utf8(value.to_vec());
+        match key {
+            b"path" => pending.path = Some(text()?),
+            b"linkpath" => pending.linkpath = Some(text()?),
+            b"size" => pending.size = Some(parse_decimal(value)?),
+            b"uid" => pending.uid = Some(parse_decimal(value)? as u32),
+            b"gid" => pending.gid = Some(parse_decimal(value)? as u32),
+            b"mtime" => pending.mtime = Some(parse_pax_time(value)?),
+            b"GNU.sparse.major" => pending.sparse_v1 = value == b"1",
+            b"GNU.sparse.name" => pending.sparse_name = Some(text()?),
+            b"GNU.sparse.realsize" => pending.sparse_realsize = Some(parse_decimal(value)?),
+            b"GNU.sparse.size" | b"GNU.sparse.numblocks" | b"GNU.sparse.map" => pending.sparse_legacy = true,
+            _ => {
+                if let Some(name) = key.strip_prefix(b"SCHILY.xattr.") {
+                    pending.xattrs.push((utf8(name.to_vec())?, value.to_vec()));
+                }
+            }
+        }
+    }
+    Ok(())
+}
+
+/// 条目路径相对于归档根目录的规范形式，去掉 `.` 和空组件；表示根目录本身时返回 `None`
+fn normalize(path: &str) -> Result<Option<String>> {
+    let mut out = String::new();
+    for name in path.split('/').filter(|name| !name.is_empty() && *name != ".") {
+        if name == ".." {
+            return Err(Error::new(ErrorKind::InvalidInput, "Tar entry path contains '..'"));
+        }
+        if !out.is_empty() {
+            out.push('/');
+        }
+        out.push_str(name);
+    }
+    Ok((!out.is_empty()).then_some(out))
+}
+
+/// 以 NUL 结尾的定长字符串字段
+fn field_str(field: &[u8]) -> Result<String> {
+    let end = field.iter().position(|&b| b == 0).unwrap_or(field.len());
+    utf8(field[..end].to_vec())
+}
+
+fn utf8(bytes: Vec<u8>) -> Result<String> {
+    String::from_utf8(bytes).map_err(|_| Error::new(ErrorKind::InvalidInput, "Tar entry name is not valid UTF-8"))
+}
+
+/// 解析八进制数值字段，首字节最高位为 1 时按 GNU 的 base-256 编码解析
+fn parse_octal(field: &[u8]) -> Result<u64> {
+    if field.first().is_some_and(|&b| b & 0x80 != 0) {
+        let mut value = (field[0] & 0x7F) as u64;
+        for &b in &field[1..] {
+            value = value
+                .checked_mul(256)
+                .ok_or(Error::new(ErrorKind::Corrupted, "Tar numeric field overflow"))?
+                | b as u64;
+        }
+        return Ok(value);
+    }
+    let mut value = 0u64;
+    for &b in field.iter().skip_while(|&&b| b == b' ') {
+        match b {
+            b'0'..=b'7' => {
+                value = value
+                    .checked_mul(8)
+                    .ok_or(Error::new(ErrorKind::Corrupted, "Tar numeric field overflow"))?
+                    + (b - b'0') as u64
+            }
+            b' ' | 0 => break,
+            _ => return Err(Error::new(ErrorKind::Corrupted, "Invalid octal field in tar header")),
+        }
+    }
+    Ok(value)
+}
+
+fn parse_decimal(text: &[u8]) -> Result<u64> {
+    const BAD: Error = Error::new(ErrorKind::Corrupted, "Invalid decimal number in tar stream");
+    if text.is_empty() {
+        return Err(BAD);
+    }
+    text.iter().try_fold(0u64, |value, &b| match b {
+        b'0'..=b'9' => value.checked_mul(10).and_then(|v| v.checked_add((b - b'0') as u64)).ok_or(BAD),
+        _ => Err(BAD),
+    })
+}
+
+/// 解析 PAX 时间（秒，可以带小数部分和负号）
+fn parse_pax_time(value: &[u8]) -> Result<(i64, u32)> {
+    let (negative, value) = match value.strip_prefix(b"-") {
+        Some(rest) => (true, rest),
+        None => (false, value),
+    };
+    let (sec, frac) = match value.iter().position(|&b| b == b'.') {
+        Some(dot) => (&value[..dot], &value[dot + 1..]),
+        None => (value, &[][..]),
+    };
+    let sec = parse_decimal(sec)? as i64;
+    let mut nsec = 0u32;
+    for i in 0..9 {
+        let digit = match frac.get(i) {
+            Some(&b @ b'0'..=b'9') => (b - b'0') as u32,
+            Some(_) => return Err(Error::new(ErrorKind::Corrupted, "Invalid PAX time")),
+            None => 0,
+        };
+        nsec = nsec * 10 + digit;
+    }
+    // 负的时间向下取整：-1.5 秒即 -2 秒加 0.5 秒
+    Ok(match (negative, nsec) {
+        (false, _) => (sec, nsec),
+        (true, 0) => (-sec, 0),
+        (true, _) => (-sec - 1, 1_000_000_000 - nsec),
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_normalize() {
+        assert_eq!(normalize("./etc//passwd").unwrap().as_deref(), Some("etc/passwd"));
+        assert_eq!(normalize("/usr/bin/").unwrap().as_deref(), Some("usr/bin"));
+        assert_eq!(normalize("./").unwrap(), None);
+        assert_eq!(normalize("a/../../etc").unwrap_err().kind(), ErrorKind::InvalidInput);
+    }
+
+    #[test]
+    fn test_parse_octal() {
+        assert_eq!(parse_octal(b"0000644\0").unwrap(), 0o644);
+        assert_eq!(parse_octal(b"  755 \0\0").unwrap(), 0o755);
+        assert_eq!(parse_octal(b"\0\0\0\0").unwrap(), 0);
+        assert_eq!(parse_octal(&[0x80, 0, 0, 0, 0, 0, 0, 2, 0, 0, 0, 0]).unwrap(), 1 << 33);
+        assert!(parse_octal(b"0000899\0").is_err());
+    }
+
+    #[test]
+    fn test_parse_pax() {
+        let mut data = Vec::new();
+        data.extend_from_slice(b"16 path=a/b/c/d\n");
+        data.extend_from_slice(b"19 mtime=12.500000\n");
+        data.extend_from_slice(b"27 SCHILY.xattr.user.k=v\0w\n");
+        let mut pending = PendingAttrs::default();
+        parse_pax(&data, &mut pending).unwrap();
+        assert_eq!(pending.path.as_deref(), Some("a/b/c/d"));
+        assert_eq!(pending.mtime, Some((12, 500_000_000)));
+        assert_eq!(pending.xattrs, [(String::from("user.k"), b"v\0w".to_vec())]);
+
+        assert!(parse_pax(b"99 path=x\n", &mut pending).is_err());
+    }
+
+    #[test]
+    fn test_parse_pax_time() {
+        assert_eq!(parse_pax_time(b"100").unwrap(), (100, 0));
+        assert_eq!(parse_pax_time(b"-1.25").unwrap(), (-2, 750_000_000));
+        assert_eq!(parse_pax_time(b"-3").unwrap(), (-3, 0));
+        assert!(parse_pax_time(b"1.x").is_err());
+    }
+}
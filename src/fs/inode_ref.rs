@@ -7,8 +7,13 @@ use crate::{
     consts::*,
     error::{Error, ErrorKind, Result},
     extent::ExtentTree,
+    inode::{
+        checksum::{set_checksum_raw, verify_checksum_raw},
+        time::{get_time, set_time, InodeTime, NSEC_PER_SEC},
+    },
+    quota::QuotaOwner,
     superblock::Superblock,
-    types::ext4_inode,
+    types::{ext4_inode, Pblk},
 };
 
 /// Inode 引用
@@ -24,6 +29,9 @@ use crate::{
 /// 2. **性能**: 避免不必要的数据复制
 /// 3. **正确语义**: 修改直接作用于 cache，自动标记为脏
 ///
+/// 启用 `metadata_csum` 时，[`get`](Self::get) 按 `FsConfig::verify_checksums` 验证 inode 校验和，
+/// 所有修改 inode 的方法在修改后自动重新计算校验和，调用者无需自行维护。
+///
 /// # 生命周期
 ///
 /// - 创建时获取包含 inode 的 block 句柄
@@ -85,28 +93,37 @@ impl<'a, D: BlockDevice> InodeRef<'a, D> {
             ));
         }
 
-        // 计算 inode 所在的块组和索引
-        let inodes_per_group = sb.inodes_per_group();
-        let block_group = (inode_num - 1) / inodes_per_group;
-        let index_in_group = (inode_num - 1) % inodes_per_group;
-
-        // 读取块组描述符以获取 inode 表位置
-        // 注意：这里我们需要临时读取块组描述符，不需要持有 BlockGroupRef
-        let inode_table_block = {
-            use crate::block_group::BlockGroup;
-            let bg = BlockGroup::load(bdev, sb, block_group)?;
-            bg.get_inode_table_first_block(sb)
-        };
-
-        // 计算 inode 在 inode 表中的位置
-        let block_size = sb.block_size() as u64;
+        let (inode_block_addr, offset_in_block) = inode_location(bdev, sb, inode_num)?;
         let inode_size = sb.inode_size() as u64;
-        let inodes_per_block = block_size / inode_size;
 
-        // 计算 inode 所在的块号和块内偏移
-        let block_index = index_in_group as u64 / inodes_per_block;
-        let offset_in_block = ((index_in_group as u64 % inodes_per_block) * inode_size) as usize;
-        let inode_block_addr = inode_table_block + block_index;
+        if sb.verify_checksums() {
+            let mut block = Block::get(bdev, inode_block_addr)?;
+            let ok = block.with_data(|data| {
+                let raw = &data[offset_in_block..offset_in_block + inode_size as usize];
+                verify_checksum_raw(sb, inode_num, raw)
+            })?;
+            if !ok {
+                return Err(Error::with_block(
+                    ErrorKind::Corrupted,
+                    "Inode checksum mismatch",
+                    inode_block_addr,
+                ));
+            }
+        }
+
+        Self::get_unverified(bdev, sb, inode_num)
+    }
+
+    /// 获取刚分配的 inode 的引用，不验证校验和
+    ///
+    /// inode 表中从未使用过的 inode 全为零，没有有效的校验和；
+    /// 初始化时第一次修改会写入校验和
+    pub(crate) fn get_unverified(
+        bdev: &'a mut BlockDev<D>,
+        sb: &'a mut Superblock,
+        inode_num: u32,
+    ) -> Result<Self> {
+        let (inode_block_addr, offset_in_block) = inode_location(bdev, sb, inode_num)?;
 
         Ok(Self {
             bdev,
@@ -124,6 +141,16 @@ impl<'a, D: BlockDevice> InodeRef<'a, D> {
         self.inode_num
     }
 
+    /// 块映射缓存 `(起始逻辑块, 块数, 起始物理块)`
+    pub(super) fn block_map_cache(&self) -> Option<(u32, u32, u64)> {
+        self.block_map_cache
+    }
+
+    /// 使用已知有效的块映射缓存（例如打开 inode 表中共享的缓存）
+    pub(super) fn set_block_map_cache(&mut self, cache: Option<(u32, u32, u64)>) {
+        self.block_map_cache = cache;
+    }
+
     /// 获取可变 Superblock 引用
     ///
     /// 注意：此方法仅供内部 API 使用，用于解决某些遗留 API 的借用冲突
@@ -149,17 +176,23 @@ impl<'a, D: BlockDevice> InodeRef<'a, D> {
 
     /// 访问 inode 数据（可写）
     ///
-    /// 通过闭包修改 inode 数据，自动标记 block 为脏
+    /// 通过闭包修改 inode 数据，自动标记 block 为脏并重新计算 inode 校验和
     pub fn with_inode_mut<F, R>(&mut self, f: F) -> Result<R>
     where
         F: FnOnce(&mut ext4_inode) -> R,
     {
+        let sb: &Superblock = self.sb;
+        let inode_num = self.inode_num;
+        let start = self.offset_in_block;
+        let end = start + sb.inode_size() as usize;
         let mut block = Block::get(self.bdev, self.inode_block_addr)?;
         let result = block.with_data_mut(|data| {
             let inode = unsafe {
-                &mut *(data.as_mut_ptr().add(self.offset_in_block) as *mut ext4_inode)
+                &mut *(data.as_mut_ptr().add(start) as *mut ext4_inode)
             };
-            f(inode)
+            let result = f(inode);
+            set_checksum_raw(sb, inode_num, &mut data[start..end]);
+            result
         })?;
         self.dirty = true;
         Ok(result)
@@ -200,7 +233,7 @@ impl<'a, D: BlockDevice> InodeRef<'a, D> {
 
     /// 访问 inode 原始字节数据（可写）
     ///
-    /// 提供对完整 inode 区域的可变字节切片访问。
+    /// 提供对完整 inode 区域的可变字节切片访问，修改后自动重新计算 inode 校验和。
     /// 修改会自动标记 block 为脏。
     ///
     /// # 参数
@@ -220,13 +253,16 @@ impl<'a, D: BlockDevice> InodeRef<'a, D> {
     where
         F: FnOnce(&mut [u8]) -> R,
     {
-        let inode_size = self.sb.inode_size() as usize;
+        let sb: &Superblock = self.sb;
+        let inode_num = self.inode_num;
+        let start = self.offset_in_block;
+        let end = start + sb.inode_size() as usize;
         let mut block = Block::get(self.bdev, self.inode_block_addr)?;
         let result = block.with_data_mut(|data| {
-            let start = self.offset_in_block;
-            let end = start + inode_size;
             let inode_data = &mut data[start..end];
-            f(inode_data)
+            let result = f(inode_data);
+            set_checksum_raw(sb, inode_num, inode_data);
+            result
         })?;
         self.dirty = true;
         Ok(result)
@@ -249,6 +285,7 @@ impl<'a, D: BlockDevice> InodeRef<'a, D> {
     /// 获取块设备和 superblock 的可变引用
     ///
     /// 用于避免双重借用问题，当需要同时使用 bdev 和 sb 时使用此方法
+    #[cfg_attr(not(feature = "xattr"), allow(dead_code))]
     pub(crate) fn bdev_and_sb_mut(&mut self) -> (&mut BlockDev<D>, &mut Superblock) {
         (self.bdev, self.sb)
     }
@@ -335,71 +372,65 @@ impl<'a, D: BlockDevice> InodeRef<'a, D> {
     }
 
     /// 获取 blocks 计数（512 字节单位）
+    ///
+    /// inode 带 `HUGE_FILE` 标志时 `i_blocks` 以文件系统块为单位，这里统一换算为 512 字节单位
     pub fn blocks_count(&mut self) -> Result<u64> {
-        // 先提取需要的 superblock 信息
-        let has_huge_file = self.sb.has_ro_compat_feature(EXT4_FEATURE_RO_COMPAT_HUGE_FILE);
-        let block_size = self.sb.block_size();
-
-        self.with_inode(|inode| {
-            // 读取 32 位低位
-            let mut cnt = u32::from_le(inode.blocks_count_lo) as u64;
-
-            // 检查是否启用了 HUGE_FILE 特性
-            if has_huge_file {
-                // 扩展到 48 位
-                cnt |= (u16::from_le(inode.blocks_high) as u64) << 32;
-
-                // 检查 inode 是否使用了 HUGE_FILE 标志
-                let flags = u32::from_le(inode.flags);
-                if flags & EXT4_INODE_FLAG_HUGE_FILE != 0 {
-                    // 进行比例换算：从文件系统块单位转换为 512 字节单位
-                    let block_bits = inode_block_bits_count(block_size);
-                    return cnt << (block_bits - 9);
-                }
-            }
-
-            cnt
-        })
+        let inode = self.with_inode(|inode| *inode)?;
+        Ok(raw_blocks_count(self.sb, &inode))
     }
 
     /// 设置 blocks 计数（512 字节单位）
+    ///
+    /// 对应内核的 `ext4_inode_blocks_set()`：超过 32 位时需要 `HUGE_FILE` 特性（未设置时自动
+    /// 设置并写回 superblock），超过 48 位时为 inode 设置 `HUGE_FILE` 标志并改用文件系统块
+    /// 为单位存储
+    ///
+    /// 启用配额时按变化量调整属主的已用空间（不检查限制，见
+    /// [`quota_check_blocks`](Self::quota_check_blocks)）。EA inode 的块已经
+    /// 计入拥有该属性的 inode，不再单独计入配额
+    ///
+    /// # 错误
+    ///
+    /// - `ErrorKind::FileTooLarge` - 以文件系统块为单位仍超出 48 位
     pub fn set_blocks_count(&mut self, count: u64) -> Result<()> {
-        // 先提取需要的 superblock 信息
-        let block_size = self.sb.block_size();
-
-        self.with_inode_mut(|inode| {
-            // 32 位最大值
-            let max_32bit: u64 = 0xFFFFFFFF;
+        // 32 位最大值
+        let max_32bit: u64 = 0xFFFFFFFF;
+        // 48 位最大值
+        let max_48bit: u64 = 0xFFFFFFFFFFFF;
+
+        let block_bits = inode_block_bits_count(self.sb.block_size());
+        if count > max_48bit && count >> (block_bits - 9) > max_48bit {
+            return Err(Error::new(ErrorKind::FileTooLarge, "Block count exceeds 48 bits"));
+        }
 
-            if count <= max_32bit {
-                // 可以用 32 位表示
-                inode.blocks_count_lo = (count as u32).to_le();
-                inode.blocks_high = 0;
-                let flags = u32::from_le(inode.flags);
-                inode.flags = (flags & !EXT4_INODE_FLAG_HUGE_FILE).to_le();
-                return;
+        let is_ea_inode = self.with_inode(|inode| u32::from_le(inode.flags) & EXT4_INODE_FLAG_EA_INODE != 0)?;
+        if self.quota_accounted() && !is_ea_inode {
+            let old = self.blocks_count()?;
+            let owner = self.quota_owner()?;
+            if let Some(quota) = self.sb.quota_mut() {
+                quota.charge(&owner, (count as i64 - old as i64) * 512, 0);
             }
+        }
 
-            // 48 位最大值
-            let max_48bit: u64 = 0xFFFFFFFFFFFF;
+        if count > max_32bit && !self.sb.has_ro_compat_feature(EXT4_FEATURE_RO_COMPAT_HUGE_FILE) {
+            self.sb.set_ro_compat_feature(EXT4_FEATURE_RO_COMPAT_HUGE_FILE);
+            self.sb.write(self.bdev)?;
+        }
+
+        self.with_inode_mut(|inode| {
+            let flags = u32::from_le(inode.flags);
 
             if count <= max_48bit {
                 // 可以用 48 位表示（不需要比例换算）
                 inode.blocks_count_lo = (count as u32).to_le();
                 inode.blocks_high = ((count >> 32) as u16).to_le();
-                let flags = u32::from_le(inode.flags);
                 inode.flags = (flags & !EXT4_INODE_FLAG_HUGE_FILE).to_le();
             } else {
-                // 需要使用 HUGE_FILE 标志和比例换算
-                let block_bits = inode_block_bits_count(block_size);
-
-                let flags = u32::from_le(inode.flags);
-                inode.flags = (flags | EXT4_INODE_FLAG_HUGE_FILE).to_le();
-
-                // 从 512 字节单位转换为文件系统块单位
+                // 需要使用 HUGE_FILE 标志和比例换算：从 512 字节单位转换为文件系统块单位
                 let scaled_count = count >> (block_bits - 9);
                 inode.blocks_count_lo = (scaled_count as u32).to_le();
                 inode.blocks_high = ((scaled_count >> 32) as u16).to_le();
+                inode.flags = (flags | EXT4_INODE_FLAG_HUGE_FILE).to_le();
             }
         })
     }
@@ -425,10 +456,110 @@ impl<'a, D: BlockDevice> InodeRef<'a, D> {
         let block_size = self.sb.block_size();
         let blocks_512 = blocks as u64 * (block_size as u64 / 512);
         let current = self.blocks_count()?;
-        if current >= blocks_512 {
-            self.set_blocks_count(current - blocks_512)
-        } else {
-            self.set_blocks_count(0)
+        self.set_blocks_count(current.saturating_sub(blocks_512))
+    }
+
+    /// 检查目录能否再增加一个子目录
+    ///
+    /// 对应内核的 `EXT4_DIR_LINK_MAX()`：HTree 目录的链接计数可以溢出
+    /// （见 [`inc_dir_links`](Self::inc_dir_links)），线性目录达到 [`EXT4_LINK_MAX`] 后不能再增加
+    ///
+    /// # 错误
+    ///
+    /// - `ErrorKind::TooManyLinks` - 线性目录的链接计数已达到上限
+    pub(crate) fn check_dir_link_max(&mut self) -> Result<()> {
+        let links = self.with_inode(|inode| u16::from_le(inode.links_count))? as u32;
+        if links >= EXT4_LINK_MAX && !crate::dir::htree::is_indexed(self)? {
+            return Err(Error::new(ErrorKind::TooManyLinks, "Too many subdirectories"));
+        }
+        Ok(())
+    }
+
+    /// 目录增加一个子目录（子目录的 `..`）时增加链接计数
+    ///
+    /// 对应内核的 `ext4_inc_count()`：HTree 目录的链接计数超过 [`EXT4_LINK_MAX`] 时
+    /// 记为 1，表示子目录数未知，并设置 `DIR_NLINK` 特性；已经是 1 的保持不变
+    pub(crate) fn inc_dir_links(&mut self) -> Result<()> {
+        let links = self.with_inode(|inode| u16::from_le(inode.links_count))? as u32 + 1;
+        let overflow = (links > EXT4_LINK_MAX || links == 2) && crate::dir::htree::is_indexed(self)?;
+        if overflow && !self.sb.has_ro_compat_feature(EXT4_FEATURE_RO_COMPAT_DIR_NLINK) {
+            self.sb.set_ro_compat_feature(EXT4_FEATURE_RO_COMPAT_DIR_NLINK);
+            self.sb.write(self.bdev)?;
+        }
+        let links = if overflow { 1 } else { links.min(u16::MAX as u32) as u16 };
+        self.with_inode_mut(|inode| inode.links_count = links.to_le())
+    }
+
+    /// 目录删除一个子目录时减少链接计数
+    ///
+    /// 对应内核的 `ext4_dec_count()`：链接计数不超过 2 的目录（包括溢出后记为 1 的）保持不变
+    pub(crate) fn dec_dir_links(&mut self) -> Result<()> {
+        self.with_inode_mut(|inode| {
+            let links = u16::from_le(inode.links_count);
+            if links > 2 {
+                inode.links_count = (links - 1).to_le();
+            }
+        })
+    }
+
+    /// 是否计入配额（已启用配额且不是保留 inode）
+    pub(crate) fn quota_accounted(&self) -> bool {
+        self.sb.quota().is_some_and(|q| q.accounts(self.inode_num))
+    }
+
+    /// 读取 inode 的配额属主（uid、gid、项目 ID）
+    ///
+    /// 没有 `RO_COMPAT_PROJECT` 特性或 inode 没有 `i_projid` 字段时项目 ID 为 0
+    pub(crate) fn quota_owner(&mut self) -> Result<QuotaOwner> {
+        let has_projid = self.sb.has_ro_compat_feature(EXT4_FEATURE_RO_COMPAT_PROJECT)
+            && self.sb.inode_size() as usize > EXT4_GOOD_OLD_INODE_SIZE;
+
+        self.with_inode(|inode| {
+            let uid = u16::from_le(inode.uid) as u32 | (u16::from_le(inode.uid_high) as u32) << 16;
+            let gid = u16::from_le(inode.gid) as u32 | (u16::from_le(inode.gid_high) as u32) << 16;
+            // i_projid 位于偏移 156，需要 i_extra_isize 覆盖到 160
+            let projid = if has_projid && u16::from_le(inode.extra_isize) >= 32 {
+                u32::from_le(inode.projid)
+            } else {
+                0
+            };
+            QuotaOwner::new(uid, gid, projid)
+        })
+    }
+
+    /// 检查属主能否再分配 `blocks` 个文件系统块
+    ///
+    /// 非特权属主不能使用保留块，见 [`balloc::check_free_blocks`](crate::balloc::check_free_blocks)。
+    /// 在实际分配块之前调用
+    ///
+    /// # 错误
+    ///
+    /// - `ErrorKind::NoSpace` - 扣除保留块后的空闲块不足
+    pub(crate) fn check_free_blocks(&mut self, blocks: u64) -> Result<()> {
+        let (uid, gid) = self.with_inode(|inode| {
+            let uid = u16::from_le(inode.uid) as u32 | (u16::from_le(inode.uid_high) as u32) << 16;
+            let gid = u16::from_le(inode.gid) as u32 | (u16::from_le(inode.gid_high) as u32) << 16;
+            (uid, gid)
+        })?;
+        crate::balloc::check_free_blocks(self.sb, blocks, uid, gid)
+    }
+
+    /// 检查再分配 `blocks` 个文件系统块是否超过属主的配额硬限制
+    ///
+    /// 在实际分配块之前调用
+    ///
+    /// # 错误
+    ///
+    /// - `ErrorKind::QuotaExceeded` - 超过硬限制
+    pub(crate) fn quota_check_blocks(&mut self, blocks: u64) -> Result<()> {
+        if !self.quota_accounted() {
+            return Ok(());
+        }
+        let owner = self.quota_owner()?;
+        let space = blocks * self.sb.block_size() as u64;
+        match self.sb.quota() {
+            Some(quota) => quota.check(&owner, space, 0),
+            None => Ok(()),
         }
     }
 
@@ -452,11 +583,26 @@ impl<'a, D: BlockDevice> InodeRef<'a, D> {
 
     /// 设置文件所有者
     ///
+    /// 启用配额时把 inode 的用量从旧属主转移到新属主
+    ///
     /// # 参数
     ///
     /// * `uid` - 用户 ID
     /// * `gid` - 组 ID
+    ///
+    /// # 错误
+    ///
+    /// - `ErrorKind::QuotaExceeded` - 新属主的用量会超过硬限制
     pub fn set_owner(&mut self, uid: u32, gid: u32) -> Result<()> {
+        if self.quota_accounted() {
+            let from = self.quota_owner()?;
+            let to = QuotaOwner::new(uid, gid, from.ids[2]);
+            let space = self.blocks_count()? * 512;
+            if let Some(quota) = self.sb.quota_mut() {
+                quota.transfer(&from, &to, space, 1)?;
+            }
+        }
+
         self.with_inode_mut(|inode| {
             // uid 存储在 uid 和 uid_high 字段
             inode.uid = (uid as u16).to_le();
@@ -501,6 +647,66 @@ impl<'a, D: BlockDevice> InodeRef<'a, D> {
         })
     }
 
+    /// 设置访问时间（纳秒精度）
+    ///
+    /// inode 有额外空间时同时写入 `i_atime_extra`，可以表示 1901 到 2446 年；
+    /// 否则秒数截断到 32 位有符号范围，纳秒被丢弃
+    ///
+    /// # 参数
+    ///
+    /// * `sec` - Unix 时间戳（秒，可以为负）
+    /// * `nsec` - 纳秒（0 - 999999999）
+    ///
+    /// # 错误
+    ///
+    /// - `ErrorKind::InvalidInput` - 纳秒超出范围
+    pub fn set_atime_ns(&mut self, sec: i64, nsec: u32) -> Result<()> {
+        self.set_time_ns(InodeTime::Access, sec, nsec).map(|_| ())
+    }
+
+    /// 设置修改时间（纳秒精度），规则同 [`set_atime_ns`](Self::set_atime_ns)
+    pub fn set_mtime_ns(&mut self, sec: i64, nsec: u32) -> Result<()> {
+        self.set_time_ns(InodeTime::Modify, sec, nsec).map(|_| ())
+    }
+
+    /// 设置变更时间（纳秒精度），规则同 [`set_atime_ns`](Self::set_atime_ns)
+    pub fn set_ctime_ns(&mut self, sec: i64, nsec: u32) -> Result<()> {
+        self.set_time_ns(InodeTime::Change, sec, nsec).map(|_| ())
+    }
+
+    /// 设置创建时间（纳秒精度）
+    ///
+    /// 创建时间保存在 inode 额外空间中
+    ///
+    /// # 错误
+    ///
+    /// - `ErrorKind::InvalidInput` - 纳秒超出范围
+    /// - `ErrorKind::Unsupported` - inode 没有保存创建时间的额外空间
+    pub fn set_crtime_ns(&mut self, sec: i64, nsec: u32) -> Result<()> {
+        if !self.set_time_ns(InodeTime::Create, sec, nsec)? {
+            return Err(Error::new(
+                ErrorKind::Unsupported,
+                "Inode has no room for creation time",
+            ));
+        }
+        Ok(())
+    }
+
+    /// 获取创建时间 `(秒, 纳秒)`，inode 没有额外空间时为 `None`
+    pub fn crtime(&mut self) -> Result<Option<(i64, u32)>> {
+        let inode_size = self.sb.inode_size() as usize;
+        self.with_inode(|inode| get_time(inode, InodeTime::Create, inode_size))
+    }
+
+    /// 写入一个时间戳，返回是否写入（见 [`set_time`]）
+    fn set_time_ns(&mut self, which: InodeTime, sec: i64, nsec: u32) -> Result<bool> {
+        if nsec >= NSEC_PER_SEC {
+            return Err(Error::new(ErrorKind::InvalidInput, "Nanoseconds out of range"));
+        }
+        let inode_size = self.sb.inode_size() as usize;
+        self.with_inode_mut(|inode| set_time(inode, which, inode_size, sec, nsec))
+    }
+
     /// 检查是否是目录
     pub fn is_dir(&mut self) -> Result<bool> {
         self.with_inode(|inode| inode.is_dir())
@@ -511,6 +717,11 @@ impl<'a, D: BlockDevice> InodeRef<'a, D> {
         self.with_inode(|inode| inode.is_file())
     }
 
+    /// 按 inode 的 mode 得到目录项中的文件类型（`EXT4_DE_*`）
+    pub fn de_type(&mut self) -> Result<u8> {
+        self.with_inode(|inode| super::types::InodeType::from_mode(u16::from_le(inode.mode) as u32).to_de_type())
+    }
+
     /// 检查是否使用 extents
     pub fn has_extents(&mut self) -> Result<bool> {
         self.with_inode(|inode| {
@@ -519,6 +730,26 @@ impl<'a, D: BlockDevice> InodeRef<'a, D> {
         })
     }
 
+    /// 检查是否使用 fscrypt 加密（`EXT4_ENCRYPT_FL`）
+    pub fn is_encrypted(&mut self) -> Result<bool> {
+        self.with_inode(|inode| u32::from_le(inode.flags) & EXT4_INODE_FLAG_ENCRYPT != 0)
+    }
+
+    /// 确认 inode 的内容可以访问
+    ///
+    /// 本库不支持解密：加密文件的数据、加密符号链接的目标都无法读写，
+    /// 也不能在加密目录中创建条目（无法加密名称）
+    ///
+    /// # 错误
+    ///
+    /// - `ErrorKind::Encrypted` - inode 已加密
+    pub(crate) fn check_not_encrypted(&mut self) -> Result<()> {
+        if self.is_encrypted()? {
+            return Err(Error::new(ErrorKind::Encrypted, "Inode is encrypted"));
+        }
+        Ok(())
+    }
+
     /// 获取 inode 数据的拷贝（用于需要长期持有的场景）
     ///
     /// 注意：返回的是数据副本，修改不会反映到磁盘
@@ -531,6 +762,32 @@ impl<'a, D: BlockDevice> InodeRef<'a, D> {
         self.with_inode(|inode| u32::from_le(inode.generation))
     }
 
+    /// 获取 inode 相关元数据（目录块、extent 块）的校验和种子
+    ///
+    /// 见 [`Superblock::inode_csum_seed`]
+    pub fn csum_seed(&mut self) -> Result<u32> {
+        let generation = self.generation()?;
+        Ok(self.sb.inode_csum_seed(self.inode_num, generation))
+    }
+
+    /// 启用 `metadata_csum` 时返回 [`csum_seed`](Self::csum_seed)，否则返回 None
+    ///
+    /// 修改非根 extent 块后用它重新计算块尾的校验和
+    pub(crate) fn extent_csum_seed(&mut self) -> Result<Option<u32>> {
+        if !self.sb.has_metadata_csum() {
+            return Ok(None);
+        }
+        self.csum_seed().map(Some)
+    }
+
+    /// 挂载时要求校验元数据校验和时返回 [`csum_seed`](Self::csum_seed)，否则返回 None
+    pub(crate) fn verify_csum_seed(&mut self) -> Result<Option<u32>> {
+        if !self.sb.verify_checksums() {
+            return Ok(None);
+        }
+        self.csum_seed().map(Some)
+    }
+
     /// 获取 inode 编号（便捷方法）
     pub fn index(&self) -> u32 {
         self.inode_num
@@ -565,7 +822,7 @@ impl<'a, D: BlockDevice> InodeRef<'a, D> {
     /// # 参数
     ///
     /// * `logical_block` - 逻辑块号（文件内的块索引）
-    /// * `create` - 是否在不存在时创建（暂不支持）
+    /// * `create` - 是否在不存在时分配（extent 或间接块映射）
     ///
     /// # 返回
     ///
@@ -583,20 +840,11 @@ impl<'a, D: BlockDevice> InodeRef<'a, D> {
         if !uses_extents {
             // 使用传统的 indirect blocks 映射
             if create {
-                // Indirect blocks 的写入/分配暂不支持
-                return Err(Error::new(
-                    ErrorKind::Unsupported,
-                    "Indirect block allocation not yet implemented",
-                ));
+                return self.alloc_indirect_block(logical_block);
             }
 
             // 使用 IndirectBlockMapper 进行只读映射
-            use crate::indirect::IndirectBlockMapper;
-
-            let mapper = IndirectBlockMapper::new(self.sb.block_size());
-            let inode_wrapper = self.get_inode()?;
-
-            match mapper.map_block(self.bdev, &inode_wrapper, logical_block as u64)? {
+            match self.map_indirect_block(logical_block)? {
                 Some(physical_block) => Ok(physical_block),
                 None => Err(Error::new(
                     ErrorKind::NotFound,
@@ -623,7 +871,9 @@ impl<'a, D: BlockDevice> InodeRef<'a, D> {
                 // 2. 获取快照后立即使用，中间无其他操作
                 // 3. InodeRef 不会被释放
                 let inode_copy = self.get_inode_copy()?;
-                let mut extent_tree = ExtentTree::new(self.bdev, self.sb.block_size());
+                let csum_seed = self.verify_csum_seed()?;
+                let mut extent_tree = ExtentTree::new(self.bdev, self.sb.block_size())
+                    .with_csum_seed(csum_seed);
 
                 match extent_tree.map_block_internal(&inode_copy, logical_block)? {
                     Some(physical_block) => {
@@ -639,13 +889,6 @@ impl<'a, D: BlockDevice> InodeRef<'a, D> {
                 }
             } else {
                 // 写入模式：使用 get_blocks 进行分配或查找
-                // 安全性说明：
-                // - get_blocks 需要 &mut Superblock 但 self 已持有 &mut sb
-                // - 使用 unsafe 指针绕过借用检查器
-                // - get_blocks 会修改 superblock 的空闲块计数，但不会与 InodeRef 冲突
-                let sb_ptr = self.superblock_mut() as *mut Superblock;
-                let sb_ref = unsafe { &mut *sb_ptr };
-
                 let mut allocator = BlockAllocator::new();
 
                 // 完全禁用推测性分配：只分配实际需要的块
@@ -663,8 +906,8 @@ impl<'a, D: BlockDevice> InodeRef<'a, D> {
                 // 所以即使每个块一个 extent 也能正常工作
                 let speculative_blocks = 1;
 
-                let (physical_block, allocated_count) =
-                    get_blocks(self, sb_ref, &mut allocator, logical_block, speculative_blocks, true)?;
+                let (Pblk(physical_block), allocated_count) =
+                    get_blocks(self, &mut allocator, logical_block, speculative_blocks, true)?;
 
                 if physical_block == 0 {
                     Err(Error::new(
@@ -681,6 +924,58 @@ impl<'a, D: BlockDevice> InodeRef<'a, D> {
         }
     }
 
+    /// 映射或分配从 `logical_block` 开始的一段连续块
+    ///
+    /// 与 [`get_inode_dblk_idx`](Self::get_inode_dblk_idx) 相同，但一次最多处理
+    /// `max_blocks` 个块：已映射时返回所在 extent 中剩余的连续块数，
+    /// 未映射且 `create` 为真时通过一次 `get_blocks` 分配并插入一个 extent。
+    /// 空闲空间碎片化时分配到的可能只是其中一段，调用方对剩余部分继续调用。
+    /// 大块追加写因此只需少量 extent 插入，而不是每块一次。间接块映射的文件逐块处理。
+    ///
+    /// # 返回
+    ///
+    /// `(物理块号, 连续块数)`，连续块数至少为 1 且不超过 `max_blocks`
+    ///
+    /// # 错误
+    ///
+    /// - `ErrorKind::NotFound` - 不创建且逻辑块是空洞
+    /// - `ErrorKind::Unsupported` - 间接块映射的文件，且未启用 `indirect` feature
+    pub fn get_inode_dblk_run(
+        &mut self,
+        logical_block: u32,
+        max_blocks: u32,
+        create: bool,
+    ) -> Result<(u64, u32)> {
+        use crate::{balloc::BlockAllocator, extent::{get_blocks, EXT_INIT_MAX_LEN}};
+
+        let max_blocks = max_blocks.clamp(1, EXT_INIT_MAX_LEN as u32);
+
+        if !create || !self.has_extents()? {
+            // 只读映射或间接块文件：逐块映射
+            let physical_block = self.get_inode_dblk_idx(logical_block, create)?;
+            return Ok((physical_block, 1));
+        }
+
+        if let Some((extent_start, extent_len, physical_start)) = self.block_map_cache {
+            if logical_block >= extent_start && logical_block < extent_start + extent_len {
+                let offset = logical_block - extent_start;
+                let count = (extent_len - offset).min(max_blocks);
+                return Ok((physical_start + offset as u64, count));
+            }
+        }
+
+        let mut allocator = BlockAllocator::new();
+        let (Pblk(physical_block), count) =
+            get_blocks(self, &mut allocator, logical_block, max_blocks, true)?;
+
+        if physical_block == 0 || count == 0 {
+            return Err(Error::new(ErrorKind::NoSpace, "Failed to allocate block"));
+        }
+
+        self.block_map_cache = Some((logical_block, count, physical_block));
+        Ok((physical_block, count))
+    }
+
     // ========================================================================
     // 块分配集成说明
     // ========================================================================
@@ -720,10 +1015,9 @@ impl<'a, D: BlockDevice> InodeRef<'a, D> {
     ///
     /// 返回包含 inode 的完整块数据
     pub fn get_inode_data(&mut self) -> Result<alloc::vec::Vec<u8>> {
-        // 直接从块设备读取 inode 所在的块
-        let mut buf = alloc::vec![0u8; self.sb.block_size() as usize];
-        self.bdev.read_block(self.inode_block_addr, &mut buf)?;
-        Ok(buf)
+        // 从缓存中复制 inode 所在的块
+        let mut block = Block::get(self.bdev, self.inode_block_addr)?;
+        block.with_data(|data| data.to_vec())
     }
 
     /// 获取可修改的 inode 块数据（用于 xattr 写操作）
@@ -747,10 +1041,22 @@ impl<'a, D: BlockDevice> InodeRef<'a, D> {
     ///
     /// # 注意
     ///
-    /// 这个方法用于 xattr 等需要修改整个 inode 块的操作
+    /// 这个方法用于 xattr 等需要修改整个 inode 块的操作，写入后重新计算本 inode 的校验和
     pub fn write_inode_data(&mut self, data: &[u8]) -> Result<()> {
-        // 写回整个块
-        self.bdev.write_block(self.inode_block_addr, data)?;
+        if data.len() < self.sb.block_size() as usize {
+            return Err(Error::new(ErrorKind::InvalidInput, "buffer too small for block"));
+        }
+        let sb: &Superblock = self.sb;
+        let inode_num = self.inode_num;
+        let start = self.offset_in_block;
+        let end = start + sb.inode_size() as usize;
+        // 覆盖缓存中的整个块
+        let mut block = Block::get_noread(self.bdev, self.inode_block_addr)?;
+        block.with_data_mut(|block_data| {
+            block_data.copy_from_slice(&data[..block_data.len()]);
+            set_checksum_raw(sb, inode_num, &mut block_data[start..end]);
+        })?;
+        drop(block);
         // 标记为 dirty（虽然已经写回，但保持一致性）
         self.dirty = true;
         Ok(())
@@ -802,9 +1108,8 @@ impl<'a, D: BlockDevice> InodeRef<'a, D> {
         }
 
         // 读取 xattr block
-        let mut buf = alloc::vec![0u8; self.sb.block_size() as usize];
-        self.bdev.read_block(file_acl, &mut buf)?;
-        Ok(Some(buf))
+        let mut block = Block::get(self.bdev, file_acl)?;
+        Ok(Some(block.with_data(|data| data.to_vec())?))
     }
 
     /// 读取可修改的 xattr block（如果存在）
@@ -920,18 +1225,17 @@ impl<'a, D: BlockDevice> InodeRef<'a, D> {
             // 使用 extent 树读取
             use crate::extent::ExtentTree;
 
+            let csum_seed = self.verify_csum_seed()?;
             let bdev_ptr = self.bdev as *mut _;
             let bdev_ref = unsafe { &mut *bdev_ptr };
-            let mut extent_tree = ExtentTree::new(bdev_ref, block_size as u32);
+            let mut extent_tree = ExtentTree::new(bdev_ref, block_size as u32)
+                .with_csum_seed(csum_seed);
 
             self.with_inode(|inode| {
                 extent_tree.read_file_internal(inode, offset, &mut buf[..to_read])
             })?
         } else {
             // 使用 indirect blocks 读取
-            #[cfg(feature = "std")]
-            eprintln!("[inode_ref] Reading with indirect blocks: offset={}, to_read={}", offset, to_read);
-
             let mut bytes_read = 0;
             let mut current_offset = offset;
 
@@ -944,23 +1248,11 @@ impl<'a, D: BlockDevice> InodeRef<'a, D> {
                 let remaining = to_read - bytes_read;
                 let to_read_in_block = remaining.min(block_size as usize - offset_in_block);
 
-                #[cfg(feature = "std")]
-                eprintln!("[inode_ref] Logical block={}, offset_in_block={}, to_read_in_block={}",
-                         logical_block, offset_in_block, to_read_in_block);
-
                 // 使用 get_inode_dblk_idx 获取物理块号（已支持 indirect blocks）
                 match self.get_inode_dblk_idx(logical_block, false) {
                     Ok(physical_block) => {
-                        #[cfg(feature = "std")]
-                        eprintln!("[inode_ref] Physical block={}", physical_block);
-
-                        // 读取块数据（复用 block_buf）
-                        let result = self.bdev.read_blocks_direct(physical_block, 1, &mut block_buf);
-
-                        #[cfg(feature = "std")]
-                        eprintln!("[inode_ref] Read result: {:?}", result);
-
-                        result?;
+                        // 读取块数据（复用 block_buf），经过缓存才能读到尚未写回的数据
+                        self.bdev.read_block(physical_block, &mut block_buf)?;
 
                         // 复制到输出缓冲区
                         buf[bytes_read..bytes_read + to_read_in_block]
@@ -970,19 +1262,12 @@ impl<'a, D: BlockDevice> InodeRef<'a, D> {
                         current_offset += to_read_in_block as u64;
                     }
                     Err(e) if e.kind() == ErrorKind::NotFound => {
-                        #[cfg(feature = "std")]
-                        eprintln!("[inode_ref] Block is a hole");
-
                         // 空洞，填充零
                         buf[bytes_read..bytes_read + to_read_in_block].fill(0);
                         bytes_read += to_read_in_block;
                         current_offset += to_read_in_block as u64;
                     }
-                    Err(e) => {
-                        #[cfg(feature = "std")]
-                        eprintln!("[inode_ref] Error getting block: {:?}", e);
-                        return Err(e);
-                    }
+                    Err(e) => return Err(e),
                 }
             }
 
@@ -1007,16 +1292,207 @@ impl<'a, D: BlockDevice> InodeRef<'a, D> {
         use crate::extent::ExtentTree;
 
         // 安全性说明：同 read_extent_file
+        let csum_seed = self.verify_csum_seed()?;
         let bdev_ptr = self.bdev as *mut _;
         let block_size = self.sb.block_size();
 
         let bdev_ref = unsafe { &mut *bdev_ptr };
-        let mut extent_tree = ExtentTree::new(bdev_ref, block_size);
+        let mut extent_tree = ExtentTree::new(bdev_ref, block_size).with_csum_seed(csum_seed);
 
         self.with_inode(|inode| {
             extent_tree.map_block_internal(inode, logical_block)
         })?
     }
+
+    /// 通过间接块映射查找物理块号，空洞返回 `None`
+    #[cfg(feature = "indirect")]
+    fn map_indirect_block(&mut self, logical_block: u32) -> Result<Option<u64>> {
+        use crate::indirect::IndirectBlockMapper;
+
+        let mapper = IndirectBlockMapper::new(self.sb.block_size());
+        let inode_wrapper = self.get_inode()?;
+        mapper.map_block(self.bdev, &inode_wrapper, logical_block as u64)
+    }
+
+    /// 未启用 `indirect` feature 时，非 extent 文件无法映射
+    #[cfg(not(feature = "indirect"))]
+    fn map_indirect_block(&mut self, _logical_block: u32) -> Result<Option<u64>> {
+        Err(Error::new(
+            ErrorKind::Unsupported,
+            "Indirect block mapping support is disabled (enable the `indirect` feature)",
+        ))
+    }
+
+    /// 通过间接块映射获取物理块号，不存在时分配
+    #[cfg(feature = "indirect")]
+    fn alloc_indirect_block(&mut self, logical_block: u32) -> Result<u64> {
+        crate::indirect::get_or_alloc_block(self, logical_block)
+    }
+
+    #[cfg(not(feature = "indirect"))]
+    fn alloc_indirect_block(&mut self, _logical_block: u32) -> Result<u64> {
+        Err(Error::new(
+            ErrorKind::Unsupported,
+            "Indirect block mapping support is disabled (enable the `indirect` feature)",
+        ))
+    }
+
+    /// 释放逻辑块 `[from, to]`（包含）映射的数据块
+    ///
+    /// 按 inode 的映射方式分派到 extent 或间接块实现
+    pub(crate) fn remove_space(&mut self, from: u32, to: u32) -> Result<()> {
+        self.block_map_cache = None;
+        if self.has_extents()? {
+            return crate::extent::remove_space(self, from, to);
+        }
+
+        #[cfg(feature = "indirect")]
+        {
+            crate::indirect::remove_space(self, from, to)
+        }
+        #[cfg(not(feature = "indirect"))]
+        {
+            Err(Error::new(
+                ErrorKind::Unsupported,
+                "Indirect block mapping support is disabled (enable the `indirect` feature)",
+            ))
+        }
+    }
+
+    /// 获取文件的所有 extent 映射范围（按逻辑块号升序）
+    ///
+    /// 空洞不会出现在结果中；unwritten extent 会被包含并带有标记。
+    ///
+    /// # 错误
+    ///
+    /// - `ErrorKind::Unsupported` - inode 不使用 extent
+    pub fn extent_ranges(&mut self) -> Result<alloc::vec::Vec<crate::extent::ExtentRange>> {
+        use crate::extent::ExtentTree;
+
+        // 安全性说明：同 read_extent_file
+        let csum_seed = self.verify_csum_seed()?;
+        let bdev_ptr = self.bdev as *mut _;
+        let block_size = self.sb.block_size();
+
+        let bdev_ref = unsafe { &mut *bdev_ptr };
+        let mut extent_tree = ExtentTree::new(bdev_ref, block_size).with_csum_seed(csum_seed);
+
+        self.with_inode(|inode| {
+            extent_tree.collect_ranges_internal(inode)
+        })?
+    }
+
+    /// 获取 extent 树非根节点所在的物理块
+    ///
+    /// 深度为 0 的树（全部 extent 存放在 inode 内）返回空列表。
+    ///
+    /// # 错误
+    ///
+    /// - `ErrorKind::Unsupported` - inode 不使用 extent
+    pub fn extent_node_blocks(&mut self) -> Result<alloc::vec::Vec<u64>> {
+        use crate::extent::ExtentTree;
+
+        // 安全性说明：同 read_extent_file
+        let bdev_ptr = self.bdev as *mut _;
+        let block_size = self.sb.block_size();
+
+        let bdev_ref = unsafe { &mut *bdev_ptr };
+        let mut extent_tree = ExtentTree::new(bdev_ref, block_size);
+
+        self.with_inode(|inode| {
+            extent_tree.collect_node_blocks_internal(inode)
+        })?
+    }
+
+    /// 获取逻辑块范围内的物理块映射（bmap/fiemap）
+    ///
+    /// 同时支持 extent 和间接块映射的文件。空洞不会出现在结果中，
+    /// 与 `range` 部分重叠的映射会被裁剪到 `range` 以内。
+    ///
+    /// - extent 文件：每个 extent 对应一段映射，unwritten extent 带有
+    ///   [`MappingFlags::UNWRITTEN`]，最后一个 extent 带有 [`MappingFlags::LAST`]
+    /// - 间接块文件：物理连续的块合并为一段映射，带有 [`MappingFlags::MERGED`]
+    ///
+    /// # 参数
+    ///
+    /// * `range` - 逻辑块范围（左闭右开）
+    ///
+    /// # 示例
+    ///
+    /// ```rust,ignore
+    /// for m in inode_ref.fiemap(0..u32::MAX)? {
+    ///     println!("{} -> {} ({} blocks)", m.logical_block, m.physical_block, m.len);
+    /// }
+    /// ```
+    pub fn fiemap(&mut self, range: core::ops::Range<u32>) -> Result<alloc::vec::Vec<super::ExtentMapping>> {
+        use super::{ExtentMapping, MappingFlags};
+
+        let mut mappings = alloc::vec::Vec::new();
+        if range.start >= range.end {
+            return Ok(mappings);
+        }
+
+        if self.has_extents()? {
+            let ranges = self.extent_ranges()?;
+            let last = ranges.len().saturating_sub(1);
+
+            for (i, r) in ranges.iter().enumerate() {
+                let start = r.logical_start.max(range.start);
+                let end = r.logical_end().min(range.end as u64);
+                if start as u64 >= end {
+                    continue;
+                }
+
+                let mut flags = MappingFlags::empty();
+                if r.unwritten {
+                    flags |= MappingFlags::UNWRITTEN;
+                }
+                if i == last {
+                    flags |= MappingFlags::LAST;
+                }
+
+                mappings.push(ExtentMapping {
+                    logical_block: start,
+                    physical_block: r.physical_start + (start - r.logical_start) as u64,
+                    len: (end - start as u64) as u32,
+                    flags,
+                });
+            }
+            return Ok(mappings);
+        }
+
+        // 间接块映射：逐块查询并合并物理连续的块
+        let block_size = self.sb.block_size();
+        let file_blocks = self.size()?.div_ceil(block_size as u64);
+        let end = (range.end as u64).min(file_blocks) as u32;
+
+        for lblk in range.start..end {
+            let pblk = match self.map_indirect_block(lblk)? {
+                Some(pblk) => pblk,
+                None => continue,
+            };
+
+            match mappings.last_mut() {
+                Some(m) if m.logical_end() == lblk as u64
+                    && m.physical_block + m.len as u64 == pblk => m.len += 1,
+                _ => mappings.push(ExtentMapping {
+                    logical_block: lblk,
+                    physical_block: pblk,
+                    len: 1,
+                    flags: MappingFlags::MERGED,
+                }),
+            }
+        }
+
+        // 只有查询覆盖到文件末尾时才能确定最后一段
+        if end as u64 == file_blocks {
+            if let Some(m) = mappings.last_mut() {
+                m.flags |= MappingFlags::LAST;
+            }
+        }
+
+        Ok(mappings)
+    }
 }
 
 impl<'a, D: BlockDevice> Drop for InodeRef<'a, D> {
@@ -1026,6 +1502,63 @@ impl<'a, D: BlockDevice> Drop for InodeRef<'a, D> {
     }
 }
 
+/// 计算 inode 在 inode 表中的位置
+///
+/// 返回 (inode 所在的块号, 块内偏移)。inode 表的起始块从块组描述符读取
+/// （经过块设备的描述符缓存）
+pub(crate) fn inode_location<D: BlockDevice>(
+    bdev: &mut BlockDev<D>,
+    sb: &Superblock,
+    inode_num: u32,
+) -> Result<(u64, usize)> {
+    // 计算 inode 所在的块组和索引
+    let inodes_per_group = sb.inodes_per_group();
+    let block_group = (inode_num - 1) / inodes_per_group;
+    let index_in_group = (inode_num - 1) % inodes_per_group;
+
+    // 读取块组描述符以获取 inode 表位置
+    // 注意：这里我们需要临时读取块组描述符，不需要持有 BlockGroupRef
+    let inode_table_block = {
+        use crate::block_group::BlockGroup;
+        let bg = BlockGroup::load(bdev, sb, block_group)?;
+        bg.get_inode_table_first_block(sb)
+    };
+
+    // 计算 inode 在 inode 表中的位置
+    let block_size = sb.block_size() as u64;
+    let inode_size = sb.inode_size() as u64;
+    let inodes_per_block = block_size / inode_size;
+
+    // 计算 inode 所在的块号和块内偏移
+    let block_index = index_in_group as u64 / inodes_per_block;
+    let offset_in_block = ((index_in_group as u64 % inodes_per_block) * inode_size) as usize;
+    Ok((inode_table_block + block_index, offset_in_block))
+}
+
+/// 读取 inode 占用的块数（512 字节单位）
+///
+/// 处理 HUGE_FILE 特性下的 48 位计数和以文件系统块为单位的计数
+pub(crate) fn raw_blocks_count(sb: &Superblock, inode: &ext4_inode) -> u64 {
+    // 读取 32 位低位
+    let mut cnt = u32::from_le(inode.blocks_count_lo) as u64;
+
+    // 检查是否启用了 HUGE_FILE 特性
+    if sb.has_ro_compat_feature(EXT4_FEATURE_RO_COMPAT_HUGE_FILE) {
+        // 扩展到 48 位
+        cnt |= (u16::from_le(inode.blocks_high) as u64) << 32;
+
+        // 检查 inode 是否使用了 HUGE_FILE 标志
+        let flags = u32::from_le(inode.flags);
+        if flags & EXT4_INODE_FLAG_HUGE_FILE != 0 {
+            // 进行比例换算：从文件系统块单位转换为 512 字节单位
+            let block_bits = inode_block_bits_count(sb.block_size());
+            return cnt << (block_bits - 9);
+        }
+    }
+
+    cnt
+}
+
 /// 计算块大小的位数
 ///
 /// 对应 lwext4 的 `ext4_inode_block_bits_count()`
@@ -1052,10 +1585,158 @@ fn inode_block_bits_count(block_size: u32) -> u32 {
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::{block_group::BlockGroup, types::{ext4_group_desc, ext4_sblock}};
+
+    struct MemDevice {
+        storage: alloc::vec::Vec<u8>,
+    }
+
+    impl BlockDevice for MemDevice {
+        fn block_size(&self) -> u32 {
+            4096
+        }
+
+        fn sector_size(&self) -> u32 {
+            512
+        }
+
+        fn total_blocks(&self) -> u64 {
+            (self.storage.len() / 4096) as u64
+        }
+
+        fn read_blocks(&mut self, lba: u64, count: u32, buf: &mut [u8]) -> Result<usize> {
+            let start = lba as usize * 512;
+            let len = count as usize * 512;
+            buf[..len].copy_from_slice(&self.storage[start..start + len]);
+            Ok(len)
+        }
+
+        fn write_blocks(&mut self, lba: u64, count: u32, buf: &[u8]) -> Result<usize> {
+            let start = lba as usize * 512;
+            let len = count as usize * 512;
+            self.storage[start..start + len].copy_from_slice(&buf[..len]);
+            Ok(len)
+        }
+    }
 
     #[test]
     fn test_inode_ref_api() {
         // 这些测试需要实际的块设备和 ext4 文件系统
         // 主要是验证 API 的设计和编译
     }
+
+    /// 单个块组的文件系统，inode 表从块 4 开始
+    fn setup(sb: ext4_sblock) -> (Superblock, BlockDev<MemDevice>) {
+        let sb = Superblock::new(ext4_sblock {
+            log_block_size: 2u32.to_le(),
+            blocks_per_group: 32768u32.to_le(),
+            inodes_per_group: 32u32.to_le(),
+            inode_size: 256u16.to_le(),
+            rev_level: 1u32.to_le(),
+            blocks_count_lo: 16u32.to_le(),
+            inodes_count: 32u32.to_le(),
+            magic: EXT4_SUPERBLOCK_MAGIC.to_le(),
+            uuid: [5; 16],
+            ..sb
+        });
+        let mut bdev = BlockDev::new(MemDevice { storage: alloc::vec![0u8; 16 * 4096] }).unwrap();
+        let mut bg = BlockGroup::new(0, ext4_group_desc::default());
+        bg.set_inode_table_first_block(&sb, 4);
+        crate::block_group::write_block_group_desc(&mut bdev, &sb, 0, bg.inner()).unwrap();
+        (sb, bdev)
+    }
+
+    #[test]
+    fn test_mutation_refreshes_checksum() {
+        let (mut sb, mut bdev) = setup(ext4_sblock {
+            feature_ro_compat: EXT4_FEATURE_RO_COMPAT_METADATA_CSUM.to_le(),
+            ..Default::default()
+        });
+
+        // 全零的 inode 校验和不匹配
+        sb.set_verify_checksums(true);
+        let err = InodeRef::get(&mut bdev, &mut sb, 12).err().unwrap();
+        assert_eq!(err.kind(), ErrorKind::Corrupted);
+
+        sb.set_verify_checksums(false);
+        InodeRef::get(&mut bdev, &mut sb, 12)
+            .unwrap()
+            .with_inode_mut(|inode| inode.extra_isize = 32u16.to_le())
+            .unwrap();
+        sb.set_verify_checksums(true);
+        let mut inode_ref = InodeRef::get(&mut bdev, &mut sb, 12).unwrap();
+
+        // 修改 inode 额外空间（如 xattr）后校验和同样更新
+        inode_ref.with_inode_raw_data_mut(|raw| raw[200] = 0xaa).unwrap();
+        let raw = inode_ref.with_inode_raw_data(|raw| raw.to_vec()).unwrap();
+        drop(inode_ref);
+        assert!(verify_checksum_raw(&sb, 12, &raw));
+        assert!(!verify_checksum_raw(&sb, 13, &raw));
+        assert!(InodeRef::get(&mut bdev, &mut sb, 12).is_ok());
+    }
+
+    #[test]
+    fn test_dir_links_overflow() {
+        let (mut sb, mut bdev) = setup(ext4_sblock {
+            feature_compat: EXT4_FEATURE_COMPAT_DIR_INDEX.to_le(),
+            ..Default::default()
+        });
+        let mut dir = InodeRef::get(&mut bdev, &mut sb, 12).unwrap();
+        dir.with_inode_mut(|inode| {
+            inode.mode = (EXT4_INODE_MODE_DIRECTORY | 0o755).to_le();
+            inode.links_count = 64999u16.to_le();
+        })
+        .unwrap();
+        let links = |dir: &mut InodeRef<MemDevice>| dir.with_inode(|inode| u16::from_le(inode.links_count)).unwrap();
+
+        // 线性目录达到上限后不能再增加子目录
+        dir.inc_dir_links().unwrap();
+        assert_eq!(links(&mut dir), 65000);
+        assert_eq!(dir.check_dir_link_max().unwrap_err().kind(), ErrorKind::TooManyLinks);
+
+        // HTree 目录溢出后记为 1 并设置 DIR_NLINK
+        dir.with_inode_mut(|inode| inode.flags = EXT4_INODE_FLAG_INDEX.to_le()).unwrap();
+        dir.check_dir_link_max().unwrap();
+        dir.inc_dir_links().unwrap();
+        assert_eq!(links(&mut dir), 1);
+        assert!(dir.sb().has_ro_compat_feature(EXT4_FEATURE_RO_COMPAT_DIR_NLINK));
+        dir.inc_dir_links().unwrap();
+        dir.dec_dir_links().unwrap();
+        assert_eq!(links(&mut dir), 1);
+
+        dir.with_inode_mut(|inode| inode.links_count = 3u16.to_le()).unwrap();
+        dir.dec_dir_links().unwrap();
+        dir.dec_dir_links().unwrap();
+        assert_eq!(links(&mut dir), 2);
+    }
+
+    #[test]
+    fn test_huge_file_blocks_count() {
+        let (mut sb, mut bdev) = setup(ext4_sblock::default());
+        let mut file = InodeRef::get(&mut bdev, &mut sb, 12).unwrap();
+        let huge_flag = |file: &mut InodeRef<MemDevice>| file.with_inode(|inode| u32::from_le(inode.flags) & EXT4_INODE_FLAG_HUGE_FILE != 0).unwrap();
+
+        // 超过 32 位时自动设置 HUGE_FILE 特性，高 16 位才会被读取
+        file.set_blocks_count(1 << 40).unwrap();
+        assert!(file.sb().has_ro_compat_feature(EXT4_FEATURE_RO_COMPAT_HUGE_FILE));
+        assert!(!huge_flag(&mut file));
+        assert_eq!(file.blocks_count().unwrap(), 1 << 40);
+
+        // 超过 48 位时改用文件系统块为单位（4K 块 = 8 个扇区）
+        file.set_blocks_count(1 << 50).unwrap();
+        assert!(huge_flag(&mut file));
+        assert_eq!(file.with_inode(|inode| u32::from_le(inode.blocks_count_lo)).unwrap(), 0);
+        assert_eq!(file.with_inode(|inode| u16::from_le(inode.blocks_high)).unwrap(), 1 << 15);
+        file.add_blocks(3).unwrap();
+        assert_eq!(file.blocks_count().unwrap(), (1 << 50) + 24);
+        file.sub_blocks(3).unwrap();
+        assert_eq!(file.blocks_count().unwrap(), 1 << 50);
+
+        assert_eq!(file.set_blocks_count(u64::MAX).unwrap_err().kind(), ErrorKind::FileTooLarge);
+
+        // 回落到 48 位以内时清除标志
+        file.set_blocks_count(8).unwrap();
+        assert!(!huge_flag(&mut file));
+        assert_eq!(file.blocks_count().unwrap(), 8);
+    }
 }
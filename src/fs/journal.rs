@@ -0,0 +1,226 @@
+//! 日志接入
+//!
+//! 挂载时带有 HAS_JOURNAL 特性且日志为空（不需要恢复）时启用。每次提交
+//! （[`fsync`](Ext4FileSystem::fsync)、定时提交、卸载）把块缓存中的脏元数据块记入
+//! 一个 JBD2 事务，事务提交后元数据已经写回原位置，日志重新置空。
+//!
+//! 文件数据不记入日志：写路径把写入的数据块登记到运行中的事务。
+//! [`JournalDataMode::Ordered`]（默认）下提交时先把这些块写回原位置，再写 commit block，
+//! 崩溃后重放的元数据不会指向尚未写入的数据；[`JournalDataMode::Writeback`] 下
+//! 数据块随缓存写回，与 commit block 没有先后关系。
+
+use crate::{
+    block::{BlockDev, BlockDevice},
+    consts::EXT4_FEATURE_COMPAT_HAS_JOURNAL,
+    error::{ErrorKind, Result},
+    journal::{JbdFs, JbdJournal, JbdTrans, JournalDataMode},
+    superblock::Superblock,
+};
+use log::warn;
+
+use super::filesystem::Ext4FileSystem;
+
+/// 挂载的文件系统使用的日志
+pub(super) struct FsJournal {
+    jbd_fs: JbdFs,
+    journal: JbdJournal,
+    /// 运行中的事务，收集下一次提交之前写入的文件数据块
+    running: JbdTrans,
+}
+
+impl FsJournal {
+    /// 读取日志，没有日志或日志需要恢复时返回 `None`
+    pub(super) fn load<D: BlockDevice>(bdev: &mut BlockDev<D>, sb: &mut Superblock) -> Result<Option<Self>> {
+        if !sb.has_compat_feature(EXT4_FEATURE_COMPAT_HAS_JOURNAL) || u32::from_le(sb.inner().journal_inum) == 0 {
+            return Ok(None);
+        }
+        let jbd_fs = JbdFs::get(bdev, sb)?;
+        if jbd_fs.start() != 0 {
+            // 不重放日志，也就不能在它后面追加事务
+            warn!("[MOUNT] Journal is not empty, journaling disabled until it is recovered");
+            return Ok(None);
+        }
+        let mut journal = JbdJournal::start(&jbd_fs);
+        let running = journal.new_transaction();
+        Ok(Some(Self { jbd_fs, journal, running }))
+    }
+
+    /// 把从 `start` 开始的 `count` 个文件数据块登记到运行中的事务
+    pub(super) fn add_data_blocks(&mut self, start: u64, count: u32) {
+        for lba in start..start + count as u64 {
+            self.running.add_data_buffer(lba);
+        }
+    }
+
+    /// 日志已经置空之后，从日志起点开始下一个事务
+    fn restart(&mut self) {
+        let mode = self.journal.data_mode();
+        self.journal = JbdJournal::start(&self.jbd_fs);
+        self.journal.set_data_mode(mode);
+        self.running = self.journal.new_transaction();
+    }
+}
+
+impl<D: BlockDevice> Ext4FileSystem<D> {
+    /// 日志的文件数据模式，没有启用日志时为 `None`
+    pub fn journal_data_mode(&self) -> Option<JournalDataMode> {
+        self.journal.as_ref().map(|fs_journal| fs_journal.journal.data_mode())
+    }
+
+    /// 设置日志的文件数据模式，从下一次提交开始生效；没有启用日志时不做任何事
+    pub fn set_journal_data_mode(&mut self, mode: JournalDataMode) {
+        if let Some(fs_journal) = self.journal.as_mut() {
+            fs_journal.journal.set_data_mode(mode);
+        }
+    }
+
+    /// 提交运行中的事务
+    ///
+    /// 在文件数据都已写入块缓存或设备（页缓存和延迟分配写回之后）、
+    /// superblock 写回之前调用。没有启用日志时不做任何事。
+    pub(super) fn commit_journal(&mut self) -> Result<()> {
+        let Some(fs_journal) = self.journal.as_mut() else {
+            return Ok(());
+        };
+
+        // 登记过的块是文件数据，其余脏块都是元数据
+        let mut trans = core::mem::replace(&mut fs_journal.running, JbdTrans::new(0, 0));
+        for lba in self.bdev.dirty_blocks() {
+            if !trans.data_buf.contains(&lba) {
+                trans.add_block(lba);
+            }
+        }
+
+        let FsJournal { jbd_fs, journal, .. } = fs_journal;
+        match journal.commit_transaction(jbd_fs, &mut trans, &mut self.bdev, &mut self.sb) {
+            // 空间检查先于任何写入：元数据照常随缓存写回，只是没有日志记录
+            Err(e) if e.kind() == ErrorKind::NoSpace => {
+                warn!("[JOURNAL] {} metadata blocks do not fit in the journal, committing without it", trans.buffer_count());
+            }
+            result => result?,
+        }
+
+        // commit block 之后的写屏障已经把元数据写回原位置，日志中的事务不再需要重放
+        if jbd_fs.start() != 0 {
+            jbd_fs.set_start(0);
+            jbd_fs.set_sequence(journal.trans_id as u32);
+            jbd_fs.put(&mut self.bdev, &mut self.sb)?;
+        }
+        fs_journal.restart();
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{
+        block::{BlockDev, BlockDevice, MemBlockDevice},
+        error::Result,
+        fs::Ext4FileSystem,
+        journal::{JournalDataMode, JBD_COMMIT_BLOCK, JBD_MAGIC_NUMBER},
+        testing::image::{self, JOURNAL_BLOCKS, JOURNAL_START},
+    };
+    use alloc::vec::Vec;
+
+    const BLOCK_SIZE: u64 = 4096;
+
+    /// 按顺序记录写到设备的每个块
+    struct RecordingDevice {
+        inner: MemBlockDevice,
+        /// `(块号, 是否是 commit block)`
+        writes: Vec<(u64, bool)>,
+    }
+
+    impl BlockDevice for RecordingDevice {
+        fn block_size(&self) -> u32 {
+            self.inner.block_size()
+        }
+
+        fn sector_size(&self) -> u32 {
+            self.inner.sector_size()
+        }
+
+        fn total_blocks(&self) -> u64 {
+            self.inner.total_blocks()
+        }
+
+        fn read_blocks(&mut self, lba: u64, count: u32, buf: &mut [u8]) -> Result<usize> {
+            self.inner.read_blocks(lba, count, buf)
+        }
+
+        fn write_blocks(&mut self, lba: u64, count: u32, buf: &[u8]) -> Result<usize> {
+            let first = lba * self.inner.sector_size() as u64 / BLOCK_SIZE;
+            for (i, block) in buf.chunks(BLOCK_SIZE as usize).enumerate() {
+                let is_commit = block[..4] == JBD_MAGIC_NUMBER.to_be_bytes()
+                    && block[4..8] == JBD_COMMIT_BLOCK.to_be_bytes();
+                self.writes.push((first + i as u64, is_commit));
+            }
+            self.inner.write_blocks(lba, count, buf)
+        }
+    }
+
+    fn mount() -> Ext4FileSystem<RecordingDevice> {
+        let dev = RecordingDevice { inner: image::image_with_journal(0), writes: Vec::new() };
+        Ext4FileSystem::mount(BlockDev::new_with_cache(dev, 64).unwrap()).unwrap()
+    }
+
+    /// 写入 `/a` 的第一个块（部分块写入，数据留在块缓存中）并提交，
+    /// 返回 inode 号、数据块号和提交期间的写入记录
+    fn write_and_fsync(fs: &mut Ext4FileSystem<RecordingDevice>) -> (u32, u64, Vec<(u64, bool)>) {
+        let ino = fs.create_file("/", "a", 0o644).unwrap();
+        fs.write_at_inode(ino, b"ordered data", 0).unwrap();
+        let pblk = fs.with_inode_ref(ino, |inode_ref| inode_ref.get_inode_dblk_idx(0, false)).unwrap();
+
+        fs.block_device_mut().device_mut().writes.clear();
+        fs.fsync().unwrap();
+        (ino, pblk, core::mem::take(&mut fs.block_device_mut().device_mut().writes))
+    }
+
+    fn in_journal(lba: u64) -> bool {
+        (JOURNAL_START..JOURNAL_START + JOURNAL_BLOCKS as u64).contains(&lba)
+    }
+
+    #[test]
+    fn test_ordered_data_before_commit_block() {
+        let mut fs = mount();
+        assert_eq!(fs.journal_data_mode(), Some(JournalDataMode::Ordered));
+
+        let (ino, pblk, writes) = write_and_fsync(&mut fs);
+        let data = writes.iter().position(|&(lba, _)| lba == pblk).expect("data block written");
+        let commit = writes.iter().position(|&(_, is_commit)| is_commit).expect("commit block written");
+        let first_journal = writes.iter().position(|&(lba, _)| in_journal(lba)).unwrap();
+
+        // 数据块先于这个事务的所有日志块（包括 commit block）写到设备，且没有记入日志
+        assert!(data < first_journal && first_journal <= commit);
+        assert_eq!(writes.iter().filter(|&&(lba, _)| lba == pblk).count(), 1);
+
+        // 提交之后日志置空，重新挂载后数据完整
+        let bdev = fs.unmount().unwrap();
+        let mut fs = Ext4FileSystem::mount(bdev).unwrap();
+        assert_eq!(fs.journal_data_mode(), Some(JournalDataMode::Ordered));
+        let mut buf = [0u8; 12];
+        assert_eq!(fs.read_at_inode(ino, &mut buf, 0).unwrap(), 12);
+        assert_eq!(&buf, b"ordered data");
+    }
+
+    #[test]
+    fn test_writeback_data_not_ordered() {
+        let mut fs = mount();
+        fs.set_journal_data_mode(JournalDataMode::Writeback);
+
+        let (_, pblk, writes) = write_and_fsync(&mut fs);
+        let data = writes.iter().position(|&(lba, _)| lba == pblk).expect("data block written");
+        let first_journal = writes.iter().position(|&(lba, _)| in_journal(lba)).unwrap();
+        // 数据块随缓存写回，晚于日志的描述符块
+        assert!(data > first_journal);
+        assert!(writes.iter().any(|&(_, is_commit)| is_commit));
+    }
+
+    #[test]
+    fn test_no_journal() {
+        let mut fs = image::mount(image::image());
+        assert_eq!(fs.journal_data_mode(), None);
+        fs.set_journal_data_mode(JournalDataMode::Writeback);
+        assert_eq!(fs.journal_data_mode(), None);
+    }
+}
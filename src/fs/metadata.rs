@@ -1,6 +1,18 @@
 //! 文件元数据
 
-use crate::{consts::*, inode::Inode};
+use crate::{
+    block::BlockDevice,
+    consts::*,
+    error::Result,
+    inode::{
+        time::{get_time, InodeTime},
+        Inode,
+    },
+    superblock::Superblock,
+    types::ext4_inode,
+};
+
+use super::{inode_ref::raw_blocks_count, special::{decode_dev, major, minor}, InodeRef};
 
 /// 文件类型
 #[derive(Debug, Clone, Copy, PartialEq, Eq)]
@@ -38,6 +50,22 @@ impl FileType {
         }
     }
 
+    /// 从目录项的类型字段解析文件类型
+    ///
+    /// 目录项没有记录类型（`EXT4_DE_UNKNOWN`）或类型无效时返回 `Unknown`
+    pub fn from_de_type(de_type: u8) -> Self {
+        match de_type {
+            EXT4_DE_REG_FILE => FileType::RegularFile,
+            EXT4_DE_DIR => FileType::Directory,
+            EXT4_DE_SYMLINK => FileType::Symlink,
+            EXT4_DE_CHRDEV => FileType::CharDevice,
+            EXT4_DE_BLKDEV => FileType::BlockDevice,
+            EXT4_DE_FIFO => FileType::Fifo,
+            EXT4_DE_SOCK => FileType::Socket,
+            _ => FileType::Unknown,
+        }
+    }
+
     /// 是否是目录
     pub fn is_dir(&self) -> bool {
         matches!(self, FileType::Directory)
@@ -52,6 +80,21 @@ impl FileType {
     pub fn is_symlink(&self) -> bool {
         matches!(self, FileType::Symlink)
     }
+
+    /// 是否是字符设备或块设备
+    pub fn is_device(&self) -> bool {
+        matches!(self, FileType::CharDevice | FileType::BlockDevice)
+    }
+
+    /// 是否是 FIFO
+    pub fn is_fifo(&self) -> bool {
+        matches!(self, FileType::Fifo)
+    }
+
+    /// 是否是 Socket
+    pub fn is_socket(&self) -> bool {
+        matches!(self, FileType::Socket)
+    }
 }
 
 /// 文件元数据
@@ -73,36 +116,109 @@ pub struct FileMetadata {
     pub gid: u32,
     /// 访问时间（Unix 时间戳）
     pub atime: i64,
+    /// 访问时间的纳秒部分（inode 没有额外空间时为 0）
+    pub atime_nsec: u32,
     /// 修改时间（Unix 时间戳）
     pub mtime: i64,
-    /// 创建时间（Unix 时间戳）
+    /// 修改时间的纳秒部分
+    pub mtime_nsec: u32,
+    /// 状态变更时间（Unix 时间戳）
     pub ctime: i64,
+    /// 状态变更时间的纳秒部分
+    pub ctime_nsec: u32,
+    /// 创建时间（Unix 时间戳），inode 没有额外空间时为 `None`
+    pub crtime: Option<i64>,
+    /// 创建时间的纳秒部分
+    pub crtime_nsec: u32,
     /// 硬链接数
     pub links_count: u16,
+    /// 真实的链接计数
+    ///
+    /// 通常与 `links_count` 相同；子目录过多的目录（`DIR_NLINK`）在磁盘上的链接计数记为 1，
+    /// 此时由 [`Ext4FileSystem::metadata`](super::Ext4FileSystem::metadata) 等扫描目录得到
+    /// （2 + 子目录数）
+    pub nlink: u32,
     /// 占用的块数（512 字节块）
     pub blocks_count: u64,
+    /// 符号链接目标的长度（字节），非符号链接为 `None`
+    ///
+    /// 快速符号链接和存放在数据块中的长符号链接都适用
+    pub symlink_target_len: Option<u64>,
+    /// inode 额外空间中是否有扩展属性
+    pub has_inode_xattrs: bool,
+    /// 是否有独立的扩展属性块（`i_file_acl` 非 0）
+    pub has_xattr_block: bool,
+    /// 设备号（[`makedev`](super::makedev) 布局），非设备文件为 0
+    pub rdev: u64,
+    /// 是否使用 fscrypt 加密（`EXT4_ENCRYPT_FL`），加密文件的内容无法读写
+    pub encrypted: bool,
 }
 
 impl FileMetadata {
-    /// 从 inode 创建元数据
-    pub(crate) fn from_inode(inode: &Inode, inode_num: u32) -> Self {
+    /// 从 inode 引用创建元数据
+    ///
+    /// 只访问 inode 所在的块（经过块缓存），因此能看到尚未刷新的修改，
+    /// 扩展属性标志也直接从 inode 的原始字节判断，不读取属性块
+    pub(crate) fn from_inode_ref<D: BlockDevice>(inode_ref: &mut InodeRef<D>) -> Result<Self> {
+        let inode_num = inode_ref.index();
+        let raw = inode_ref.with_inode_raw_data(|data| data.to_vec())?;
+        Ok(Self::from_raw(inode_ref.sb(), inode_num, &raw))
+    }
+
+    /// 从完整的磁盘 inode 字节创建元数据
+    ///
+    /// `data` 的长度为 superblock 的 inode 大小，调用者负责校验和检查
+    pub(crate) fn from_raw(sb: &Superblock, inode_num: u32, data: &[u8]) -> Self {
+        let inner = unsafe { core::ptr::read_unaligned(data.as_ptr() as *const ext4_inode) };
+        let has_inode_xattrs = has_ibody_xattrs(data);
+        let inode_size = data.len();
+        let inode = Inode::from_raw(inner, inode_num);
         let mode = inode.mode();
+        let file_type = FileType::from_mode(mode);
+        let size = inode.file_size();
+        let time = |which| get_time(&inner, which, inode_size);
+        let (atime, atime_nsec) = time(InodeTime::Access).unwrap_or_default();
+        let (mtime, mtime_nsec) = time(InodeTime::Modify).unwrap_or_default();
+        let (ctime, ctime_nsec) = time(InodeTime::Change).unwrap_or_default();
+        let crtime = time(InodeTime::Create);
 
         Self {
-            file_type: FileType::from_mode(mode),
-            size: inode.file_size(),
+            file_type,
+            size,
             inode_num,
             permissions: mode & 0o7777, // 提取权限位
             uid: inode.uid(),
             gid: inode.gid(),
-            atime: inode.access_time() as i64,
-            mtime: inode.modification_time() as i64,
-            ctime: inode.change_time() as i64,
+            atime,
+            atime_nsec,
+            mtime,
+            mtime_nsec,
+            ctime,
+            ctime_nsec,
+            crtime: crtime.map(|(sec, _)| sec),
+            crtime_nsec: crtime.map_or(0, |(_, nsec)| nsec),
             links_count: inode.links_count(),
-            blocks_count: inode.blocks_count(),
+            nlink: inode.links_count() as u32,
+            blocks_count: raw_blocks_count(sb, &inner),
+            symlink_target_len: file_type.is_symlink().then_some(size),
+            has_inode_xattrs,
+            has_xattr_block: inode.get_file_acl(sb) != 0,
+            rdev: match file_type {
+                FileType::CharDevice | FileType::BlockDevice => decode_dev([
+                    u32::from_le(inner.blocks[0]),
+                    u32::from_le(inner.blocks[1]),
+                ]),
+                _ => 0,
+            },
+            encrypted: u32::from_le(inner.flags) & EXT4_INODE_FLAG_ENCRYPT != 0,
         }
     }
 
+    /// 是否有扩展属性（inode 内或属性块）
+    pub fn has_xattrs(&self) -> bool {
+        self.has_inode_xattrs || self.has_xattr_block
+    }
+
     /// 是否是目录
     pub fn is_dir(&self) -> bool {
         self.file_type.is_dir()
@@ -117,12 +233,60 @@ impl FileMetadata {
     pub fn is_symlink(&self) -> bool {
         self.file_type.is_symlink()
     }
+
+    /// 是否是字符设备或块设备
+    pub fn is_device(&self) -> bool {
+        self.file_type.is_device()
+    }
+
+    /// 设备文件的主设备号，非设备文件为 `None`
+    pub fn rdev_major(&self) -> Option<u32> {
+        self.is_device().then(|| major(self.rdev))
+    }
+
+    /// 设备文件的次设备号，非设备文件为 `None`
+    pub fn rdev_minor(&self) -> Option<u32> {
+        self.is_device().then(|| minor(self.rdev))
+    }
+}
+
+/// inode 额外空间（`i_extra_isize` 之后）是否以扩展属性魔数开头
+///
+/// `data` 为完整的磁盘 inode（长度为 superblock 的 inode 大小）
+fn has_ibody_xattrs(data: &[u8]) -> bool {
+    let base = EXT4_GOOD_OLD_INODE_SIZE;
+    if data.len() < base + 2 {
+        return false;
+    }
+    let extra_isize = u16::from_le_bytes([data[base], data[base + 1]]) as usize;
+    let header = base + extra_isize;
+    match data.get(header..header + 4) {
+        Some(magic) => u32::from_le_bytes([magic[0], magic[1], magic[2], magic[3]]) == EXT4_XATTR_MAGIC,
+        None => false,
+    }
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
 
+    #[test]
+    fn test_ibody_xattr_detection() {
+        let mut data = alloc::vec![0u8; 256];
+        // 128 字节的旧式 inode 没有额外空间
+        assert!(!has_ibody_xattrs(&data[..128]));
+
+        data[128..130].copy_from_slice(&32u16.to_le_bytes());
+        assert!(!has_ibody_xattrs(&data));
+
+        data[160..164].copy_from_slice(&EXT4_XATTR_MAGIC.to_le_bytes());
+        assert!(has_ibody_xattrs(&data));
+
+        // extra_isize 越界时不误判
+        data[128..130].copy_from_slice(&254u16.to_le_bytes());
+        assert!(!has_ibody_xattrs(&data));
+    }
+
     #[test]
     fn test_file_type_from_mode() {
         assert_eq!(
@@ -153,4 +317,27 @@ mod tests {
         assert!(!FileType::Symlink.is_file());
         assert!(FileType::Symlink.is_symlink());
     }
+
+    #[test]
+    fn test_special_file_types() {
+        let special = [
+            (EXT4_INODE_MODE_CHARDEV, EXT4_DE_CHRDEV, FileType::CharDevice),
+            (EXT4_INODE_MODE_BLOCKDEV, EXT4_DE_BLKDEV, FileType::BlockDevice),
+            (EXT4_INODE_MODE_FIFO, EXT4_DE_FIFO, FileType::Fifo),
+            (EXT4_INODE_MODE_SOCKET, EXT4_DE_SOCK, FileType::Socket),
+        ];
+        for (mode, de_type, file_type) in special {
+            assert_eq!(FileType::from_mode(mode | 0o644), file_type);
+            assert_eq!(FileType::from_de_type(de_type), file_type);
+        }
+        assert_eq!(FileType::from_de_type(EXT4_DE_REG_FILE), FileType::RegularFile);
+        assert_eq!(FileType::from_de_type(EXT4_DE_UNKNOWN), FileType::Unknown);
+        assert_eq!(FileType::from_de_type(0xFF), FileType::Unknown);
+
+        assert!(FileType::CharDevice.is_device());
+        assert!(FileType::BlockDevice.is_device());
+        assert!(!FileType::Fifo.is_device());
+        assert!(FileType::Fifo.is_fifo());
+        assert!(FileType::Socket.is_socket());
+    }
 }
@@ -8,10 +8,55 @@ mod metadata;
 mod inode_ref;
 mod block_group_ref;
 mod types;
+mod copy;
+mod mv;
+mod special;
+mod scrub;
+mod undo;
+mod delalloc;
+mod pagecache;
+mod estimate;
+mod commit;
+mod fsck;
+mod fsync;
+mod quota;
+mod crypt;
+mod resize;
+mod shrink;
+mod defrag;
+mod statfs;
+mod vectored;
+mod open_table;
+mod dma;
+mod hash;
+mod batch;
+mod readdir;
+pub mod ops;
+pub mod export;
+pub mod import;
+#[cfg(feature = "journal")]
+mod journal;
+#[cfg(feature = "sync")]
+mod sync;
 
-pub use filesystem::Ext4FileSystem;
-pub use file::File;
+pub use filesystem::{Ext4FileSystem, MAX_SYMLINK_FOLLOW};
+pub use file::{File, OpenOptions, SeekFrom};
 pub use metadata::{FileMetadata, FileType};
 pub use inode_ref::InodeRef;
 pub use block_group_ref::BlockGroupRef;
-pub use types::{FileAttr, FsConfig, InodeType, StatFs, SystemHal};
+pub use copy::{copy_between, COPY_CHUNK_SIZE};
+pub use mv::move_between;
+pub use special::{major, makedev, minor};
+pub use scrub::{BadRange, ScrubIssue, ScrubProgress, ScrubReport};
+pub use estimate::{SpaceEstimate, SpaceEstimateRequest};
+pub use commit::DEFAULT_COMMIT_INTERVAL;
+pub use fsck::FsckReason;
+pub use pagecache::PageCacheStats;
+pub use defrag::DefragReport;
+pub use open_table::InodeHandle;
+pub use hash::FileHasher;
+pub use batch::Batch;
+pub use readdir::{DirStream, ReadDirOptions};
+#[cfg(feature = "sync")]
+pub use sync::{SyncExt4FileSystem, IO_CHUNK_SIZE};
+pub use types::{CreatePolicy, ExtentMapping, FollowSymlink, FileAttr, FsConfig, GroupWrites, InodeType, MappingFlags, StatFs, SystemHal};
@@ -0,0 +1,264 @@
+//! 路径移动（mv 语义）
+//!
+//! 同一文件系统实例内通过 rename 完成移动；在两个不同实例之间则递归复制
+//! 整棵子树（保留元数据、扩展属性和子树内的硬链接），复制成功后再删除源。
+
+use crate::{
+    block::BlockDevice,
+    error::{Error, ErrorKind, Result},
+};
+use alloc::{string::String, vec::Vec};
+
+use super::{
+    copy::{copy_attrs, copy_between, split_parent},
+    filesystem::Ext4FileSystem,
+    metadata::FileType,
+    types::InodeType,
+};
+
+impl<D: BlockDevice> Ext4FileSystem<D> {
+    /// 在同一文件系统内移动文件或目录（mv 语义）
+    ///
+    /// - `dst` 是已存在的目录时，移动到 `dst/<src 的文件名>`
+    /// - 最终目标已存在时先删除：非目录被替换，空目录只能被目录替换
+    /// - 不允许把目录移动到它自己的子树中
+    ///
+    /// 跨文件系统实例移动请使用 [`move_between`]。
+    ///
+    /// # 参数
+    ///
+    /// * `src` - 源路径（绝对路径）
+    /// * `dst` - 目标路径（绝对路径）
+    ///
+    /// # 错误
+    ///
+    /// - `ErrorKind::NotFound` - 源或目标父目录不存在
+    /// - `ErrorKind::InvalidInput` - 目录移动到自身子树，或文件与目录互相替换
+    /// - `ErrorKind::NotEmpty` - 要替换的目标目录非空
+    ///
+    /// # 示例
+    ///
+    /// ```rust,ignore
+    /// fs.move_path("/tmp/build", "/opt")?; // -> /opt/build
+    /// ```
+    pub fn move_path(&mut self, src: &str, dst: &str) -> Result<()> {
+        let src_meta = self.symlink_metadata(src)?;
+        let (src_parent, src_name) = split_parent(src)?;
+        let dst = resolve_destination(self, src_name, dst)?;
+
+        if src.trim_end_matches('/') == dst {
+            return Ok(());
+        }
+        if src_meta.is_dir() && is_within(&dst, src) {
+            return Err(Error::new(
+                ErrorKind::InvalidInput,
+                "Cannot move a directory into its own subtree",
+            ));
+        }
+
+        replace_existing(self, &dst, src_meta.file_type)?;
+
+        let (dst_parent, dst_name) = split_parent(&dst)?;
+        self.rename(src_parent, src_name, dst_parent, dst_name)
+    }
+}
+
+/// 在两个文件系统实例之间移动文件或目录（mv 语义）
+///
+/// 先把 `src` 整棵子树复制到 `dst_fs`，全部成功后再从 `src_fs` 删除源。
+/// 目标路径的解析和替换规则与 [`Ext4FileSystem::move_path`] 相同。
+///
+/// 复制时保留：
+/// - 普通文件的数据（保持稀疏布局）
+/// - 权限位、uid、gid、atime、mtime、ctime
+/// - 扩展属性（需要 `xattr` feature）
+/// - 子树内部的硬链接关系
+/// - 符号链接目标
+/// - 设备文件的设备号（FIFO、socket 重新创建为同类型的文件）
+///
+/// 复制过程中出错时源保持不变，目标中可能残留部分复制的内容。
+///
+/// # 错误
+///
+/// - `ErrorKind::NotFound` - 源或目标父目录不存在
+/// - `ErrorKind::Unsupported` - 子树中包含未知类型的文件
+///
+/// # 示例
+///
+/// ```rust,ignore
+/// move_between(&mut usb, "/photos", &mut disk, "/backup")?; // -> /backup/photos
+/// ```
+pub fn move_between<S: BlockDevice, T: BlockDevice>(
+    src_fs: &mut Ext4FileSystem<S>,
+    src: &str,
+    dst_fs: &mut Ext4FileSystem<T>,
+    dst: &str,
+) -> Result<()> {
+    let src_meta = src_fs.symlink_metadata(src)?;
+    let (src_parent, src_name) = split_parent(src)?;
+    let dst = resolve_destination(dst_fs, src_name, dst)?;
+
+    replace_existing(dst_fs, &dst, src_meta.file_type)?;
+
+    let mut links = Vec::new();
+    copy_tree(src_fs, src.trim_end_matches('/'), dst_fs, &dst, &mut links)?;
+
+    remove_tree(src_fs, src_parent, src_name)
+}
+
+/// 按 mv 语义解析最终目标路径
+///
+/// `dst` 是已存在的目录时返回 `dst/<name>`，否则返回去掉末尾 `/` 的 `dst`
+fn resolve_destination<D: BlockDevice>(
+    fs: &mut Ext4FileSystem<D>,
+    name: &str,
+    dst: &str,
+) -> Result<String> {
+    match fs.symlink_metadata(dst) {
+        Ok(meta) if meta.is_dir() => Ok(join_path(dst, name)),
+        Ok(_) => Ok(String::from(dst.trim_end_matches('/'))),
+        Err(e) if e.kind() == ErrorKind::NotFound => Ok(String::from(dst.trim_end_matches('/'))),
+        Err(e) => Err(e),
+    }
+}
+
+/// 删除将被替换的已存在目标
+///
+/// 目录只能替换空目录，非目录只能替换非目录
+fn replace_existing<D: BlockDevice>(
+    fs: &mut Ext4FileSystem<D>,
+    dst: &str,
+    src_type: FileType,
+) -> Result<()> {
+    let dst_meta = match fs.symlink_metadata(dst) {
+        Ok(meta) => meta,
+        Err(e) if e.kind() == ErrorKind::NotFound => return Ok(()),
+        Err(e) => return Err(e),
+    };
+
+    let (parent, name) = split_parent(dst)?;
+    match (src_type.is_dir(), dst_meta.is_dir()) {
+        (true, true) => fs.remove_dir(parent, name),
+        (false, false) => fs.remove_file(parent, name),
+        (true, false) => Err(Error::new(
+            ErrorKind::NotADirectory,
+            "Cannot overwrite non-directory with directory",
+        )),
+        (false, true) => Err(Error::new(
+            ErrorKind::IsADirectory,
+            "Cannot overwrite directory with non-directory",
+        )),
+    }
+}
+
+/// 递归复制子树
+///
+/// `links` 记录已复制的多链接文件（源 inode -> 目标路径），用于在目标中重建硬链接
+fn copy_tree<S: BlockDevice, T: BlockDevice>(
+    src_fs: &mut Ext4FileSystem<S>,
+    src: &str,
+    dst_fs: &mut Ext4FileSystem<T>,
+    dst: &str,
+    links: &mut Vec<(u32, String)>,
+) -> Result<()> {
+    let meta = src_fs.symlink_metadata(src)?;
+    let (dst_parent, dst_name) = split_parent(dst)?;
+
+    match meta.file_type {
+        FileType::RegularFile => {
+            if meta.links_count > 1 {
+                if let Some((_, first)) = links.iter().find(|(ino, _)| *ino == meta.inode_num) {
+                    return dst_fs.flink(first, dst_parent, dst_name);
+                }
+                links.push((meta.inode_num, String::from(dst)));
+            }
+            copy_between(src_fs, src, dst_fs, dst)?;
+        }
+        FileType::Symlink => {
+            let target = src_fs.readlink(src)?;
+            let ino = dst_fs.fsymlink(&target, dst_parent, dst_name)?;
+            copy_attrs(src_fs, src, &meta, dst_fs, dst, ino)?;
+        }
+        FileType::Directory => {
+            let ino = dst_fs.create_dir(dst_parent, dst_name, meta.permissions)?;
+            for entry in src_fs.read_dir(src)? {
+                if entry.name == "." || entry.name == ".." {
+                    continue;
+                }
+                let child_src = join_path(src, &entry.name);
+                let child_dst = join_path(dst, &entry.name);
+                copy_tree(src_fs, &child_src, dst_fs, &child_dst, links)?;
+            }
+            // 子项创建会更新目录时间戳，因此最后再复制元数据
+            copy_attrs(src_fs, src, &meta, dst_fs, dst, ino)?;
+        }
+        FileType::CharDevice | FileType::BlockDevice | FileType::Fifo | FileType::Socket => {
+            let node_type = match meta.file_type {
+                FileType::CharDevice => InodeType::CharacterDevice,
+                FileType::BlockDevice => InodeType::BlockDevice,
+                FileType::Fifo => InodeType::Fifo,
+                _ => InodeType::Socket,
+            };
+            let ino = dst_fs.mknod(dst_parent, dst_name, node_type, meta.permissions, meta.rdev)?;
+            copy_attrs(src_fs, src, &meta, dst_fs, dst, ino)?;
+        }
+        FileType::Unknown => {
+            return Err(Error::new(
+                ErrorKind::Unsupported,
+                "Cannot move files of unknown type between filesystems",
+            ))
+        }
+    }
+
+    Ok(())
+}
+
+/// 后序删除子树
+fn remove_tree<D: BlockDevice>(fs: &mut Ext4FileSystem<D>, parent: &str, name: &str) -> Result<()> {
+    let path = join_path(parent, name);
+    if !fs.symlink_metadata(&path)?.is_dir() {
+        return fs.remove_file(parent, name);
+    }
+
+    for entry in fs.read_dir(&path)? {
+        if entry.name == "." || entry.name == ".." {
+            continue;
+        }
+        remove_tree(fs, &path, &entry.name)?;
+    }
+    fs.remove_dir(parent, name)
+}
+
+/// 拼接父目录和文件名
+fn join_path(parent: &str, name: &str) -> String {
+    let parent = parent.trim_end_matches('/');
+    alloc::format!("{parent}/{name}")
+}
+
+/// `path` 是否位于 `ancestor` 之下（不含 `ancestor` 本身）
+fn is_within(path: &str, ancestor: &str) -> bool {
+    let ancestor = ancestor.trim_end_matches('/');
+    path.strip_prefix(ancestor)
+        .is_some_and(|rest| rest.starts_with('/') && rest.len() > 1)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_join_path() {
+        assert_eq!(join_path("/", "a"), "/a");
+        assert_eq!(join_path("/a/b/", "c"), "/a/b/c");
+    }
+
+    #[test]
+    fn test_is_within() {
+        assert!(is_within("/a/b", "/a"));
+        assert!(is_within("/a/b/c", "/a/"));
+        assert!(is_within("/x", "/"));
+        assert!(!is_within("/a", "/a"));
+        assert!(!is_within("/ab", "/a"));
+        assert!(!is_within("/b", "/a"));
+    }
+}
@@ -0,0 +1,221 @@
+//! 打开 inode 表
+//!
+//! 同一个 inode 的所有打开（[`File`](super::File) 或 [`Ext4FileSystem::open_inode`]）
+//! 共享一个表项：
+//!
+//! - 尚未写入 inode 的文件大小（写入合并），任何一个句柄的 `sync`/`close` 都会提交它
+//! - extent 映射缓存，一个句柄顺序写入时查到的映射对其他句柄同样有效
+//! - 引用计数：链接数降为 0 的 inode 在最后一个句柄关闭时才释放（`drop_inode`），
+//!   与 POSIX 打开后删除的语义相同
+//!
+//! 截断和碎片整理会释放或移动数据块，它们使对应表项的映射缓存失效；收缩文件系统
+//! 可能改变 inode 编号，还有打开的 inode 时拒绝执行。
+//! 没有关闭就丢弃的句柄不会释放引用，其表项在卸载时处理。
+
+use crate::{
+    block::BlockDevice,
+    error::{Error, ErrorKind, Result},
+};
+use alloc::collections::BTreeMap;
+
+use super::filesystem::Ext4FileSystem;
+
+/// 打开的 inode 的句柄
+///
+/// 由 [`Ext4FileSystem::open_inode`] 返回，交给 [`Ext4FileSystem::close_inode`] 释放。
+/// 句柄不可复制，每个句柄恰好对应表项中的一个引用
+#[derive(Debug, PartialEq, Eq)]
+pub struct InodeHandle {
+    ino: u32,
+}
+
+impl InodeHandle {
+    /// inode 编号
+    pub fn ino(&self) -> u32 {
+        self.ino
+    }
+}
+
+/// 一个打开的 inode 的共享状态
+#[derive(Debug, Default)]
+pub(super) struct OpenInode {
+    /// 句柄数
+    refs: u32,
+    /// 尚未写入 inode 的文件大小
+    pub(super) pending_size: Option<u64>,
+    /// extent 映射缓存 `(起始逻辑块, 块数, 起始物理块)`，与 `InodeRef` 的块映射缓存相同
+    pub(super) extent: Option<(u32, u32, u64)>,
+    /// 链接数已降为 0，最后一个句柄关闭时释放
+    pub(super) unlinked: bool,
+}
+
+/// 打开 inode 表
+#[derive(Debug, Default)]
+pub(super) struct OpenInodeTable {
+    inodes: BTreeMap<u32, OpenInode>,
+}
+
+impl OpenInodeTable {
+    pub(super) fn new() -> Self {
+        Self::default()
+    }
+
+    /// 增加一个引用
+    fn acquire(&mut self, ino: u32) -> InodeHandle {
+        self.inodes.entry(ino).or_default().refs += 1;
+        InodeHandle { ino }
+    }
+
+    /// 释放一个引用，返回最后一个引用释放时的表项
+    fn release(&mut self, handle: InodeHandle) -> Option<OpenInode> {
+        let entry = self.inodes.get_mut(&handle.ino)?;
+        entry.refs -= 1;
+        if entry.refs > 0 {
+            return None;
+        }
+        self.inodes.remove(&handle.ino)
+    }
+
+    /// inode 的句柄数
+    pub(super) fn refs(&self, ino: u32) -> u32 {
+        self.inodes.get(&ino).map_or(0, |entry| entry.refs)
+    }
+
+    /// 没有打开的 inode
+    pub(super) fn is_empty(&self) -> bool {
+        self.inodes.is_empty()
+    }
+
+    pub(super) fn get(&self, ino: u32) -> Option<&OpenInode> {
+        self.inodes.get(&ino)
+    }
+
+    pub(super) fn get_mut(&mut self, ino: u32) -> Option<&mut OpenInode> {
+        self.inodes.get_mut(&ino)
+    }
+
+    /// 数据块被释放或移动：丢弃映射缓存
+    pub(super) fn invalidate_extent(&mut self, ino: u32) {
+        if let Some(entry) = self.inodes.get_mut(&ino) {
+            entry.extent = None;
+        }
+    }
+
+    /// 取出所有表项（卸载时）
+    fn drain(&mut self) -> BTreeMap<u32, OpenInode> {
+        core::mem::take(&mut self.inodes)
+    }
+}
+
+impl<D: BlockDevice> Ext4FileSystem<D> {
+    /// 打开 inode，返回共享同一表项的句柄
+    ///
+    /// # 错误
+    ///
+    /// - `ErrorKind::InvalidInput` - inode 编号为 0 或超出范围
+    /// - `ErrorKind::NotFound` - inode 未被使用（链接数为 0 且没有打开）
+    pub fn open_inode(&mut self, ino: u32) -> Result<InodeHandle> {
+        if self.open_inodes.refs(ino) == 0 {
+            let links = self.with_inode_ref(ino, |inode_ref| inode_ref.with_inode(|inode| u16::from_le(inode.links_count)))?;
+            if links == 0 {
+                return Err(Error::new(ErrorKind::NotFound, "Inode is not in use"));
+            }
+        }
+        Ok(self.open_inodes.acquire(ino))
+    }
+
+    /// 关闭句柄
+    ///
+    /// 最后一个句柄关闭时提交尚未写入的文件大小；链接数已降为 0 时
+    /// 通过 [`drop_inode`](Self::drop_inode) 释放 inode 和数据块
+    pub fn close_inode(&mut self, handle: InodeHandle) -> Result<()> {
+        let ino = handle.ino;
+        match self.open_inodes.release(handle) {
+            Some(entry) => self.release_open_inode(ino, entry),
+            None => Ok(()),
+        }
+    }
+
+    /// inode 当前的句柄数
+    pub fn open_count(&self, ino: u32) -> u32 {
+        self.open_inodes.refs(ino)
+    }
+
+    /// 把表项中尚未写入的文件大小写入 inode
+    pub(super) fn commit_pending_size(&mut self, ino: u32) -> Result<()> {
+        match self.open_inodes.get_mut(ino).and_then(|entry| entry.pending_size.take()) {
+            Some(pending) => self.extend_size(ino, pending),
+            None => Ok(()),
+        }
+    }
+
+    /// 文件大小小于 `size` 时扩大到 `size`
+    fn extend_size(&mut self, ino: u32, size: u64) -> Result<()> {
+        self.with_inode_ref(ino, |inode_ref| {
+            if size > inode_ref.size()? {
+                inode_ref.set_size(size)?;
+                inode_ref.mark_dirty()?;
+            }
+            Ok(())
+        })
+    }
+
+    /// 链接数降为 0 的 inode：仍然打开时推迟释放
+    ///
+    /// 返回真表示已推迟，调用者不应释放 inode
+    pub(super) fn defer_unlinked(&mut self, ino: u32) -> bool {
+        match self.open_inodes.get_mut(ino) {
+            Some(entry) => {
+                log::debug!("[OPEN] inode {ino} unlinked while open, freeing on last close");
+                entry.unlinked = true;
+                true
+            }
+            None => false,
+        }
+    }
+
+    /// 卸载前处理所有仍然打开的 inode
+    pub(super) fn release_open_inodes(&mut self) -> Result<()> {
+        for (ino, entry) in self.open_inodes.drain() {
+            self.release_open_inode(ino, entry)?;
+        }
+        Ok(())
+    }
+
+    /// 最后一个引用释放后的处理
+    fn release_open_inode(&mut self, ino: u32, entry: OpenInode) -> Result<()> {
+        if entry.unlinked {
+            return self.drop_inode(ino);
+        }
+        match entry.pending_size {
+            Some(pending) => self.extend_size(ino, pending),
+            None => Ok(()),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_open_inode_table_refs() {
+        let mut table = OpenInodeTable::new();
+        let a = table.acquire(12);
+        let b = table.acquire(12);
+        assert_eq!(table.refs(12), 2);
+
+        let entry = table.get_mut(12).unwrap();
+        entry.pending_size = Some(4096);
+        entry.extent = Some((0, 8, 1000));
+        table.invalidate_extent(12);
+        assert!(table.get(12).unwrap().extent.is_none());
+
+        // 最后一个引用释放时才返回表项
+        assert!(table.release(a).is_none());
+        let entry = table.release(b).unwrap();
+        assert_eq!(entry.pending_size, Some(4096));
+        assert_eq!(table.refs(12), 0);
+        assert!(table.is_empty());
+    }
+}
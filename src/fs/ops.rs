@@ -0,0 +1,245 @@
+//! 按路径操作的便捷函数，对应 `std::fs` 中的同名函数
+//!
+//! 基于 [`Ext4FileSystem`] 的底层 API 实现，调用者只需要提供完整路径，
+//! 不必自己拆分父目录和文件名，也不必处理 inode 号和文件句柄。
+//! 接受完整路径的创建方法 [`Ext4FileSystem::create_file_path`]、
+//! [`Ext4FileSystem::create_dir_path`] 和 [`Ext4FileSystem::create_dir_all`] 也在这里实现。
+//!
+//! ```rust,ignore
+//! use lwext4_core::ops;
+//!
+//! ops::create_dir_all(&mut fs, "/etc/app")?;
+//! ops::write(&mut fs, "/etc/app/config", b"debug=1\n")?;
+//! let data = ops::read(&mut fs, "/etc/app/config")?;
+//! let size = ops::metadata(&mut fs, "/etc/app/config")?.size;
+//! ops::remove_file(&mut fs, "/etc/app/config")?;
+//! ```
+
+use crate::{
+    block::{BlockDevice, FsOp},
+    error::{Error, ErrorKind, Result},
+};
+use alloc::vec::Vec;
+
+use super::{
+    copy::split_parent,
+    file::{File, OpenOptions},
+    filesystem::Ext4FileSystem,
+    metadata::FileMetadata,
+    types::FollowSymlink,
+};
+
+/// 新建目录的权限
+const DIR_MODE: u16 = 0o755;
+
+/// 读取整个文件，对应 `std::fs::read`
+///
+/// 路径最后的符号链接会被跟随
+pub fn read<D: BlockDevice>(fs: &mut Ext4FileSystem<D>, path: &str) -> Result<Vec<u8>> {
+    let inode_num = fs.lookup_with(path, FollowSymlink::Follow)?;
+    fs.check_regular_file(inode_num)?;
+    let mut file = File::new(fs.open_inode(inode_num)?, fs.superblock().block_size());
+    let data = file.read_to_end(fs);
+    file.close(fs)?;
+    data
+}
+
+/// 把 `data` 写为文件的全部内容，对应 `std::fs::write`
+///
+/// 文件不存在时以 0o644 创建，存在时先截断。父目录必须已经存在；
+/// 路径最后一个组件是符号链接时返回 `ErrorKind::InvalidInput`
+pub fn write<D: BlockDevice>(fs: &mut Ext4FileSystem<D>, path: &str, data: &[u8]) -> Result<()> {
+    let mut file = fs.open_with(path, OpenOptions::new().write(true).create(true).truncate(true))?;
+    let written = write_all(&mut file, fs, data);
+    let closed = file.close(fs);
+    written.and(closed)
+}
+
+/// 依次创建路径上所有不存在的目录，对应 `std::fs::create_dir_all`
+///
+/// 新目录的权限为 0o755，见 [`Ext4FileSystem::create_dir_all`]
+pub fn create_dir_all<D: BlockDevice>(fs: &mut Ext4FileSystem<D>, path: &str) -> Result<()> {
+    fs.create_dir_all(path, DIR_MODE).map(|_| ())
+}
+
+/// 删除文件，对应 `std::fs::remove_file`
+pub fn remove_file<D: BlockDevice>(fs: &mut Ext4FileSystem<D>, path: &str) -> Result<()> {
+    let (parent, name) = split_parent(path)?;
+    fs.remove_file(parent, name)
+}
+
+/// 获取文件元数据，对应 `std::fs::metadata`
+///
+/// 路径最后的符号链接会被跟随，见 [`Ext4FileSystem::metadata`]
+pub fn metadata<D: BlockDevice>(fs: &mut Ext4FileSystem<D>, path: &str) -> Result<FileMetadata> {
+    fs.metadata(path)
+}
+
+impl<D: BlockDevice> Ext4FileSystem<D> {
+    /// 按完整路径创建普通文件，见 [`create_file`](Self::create_file)
+    ///
+    /// 末尾的 `/` 会被忽略。父目录必须已经存在，需要时先调用
+    /// [`create_dir_all`](Self::create_dir_all)
+    ///
+    /// # 错误
+    ///
+    /// - `ErrorKind::InvalidInput` - 路径没有文件名（如 `/`）
+    ///
+    /// # 示例
+    ///
+    /// ```rust,ignore
+    /// fs.create_dir_all("/var/log", 0o755)?;
+    /// let ino = fs.create_file_path("/var/log/boot.log", 0o644)?;
+    /// ```
+    pub fn create_file_path(&mut self, path: &str, mode: u16) -> Result<u32> {
+        let (parent, name) = split_parent(path)?;
+        self.create_file(parent, name, mode)
+    }
+
+    /// 按完整路径创建目录，见 [`create_dir`](Self::create_dir)
+    ///
+    /// 末尾的 `/` 会被忽略。父目录必须已经存在
+    ///
+    /// # 错误
+    ///
+    /// - `ErrorKind::AlreadyExists` - 路径已经存在（包括根目录）
+    pub fn create_dir_path(&mut self, path: &str, mode: u16) -> Result<u32> {
+        if components(path).next().is_none() {
+            return Err(Error::new(ErrorKind::AlreadyExists, "Root directory already exists"));
+        }
+        let (parent, name) = split_parent(path)?;
+        self.create_dir(parent, name, mode)
+    }
+
+    /// 依次创建路径上所有不存在的目录，返回最后一级目录的 inode 号
+    ///
+    /// 新建的目录使用 `mode`，已经存在的目录不修改。空组件、`.` 和末尾的 `/`
+    /// 会被忽略，`/` 本身返回根目录。与路径查找一样，路径中的符号链接不会被跟随
+    ///
+    /// # 错误
+    ///
+    /// - `ErrorKind::NotADirectory` - 路径上的某个组件存在但不是目录（包括符号链接）
+    ///
+    /// # 示例
+    ///
+    /// ```rust,ignore
+    /// let ino = fs.create_dir_all("/usr/local/share/", 0o755)?;
+    /// ```
+    pub fn create_dir_all(&mut self, path: &str, mode: u16) -> Result<u32> {
+        let mut inode_num = crate::consts::EXT4_ROOT_INODE;
+        for name in components(path) {
+            let parent = inode_num;
+            inode_num = match self.lookup_in_dir(parent, name) {
+                Ok(ino) if self.get_inode_ref(ino)?.is_dir()? => ino,
+                Ok(_) => return Err(Error::new(ErrorKind::NotADirectory, "Path component is not a directory")),
+                Err(e) if e.kind() == ErrorKind::NotFound => self.observe(FsOp::Create, |fs| {
+                    fs.with_alloc_undo(|fs, undo| fs.create_dir_in_steps(undo, parent, name, mode))
+                })?,
+                Err(e) => return Err(e),
+            };
+        }
+        Ok(inode_num)
+    }
+}
+
+fn write_all<D: BlockDevice>(file: &mut File<D>, fs: &mut Ext4FileSystem<D>, mut data: &[u8]) -> Result<()> {
+    while !data.is_empty() {
+        let n = file.write(fs, data)?;
+        if n == 0 {
+            return Err(Error::new(ErrorKind::Io, "Failed to write whole buffer"));
+        }
+        data = &data[n..];
+    }
+    Ok(())
+}
+
+/// 路径中的各个组件，忽略空组件和 `.`
+fn components(path: &str) -> impl Iterator<Item = &str> {
+    path.split('/').filter(|name| !name.is_empty() && *name != ".")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing::image;
+
+    #[test]
+    fn test_components() {
+        assert_eq!(components("/a//b/./c/").collect::<Vec<_>>(), ["a", "b", "c"]);
+        assert_eq!(components("/").count(), 0);
+        assert_eq!(components("rel/x").collect::<Vec<_>>(), ["rel", "x"]);
+    }
+
+    #[test]
+    fn test_read_write() {
+        let mut fs = image::mount(image::image());
+        write(&mut fs, "/file", b"hello world").unwrap();
+        assert_eq!(read(&mut fs, "/file").unwrap(), b"hello world");
+        assert_eq!(metadata(&mut fs, "/file").unwrap().size, 11);
+
+        // 覆盖时先截断
+        write(&mut fs, "/file", b"bye").unwrap();
+        assert_eq!(read(&mut fs, "/file").unwrap(), b"bye");
+        assert_eq!(metadata(&mut fs, "/file").unwrap().size, 3);
+
+        // 跟随符号链接读取，写入符号链接被拒绝
+        fs.fsymlink("file", "/", "link").unwrap();
+        assert_eq!(read(&mut fs, "/link").unwrap(), b"bye");
+        assert!(metadata(&mut fs, "/link").unwrap().is_file());
+        assert_eq!(write(&mut fs, "/link", b"x").unwrap_err().kind(), ErrorKind::InvalidInput);
+
+        assert_eq!(read(&mut fs, "/missing").unwrap_err().kind(), ErrorKind::NotFound);
+        // 父目录必须存在
+        assert_eq!(write(&mut fs, "/no/file", b"x").unwrap_err().kind(), ErrorKind::NotFound);
+
+        remove_file(&mut fs, "/file").unwrap();
+        assert_eq!(read(&mut fs, "/file").unwrap_err().kind(), ErrorKind::NotFound);
+        assert_eq!(remove_file(&mut fs, "/file").unwrap_err().kind(), ErrorKind::NotFound);
+    }
+
+    #[test]
+    fn test_create_paths() {
+        let mut fs = image::mount(image::image());
+        fs.create_dir_path("/dir/", 0o700).unwrap();
+        let ino = fs.create_file_path("/dir/file", 0o640).unwrap();
+
+        let meta = metadata(&mut fs, "/dir/file").unwrap();
+        assert_eq!(meta.inode_num, ino);
+        assert!(meta.is_file());
+        assert_eq!(meta.permissions & 0o777, 0o640);
+        assert_eq!(metadata(&mut fs, "/dir").unwrap().permissions & 0o777, 0o700);
+
+        assert_eq!(fs.create_file_path("/", 0o644).unwrap_err().kind(), ErrorKind::InvalidInput);
+        assert_eq!(fs.create_dir_path("/", 0o755).unwrap_err().kind(), ErrorKind::AlreadyExists);
+        assert_eq!(fs.create_dir_path("/dir", 0o755).unwrap_err().kind(), ErrorKind::AlreadyExists);
+        assert_eq!(fs.create_file_path("/dir/file", 0o644).unwrap_err().kind(), ErrorKind::AlreadyExists);
+        assert_eq!(fs.read_dir("/dir").unwrap().len(), 3);
+        assert_eq!(fs.create_file_path("/none/file", 0o644).unwrap_err().kind(), ErrorKind::NotFound);
+    }
+
+    #[test]
+    fn test_create_dir_all() {
+        let mut fs = image::mount(image::image());
+        assert_eq!(fs.create_dir_all("/", 0o755).unwrap(), crate::consts::EXT4_ROOT_INODE);
+
+        // 部分目录已经存在：已有目录保持原来的权限
+        let a = fs.create_dir_path("/a", 0o700).unwrap();
+        let c = fs.create_dir_all("/a//b/./c/", 0o755).unwrap();
+        assert_eq!(fs.lookup_with("/a/b/c", FollowSymlink::NoFollow).unwrap(), c);
+        assert_eq!(fs.lookup_with("/a", FollowSymlink::NoFollow).unwrap(), a);
+        assert_eq!(metadata(&mut fs, "/a").unwrap().permissions & 0o777, 0o700);
+        assert_eq!(metadata(&mut fs, "/a/b").unwrap().permissions & 0o777, 0o755);
+
+        // 全部存在时返回最后一级目录
+        assert_eq!(fs.create_dir_all("/a/b/c", 0o755).unwrap(), c);
+        create_dir_all(&mut fs, "/a/b/c/d").unwrap();
+        assert!(fs.is_dir("/a/b/c/d").unwrap());
+
+        // 路径上的组件是普通文件
+        write(&mut fs, "/a/file", b"x").unwrap();
+        let err = fs.create_dir_all("/a/file/sub", 0o755).unwrap_err();
+        assert_eq!(err.kind(), ErrorKind::NotADirectory);
+        assert_eq!(create_dir_all(&mut fs, "/a/file").unwrap_err().kind(), ErrorKind::NotADirectory);
+        assert!(!fs.exists("/a/file/sub"));
+    }
+}
@@ -0,0 +1,516 @@
+//! 文件数据页缓存
+//!
+//! 块缓存只适合元数据：多块文件的小块、非对齐读取每次都要重新查 extent 树、
+//! 读整块。启用页缓存后，[`read_at_inode`](Ext4FileSystem::read_at_inode)
+//! 以页（一个文件系统块）为单位把文件数据缓存在内存中，重复读取直接从缓存返回。
+//!
+//! 只缓存已经映射且已写入的块。完全落在这些块内、不扩展文件的写入只修改
+//! 缓存页并标记为脏，在 [`flush`](Ext4FileSystem::flush)、`fsync`、卸载或
+//! 页被淘汰时写回设备；其余写入（需要分配块、写入 unwritten extent、扩展文件）
+//! 仍走原来的路径，并先写回、丢弃涉及的缓存页。
+//!
+//! # 限制
+//!
+//! - 只作用于使用 extent 的普通文件
+//! - 与延迟分配相同，直接操作 `InodeRef` 等底层接口看到的是磁盘状态，
+//!   使用前应先 `flush`
+//! - 超过缓存容量一半的读写直接访问设备，不占用缓存
+
+use crate::{
+    block::BlockDevice,
+    error::{Error, Result},
+};
+use alloc::{boxed::Box, collections::BTreeMap, vec::Vec};
+
+use super::{
+    filesystem::{Ext4FileSystem, MAX_WRITE_RUN},
+    MappingFlags,
+};
+
+/// 缓存页的键：`(inode 编号, 逻辑块号)`
+type PageKey = (u32, u32);
+
+/// 页缓存统计信息
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct PageCacheStats {
+    /// 容量（页数）
+    pub capacity: usize,
+    /// 缓存的页数
+    pub pages: usize,
+    /// 尚未写回的脏页数
+    pub dirty_pages: usize,
+    /// 命中次数（按页计）
+    pub hits: u64,
+    /// 未命中次数（按页计）
+    pub misses: u64,
+}
+
+/// 一个缓存页
+#[derive(Debug)]
+struct Page {
+    data: Box<[u8]>,
+    /// 对应的物理块
+    pblk: u64,
+    dirty: bool,
+    /// 最近一次访问的时间戳，LRU 淘汰用
+    tick: u64,
+}
+
+/// 页缓存：按 LRU 淘汰
+#[derive(Debug)]
+pub(super) struct PageCache {
+    capacity: usize,
+    pages: BTreeMap<PageKey, Page>,
+    /// 访问时间戳 → 页，最旧的在前
+    lru: BTreeMap<u64, PageKey>,
+    tick: u64,
+    hits: u64,
+    misses: u64,
+}
+
+impl PageCache {
+    pub(super) fn new(capacity: usize) -> Self {
+        Self {
+            capacity: capacity.max(1),
+            pages: BTreeMap::new(),
+            lru: BTreeMap::new(),
+            tick: 0,
+            hits: 0,
+            misses: 0,
+        }
+    }
+
+    pub(super) fn stats(&self) -> PageCacheStats {
+        PageCacheStats {
+            capacity: self.capacity,
+            pages: self.pages.len(),
+            dirty_pages: self.pages.values().filter(|p| p.dirty).count(),
+            hits: self.hits,
+            misses: self.misses,
+        }
+    }
+
+    /// 一次访问涉及 `pages` 页时是否经过缓存
+    fn admits(&self, pages: u64) -> bool {
+        pages <= (self.capacity as u64 / 2).max(1)
+    }
+
+    /// 查找页并更新访问时间
+    fn get(&mut self, key: PageKey) -> Option<&mut Page> {
+        let page = self.pages.get_mut(&key)?;
+        self.lru.remove(&page.tick);
+        self.tick += 1;
+        page.tick = self.tick;
+        self.lru.insert(self.tick, key);
+        Some(page)
+    }
+
+    /// 插入页，缓存已满时返回被淘汰的脏页 `(物理块, 数据)`，由调用者写回
+    fn insert(&mut self, key: PageKey, mut page: Page) -> Option<(u64, Box<[u8]>)> {
+        self.tick += 1;
+        page.tick = self.tick;
+        self.lru.insert(self.tick, key);
+        if let Some(old) = self.pages.insert(key, page) {
+            self.lru.remove(&old.tick);
+        }
+
+        if self.pages.len() <= self.capacity {
+            return None;
+        }
+        let (_, victim) = self.lru.pop_first()?;
+        let victim = self.pages.remove(&victim)?;
+        victim.dirty.then_some((victim.pblk, victim.data))
+    }
+
+    /// 移除 inode 在逻辑块区间 `[start, end)` 内的页，返回其中的脏页
+    fn remove_range(&mut self, ino: u32, start: u32, end: u32) -> Vec<(u64, Box<[u8]>)> {
+        if start >= end {
+            return Vec::new();
+        }
+        let keys: Vec<PageKey> = self.pages.range((ino, start)..(ino, end)).map(|(&k, _)| k).collect();
+
+        let mut dirty = Vec::new();
+        for key in keys {
+            if let Some(page) = self.pages.remove(&key) {
+                self.lru.remove(&page.tick);
+                if page.dirty {
+                    dirty.push((page.pblk, page.data));
+                }
+            }
+        }
+        dirty
+    }
+
+    /// 取出所有脏页的副本并标记为干净，按物理块排序
+    fn clean_all(&mut self) -> Vec<(u64, Box<[u8]>)> {
+        let mut dirty: Vec<_> = self
+            .pages
+            .values_mut()
+            .filter(|p| p.dirty)
+            .map(|p| {
+                p.dirty = false;
+                (p.pblk, p.data.clone())
+            })
+            .collect();
+        dirty.sort_unstable_by_key(|&(pblk, _)| pblk);
+        dirty
+    }
+
+    /// 取出 inode 的脏页副本并标记为干净，按物理块排序
+    fn clean_inode(&mut self, ino: u32) -> Vec<(u64, Box<[u8]>)> {
+        let mut dirty: Vec<_> = self
+            .pages
+            .range_mut((ino, 0)..=(ino, u32::MAX))
+            .filter(|(_, p)| p.dirty)
+            .map(|(_, p)| {
+                p.dirty = false;
+                (p.pblk, p.data.clone())
+            })
+            .collect();
+        dirty.sort_unstable_by_key(|&(pblk, _)| pblk);
+        dirty
+    }
+
+    /// 把写回失败的页重新标记为脏
+    fn mark_dirty(&mut self, pblks: &[u64]) {
+        for page in self.pages.values_mut() {
+            if pblks.binary_search(&page.pblk).is_ok() {
+                page.dirty = true;
+            }
+        }
+    }
+}
+
+impl<D: BlockDevice> Ext4FileSystem<D> {
+    /// 设置页缓存容量（页数），0 表示关闭
+    ///
+    /// 修改容量或关闭时先写回所有脏页。也可以通过
+    /// [`FsConfig::page_cache_pages`](super::FsConfig::page_cache_pages) 在挂载时启用。
+    pub fn set_page_cache(&mut self, pages: usize) -> Result<()> {
+        self.flush_page_cache()?;
+        self.page_cache = (pages > 0).then(|| PageCache::new(pages));
+        Ok(())
+    }
+
+    /// 页缓存统计信息，未启用时返回 `None`
+    pub fn page_cache_stats(&self) -> Option<PageCacheStats> {
+        self.page_cache.as_ref().map(PageCache::stats)
+    }
+
+    /// 把页缓存中的所有脏页写回设备
+    ///
+    /// 物理连续的脏页合并为一次设备写入。失败时未写回的页仍为脏。
+    pub fn flush_page_cache(&mut self) -> Result<()> {
+        let dirty = match self.page_cache.as_mut() {
+            Some(cache) => cache.clean_all(),
+            None => return Ok(()),
+        };
+        self.write_back_cleaned(dirty)
+    }
+
+    /// 只写回 inode 的脏页，见 [`fsync_inode`](Self::fsync_inode)
+    pub(super) fn flush_page_cache_inode(&mut self, ino: u32) -> Result<()> {
+        let dirty = match self.page_cache.as_mut() {
+            Some(cache) => cache.clean_inode(ino),
+            None => return Ok(()),
+        };
+        self.write_back_cleaned(dirty)
+    }
+
+    /// 写回已标记为干净的页，失败时把未写回的页重新标记为脏
+    fn write_back_cleaned(&mut self, dirty: Vec<(u64, Box<[u8]>)>) -> Result<()> {
+        if let Err((e, failed)) = self.write_back_pages(dirty) {
+            if let Some(cache) = self.page_cache.as_mut() {
+                cache.mark_dirty(&failed);
+            }
+            return Err(e);
+        }
+        Ok(())
+    }
+
+    /// 写回页（按物理块排序），失败时返回错误和尚未写回的物理块
+    fn write_back_pages(&mut self, pages: Vec<(u64, Box<[u8]>)>) -> core::result::Result<(), (Error, Vec<u64>)> {
+        let mut buf = Vec::new();
+        let mut i = 0;
+        while i < pages.len() {
+            let start = pages[i].0;
+            let mut run = 1;
+            while i + run < pages.len()
+                && pages[i + run].0 == start + run as u64
+                && run < MAX_WRITE_RUN as usize
+            {
+                run += 1;
+            }
+
+            buf.clear();
+            for (_, data) in &pages[i..i + run] {
+                buf.extend_from_slice(data);
+            }
+            if let Err(e) = self.bdev.write_blocks(start, run as u32, &buf) {
+                return Err((e, pages[i..].iter().map(|&(pblk, _)| pblk).collect()));
+            }
+            i += run;
+        }
+        Ok(())
+    }
+
+    /// 写回并丢弃 inode 在逻辑块区间 `[start, end)` 内的缓存页
+    pub(super) fn page_cache_evict(&mut self, ino: u32, start: u32, end: u32) -> Result<()> {
+        let mut dirty = match self.page_cache.as_mut() {
+            Some(cache) => cache.remove_range(ino, start, end),
+            None => return Ok(()),
+        };
+        dirty.sort_unstable_by_key(|&(pblk, _)| pblk);
+        self.write_back_pages(dirty).map_err(|(e, _)| e)
+    }
+
+    /// 写回所有脏页并清空缓存（块被迁移之前调用）
+    pub(super) fn page_cache_clear(&mut self) -> Result<()> {
+        self.flush_page_cache()?;
+        if let Some(cache) = self.page_cache.as_mut() {
+            *cache = PageCache::new(cache.capacity);
+        }
+        Ok(())
+    }
+
+    /// 丢弃 inode 的所有缓存页，不写回（inode 被释放时调用）
+    pub(super) fn page_cache_discard(&mut self, ino: u32) {
+        if let Some(cache) = self.page_cache.as_mut() {
+            cache.remove_range(ino, 0, u32::MAX);
+        }
+    }
+
+    /// 经由页缓存读取
+    ///
+    /// 未启用、文件不适用或读取范围太大时返回 `None`，由调用者直接读取设备；
+    /// 此时已经写回了范围内的脏页，直接读取能看到最新数据
+    pub(super) fn page_cache_read(&mut self, ino: u32, buf: &mut [u8], offset: u64) -> Result<Option<usize>> {
+        if self.page_cache.is_none() || buf.is_empty() {
+            return Ok(None);
+        }
+
+        let block_size = self.superblock().block_size() as u64;
+        let (eligible, size) = self.with_inode_ref(ino, |inode_ref| {
+            Ok((inode_ref.is_file()? && inode_ref.has_extents()?, inode_ref.size()?))
+        })?;
+        if offset >= size {
+            return Ok(Some(0));
+        }
+        let n = (buf.len() as u64).min(size - offset) as usize;
+        let first = (offset / block_size) as u32;
+        let last = ((offset + n as u64 - 1) / block_size) as u32;
+
+        if !eligible || !self.page_cache.as_ref().is_some_and(|c| c.admits((last - first + 1) as u64)) {
+            self.page_cache_evict(ino, first, last + 1)?;
+            return Ok(None);
+        }
+
+        self.fill_pages(ino, first, last + 1)?;
+
+        let cache = self.page_cache.as_mut().expect("page cache enabled");
+        for lblk in first..=last {
+            let page_start = lblk as u64 * block_size;
+            let from = page_start.max(offset);
+            let to = (page_start + block_size).min(offset + n as u64);
+            let dst = &mut buf[(from - offset) as usize..(to - offset) as usize];
+            match cache.get((ino, lblk)) {
+                Some(page) => {
+                    dst.copy_from_slice(&page.data[(from - page_start) as usize..(to - page_start) as usize])
+                }
+                // 空洞和 unwritten extent 不缓存
+                None => dst.fill(0),
+            }
+        }
+        Ok(Some(n))
+    }
+
+    /// 把 `[start, end)` 内已映射、已写入但不在缓存中的块读入缓存
+    fn fill_pages(&mut self, ino: u32, start: u32, end: u32) -> Result<()> {
+        let cache = self.page_cache.as_mut().expect("page cache enabled");
+        let mut missing = 0;
+        for lblk in start..end {
+            if cache.get((ino, lblk)).is_some() {
+                cache.hits += 1;
+            } else {
+                missing += 1;
+            }
+        }
+        if missing == 0 {
+            return Ok(());
+        }
+        cache.misses += missing;
+
+        let block_size = self.superblock().block_size() as usize;
+        let mappings = self.with_inode_ref(ino, |inode_ref| inode_ref.fiemap(start..end))?;
+
+        let mut buf = Vec::new();
+        for m in mappings.iter().filter(|m| !m.flags.contains(MappingFlags::UNWRITTEN)) {
+            let mut lblk = m.logical_block;
+            let map_end = m.logical_end() as u32;
+            while lblk < map_end {
+                if self.page_cache.as_ref().is_some_and(|c| c.pages.contains_key(&(ino, lblk))) {
+                    lblk += 1;
+                    continue;
+                }
+
+                // 读入连续的一段未缓存块
+                let run_start = lblk;
+                while lblk < map_end
+                    && lblk - run_start < MAX_WRITE_RUN
+                    && !self.page_cache.as_ref().is_some_and(|c| c.pages.contains_key(&(ino, lblk)))
+                {
+                    lblk += 1;
+                }
+                let run = lblk - run_start;
+                let pblk = m.physical_block + (run_start - m.logical_block) as u64;
+
+                buf.resize(run as usize * block_size, 0);
+                self.bdev.read_blocks(pblk, run, &mut buf)?;
+                for (i, data) in buf.chunks_exact(block_size).enumerate() {
+                    let page = Page { data: data.into(), pblk: pblk + i as u64, dirty: false, tick: 0 };
+                    self.page_cache_insert((ino, run_start + i as u32), page)?;
+                }
+            }
+        }
+        Ok(())
+    }
+
+    fn page_cache_insert(&mut self, key: PageKey, page: Page) -> Result<()> {
+        let victim = match self.page_cache.as_mut() {
+            Some(cache) => cache.insert(key, page),
+            None => None,
+        };
+        match victim {
+            Some((pblk, data)) => self.bdev.write_block(pblk, &data).map(|_| ()),
+            None => Ok(()),
+        }
+    }
+
+    /// 经由页缓存写入
+    ///
+    /// 只有写入范围全部落在已映射、已写入的块内且不扩展文件时才写入缓存，
+    /// 返回值的含义见 [`write_at_inode_sized`](Self::write_at_inode_sized)。
+    /// 否则写回并丢弃范围内的缓存页后返回 `None`，由调用者走原来的写入路径
+    pub(super) fn page_cache_write(&mut self, ino: u32, buf: &[u8], offset: u64) -> Result<Option<(usize, bool)>> {
+        if self.page_cache.is_none() {
+            return Ok(None);
+        }
+
+        let block_size = self.superblock().block_size() as u64;
+        let end = offset + buf.len() as u64;
+        let first = (offset / block_size) as u32;
+        let last = ((end - 1) / block_size) as u32;
+
+        if !self.page_cache_writable(ino, first, last + 1, end)? {
+            self.page_cache_evict(ino, first, last + 1)?;
+            return Ok(None);
+        }
+
+        self.fill_pages(ino, first, last + 1)?;
+
+        let cache = self.page_cache.as_mut().expect("page cache enabled");
+        for lblk in first..=last {
+            let page_start = lblk as u64 * block_size;
+            let from = page_start.max(offset);
+            let to = (page_start + block_size).min(end);
+            let page = cache.get((ino, lblk)).expect("mapped block was just cached");
+            page.data[(from - page_start) as usize..(to - page_start) as usize]
+                .copy_from_slice(&buf[(from - offset) as usize..(to - offset) as usize]);
+            page.dirty = true;
+        }
+        Ok(Some((buf.len(), true)))
+    }
+
+    /// `[start, end)` 能否只写缓存：适用的文件、不超过文件末尾 `write_end`，
+    /// 且每个块都已映射、已写入
+    fn page_cache_writable(&mut self, ino: u32, start: u32, end: u32, write_end: u64) -> Result<bool> {
+        if !self.page_cache.as_ref().is_some_and(|c| c.admits((end - start) as u64)) {
+            return Ok(false);
+        }
+
+        // 缓存中的页都对应已写入的块
+        let all_cached = self
+            .page_cache
+            .as_ref()
+            .is_some_and(|c| (start..end).all(|lblk| c.pages.contains_key(&(ino, lblk))));
+
+        self.with_inode_ref(ino, |inode_ref| {
+            if !inode_ref.is_file()? || !inode_ref.has_extents()? || write_end > inode_ref.size()? {
+                return Ok(false);
+            }
+            if all_cached {
+                return Ok(true);
+            }
+
+            let mut next = start as u64;
+            for m in inode_ref.fiemap(start..end)? {
+                if m.logical_block as u64 != next || m.flags.contains(MappingFlags::UNWRITTEN) {
+                    return Ok(false);
+                }
+                next = m.logical_end();
+            }
+            Ok(next == end as u64)
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn page(pblk: u64, dirty: bool) -> Page {
+        Page { data: alloc::vec![pblk as u8; 16].into_boxed_slice(), pblk, dirty, tick: 0 }
+    }
+
+    #[test]
+    fn test_lru_eviction_returns_dirty_victim() {
+        let mut cache = PageCache::new(2);
+        assert!(cache.insert((12, 0), page(100, true)).is_none());
+        assert!(cache.insert((12, 1), page(101, false)).is_none());
+
+        // 访问使 (12, 0) 变新，淘汰干净的 (12, 1)
+        assert!(cache.get((12, 0)).is_some());
+        assert!(cache.insert((12, 2), page(102, false)).is_none());
+        assert!(cache.get((12, 1)).is_none());
+
+        // 淘汰脏页时交给调用者写回
+        assert!(cache.get((12, 2)).is_some());
+        let (pblk, data) = cache.insert((13, 0), page(200, false)).unwrap();
+        assert_eq!(pblk, 100);
+        assert_eq!(data[0], 100);
+        assert_eq!(cache.stats().pages, 2);
+    }
+
+    #[test]
+    fn test_remove_range_and_clean_all() {
+        let mut cache = PageCache::new(8);
+        cache.insert((12, 0), page(103, true));
+        cache.insert((12, 5), page(101, true));
+        cache.insert((12, 9), page(102, false));
+        cache.insert((13, 0), page(100, true));
+
+        let removed = cache.remove_range(12, 1, 10);
+        assert_eq!(removed.len(), 1);
+        assert_eq!(removed[0].0, 101);
+        assert_eq!(cache.stats().pages, 2);
+
+        let dirty: Vec<u64> = cache.clean_all().iter().map(|&(pblk, _)| pblk).collect();
+        assert_eq!(dirty, alloc::vec![100, 103]);
+        assert_eq!(cache.stats().dirty_pages, 0);
+
+        cache.mark_dirty(&[103]);
+        assert_eq!(cache.stats().dirty_pages, 1);
+    }
+
+    #[test]
+    fn test_clean_inode() {
+        let mut cache = PageCache::new(8);
+        cache.insert((12, 7), page(201, true));
+        cache.insert((12, u32::MAX), page(200, true));
+        cache.insert((13, 0), page(100, true));
+
+        let dirty: Vec<u64> = cache.clean_inode(12).iter().map(|&(pblk, _)| pblk).collect();
+        assert_eq!(dirty, alloc::vec![200, 201]);
+        assert_eq!(cache.stats().dirty_pages, 1);
+    }
+}
@@ -0,0 +1,167 @@
+//! 磁盘配额的挂载、查询和写回
+//!
+//! 配额的内存结构和文件格式见 [`crate::quota`]。这里负责：
+//!
+//! - 挂载时读入 superblock 指向的配额 inode
+//! - 分配、释放 inode 时调整 inode 用量（空间用量在
+//!   [`InodeRef::set_blocks_count`](super::InodeRef::set_blocks_count) 中调整）
+//! - 刷新、提交和卸载时写回修改过的配额文件
+
+use crate::{
+    block::BlockDevice,
+    consts::{EXT4_FEATURE_RO_COMPAT_PROJECT, EXT4_FEATURE_RO_COMPAT_QUOTA},
+    error::{Error, ErrorKind, Result},
+    quota::{format, QuotaId, QuotaLimits, QuotaState, QuotaTable, QuotaType, QuotaUsage},
+};
+use alloc::{boxed::Box, vec, vec::Vec};
+use log::debug;
+
+use super::filesystem::Ext4FileSystem;
+
+impl<D: BlockDevice> Ext4FileSystem<D> {
+    /// 查询配额用量和限制
+    ///
+    /// # 错误
+    ///
+    /// - `ErrorKind::Unsupported` - 文件系统没有启用该类型的配额
+    ///
+    /// # 示例
+    ///
+    /// ```rust,ignore
+    /// let usage = fs.quota_usage(QuotaId::User(1000))?;
+    /// println!("{} bytes, {} inodes", usage.space, usage.inodes);
+    /// ```
+    pub fn quota_usage(&self, id: QuotaId) -> Result<QuotaUsage> {
+        self.superblock()
+            .quota()
+            .and_then(|q| q.usage(id))
+            .ok_or(Error::new(ErrorKind::Unsupported, "Quota type not enabled"))
+    }
+
+    /// 设置配额限制
+    ///
+    /// 新限制只约束之后的分配，已有用量超过限制时不会回收。
+    /// 修改在下一次刷新或提交时写回配额文件。
+    ///
+    /// # 错误
+    ///
+    /// - `ErrorKind::Unsupported` - 文件系统没有启用该类型的配额
+    ///
+    /// # 示例
+    ///
+    /// ```rust,ignore
+    /// let limits = QuotaLimits { space_hard: 100 << 20, inodes_hard: 1000, ..Default::default() };
+    /// fs.set_quota_limits(QuotaId::User(1000), limits)?;
+    /// ```
+    pub fn set_quota_limits(&mut self, id: QuotaId, limits: QuotaLimits) -> Result<()> {
+        self.superblock_mut()
+            .quota_mut()
+            .ok_or(Error::new(ErrorKind::Unsupported, "Quota type not enabled"))?
+            .set_limits(id, limits)
+    }
+
+    /// 挂载时读入配额文件
+    ///
+    /// 没有 `RO_COMPAT_QUOTA` 特性时不做任何事；项目配额还需要 `RO_COMPAT_PROJECT`
+    pub(super) fn load_quota(&mut self) -> Result<()> {
+        let sb = self.superblock();
+        if !sb.has_ro_compat_feature(EXT4_FEATURE_RO_COMPAT_QUOTA) {
+            return Ok(());
+        }
+        let inner = sb.inner();
+        let has_project = sb.has_ro_compat_feature(EXT4_FEATURE_RO_COMPAT_PROJECT);
+        let inums = [
+            u32::from_le(inner.usr_quota_inum),
+            u32::from_le(inner.grp_quota_inum),
+            if has_project { u32::from_le(inner.prj_quota_inum) } else { 0 },
+        ];
+        let mut state = QuotaState::new(u32::from_le(inner.first_ino));
+
+        for (qtype, inum) in QuotaType::ALL.into_iter().zip(inums) {
+            if inum == 0 {
+                continue;
+            }
+            let size = self.with_inode_ref(inum, |inode_ref| inode_ref.size())?;
+            let mut file = vec![0u8; size as usize];
+            let n = self.read_at_inode(inum, &mut file, 0)?;
+            file.truncate(n);
+
+            let (info, dquots) = format::parse(&file, qtype)?;
+            debug!("[quota] {:?}: inode {}, {} ids", qtype, inum, dquots.len());
+            state.insert(qtype, QuotaTable::new(inum, info, dquots));
+        }
+
+        self.superblock_mut().set_quota(Some(Box::new(state)));
+        Ok(())
+    }
+
+    /// 把修改过的配额文件写回
+    ///
+    /// 整个文件重新生成；新文件比原文件短时用 0 补齐，不截断
+    pub(super) fn sync_quota(&mut self) -> Result<()> {
+        let Some(quota) = self.superblock_mut().quota_mut() else {
+            return Ok(());
+        };
+        let files: Vec<_> = quota
+            .tables_mut()
+            .filter(|(_, table)| table.dirty)
+            .map(|(qtype, table)| (qtype, table.inum, format::build(qtype, &table.info, &table.dquots)))
+            .collect();
+
+        for (qtype, inum, mut bytes) in files {
+            let old_size = self.with_inode_ref(inum, |inode_ref| inode_ref.size())?;
+            if (bytes.len() as u64) < old_size {
+                bytes.resize(old_size as usize, 0);
+            }
+            self.write_at_inode_direct(inum, &bytes, 0, false)?;
+
+            if let Some(table) = self
+                .superblock_mut()
+                .quota_mut()
+                .and_then(|q| q.tables_mut().find(|(t, _)| *t == qtype))
+                .map(|(_, table)| table)
+            {
+                table.dirty = false;
+            }
+        }
+        Ok(())
+    }
+
+    /// 为新分配的 inode 计入 inode 用量
+    ///
+    /// 分配器不清空 inode，残留的 `i_blocks` 同样计入，与
+    /// [`quota_release_inode`](Self::quota_release_inode) 对称
+    ///
+    /// # 错误
+    ///
+    /// - `ErrorKind::QuotaExceeded` - 属主的 inode 数会超过硬限制
+    pub(super) fn quota_charge_new_inode(&mut self, inode_num: u32) -> Result<()> {
+        self.with_inode_ref(inode_num, |inode_ref| {
+            if !inode_ref.quota_accounted() {
+                return Ok(());
+            }
+            let owner = inode_ref.quota_owner()?;
+            let space = inode_ref.blocks_count()? * 512;
+            if let Some(quota) = inode_ref.superblock_mut().quota_mut() {
+                quota.check(&owner, 0, 1)?;
+                quota.charge(&owner, space as i64, 1);
+            }
+            Ok(())
+        })
+    }
+
+    /// 释放 inode 前扣除它的 inode 用量和剩余的空间用量
+    pub(super) fn quota_release_inode(&mut self, inode_num: u32) -> Result<()> {
+        self.with_inode_ref(inode_num, |inode_ref| {
+            if !inode_ref.quota_accounted() {
+                return Ok(());
+            }
+            let owner = inode_ref.quota_owner()?;
+            let space = inode_ref.blocks_count()? * 512;
+            if let Some(quota) = inode_ref.superblock_mut().quota_mut() {
+                quota.charge(&owner, -(space as i64), -1);
+            }
+            Ok(())
+        })
+    }
+}
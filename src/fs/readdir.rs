@@ -0,0 +1,189 @@
+//! 可配置的目录读取和按需读取的目录迭代器
+//!
+//! [`Ext4FileSystem::read_dir`] 按磁盘顺序返回包括 `.` 和 `..` 在内的全部条目。
+//! [`ReadDirOptions`] 可以跳过 `.`/`..`、按名称排序或只保留某种类型的条目；
+//! [`Ext4FileSystem::iter_dir`] 每次只解析一个目录项，遍历十万级条目的大目录时
+//! 不需要先把所有条目收集到 `Vec` 中。
+//!
+//! ```rust,ignore
+//! let opts = ReadDirOptions { skip_dots: true, sorted_by_name: true, ..Default::default() };
+//! let names: Vec<_> = fs.read_dir_with("/etc", &opts)?.into_iter().map(|e| e.name).collect();
+//!
+//! for entry in fs.iter_dir("/var/spool")? {
+//!     let entry = entry?;
+//!     println!("{}", entry.name);
+//! }
+//! ```
+
+use crate::{
+    block::{BlockDevice, FsOp},
+    consts::*,
+    dir::{fill_file_type, DirEntry, DirIterator, DirOrder, sort_entries},
+    error::{Error, ErrorKind, Result},
+};
+use alloc::vec::Vec;
+
+use super::{filesystem::Ext4FileSystem, metadata::FileType};
+
+/// 目录读取选项，见 [`Ext4FileSystem::read_dir_with`]
+///
+/// 默认值与 [`Ext4FileSystem::read_dir`] 的行为相同
+#[derive(Debug, Clone, Copy, Default)]
+pub struct ReadDirOptions {
+    /// 跳过 `.` 和 `..`
+    pub skip_dots: bool,
+    /// 按文件名的字节序排序，见 [`DirOrder::ByName`]
+    ///
+    /// 排序需要先读取全部条目，[`Ext4FileSystem::iter_dir_with`] 不支持此选项
+    pub sorted_by_name: bool,
+    /// 只保留指定类型的条目
+    pub file_type_filter: Option<FileType>,
+}
+
+impl ReadDirOptions {
+    /// 是否按名称跳过条目
+    fn skips_name(&self, name: &str) -> bool {
+        self.skip_dots && (name == "." || name == "..")
+    }
+}
+
+/// 按需读取的目录迭代器，见 [`Ext4FileSystem::iter_dir`]
+///
+/// 每次调用 `next` 只解析下一个目录项，期间独占文件系统的可变借用。
+/// 遇到错误后迭代结束。
+pub struct DirStream<'a, D: BlockDevice> {
+    fs: &'a mut Ext4FileSystem<D>,
+    dir_inode: u32,
+    iter: DirIterator,
+    options: ReadDirOptions,
+    done: bool,
+}
+
+impl<D: BlockDevice> DirStream<'_, D> {
+    /// 目录的 inode 编号
+    pub fn dir_inode(&self) -> u32 {
+        self.dir_inode
+    }
+
+    /// 下一个目录项在目录中的字节偏移，可用于 [`DirReader::seek`](crate::DirReader::seek)
+    pub fn offset(&self) -> u64 {
+        self.iter.current_offset()
+    }
+
+    fn next_entry(&mut self) -> Result<Option<DirEntry>> {
+        loop {
+            let iter = &mut self.iter;
+            let entry = self.fs.with_inode_ref(self.dir_inode, |inode_ref| {
+                let Some(mut entry) = iter.next(inode_ref)? else {
+                    return Ok(None);
+                };
+                fill_file_type(inode_ref, &mut entry)?;
+                Ok(Some(entry))
+            })?;
+            let Some(entry) = entry else {
+                return Ok(None);
+            };
+            if self.options.skips_name(&entry.name) {
+                continue;
+            }
+            if self.options.file_type_filter.is_some_and(|file_type| entry.kind() != file_type) {
+                continue;
+            }
+            return Ok(Some(entry));
+        }
+    }
+}
+
+impl<D: BlockDevice> Iterator for DirStream<'_, D> {
+    type Item = Result<DirEntry>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.done {
+            return None;
+        }
+        let result = self.next_entry().transpose();
+        if !matches!(result, Some(Ok(_))) {
+            self.done = true;
+        }
+        result
+    }
+}
+
+impl<D: BlockDevice> Ext4FileSystem<D> {
+    /// 按选项读取目录内容
+    ///
+    /// # 错误
+    ///
+    /// - `ErrorKind::NotADirectory` - 路径不是目录
+    ///
+    /// # 示例
+    ///
+    /// ```rust,ignore
+    /// let opts = ReadDirOptions { file_type_filter: Some(FileType::Directory), skip_dots: true, ..Default::default() };
+    /// for entry in fs.read_dir_with("/home", &opts)? {
+    ///     println!("{}", entry.name);
+    /// }
+    /// ```
+    pub fn read_dir_with(&mut self, path: &str, options: &ReadDirOptions) -> Result<Vec<DirEntry>> {
+        let unsorted = ReadDirOptions { sorted_by_name: false, ..*options };
+        let mut entries = self.iter_dir_with(path, &unsorted)?.collect::<Result<Vec<_>>>()?;
+        if options.sorted_by_name {
+            sort_entries(&mut entries, DirOrder::ByName);
+        }
+        Ok(entries)
+    }
+
+    /// 按需逐个读取目录项，包括 `.` 和 `..`，顺序与 [`read_dir`](Self::read_dir) 相同
+    ///
+    /// 不会一次分配所有条目，适合遍历很大的目录。迭代期间修改目录的结果未定义，
+    /// 需要修改时先收集条目或使用 [`DirReader`](crate::DirReader)。
+    ///
+    /// # 错误
+    ///
+    /// - `ErrorKind::NotADirectory` - 路径不是目录
+    ///
+    /// # 示例
+    ///
+    /// ```rust,ignore
+    /// let mut count = 0;
+    /// for entry in fs.iter_dir("/var/spool/mail")? {
+    ///     entry?;
+    ///     count += 1;
+    /// }
+    /// ```
+    pub fn iter_dir(&mut self, path: &str) -> Result<DirStream<'_, D>> {
+        self.iter_dir_with(path, &ReadDirOptions::default())
+    }
+
+    /// 按选项逐个读取目录项，见 [`iter_dir`](Self::iter_dir)
+    ///
+    /// # 错误
+    ///
+    /// - `ErrorKind::NotADirectory` - 路径不是目录
+    /// - `ErrorKind::InvalidInput` - 设置了 `sorted_by_name`，排序需要使用 [`read_dir_with`](Self::read_dir_with)
+    pub fn iter_dir_with(&mut self, path: &str, options: &ReadDirOptions) -> Result<DirStream<'_, D>> {
+        if options.sorted_by_name {
+            return Err(Error::new(ErrorKind::InvalidInput, "Sorted iteration requires read_dir_with"));
+        }
+        let (dir_inode, iter) = self.observe(FsOp::ReadDir, |fs| {
+            let dir_inode = fs.lookup_at(EXT4_ROOT_INODE, path)?;
+            let iter = fs.with_inode_ref(dir_inode, |inode_ref| DirIterator::new(inode_ref, 0))?;
+            Ok((dir_inode, iter))
+        })?;
+        Ok(DirStream { fs: self, dir_inode, iter, options: *options, done: false })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_skips_name() {
+        let opts = ReadDirOptions { skip_dots: true, ..Default::default() };
+        assert!(opts.skips_name("."));
+        assert!(opts.skips_name(".."));
+        assert!(!opts.skips_name("..."));
+        assert!(!ReadDirOptions::default().skips_name("."));
+    }
+}
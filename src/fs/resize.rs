@@ -0,0 +1,415 @@
+//! 在线扩容
+//!
+//! 把文件系统扩展到更大的设备上，对应内核在线扩容（`EXT4_IOC_RESIZE_FS`）中
+//! 不需要转换为 META_BG 的部分：
+//!
+//! - 先把最后一个块组扩展到完整大小，再逐个追加新块组
+//! - 新块组的位图和 inode 表放在组内，紧跟在 superblock 备份和 GDT 之后
+//! - 描述符表需要更多块时，从 resize inode（7 号）管理的保留 GDT 块中取用
+//!
+//! resize inode 的二级间接块指向每个保留 GDT 块，保留 GDT 块本身作为间接块，
+//! 记录它在各个备份块组中的副本，布局与 e2fsck 的 `check_resize_inode()` 一致。
+
+use crate::{
+    bitmap,
+    block::BlockDevice,
+    block_group::{get_block_group_desc_location, BlockGroup},
+    consts::*,
+    error::{Error, ErrorKind, Result},
+    superblock::Superblock,
+    types::ext4_group_desc,
+};
+use alloc::{vec, vec::Vec};
+
+use super::filesystem::Ext4FileSystem;
+
+/// 新的最后一个块组至少要有这么多数据块，否则舍弃（与 resize2fs 相同）
+pub(super) const MIN_LAST_GROUP_DATA_BLOCKS: u64 = 50;
+
+impl<D: BlockDevice> Ext4FileSystem<D> {
+    /// 把文件系统扩展到 `new_blocks` 个块
+    ///
+    /// 新增的块组需要的描述符块只能来自保留的 GDT 块（mke2fs 默认预留，
+    /// 足够扩展到原大小的 1024 倍）。新的最后一个块组太小、放不下自己的元数据时
+    /// 会被舍弃，实际大小可以通过 [`superblock`](Self::superblock) 查看。
+    ///
+    /// 完成后会更新 superblock 和描述符表的备份。
+    ///
+    /// # 错误
+    ///
+    /// - `ErrorKind::InvalidInput` - `new_blocks` 小于当前大小（缩小见
+    ///   [`resize_shrink`](Self::resize_shrink)）、超出设备容量，
+    ///   或者未启用 64 位特性时超过 2^32
+    /// - `ErrorKind::NoSpace` - 保留的 GDT 块不够
+    /// - `ErrorKind::Unsupported` - 文件系统使用 META_BG 或 BIGALLOC
+    /// - `ErrorKind::Corrupted` - resize inode 与保留 GDT 块不一致
+    ///
+    /// # 示例
+    ///
+    /// ```rust,ignore
+    /// // 首次启动时扩展到整张 SD 卡
+    /// fs.resize(sd_card_bytes / fs.superblock().block_size() as u64)?;
+    /// ```
+    pub fn resize(&mut self, new_blocks: u64) -> Result<()> {
+        let old = Superblock::new(*self.superblock().inner());
+        let old_blocks = old.blocks_count();
+        if new_blocks < old_blocks {
+            return Err(Error::new(ErrorKind::InvalidInput, "Use resize_shrink to shrink the filesystem"));
+        }
+        if new_blocks == old_blocks {
+            return Ok(());
+        }
+        if old.has_incompat_feature(EXT4_FEATURE_INCOMPAT_META_BG)
+            || old.has_ro_compat_feature(EXT4_FEATURE_RO_COMPAT_BIGALLOC)
+        {
+            return Err(Error::new(
+                ErrorKind::Unsupported,
+                "Resizing META_BG or BIGALLOC filesystems is not supported",
+            ));
+        }
+        if !old.is_64bit() && new_blocks > u32::MAX as u64 {
+            return Err(Error::new(
+                ErrorKind::InvalidInput,
+                "Block count exceeds 2^32 without 64BIT feature",
+            ));
+        }
+        let device_bytes = self.bdev.total_blocks() * self.bdev.block_size() as u64;
+        if new_blocks * old.block_size() as u64 > device_bytes {
+            return Err(Error::new(ErrorKind::InvalidInput, "New size exceeds device"));
+        }
+
+        let new = grown_superblock(&old, new_blocks)?;
+        let new_blocks = new.blocks_count();
+        if new_blocks == old_blocks {
+            return Ok(());
+        }
+        let old_groups = old.block_group_count();
+        let new_groups = new.block_group_count();
+
+        let mut free_blocks = self.extend_last_group(&old, &new)?;
+        self.take_reserved_gdt_blocks(&old, &new)?;
+        for group in old_groups..new_groups {
+            free_blocks += self.add_group(&new, group)?;
+        }
+        self.reserve_backup_gdt_blocks(&old, &new)?;
+
+        let added_inodes = (new_groups - old_groups) * new.inodes_per_group();
+        let r_blocks =
+            (old.inner().r_blocks_count() as u128 * new_blocks as u128 / old_blocks as u128) as u64;
+
+        let sb = self.superblock_mut();
+        sb.set_blocks_count(new_blocks);
+        sb.set_inodes_count(new.inodes_count());
+        sb.set_reserved_gdt_blocks(new.reserved_gdt_blocks());
+        sb.set_r_blocks_count(r_blocks);
+        sb.add_free_blocks(free_blocks);
+        sb.add_free_inodes(added_inodes);
+
+        self.write_metadata_backups()?;
+        self.repin_metadata()
+    }
+
+    /// 把原来的最后一个块组扩展到新的大小，返回增加的空闲块数
+    fn extend_last_group(&mut self, old: &Superblock, new: &Superblock) -> Result<u64> {
+        let group = old.block_group_count() - 1;
+        let old_count = old.blocks_in_group_cnt(group);
+        let new_count = new.blocks_in_group_cnt(group);
+        if new_count == old_count {
+            return Ok(0);
+        }
+
+        let block_size = new.block_size() as u64;
+        let mut bg = BlockGroup::load(&mut self.bdev, new, group)?;
+        let bitmap_addr = bg.get_block_bitmap(new) * block_size;
+        let mut bitmap = vec![0u8; block_size as usize];
+        self.bdev.read_bytes(bitmap_addr, &mut bitmap)?;
+
+        // 原来超出文件系统末尾的部分是填充位，置为已用
+        bitmap::clear_bits(&mut bitmap, old_count, new_count - old_count)?;
+        self.bdev.write_bytes(bitmap_addr, &bitmap)?;
+
+        let added = new_count - old_count;
+        let free = bg.get_free_blocks_count(new) + added;
+        bg.set_free_blocks_count(new, free);
+        crate::balloc::set_bitmap_csum(new, bg.inner_mut(), &bitmap);
+        bg.update_checksum(new);
+        bg.write(&mut self.bdev, new)?;
+
+        Ok(added as u64)
+    }
+
+    /// 把描述符表新增的块从保留 GDT 块中取出
+    ///
+    /// 对应内核的 `ext4_add_new_gdb()`：从 resize inode 中解除映射，
+    /// 并把块清零，之后作为普通的描述符块使用
+    fn take_reserved_gdt_blocks(&mut self, old: &Superblock, new: &Superblock) -> Result<()> {
+        let old_desc_blocks = desc_blocks(old);
+        let new_desc_blocks = desc_blocks(new);
+        if new_desc_blocks == old_desc_blocks {
+            return Ok(());
+        }
+
+        let block_size = new.block_size() as u64;
+        let zero = vec![0u8; block_size as usize];
+        let has_resize_inode = old.has_compat_feature(EXT4_FEATURE_COMPAT_RESIZE_INODE);
+        // 每个保留 GDT 块及其在原有块组中的备份
+        let backups = old.backup_groups().len() as u64;
+
+        for nr in old_desc_blocks..new_desc_blocks {
+            let gdt_block = reserved_gdt_block(new, nr);
+            if has_resize_inode {
+                let (dind, mut entries) = self.resize_inode_dind()?;
+                let slot = nr as usize % entries.len();
+                if entries[slot] as u64 != gdt_block {
+                    return Err(Error::with_block(
+                        ErrorKind::Corrupted,
+                        "Resize inode does not map reserved GDT block",
+                        dind,
+                    ));
+                }
+                entries[slot] = 0;
+                self.write_u32_block(dind, &entries)?;
+
+                let mut inode_ref = self.get_inode_ref(EXT4_RESIZE_INODE)?;
+                let blocks = inode_ref.blocks_count()?;
+                inode_ref.set_blocks_count(blocks - (backups + 1) * (block_size / 512))?;
+            }
+            self.bdev.write_bytes(gdt_block * block_size, &zero)?;
+        }
+
+        Ok(())
+    }
+
+    /// 在剩余的保留 GDT 块中登记新块组里的备份
+    ///
+    /// 对应内核的 `reserve_backup_gdb()`：新块组中带 superblock 备份的，
+    /// 其中的保留 GDT 区域也属于 resize inode
+    fn reserve_backup_gdt_blocks(&mut self, old: &Superblock, new: &Superblock) -> Result<()> {
+        if !new.has_compat_feature(EXT4_FEATURE_COMPAT_RESIZE_INODE) || new.reserved_gdt_blocks() == 0
+        {
+            return Ok(());
+        }
+
+        let old_groups = old.block_group_count();
+        let new_backups: Vec<u32> =
+            new.backup_groups().into_iter().filter(|&g| g >= old_groups).collect();
+        if new_backups.is_empty() {
+            return Ok(());
+        }
+
+        let block_size = new.block_size() as u64;
+        let blocks_per_group = new.blocks_per_group() as u64;
+        let existing = old.backup_groups().len();
+        let (dind, dind_entries) = self.resize_inode_dind()?;
+
+        let first = desc_blocks(new);
+        for nr in first..first + new.reserved_gdt_blocks() as u32 {
+            let gdt_block = reserved_gdt_block(new, nr);
+            if dind_entries[nr as usize % dind_entries.len()] as u64 != gdt_block {
+                return Err(Error::with_block(
+                    ErrorKind::Corrupted,
+                    "Resize inode does not map reserved GDT block",
+                    dind,
+                ));
+            }
+
+            let mut entries = self.read_u32_block(gdt_block)?;
+            if existing + new_backups.len() > entries.len() {
+                return Err(Error::new(ErrorKind::NoSpace, "Too many reserved GDT backups"));
+            }
+            for (i, &group) in new_backups.iter().enumerate() {
+                let backup = gdt_block + group as u64 * blocks_per_group;
+                entries[existing + i] = u32::try_from(backup).map_err(|_| {
+                    Error::new(ErrorKind::Unsupported, "Reserved GDT backup beyond 2^32")
+                })?;
+            }
+            self.write_u32_block(gdt_block, &entries)?;
+        }
+
+        let added = new_backups.len() as u64 * new.reserved_gdt_blocks() as u64;
+        let mut inode_ref = self.get_inode_ref(EXT4_RESIZE_INODE)?;
+        let blocks = inode_ref.blocks_count()?;
+        inode_ref.set_blocks_count(blocks + added * (block_size / 512))
+    }
+
+    /// 初始化一个新块组，返回其中的空闲块数
+    fn add_group(&mut self, sb: &Superblock, group: u32) -> Result<u64> {
+        let block_size = sb.block_size() as u64;
+        let bits = sb.block_size() * 8;
+        let start = sb.first_data_block() as u64 + group as u64 * sb.blocks_per_group() as u64;
+        let count = sb.blocks_in_group_cnt(group);
+        let base_meta = sb.num_base_meta_clusters(group);
+        let overhead = group_overhead(sb, group);
+
+        let block_bitmap = start + base_meta as u64;
+        let inode_bitmap = block_bitmap + 1;
+        let inode_table = block_bitmap + 2;
+
+        let mut bb = vec![0u8; block_size as usize];
+        bitmap::set_bits(&mut bb, 0, overhead)?;
+        bitmap::set_bits(&mut bb, count, bits - count)?;
+        self.bdev.write_bytes(block_bitmap * block_size, &bb)?;
+
+        let inodes = sb.inodes_per_group();
+        let mut ib = vec![0u8; block_size as usize];
+        bitmap::set_bits(&mut ib, inodes, bits - inodes)?;
+        self.bdev.write_bytes(inode_bitmap * block_size, &ib)?;
+
+        let zero = vec![0u8; block_size as usize];
+        for block in inode_table..inode_table + inode_table_blocks(sb) as u64 {
+            self.bdev.write_bytes(block * block_size, &zero)?;
+        }
+
+        let mut bg = BlockGroup::new(group, ext4_group_desc::default());
+        bg.set_block_bitmap(sb, block_bitmap);
+        bg.set_inode_bitmap(sb, inode_bitmap);
+        bg.set_inode_table_first_block(sb, inode_table);
+        bg.set_free_blocks_count(sb, count - overhead);
+        bg.set_free_inodes_count(sb, inodes);
+        if sb.has_metadata_csum() || sb.has_ro_compat_feature(EXT4_FEATURE_RO_COMPAT_GDT_CSUM) {
+            bg.set_itable_unused(sb, inodes);
+            bg.set_flag(EXT4_BG_INODE_ZEROED);
+        }
+        crate::balloc::set_bitmap_csum(sb, bg.inner_mut(), &bb);
+        crate::ialloc::set_bitmap_csum(sb, bg.inner_mut(), &ib);
+        bg.update_checksum(sb);
+        bg.write(&mut self.bdev, sb)?;
+
+        Ok((count - overhead) as u64)
+    }
+
+    /// 读取 resize inode 的二级间接块，返回块号和其中的表项
+    pub(super) fn resize_inode_dind(&mut self) -> Result<(u64, Vec<u32>)> {
+        let mut inode_ref = self.get_inode_ref(EXT4_RESIZE_INODE)?;
+        let dind = inode_ref
+            .with_inode(|inode| u32::from_le(inode.blocks[EXT4_INODE_DOUBLE_INDIRECT_BLOCK]))?;
+        drop(inode_ref);
+        if dind == 0 {
+            return Err(Error::new(ErrorKind::Corrupted, "Resize inode has no DIND block"));
+        }
+        Ok((dind as u64, self.read_u32_block(dind as u64)?))
+    }
+
+    pub(super) fn read_u32_block(&mut self, block: u64) -> Result<Vec<u32>> {
+        let block_size = self.superblock().block_size() as u64;
+        let mut buf = vec![0u8; block_size as usize];
+        self.bdev.read_bytes(block * block_size, &mut buf)?;
+        Ok(buf.chunks_exact(4).map(|c| u32::from_le_bytes([c[0], c[1], c[2], c[3]])).collect())
+    }
+
+    pub(super) fn write_u32_block(&mut self, block: u64, entries: &[u32]) -> Result<()> {
+        let block_size = self.superblock().block_size() as u64;
+        let buf: Vec<u8> = entries.iter().flat_map(|e| e.to_le_bytes()).collect();
+        self.bdev.write_bytes(block * block_size, &buf)?;
+        Ok(())
+    }
+}
+
+/// 计算扩容后的 superblock（只用于计算布局，不写入磁盘）
+///
+/// 新的最后一个块组放不下自己的元数据时舍弃该块组
+fn grown_superblock(old: &Superblock, new_blocks: u64) -> Result<Superblock> {
+    let mut new = layout(old, new_blocks)?;
+
+    let last = new.block_group_count() - 1;
+    if last >= old.block_group_count()
+        && (new.blocks_in_group_cnt(last) as u64)
+            < group_overhead(&new, last) as u64 + MIN_LAST_GROUP_DATA_BLOCKS
+    {
+        let end = new.first_data_block() as u64 + last as u64 * new.blocks_per_group() as u64;
+        new = layout(old, end)?;
+    }
+
+    Ok(new)
+}
+
+/// 按新的块数更新块数、inode 数和保留 GDT 块数
+fn layout(old: &Superblock, new_blocks: u64) -> Result<Superblock> {
+    let mut new = Superblock::new(*old.inner());
+    new.set_blocks_count(new_blocks);
+
+    let groups = new.block_group_count();
+    let inodes = groups as u64 * new.inodes_per_group() as u64;
+    let inodes = u32::try_from(inodes)
+        .map_err(|_| Error::new(ErrorKind::InvalidInput, "Too many inodes for new size"))?;
+    new.set_inodes_count(inodes);
+
+    let needed = desc_blocks(&new) - desc_blocks(old);
+    if needed > old.reserved_gdt_blocks() as u32 {
+        return Err(Error::new(ErrorKind::NoSpace, "Not enough reserved GDT blocks"));
+    }
+    new.set_reserved_gdt_blocks(old.reserved_gdt_blocks() - needed as u16);
+
+    Ok(new)
+}
+
+/// 描述符表占用的块数
+pub(super) fn desc_blocks(sb: &Superblock) -> u32 {
+    let desc_per_block = sb.block_size() / sb.group_desc_size() as u32;
+    sb.block_group_count().div_ceil(desc_per_block)
+}
+
+/// 第 `nr` 个描述符块（或保留 GDT 块）的主副本位置
+pub(super) fn reserved_gdt_block(sb: &Superblock, nr: u32) -> u64 {
+    let desc_per_block = sb.block_size() / sb.group_desc_size() as u32;
+    get_block_group_desc_location(sb, nr * desc_per_block).0
+}
+
+/// 每个块组的 inode 表块数
+pub(super) fn inode_table_blocks(sb: &Superblock) -> u32 {
+    (sb.inodes_per_group() * sb.inode_size() as u32).div_ceil(sb.block_size())
+}
+
+/// 新块组中元数据占用的块数：superblock 备份、GDT、两个位图和 inode 表
+pub(super) fn group_overhead(sb: &Superblock, group: u32) -> u32 {
+    sb.num_base_meta_clusters(group) + 2 + inode_table_blocks(sb)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::types::ext4_sblock;
+
+    fn sb_4k(blocks: u64, reserved_gdt: u16) -> Superblock {
+        Superblock::new(ext4_sblock {
+            log_block_size: 2u32.to_le(),
+            log_cluster_size: 2u32.to_le(),
+            blocks_per_group: 32768u32.to_le(),
+            inodes_per_group: 8192u32.to_le(),
+            inode_size: 256u16.to_le(),
+            blocks_count_lo: (blocks as u32).to_le(),
+            inodes_count: (8192 * (blocks as u32).div_ceil(32768)).to_le(),
+            reserved_gdt_blocks: reserved_gdt.to_le(),
+            feature_ro_compat: EXT4_FEATURE_RO_COMPAT_SPARSE_SUPER.to_le(),
+            ..Default::default()
+        })
+    }
+
+    #[test]
+    fn test_layout() {
+        let old = sb_4k(32768, 15);
+        // 32 字节描述符，每块 128 个：第 129 个块组需要第二个描述符块
+        let new = grown_superblock(&old, 129 * 32768).unwrap();
+        assert_eq!(new.block_group_count(), 129);
+        assert_eq!(new.reserved_gdt_blocks(), 14);
+        assert_eq!(new.inodes_count(), 129 * 8192);
+
+        // 保留 GDT 块不够
+        let old = sb_4k(32768, 0);
+        assert_eq!(
+            grown_superblock(&old, 129 * 32768).err().map(|e| e.kind()),
+            Some(ErrorKind::NoSpace)
+        );
+    }
+
+    #[test]
+    fn test_drop_small_last_group() {
+        let old = sb_4k(32768, 15);
+        // 块组 1 有 superblock 备份：1 + 1 + 15 + 2 + 512 块元数据
+        assert_eq!(group_overhead(&old, 1), 531);
+        let new = grown_superblock(&old, 32768 + 531 + 49).unwrap();
+        assert_eq!(new.blocks_count(), 32768);
+        let new = grown_superblock(&old, 32768 + 531 + 50).unwrap();
+        assert_eq!(new.blocks_count(), 32768 + 581);
+    }
+}
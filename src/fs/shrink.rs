@@ -0,0 +1,949 @@
+//! 离线缩小
+//!
+//! [`resize`](Ext4FileSystem::resize) 的反向操作，流程与 resize2fs 缩小文件系统相同：
+//!
+//! 1. 位于被删除块组中的 inode 换成保留块组中的新编号，目录项随之改写
+//! 2. 释放被删除块组放在保留区域内的位图和 inode 表（flex_bg）
+//! 3. 截断区域中的数据块、extent/间接块和扩展属性块搬到新的末尾之前
+//! 4. 多余的描述符块转为保留 GDT 块，截断最后一个块组并更新 superblock
+//!
+//! 搬移期间用 [`Superblock::set_max_blocks`] 限制分配器，新分配的块和 inode
+//! 都位于新的末尾之前。inode 号和块号改变后，相关的 inode、extent 块、目录块和
+//! 扩展属性块校验和都会重新计算。
+
+use crate::{
+    balloc, bitmap,
+    block::BlockDevice,
+    block_group::BlockGroup,
+    consts::*,
+    dir::checksum as dir_checksum,
+    error::{Error, ErrorKind, Result},
+    extent::{self, EXT_INIT_MAX_LEN},
+    ialloc,
+    inode::checksum as inode_checksum,
+    superblock::Superblock,
+    types::{ext4_extent, ext4_extent_header, ext4_extent_idx, ext4_inode, Pblk},
+};
+use alloc::{collections::BTreeMap, vec, vec::Vec};
+
+use super::{
+    filesystem::Ext4FileSystem,
+    resize::{
+        desc_blocks, group_overhead, inode_table_blocks, reserved_gdt_block,
+        MIN_LAST_GROUP_DATA_BLOCKS,
+    },
+    MappingFlags,
+};
+
+/// `s_jnl_backup_type`：`s_jnl_blocks` 中保存了日志 inode 的块映射
+const EXT3_JNL_BACKUP_BLOCKS: u8 = 1;
+
+/// 坏块 inode，它记录的块不搬移
+const EXT4_BAD_BLOCKS_INODE: u32 = 1;
+
+/// xattr 块头中 `h_checksum` 的偏移
+const XATTR_CHECKSUM_OFFSET: usize = 16;
+
+/// 搬移过程中的状态
+struct Relocation {
+    /// 缩小后的布局（只用于计算，不写入磁盘）
+    sb: Superblock,
+    /// 新的末尾，`>=` 它的块都要搬走
+    end: u64,
+    /// 换了编号的 inode：旧编号 -> 新编号
+    inodes: BTreeMap<u32, u32>,
+    /// 已搬移的扩展属性块（可能被多个 inode 共享）：旧块号 -> 新块号
+    xattr_blocks: BTreeMap<u64, u64>,
+}
+
+impl<D: BlockDevice> Ext4FileSystem<D> {
+    /// 把文件系统缩小到 `new_blocks` 个块
+    ///
+    /// 截断区域中的数据和元数据先搬到前面的空闲块中，位于被删除块组的 inode
+    /// 换成新的编号（目录项随之更新）。新的最后一个块组放不下自己的元数据时
+    /// 整个舍弃，实际大小可以通过 [`superblock`](Self::superblock) 查看。
+    /// 完成后会更新 superblock 和描述符表的备份，之后可以缩小分区。
+    ///
+    /// 操作不经过日志；中途出错（如设备错误）时文件系统可能处于中间状态，
+    /// 需要用 e2fsck 检查。
+    ///
+    /// # 错误
+    ///
+    /// - `ErrorKind::InvalidInput` - `new_blocks` 大于当前大小，或者小到放不下一个块组
+    /// - `ErrorKind::NoSpace` - 新末尾之前的空闲块或空闲 inode 不够，
+    ///   或者空闲空间太碎、extent 节点放不下拆分后的 extent
+    /// - `ErrorKind::Unsupported` - 文件系统使用 META_BG、BIGALLOC、SPARSE_SUPER2、
+    ///   INLINE_DATA 或 EA_INODE，或者保留块组的位图、inode 表位于截断区域
+    /// - `ErrorKind::InvalidState` - 孤儿 inode 链表非空
+    /// - `ErrorKind::Busy` - 还有打开的 inode（搬移可能改变 inode 编号）
+    ///
+    /// # 示例
+    ///
+    /// ```rust,ignore
+    /// // 把分区缩小到 256 MiB 之前先缩小文件系统
+    /// fs.resize_shrink(256 * 1024 * 1024 / fs.superblock().block_size() as u64)?;
+    /// ```
+    pub fn resize_shrink(&mut self, new_blocks: u64) -> Result<()> {
+        let old = Superblock::new(*self.superblock().inner());
+        let old_blocks = old.blocks_count();
+        if new_blocks > old_blocks {
+            return Err(Error::new(ErrorKind::InvalidInput, "Use resize to grow the filesystem"));
+        }
+        if new_blocks == old_blocks {
+            return Ok(());
+        }
+        if old.has_incompat_feature(EXT4_FEATURE_INCOMPAT_META_BG)
+            || old.has_ro_compat_feature(EXT4_FEATURE_RO_COMPAT_BIGALLOC)
+            || old.has_compat_feature(EXT4_FEATURE_COMPAT_SPARSE_SUPER2)
+            || old.has_incompat_feature(EXT4_FEATURE_INCOMPAT_INLINE_DATA)
+            || old.has_incompat_feature(EXT4_FEATURE_INCOMPAT_EA_INODE)
+        {
+            return Err(Error::new(
+                ErrorKind::Unsupported,
+                "Shrinking is not supported with this feature set",
+            ));
+        }
+        if u32::from_le(old.inner().last_orphan) != 0 {
+            return Err(Error::new(ErrorKind::InvalidState, "Orphan list is not empty"));
+        }
+        if !self.open_inodes.is_empty() {
+            return Err(Error::new(ErrorKind::Busy, "Inodes are still open"));
+        }
+
+        let new = shrunk_superblock(&old, new_blocks)?;
+        let end = new.blocks_count();
+        if end == old_blocks {
+            return Ok(());
+        }
+
+        // 块会被迁移，缓存页记录的物理块随之失效
+        self.page_cache_clear()?;
+        self.flush_delalloc()?;
+        self.check_kept_metadata(&new)?;
+        self.check_shrink_space(&old, &new)?;
+
+        let max_blocks = self.superblock().max_blocks();
+        self.superblock_mut().set_max_blocks(Some(max_blocks.map_or(end, |max| max.min(end))));
+        let result = self.shrink_steps(&old, new);
+        self.superblock_mut().set_max_blocks(max_blocks);
+        result?;
+
+        self.write_metadata_backups()?;
+        self.repin_metadata()
+    }
+
+    fn shrink_steps(&mut self, old: &Superblock, new: Superblock) -> Result<()> {
+        let mut ctx = Relocation {
+            end: new.blocks_count(),
+            sb: new,
+            inodes: BTreeMap::new(),
+            xattr_blocks: BTreeMap::new(),
+        };
+        let groups = ctx.sb.block_group_count();
+
+        self.move_removed_inodes(old, &mut ctx)?;
+        self.free_removed_group_metadata(old, &ctx)?;
+        self.init_last_block_bitmap(&ctx.sb)?;
+
+        let journal_inum = u32::from_le(old.inner().journal_inum);
+        let mut dirs = Vec::new();
+        for group in 0..groups {
+            for ino in self.used_inodes(&ctx.sb, group)? {
+                if ino == EXT4_BAD_BLOCKS_INODE || ino == EXT4_RESIZE_INODE {
+                    continue;
+                }
+                let (is_dir, changed) = self.relocate_inode_blocks(&mut ctx, ino)?;
+                if is_dir {
+                    dirs.push(ino);
+                }
+                if changed && ino == journal_inum {
+                    self.backup_journal_blocks(&ctx.sb, ino)?;
+                }
+            }
+        }
+
+        if !ctx.inodes.is_empty() {
+            for dir in dirs {
+                self.remap_dir_entries(&ctx, dir)?;
+            }
+            let inodes = &ctx.inodes;
+            let remap = |ino: &mut u32| {
+                if let Some(&new_ino) = inodes.get(&u32::from_le(*ino)) {
+                    *ino = new_ino.to_le();
+                }
+            };
+            let inner = self.superblock_mut().inner_mut();
+            remap(&mut inner.lpf_ino);
+            remap(&mut inner.usr_quota_inum);
+            remap(&mut inner.grp_quota_inum);
+            remap(&mut inner.prj_quota_inum);
+        }
+
+        self.shrink_gdt(old, &ctx.sb)?;
+        self.truncate_last_group(&ctx.sb)?;
+
+        let new = &ctx.sb;
+        let mut free_blocks = 0u64;
+        let mut free_inodes = 0u32;
+        for group in 0..groups {
+            let bg = BlockGroup::load(&mut self.bdev, new, group)?;
+            free_blocks += bg.get_free_blocks_count(new) as u64;
+            free_inodes += bg.get_free_inodes_count(new);
+        }
+        let r_blocks = (old.inner().r_blocks_count() as u128 * new.blocks_count() as u128
+            / old.blocks_count() as u128) as u64;
+
+        let sb = self.superblock_mut();
+        sb.set_blocks_count(new.blocks_count());
+        sb.set_inodes_count(new.inodes_count());
+        sb.set_reserved_gdt_blocks(new.reserved_gdt_blocks());
+        sb.set_r_blocks_count(r_blocks);
+        sb.set_free_blocks_count(free_blocks);
+        sb.set_free_inodes_count(free_inodes);
+        Ok(())
+    }
+
+    /// 保留块组的位图和 inode 表必须位于新的末尾之前
+    fn check_kept_metadata(&mut self, new: &Superblock) -> Result<()> {
+        let end = new.blocks_count();
+        let itable_blocks = inode_table_blocks(new) as u64;
+        for group in 0..new.block_group_count() {
+            let bg = BlockGroup::load(&mut self.bdev, new, group)?;
+            if bg.get_block_bitmap(new) >= end
+                || bg.get_inode_bitmap(new) >= end
+                || bg.get_inode_table_first_block(new) + itable_blocks > end
+            {
+                return Err(Error::new(
+                    ErrorKind::Unsupported,
+                    "Block group metadata lies beyond the new end",
+                ));
+            }
+        }
+        Ok(())
+    }
+
+    /// 预先检查新末尾之前是否放得下要搬移的 inode 和块，避免做到一半才失败
+    fn check_shrink_space(&mut self, old: &Superblock, new: &Superblock) -> Result<()> {
+        let end = new.blocks_count();
+        let old_groups = old.block_group_count();
+        let new_groups = new.block_group_count();
+        let last = new_groups - 1;
+        let block_size = old.block_size() as usize;
+        let mut bitmap = vec![0u8; block_size];
+
+        let mut free_inodes = 0u64;
+        let mut free_blocks = 0u64;
+        for group in 0..new_groups {
+            let bg = BlockGroup::load(&mut self.bdev, old, group)?;
+            free_inodes += bg.get_free_inodes_count(old) as u64;
+            free_blocks += bg.get_free_blocks_count(old) as u64;
+        }
+
+        // 截断区域中已用的块数；位图未初始化的块组中只有元数据
+        let mut counted = vec![false; (old_groups - last) as usize];
+        let mut used_inodes = 0u64;
+        let mut used_blocks = 0u64;
+        let last_start = group_start(old, last);
+        for group in last..old_groups {
+            let bg = BlockGroup::load(&mut self.bdev, old, group)?;
+            if group > last && !bg.has_flag(EXT4_BG_INODE_UNINIT) {
+                used_inodes += (old.inodes_per_group() - bg.get_free_inodes_count(old)) as u64;
+            }
+            if bg.has_flag(EXT4_BG_BLOCK_UNINIT) {
+                if group == last {
+                    free_blocks = free_blocks.saturating_sub(old.blocks_in_group_cnt(last) as u64
+                        - (end - last_start));
+                }
+                continue;
+            }
+            counted[(group - last) as usize] = true;
+            self.bdev.read_bytes(bg.get_block_bitmap(old) * block_size as u64, &mut bitmap)?;
+            let count = old.blocks_in_group_cnt(group);
+            let first = if group == last { (end - last_start) as u32 } else { 0 };
+            used_blocks += bitmap::count_ones(&bitmap, first, count) as u64;
+            if group == last {
+                free_blocks -= bitmap::count_zeros(&bitmap, first, count) as u64;
+            }
+        }
+        if used_inodes > free_inodes {
+            return Err(Error::new(ErrorKind::NoSpace, "Not enough free inodes below the new end"));
+        }
+
+        // 被删除块组自己的元数据不需要搬移
+        let itable_blocks = inode_table_blocks(old) as u64;
+        let mut meta_blocks = 0u64;
+        for group in new_groups..old_groups {
+            let bg = BlockGroup::load(&mut self.bdev, old, group)?;
+            let ranges = [
+                (group_start(old, group), old.num_base_meta_clusters(group) as u64),
+                (bg.get_block_bitmap(old), 1),
+                (bg.get_inode_bitmap(old), 1),
+                (bg.get_inode_table_first_block(old), itable_blocks),
+            ];
+            for (start, len) in ranges {
+                for block in start.max(end)..start + len {
+                    let owner = balloc::get_bgid_of_block(old, block);
+                    if owner >= last && counted[(owner - last) as usize] {
+                        meta_blocks += 1;
+                    }
+                }
+            }
+        }
+
+        if used_blocks.saturating_sub(meta_blocks) > free_blocks {
+            return Err(Error::new(ErrorKind::NoSpace, "Not enough free blocks below the new end"));
+        }
+        Ok(())
+    }
+
+    /// 读取块组 `group` 的 inode 位图，返回其中已用的 inode 号
+    fn used_inodes(&mut self, sb: &Superblock, group: u32) -> Result<Vec<u32>> {
+        let bg = BlockGroup::load(&mut self.bdev, sb, group)?;
+        if bg.has_flag(EXT4_BG_INODE_UNINIT) {
+            return Ok(Vec::new());
+        }
+
+        let mut bitmap = vec![0u8; sb.block_size() as usize];
+        self.bdev.read_bytes(bg.get_inode_bitmap(sb) * sb.block_size() as u64, &mut bitmap)?;
+        let inodes_per_group = sb.inodes_per_group();
+        Ok((0..inodes_per_group)
+            .filter(|&i| bitmap::test_bit(&bitmap, i))
+            .map(|i| group * inodes_per_group + i + 1)
+            .collect())
+    }
+
+    /// inode 在 inode 表中的字节偏移
+    fn inode_offset(&mut self, sb: &Superblock, ino: u32) -> Result<u64> {
+        let group = (ino - 1) / sb.inodes_per_group();
+        let index = (ino - 1) % sb.inodes_per_group();
+        let bg = BlockGroup::load(&mut self.bdev, sb, group)?;
+        Ok(bg.get_inode_table_first_block(sb) * sb.block_size() as u64
+            + index as u64 * sb.inode_size() as u64)
+    }
+
+    fn read_inode_raw(&mut self, sb: &Superblock, ino: u32) -> Result<Vec<u8>> {
+        let offset = self.inode_offset(sb, ino)?;
+        let mut raw = vec![0u8; sb.inode_size() as usize];
+        self.bdev.read_bytes(offset, &mut raw)?;
+        Ok(raw)
+    }
+
+    /// 重新计算校验和后写回 inode
+    fn write_inode_raw(&mut self, sb: &Superblock, ino: u32, raw: &mut [u8]) -> Result<()> {
+        inode_checksum::set_checksum_raw(sb, ino, raw);
+        let offset = self.inode_offset(sb, ino)?;
+        self.bdev.write_bytes(offset, raw)?;
+        Ok(())
+    }
+
+    /// 把被删除块组中的 inode 复制到保留块组，记录新旧编号
+    fn move_removed_inodes(&mut self, old: &Superblock, ctx: &mut Relocation) -> Result<()> {
+        for group in ctx.sb.block_group_count()..old.block_group_count() {
+            for ino in self.used_inodes(old, group)? {
+                let mut raw = self.read_inode_raw(old, ino)?;
+                let is_dir = inode_from_raw(&raw).is_dir();
+
+                let (bdev, sb) = self.bdev_and_sb_mut();
+                let new_ino = ialloc::alloc_inode(bdev, sb, is_dir)?;
+                if new_ino > ctx.sb.inodes_count() {
+                    return Err(Error::new(
+                        ErrorKind::NoSpace,
+                        "No free inodes below the new end",
+                    ));
+                }
+                self.write_inode_raw(&ctx.sb, new_ino, &mut raw)?;
+                ctx.inodes.insert(ino, new_ino);
+            }
+        }
+        Ok(())
+    }
+
+    /// 释放被删除块组放在保留区域中的位图和 inode 表
+    fn free_removed_group_metadata(&mut self, old: &Superblock, ctx: &Relocation) -> Result<()> {
+        let itable_blocks = inode_table_blocks(old) as u64;
+        for group in ctx.sb.block_group_count()..old.block_group_count() {
+            let bg = BlockGroup::load(&mut self.bdev, old, group)?;
+            let ranges = [
+                (bg.get_block_bitmap(old), 1),
+                (bg.get_inode_bitmap(old), 1),
+                (bg.get_inode_table_first_block(old), itable_blocks),
+            ];
+            for (start, len) in ranges {
+                if start < ctx.end {
+                    let len = len.min(ctx.end - start) as u32;
+                    let (bdev, sb) = self.bdev_and_sb_mut();
+                    balloc::free_blocks(bdev, sb, start, len)?;
+                }
+            }
+        }
+        Ok(())
+    }
+
+    /// 新的最后一个块组的块位图尚未初始化时按保留块组的元数据生成，
+    /// 之后分配器才能在其中安全地分配
+    fn init_last_block_bitmap(&mut self, new: &Superblock) -> Result<()> {
+        balloc::init_block_bitmap(&mut self.bdev, new, new.block_group_count() - 1)?;
+        Ok(())
+    }
+
+    /// 搬移 inode 位于截断区域中的块，返回 `(是否为目录, 块映射是否改变)`
+    ///
+    /// 换了编号的 inode 即使没有块需要搬移，也要按新编号重写 extent 块的校验和
+    fn relocate_inode_blocks(&mut self, ctx: &mut Relocation, ino: u32) -> Result<(bool, bool)> {
+        let mut raw = self.read_inode_raw(&ctx.sb, ino)?;
+        let mut inode = inode_from_raw(&raw);
+        let renumbered = ctx.inodes.values().any(|&new_ino| new_ino == ino);
+        let block_size = ctx.sb.block_size() as u64;
+
+        let mut changed = false;
+        let file_acl = inode.file_acl_lo as u64 | (u16::from_le(inode.file_acl_high) as u64) << 32;
+        let file_acl = u64::from_le(file_acl);
+        if has_block_map(&inode, file_acl, block_size) {
+            if u32::from_le(inode.flags) & EXT4_INODE_FLAG_EXTENTS != 0 {
+                let mut root = [0u8; 60];
+                for (chunk, block) in root.chunks_exact_mut(4).zip(inode.blocks.iter()) {
+                    chunk.copy_from_slice(&block.to_ne_bytes());
+                }
+                let generation = u32::from_le(inode.generation);
+                if self.relocate_extent_node(ctx, ino, generation, &mut root, renumbered)? {
+                    for (block, chunk) in inode.blocks.iter_mut().zip(root.chunks_exact(4)) {
+                        *block = u32::from_ne_bytes([chunk[0], chunk[1], chunk[2], chunk[3]]);
+                    }
+                    changed = true;
+                }
+            } else {
+                for (i, block) in inode.blocks.iter_mut().enumerate() {
+                    let depth = i.saturating_sub(EXT4_INODE_INDIRECT_BLOCK - 1) as u32;
+                    let mut ptr = u32::from_le(*block);
+                    if self.relocate_indirect(ctx, &mut ptr, depth)? {
+                        *block = ptr.to_le();
+                        changed = true;
+                    }
+                }
+            }
+        }
+
+        if file_acl >= ctx.end {
+            let new_acl = match ctx.xattr_blocks.get(&file_acl) {
+                Some(&block) => block,
+                None => {
+                    let block = self.move_blocks(ctx, file_acl, 1, true)?[0].0;
+                    self.update_xattr_block_checksum(&ctx.sb, block)?;
+                    ctx.xattr_blocks.insert(file_acl, block);
+                    block
+                }
+            };
+            inode.file_acl_lo = (new_acl as u32).to_le();
+            inode.file_acl_high = ((new_acl >> 32) as u16).to_le();
+            changed = true;
+        }
+
+        if changed || renumbered {
+            store_inode_raw(&mut raw, &inode);
+            self.write_inode_raw(&ctx.sb, ino, &mut raw)?;
+        }
+        Ok((inode.is_dir(), changed))
+    }
+
+    /// 处理一个 extent 树节点，返回节点内容是否改变
+    ///
+    /// 子节点内容改变、位置改变或者需要按新 inode 号重写校验和时写回子节点
+    fn relocate_extent_node(
+        &mut self,
+        ctx: &Relocation,
+        ino: u32,
+        generation: u32,
+        node: &mut [u8],
+        rewrite: bool,
+    ) -> Result<bool> {
+        let header = unsafe { core::ptr::read_unaligned(node.as_ptr() as *const ext4_extent_header) };
+        let entries = header.entries_count() as usize;
+        if !header.is_valid() || 12 + entries * 12 > node.len() {
+            return Err(Error::new(ErrorKind::Corrupted, "Invalid extent header"));
+        }
+        if header.depth() == 0 {
+            return self.relocate_extent_leaf(ctx, node, &header);
+        }
+
+        let block_size = ctx.sb.block_size() as u64;
+        let mut buf = vec![0u8; block_size as usize];
+        let mut changed = false;
+        for i in 0..entries {
+            let offset = 12 + i * 12;
+            let idx_ptr = node[offset..].as_mut_ptr() as *mut ext4_extent_idx;
+            let mut idx = unsafe { core::ptr::read_unaligned(idx_ptr) };
+            let child = extent::ext4_idx_pblock(&idx);
+
+            self.bdev.read_bytes(child * block_size, &mut buf)?;
+            let child_changed = self.relocate_extent_node(ctx, ino, generation, &mut buf, rewrite)?;
+            let target = if child >= ctx.end { self.move_blocks(ctx, child, 1, false)?[0].0 } else { child };
+            if child_changed || rewrite || target != child {
+                extent::set_checksum(&ctx.sb, ino, generation, &mut buf);
+                self.bdev.write_bytes(target * block_size, &buf)?;
+            }
+            if target != child {
+                extent::ext4_idx_store_pblock(&mut idx, target);
+                unsafe { core::ptr::write_unaligned(idx_ptr, idx) };
+                changed = true;
+            }
+        }
+        Ok(changed)
+    }
+
+    /// 搬移叶子节点中越过新末尾的 extent
+    ///
+    /// 新位置不连续时一个 extent 拆成多个，叶子节点放不下时放弃并释放新分配的块
+    fn relocate_extent_leaf(
+        &mut self,
+        ctx: &Relocation,
+        node: &mut [u8],
+        header: &ext4_extent_header,
+    ) -> Result<bool> {
+        let entries = header.entries_count() as usize;
+        let mut extents = Vec::with_capacity(entries);
+        let mut allocated = Vec::new();
+        let mut old_kept = Vec::new();
+
+        for i in 0..entries {
+            let ext = unsafe { core::ptr::read_unaligned(node[12 + i * 12..].as_ptr() as *const ext4_extent) };
+            let pblk = extent::ext4_ext_pblock(&ext);
+            let unwritten = ext.len() > EXT_INIT_MAX_LEN;
+            let len = if unwritten { ext.len() - EXT_INIT_MAX_LEN } else { ext.len() };
+            if pblk + len as u64 <= ctx.end {
+                extents.push(ext);
+                continue;
+            }
+
+            let runs = match self.move_blocks(ctx, pblk, len as u32, !unwritten) {
+                Ok(runs) => runs,
+                Err(e) => {
+                    self.free_runs(&allocated)?;
+                    return Err(e);
+                }
+            };
+            let mut lblk = ext.logical_block();
+            for &(start, count) in &runs {
+                let count = count as u16;
+                let mut piece = ext4_extent {
+                    block: lblk.to_le(),
+                    len: if unwritten { count + EXT_INIT_MAX_LEN } else { count }.to_le(),
+                    ..Default::default()
+                };
+                extent::ext4_ext_store_pblock(&mut piece, start);
+                extents.push(piece);
+                lblk += count as u32;
+            }
+            allocated.extend(runs);
+            if pblk < ctx.end {
+                old_kept.push((pblk, (ctx.end - pblk) as u32));
+            }
+        }
+
+        if allocated.is_empty() {
+            return Ok(false);
+        }
+        if extents.len() > header.max_entries() as usize {
+            self.free_runs(&allocated)?;
+            return Err(Error::new(
+                ErrorKind::NoSpace,
+                "Free space too fragmented to relocate extent",
+            ));
+        }
+
+        for (i, ext) in extents.iter().enumerate() {
+            unsafe { core::ptr::write_unaligned(node[12 + i * 12..].as_mut_ptr() as *mut ext4_extent, *ext) };
+        }
+        let mut header = *header;
+        header.entries = (extents.len() as u16).to_le();
+        unsafe { core::ptr::write_unaligned(node.as_mut_ptr() as *mut ext4_extent_header, header) };
+
+        self.free_runs(&old_kept)?;
+        Ok(true)
+    }
+
+    /// 处理一个间接块指针，`depth` 为 0 表示数据块，返回指针是否改变
+    fn relocate_indirect(&mut self, ctx: &Relocation, ptr: &mut u32, depth: u32) -> Result<bool> {
+        if *ptr == 0 {
+            return Ok(false);
+        }
+        let old = *ptr as u64;
+
+        let new = if depth == 0 {
+            if old < ctx.end {
+                return Ok(false);
+            }
+            self.move_blocks(ctx, old, 1, true)?[0].0
+        } else {
+            let mut entries = self.read_u32_block(old)?;
+            let mut changed = false;
+            for entry in entries.iter_mut() {
+                let mut child = u32::from_le(*entry);
+                if self.relocate_indirect(ctx, &mut child, depth - 1)? {
+                    *entry = child.to_le();
+                    changed = true;
+                }
+            }
+            let target = if old >= ctx.end { self.move_blocks(ctx, old, 1, false)?[0].0 } else { old };
+            if changed || target != old {
+                self.write_u32_block(target, &entries)?;
+            }
+            target
+        };
+
+        if new == old {
+            return Ok(false);
+        }
+        *ptr = new as u32;
+        Ok(true)
+    }
+
+    /// 在新末尾之前分配 `len` 个块，`copy` 为真时把从 `old` 开始的内容复制过去
+    ///
+    /// 找不到足够长的连续空间时减半请求长度，返回各段 `(起始块, 块数)`
+    fn move_blocks(&mut self, ctx: &Relocation, old: u64, len: u32, copy: bool) -> Result<Vec<(u64, u32)>> {
+        let goal = ctx.sb.first_data_block() as u64;
+        let runs = self.alloc_runs(goal, len)?;
+
+        if copy {
+            let block_size = ctx.sb.block_size() as u64;
+            let mut buf = vec![0u8; block_size as usize];
+            let mut src = old;
+            for &(start, count) in &runs {
+                for dst in start..start + count as u64 {
+                    self.bdev.read_bytes(src * block_size, &mut buf)?;
+                    self.bdev.write_bytes(dst * block_size, &buf)?;
+                    src += 1;
+                }
+            }
+        }
+        Ok(runs)
+    }
+
+    /// 从 `goal` 开始分配 `len` 个块
+    ///
+    /// 找不到足够长的连续空间时减半请求长度，下一段从上一段之后继续找，
+    /// 返回各段 `(起始块, 块数)`；失败时已分配的段会被释放
+    pub(super) fn alloc_runs(&mut self, goal: u64, len: u32) -> Result<Vec<(u64, u32)>> {
+        let mut runs: Vec<(u64, u32)> = Vec::new();
+        let mut done = 0u32;
+        let mut want = len;
+        while done < len {
+            let goal = runs.last().map_or(goal, |&(start, count)| start + count as u64);
+            let (bdev, sb) = self.bdev_and_sb_mut();
+            match balloc::alloc_blocks(bdev, sb, goal, want.min(len - done)) {
+                Ok((Pblk(start), count)) => {
+                    runs.push((start, count));
+                    done += count;
+                }
+                Err(e) if e.kind() == ErrorKind::NoSpace && want > 1 => want /= 2,
+                Err(e) => {
+                    self.free_runs(&runs)?;
+                    return Err(e);
+                }
+            }
+        }
+        Ok(runs)
+    }
+
+    pub(super) fn free_runs(&mut self, runs: &[(u64, u32)]) -> Result<()> {
+        for &(start, count) in runs {
+            let (bdev, sb) = self.bdev_and_sb_mut();
+            balloc::free_blocks(bdev, sb, start, count)?;
+        }
+        Ok(())
+    }
+
+    /// xattr 块的校验和包含块号，搬移后需要重新计算
+    fn update_xattr_block_checksum(&mut self, sb: &Superblock, block: u64) -> Result<()> {
+        if !sb.has_metadata_csum() {
+            return Ok(());
+        }
+
+        let block_size = sb.block_size() as u64;
+        let mut buf = vec![0u8; block_size as usize];
+        self.bdev.read_bytes(block * block_size, &mut buf)?;
+        buf[XATTR_CHECKSUM_OFFSET..XATTR_CHECKSUM_OFFSET + 4].fill(0);
+        let csum = crate::crc::crc32c_append(sb.csum_seed(), &block.to_le_bytes());
+        let csum = crate::crc::crc32c_append(csum, &buf);
+        buf[XATTR_CHECKSUM_OFFSET..XATTR_CHECKSUM_OFFSET + 4].copy_from_slice(&csum.to_le_bytes());
+        self.bdev.write_bytes(block * block_size, &buf)?;
+        Ok(())
+    }
+
+    /// 日志 inode 的块映射改变后同步 superblock 中的备份（`s_jnl_blocks`）
+    fn backup_journal_blocks(&mut self, sb: &Superblock, ino: u32) -> Result<()> {
+        if sb.inner().jnl_backup_type != EXT3_JNL_BACKUP_BLOCKS {
+            return Ok(());
+        }
+
+        let inode = inode_from_raw(&self.read_inode_raw(sb, ino)?);
+        let jnl_blocks = &mut self.superblock_mut().inner_mut().jnl_blocks;
+        jnl_blocks[..EXT4_INODE_BLOCKS].copy_from_slice(&inode.blocks);
+        jnl_blocks[EXT4_INODE_BLOCKS] = inode.size_hi;
+        jnl_blocks[EXT4_INODE_BLOCKS + 1] = inode.size_lo;
+        Ok(())
+    }
+
+    /// 改写目录中指向换了编号的 inode 的目录项
+    ///
+    /// 目录自己换了编号时，所有目录块都要按新编号重写校验和
+    fn remap_dir_entries(&mut self, ctx: &Relocation, dir: u32) -> Result<()> {
+        let mut inode_ref = self.get_inode_ref(dir)?;
+        let generation = inode_ref.generation()?;
+        let mappings = inode_ref.fiemap(0..u32::MAX)?;
+        drop(inode_ref);
+
+        let renumbered = ctx.inodes.values().any(|&new_ino| new_ino == dir);
+        let block_size = ctx.sb.block_size() as usize;
+        let mut buf = vec![0u8; block_size];
+        for m in mappings.iter().filter(|m| !m.flags.contains(MappingFlags::UNWRITTEN)) {
+            for pblk in m.physical_block..m.physical_block + m.len as u64 {
+                self.bdev.read_bytes(pblk * block_size as u64, &mut buf)?;
+                if remap_entries(&mut buf, &ctx.inodes) || renumbered {
+                    dir_checksum::set_csum(&ctx.sb, dir, generation, &mut buf, block_size);
+                    self.bdev.write_bytes(pblk * block_size as u64, &buf)?;
+                }
+            }
+        }
+        Ok(())
+    }
+
+    /// 描述符表变短时，空出来的描述符块转为保留 GDT 块；
+    /// 超出 resize inode 容量（或没有 resize inode）的部分释放
+    fn shrink_gdt(&mut self, old: &Superblock, new: &Superblock) -> Result<()> {
+        if new.has_compat_feature(EXT4_FEATURE_COMPAT_RESIZE_INODE) && new.reserved_gdt_blocks() > 0 {
+            self.rebuild_resize_inode(new)?;
+        }
+
+        let old_meta = desc_blocks(old) + old.reserved_gdt_blocks() as u32;
+        let new_meta = desc_blocks(new) + new.reserved_gdt_blocks() as u32;
+        if new_meta == old_meta {
+            return Ok(());
+        }
+
+        let first = reserved_gdt_block(new, new_meta);
+        let blocks_per_group = new.blocks_per_group() as u64;
+        let mut groups = new.backup_groups();
+        groups.insert(0, 0);
+        for group in groups {
+            let (bdev, sb) = self.bdev_and_sb_mut();
+            balloc::free_blocks(bdev, sb, first + group as u64 * blocks_per_group, old_meta - new_meta)?;
+        }
+        Ok(())
+    }
+
+    /// 按缩小后的布局重建 resize inode：二级间接块指向每个保留 GDT 块，
+    /// 保留 GDT 块记录它在剩余备份块组中的副本
+    fn rebuild_resize_inode(&mut self, new: &Superblock) -> Result<()> {
+        let (dind, _) = self.resize_inode_dind()?;
+        if dind >= new.blocks_count() {
+            return Err(Error::with_block(
+                ErrorKind::Unsupported,
+                "Resize inode DIND block lies beyond the new end",
+                dind,
+            ));
+        }
+
+        let block_size = new.block_size() as u64;
+        let blocks_per_group = new.blocks_per_group() as u64;
+        let per_block = (block_size / 4) as usize;
+        let backups = new.backup_groups();
+        let mut dind_entries = vec![0u32; per_block];
+
+        let first = desc_blocks(new);
+        for nr in first..first + new.reserved_gdt_blocks() as u32 {
+            let gdt_block = reserved_gdt_block(new, nr);
+            dind_entries[nr as usize % per_block] = gdt_block as u32;
+
+            let mut entries = vec![0u32; per_block];
+            for (entry, &group) in entries.iter_mut().zip(backups.iter()) {
+                *entry = (gdt_block + group as u64 * blocks_per_group) as u32;
+            }
+            self.write_u32_block(gdt_block, &entries)?;
+        }
+        self.write_u32_block(dind, &dind_entries)?;
+
+        let blocks = 1 + new.reserved_gdt_blocks() as u64 * (backups.len() as u64 + 1);
+        let mut raw = self.read_inode_raw(new, EXT4_RESIZE_INODE)?;
+        let mut inode = inode_from_raw(&raw);
+        let sectors = blocks * (block_size / 512);
+        inode.blocks_count_lo = (sectors as u32).to_le();
+        inode.blocks_high = ((sectors >> 32) as u16).to_le();
+        store_inode_raw(&mut raw, &inode);
+        self.write_inode_raw(new, EXT4_RESIZE_INODE, &mut raw)
+    }
+
+    /// 截断最后一个块组：新末尾之后的位置作为填充位置为已用，并重新统计空闲块
+    fn truncate_last_group(&mut self, new: &Superblock) -> Result<()> {
+        let last = new.block_group_count() - 1;
+        let count = new.blocks_in_group_cnt(last);
+        let block_size = new.block_size() as u64;
+
+        let mut bg = BlockGroup::load(&mut self.bdev, new, last)?;
+        let bitmap_addr = bg.get_block_bitmap(new) * block_size;
+        let mut bitmap = vec![0u8; block_size as usize];
+        self.bdev.read_bytes(bitmap_addr, &mut bitmap)?;
+        bitmap::set_bits(&mut bitmap, count, new.block_size() * 8 - count)?;
+        self.bdev.write_bytes(bitmap_addr, &bitmap)?;
+
+        bg.set_free_blocks_count(new, bitmap::count_zeros(&bitmap, 0, count));
+        balloc::set_bitmap_csum(new, bg.inner_mut(), &bitmap);
+        bg.update_checksum(new);
+        bg.write(&mut self.bdev, new)
+    }
+}
+
+/// 计算缩小后的 superblock（只用于计算布局，不写入磁盘）
+///
+/// 新的最后一个块组放不下自己的元数据时舍弃该块组；描述符表变短空出的块
+/// 在 resize inode 容量之内转为保留 GDT 块
+fn shrunk_superblock(old: &Superblock, new_blocks: u64) -> Result<Superblock> {
+    let too_small = || Error::new(ErrorKind::InvalidInput, "New size is too small");
+    let first_data_block = old.first_data_block() as u64;
+    if new_blocks <= first_data_block {
+        return Err(too_small());
+    }
+
+    let mut new = Superblock::new(*old.inner());
+    new.set_blocks_count(new_blocks);
+    let last = new.block_group_count() - 1;
+    if (new.blocks_in_group_cnt(last) as u64)
+        < group_overhead(&new, last) as u64 + MIN_LAST_GROUP_DATA_BLOCKS
+    {
+        if last == 0 {
+            return Err(too_small());
+        }
+        new.set_blocks_count(first_data_block + last as u64 * new.blocks_per_group() as u64);
+    }
+
+    new.set_inodes_count(new.block_group_count() * new.inodes_per_group());
+    if old.has_compat_feature(EXT4_FEATURE_COMPAT_RESIZE_INODE) {
+        let freed = desc_blocks(old) - desc_blocks(&new);
+        let max = (new.block_size() / 4) as u16;
+        new.set_reserved_gdt_blocks((old.reserved_gdt_blocks() + freed as u16).min(max));
+    }
+
+    Ok(new)
+}
+
+/// 块组的第一个块
+fn group_start(sb: &Superblock, group: u32) -> u64 {
+    sb.first_data_block() as u64 + group as u64 * sb.blocks_per_group() as u64
+}
+
+/// inode 是否通过 `i_block` 映射数据块
+///
+/// 设备文件在 `i_block` 中保存设备号，快速符号链接直接保存目标路径
+fn has_block_map(inode: &ext4_inode, file_acl: u64, block_size: u64) -> bool {
+    match u16::from_le(inode.mode) & EXT4_INODE_MODE_TYPE_MASK {
+        EXT4_INODE_MODE_FILE | EXT4_INODE_MODE_DIRECTORY => true,
+        EXT4_INODE_MODE_SOFTLINK => {
+            let acl_sectors = if file_acl != 0 { block_size / 512 } else { 0 };
+            u32::from_le(inode.blocks_count_lo) as u64 > acl_sectors
+        }
+        _ => false,
+    }
+}
+
+/// 从 inode 表中的原始字节取出 `ext4_inode`（128 字节的 inode 超出部分按 0 处理）
+fn inode_from_raw(raw: &[u8]) -> ext4_inode {
+    let mut inode = ext4_inode::default();
+    let len = raw.len().min(core::mem::size_of::<ext4_inode>());
+    unsafe {
+        core::slice::from_raw_parts_mut(&mut inode as *mut ext4_inode as *mut u8, len)
+            .copy_from_slice(&raw[..len]);
+    }
+    inode
+}
+
+/// 把 `ext4_inode` 写回原始字节，保留结构体之外的 inode 内扩展属性
+fn store_inode_raw(raw: &mut [u8], inode: &ext4_inode) {
+    let len = raw.len().min(core::mem::size_of::<ext4_inode>());
+    let bytes = unsafe { core::slice::from_raw_parts(inode as *const ext4_inode as *const u8, len) };
+    raw[..len].copy_from_slice(bytes);
+}
+
+/// 把目录块中指向 `moved` 中旧编号的目录项改为新编号，返回是否有修改
+///
+/// HTree 索引块开头是 inode 为 0 的假目录项，按普通目录项遍历会直接跳过
+fn remap_entries(block: &mut [u8], moved: &BTreeMap<u32, u32>) -> bool {
+    let mut changed = false;
+    let mut offset = 0;
+    while offset + 8 <= block.len() {
+        let ino = u32::from_le_bytes([block[offset], block[offset + 1], block[offset + 2], block[offset + 3]]);
+        let rec_len = u16::from_le_bytes([block[offset + 4], block[offset + 5]]) as usize;
+        if let Some(&new_ino) = moved.get(&ino) {
+            block[offset..offset + 4].copy_from_slice(&new_ino.to_le_bytes());
+            changed = true;
+        }
+        if rec_len < 8 {
+            break;
+        }
+        offset += rec_len;
+    }
+    changed
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::types::ext4_sblock;
+
+    fn sb_4k(blocks: u64, reserved_gdt: u16) -> Superblock {
+        Superblock::new(ext4_sblock {
+            log_block_size: 2u32.to_le(),
+            log_cluster_size: 2u32.to_le(),
+            blocks_per_group: 32768u32.to_le(),
+            inodes_per_group: 8192u32.to_le(),
+            inode_size: 256u16.to_le(),
+            blocks_count_lo: (blocks as u32).to_le(),
+            inodes_count: (8192 * (blocks as u32).div_ceil(32768)).to_le(),
+            reserved_gdt_blocks: reserved_gdt.to_le(),
+            feature_compat: EXT4_FEATURE_COMPAT_RESIZE_INODE.to_le(),
+            feature_ro_compat: EXT4_FEATURE_RO_COMPAT_SPARSE_SUPER.to_le(),
+            ..Default::default()
+        })
+    }
+
+    #[test]
+    fn test_shrunk_layout() {
+        // 32 字节描述符，每块 128 个：缩到 128 个块组后第二个描述符块变为保留 GDT 块
+        let old = sb_4k(129 * 32768, 14);
+        let new = shrunk_superblock(&old, 128 * 32768).unwrap();
+        assert_eq!(new.block_group_count(), 128);
+        assert_eq!(new.inodes_count(), 128 * 8192);
+        assert_eq!(new.reserved_gdt_blocks(), 15);
+
+        // 块组 1 有 superblock 备份：1 + 1 + 15 + 2 + 512 块元数据，放不下时舍弃
+        let old = sb_4k(4 * 32768, 15);
+        let new = shrunk_superblock(&old, 32768 + 531 + 49).unwrap();
+        assert_eq!(new.blocks_count(), 32768);
+        let new = shrunk_superblock(&old, 32768 + 531 + 50).unwrap();
+        assert_eq!(new.blocks_count(), 32768 + 581);
+
+        assert_eq!(
+            shrunk_superblock(&old, 500).err().map(|e| e.kind()),
+            Some(ErrorKind::InvalidInput)
+        );
+    }
+
+    #[test]
+    fn test_remap_entries() {
+        let mut block = vec![0u8; 64];
+        // "." -> 12，".." -> 2，最后一项占满剩余空间
+        block[0..4].copy_from_slice(&12u32.to_le_bytes());
+        block[4..6].copy_from_slice(&12u16.to_le_bytes());
+        block[12..16].copy_from_slice(&2u32.to_le_bytes());
+        block[16..18].copy_from_slice(&12u16.to_le_bytes());
+        block[24..28].copy_from_slice(&40000u32.to_le_bytes());
+        block[28..30].copy_from_slice(&40u16.to_le_bytes());
+
+        let moved = BTreeMap::from([(40000, 13), (12, 14)]);
+        assert!(remap_entries(&mut block, &moved));
+        assert_eq!(&block[0..4], &14u32.to_le_bytes());
+        assert_eq!(&block[12..16], &2u32.to_le_bytes());
+        assert_eq!(&block[24..28], &13u32.to_le_bytes());
+
+        assert!(!remap_entries(&mut block, &moved));
+    }
+}
@@ -0,0 +1,152 @@
+//! 按路径查询文件系统统计信息
+//!
+//! 与 `statfs(2)` 相同，总块数扣除元数据开销（superblock 备份、GDT、位图、
+//! inode 表和内部日志），可用块数按路径所指文件的属主决定是否扣除保留块。
+
+use crate::{
+    block::BlockDevice,
+    consts::*,
+    error::Result,
+    superblock::Superblock,
+};
+
+use super::{filesystem::Ext4FileSystem, resize::group_overhead, types::{FollowSymlink, StatFs}};
+
+impl<D: BlockDevice> Ext4FileSystem<D> {
+    /// 获取 `path` 所在文件系统的统计信息，跟随最后的符号链接
+    ///
+    /// - `blocks_count` 是可分配块数扣除元数据开销后的结果
+    /// - `free_blocks_count` 不含延迟分配已预留的块
+    /// - `available_blocks` 对非特权属主还扣除为特权属主保留的块
+    ///   （见 [`Superblock::can_use_reserved`]），属主取 `path` 所指文件的属主
+    ///
+    /// # 错误
+    ///
+    /// - `ErrorKind::NotFound` - 路径不存在
+    /// - `ErrorKind::SymlinkLoop` - 符号链接层数过多
+    ///
+    /// # 示例
+    ///
+    /// ```rust,ignore
+    /// let st = fs.statfs("/home/user")?;
+    /// println!("{} of {} blocks available", st.available_blocks, st.blocks_count);
+    /// ```
+    pub fn statfs(&mut self, path: &str) -> Result<StatFs> {
+        let inode_num = self.lookup_with(path, FollowSymlink::Follow)?;
+        let (uid, gid) = self.with_inode_ref(inode_num, |inode_ref| {
+            inode_ref.with_inode(|inode| {
+                let uid = u16::from_le(inode.uid) as u32 | (u16::from_le(inode.uid_high) as u32) << 16;
+                let gid = u16::from_le(inode.gid) as u32 | (u16::from_le(inode.gid_high) as u32) << 16;
+                (uid, gid)
+            })
+        })?;
+
+        let overhead = self.overhead_blocks()?;
+        let sb = self.superblock();
+        let free = crate::balloc::unreserved_free_blocks(sb);
+        let reserved = sb.root_reserved_blocks();
+        let available = if sb.can_use_reserved(uid, gid) {
+            free
+        } else {
+            free.saturating_sub(reserved)
+        };
+
+        Ok(StatFs {
+            inodes_count: sb.inodes_count(),
+            free_inodes_count: u32::from_le(sb.inner().free_inodes_count),
+            blocks_count: sb.alloc_limit().saturating_sub(overhead),
+            free_blocks_count: free,
+            available_blocks: available,
+            reserved_blocks: reserved,
+            overhead_blocks: overhead,
+            block_size: sb.block_size(),
+        })
+    }
+
+    /// 元数据占用的块数
+    ///
+    /// 优先使用 superblock 记录的 `s_overhead_clusters`（设置了分配上限时除外）；
+    /// 否则与内核 `ext4_calculate_overhead()` 一样逐块组累加，再加上内部日志的块数
+    fn overhead_blocks(&mut self) -> Result<u64> {
+        let sb = self.superblock();
+        let recorded = u32::from_le(sb.inner().overhead_blocks) as u64;
+        if recorded != 0 && sb.max_blocks().is_none() {
+            return Ok(recorded);
+        }
+
+        let mut overhead = group_metadata_blocks(sb);
+
+        let journal_inum = u32::from_le(sb.inner().journal_inum);
+        if sb.has_compat_feature(EXT4_FEATURE_COMPAT_HAS_JOURNAL) && journal_inum != 0 {
+            let block_size = sb.block_size() as u64;
+            overhead += self.with_inode_ref(journal_inum, |inode_ref| inode_ref.size())? / block_size;
+        }
+        Ok(overhead)
+    }
+}
+
+/// 分配上限以内各块组的元数据块数，包括第一个块组之前的块
+fn group_metadata_blocks(sb: &Superblock) -> u64 {
+    let limit = sb.alloc_limit();
+    let mut blocks = sb.first_data_block() as u64;
+    for group in 0..sb.block_group_count() {
+        let start = sb.first_data_block() as u64 + group as u64 * sb.blocks_per_group() as u64;
+        if start >= limit {
+            break;
+        }
+        blocks += group_overhead(sb, group) as u64;
+    }
+    blocks
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::types::ext4_sblock;
+
+    fn sb_4k(blocks: u64) -> Superblock {
+        Superblock::new(ext4_sblock {
+            log_block_size: 2u32.to_le(),
+            log_cluster_size: 2u32.to_le(),
+            blocks_per_group: 32768u32.to_le(),
+            inodes_per_group: 8192u32.to_le(),
+            inode_size: 256u16.to_le(),
+            blocks_count_lo: (blocks as u32).to_le(),
+            inodes_count: (8192 * (blocks as u32).div_ceil(32768)).to_le(),
+            reserved_gdt_blocks: 15u16.to_le(),
+            feature_ro_compat: EXT4_FEATURE_RO_COMPAT_SPARSE_SUPER.to_le(),
+            ..Default::default()
+        })
+    }
+
+    #[test]
+    fn test_group_metadata_blocks() {
+        // 块组 0 和 1 有 superblock 备份：1 + 1 个 GDT 块 + 15 个保留 GDT 块 + 2 个位图 + 512 个 inode 表块
+        // 块组 2 没有备份：2 个位图 + 512 个 inode 表块
+        let mut sb = sb_4k(3 * 32768);
+        assert_eq!(group_metadata_blocks(&sb), 2 * 531 + 514);
+
+        // 分配上限之外的块组不计入
+        sb.set_max_blocks(Some(32768 + 1));
+        assert_eq!(group_metadata_blocks(&sb), 2 * 531);
+    }
+
+    #[test]
+    fn test_reserved_policy() {
+        let mut sb = Superblock::new(ext4_sblock {
+            blocks_count_lo: 1000u32.to_le(),
+            r_blocks_count_lo: 50u32.to_le(),
+            def_resuid: 7u16.to_le(),
+            ..Default::default()
+        });
+        assert_eq!(sb.root_reserved_blocks(), 50);
+        sb.set_reserved_percent(Some(10));
+        assert_eq!(sb.root_reserved_blocks(), 100);
+
+        assert!(sb.can_use_reserved(0, 100));
+        assert!(sb.can_use_reserved(7, 100));
+        assert!(!sb.can_use_reserved(1000, 100));
+        // s_def_resgid 为 0 时不按组放行
+        assert!(!sb.can_use_reserved(1000, 0));
+    }
+}
@@ -0,0 +1,395 @@
+//! 多线程共享的文件系统句柄
+//!
+//! [`SyncExt4FileSystem`] 用 [`DeviceLock`] 保护内部的 [`Ext4FileSystem`]，
+//! 所有操作只需要 `&self`，可以放进 `Arc` 在内核线程之间共享。
+//!
+//! 加锁规则：
+//!
+//! - 全局锁保护整个文件系统状态（块缓存、superblock、分配器），
+//!   创建、删除、重命名、stat 等元数据操作在全局锁内一次完成
+//! - 文件读写按 [`IO_CHUNK_SIZE`] 分段，每段单独获取全局锁，段与段之间
+//!   其他线程可以操作别的文件；同一 inode 的读写、截断和删除由 inode 锁串行化，
+//!   读者不会看到写了一半的数据
+//! - 加锁顺序固定为先 inode 锁后全局锁，持有全局锁时从不等待 inode 锁
+//!
+//! `DeviceLock` 只有 lock/unlock 接口，类型上无法证明它真的互斥。句柄额外用
+//! 一个原子标志检测重叠的访问（例如 [`NoLock`] 被多个线程共享，或者在
+//! [`with_fs`](SyncExt4FileSystem::with_fs) 的闭包里再次调用句柄），
+//! 这时返回 `ErrorKind::Busy`，而不是产生数据竞争。
+
+use crate::{
+    block::{BlockDevice, DeviceLock, NoLock},
+    dir::DirEntry,
+    error::{Error, ErrorKind, Result},
+};
+use alloc::{collections::BTreeMap, sync::Arc, vec::Vec};
+use core::{
+    cell::UnsafeCell,
+    sync::atomic::{AtomicBool, Ordering},
+};
+
+use super::{filesystem::Ext4FileSystem, metadata::FileMetadata, types::StatFs};
+
+/// 文件读写每次持有全局锁处理的最大字节数
+pub const IO_CHUNK_SIZE: usize = 64 * 1024;
+
+/// 可以在线程之间共享的文件系统
+///
+/// `L` 是全局锁和 inode 锁的类型，inode 锁按需用 `L::default()` 创建。
+/// 默认的 [`NoLock`] 只适合单线程：句柄仍然可以通过 `&self` 使用，
+/// 但多个线程同时访问时会得到 `ErrorKind::Busy`。
+///
+/// # 示例
+///
+/// ```rust,ignore
+/// let fs = Arc::new(SyncExt4FileSystem::new(Ext4FileSystem::mount(bdev)?, KernelSpinLock::default()));
+///
+/// let worker = fs.clone();
+/// spawn(move || {
+///     let ino = worker.create_file("/", "log", 0o644).unwrap();
+///     worker.write_at(ino, b"hello", 0).unwrap();
+/// });
+///
+/// let md = fs.metadata("/")?;
+/// ```
+pub struct SyncExt4FileSystem<D: BlockDevice, L: DeviceLock = NoLock> {
+    state: LockCell<State<D, L>, L>,
+}
+
+/// 全局锁保护的状态
+struct State<D: BlockDevice, L> {
+    fs: Ext4FileSystem<D>,
+    /// 当前被锁住的 inode 及其锁
+    inode_locks: BTreeMap<u32, Arc<L>>,
+}
+
+/// 用 `DeviceLock` 保护的值
+///
+/// `busy` 标志保证同一时刻最多只有一个 `&mut T`，与锁是否真的互斥无关
+struct LockCell<T, L: DeviceLock> {
+    value: UnsafeCell<T>,
+    lock: L,
+    busy: AtomicBool,
+}
+
+// SAFETY: `value` 只在 `busy` 标志从 false 置为 true 之后访问，
+// 同一时刻最多只有一个线程持有 `&mut T`
+unsafe impl<T: Send, L: DeviceLock + Sync> Sync for LockCell<T, L> {}
+
+/// `LockCell` 的 RAII 守卫，panic 时也会释放锁
+struct CellGuard<'a, T, L: DeviceLock> {
+    cell: &'a LockCell<T, L>,
+}
+
+impl<T, L: DeviceLock> Drop for CellGuard<'_, T, L> {
+    fn drop(&mut self) {
+        self.cell.busy.store(false, Ordering::Release);
+        let _ = self.cell.lock.unlock();
+    }
+}
+
+impl<T, L: DeviceLock> LockCell<T, L> {
+    fn new(value: T, lock: L) -> Self {
+        Self { value: UnsafeCell::new(value), lock, busy: AtomicBool::new(false) }
+    }
+
+    fn with<R>(&self, f: impl FnOnce(&mut T) -> Result<R>) -> Result<R> {
+        self.lock.lock()?;
+        if self.busy.swap(true, Ordering::Acquire) {
+            let _ = self.lock.unlock();
+            return Err(Error::new(ErrorKind::Busy, "Filesystem is already being accessed"));
+        }
+        let _guard = CellGuard { cell: self };
+        // SAFETY: busy 标志由 false 置为 true 的线程独占访问，直到守卫释放
+        f(unsafe { &mut *self.value.get() })
+    }
+}
+
+/// inode 锁的 RAII 守卫
+struct InodeGuard<'a, D: BlockDevice, L: DeviceLock + Default> {
+    owner: &'a SyncExt4FileSystem<D, L>,
+    inode_num: u32,
+    lock: Option<Arc<L>>,
+}
+
+impl<D: BlockDevice, L: DeviceLock + Default> Drop for InodeGuard<'_, D, L> {
+    fn drop(&mut self) {
+        if let Some(lock) = self.lock.take() {
+            let _ = lock.unlock();
+        }
+        // 没有其他线程在等待时移除锁；失败只会留下一个空闲的表项
+        let inode_num = self.inode_num;
+        let _ = self.owner.locked(|_, locks| {
+            if locks.get(&inode_num).is_some_and(|lock| Arc::strong_count(lock) == 1) {
+                locks.remove(&inode_num);
+            }
+            Ok(())
+        });
+    }
+}
+
+impl<D: BlockDevice, L: DeviceLock + Default> SyncExt4FileSystem<D, L> {
+    /// 包装已挂载的文件系统，`lock` 作为全局锁
+    pub fn new(fs: Ext4FileSystem<D>, lock: L) -> Self {
+        Self { state: LockCell::new(State { fs, inode_locks: BTreeMap::new() }, lock) }
+    }
+
+    /// 取回内部的文件系统，例如用于 [`unmount`](Ext4FileSystem::unmount)
+    pub fn into_inner(self) -> Ext4FileSystem<D> {
+        self.state.value.into_inner().fs
+    }
+
+    /// 持有全局锁访问内部的文件系统
+    ///
+    /// 用于没有单独包装的操作。闭包内不能再调用同一个句柄：
+    /// 不可重入的锁会死锁，其他情况返回 `ErrorKind::Busy`。
+    pub fn with_fs<R>(&self, f: impl FnOnce(&mut Ext4FileSystem<D>) -> Result<R>) -> Result<R> {
+        self.locked(|fs, _| f(fs))
+    }
+
+    fn locked<R>(
+        &self,
+        f: impl FnOnce(&mut Ext4FileSystem<D>, &mut BTreeMap<u32, Arc<L>>) -> Result<R>,
+    ) -> Result<R> {
+        self.state.with(|state| f(&mut state.fs, &mut state.inode_locks))
+    }
+
+    fn lock_inode(&self, inode_num: u32) -> Result<InodeGuard<'_, D, L>> {
+        let lock = self.locked(|_, locks| Ok(locks.entry(inode_num).or_default().clone()))?;
+        let mut guard = InodeGuard { owner: self, inode_num, lock: None };
+        lock.lock()?;
+        guard.lock = Some(lock);
+        Ok(guard)
+    }
+
+    /// 解析 `parent_path/name` 并锁住它指向的 inode，然后在全局锁内执行 `f`
+    ///
+    /// 等待 inode 锁期间路径可能被改为指向别的 inode，拿到锁后重新解析，
+    /// 不一致时重试。路径不存在时不加 inode 锁，直接执行 `f`。
+    fn with_entry_locked<R>(
+        &self,
+        parent_path: &str,
+        name: &str,
+        mut f: impl FnMut(&mut Ext4FileSystem<D>) -> Result<R>,
+    ) -> Result<R> {
+        let lookup = |fs: &mut Ext4FileSystem<D>| -> Result<Option<u32>> {
+            let parent = fs.lookup_at(crate::consts::EXT4_ROOT_INODE, parent_path)?;
+            match fs.lookup_in_dir(parent, name) {
+                Ok(inode_num) => Ok(Some(inode_num)),
+                Err(e) if e.kind() == ErrorKind::NotFound => Ok(None),
+                Err(e) => Err(e),
+            }
+        };
+
+        loop {
+            let Some(inode_num) = self.locked(|fs, _| lookup(fs))? else {
+                return self.locked(|fs, _| f(fs));
+            };
+            let _inode = self.lock_inode(inode_num)?;
+            let done = self.locked(|fs, _| {
+                if lookup(fs)? != Some(inode_num) {
+                    return Ok(None);
+                }
+                f(fs).map(Some)
+            })?;
+            if let Some(result) = done {
+                return Ok(result);
+            }
+        }
+    }
+
+    /// 从 inode 的 `offset` 处读取，返回读取的字节数（到达文件末尾时可能小于 `buf.len()`）
+    ///
+    /// 整个读取期间持有 inode 锁，不会与同一 inode 的写入交错
+    pub fn read_at(&self, inode_num: u32, buf: &mut [u8], offset: u64) -> Result<usize> {
+        let _inode = self.lock_inode(inode_num)?;
+        let mut done = 0;
+        while done < buf.len() {
+            let end = buf.len().min(done + IO_CHUNK_SIZE);
+            let chunk = &mut buf[done..end];
+            let want = chunk.len();
+            let n = self.locked(|fs, _| {
+                let mut filled = 0;
+                while filled < want {
+                    let n = fs.read_at_inode(inode_num, &mut chunk[filled..], offset + (done + filled) as u64)?;
+                    if n == 0 {
+                        break;
+                    }
+                    filled += n;
+                }
+                Ok(filled)
+            })?;
+            done += n;
+            if n < want {
+                break;
+            }
+        }
+        Ok(done)
+    }
+
+    /// 把 `buf` 全部写到 inode 的 `offset` 处，返回写入的字节数
+    ///
+    /// 整个写入期间持有 inode 锁，其他线程读到的要么是写入前、要么是写入后的内容
+    pub fn write_at(&self, inode_num: u32, buf: &[u8], offset: u64) -> Result<usize> {
+        let _inode = self.lock_inode(inode_num)?;
+        for (i, chunk) in buf.chunks(IO_CHUNK_SIZE).enumerate() {
+            let chunk_offset = offset + (i * IO_CHUNK_SIZE) as u64;
+            self.locked(|fs, _| {
+                let mut written = 0;
+                while written < chunk.len() {
+                    written += fs.write_at_inode(inode_num, &chunk[written..], chunk_offset + written as u64)?;
+                }
+                Ok(())
+            })?;
+        }
+        Ok(buf.len())
+    }
+
+    /// 把 inode 截断（或扩展）到 `new_size`
+    pub fn truncate(&self, inode_num: u32, new_size: u64) -> Result<()> {
+        let _inode = self.lock_inode(inode_num)?;
+        self.locked(|fs, _| fs.truncate_file(inode_num, new_size))
+    }
+
+    /// 获取路径的元数据，跟随最后的符号链接
+    pub fn metadata(&self, path: &str) -> Result<FileMetadata> {
+        self.locked(|fs, _| fs.metadata(path))
+    }
+
+    /// 获取路径的元数据，不跟随最后的符号链接
+    pub fn symlink_metadata(&self, path: &str) -> Result<FileMetadata> {
+        self.locked(|fs, _| fs.symlink_metadata(path))
+    }
+
+    /// 获取路径所在文件系统的统计信息，见 [`Ext4FileSystem::statfs`]
+    pub fn statfs(&self, path: &str) -> Result<StatFs> {
+        self.locked(|fs, _| fs.statfs(path))
+    }
+
+    /// 读取目录的所有项
+    pub fn read_dir(&self, path: &str) -> Result<Vec<DirEntry>> {
+        self.locked(|fs, _| fs.read_dir(path))
+    }
+
+    /// 读取目录的所有项及其元数据，见 [`Ext4FileSystem::read_dir_plus`]
+    pub fn read_dir_plus(&self, path: &str) -> Result<Vec<(DirEntry, FileMetadata)>> {
+        self.locked(|fs, _| fs.read_dir_plus(path))
+    }
+
+    /// 创建普通文件，返回新 inode 编号
+    pub fn create_file(&self, parent_path: &str, name: &str, mode: u16) -> Result<u32> {
+        self.locked(|fs, _| fs.create_file(parent_path, name, mode))
+    }
+
+    /// 创建目录，返回新 inode 编号
+    pub fn create_dir(&self, parent_path: &str, name: &str, mode: u16) -> Result<u32> {
+        self.locked(|fs, _| fs.create_dir(parent_path, name, mode))
+    }
+
+    /// 删除文件
+    ///
+    /// 等待正在进行的同一文件的读写完成后才释放它的块
+    pub fn remove_file(&self, parent_path: &str, name: &str) -> Result<()> {
+        self.with_entry_locked(parent_path, name, |fs| fs.remove_file(parent_path, name))
+    }
+
+    /// 删除空目录
+    pub fn remove_dir(&self, parent_path: &str, name: &str) -> Result<()> {
+        self.locked(|fs, _| fs.remove_dir(parent_path, name))
+    }
+
+    /// 重命名或移动
+    ///
+    /// 目标已存在时会被替换，与 [`remove_file`](Self::remove_file) 一样先等待
+    /// 目标文件上正在进行的读写完成
+    pub fn rename(
+        &self,
+        old_parent_path: &str,
+        old_name: &str,
+        new_parent_path: &str,
+        new_name: &str,
+    ) -> Result<()> {
+        self.with_entry_locked(new_parent_path, new_name, |fs| {
+            fs.rename(old_parent_path, old_name, new_parent_path, new_name)
+        })
+    }
+
+    /// 提交所有未提交的修改，见 [`Ext4FileSystem::fsync`]
+    pub fn fsync(&self) -> Result<()> {
+        self.locked(|fs, _| fs.fsync())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use core::sync::atomic::AtomicU32;
+
+    /// 不做任何互斥的锁，记录当前的加锁次数
+    #[derive(Default)]
+    struct CountingLock(AtomicU32);
+
+    impl DeviceLock for CountingLock {
+        fn lock(&self) -> Result<()> {
+            self.0.fetch_add(1, Ordering::Relaxed);
+            Ok(())
+        }
+
+        fn unlock(&self) -> Result<()> {
+            self.0.fetch_sub(1, Ordering::Relaxed);
+            Ok(())
+        }
+    }
+
+    fn assert_sync<T: Sync>() {}
+
+    #[test]
+    fn test_handle_is_sync() {
+        struct Dev;
+        impl BlockDevice for Dev {
+            fn block_size(&self) -> u32 {
+                4096
+            }
+            fn sector_size(&self) -> u32 {
+                512
+            }
+            fn total_blocks(&self) -> u64 {
+                0
+            }
+            fn read_blocks(&mut self, _lba: u64, _count: u32, _buf: &mut [u8]) -> Result<usize> {
+                Ok(0)
+            }
+            fn write_blocks(&mut self, _lba: u64, _count: u32, _buf: &[u8]) -> Result<usize> {
+                Ok(0)
+            }
+        }
+        assert_sync::<SyncExt4FileSystem<Dev, CountingLock>>();
+    }
+
+    #[test]
+    fn test_overlapping_access_is_busy() {
+        let cell = LockCell::new(1u32, CountingLock::default());
+
+        let inner = cell.with(|value| {
+            *value += 1;
+            // 锁没有互斥时，重叠的访问由 busy 标志拒绝
+            Ok(cell.with(|_| Ok(())).map_err(|e| e.kind()))
+        });
+        assert_eq!(inner.unwrap(), Err(ErrorKind::Busy));
+
+        // 两次访问都已释放锁，之后可以正常访问
+        assert_eq!(cell.lock.0.load(Ordering::Relaxed), 0);
+        assert_eq!(cell.with(|value| Ok(*value)).unwrap(), 2);
+    }
+
+    #[test]
+    fn test_error_releases_lock() {
+        let cell = LockCell::new((), CountingLock::default());
+        let err = cell
+            .with(|_| Err::<(), _>(Error::new(ErrorKind::Io, "device error")))
+            .unwrap_err();
+        assert_eq!(err.kind(), ErrorKind::Io);
+        assert_eq!(cell.lock.0.load(Ordering::Relaxed), 0);
+        assert!(cell.with(|_| Ok(())).is_ok());
+    }
+}
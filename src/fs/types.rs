@@ -3,6 +3,10 @@
 //! 这个模块定义了与 lwext4_rust 兼容的类型，用于 ArceOS 文件系统集成
 
 use crate::consts::*;
+use crate::block::FsObserver;
+use crate::cache::{CachePolicy, WritebackConfig};
+use crate::ialloc::InodeAllocPolicy;
+use bitflags::bitflags;
 use core::time::Duration;
 
 /// 系统硬件抽象层 trait
@@ -32,14 +36,157 @@ pub trait SystemHal {
 /// 文件系统配置
 #[derive(Debug, Clone, Copy)]
 pub struct FsConfig {
-    /// 块缓存大小（块数）
-    pub bcache_size: u32,
+    /// 块缓存大小（块数），`None` 保持块设备现有的缓存
+    ///
+    /// 挂载后仍可以通过 [`BlockDev::set_cache_capacity`](crate::block::BlockDev::set_cache_capacity)
+    /// 动态调整
+    pub cache_blocks: Option<usize>,
+    /// 块缓存淘汰策略
+    pub cache_policy: CachePolicy,
+    /// 块缓存脏块写回阈值
+    pub cache_writeback: WritebackConfig,
+    /// 挂载时在块缓存中固定关键元数据块
+    ///
+    /// 见 [`Ext4FileSystem::pin_metadata`](super::Ext4FileSystem::pin_metadata)
+    pub pin_metadata: bool,
+    /// 块分配上限（块号，不含）
+    ///
+    /// 设置后分配器不会使用 `>= max_blocks` 的块，统计信息中的总块数也按此上限报告。
+    /// 用于在较大的稀疏镜像中构建要烧录到较小分区的文件系统。
+    pub max_blocks: Option<u64>,
+    /// 启用延迟分配
+    ///
+    /// 写入空洞的数据先缓存在内存中，刷新时再按整段分配连续的物理块，
+    /// 见 [`Ext4FileSystem::set_delalloc`](super::Ext4FileSystem::set_delalloc)
+    pub delalloc: bool,
+    /// 文件数据页缓存容量（页数），0 表示不启用
+    ///
+    /// 见 [`Ext4FileSystem::set_page_cache`](super::Ext4FileSystem::set_page_cache)
+    pub page_cache_pages: usize,
+    /// 新目录直接创建为 HTree 索引目录
+    ///
+    /// 需要 `htree-write` 特性且文件系统支持 `DIR_INDEX`，否则仍创建线性目录
+    pub index_new_dirs: bool,
+    /// inode 分配策略
+    ///
+    /// 默认取第一个空闲 inode；[`InodeAllocPolicy::Orlov`] 会把顶层目录
+    /// 分散到较空闲的块组，并把文件放在父目录附近
+    pub inode_alloc: InodeAllocPolicy,
+    /// 定时提交间隔，`None` 表示只在 fsync 等显式触发时提交
+    ///
+    /// 需要宿主系统周期性调用
+    /// [`Ext4FileSystem::on_timer_tick`](super::Ext4FileSystem::on_timer_tick)
+    pub commit_interval: Option<Duration>,
+    /// 读取元数据时验证校验和（需要文件系统启用 `metadata_csum`）
+    ///
+    /// 挂载时验证 superblock 和所有块组描述符；之后加载 inode、目录块、
+    /// extent 块和位图时逐个验证，不匹配时返回带块号的 `ErrorKind::Corrupted`
+    /// （见 [`Error::block`](crate::error::Error::block)），用于尽早发现存储介质上的位翻转
+    pub verify_checksums: bool,
+    /// 为特权属主保留的块比例（百分比，0 - 50），`None` 使用 superblock 中的 `s_r_blocks_count`
+    ///
+    /// 非特权属主的文件分配块时不能使用保留块，见
+    /// [`Superblock::can_use_reserved`](crate::superblock::Superblock::can_use_reserved)。
+    /// 只影响本次挂载，不写入磁盘
+    pub reserved_percent: Option<u8>,
+    /// 只读挂载
+    ///
+    /// 所有修改文件系统的操作返回 `ErrorKind::ReadOnlyFs`，块设备不会被写入。
+    /// 没有设置时，遇到不支持的特性或只读设备也会自动以只读方式挂载，
+    /// 见 [`Ext4FileSystem::mount`](super::Ext4FileSystem::mount)
+    pub read_only: bool,
+    /// 观测者，接收块读写、缓存命中、分配器重试、日志提交和操作耗时的回调
+    ///
+    /// 见 [`FsObserver`]，挂载后也可以通过
+    /// [`BlockDev::set_observer`](crate::block::BlockDev::set_observer) 更换
+    pub observer: Option<&'static dyn FsObserver>,
+    /// 新建文件和目录的属主、属组和权限策略
+    ///
+    /// 挂载后可以通过 [`Ext4FileSystem::set_create_policy`](super::Ext4FileSystem::set_create_policy) 更换
+    pub create_policy: CreatePolicy,
+    /// 时钟，通常是 [`SystemHal::now`]
+    ///
+    /// 挂载时记录到 superblock 的挂载时间，
+    /// [`Ext4FileSystem::needs_fsck`](super::Ext4FileSystem::needs_fsck) 用它判断检查间隔
+    pub clock: Option<fn() -> Option<Duration>>,
+    /// 挂载点路径，挂载时记录到 superblock 的 `s_last_mounted`
+    pub mount_point: Option<&'static str>,
+    /// 检测到损坏（`ErrorKind::Corrupted`）时在 superblock 中记录错误的时间、操作和块号
+    ///
+    /// 见 [`Ext4FileSystem::error_state`](super::Ext4FileSystem::error_state)，随下一次提交或卸载写回
+    pub record_errors: bool,
+}
+
+/// 新建 inode 的属主、属组和权限策略，见 [`FsConfig::create_policy`]
+///
+/// 适用于 `create_file`、`create_dir`、`create_in_dir`、`mknod` 和 `fsymlink` 等创建操作，
+/// 与 Linux 创建文件时的规则相同：
+/// - 权限位去掉 `umask` 中的位，符号链接的权限总是 0o777
+/// - 属主为 `uid`
+/// - 父目录设置了 SGID 且 `inherit_sgid` 为真时，属组取父目录的属组，
+///   新目录同样设置 SGID；否则属组为 `gid`
+///
+/// 默认值（属主属组为 0、`umask` 为 0）保持调用者传入的权限不变
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct CreatePolicy {
+    /// 新 inode 的属主
+    pub uid: u32,
+    /// 新 inode 的属组（父目录没有 SGID 时）
+    pub gid: u32,
+    /// 从权限位中去掉的位，只使用低 9 位
+    pub umask: u16,
+    /// 在设置了 SGID 的目录中创建时继承父目录的属组
+    pub inherit_sgid: bool,
+}
+
+impl Default for CreatePolicy {
+    fn default() -> Self {
+        Self { uid: 0, gid: 0, umask: 0, inherit_sgid: true }
+    }
+}
+
+impl CreatePolicy {
+    /// 按策略计算新 inode 的 mode 和属组
+    ///
+    /// `mode` 包含类型位，`parent_mode`/`parent_gid` 是父目录的 mode 和属组
+    pub(crate) fn apply(&self, mode: u16, parent_mode: u16, parent_gid: u32) -> (u16, u32) {
+        let file_type = mode & EXT4_INODE_MODE_TYPE_MASK;
+        let mut mode = match file_type {
+            EXT4_INODE_MODE_SOFTLINK => mode,
+            _ => mode & !(self.umask & 0o777),
+        };
+        if self.inherit_sgid && parent_mode & EXT4_INODE_MODE_SETGID != 0 {
+            if file_type == EXT4_INODE_MODE_DIRECTORY {
+                mode |= EXT4_INODE_MODE_SETGID;
+            }
+            (mode, parent_gid)
+        } else {
+            (mode, self.gid)
+        }
+    }
 }
 
 impl Default for FsConfig {
     fn default() -> Self {
         Self {
-            bcache_size: 256, // 默认 256 个块
+            cache_blocks: None,
+            cache_policy: CachePolicy::Lru,
+            cache_writeback: WritebackConfig::default(),
+            pin_metadata: false,
+            max_blocks: None,
+            delalloc: false,
+            page_cache_pages: 0,
+            index_new_dirs: false,
+            inode_alloc: InodeAllocPolicy::FirstFree,
+            commit_interval: Some(super::DEFAULT_COMMIT_INTERVAL),
+            verify_checksums: false,
+            reserved_percent: None,
+            read_only: false,
+            observer: None,
+            create_policy: CreatePolicy::default(),
+            clock: None,
+            mount_point: None,
+            record_errors: false,
         }
     }
 }
@@ -51,14 +198,31 @@ pub struct StatFs {
     pub inodes_count: u32,
     /// 空闲 inode 数
     pub free_inodes_count: u32,
-    /// 总块数
+    /// 总块数（扣除元数据开销）
     pub blocks_count: u64,
     /// 空闲块数
     pub free_blocks_count: u64,
+    /// 调用者可用的空闲块数（非特权属主扣除保留块）
+    pub available_blocks: u64,
+    /// 为特权属主保留的块数
+    pub reserved_blocks: u64,
+    /// 元数据开销块数（superblock 备份、GDT、位图、inode 表和日志）
+    pub overhead_blocks: u64,
     /// 块大小（字节）
     pub block_size: u32,
 }
 
+/// 单个块组的写入统计，见 [`Ext4FileSystem::write_heatmap`](super::Ext4FileSystem::write_heatmap)
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct GroupWrites {
+    /// 块组号
+    pub group: u32,
+    /// 采样计数
+    pub samples: u64,
+    /// 估算的写入块数（采样计数 × 采样间隔）
+    pub estimated_blocks: u64,
+}
+
 /// 文件属性
 #[derive(Debug, Clone, Copy, Default)]
 pub struct FileAttr {
@@ -88,6 +252,52 @@ pub struct FileAttr {
     pub ctime: u64,
 }
 
+bitflags! {
+    /// 块映射标志
+    ///
+    /// 对应 Linux `FIEMAP_EXTENT_*` 标志的子集
+    #[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+    pub struct MappingFlags: u32 {
+        /// 文件的最后一段映射
+        const LAST      = 0x0001;
+        /// 已分配但未写入（读取时为全零）
+        const UNWRITTEN = 0x0800;
+        /// 由逐块映射合并而成（间接块映射的文件）
+        const MERGED    = 0x1000;
+    }
+}
+
+/// 一段逻辑块到物理块的映射
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ExtentMapping {
+    /// 起始逻辑块号
+    pub logical_block: u32,
+    /// 起始物理块号
+    pub physical_block: u64,
+    /// 块数
+    pub len: u32,
+    /// 映射标志
+    pub flags: MappingFlags,
+}
+
+impl ExtentMapping {
+    /// 结束逻辑块号（不包含）
+    pub fn logical_end(&self) -> u64 {
+        self.logical_block as u64 + self.len as u64
+    }
+}
+
+/// 路径的最后一个组件是符号链接时是否跟随
+///
+/// 中间的组件不受影响，见 [`Ext4FileSystem::lookup_with`](super::Ext4FileSystem::lookup_with)
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum FollowSymlink {
+    /// 跟随到最终目标（`stat`）
+    Follow,
+    /// 返回链接本身（`lstat`）
+    NoFollow,
+}
+
 /// Inode 类型枚举
 #[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
 #[repr(u8)]
@@ -205,6 +415,31 @@ mod tests {
         );
     }
 
+    #[test]
+    fn test_create_policy_apply() {
+        let policy = CreatePolicy { uid: 1000, gid: 100, umask: 0o022, inherit_sgid: true };
+        let file = EXT4_INODE_MODE_FILE | 0o666;
+        let dir = EXT4_INODE_MODE_DIRECTORY | 0o777;
+        let link = EXT4_INODE_MODE_SOFTLINK | 0o777;
+        let plain_parent = EXT4_INODE_MODE_DIRECTORY | 0o755;
+        let sgid_parent = plain_parent | EXT4_INODE_MODE_SETGID;
+
+        assert_eq!(policy.apply(file, plain_parent, 50), (EXT4_INODE_MODE_FILE | 0o644, 100));
+        assert_eq!(policy.apply(link, plain_parent, 50), (link, 100));
+        assert_eq!(policy.apply(file, sgid_parent, 50), (EXT4_INODE_MODE_FILE | 0o644, 50));
+        assert_eq!(
+            policy.apply(dir, sgid_parent, 50),
+            (EXT4_INODE_MODE_DIRECTORY | EXT4_INODE_MODE_SETGID | 0o755, 50)
+        );
+
+        let no_inherit = CreatePolicy { inherit_sgid: false, ..policy };
+        assert_eq!(no_inherit.apply(dir, sgid_parent, 50), (EXT4_INODE_MODE_DIRECTORY | 0o755, 100));
+        // umask 只作用于 rwx 位，默认策略不改变传入的权限
+        let sticky = EXT4_INODE_MODE_DIRECTORY | EXT4_INODE_MODE_STICKY | 0o777;
+        assert_eq!(CreatePolicy { umask: 0o7777, ..policy }.apply(sticky, plain_parent, 0).0, sticky & !0o777);
+        assert_eq!(CreatePolicy::default().apply(file, plain_parent, 50), (file, 0));
+    }
+
     #[test]
     fn test_inode_type_to_mode_bits() {
         assert_eq!(InodeType::RegularFile.to_mode_bits(), EXT4_INODE_MODE_FILE);
@@ -238,6 +473,27 @@ mod tests {
     #[test]
     fn test_fs_config_default() {
         let config = FsConfig::default();
-        assert_eq!(config.bcache_size, 256);
+        assert_eq!(config.cache_blocks, None);
+        assert_eq!(config.cache_policy, CachePolicy::Lru);
+        assert_eq!(config.cache_writeback, WritebackConfig::default());
+        assert_eq!(config.max_blocks, None);
+        assert!(!config.delalloc);
+        assert_eq!(config.page_cache_pages, 0);
+        assert!(!config.index_new_dirs);
+        assert_eq!(config.inode_alloc, InodeAllocPolicy::FirstFree);
+        assert!(config.observer.is_none());
+    }
+
+    #[test]
+    fn test_extent_mapping_logical_end() {
+        let m = ExtentMapping {
+            logical_block: u32::MAX - 1,
+            physical_block: 1000,
+            len: 2,
+            flags: MappingFlags::LAST | MappingFlags::UNWRITTEN,
+        };
+        assert_eq!(m.logical_end(), u32::MAX as u64 + 1);
+        assert!(m.flags.contains(MappingFlags::UNWRITTEN));
+        assert!(!m.flags.contains(MappingFlags::MERGED));
     }
 }
@@ -0,0 +1,133 @@
+//! 多步分配操作的回滚
+//!
+//! `create_dir`、慢速符号链接等操作由多个立即生效的步骤组成
+//! （分配 inode → 分配目录块 → 添加父目录项 …）。中途失败时，
+//! 之前步骤分配的 inode 和块会泄漏。
+//!
+//! 在完整的 JBD2 事务覆盖这些路径之前，用 [`AllocUndo`] 记录
+//! 操作过程中的分配，失败时按相反顺序释放。
+
+use crate::{block::BlockDevice, error::Result};
+use alloc::{string::String, vec::Vec};
+
+use super::filesystem::Ext4FileSystem;
+
+/// 一次操作中已生效的分配记录
+#[derive(Debug, Default)]
+pub(crate) struct AllocUndo {
+    /// 新分配的 inode 及是否为目录
+    ///
+    /// 回滚时连同其数据块和 extent 树一起释放
+    inodes: Vec<(u32, bool)>,
+    /// 单独分配、尚未挂到任何 inode 上的块
+    blocks: Vec<u64>,
+    /// 已添加的目录项 (目录 inode, 名称)
+    entries: Vec<(u32, String)>,
+}
+
+impl AllocUndo {
+    pub(crate) fn new() -> Self {
+        Self::default()
+    }
+
+    /// 记录新分配的 inode
+    pub(crate) fn inode(&mut self, ino: u32, is_dir: bool) {
+        self.inodes.push((ino, is_dir));
+    }
+
+    /// 记录单独分配的块
+    #[allow(dead_code)]
+    pub(crate) fn block(&mut self, block: u64) {
+        self.blocks.push(block);
+    }
+
+    /// 记录已添加的目录项
+    pub(crate) fn entry(&mut self, dir: u32, name: &str) {
+        self.entries.push((dir, String::from(name)));
+    }
+
+    /// 是否没有任何记录
+    pub(crate) fn is_empty(&self) -> bool {
+        self.inodes.is_empty() && self.blocks.is_empty() && self.entries.is_empty()
+    }
+}
+
+impl<D: BlockDevice> Ext4FileSystem<D> {
+    /// 执行一个多步分配操作，失败时回滚已记录的分配
+    ///
+    /// `op` 每完成一次分配就应记录到 `undo` 中。返回 `Err` 时按
+    /// 目录项 → inode → 块的顺序撤销（各自按记录的相反顺序），
+    /// 回滚过程中的错误只记录日志，返回原始错误。
+    pub(crate) fn with_alloc_undo<T, F>(&mut self, op: F) -> Result<T>
+    where
+        F: FnOnce(&mut Self, &mut AllocUndo) -> Result<T>,
+    {
+        let mut undo = AllocUndo::new();
+        let result = op(self, &mut undo);
+        if result.is_err() && !undo.is_empty() {
+            self.rollback_allocs(undo);
+        }
+        result
+    }
+
+    fn rollback_allocs(&mut self, undo: AllocUndo) {
+        let AllocUndo { inodes, blocks, entries } = undo;
+
+        for (dir, name) in entries.into_iter().rev() {
+            if let Err(e) = self.remove_dir_entry(dir, &name) {
+                log::warn!("[UNDO] failed to remove entry {name:?} from dir {dir}: {e:?}");
+            }
+        }
+
+        for (ino, is_dir) in inodes.into_iter().rev() {
+            if let Err(e) = self.release_new_inode(ino, is_dir) {
+                log::warn!("[UNDO] failed to release inode {ino}: {e:?}");
+            }
+        }
+
+        for block in blocks.into_iter().rev() {
+            if let Err(e) = self.free_block(block) {
+                log::warn!("[UNDO] failed to free block {block}: {e:?}");
+            }
+        }
+    }
+
+    /// 释放一个刚分配、尚未被任何目录项引用的 inode
+    fn release_new_inode(&mut self, ino: u32, is_dir: bool) -> Result<()> {
+        // 释放数据块（含 extent 树节点）
+        self.truncate_file(ino, 0)?;
+
+        // 创建路径不会重置 i_blocks，inode 被重新分配时会继承旧值，
+        // 因此这里连同块计数一起清零；mode 清零使其成为未使用的 inode
+        self.with_inode_ref(ino, |inode_ref| {
+            inode_ref.set_blocks_count(0)?;
+            inode_ref.with_inode_mut(|inode| {
+                inode.mode = 0;
+                inode.links_count = 0;
+            })?;
+            inode_ref.mark_dirty()
+        })?;
+
+        self.free_inode(ino, is_dir)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_alloc_undo_records() {
+        let mut undo = AllocUndo::new();
+        assert!(undo.is_empty());
+
+        undo.inode(12, true);
+        undo.block(1000);
+        undo.entry(2, "lost");
+
+        assert!(!undo.is_empty());
+        assert_eq!(undo.inodes, alloc::vec![(12, true)]);
+        assert_eq!(undo.blocks, alloc::vec![1000]);
+        assert_eq!(undo.entries[0].1, "lost");
+    }
+}
@@ -0,0 +1,275 @@
+//! 向量化读写（scatter/gather）
+//!
+//! [`Ext4FileSystem::read_at_inode_vectored`] 按映射把物理连续的整块直接读入调用者的缓冲区，
+//! 只有不对齐或跨越两个缓冲区的块才经过一个块大小的中间缓冲区。
+//! [`Ext4FileSystem::write_at_inode_vectored`] 依次写入每个缓冲区，其中的整块与
+//! [`write_at_inode_batch`](Ext4FileSystem::write_at_inode_batch) 一样直接从调用者的缓冲区写入设备。
+//!
+//! 启用页缓存、以及非普通文件（符号链接等）时，读取退回到逐个缓冲区调用
+//! [`read_at_inode`](Ext4FileSystem::read_at_inode)。
+
+use crate::{
+    block::{BlockDevice, FsOp},
+    error::{Error, ErrorKind, Result},
+};
+use alloc::vec::Vec;
+
+use super::{
+    filesystem::{Ext4FileSystem, MAX_WRITE_RUN},
+    MappingFlags,
+};
+
+/// 调用者缓冲区序列上的写入位置
+struct ScatterCursor<'a, 'b> {
+    bufs: &'a mut [&'b mut [u8]],
+    /// 当前缓冲区下标
+    idx: usize,
+    /// 当前缓冲区内的偏移
+    pos: usize,
+}
+
+impl<'a, 'b> ScatterCursor<'a, 'b> {
+    fn new(bufs: &'a mut [&'b mut [u8]]) -> Self {
+        Self { bufs, idx: 0, pos: 0 }
+    }
+
+    /// 跳过已经写满的缓冲区（包括空缓冲区）
+    fn skip_full(&mut self) {
+        while self.idx < self.bufs.len() && self.pos == self.bufs[self.idx].len() {
+            self.idx += 1;
+            self.pos = 0;
+        }
+    }
+
+    /// 当前缓冲区剩余的字节数
+    fn current_len(&mut self) -> usize {
+        self.skip_full();
+        self.bufs.get(self.idx).map_or(0, |buf| buf.len() - self.pos)
+    }
+
+    /// 当前缓冲区中接下来的 `len` 个字节，并前移游标
+    ///
+    /// 调用者保证 `len` 不超过 [`current_len`](Self::current_len)
+    fn take(&mut self, len: usize) -> &mut [u8] {
+        let pos = self.pos;
+        self.pos += len;
+        &mut self.bufs[self.idx][pos..pos + len]
+    }
+
+    /// 把 `data` 依次复制到后续缓冲区，可以跨越缓冲区边界
+    fn copy_from(&mut self, mut data: &[u8]) {
+        while !data.is_empty() {
+            let n = self.current_len().min(data.len());
+            self.take(n).copy_from_slice(&data[..n]);
+            data = &data[n..];
+        }
+    }
+
+    /// 把接下来的 `len` 个字节填零
+    fn fill_zero(&mut self, mut len: usize) {
+        while len > 0 {
+            let n = self.current_len().min(len);
+            self.take(n).fill(0);
+            len -= n;
+        }
+    }
+}
+
+impl<D: BlockDevice> Ext4FileSystem<D> {
+    /// 从 `offset` 开始把文件数据依次读入多个缓冲区（`preadv`）
+    ///
+    /// 与对每个缓冲区分别调用 [`read_at_inode`](Self::read_at_inode) 的结果相同，
+    /// 但同一映射内物理连续、且完整落在一个缓冲区中的整块合并为一次设备读取，
+    /// 直接写入调用者的缓冲区。
+    ///
+    /// # 返回
+    ///
+    /// 读取的总字节数，遇到文件末尾时小于缓冲区总长度
+    ///
+    /// # 错误
+    ///
+    /// - `ErrorKind::Encrypted` - inode 已加密
+    /// - `ErrorKind::Corrupted` - 映射指向设备之外的块
+    ///
+    /// # 示例
+    ///
+    /// ```rust,ignore
+    /// let (mut header, mut body) = ([0u8; 512], vec![0u8; 64 * 1024]);
+    /// let n = fs.read_at_inode_vectored(ino, 0, &mut [&mut header[..], &mut body[..]])?;
+    /// ```
+    pub fn read_at_inode_vectored(&mut self, inode_num: u32, offset: u64, bufs: &mut [&mut [u8]]) -> Result<usize> {
+        self.observe(FsOp::Read, |fs| fs.read_at_inode_vectored_steps(inode_num, offset, bufs))
+    }
+
+    /// `read_at_inode_vectored` 的各个步骤
+    fn read_at_inode_vectored_steps(
+        &mut self,
+        inode_num: u32,
+        offset: u64,
+        bufs: &mut [&mut [u8]],
+    ) -> Result<usize> {
+        let total: usize = bufs.iter().map(|buf| buf.len()).sum();
+        if total == 0 {
+            return Ok(0);
+        }
+
+        let block_size = self.superblock().block_size() as u64;
+        let (is_file, size) = self.with_inode_ref(inode_num, |inode_ref| {
+            inode_ref.check_not_encrypted()?;
+            Ok((inode_ref.is_file()?, inode_ref.size()?))
+        })?;
+        if offset >= size {
+            return Ok(0);
+        }
+        if !is_file || self.page_cache.is_some() {
+            return self.read_vectored_each(inode_num, offset, bufs);
+        }
+
+        let to_read = (total as u64).min(size - offset) as usize;
+        let first = offset / block_size;
+        let end = (offset + to_read as u64).div_ceil(block_size).min(u32::MAX as u64);
+        let mappings = self.with_inode_ref(inode_num, |inode_ref| inode_ref.fiemap(first as u32..end as u32))?;
+        let device_blocks = self.bdev.total_blocks();
+
+        let mut cursor = ScatterCursor::new(bufs);
+        let mut block_buf = Vec::new();
+        let mut done = 0;
+        let mut idx = 0;
+        while done < to_read {
+            let pos = offset + done as u64;
+            let block = pos / block_size;
+            let in_block = (pos % block_size) as usize;
+            let left = to_read - done;
+
+            while idx < mappings.len() && mappings[idx].logical_end() <= block {
+                idx += 1;
+            }
+            let mapping = mappings.get(idx).filter(|m| m.logical_block as u64 <= block);
+            let m = match mapping {
+                Some(m) if !m.flags.contains(MappingFlags::UNWRITTEN) => m,
+                _ => {
+                    // 空洞或 unwritten extent：填零到下一段有数据的映射
+                    let zero_end = match (mapping, mappings.get(idx)) {
+                        (Some(m), _) => m.logical_end() * block_size,
+                        (None, Some(next)) => next.logical_block as u64 * block_size,
+                        (None, None) => u64::MAX,
+                    };
+                    let n = (zero_end - pos).min(left as u64) as usize;
+                    cursor.fill_zero(n);
+                    done += n;
+                    continue;
+                }
+            };
+
+            let in_mapping = block - m.logical_block as u64;
+            let physical = m.physical_block + in_mapping;
+            let whole = (cursor.current_len().min(left) as u64 / block_size)
+                .min(m.len as u64 - in_mapping)
+                .min(MAX_WRITE_RUN as u64);
+            let blocks = if in_block == 0 && whole > 0 { whole } else { 1 };
+            if physical + blocks > device_blocks {
+                return Err(Error::with_block(
+                    ErrorKind::Corrupted,
+                    "Physical block address exceeds device size",
+                    physical,
+                ));
+            }
+
+            if in_block == 0 && whole > 0 {
+                // 整块直接读入调用者的缓冲区
+                let len = (whole * block_size) as usize;
+                self.bdev.read_blocks(physical, whole as u32, cursor.take(len))?;
+                done += len;
+            } else {
+                // 不对齐或跨越缓冲区边界的块经过中间缓冲区
+                block_buf.resize(block_size as usize, 0);
+                self.bdev.read_block(physical, &mut block_buf)?;
+                let n = (block_size as usize - in_block).min(left);
+                cursor.copy_from(&block_buf[in_block..in_block + n]);
+                done += n;
+            }
+        }
+
+        // 延迟分配的数据尚未落盘，按每个缓冲区对应的文件偏移覆盖
+        if self.delalloc.is_some() {
+            let (mut pos, mut left) = (offset, done);
+            for buf in bufs.iter_mut() {
+                let n = buf.len().min(left);
+                self.delalloc_overlay_read(inode_num, pos, &mut buf[..n]);
+                pos += n as u64;
+                left -= n;
+            }
+        }
+        Ok(done)
+    }
+
+    /// 逐个缓冲区读取，遇到文件末尾时停止
+    fn read_vectored_each(&mut self, inode_num: u32, mut offset: u64, bufs: &mut [&mut [u8]]) -> Result<usize> {
+        let mut total = 0;
+        for buf in bufs.iter_mut() {
+            let mut filled = 0;
+            while filled < buf.len() {
+                let n = self.read_at_inode(inode_num, &mut buf[filled..], offset)?;
+                if n == 0 {
+                    return Ok(total);
+                }
+                filled += n;
+                offset += n as u64;
+                total += n;
+            }
+        }
+        Ok(total)
+    }
+
+    /// 把多个缓冲区的数据依次写入 `offset` 开始的位置（`pwritev`）
+    ///
+    /// 每个缓冲区按 [`write_at_inode_batch`](Self::write_at_inode_batch) 写入：
+    /// 未映射的区域一次分配整段，缓冲区中物理连续的整块直接从缓冲区写入设备，
+    /// 不对齐或跨越两个缓冲区的块在块缓存中读-改-写。
+    ///
+    /// # 返回
+    ///
+    /// 写入的总字节数
+    ///
+    /// # 错误
+    ///
+    /// - `ErrorKind::ReadOnlyFs` - 只读挂载
+    /// - `ErrorKind::Encrypted` - inode 已加密
+    /// - `ErrorKind::NoSpace` - 空间不足，此前的缓冲区已经写入
+    pub fn write_at_inode_vectored(&mut self, inode_num: u32, offset: u64, bufs: &[&[u8]]) -> Result<usize> {
+        self.observe(FsOp::Write, |fs| {
+            let mut written = 0;
+            for buf in bufs {
+                let (n, _) = fs.write_at_inode_sized(inode_num, buf, offset + written as u64, false)?;
+                written += n;
+                if n < buf.len() {
+                    break;
+                }
+            }
+            Ok(written)
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_scatter_cursor() {
+        let (mut a, mut b, mut c) = ([1u8; 3], [1u8; 0], [1u8; 5]);
+        let mut bufs: [&mut [u8]; 3] = [&mut a, &mut b, &mut c];
+        let mut cursor = ScatterCursor::new(&mut bufs);
+
+        assert_eq!(cursor.current_len(), 3);
+        cursor.take(2).copy_from_slice(&[7, 8]);
+        // 跨越空缓冲区和缓冲区边界
+        cursor.copy_from(&[9, 10, 11]);
+        assert_eq!(cursor.current_len(), 3);
+        cursor.fill_zero(2);
+        assert_eq!(cursor.current_len(), 1);
+
+        assert_eq!(a, [7, 8, 9]);
+        assert_eq!(c, [10, 11, 0, 0, 1]);
+    }
+}
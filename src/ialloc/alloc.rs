@@ -1,15 +1,21 @@
 //! Inode 分配功能
 
 use crate::{
+    balloc::check_bitmap_cached,
     bitmap::*,
     block::{Block, BlockDev, BlockDevice},
     block_group::BlockGroup,
+    consts::*,
     error::{Error, ErrorKind, Result},
     fs::BlockGroupRef,
     superblock::Superblock,
 };
 
-use super::{checksum::*, helpers::*};
+use super::{
+    checksum::*,
+    helpers::*,
+    uninit::{init_inode_bitmap, zero_inode_slot},
+};
 
 /// Inode 分配器状态
 ///
@@ -46,6 +52,7 @@ impl InodeAllocator {
         sb: &mut Superblock,
         is_dir: bool,
     ) -> Result<u32> {
+        sb.check_writable()?;
         let mut bgid = self.last_inode_bg_id;
         let bg_count = sb.block_group_count();
         let mut sb_free_inodes = sb.free_inodes_count();
@@ -68,32 +75,41 @@ impl InodeAllocator {
                 continue;
             }
 
+            // 位图和块组描述符在块组锁内一起更新
+            let group_locks = sb.group_locks();
+            let _group = group_locks.lock(bgid);
+
             // 第一步：读取块组信息
-            let (free_inodes, used_dirs, bmp_blk_addr, bg_copy) = {
+            let (free_inodes, bmp_blk_addr, itable_addr, uninit) = {
                 let mut bg_ref = BlockGroupRef::get(bdev, sb, bgid)?;
                 let free = bg_ref.free_inodes_count()?;
-                let dirs = bg_ref.used_dirs_count()?;
                 let bitmap_addr = bg_ref.inode_bitmap()?;
-                let bg_data = bg_ref.get_block_group_copy()?;
-                (free, dirs, bitmap_addr, bg_data)
+                let itable = bg_ref.inode_table()?;
+                let uninit = bg_ref.has_flag(EXT4_BG_INODE_UNINIT | EXT4_BG_BLOCK_UNINIT)?;
+                (free, bitmap_addr, itable, uninit)
             };
 
             // 检查此块组是否有空闲 inode
-            if free_inodes > 0 {
+            // inode 表位于挂载时分配上限之外的块组不参与分配
+            if free_inodes > 0 && itable_addr < sb.alloc_limit() {
+                // 未初始化的块组先生成位图；与内核相同，块位图也一并初始化
+                if uninit {
+                    init_inode_bitmap(bdev, sb, bgid)?;
+                    crate::balloc::init_block_bitmap(bdev, sb, bgid)?;
+                }
+                let bg_copy = BlockGroupRef::get(bdev, sb, bgid)?.get_block_group_copy()?;
+
                 // 计算此块组中的 inode 数（后续需要使用）
                 let inodes_in_bg = inodes_in_group_cnt(sb, bgid);
 
                 // 第二步：操作 bitmap
+                let mut new_csum = 0;
                 let idx_in_bg_opt = {
+                    check_bitmap_cached(bdev, sb, bmp_blk_addr, |bitmap_data| check_bitmap_csum(sb, &bg_copy, bitmap_data, bmp_blk_addr))?;
                     let mut bitmap_block = Block::get(bdev, bmp_blk_addr)?;
 
                     // 在闭包内操作位图数据
                     bitmap_block.with_data_mut(|bitmap_data| {
-                        // 验证位图校验和（如果启用）
-                        if !verify_bitmap_csum(sb, &bg_copy, bitmap_data) {
-                            // 这里只是记录警告，不阻止操作
-                        }
-
                         // 查找第一个空闲的 inode
                         let idx_in_bg = match find_first_zero(bitmap_data, 0, inodes_in_bg) {
                             Some(idx) => idx,
@@ -105,9 +121,8 @@ impl InodeAllocator {
                             return None;
                         }
 
-                        // 更新位图校验和
-                        let mut bg_for_csum = bg_copy;
-                        set_bitmap_csum(sb, &mut bg_for_csum, bitmap_data);
+                        // 更新位图校验和（在第三步写入块组描述符）
+                        new_csum = bitmap_csum(sb, bitmap_data);
 
                         Some(idx_in_bg)
                     })?
@@ -124,11 +139,12 @@ impl InodeAllocator {
                 };
 
                 // 第三步：更新块组描述符
-                {
+                let fresh = {
                     let mut bg_ref = BlockGroupRef::get(bdev, sb, bgid)?;
 
                     // 修改文件系统计数器
                     bg_ref.dec_free_inodes(1)?;
+                    bg_ref.set_inode_bitmap_csum(new_csum)?;
 
                     // 如果是目录，增加已使用目录计数
                     if is_dir {
@@ -144,7 +160,13 @@ impl InodeAllocator {
                         bg_ref.set_itable_unused(new_unused)?;
                     }
 
-                    // bg_ref 在此处自动释放并写回
+                    // 返回 inode 是否位于未清零 inode 表的未使用区域；bg_ref 在此处自动释放并写回
+                    idx_in_bg >= free && !bg_ref.has_flag(EXT4_BG_INODE_ZEROED)?
+                };
+
+                // inode 表未清零时，从未使用过的 inode 可能是任意内容
+                if fresh {
+                    zero_inode_slot(bdev, sb, itable_addr, idx_in_bg)?;
                 }
 
                 // 更新 superblock
@@ -4,6 +4,7 @@
 
 use crate::{
     consts::*,
+    error::{Error, ErrorKind, Result},
     superblock::Superblock,
     types::ext4_group_desc,
 };
@@ -28,8 +29,8 @@ pub fn bitmap_csum(sb: &Superblock, bitmap: &[u8]) -> u32 {
 
     let inodes_per_group = sb.inodes_per_group();
 
-    // 先计算 UUID 的校验和
-    let mut csum = crate::crc::crc32c_append(0xFFFFFFFF, sb.uuid());
+    // 先计算校验和种子
+    let mut csum = sb.csum_seed();
 
     // 然后计算位图的校验和
     let bitmap_size = ((inodes_per_group + 7) / 8) as usize;
@@ -65,8 +66,8 @@ pub fn set_bitmap_csum(sb: &Superblock, bg: &mut ext4_group_desc, bitmap: &[u8])
     // 设置低 16 位
     bg.inode_bitmap_csum_lo = lo_csum.to_le();
 
-    // 如果是 64 位描述符，设置高 16 位
-    if sb.group_desc_size() == EXT4_MAX_BLOCK_GROUP_DESCRIPTOR_SIZE {
+    // 描述符包含高 16 位字段时（64 位描述符）设置高 16 位
+    if sb.group_desc_size() >= EXT4_BG_INODE_BITMAP_CSUM_HI_END {
         bg.inode_bitmap_csum_hi = hi_csum.to_le();
     }
 }
@@ -99,11 +100,11 @@ pub fn verify_bitmap_csum(sb: &Superblock, bg: &ext4_group_desc, bitmap: &[u8])
         return false;
     }
 
-    // 如果是 64 位描述符，验证高 16 位
-    if sb.group_desc_size() == EXT4_MAX_BLOCK_GROUP_DESCRIPTOR_SIZE {
-        if u16::from_le(bg.inode_bitmap_csum_hi) != hi_csum {
-            return false;
-        }
+    // 描述符包含高 16 位字段时（64 位描述符）验证高 16 位
+    if sb.group_desc_size() >= EXT4_BG_INODE_BITMAP_CSUM_HI_END
+        && u16::from_le(bg.inode_bitmap_csum_hi) != hi_csum
+    {
+        return false;
     }
 
     true
@@ -115,6 +116,33 @@ pub fn verify_bitmap_csum(_sb: &Superblock, _bg: &ext4_group_desc, _bitmap: &[u8
     true
 }
 
+/// 读取 inode 位图时检查校验和
+///
+/// 只在挂载时打开了 `FsConfig::verify_checksums` 时检查；
+/// 带 `EXT4_BG_INODE_UNINIT` 标志的块组位图尚未初始化，不做检查
+///
+/// # 错误
+///
+/// - `ErrorKind::Corrupted` - 校验和不匹配，错误中带有位图所在的块号
+pub(crate) fn check_bitmap_csum(
+    sb: &Superblock,
+    bg: &ext4_group_desc,
+    bitmap: &[u8],
+    bitmap_block: u64,
+) -> Result<()> {
+    if !sb.verify_checksums() || u16::from_le(bg.flags) & EXT4_BG_INODE_UNINIT != 0 {
+        return Ok(());
+    }
+    if !verify_bitmap_csum(sb, bg, bitmap) {
+        return Err(Error::with_block(
+            ErrorKind::Corrupted,
+            "Inode bitmap checksum mismatch",
+            bitmap_block,
+        ));
+    }
+    Ok(())
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -1,6 +1,7 @@
 //! Inode 释放功能
 
 use crate::{
+    balloc::check_bitmap_cached,
     bitmap::*,
     block::{Block, BlockDev, BlockDevice},
     block_group::BlockGroup,
@@ -31,9 +32,17 @@ pub fn free_inode<D: BlockDevice>(
     inode: u32,
     is_dir: bool,
 ) -> Result<()> {
+    sb.check_writable()?;
     // 计算块组编号
     let block_group = get_bgid_of_inode(sb, inode);
 
+    // 位图和块组描述符在块组锁内一起更新
+    let group_locks = sb.group_locks();
+    let _group = group_locks.lock(block_group);
+
+    // INODE_UNINIT 的块组先生成位图
+    super::uninit::init_inode_bitmap(bdev, sb, block_group)?;
+
     // 第一步：操作 bitmap
     // 需要先获取 bitmap 地址和块组描述符副本（用于校验和）
     let bitmap_block_addr = {
@@ -48,25 +57,19 @@ pub fn free_inode<D: BlockDevice>(
     };
 
     // 操作位图
+    let mut new_csum = 0;
     {
+        check_bitmap_cached(bdev, sb, bitmap_block_addr, |bitmap_data| check_bitmap_csum(sb, &bg_copy, bitmap_data, bitmap_block_addr))?;
         let mut bitmap_block = Block::get(bdev, bitmap_block_addr)?;
 
         // 在闭包内操作位图数据
         bitmap_block.with_data_mut(|bitmap_data| {
-            // 验证位图校验和（如果启用）
-            if !verify_bitmap_csum(sb, &bg_copy, bitmap_data) {
-                // 这里只是记录警告，不阻止操作
-                // 在实际应用中可以添加日志
-            }
-
             // 在位图中释放 inode
             let index_in_group = inode_to_bgidx(sb, inode);
             clear_bit(bitmap_data, index_in_group)?;
 
-            // 更新位图校验和
-            // 注意：这里我们需要一个可变的 BlockGroup 副本
-            let mut bg_for_csum = bg_copy;
-            set_bitmap_csum(sb, &mut bg_for_csum, bitmap_data);
+            // 更新位图校验和（在第二步写入块组描述符）
+            new_csum = bitmap_csum(sb, bitmap_data);
 
             Ok::<_, Error>(())
         })??;
@@ -84,6 +87,7 @@ pub fn free_inode<D: BlockDevice>(
 
         // 更新块组空闲 inode 计数
         bg_ref.inc_free_inodes(1)?;
+        bg_ref.set_inode_bitmap_csum(new_csum)?;
 
         // bg_ref 在此处自动释放并写回
     }
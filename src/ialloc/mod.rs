@@ -6,8 +6,12 @@ mod alloc;
 mod free;
 mod helpers;
 mod checksum;
+mod orlov;
+mod uninit;
 
 pub use alloc::*;
 pub use free::*;
 pub use helpers::*;
 pub use checksum::*;
+pub use orlov::{find_group_orlov, InodeAllocPolicy};
+pub use uninit::init_inode_bitmap;
@@ -0,0 +1,178 @@
+//! Orlov inode 分配策略
+//!
+//! 对应 Linux ext4 `ialloc.c` 中的 `find_group_orlov()` / `find_group_other()`：
+//!
+//! - 顶层目录（父目录为根目录）分散到空闲 inode 和空闲块都不低于平均值、
+//!   且目录数最少的块组，避免所有目录挤在块组 0
+//! - 其他目录优先放在父目录附近，但块组的目录数不能过多、空闲资源不能过少
+//! - 普通文件放在父目录所在块组，该组没有空闲 inode 或空闲块时向后查找
+//!
+//! 这里只负责选择起始块组，实际的位图操作仍由 [`InodeAllocator`](super::InodeAllocator) 完成。
+
+use crate::{
+    block::{BlockDev, BlockDevice},
+    consts::EXT4_ROOT_INODE,
+    error::Result,
+    fs::BlockGroupRef,
+    superblock::Superblock,
+};
+use alloc::vec::Vec;
+
+/// inode 分配策略
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum InodeAllocPolicy {
+    /// 从上次分配的块组开始，取第一个空闲 inode
+    #[default]
+    FirstFree,
+    /// Orlov 目录分散策略
+    Orlov,
+}
+
+/// 单个块组的使用情况
+#[derive(Debug, Clone, Copy)]
+struct GroupUsage {
+    free_inodes: u32,
+    free_blocks: u32,
+    used_dirs: u32,
+}
+
+/// 读取所有可分配块组的使用情况（inode 表在分配上限之外的块组为 `None`）
+fn group_usage<D: BlockDevice>(
+    bdev: &mut BlockDev<D>,
+    sb: &mut Superblock,
+) -> Result<Vec<Option<GroupUsage>>> {
+    let mut groups = Vec::with_capacity(sb.block_group_count() as usize);
+    for bgid in 0..sb.block_group_count() {
+        let mut bg_ref = BlockGroupRef::get(bdev, sb, bgid)?;
+        let usage = GroupUsage {
+            free_inodes: bg_ref.free_inodes_count()?,
+            free_blocks: bg_ref.free_blocks_count()?,
+            used_dirs: bg_ref.used_dirs_count()?,
+        };
+        let itable = bg_ref.inode_table()?;
+        drop(bg_ref);
+        groups.push((itable < sb.alloc_limit()).then_some(usage));
+    }
+    Ok(groups)
+}
+
+/// 为新 inode 选择起始块组
+///
+/// # 参数
+///
+/// * `bdev` - 块设备引用
+/// * `sb` - superblock 可变引用
+/// * `parent_inode` - 父目录的 inode 编号
+/// * `is_dir` - 新 inode 是否是目录
+///
+/// # 返回
+///
+/// 建议的起始块组；找不到合适的块组时返回父目录所在块组，
+/// 由分配器从这里开始线性查找
+pub fn find_group_orlov<D: BlockDevice>(
+    bdev: &mut BlockDev<D>,
+    sb: &mut Superblock,
+    parent_inode: u32,
+    is_dir: bool,
+) -> Result<u32> {
+    let bg_count = sb.block_group_count();
+    let parent_group = ((parent_inode.max(1) - 1) / sb.inodes_per_group()).min(bg_count - 1);
+    let groups = group_usage(bdev, sb)?;
+
+    if !is_dir {
+        return Ok(find_group_other(&groups, parent_group));
+    }
+
+    let usable = groups.iter().flatten().count().max(1) as u64;
+    let avg_free_inodes = groups.iter().flatten().map(|g| g.free_inodes as u64).sum::<u64>() / usable;
+    let avg_free_blocks = groups.iter().flatten().map(|g| g.free_blocks as u64).sum::<u64>() / usable;
+    let avg_dirs = groups.iter().flatten().map(|g| g.used_dirs as u64).sum::<u64>() / usable;
+
+    // 顶层目录：在资源充足的块组中选目录最少的
+    if parent_inode == EXT4_ROOT_INODE {
+        if let Some(group) = spread_dir_group(&groups, avg_free_inodes, avg_free_blocks) {
+            return Ok(group);
+        }
+    } else {
+        // 子目录：从父目录所在块组开始，找第一个未过载的块组
+        let max_dirs = avg_dirs + sb.inodes_per_group() as u64 / 16;
+        let min_inodes = avg_free_inodes.saturating_sub(sb.inodes_per_group() as u64 / 4).max(1);
+        let min_blocks = avg_free_blocks.saturating_sub(sb.blocks_per_group() as u64 / 4).max(1);
+
+        for i in 0..bg_count {
+            let bgid = (parent_group + i) % bg_count;
+            if let Some(g) = groups[bgid as usize] {
+                if (g.used_dirs as u64) < max_dirs
+                    && g.free_inodes as u64 >= min_inodes
+                    && g.free_blocks as u64 >= min_blocks
+                {
+                    return Ok(bgid);
+                }
+            }
+        }
+
+        if let Some(group) = spread_dir_group(&groups, avg_free_inodes, avg_free_blocks) {
+            return Ok(group);
+        }
+    }
+
+    // 资源普遍紧张：退化为任意有空闲 inode 的块组
+    Ok(find_group_other(&groups, parent_group))
+}
+
+/// 在空闲 inode 和空闲块都不低于平均值的块组中，选目录数最少的
+/// （相同时取空闲块更多的）
+fn spread_dir_group(groups: &[Option<GroupUsage>], avg_free_inodes: u64, avg_free_blocks: u64) -> Option<u32> {
+    groups
+        .iter()
+        .enumerate()
+        .filter_map(|(bgid, g)| g.map(|g| (bgid as u32, g)))
+        .filter(|(_, g)| {
+            g.free_inodes > 0
+                && g.free_inodes as u64 >= avg_free_inodes
+                && g.free_blocks as u64 >= avg_free_blocks
+        })
+        .min_by(|(_, a), (_, b)| a.used_dirs.cmp(&b.used_dirs).then(b.free_blocks.cmp(&a.free_blocks)))
+        .map(|(bgid, _)| bgid)
+}
+
+/// 普通文件：父目录所在块组优先，否则找下一个同时有空闲 inode 和空闲块的块组
+fn find_group_other(groups: &[Option<GroupUsage>], parent_group: u32) -> u32 {
+    let bg_count = groups.len() as u32;
+    for i in 0..bg_count {
+        let bgid = (parent_group + i) % bg_count;
+        if let Some(g) = groups[bgid as usize] {
+            if g.free_inodes > 0 && g.free_blocks > 0 {
+                return bgid;
+            }
+        }
+    }
+    parent_group
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn usage(free_inodes: u32, free_blocks: u32, used_dirs: u32) -> Option<GroupUsage> {
+        Some(GroupUsage { free_inodes, free_blocks, used_dirs })
+    }
+
+    #[test]
+    fn test_orlov_group_selection() {
+        let groups = [
+            usage(100, 1000, 10),
+            usage(200, 3000, 2),
+            usage(200, 3000, 1),
+            None,
+            usage(0, 0, 0),
+        ];
+
+        // 顶层目录：资源不低于平均值且目录最少
+        assert_eq!(spread_dir_group(&groups, 125, 1750), Some(2));
+
+        // 普通文件：父目录所在块组已满时向后查找并回绕
+        assert_eq!(find_group_other(&groups, 1), 1);
+        assert_eq!(find_group_other(&groups, 3), 0);
+    }
+}
@@ -0,0 +1,66 @@
+//! 未初始化块组（`EXT4_BG_INODE_UNINIT`）的 inode 位图和未清零的 inode 表
+//!
+//! 与块位图一样（见 [`crate::balloc::init_block_bitmap`]），mke2fs 不为
+//! 没有 inode 的块组写 inode 位图；使用 `lazy_itable_init` 时 inode 表也
+//! 不清零（描述符中没有 `ITABLE_ZEROED`），`itable_unused` 之后的 inode 内容未定义。
+//! 这里在第一次分配时生成位图，并在分配到从未使用过的 inode 时清零它。
+
+use crate::{
+    bitmap,
+    block::{Block, BlockDev, BlockDevice},
+    block_group::BlockGroup,
+    consts::*,
+    error::Result,
+    superblock::Superblock,
+};
+
+use super::checksum::set_bitmap_csum;
+
+/// 块组 `bgid` 带 `INODE_UNINIT` 标志时生成并写入它的 inode 位图
+///
+/// 所有 inode 都空闲，位图末尾超出每组 inode 数的填充位置 1。
+/// 返回是否进行了初始化
+pub fn init_inode_bitmap<D: BlockDevice>(
+    bdev: &mut BlockDev<D>,
+    sb: &Superblock,
+    bgid: u32,
+) -> Result<bool> {
+    let mut bg = BlockGroup::load(bdev, sb, bgid)?;
+    if !bg.has_flag(EXT4_BG_INODE_UNINIT) {
+        return Ok(false);
+    }
+
+    let block_size = sb.block_size();
+    let inodes_per_group = sb.inodes_per_group();
+    let mut bitmap = alloc::vec![0u8; block_size as usize];
+    bitmap::set_bits(&mut bitmap, inodes_per_group, block_size * 8 - inodes_per_group)?;
+
+    let bitmap_addr = bg.get_inode_bitmap(sb);
+    Block::get_noread(bdev, bitmap_addr)?.with_data_mut(|data| data.copy_from_slice(&bitmap))?;
+
+    bg.clear_flag(EXT4_BG_INODE_UNINIT);
+    set_bitmap_csum(sb, bg.inner_mut(), &bitmap);
+    bg.update_checksum(sb);
+    bg.write(bdev, sb)?;
+    log::debug!("[IALLOC] Initialized inode bitmap of uninit group {bgid}");
+    Ok(true)
+}
+
+/// 清零 inode 表中下标为 `idx_in_bg` 的 inode
+///
+/// 用于 inode 表没有 `ITABLE_ZEROED` 标志、且该 inode 位于 `itable_unused`
+/// 区域（从未使用过）的情况
+pub(super) fn zero_inode_slot<D: BlockDevice>(
+    bdev: &mut BlockDev<D>,
+    sb: &Superblock,
+    itable_addr: u64,
+    idx_in_bg: u32,
+) -> Result<()> {
+    let inode_size = sb.inode_size() as u64;
+    let block_size = sb.block_size() as u64;
+    let byte_offset = idx_in_bg as u64 * inode_size;
+    let offset = (byte_offset % block_size) as usize;
+
+    Block::get(bdev, itable_addr + byte_offset / block_size)?
+        .with_data_mut(|data| data[offset..offset + inode_size as usize].fill(0))
+}
@@ -2,7 +2,7 @@
 //!
 //! 将文件的逻辑块号映射到物理块号，支持直接块和多级间接块。
 
-use crate::block::BlockDev;
+use crate::block::{Block, BlockDev};
 use crate::consts::EXT4_INODE_DIRECT_BLOCKS;
 use crate::error::{Error, ErrorKind, Result};
 use crate::inode::Inode;
@@ -81,21 +81,13 @@ impl IndirectBlockMapper {
         inode: &Inode,
         logical_block: u64,
     ) -> Result<Option<u64>> {
-        #[cfg(feature = "std")]
-        eprintln!("[indirect] Mapping logical block {}", logical_block);
-
         // 1. 检查是否是直接块
         if logical_block < EXT4_INODE_DIRECT_BLOCKS as u64 {
-            let result = self.map_direct_block(inode, logical_block as u32);
-            #[cfg(feature = "std")]
-            eprintln!("[indirect] Direct block {} -> {:?}", logical_block, result);
-            return result;
+            return self.map_direct_block(inode, logical_block as u32);
         }
 
         // 2. 确定间接层级
         let level = self.determine_indirect_level(logical_block)?;
-        #[cfg(feature = "std")]
-        eprintln!("[indirect] Block {} is at indirect level {}", logical_block, level);
 
         // 3. 根据层级进行映射
         match level {
@@ -130,7 +122,7 @@ impl IndirectBlockMapper {
         }
 
         Err(Error::new(
-            ErrorKind::InvalidInput,
+            ErrorKind::FileTooLarge,
             "Logical block number exceeds maximum file size",
         ))
     }
@@ -267,31 +259,23 @@ impl IndirectBlockMapper {
         indirect_block: u64,
         index: u32,
     ) -> Result<Option<u64>> {
-        use alloc::vec;
-
-        // 读取间接块数据
-        let block_size = blockdev.block_size() as usize;
-        let mut buf = vec![0u8; block_size];
-        blockdev.read_blocks_direct(indirect_block, 1, &mut buf)?;
-
         // 计算指针在块内的字节偏移
         let offset = (index as usize) * 4;
 
         // 检查边界
-        if offset + 4 > buf.len() {
+        if offset + 4 > blockdev.block_size() as usize {
             return Err(Error::new(
                 ErrorKind::InvalidInput,
                 "Index out of bounds in indirect block",
             ));
         }
 
+        // 经过缓存读取间接块，尚未写回的指针修改也能看到
         // 读取 4 字节的块号（小端序）
-        let block_num = u32::from_le_bytes([
-            buf[offset],
-            buf[offset + 1],
-            buf[offset + 2],
-            buf[offset + 3],
-        ]);
+        let mut block = Block::get(blockdev, indirect_block)?;
+        let block_num = block.with_data(|buf| {
+            u32::from_le_bytes([buf[offset], buf[offset + 1], buf[offset + 2], buf[offset + 3]])
+        })?;
 
         Ok(if block_num == 0 {
             None
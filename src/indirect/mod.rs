@@ -19,5 +19,7 @@
 //! - 三级间接: [1049612, 1049612 + 1024*1024*1024)
 
 mod mapper;
+mod write;
 
 pub use mapper::IndirectBlockMapper;
+pub use write::{collect_blocks, get_or_alloc_block, remove_space};
@@ -0,0 +1,347 @@
+//! 间接块映射的写操作
+//!
+//! 为不带 EXTENTS 标志的 inode（ext2/ext3 格式）分配和释放数据块。
+//! 缺失的间接块按需分配并清零；数据块和间接块都计入 i_blocks。
+
+use crate::{
+    balloc::{self, BlockAllocator},
+    block::{Block, BlockDevice},
+    consts::EXT4_INODE_DIRECT_BLOCKS,
+    error::{Error, ErrorKind, Result},
+    fs::InodeRef,
+};
+use alloc::vec::Vec;
+
+/// 一级、二级、三级间接块在 `i_block` 中的位置
+const INDIRECT_SLOTS: [usize; 3] = [12, 13, 14];
+
+/// 逻辑块在间接映射中的位置
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+struct IndirectPath {
+    /// `i_block` 中的下标
+    slot: usize,
+    /// 间接层数（0 表示直接块）
+    depth: usize,
+    /// 每层间接块中的指针下标
+    offsets: [u32; 3],
+}
+
+/// 计算逻辑块的间接映射路径
+fn indirect_path(ptrs_per_block: u32, logical_block: u32) -> Result<IndirectPath> {
+    let direct = EXT4_INODE_DIRECT_BLOCKS as u64;
+    let p = ptrs_per_block as u64;
+    let mut lblk = logical_block as u64;
+
+    if lblk < direct {
+        return Ok(IndirectPath { slot: lblk as usize, depth: 0, offsets: [0; 3] });
+    }
+    lblk -= direct;
+
+    let mut span = 1u64;
+    for (level, &slot) in INDIRECT_SLOTS.iter().enumerate() {
+        span *= p;
+        if lblk < span {
+            let depth = level + 1;
+            let mut offsets = [0u32; 3];
+            let mut rest = lblk;
+            for i in (0..depth).rev() {
+                offsets[i] = (rest % p) as u32;
+                rest /= p;
+            }
+            return Ok(IndirectPath { slot, depth, offsets });
+        }
+        lblk -= span;
+    }
+
+    Err(Error::new(
+        ErrorKind::InvalidInput,
+        "Logical block beyond indirect mapping limit",
+    ))
+}
+
+/// 读取间接块中的全部指针
+fn read_pointers<D: BlockDevice>(inode_ref: &mut InodeRef<D>, block: u64) -> Result<Vec<u32>> {
+    Block::get(inode_ref.bdev(), block)?.with_data(|data| {
+        data.chunks_exact(4)
+            .map(|b| u32::from_le_bytes([b[0], b[1], b[2], b[3]]))
+            .collect()
+    })
+}
+
+/// 修改间接块中的一个指针
+fn write_pointer<D: BlockDevice>(
+    inode_ref: &mut InodeRef<D>,
+    block: u64,
+    index: u32,
+    value: u32,
+) -> Result<()> {
+    let offset = index as usize * 4;
+    Block::get(inode_ref.bdev(), block)?.with_data_mut(|data| {
+        data[offset..offset + 4].copy_from_slice(&value.to_le_bytes());
+    })
+}
+
+/// 分配一个块并计入 i_blocks，间接块清零
+fn alloc_one<D: BlockDevice>(inode_ref: &mut InodeRef<D>, goal: u64, zero: bool) -> Result<u32> {
+    inode_ref.check_free_blocks(1)?;
+    inode_ref.quota_check_blocks(1)?;
+
+    let (bdev, sb) = inode_ref.bdev_and_sb_mut();
+    let block = BlockAllocator::new().alloc_block(bdev, sb, goal)?.0;
+
+    // 间接映射的块指针只有 32 位
+    let Ok(block32) = u32::try_from(block) else {
+        balloc::free_block(bdev, sb, block)?;
+        return Err(Error::new(
+            ErrorKind::NoSpace,
+            "Block beyond 32-bit range for indirect mapping",
+        ));
+    };
+
+    if zero {
+        Block::get_noread(bdev, block)?.with_data_mut(|data| data.fill(0))?;
+    }
+    inode_ref.add_blocks(1)?;
+    Ok(block32)
+}
+
+/// 获取逻辑块对应的物理块，不存在时分配（包括路径上缺失的间接块）
+///
+/// 新分配的数据块不清零，与 extent 映射的文件一致
+///
+/// # 错误
+///
+/// - `ErrorKind::InvalidInput` - 逻辑块超出间接映射的范围
+/// - `ErrorKind::NoSpace` - 没有空闲块
+pub fn get_or_alloc_block<D: BlockDevice>(
+    inode_ref: &mut InodeRef<D>,
+    logical_block: u32,
+) -> Result<u64> {
+    let path = indirect_path(inode_ref.sb().block_size() / 4, logical_block)?;
+
+    // i_block 中的指针
+    let (mut current, prev) = inode_ref.with_inode(|inode| {
+        let prev = if path.slot > 0 { u32::from_le(inode.blocks[path.slot - 1]) } else { 0 };
+        (u32::from_le(inode.blocks[path.slot]), prev)
+    })?;
+    if current == 0 {
+        let goal = if prev != 0 { prev as u64 + 1 } else { 0 };
+        current = alloc_one(inode_ref, goal, path.depth > 0)?;
+        inode_ref.with_inode_mut(|inode| inode.blocks[path.slot] = current.to_le())?;
+        inode_ref.mark_dirty()?;
+    }
+
+    // 逐层向下，分配缺失的间接块和数据块
+    for level in 0..path.depth {
+        let index = path.offsets[level];
+        let pointers = read_pointers(inode_ref, current as u64)?;
+        let mut next = pointers[index as usize];
+        if next == 0 {
+            // 优先紧跟前一个指针，否则紧跟所在的间接块
+            let goal = match index {
+                0 => current as u64 + 1,
+                i => match pointers[i as usize - 1] {
+                    0 => current as u64 + 1,
+                    p => p as u64 + 1,
+                },
+            };
+            next = alloc_one(inode_ref, goal, level + 1 < path.depth)?;
+            write_pointer(inode_ref, current as u64, index, next)?;
+        }
+        current = next;
+    }
+
+    Ok(current as u64)
+}
+
+/// 待释放的连续数据块
+#[derive(Default)]
+struct FreeRun {
+    start: u64,
+    count: u32,
+}
+
+impl FreeRun {
+    fn push<D: BlockDevice>(&mut self, inode_ref: &mut InodeRef<D>, block: u64) -> Result<()> {
+        if self.count > 0 && self.start + self.count as u64 == block {
+            self.count += 1;
+            return Ok(());
+        }
+        self.flush(inode_ref)?;
+        self.start = block;
+        self.count = 1;
+        Ok(())
+    }
+
+    fn flush<D: BlockDevice>(&mut self, inode_ref: &mut InodeRef<D>) -> Result<()> {
+        if self.count == 0 {
+            return Ok(());
+        }
+        let (bdev, sb) = inode_ref.bdev_and_sb_mut();
+        balloc::free_blocks(bdev, sb, self.start, self.count)?;
+        inode_ref.sub_blocks(self.count)?;
+        self.count = 0;
+        Ok(())
+    }
+}
+
+/// 收集 inode 映射的所有块，返回 `(数据块, 间接块)`
+///
+/// 读取的是块缓存中的间接块，包含尚未写回设备的修改
+pub fn collect_blocks<D: BlockDevice>(inode_ref: &mut InodeRef<D>) -> Result<(Vec<u64>, Vec<u64>)> {
+    let blocks = inode_ref.with_inode(|inode| inode.blocks.map(u32::from_le))?;
+    let mut data = Vec::new();
+    let mut meta = Vec::new();
+
+    for &block in blocks.iter().take(EXT4_INODE_DIRECT_BLOCKS) {
+        if block != 0 {
+            data.push(block as u64);
+        }
+    }
+    for (level, &slot) in INDIRECT_SLOTS.iter().enumerate() {
+        if blocks[slot] != 0 {
+            collect_in_tree(inode_ref, blocks[slot], level + 1, &mut data, &mut meta)?;
+        }
+    }
+    Ok((data, meta))
+}
+
+/// 收集间接块 `block`（`depth` 层）及其下的所有块
+fn collect_in_tree<D: BlockDevice>(
+    inode_ref: &mut InodeRef<D>,
+    block: u32,
+    depth: usize,
+    data: &mut Vec<u64>,
+    meta: &mut Vec<u64>,
+) -> Result<()> {
+    meta.push(block as u64);
+    for ptr in read_pointers(inode_ref, block as u64)? {
+        if ptr == 0 {
+            continue;
+        }
+        if depth == 1 {
+            data.push(ptr as u64);
+        } else {
+            collect_in_tree(inode_ref, ptr, depth - 1, data, meta)?;
+        }
+    }
+    Ok(())
+}
+
+/// 释放逻辑块 `[from, to]`（包含）映射的数据块
+///
+/// 变空的间接块一并释放并清除上层指针；空洞直接跳过。
+pub fn remove_space<D: BlockDevice>(inode_ref: &mut InodeRef<D>, from: u32, to: u32) -> Result<()> {
+    if from > to {
+        return Ok(());
+    }
+    let p = (inode_ref.sb().block_size() / 4) as u64;
+    let (from, to) = (from as u64, to as u64);
+    let blocks = inode_ref.with_inode(|inode| inode.blocks.map(u32::from_le))?;
+    let mut run = FreeRun::default();
+
+    // 直接块
+    for (slot, &block) in blocks.iter().enumerate().take(EXT4_INODE_DIRECT_BLOCKS) {
+        let lblk = slot as u64;
+        if block != 0 && lblk >= from && lblk <= to {
+            run.push(inode_ref, block as u64)?;
+            inode_ref.with_inode_mut(|inode| inode.blocks[slot] = 0)?;
+        }
+    }
+
+    // 各级间接块
+    let mut base = EXT4_INODE_DIRECT_BLOCKS as u64;
+    let mut span = 1u64;
+    for (level, &slot) in INDIRECT_SLOTS.iter().enumerate() {
+        span *= p;
+        let block = blocks[slot];
+        if block != 0 && base <= to && base + span > from {
+            let emptied = remove_in_tree(inode_ref, &mut run, block, level + 1, base, from, to)?;
+            if emptied {
+                inode_ref.with_inode_mut(|inode| inode.blocks[slot] = 0)?;
+            }
+        }
+        base += span;
+    }
+
+    run.flush(inode_ref)?;
+    inode_ref.mark_dirty()
+}
+
+/// 释放间接块 `block`（覆盖从 `base` 开始的逻辑块）下与 `[from, to]` 相交的部分
+///
+/// 返回该间接块是否已变空并被释放
+fn remove_in_tree<D: BlockDevice>(
+    inode_ref: &mut InodeRef<D>,
+    run: &mut FreeRun,
+    block: u32,
+    depth: usize,
+    base: u64,
+    from: u64,
+    to: u64,
+) -> Result<bool> {
+    let p = (inode_ref.sb().block_size() / 4) as u64;
+    let child_span = p.pow(depth as u32 - 1);
+    let mut pointers = read_pointers(inode_ref, block as u64)?;
+    let mut changed = false;
+
+    for (i, ptr) in pointers.iter_mut().enumerate() {
+        let child_base = base + i as u64 * child_span;
+        if *ptr == 0 || child_base > to || child_base + child_span <= from {
+            continue;
+        }
+        let emptied = if depth == 1 {
+            run.push(inode_ref, *ptr as u64)?;
+            true
+        } else {
+            remove_in_tree(inode_ref, run, *ptr, depth - 1, child_base, from, to)?
+        };
+        if emptied {
+            *ptr = 0;
+            changed = true;
+        }
+    }
+
+    if pointers.iter().all(|&ptr| ptr == 0) {
+        let (bdev, sb) = inode_ref.bdev_and_sb_mut();
+        balloc::free_block(bdev, sb, block as u64)?;
+        inode_ref.sub_blocks(1)?;
+        return Ok(true);
+    }
+
+    if changed {
+        Block::get(inode_ref.bdev(), block as u64)?.with_data_mut(|data| {
+            for (dst, ptr) in data.chunks_exact_mut(4).zip(&pointers) {
+                dst.copy_from_slice(&ptr.to_le_bytes());
+            }
+        })?;
+    }
+    Ok(false)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_indirect_path() {
+        // 4K 块：每个间接块 1024 个指针
+        let path = |lblk| indirect_path(1024, lblk).unwrap();
+
+        assert_eq!(path(5), IndirectPath { slot: 5, depth: 0, offsets: [0; 3] });
+        assert_eq!(path(12), IndirectPath { slot: 12, depth: 1, offsets: [0, 0, 0] });
+        assert_eq!(path(1035), IndirectPath { slot: 12, depth: 1, offsets: [1023, 0, 0] });
+        assert_eq!(path(1036), IndirectPath { slot: 13, depth: 2, offsets: [0, 0, 0] });
+        assert_eq!(path(1036 + 1024 + 7), IndirectPath { slot: 13, depth: 2, offsets: [1, 7, 0] });
+        assert_eq!(path(1049612), IndirectPath { slot: 14, depth: 3, offsets: [0, 0, 0] });
+        assert_eq!(
+            path(1049612 + 2 * 1024 * 1024 + 3 * 1024 + 4),
+            IndirectPath { slot: 14, depth: 3, offsets: [2, 3, 4] }
+        );
+
+        // 1K 块：三级间接的上限为 12 + 256 + 256^2 + 256^3
+        let limit = 12 + 256 + 256 * 256 + 256 * 256 * 256;
+        assert!(indirect_path(256, limit - 1).is_ok());
+        assert_eq!(indirect_path(256, limit).unwrap_err().kind(), ErrorKind::InvalidInput);
+    }
+}
@@ -6,7 +6,6 @@ use crate::{
     consts::{EXT4_FEATURE_RO_COMPAT_METADATA_CSUM, EXT4_GOOD_OLD_INODE_SIZE},
     superblock::Superblock,
     types::ext4_inode,
-    crc::EXT4_CRC32_INIT,
 };
 
 /// 获取 inode 校验和
@@ -52,6 +51,8 @@ pub fn set_checksum(sb: &Superblock, inode: &mut ext4_inode, checksum: u32) {
 
 /// 计算 inode 的 CRC32C 校验和
 ///
+/// 对应内核的 `ext4_inode_csum()`
+///
 /// # 参数
 ///
 /// * `sb` - superblock 引用
@@ -62,59 +63,63 @@ pub fn set_checksum(sb: &Superblock, inode: &mut ext4_inode, checksum: u32) {
 ///
 /// 32 位 CRC32C 校验和
 pub fn compute_checksum(sb: &Superblock, inode_num: u32, inode: &ext4_inode) -> u32 {
+    // ext4_inode 结构体可能短于磁盘上的 inode，超出部分按 0 计算
     let inode_size = sb.inode_size() as usize;
-
-    // 将 inode 转换为字节切片
+    let mut raw = alloc::vec![0u8; inode_size];
+    let len = inode_size.min(core::mem::size_of::<ext4_inode>());
     let inode_bytes = unsafe {
-        core::slice::from_raw_parts(
-            inode as *const ext4_inode as *const u8,
-            inode_size,
-        )
+        core::slice::from_raw_parts(inode as *const ext4_inode as *const u8, len)
     };
+    raw[..len].copy_from_slice(inode_bytes);
 
-    // 初始化 CRC，包含 inode 编号
-    let inode_num_bytes = inode_num.to_le_bytes();
-    let mut crc = crate::crc::crc32c_append(EXT4_CRC32_INIT, &inode_num_bytes);
-
-    // 计算 checksum_lo 字段的偏移量
-    let checksum_lo_offset = offset_of_checksum_lo();
-
-    // 计算到 checksum_lo 之前的数据
-    if checksum_lo_offset > 0 {
-        crc = crate::crc::crc32c_append(crc, &inode_bytes[..checksum_lo_offset]);
-    }
+    compute_checksum_raw(sb, inode_num, &raw)
+}
 
-    // checksum_lo 字段应该被视为0（2字节）
+/// 根据 inode 表中的原始字节计算 inode 的 CRC32C 校验和
+///
+/// 种子为 [`Superblock::inode_csum_seed`]，校验和字段本身按 0 计算。
+/// `raw` 的长度应为 inode 大小。
+pub fn compute_checksum_raw(sb: &Superblock, inode_num: u32, raw: &[u8]) -> u32 {
+    let generation = u32::from_le_bytes([raw[100], raw[101], raw[102], raw[103]]);
     let zero_bytes = [0u8; 2];
-    crc = crate::crc::crc32c_append(crc, &zero_bytes);
 
-    let after_checksum_lo = checksum_lo_offset + 2;
+    let checksum_lo_offset = offset_of_checksum_lo();
+    let mut crc = sb.inode_csum_seed(inode_num, generation);
+    crc = crate::crc::crc32c_append(crc, &raw[..checksum_lo_offset]);
+    crc = crate::crc::crc32c_append(crc, &zero_bytes);
+    crc = crate::crc::crc32c_append(crc, &raw[checksum_lo_offset + 2..EXT4_GOOD_OLD_INODE_SIZE]);
 
-    if inode_size > EXT4_GOOD_OLD_INODE_SIZE {
-        // 对于扩展 inode，需要处理 checksum_hi 字段
+    if raw.len() > EXT4_GOOD_OLD_INODE_SIZE {
         let checksum_hi_offset = offset_of_checksum_hi();
-
-        // 计算 checksum_lo 之后到 checksum_hi 之前的数据
-        if checksum_hi_offset > after_checksum_lo {
-            crc = crate::crc::crc32c_append(crc, &inode_bytes[after_checksum_lo..checksum_hi_offset]);
+        crc = crate::crc::crc32c_append(crc, &raw[EXT4_GOOD_OLD_INODE_SIZE..checksum_hi_offset]);
+        if checksum_hi_fits(raw) {
+            crc = crate::crc::crc32c_append(crc, &zero_bytes);
+        } else {
+            crc = crate::crc::crc32c_append(crc, &raw[checksum_hi_offset..checksum_hi_offset + 2]);
         }
+        crc = crate::crc::crc32c_append(crc, &raw[checksum_hi_offset + 2..]);
+    }
 
-        // checksum_hi 字段应该被视为0（2字节）
-        crc = crate::crc::crc32c_append(crc, &zero_bytes);
+    crc
+}
 
-        // 计算 checksum_hi 之后的数据
-        let after_checksum_hi = checksum_hi_offset + 2;
-        if inode_size > after_checksum_hi {
-            crc = crate::crc::crc32c_append(crc, &inode_bytes[after_checksum_hi..inode_size]);
-        }
-    } else {
-        // 对于标准 inode，计算 checksum_lo 之后的所有数据
-        if inode_size > after_checksum_lo {
-            crc = crate::crc::crc32c_append(crc, &inode_bytes[after_checksum_lo..inode_size]);
-        }
+/// 根据 inode 表中的原始字节重新计算并写入 inode 校验和
+///
+/// 未启用 METADATA_CSUM 时不做任何修改；`i_extra_isize` 容纳不下
+/// `i_checksum_hi` 时只写低 16 位
+pub fn set_checksum_raw(sb: &Superblock, inode_num: u32, raw: &mut [u8]) {
+    if !sb.has_ro_compat_feature(EXT4_FEATURE_RO_COMPAT_METADATA_CSUM) {
+        return;
     }
 
-    crc
+    let checksum = compute_checksum_raw(sb, inode_num, raw);
+    let checksum_lo_offset = offset_of_checksum_lo();
+    raw[checksum_lo_offset..checksum_lo_offset + 2].copy_from_slice(&(checksum as u16).to_le_bytes());
+    if checksum_hi_fits(raw) {
+        let checksum_hi_offset = offset_of_checksum_hi();
+        raw[checksum_hi_offset..checksum_hi_offset + 2]
+            .copy_from_slice(&((checksum >> 16) as u16).to_le_bytes());
+    }
 }
 
 /// 验证 inode 校验和
@@ -134,12 +139,50 @@ pub fn verify_checksum(sb: &Superblock, inode_num: u32, inode: &ext4_inode) -> b
         return true;
     }
 
-    let stored = get_checksum(sb, inode);
-    let computed = compute_checksum(sb, inode_num, inode);
+    let inode_size = sb.inode_size() as usize;
+    let mut raw = alloc::vec![0u8; inode_size];
+    let len = inode_size.min(core::mem::size_of::<ext4_inode>());
+    let inode_bytes = unsafe {
+        core::slice::from_raw_parts(inode as *const ext4_inode as *const u8, len)
+    };
+    raw[..len].copy_from_slice(inode_bytes);
+
+    verify_checksum_raw(sb, inode_num, &raw)
+}
+
+/// 根据 inode 表中的原始字节验证 inode 校验和
+///
+/// 对应内核的 `ext4_inode_csum_verify()`
+///
+/// `i_extra_isize` 容纳不下 `i_checksum_hi` 时只比较低 16 位
+pub fn verify_checksum_raw(sb: &Superblock, inode_num: u32, raw: &[u8]) -> bool {
+    if !sb.has_ro_compat_feature(EXT4_FEATURE_RO_COMPAT_METADATA_CSUM) {
+        return true;
+    }
+
+    let checksum_lo_offset = offset_of_checksum_lo();
+    let mut stored = u16::from_le_bytes([raw[checksum_lo_offset], raw[checksum_lo_offset + 1]]) as u32;
+    let mut computed = compute_checksum_raw(sb, inode_num, raw);
+
+    if checksum_hi_fits(raw) {
+        let checksum_hi_offset = offset_of_checksum_hi();
+        stored |= (u16::from_le_bytes([raw[checksum_hi_offset], raw[checksum_hi_offset + 1]]) as u32) << 16;
+    } else {
+        computed &= 0xFFFF;
+    }
 
     stored == computed
 }
 
+/// `i_checksum_hi` 是否位于 `i_extra_isize` 覆盖的范围内
+fn checksum_hi_fits(raw: &[u8]) -> bool {
+    if raw.len() <= EXT4_GOOD_OLD_INODE_SIZE {
+        return false;
+    }
+    let extra_isize = u16::from_le_bytes([raw[128], raw[129]]) as usize;
+    EXT4_GOOD_OLD_INODE_SIZE + extra_isize >= offset_of_checksum_hi() + 2
+}
+
 /// 获取 checksum_lo 字段的偏移量
 ///
 /// 在 ext4_inode 结构中的位置
@@ -184,7 +227,7 @@ mod tests {
         sb.feature_ro_compat = EXT4_FEATURE_RO_COMPAT_METADATA_CSUM.to_le();
 
         let superblock = Superblock::new(sb);
-        let mut inode = ext4_inode::default();
+        let mut inode = ext4_inode { extra_isize: 32u16.to_le(), ..Default::default() };
 
         // 计算并设置校验和
         let csum = compute_checksum(&superblock, 1, &inode);
@@ -202,7 +245,7 @@ mod tests {
         sb.feature_ro_compat = EXT4_FEATURE_RO_COMPAT_METADATA_CSUM.to_le();
 
         let superblock = Superblock::new(sb);
-        let mut inode = ext4_inode::default();
+        let mut inode = ext4_inode { extra_isize: 32u16.to_le(), ..Default::default() };
 
         // 设置正确的校验和
         let csum = compute_checksum(&superblock, 1, &inode);
@@ -233,4 +276,42 @@ mod tests {
         // 读取应该相同
         assert_eq!(get_checksum(&superblock, &inode), 0x12345678);
     }
+
+    #[test]
+    fn test_checksum_raw_hi_not_in_extra_isize() {
+        let superblock = Superblock::new(ext4_sblock {
+            magic: crate::consts::EXT4_SUPERBLOCK_MAGIC.to_le(),
+            inode_size: 256u16.to_le(),
+            feature_ro_compat: EXT4_FEATURE_RO_COMPAT_METADATA_CSUM.to_le(),
+            ..Default::default()
+        });
+
+        // i_extra_isize 为 0：i_checksum_hi 属于普通数据，只比较低 16 位
+        let mut raw = alloc::vec![0u8; 256];
+        raw[130] = 0xab;
+        let csum = compute_checksum_raw(&superblock, 12, &raw);
+        raw[124..126].copy_from_slice(&(csum as u16).to_le_bytes());
+        assert!(verify_checksum_raw(&superblock, 12, &raw));
+
+        raw[130] = 0xac;
+        assert!(!verify_checksum_raw(&superblock, 12, &raw));
+    }
+
+    #[test]
+    fn test_set_checksum_raw() {
+        let superblock = Superblock::new(ext4_sblock {
+            magic: crate::consts::EXT4_SUPERBLOCK_MAGIC.to_le(),
+            inode_size: 256u16.to_le(),
+            feature_ro_compat: EXT4_FEATURE_RO_COMPAT_METADATA_CSUM.to_le(),
+            ..Default::default()
+        });
+
+        let mut raw = alloc::vec![0u8; 256];
+        raw[128..130].copy_from_slice(&32u16.to_le_bytes());
+        raw[200] = 0x5a;
+        set_checksum_raw(&superblock, 12, &mut raw);
+        assert!(verify_checksum_raw(&superblock, 12, &raw));
+        // 换成其他 inode 号后校验和不再匹配
+        assert!(!verify_checksum_raw(&superblock, 13, &raw));
+    }
 }
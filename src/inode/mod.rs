@@ -8,6 +8,7 @@
 mod read;
 mod write;
 pub mod checksum;
+pub(crate) mod time;
 
 pub use read::*;
 pub use write::*;
@@ -0,0 +1,180 @@
+//! inode 时间戳编码
+//!
+//! 128 字节之外有额外空间（`i_extra_isize` 足够大）时，每个时间戳有一个
+//! `*_extra` 字段，低 2 位扩展秒数的纪元（epoch），高 30 位是纳秒：
+//!
+//! ```text
+//! 秒 = (i32)i_xtime + ((i_xtime_extra & 3) << 32)
+//! 纳秒 = i_xtime_extra >> 2
+//! ```
+//!
+//! 可表示的范围是 1901-12-13 到 2446-05-10。没有额外字段时只能保存
+//! 32 位有符号秒数（到 2038 年），超出范围的值被截断到边界，纳秒被丢弃。
+//! 编码方式与内核 `ext4_encode_extra_time` / `ext4_decode_extra_time` 相同。
+//!
+//! 创建时间 `i_crtime` 本身也位于额外空间，没有额外空间的 inode 没有创建时间。
+
+use crate::{consts::EXT4_GOOD_OLD_INODE_SIZE, types::ext4_inode};
+
+/// `*_extra` 中纪元所占的位数
+const EXT4_EPOCH_BITS: u32 = 2;
+/// `*_extra` 中纪元的掩码
+const EXT4_EPOCH_MASK: u32 = (1 << EXT4_EPOCH_BITS) - 1;
+/// 有额外字段时可表示的最大秒数
+const EXT4_EXTRA_TIMESTAMP_MAX: i64 = (EXT4_EPOCH_MASK as i64) << 32 | i32::MAX as i64;
+/// 每秒的纳秒数
+pub(crate) const NSEC_PER_SEC: u32 = 1_000_000_000;
+
+/// inode 中的时间戳
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) enum InodeTime {
+    /// `i_atime`
+    Access,
+    /// `i_mtime`
+    Modify,
+    /// `i_ctime`
+    Change,
+    /// `i_crtime`（创建时间）
+    Create,
+}
+
+impl InodeTime {
+    /// 额外字段的结束偏移，`128 + i_extra_isize` 不小于它时该字段有效
+    const fn extra_end(self) -> usize {
+        match self {
+            InodeTime::Change => 136,
+            InodeTime::Modify => 140,
+            InodeTime::Access => 144,
+            InodeTime::Create => 152,
+        }
+    }
+
+    /// `(秒字段, 额外字段)` 的可变引用
+    fn fields(self, inode: &mut ext4_inode) -> (&mut u32, &mut u32) {
+        match self {
+            InodeTime::Access => (&mut inode.atime, &mut inode.atime_extra),
+            InodeTime::Modify => (&mut inode.mtime, &mut inode.mtime_extra),
+            InodeTime::Change => (&mut inode.ctime, &mut inode.ctime_extra),
+            InodeTime::Create => (&mut inode.crtime, &mut inode.crtime_extra),
+        }
+    }
+}
+
+/// 额外字段是否位于 inode 的有效额外空间内
+///
+/// `extra_isize` 为 inode 的 `i_extra_isize`，`inode_size` 为 superblock 中的 inode 大小
+fn has_extra(which: InodeTime, inode_size: usize, extra_isize: usize) -> bool {
+    inode_size > EXT4_GOOD_OLD_INODE_SIZE && EXT4_GOOD_OLD_INODE_SIZE + extra_isize >= which.extra_end()
+}
+
+/// 把秒和纳秒编码为 `(i_xtime, i_xtime_extra)`
+///
+/// 秒数超出可表示范围时截断到边界
+pub(crate) fn encode_time(sec: i64, nsec: u32) -> (u32, u32) {
+    let sec = sec.clamp(i32::MIN as i64, EXT4_EXTRA_TIMESTAMP_MAX);
+    let epoch = ((sec - sec as i32 as i64) >> 32) as u32 & EXT4_EPOCH_MASK;
+    (sec as u32, (nsec << EXT4_EPOCH_BITS) | epoch)
+}
+
+/// 从 `(i_xtime, i_xtime_extra)` 解码秒和纳秒
+pub(crate) fn decode_time(lo: u32, extra: u32) -> (i64, u32) {
+    let sec = lo as i32 as i64 + (((extra & EXT4_EPOCH_MASK) as i64) << 32);
+    (sec, extra >> EXT4_EPOCH_BITS)
+}
+
+/// 读取时间戳
+///
+/// 没有额外字段时纳秒为 0；创建时间没有存储空间时返回 `None`
+pub(crate) fn get_time(inode: &ext4_inode, which: InodeTime, inode_size: usize) -> Option<(i64, u32)> {
+    let extra_isize = u16::from_le(inode.extra_isize) as usize;
+    let (lo, extra) = match which {
+        InodeTime::Access => (inode.atime, inode.atime_extra),
+        InodeTime::Modify => (inode.mtime, inode.mtime_extra),
+        InodeTime::Change => (inode.ctime, inode.ctime_extra),
+        InodeTime::Create => (inode.crtime, inode.crtime_extra),
+    };
+    if has_extra(which, inode_size, extra_isize) {
+        Some(decode_time(u32::from_le(lo), u32::from_le(extra)))
+    } else if which == InodeTime::Create {
+        None
+    } else {
+        Some((u32::from_le(lo) as i32 as i64, 0))
+    }
+}
+
+/// 写入时间戳
+///
+/// 没有额外字段时秒数截断到 32 位有符号范围并丢弃纳秒。
+/// 创建时间没有存储空间时不修改 inode，返回 `false`
+pub(crate) fn set_time(inode: &mut ext4_inode, which: InodeTime, inode_size: usize, sec: i64, nsec: u32) -> bool {
+    let extra_isize = u16::from_le(inode.extra_isize) as usize;
+    let with_extra = has_extra(which, inode_size, extra_isize);
+    if !with_extra && which == InodeTime::Create {
+        return false;
+    }
+
+    let (lo, extra) = which.fields(inode);
+    if with_extra {
+        let (sec_lo, sec_extra) = encode_time(sec, nsec);
+        *lo = sec_lo.to_le();
+        *extra = sec_extra.to_le();
+    } else {
+        *lo = (sec.clamp(i32::MIN as i64, i32::MAX as i64) as i32 as u32).to_le();
+    }
+    true
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_encode_decode() {
+        for (sec, nsec) in [
+            (0, 0),
+            (1_700_000_000, 123_456_789),
+            (-1, 999_999_999),
+            (i32::MIN as i64, 0),
+            // 2038 年之后
+            (i32::MAX as i64 + 1, 1),
+            (4_000_000_000, 500),
+            (EXT4_EXTRA_TIMESTAMP_MAX, NSEC_PER_SEC - 1),
+        ] {
+            let (lo, extra) = encode_time(sec, nsec);
+            assert_eq!(decode_time(lo, extra), (sec, nsec), "sec={sec}");
+        }
+
+        // 与内核相同：2038 年之后的第一秒使用纪元 1
+        assert_eq!(encode_time(1 << 31, 0), (0x8000_0000, 1));
+        // 1970 年之前纪元为 0
+        assert_eq!(encode_time(-1, 0), (0xffff_ffff, 0));
+
+        // 超出范围时截断
+        assert_eq!(decode_time(encode_time(i64::MAX, 0).0, encode_time(i64::MAX, 0).1).0, EXT4_EXTRA_TIMESTAMP_MAX);
+        assert_eq!(decode_time(encode_time(i64::MIN, 0).0, encode_time(i64::MIN, 0).1).0, i32::MIN as i64);
+    }
+
+    #[test]
+    fn test_get_set_time() {
+        let mut inode = ext4_inode { extra_isize: 32u16.to_le(), ..Default::default() };
+
+        assert!(set_time(&mut inode, InodeTime::Modify, 256, 5_000_000_000, 42));
+        assert_eq!(get_time(&inode, InodeTime::Modify, 256), Some((5_000_000_000, 42)));
+        assert!(set_time(&mut inode, InodeTime::Create, 256, 100, 7));
+        assert_eq!(get_time(&inode, InodeTime::Create, 256), Some((100, 7)));
+
+        // 128 字节 inode：没有纳秒和创建时间，秒数截断到 2038 年
+        let mut small = ext4_inode::default();
+        assert!(set_time(&mut small, InodeTime::Access, 128, 5_000_000_000, 42));
+        assert_eq!(get_time(&small, InodeTime::Access, 128), Some((i32::MAX as i64, 0)));
+        assert!(!set_time(&mut small, InodeTime::Create, 128, 100, 0));
+        assert_eq!(get_time(&small, InodeTime::Create, 128), None);
+
+        // i_extra_isize 只覆盖到 ctime_extra
+        let mut partial = ext4_inode { extra_isize: 8u16.to_le(), ..Default::default() };
+        assert!(set_time(&mut partial, InodeTime::Change, 256, -5, 9));
+        assert_eq!(get_time(&partial, InodeTime::Change, 256), Some((-5, 9)));
+        assert!(set_time(&mut partial, InodeTime::Modify, 256, 10, 9));
+        assert_eq!(get_time(&partial, InodeTime::Modify, 256), Some((10, 0)));
+    }
+}
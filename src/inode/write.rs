@@ -327,6 +327,7 @@ impl super::Inode {
     /// # 返回
     ///
     /// 成功返回 `Ok(())`，如果count超出支持范围返回错误
+    /// （未启用 `HUGE_FILE` 特性时为 `InvalidInput`，换算后仍超出 48 位时为 `FileTooLarge`）
     pub fn set_blocks_count(&mut self, sb: &Superblock, count: u64) -> Result<()> {
         use crate::error::{Error, ErrorKind};
 
@@ -362,10 +363,15 @@ impl super::Inode {
             let block_size = sb.block_size();
             let block_bits = inode_block_bits_count(block_size);
 
-            self.set_flag(EXT4_INODE_FLAG_HUGE_FILE);
-
             // 从 512 字节单位转换为文件系统块单位
             let scaled_count = count >> (block_bits - 9);
+            if scaled_count > max_48bit {
+                return Err(Error::new(
+                    ErrorKind::FileTooLarge,
+                    "Block count exceeds 48 bits",
+                ));
+            }
+            self.set_flag(EXT4_INODE_FLAG_HUGE_FILE);
             self.inner.blocks_count_lo = (scaled_count as u32).to_le();
             self.inner.blocks_high = ((scaled_count >> 32) as u16).to_le();
         }
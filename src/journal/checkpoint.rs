@@ -38,6 +38,8 @@ pub fn do_checkpoint<D: BlockDevice>(
     bdev: &mut BlockDev<D>,
     superblock: &mut Superblock,
 ) -> Result<()> {
+    superblock.check_writable()?;
+
     // 如果检查点队列为空，直接返回
     if jbd_journal.checkpoint_queue_len() == 0 {
         return Ok(());
@@ -85,6 +87,9 @@ pub fn do_checkpoint<D: BlockDevice>(
 
     // 如果有事务被检查点，更新 journal superblock
     if completed_transactions > 0 {
+        // 写回原位置的块落盘之后才能推进日志起点，释放这些事务占用的日志空间
+        bdev.barrier()?;
+
         // 计算新的 start 位置
         update_journal_start(jbd_fs, jbd_journal)?;
         jbd_fs.mark_dirty();
@@ -152,31 +157,26 @@ fn checkpoint_transaction<D: BlockDevice>(
     bdev: &mut BlockDev<D>,
     superblock: &mut Superblock,
 ) -> Result<()> {
-    // 注意：在 ordered 或 writeback 模式下，数据可能已经在 commit 之前写入
-    // 在 journal 模式下，我们需要从 journal 读取并写回到文件系统
-
-    // 计算 journal 中数据块的起始位置
-    // 跳过 descriptor blocks
-    let mut current_jblock = trans.start_iblock;
-
-    // 计算这个事务使用了多少个 descriptor blocks
-    let data_blocks = trans.buffer_count() as u32;
-    let descriptor_blocks = calculate_descriptor_blocks_count(data_blocks, jbd_fs.block_size());
-
-    // 跳过 descriptor blocks，从第一个数据块开始
-    current_jblock += descriptor_blocks;
+    // 文件数据块不在 buf_queue 中：ordered 模式下已经在 commit 之前写回，
+    // writeback 模式下由缓存自行写回；这里只需要写回记入日志的元数据块
 
     // 遍历所有缓冲区，从 journal 读取并写回到文件系统
+    // （commit 时记录了每个缓冲区在 journal 中的位置）
     for buf in &trans.buf_queue {
         // 获取 journal 中这个数据块的物理位置
-        let journal_phys_block = jbd_fs.inode_bmap(bdev, superblock, current_jblock)?;
+        let journal_phys_block = jbd_fs.inode_bmap(bdev, superblock, buf.jbd_lba())?;
 
         // 从 journal 读取数据
-        let journal_data = {
+        let mut journal_data = {
             let mut journal_block = Block::get(bdev, journal_phys_block)?;
             journal_block.with_data(|data| Ok::<_, Error>(data.to_vec()))?
         }?;
 
+        // 恢复写入日志时清零的 magic
+        if buf.escaped {
+            journal_data[..4].copy_from_slice(&JBD_MAGIC_NUMBER.to_be_bytes());
+        }
+
         // 写回到文件系统的目标位置
         let fs_target_block = buf.fs_lba();
         let mut fs_block = Block::get(bdev, fs_target_block)?;
@@ -184,38 +184,11 @@ fn checkpoint_transaction<D: BlockDevice>(
             let len = journal_data.len().min(data.len());
             data[..len].copy_from_slice(&journal_data[..len]);
         })?;
-
-        // 移动到下一个 journal 块
-        current_jblock = next_journal_block(current_jblock, jbd_fs.first(), jbd_fs.max_len());
     }
 
     Ok(())
 }
 
-/// 计算需要多少个 descriptor blocks（与 commit.rs 保持一致）
-fn calculate_descriptor_blocks_count(data_blocks: u32, block_size: u32) -> u32 {
-    let tag_size = core::mem::size_of::<jbd_block_tag>() as u32;
-    let header_size = core::mem::size_of::<jbd_bhdr>() as u32;
-    let tags_per_block = (block_size - header_size) / tag_size;
-
-    if data_blocks == 0 {
-        0
-    } else {
-        (data_blocks + tags_per_block - 1) / tags_per_block
-    }
-}
-
-/// 计算下一个 journal 块号（处理环形）
-fn next_journal_block(current: u32, first: u32, max_len: u32) -> u32 {
-    let next = current + 1;
-    let last = first + max_len;
-    if next >= last {
-        first
-    } else {
-        next
-    }
-}
-
 /// 更新 journal superblock 的 start 指针
 ///
 /// # 参数
@@ -226,7 +199,8 @@ fn next_journal_block(current: u32, first: u32, max_len: u32) -> u32 {
 /// # 说明
 ///
 /// 在检查点完成后，更新 journal 的 start 指针，
-/// 以释放已经检查点的空间供新事务使用
+/// 以释放已经检查点的空间供新事务使用。序列号同时更新为
+/// start 处事务的序列号，恢复时从这里开始匹配
 fn update_journal_start(
     jbd_fs: &mut JbdFs,
     jbd_journal: &JbdJournal,
@@ -235,10 +209,12 @@ fn update_journal_start(
     if jbd_journal.checkpoint_queue_len() == 0 {
         let new_start = jbd_journal.start;
         jbd_fs.set_start(new_start);
+        jbd_fs.set_sequence(jbd_journal.trans_id as u32);
     } else {
         // 否则，将 start 设置为第一个未检查点事务的起始位置
         if let Some(first_trans) = jbd_journal.cp_queue.front() {
             jbd_fs.set_start(first_trans.start_iblock);
+            jbd_fs.set_sequence(first_trans.trans_id as u32);
         }
     }
 
@@ -433,5 +409,6 @@ mod tests {
         // 空队列时，应该设置为当前 journal start
         update_journal_start(&mut jbd_fs, &jbd_journal).unwrap();
         assert_eq!(jbd_fs.start(), jbd_journal.start);
+        assert_eq!(jbd_fs.sequence() as u64, jbd_journal.trans_id);
     }
 }
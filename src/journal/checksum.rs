@@ -1,11 +1,16 @@
 //! Journal 校验和计算
 //!
-//! 对应 lwext4 的 journal checksum 功能
+//! 对应 lwext4 的 journal checksum 功能，格式与内核 JBD2 一致：
+//!
+//! - v2/v3（`CSUM_V2`/`CSUM_V3`）：descriptor、revoke、commit 块对整个块计算
+//!   crc32c（种子为 `crc32c(uuid)`，校验和字段按 0 计算）；每个数据块的
+//!   crc32c（含事务序列号）存放在对应的 block tag 中，v2 只保留低 16 位
+//! - v1（`COMPAT_CHECKSUM`）：事务中所有 descriptor 块和数据块的大端 CRC32
+//!   累加值存放在 commit 块中，见 [`crate::crc::crc32_be`]
 
 use super::types::*;
-use crate::error::Result;
 
-/// 计算 journal block 的 CRC32C 校验和
+/// 计算 journal 数据块的 CRC32C 校验和（block tag 中的校验和）
 ///
 /// 对应 lwext4 的 `jbd_block_csum()`
 ///
@@ -25,42 +30,42 @@ pub fn block_csum(uuid: &[u8; 16], data: &[u8], sequence: u32) -> u32 {
     crc
 }
 
+/// 计算 journal 元数据块（descriptor/revoke/commit）的校验和
+///
+/// 对应 lwext4 的 `jbd_meta_csum()`，`csum_offset` 处的 4 字节校验和字段按 0 计算
+fn meta_csum(uuid: &[u8; 16], data: &[u8], csum_offset: usize) -> u32 {
+    let mut crc = crate::crc::crc32c(uuid);
+    crc = crate::crc::crc32c_append(crc, &data[..csum_offset]);
+    crc = crate::crc::crc32c_append(crc, &[0; 4]);
+    crate::crc::crc32c_append(crc, &data[csum_offset + 4..])
+}
+
+/// 读取块中 `offset` 处的大端 u32
+fn read_be32(data: &[u8], offset: usize) -> u32 {
+    u32::from_be_bytes([data[offset], data[offset + 1], data[offset + 2], data[offset + 3]])
+}
+
+/// 块尾部（[`jbd_block_tail`]）的偏移，块太小时返回 `None`
+fn tail_offset(data: &[u8], header_size: usize) -> Option<usize> {
+    let tail_size = core::mem::size_of::<jbd_block_tail>();
+    (data.len() >= header_size + tail_size).then(|| data.len() - tail_size)
+}
+
 /// 验证 journal descriptor block 的校验和
 ///
 /// # 参数
 ///
 /// * `uuid` - Journal UUID
-/// * `data` - 块数据
+/// * `data` - 整个块的数据
 ///
 /// # 返回
 ///
 /// 校验和是否有效
 pub fn verify_descriptor_block(uuid: &[u8; 16], data: &[u8]) -> bool {
-    let tail_size = core::mem::size_of::<jbd_block_tail>();
-    if data.len() < core::mem::size_of::<jbd_bhdr>() + tail_size {
-        return false;
+    match tail_offset(data, core::mem::size_of::<jbd_bhdr>()) {
+        Some(offset) => read_be32(data, offset) == meta_csum(uuid, data, offset),
+        None => false,
     }
-
-    // 读取块头获取序列号
-    let header = unsafe {
-        core::ptr::read_unaligned(data.as_ptr() as *const jbd_bhdr)
-    };
-    let sequence = u32::from_be(header.sequence);
-
-    // 读取存储在块尾部的校验和
-    let tail_offset = data.len() - tail_size;
-    let tail = unsafe {
-        core::ptr::read_unaligned(
-            data.as_ptr().add(tail_offset) as *const jbd_block_tail
-        )
-    };
-    let stored_csum = u32::from_be(tail.checksum);
-
-    // 计算校验和（不包含尾部）
-    let data_to_check = &data[..tail_offset];
-    let calculated_csum = block_csum(uuid, data_to_check, sequence);
-
-    stored_csum == calculated_csum
 }
 
 /// 验证 journal commit block 的校验和
@@ -68,7 +73,7 @@ pub fn verify_descriptor_block(uuid: &[u8; 16], data: &[u8]) -> bool {
 /// # 参数
 ///
 /// * `uuid` - Journal UUID
-/// * `data` - 块数据
+/// * `data` - 整个块的数据
 ///
 /// # 返回
 ///
@@ -77,19 +82,8 @@ pub fn verify_commit_block(uuid: &[u8; 16], data: &[u8]) -> bool {
     if data.len() < core::mem::size_of::<jbd_commit_header>() {
         return false;
     }
-
-    let commit_header = unsafe {
-        core::ptr::read_unaligned(data.as_ptr() as *const jbd_commit_header)
-    };
-
-    // 获取存储的校验和
-    let stored_csum = u32::from_be(commit_header.chksum[0]);
-
-    // 计算校验和
-    let sequence = u32::from_be(commit_header.header.sequence);
-    let calculated_csum = block_csum(uuid, data, sequence);
-
-    stored_csum == calculated_csum
+    let offset = core::mem::offset_of!(jbd_commit_header, chksum);
+    read_be32(data, offset) == meta_csum(uuid, data, offset)
 }
 
 /// 计算 journal descriptor block 的校验和
@@ -97,22 +91,16 @@ pub fn verify_commit_block(uuid: &[u8; 16], data: &[u8]) -> bool {
 /// # 参数
 ///
 /// * `uuid` - Journal UUID
-/// * `data` - 块数据
+/// * `data` - 整个块的数据（尾部的校验和字段不参与计算）
 ///
 /// # 返回
 ///
-/// 计算得到的校验和
+/// 计算得到的校验和，块太小时返回 0
 pub fn calculate_descriptor_csum(uuid: &[u8; 16], data: &[u8]) -> u32 {
-    if data.len() < core::mem::size_of::<jbd_bhdr>() {
-        return 0;
+    match tail_offset(data, core::mem::size_of::<jbd_bhdr>()) {
+        Some(offset) => meta_csum(uuid, data, offset),
+        None => 0,
     }
-
-    let header = unsafe {
-        core::ptr::read_unaligned(data.as_ptr() as *const jbd_bhdr)
-    };
-    let sequence = u32::from_be(header.sequence);
-
-    block_csum(uuid, data, sequence)
 }
 
 /// 计算 journal commit block 的校验和
@@ -120,22 +108,16 @@ pub fn calculate_descriptor_csum(uuid: &[u8; 16], data: &[u8]) -> u32 {
 /// # 参数
 ///
 /// * `uuid` - Journal UUID
-/// * `data` - 块数据
+/// * `data` - 整个块的数据（`chksum[0]` 不参与计算）
 ///
 /// # 返回
 ///
-/// 计算得到的校验和
+/// 计算得到的校验和，块太小时返回 0
 pub fn calculate_commit_csum(uuid: &[u8; 16], data: &[u8]) -> u32 {
     if data.len() < core::mem::size_of::<jbd_commit_header>() {
         return 0;
     }
-
-    let header = unsafe {
-        core::ptr::read_unaligned(data.as_ptr() as *const jbd_commit_header)
-    };
-    let sequence = u32::from_be(header.header.sequence);
-
-    block_csum(uuid, data, sequence)
+    meta_csum(uuid, data, core::mem::offset_of!(jbd_commit_header, chksum))
 }
 
 /// 计算 revoke block 的校验和
@@ -143,22 +125,16 @@ pub fn calculate_commit_csum(uuid: &[u8; 16], data: &[u8]) -> u32 {
 /// # 参数
 ///
 /// * `uuid` - Journal UUID
-/// * `data` - 块数据
+/// * `data` - 整个块的数据（尾部的校验和字段不参与计算）
 ///
 /// # 返回
 ///
-/// 计算得到的校验和
+/// 计算得到的校验和，块太小时返回 0
 pub fn calculate_revoke_csum(uuid: &[u8; 16], data: &[u8]) -> u32 {
-    if data.len() < core::mem::size_of::<jbd_revoke_header>() {
-        return 0;
+    match tail_offset(data, core::mem::size_of::<jbd_revoke_header>()) {
+        Some(offset) => meta_csum(uuid, data, offset),
+        None => 0,
     }
-
-    let header = unsafe {
-        core::ptr::read_unaligned(data.as_ptr() as *const jbd_revoke_header)
-    };
-    let sequence = u32::from_be(header.header.sequence);
-
-    block_csum(uuid, data, sequence)
 }
 
 /// 验证 journal revoke block 的校验和
@@ -166,37 +142,16 @@ pub fn calculate_revoke_csum(uuid: &[u8; 16], data: &[u8]) -> u32 {
 /// # 参数
 ///
 /// * `uuid` - Journal UUID
-/// * `data` - 块数据
+/// * `data` - 整个块的数据
 ///
 /// # 返回
 ///
 /// 校验和是否有效
 pub fn verify_revoke_block(uuid: &[u8; 16], data: &[u8]) -> bool {
-    let tail_size = core::mem::size_of::<jbd_revoke_tail>();
-    if data.len() < core::mem::size_of::<jbd_revoke_header>() + tail_size {
-        return false;
+    match tail_offset(data, core::mem::size_of::<jbd_revoke_header>()) {
+        Some(offset) => read_be32(data, offset) == meta_csum(uuid, data, offset),
+        None => false,
     }
-
-    // 读取块头获取序列号
-    let header = unsafe {
-        core::ptr::read_unaligned(data.as_ptr() as *const jbd_revoke_header)
-    };
-    let sequence = u32::from_be(header.header.sequence);
-
-    // 读取存储在块尾部的校验和
-    let tail_offset = data.len() - tail_size;
-    let tail = unsafe {
-        core::ptr::read_unaligned(
-            data.as_ptr().add(tail_offset) as *const jbd_revoke_tail
-        )
-    };
-    let stored_csum = u32::from_be(tail.checksum);
-
-    // 计算校验和（不包含尾部）
-    let data_to_check = &data[..tail_offset];
-    let calculated_csum = block_csum(uuid, data_to_check, sequence);
-
-    stored_csum == calculated_csum
 }
 
 /// 验证 journal superblock 的校验和
@@ -275,6 +230,35 @@ mod tests {
         assert_eq!(csum, csum2);
     }
 
+    #[test]
+    fn test_descriptor_csum() {
+        let uuid = [7u8; 16];
+        let mut data = [0u8; 1024];
+        data[..12].copy_from_slice(&[0xC0, 0x3B, 0x39, 0x98, 0, 0, 0, 1, 0, 0, 0, 5]);
+
+        // 校验和写入尾部后仍然有效（尾部按 0 计算）
+        let csum = calculate_descriptor_csum(&uuid, &data);
+        data[1020..].copy_from_slice(&csum.to_be_bytes());
+        assert!(verify_descriptor_block(&uuid, &data));
+        assert_eq!(calculate_descriptor_csum(&uuid, &data), csum);
+
+        data[100] = 1;
+        assert!(!verify_descriptor_block(&uuid, &data));
+        assert!(!verify_descriptor_block(&[0u8; 16], &[0u8; 8]));
+    }
+
+    #[test]
+    fn test_commit_csum() {
+        let uuid = [7u8; 16];
+        let mut data = [0u8; 1024];
+        let csum = calculate_commit_csum(&uuid, &data);
+        data[16..20].copy_from_slice(&csum.to_be_bytes());
+        assert!(verify_commit_block(&uuid, &data));
+
+        data[1000] = 1;
+        assert!(!verify_commit_block(&uuid, &data));
+    }
+
     #[test]
     fn test_superblock_csum() {
         let mut sb = jbd_sb::default();
@@ -2,13 +2,13 @@
 //!
 //! 对应 lwext4 的 journal commit 功能
 
-use super::{checksum, types::*, JbdFs, JbdJournal, JbdTrans, JournalError};
+use super::{checksum, types::*, JbdFs, JbdJournal, JbdTrans, JournalDataMode, JournalError};
 use crate::{
     block::{Block, BlockDev, BlockDevice},
-    error::{Error, ErrorKind, Result},
+    error::{Error, Result},
     superblock::Superblock,
 };
-use alloc::vec::Vec;
+use alloc::{collections::BTreeSet, vec::Vec};
 
 /// 提交一个事务到 journal
 ///
@@ -24,11 +24,16 @@ use alloc::vec::Vec;
 ///
 /// # 提交流程
 ///
-/// 1. 分配 journal 空间
-/// 2. 写入 descriptor block(s)（包含块映射）
-/// 3. 写入数据块到 journal
-/// 4. 写入 commit block（标记事务完成）
-/// 5. 更新 journal superblock
+/// 1. 分配 journal 空间和序列号
+/// 2. ordered 模式下，把事务的文件数据块写回原位置
+/// 3. 写入 revoke block(s) 和 descriptor block(s)（包含块映射）
+/// 4. 写入元数据块到 journal
+/// 5. 日志原来为空时，把日志起点和这个事务的序列号写入 journal superblock
+/// 6. 写屏障，保证文件数据块、描述符块和元数据块先于 commit block 落盘
+/// 7. 写入 commit block（标记事务完成）并再次写屏障
+///
+/// 事务的序列号在提交时分配（`trans.trans_id` 被覆盖），
+/// 保证日志中的事务按提交顺序连续
 ///
 /// # 返回
 ///
@@ -40,17 +45,24 @@ pub fn commit_transaction<D: BlockDevice>(
     bdev: &mut BlockDev<D>,
     superblock: &mut Superblock,
 ) -> Result<()> {
+    superblock.check_writable()?;
+
     // 检查事务是否有数据
-    if trans.buffer_count() == 0 {
-        // 空事务，直接返回
+    if trans.buffer_count() == 0 && trans.revoke_count() == 0 {
+        // 没有元数据修改，不需要 commit block；ordered 数据直接写回即可
+        if jbd_journal.data_mode() == JournalDataMode::Ordered {
+            write_ordered_data(trans, bdev)?;
+        }
         return Ok(());
     }
 
     // 计算需要的 journal 块数
-    // descriptor blocks + data blocks + commit block
+    // revoke blocks + descriptor blocks + data blocks + commit block
+    let sb = *jbd_fs.sb();
     let data_blocks = trans.buffer_count() as u32;
-    let descriptor_blocks = calculate_descriptor_blocks(data_blocks, jbd_fs.block_size());
-    let total_blocks = descriptor_blocks + data_blocks + 1; // +1 for commit block
+    let revoke_blocks = (trans.revoke_count() as u32).div_ceil(revoke_records_per_block(&sb));
+    let descriptor_blocks = calculate_descriptor_blocks(data_blocks, tags_per_block(&sb));
+    let total_blocks = revoke_blocks + descriptor_blocks + data_blocks + 1; // +1 for commit block
 
     // 检查 journal 空间是否足够
     if !jbd_journal.has_space(total_blocks) {
@@ -61,14 +73,28 @@ pub fn commit_transaction<D: BlockDevice>(
     let journal_start = jbd_journal.allocate_blocks(total_blocks)
         .ok_or(Error::from(JournalError::NoSpace))?;
 
+    trans.trans_id = jbd_journal.trans_id;
     trans.start_iblock = journal_start;
     trans.alloc_blocks = total_blocks as i32;
 
-    // 当前 journal 块号
-    let mut current_jblock = journal_start;
-
     // 获取 UUID 用于校验和
-    let uuid = jbd_fs.sb().uuid;
+    let uuid = sb.uuid;
+
+    // ordered 模式：文件数据先于 commit block 落盘（由下面的写屏障保证），
+    // 崩溃后重放的元数据不会指向未写入的数据块
+    if jbd_journal.data_mode() == JournalDataMode::Ordered {
+        write_ordered_data(trans, bdev)?;
+    }
+
+    // 与内核相同，revoke blocks 写在 descriptor blocks 之前
+    let mut current_jblock = write_revoke_blocks(
+        jbd_fs,
+        trans,
+        bdev,
+        superblock,
+        journal_start,
+        &uuid,
+    )?;
 
     // 写入 descriptor blocks 和数据块
     current_jblock = write_descriptor_and_data_blocks(
@@ -80,6 +106,18 @@ pub fn commit_transaction<D: BlockDevice>(
         &uuid,
     )?;
 
+    // 恢复从 journal superblock 记录的起点开始扫描，
+    // 日志为空时 start 为 0，需要先指向这个事务
+    if jbd_fs.start() == 0 {
+        jbd_fs.set_start(journal_start);
+        jbd_fs.set_sequence(trans.trans_id as u32);
+        jbd_fs.put(bdev, superblock)?;
+    }
+
+    // commit block 落盘前，事务的其余部分（包括 ordered 数据）必须已经落盘，
+    // 否则恢复时可能重放不完整的事务
+    bdev.barrier()?;
+
     // 写入 commit block
     write_commit_block(
         jbd_fs,
@@ -90,10 +128,14 @@ pub fn commit_transaction<D: BlockDevice>(
         &uuid,
     )?;
 
-    // 更新 journal superblock
-    let new_sequence = jbd_fs.sequence() + 1;
-    jbd_fs.set_sequence(new_sequence);
-    jbd_fs.mark_dirty();
+    // 检查点写回原位置之前，commit block 必须已经落盘
+    bdev.barrier()?;
+    if let Some(observer) = bdev.observer() {
+        observer.on_journal_commit(trans.trans_id, total_blocks);
+    }
+
+    // journal superblock 的序列号属于日志中的第一个事务，提交不改变它
+    jbd_journal.trans_id += 1;
 
     // 将事务添加到检查点队列
     // (在实际实现中，这里应该移动事务的所有权)
@@ -102,128 +144,211 @@ pub fn commit_transaction<D: BlockDevice>(
     Ok(())
 }
 
+/// 把事务的文件数据块从缓存写回原位置
+///
+/// 同时作为元数据记入日志的块不在这里写回，它们只能在 commit 之后由检查点写回
+fn write_ordered_data<D: BlockDevice>(trans: &JbdTrans, bdev: &mut BlockDev<D>) -> Result<()> {
+    let journaled: BTreeSet<u64> = trans.buf_queue.iter().map(|buf| buf.fs_lba()).collect();
+    for &lba in trans.data_buf.difference(&journaled) {
+        bdev.flush_lba(lba)?;
+    }
+    Ok(())
+}
+
+/// 每个 descriptor block 能容纳的 tag 数
+///
+/// 块头之后依次是各个 tag，第一个 tag 之后还有 16 字节 UUID；
+/// 启用 v2/v3 校验和时块尾部是 [`jbd_block_tail`]
+fn tags_per_block(sb: &jbd_sb) -> u32 {
+    let block_size = u32::from_be(sb.blocksize) as usize;
+    let header_size = core::mem::size_of::<jbd_bhdr>();
+    let tail_size = if sb.has_csum_v2or3() { core::mem::size_of::<jbd_block_tail>() } else { 0 };
+    ((block_size - header_size - tail_size - UUID_SIZE) / sb.tag_bytes()) as u32
+}
+
+/// 每个 revoke block 能容纳的撤销记录数
+fn revoke_records_per_block(sb: &jbd_sb) -> u32 {
+    let block_size = u32::from_be(sb.blocksize) as usize;
+    let header_size = core::mem::size_of::<jbd_revoke_header>();
+    let tail_size = if sb.has_csum_v2or3() { core::mem::size_of::<jbd_revoke_tail>() } else { 0 };
+    ((block_size - header_size - tail_size) / sb.revoke_record_bytes()) as u32
+}
+
 /// 计算需要多少个 descriptor blocks
-fn calculate_descriptor_blocks(data_blocks: u32, block_size: u32) -> u32 {
-    let tag_size = core::mem::size_of::<jbd_block_tag>() as u32;
-    let header_size = core::mem::size_of::<jbd_bhdr>() as u32;
-    let tags_per_block = (block_size - header_size) / tag_size;
+fn calculate_descriptor_blocks(data_blocks: u32, tags_per_block: u32) -> u32 {
+    data_blocks.div_ceil(tags_per_block)
+}
 
-    (data_blocks + tags_per_block - 1) / tags_per_block
+/// 日志区中的下一个块号（日志区为 `[first, maxlen)`）
+fn next_jblock(jbd_fs: &JbdFs, jblock: u32) -> u32 {
+    if jblock + 1 >= jbd_fs.max_len() {
+        jbd_fs.first()
+    } else {
+        jblock + 1
+    }
+}
+
+/// 按 journal 特性写入一个 block tag
+///
+/// `tag` 的长度为 [`jbd_sb::tag_bytes`]，v2 只保存校验和的低 16 位
+fn write_tag(sb: &jbd_sb, tag: &mut [u8], lba: u64, flags: u16, csum: u32) {
+    tag[0..4].copy_from_slice(&(lba as u32).to_be_bytes());
+    if sb.has_incompat_feature(JBD_FEATURE_INCOMPAT_CSUM_V3) {
+        tag[4..8].copy_from_slice(&(flags as u32).to_be_bytes());
+        tag[12..16].copy_from_slice(&csum.to_be_bytes());
+    } else {
+        tag[4..6].copy_from_slice(&(csum as u16).to_be_bytes());
+        tag[6..8].copy_from_slice(&flags.to_be_bytes());
+    }
+    if sb.is_64bit() {
+        tag[8..12].copy_from_slice(&((lba >> 32) as u32).to_be_bytes());
+    }
 }
 
 /// 写入 descriptor blocks 和数据块
 ///
+/// 启用 v1 校验和时，descriptor block 和数据块的 CRC32 累加值记录在
+/// `trans.data_csum` 中，由 commit block 保存
+///
 /// # 返回
 ///
 /// 下一个可用的 journal 块号
 fn write_descriptor_and_data_blocks<D: BlockDevice>(
     jbd_fs: &JbdFs,
-    trans: &JbdTrans,
+    trans: &mut JbdTrans,
     bdev: &mut BlockDev<D>,
     superblock: &mut Superblock,
     start_jblock: u32,
     uuid: &[u8; 16],
 ) -> Result<u32> {
-    let block_size = jbd_fs.block_size();
-    let tag_size = core::mem::size_of::<jbd_block_tag>() as usize;
-    let header_size = core::mem::size_of::<jbd_bhdr>() as usize;
-    let tail_size = core::mem::size_of::<jbd_block_tail>() as usize;
+    let sb = *jbd_fs.sb();
+    let tag_bytes = sb.tag_bytes();
+    let header_size = core::mem::size_of::<jbd_bhdr>();
+    let tail_size = core::mem::size_of::<jbd_block_tail>();
 
     // 检查是否启用了校验和特性
-    let has_csum = jbd_fs.has_incompat_feature(JBD_FEATURE_INCOMPAT_CSUM_V2 | JBD_FEATURE_INCOMPAT_CSUM_V3);
-    let available_space = if has_csum {
-        block_size as usize - header_size - tail_size
-    } else {
-        block_size as usize - header_size
-    };
-    let tags_per_block = available_space / tag_size;
+    let has_csum = sb.has_csum_v2or3();
+    let has_csum_v1 = !has_csum && sb.has_compat_feature(JBD_FEATURE_COMPAT_CHECKSUM);
+    let tags_per_block = tags_per_block(&sb) as usize;
 
     let mut current_jblock = start_jblock;
-    let sequence = jbd_fs.sequence();
+    let sequence = trans.trans_id as u32;
+    let mut crc32_sum = !0u32;
 
     // 遍历所有缓冲区
-    let buffers: Vec<_> = trans.buf_queue.iter().collect();
+    let lbas: Vec<u64> = trans.buf_queue.iter().map(|buf| buf.fs_lba()).collect();
+    let mut buf_idx = 0;
+
+    for chunk in lbas.chunks(tags_per_block) {
+        // 从文件系统读取原始数据，作为写入 journal 的副本
+        let mut blocks = Vec::with_capacity(chunk.len());
+        for &lba in chunk {
+            let mut fs_block = Block::get(bdev, lba)?;
+            let mut data = fs_block.with_data(|d| d.to_vec())?;
+            // 以 JBD magic 开头的块需要转义，否则恢复时会被当作 journal 元数据块
+            let escaped = data[..4] == JBD_MAGIC_NUMBER.to_be_bytes();
+            if escaped {
+                data[..4].fill(0);
+            }
+            blocks.push((lba, data, escaped));
+        }
 
-    for (chunk_idx, chunk) in buffers.chunks(tags_per_block).enumerate() {
         // 为这个 descriptor block 分配物理块
         let desc_phys_block = jbd_fs.inode_bmap(bdev, superblock, current_jblock)?;
-        current_jblock += 1;
+        current_jblock = next_jblock(jbd_fs, current_jblock);
 
         // 写入 descriptor block
         {
-            let mut desc_block = Block::get(bdev, desc_phys_block)?;
+            let mut desc_block = Block::get_noread(bdev, desc_phys_block)?;
             desc_block.with_data_mut(|data| {
+                data.fill(0);
+
                 // 写入 header
                 let header = jbd_bhdr::new(JBD_DESCRIPTOR_BLOCK, sequence);
                 unsafe {
                     core::ptr::write_unaligned(data.as_mut_ptr() as *mut jbd_bhdr, header);
                 }
 
-                // 写入 block tags
+                // 写入 block tags，第一个 tag 之后是 UUID，其余 tag 带 SAME_UUID
                 let mut offset = header_size;
-                for (i, buf) in chunk.iter().enumerate() {
-                    let is_last = (i == chunk.len() - 1) && (chunk_idx == buffers.chunks(tags_per_block).count() - 1);
-
-                    let mut tag = jbd_block_tag {
-                        blocknr: (buf.fs_lba() as u32).to_be(),
-                        checksum: 0,
-                        flags: if is_last { JBD_FLAG_LAST_TAG.to_be() } else { 0 },
-                        blocknr_high: 0,
-                    };
-
-                    unsafe {
-                        core::ptr::write_unaligned(
-                            data.as_mut_ptr().add(offset) as *mut jbd_block_tag,
-                            tag,
-                        );
+                for (i, (lba, block, escaped)) in blocks.iter().enumerate() {
+                    let mut flags = 0;
+                    if *escaped {
+                        flags |= JBD_FLAG_ESCAPE;
                     }
+                    if i > 0 {
+                        flags |= JBD_FLAG_SAME_UUID;
+                    }
+                    if i == blocks.len() - 1 {
+                        flags |= JBD_FLAG_LAST_TAG;
+                    }
+                    let csum = if has_csum { checksum::block_csum(uuid, block, sequence) } else { 0 };
+                    write_tag(&sb, &mut data[offset..offset + tag_bytes], *lba, flags, csum);
+                    offset += tag_bytes;
 
-                    offset += tag_size;
+                    if i == 0 {
+                        data[offset..offset + UUID_SIZE].copy_from_slice(uuid);
+                        offset += UUID_SIZE;
+                    }
                 }
 
                 // 如果启用了校验和，写入 tail
                 if has_csum {
                     let tail_offset = data.len() - tail_size;
-                    let csum = checksum::calculate_descriptor_csum(uuid, &data[..tail_offset]);
-                    let tail = jbd_block_tail {
-                        checksum: csum.to_be(),
-                    };
-                    unsafe {
-                        core::ptr::write_unaligned(
-                            data.as_mut_ptr().add(tail_offset) as *mut jbd_block_tail,
-                            tail,
-                        );
-                    }
+                    let csum = checksum::calculate_descriptor_csum(uuid, data);
+                    data[tail_offset..].copy_from_slice(&csum.to_be_bytes());
                 }
 
-                Ok::<_, Error>(())
-            })??;
+                if has_csum_v1 {
+                    crc32_sum = crate::crc::crc32_be(crc32_sum, data);
+                }
+            })?;
         } // desc_block 在这里释放
 
         // 写入对应的数据块
-        for buf in chunk {
+        // journal 中物理连续的数据块合并为一次设备写入
+        let bs = bdev.block_size() as usize;
+        let mut run_start = 0u64;
+        let mut run_data: Vec<u8> = Vec::new();
+
+        for (_, block, escaped) in &blocks {
+            // 记录日志副本的位置，供检查点使用
+            let buf = &mut trans.buf_queue[buf_idx];
+            buf.jbd_lba = current_jblock;
+            buf.escaped = *escaped;
+            buf_idx += 1;
+
             let data_phys_block = jbd_fs.inode_bmap(bdev, superblock, current_jblock)?;
-            current_jblock += 1;
-
-            // 从文件系统读取原始数据
-            let fs_block_data = {
-                let mut fs_block = Block::get(bdev, buf.fs_lba())?;
-                fs_block.with_data(|d| Ok::<_, Error>(d.to_vec()))?
-            }?;
-
-            // 写入到 journal
-            let mut journal_block = Block::get(bdev, data_phys_block)?;
-            journal_block.with_data_mut(|d| {
-                let len = fs_block_data.len().min(d.len());
-                d[..len].copy_from_slice(&fs_block_data[..len]);
-            })?;
+            current_jblock = next_jblock(jbd_fs, current_jblock);
+
+            let run_len = (run_data.len() / bs) as u64;
+            if run_len > 0 && data_phys_block != run_start + run_len {
+                bdev.write_blocks(run_start, run_len as u32, &run_data)?;
+                run_data.clear();
+            }
+            if run_data.is_empty() {
+                run_start = data_phys_block;
+            }
+
+            if has_csum_v1 {
+                crc32_sum = crate::crc::crc32_be(crc32_sum, block);
+            }
+            run_data.extend_from_slice(block);
+        }
+
+        if !run_data.is_empty() {
+            bdev.write_blocks(run_start, (run_data.len() / bs) as u32, &run_data)?;
         }
     }
 
+    trans.data_csum = crc32_sum;
     Ok(current_jblock)
 }
 
 /// 写入 commit block
+///
+/// v2/v3 校验和保存整个 commit block 的 crc32c，`chksum_type` 为 0；
+/// v1 校验和保存 `trans.data_csum`（整个事务的 CRC32）
 fn write_commit_block<D: BlockDevice>(
     jbd_fs: &JbdFs,
     trans: &JbdTrans,
@@ -233,10 +358,14 @@ fn write_commit_block<D: BlockDevice>(
     uuid: &[u8; 16],
 ) -> Result<()> {
     let commit_phys_block = jbd_fs.inode_bmap(bdev, superblock, commit_jblock)?;
-    let sequence = jbd_fs.sequence();
+    let sequence = trans.trans_id as u32;
+    let has_csum = jbd_fs.sb().has_csum_v2or3();
+    let has_csum_v1 = !has_csum && jbd_fs.has_compat_feature(JBD_FEATURE_COMPAT_CHECKSUM);
 
-    let mut commit_block = Block::get(bdev, commit_phys_block)?;
+    let mut commit_block = Block::get_noread(bdev, commit_phys_block)?;
     commit_block.with_data_mut(|data| {
+        data.fill(0);
+
         // 创建 commit header
         let mut commit_header = jbd_commit_header {
             header: jbd_bhdr::new(JBD_COMMIT_BLOCK, sequence),
@@ -248,24 +377,12 @@ fn write_commit_block<D: BlockDevice>(
             commit_nsec: 0,
         };
 
-        // 如果启用了校验和，计算并填充
-        if jbd_fs.has_incompat_feature(JBD_FEATURE_INCOMPAT_CSUM_V2 | JBD_FEATURE_INCOMPAT_CSUM_V3) {
-            commit_header.chksum_type = 1; // CRC32C
-            commit_header.chksum_size = 4;
-
-            // 计算校验和（先写入 header，然后计算）
-            unsafe {
-                core::ptr::write_unaligned(
-                    data.as_mut_ptr() as *mut jbd_commit_header,
-                    commit_header,
-                );
-            }
-
-            let csum = checksum::calculate_commit_csum(uuid, data);
-            commit_header.chksum[0] = csum.to_be();
+        if has_csum_v1 {
+            commit_header.chksum_type = JbdChecksumType::Crc32 as u8;
+            commit_header.chksum_size = JBD_CRC32_CHKSUM_SIZE as u8;
+            commit_header.chksum[0] = trans.data_csum.to_be();
         }
 
-        // 写入最终的 commit header
         unsafe {
             core::ptr::write_unaligned(
                 data.as_mut_ptr() as *mut jbd_commit_header,
@@ -273,13 +390,18 @@ fn write_commit_block<D: BlockDevice>(
             );
         }
 
-        Ok::<_, Error>(())
-    })??;
+        // 如果启用了校验和，对整个块计算并填充 chksum[0]
+        if has_csum {
+            let csum = checksum::calculate_commit_csum(uuid, data);
+            let offset = core::mem::offset_of!(jbd_commit_header, chksum);
+            data[offset..offset + 4].copy_from_slice(&csum.to_be_bytes());
+        }
+    })?;
 
     Ok(())
 }
 
-/// 写入 revoke block（撤销块）
+/// 写入 revoke blocks（撤销块）
 ///
 /// # 参数
 ///
@@ -287,91 +409,99 @@ fn write_commit_block<D: BlockDevice>(
 /// * `trans` - 事务
 /// * `bdev` - 块设备引用
 /// * `superblock` - 文件系统 superblock
-/// * `revoke_jblock` - revoke block 的 journal 块号
+/// * `start_jblock` - 第一个 revoke block 的 journal 块号
 /// * `uuid` - Journal UUID
 ///
-/// # 说明
+/// # 返回
 ///
-/// 当事务包含撤销记录时调用
-fn write_revoke_block<D: BlockDevice>(
+/// 下一个可用的 journal 块号，事务没有撤销记录时就是 `start_jblock`
+fn write_revoke_blocks<D: BlockDevice>(
     jbd_fs: &JbdFs,
     trans: &JbdTrans,
     bdev: &mut BlockDev<D>,
     superblock: &mut Superblock,
-    revoke_jblock: u32,
+    start_jblock: u32,
     uuid: &[u8; 16],
-) -> Result<()> {
-    if trans.revoke_count() == 0 {
-        return Ok(());
-    }
+) -> Result<u32> {
+    let sb = *jbd_fs.sb();
+    let header_size = core::mem::size_of::<jbd_revoke_header>();
+    let tail_size = core::mem::size_of::<jbd_revoke_tail>();
+    let record_bytes = sb.revoke_record_bytes();
+    let has_csum = sb.has_csum_v2or3();
+    let sequence = trans.trans_id as u32;
+
+    let lbas: Vec<u64> = trans.revoke_root.keys().copied().collect();
+    let mut current_jblock = start_jblock;
 
-    let revoke_phys_block = jbd_fs.inode_bmap(bdev, superblock, revoke_jblock)?;
-    let sequence = jbd_fs.sequence();
+    for chunk in lbas.chunks(revoke_records_per_block(&sb) as usize) {
+        let revoke_phys_block = jbd_fs.inode_bmap(bdev, superblock, current_jblock)?;
+        current_jblock = next_jblock(jbd_fs, current_jblock);
 
-    let mut revoke_block = Block::get(bdev, revoke_phys_block)?;
-    revoke_block.with_data_mut(|data| {
-        // 创建 revoke header
-        let header = jbd_revoke_header {
-            header: jbd_bhdr::new(JBD_REVOKE_BLOCK, sequence),
-            count: (trans.revoke_count() as u32).to_be(),
-        };
+        let mut revoke_block = Block::get_noread(bdev, revoke_phys_block)?;
+        revoke_block.with_data_mut(|data| {
+            data.fill(0);
 
-        unsafe {
-            core::ptr::write_unaligned(
-                data.as_mut_ptr() as *mut jbd_revoke_header,
-                header,
-            );
-        }
+            // 创建 revoke header，count 是块中已使用的字节数（包含块头）
+            let header = jbd_revoke_header {
+                header: jbd_bhdr::new(JBD_REVOKE_BLOCK, sequence),
+                count: ((header_size + chunk.len() * record_bytes) as u32).to_be(),
+            };
 
-        // 写入所有撤销记录
-        let mut offset = core::mem::size_of::<jbd_revoke_header>();
-        for (lba, _revoke_rec) in &trans.revoke_root {
             unsafe {
                 core::ptr::write_unaligned(
-                    data.as_mut_ptr().add(offset) as *mut u64,
-                    lba.to_be(),
+                    data.as_mut_ptr() as *mut jbd_revoke_header,
+                    header,
                 );
             }
-            offset += core::mem::size_of::<u64>();
-        }
 
-        // 计算并写入校验和（如果启用）
-        if jbd_fs.has_incompat_feature(JBD_FEATURE_INCOMPAT_CSUM_V2 | JBD_FEATURE_INCOMPAT_CSUM_V3) {
-            let tail_size = core::mem::size_of::<jbd_revoke_tail>();
-            let tail_offset = data.len() - tail_size;
-
-            // 计算校验和（不包含尾部）
-            let csum = checksum::calculate_revoke_csum(uuid, &data[..tail_offset]);
-
-            // 写入 revoke block tail
-            let tail = jbd_revoke_tail {
-                checksum: csum.to_be(),
-            };
-            unsafe {
-                core::ptr::write_unaligned(
-                    data.as_mut_ptr().add(tail_offset) as *mut jbd_revoke_tail,
-                    tail,
-                );
+            // 写入撤销记录（64 位 journal 为 8 字节，否则为 4 字节）
+            let mut offset = header_size;
+            for &lba in chunk {
+                if record_bytes == 8 {
+                    data[offset..offset + 8].copy_from_slice(&lba.to_be_bytes());
+                } else {
+                    data[offset..offset + 4].copy_from_slice(&(lba as u32).to_be_bytes());
+                }
+                offset += record_bytes;
             }
-        }
 
-        Ok::<_, Error>(())
-    })??;
+            // 计算并写入校验和（如果启用）
+            if has_csum {
+                let tail_offset = data.len() - tail_size;
+                let csum = checksum::calculate_revoke_csum(uuid, data);
+                data[tail_offset..].copy_from_slice(&csum.to_be_bytes());
+            }
+        })?;
+    }
 
-    Ok(())
+    Ok(current_jblock)
 }
 
-/// 提交事务的便捷包装函数
-///
-/// 对应 lwext4 的 `jbd_trans_commit()`
-pub fn trans_commit<D: BlockDevice>(
-    jbd_fs: &mut JbdFs,
-    jbd_journal: &mut JbdJournal,
-    trans: &mut JbdTrans,
-    bdev: &mut BlockDev<D>,
-    superblock: &mut Superblock,
-) -> Result<()> {
-    commit_transaction(jbd_fs, jbd_journal, trans, bdev, superblock)
+impl JbdJournal {
+    /// 提交一个事务到 journal，见 [`commit_transaction`]
+    ///
+    /// 对应 lwext4 的 `jbd_journal_commit_trans()`
+    ///
+    /// # 示例
+    ///
+    /// ```rust,ignore
+    /// let mut jbd_fs = JbdFs::get(&mut bdev, &mut sb)?;
+    /// jbd_fs.recover(&mut bdev, &mut sb)?;
+    /// let mut journal = JbdJournal::start(&jbd_fs);
+    ///
+    /// let mut trans = journal.new_transaction();
+    /// trans.add_block(lba);
+    /// journal.commit_transaction(&mut jbd_fs, &mut trans, &mut bdev, &mut sb)?;
+    /// ```
+    pub fn commit_transaction<D: BlockDevice>(
+        &mut self,
+        jbd_fs: &mut JbdFs,
+        trans: &mut JbdTrans,
+        bdev: &mut BlockDev<D>,
+        superblock: &mut Superblock,
+    ) -> Result<()> {
+        commit_transaction(jbd_fs, self, trans, bdev, superblock)
+    }
 }
 
 #[cfg(test)]
@@ -383,12 +513,29 @@ mod tests {
         // 假设 block size = 4096, tag size = 12, header size = 12
         // tags_per_block = (4096 - 12) / 12 = 340
 
-        assert_eq!(calculate_descriptor_blocks(0, 4096), 0);
-        assert_eq!(calculate_descriptor_blocks(1, 4096), 1);
-        assert_eq!(calculate_descriptor_blocks(340, 4096), 1);
-        assert_eq!(calculate_descriptor_blocks(341, 4096), 2);
-        assert_eq!(calculate_descriptor_blocks(680, 4096), 2);
-        assert_eq!(calculate_descriptor_blocks(681, 4096), 3);
+        assert_eq!(calculate_descriptor_blocks(0, 340), 0);
+        assert_eq!(calculate_descriptor_blocks(1, 340), 1);
+        assert_eq!(calculate_descriptor_blocks(340, 340), 1);
+        assert_eq!(calculate_descriptor_blocks(341, 340), 2);
+        assert_eq!(calculate_descriptor_blocks(680, 340), 2);
+        assert_eq!(calculate_descriptor_blocks(681, 340), 3);
+    }
+
+    #[test]
+    fn test_tags_per_block() {
+        let mut sb = jbd_sb::default();
+        // (4096 - 12 - 16) / 8
+        assert_eq!(tags_per_block(&sb), 508);
+        assert_eq!(revoke_records_per_block(&sb), (4096 - 16) / 4);
+
+        sb.feature_incompat = (JBD_FEATURE_INCOMPAT_CSUM_V3 | JBD_FEATURE_INCOMPAT_64BIT).to_be();
+        // (4096 - 12 - 4 - 16) / 16
+        assert_eq!(tags_per_block(&sb), 254);
+        assert_eq!(revoke_records_per_block(&sb), (4096 - 16 - 4) / 8);
+
+        let mut tag = [0u8; 16];
+        write_tag(&sb, &mut tag, 0x2_0000_0001, JBD_FLAG_LAST_TAG, 0x1234_5678);
+        assert_eq!(tag, [0, 0, 0, 1, 0, 0, 0, 8, 0, 0, 0, 2, 0x12, 0x34, 0x56, 0x78]);
     }
 
     #[test]
@@ -396,4 +543,68 @@ mod tests {
         // 这些测试需要实际的块设备和文件系统
         // 主要验证 API 设计和编译
     }
+
+    /// 提交一个事务，在检查点之前“崩溃”，然后从日志恢复
+    fn commit_then_recover(jbd_incompat: u32) {
+        use crate::testing::image;
+
+        const BS: usize = 4096;
+        // 日志之后的空闲块
+        let target = image::JOURNAL_START + image::JOURNAL_BLOCKS as u64;
+        // 以 JBD magic 开头，写入日志时需要转义
+        let escaped = target + 1;
+        let revoked = target + 2;
+
+        let mut bdev = BlockDev::new_with_cache(image::image_with_journal(jbd_incompat), 64).unwrap();
+        let mut sb = Superblock::load(&mut bdev).unwrap();
+        let mut jbd_fs = JbdFs::get(&mut bdev, &mut sb).unwrap();
+        let mut journal = JbdJournal::start(&jbd_fs);
+
+        Block::get_noread(&mut bdev, target).unwrap().with_data_mut(|d| d.fill(0x5a)).unwrap();
+        Block::get_noread(&mut bdev, escaped)
+            .unwrap()
+            .with_data_mut(|d| {
+                d.fill(0x11);
+                d[..4].copy_from_slice(&JBD_MAGIC_NUMBER.to_be_bytes());
+            })
+            .unwrap();
+
+        let mut trans = journal.new_transaction();
+        trans.add_block(target);
+        trans.add_block(escaped);
+        trans.add_revoke(revoked);
+        journal.commit_transaction(&mut jbd_fs, &mut trans, &mut bdev, &mut sb).unwrap();
+        assert_eq!(trans.trans_id, 1);
+        assert_eq!(journal.trans_id, 2);
+
+        // 崩溃：原位置的块还没有写回
+        let mut dev = bdev.device().clone();
+        dev.as_bytes_mut()[target as usize * BS..][..2 * BS].fill(0);
+
+        let mut bdev = BlockDev::new_with_cache(dev, 64).unwrap();
+        let mut sb = Superblock::load(&mut bdev).unwrap();
+        let mut jbd_fs = JbdFs::get(&mut bdev, &mut sb).unwrap();
+        assert_eq!(jbd_fs.start(), trans.start_iblock);
+        assert_eq!(jbd_fs.sequence(), 1);
+
+        jbd_fs.recover(&mut bdev, &mut sb).unwrap();
+        assert_eq!(jbd_fs.start(), 0);
+        assert_eq!(jbd_fs.sequence(), 2);
+
+        let data = Block::get(&mut bdev, target).unwrap().with_data(|d| d.to_vec()).unwrap();
+        assert!(data.iter().all(|&b| b == 0x5a));
+        let data = Block::get(&mut bdev, escaped).unwrap().with_data(|d| d.to_vec()).unwrap();
+        assert_eq!(data[..4], JBD_MAGIC_NUMBER.to_be_bytes());
+        assert!(data[4..].iter().all(|&b| b == 0x11));
+    }
+
+    #[test]
+    fn test_commit_then_recover_csum_v2() {
+        commit_then_recover(JBD_FEATURE_INCOMPAT_CSUM_V2);
+    }
+
+    #[test]
+    fn test_commit_then_recover_csum_v3() {
+        commit_then_recover(JBD_FEATURE_INCOMPAT_CSUM_V3);
+    }
 }
@@ -53,6 +53,10 @@ pub struct JbdBuf {
 
     /// Whether this buffer is dirty
     pub(super) dirty: bool,
+
+    /// Whether the journal copy was escaped (first 4 bytes zeroed
+    /// because the block starts with the JBD magic number)
+    pub(super) escaped: bool,
 }
 
 impl JbdBuf {
@@ -64,6 +68,7 @@ impl JbdBuf {
             trans_id: None,
             block_rec_id: None,
             dirty: false,
+            escaped: false,
         }
     }
 
@@ -106,6 +111,7 @@ impl core::fmt::Debug for JbdBuf {
             .field("trans_id", &self.trans_id)
             .field("block_rec_id", &self.block_rec_id)
             .field("dirty", &self.dirty)
+            .field("escaped", &self.escaped)
             .finish()
     }
 }
@@ -2,7 +2,7 @@
 //!
 //! 对应 lwext4 的 `struct jbd_fs` 和相关操作
 
-use super::{recovery, types::*, JournalError};
+use super::{checksum, recovery, types::*, JournalError};
 use crate::{
     block::{Block, BlockDev, BlockDevice},
     consts::*,
@@ -172,8 +172,16 @@ impl JbdFs {
         bdev: &mut BlockDev<D>,
         superblock: &mut Superblock,
     ) -> Result<()> {
+        // 回放日志会写入文件系统块
+        superblock.check_writable()?;
         // 调用 recovery 模块执行实际恢复
-        recovery::recover(self, bdev, superblock)
+        recovery::recover(self, bdev, superblock)?;
+
+        // 先写回清空后的 journal superblock，再清除 RECOVER 标志
+        // （恢复时发现损坏的块还会标记文件系统错误状态）
+        self.put(bdev, superblock)?;
+        superblock.inner_mut().feature_incompat &= !EXT4_FEATURE_INCOMPAT_RECOVER.to_le();
+        superblock.write(bdev)
     }
 
     /// 写回 journal superblock
@@ -193,6 +201,11 @@ impl JbdFs {
             inode_ref.get_inode_dblk_idx(0, false)?
         };
 
+        let mut sb = self.sb;
+        if sb.has_csum_v2or3() {
+            checksum::calculate_superblock_csum(&mut sb);
+        }
+
         // 写入 journal superblock
         let mut block = Block::get(bdev, first_block)?;
         block.with_data_mut(|data| {
@@ -200,7 +213,7 @@ impl JbdFs {
             unsafe {
                 core::ptr::write_unaligned(
                     data.as_mut_ptr() as *mut jbd_sb,
-                    self.sb,
+                    sb,
                 );
             }
         })?;
@@ -2,10 +2,24 @@
 //!
 //! 对应 lwext4 的 `struct jbd_journal`
 
-use super::{JbdTrans, jbd_trans::JbdBlockRec};
+use super::{JbdFs, JbdTrans, jbd_trans::JbdBlockRec};
 use crate::error::Result;
 use alloc::collections::{BTreeMap, VecDeque};
 
+/// 文件数据的日志模式
+///
+/// 对应内核的 `data=ordered` / `data=writeback` 挂载选项。两种模式下文件数据
+/// 都不写入日志，区别在于提交事务时数据块与 commit block 的先后顺序。
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum JournalDataMode {
+    /// 事务的数据块（[`JbdTrans::add_data_buffer`]）先写回原位置，
+    /// 再写 commit block，崩溃后文件中不会出现未初始化的旧数据
+    #[default]
+    Ordered,
+    /// 不约束数据块的写回顺序，崩溃后新分配的块中可能是旧数据
+    Writeback,
+}
+
 /// JBD Journal（日志管理器）
 ///
 /// 对应 lwext4 的 `struct jbd_journal`
@@ -59,6 +73,9 @@ pub struct JbdJournal {
     /// Global block record index (all active block records)
     /// Key: LBA, Value: JbdBlockRec
     pub block_rec_root: BTreeMap<u64, JbdBlockRec>,
+
+    /// File data mode (ordered by default)
+    pub data_mode: JournalDataMode,
 }
 
 impl JbdJournal {
@@ -79,9 +96,31 @@ impl JbdJournal {
             block_size,
             cp_queue: VecDeque::new(),
             block_rec_root: BTreeMap::new(),
+            data_mode: JournalDataMode::default(),
         }
     }
 
+    /// Start the journal on an empty log
+    ///
+    /// 对应 lwext4 的 `jbd_journal_start()`。日志区取自 journal superblock，
+    /// 第一个提交的事务使用 superblock 中的序列号。日志不为空时需要先调用
+    /// [`JbdFs::recover`]。
+    pub fn start(jbd_fs: &JbdFs) -> Self {
+        let mut journal = Self::new(jbd_fs.first(), jbd_fs.max_len(), jbd_fs.block_size());
+        journal.trans_id = jbd_fs.sequence() as u64;
+        journal
+    }
+
+    /// Get the file data mode
+    pub fn data_mode(&self) -> JournalDataMode {
+        self.data_mode
+    }
+
+    /// Set the file data mode
+    pub fn set_data_mode(&mut self, mode: JournalDataMode) {
+        self.data_mode = mode;
+    }
+
     /// Allocate a new transaction ID
     ///
     /// # Returns
@@ -231,6 +270,15 @@ mod tests {
         assert_eq!(journal.total_blocks(), 100);
     }
 
+    #[test]
+    fn test_data_mode() {
+        let mut journal = JbdJournal::new(100, 200, 4096);
+        assert_eq!(journal.data_mode(), JournalDataMode::Ordered);
+
+        journal.set_data_mode(JournalDataMode::Writeback);
+        assert_eq!(journal.data_mode(), JournalDataMode::Writeback);
+    }
+
     #[test]
     fn test_alloc_trans_id() {
         let mut journal = JbdJournal::new(100, 200, 4096);
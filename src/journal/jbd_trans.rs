@@ -4,7 +4,7 @@
 
 use super::{JbdBuf, types::*};
 use crate::error::Result;
-use alloc::{collections::{BTreeMap, VecDeque}, vec::Vec};
+use alloc::{collections::{BTreeMap, BTreeSet, VecDeque}, vec::Vec};
 
 /// JBD Revoke Record（撤销记录）
 ///
@@ -116,6 +116,12 @@ pub struct JbdTrans {
 
     /// Block record list (blocks involved in this transaction)
     pub tbrec_list: Vec<JbdBlockRec>,
+
+    /// File data blocks written by this transaction (not journaled)
+    ///
+    /// In [`JournalDataMode::Ordered`](super::JournalDataMode::Ordered) they
+    /// are written to their final location before the commit block
+    pub data_buf: BTreeSet<u64>,
 }
 
 impl JbdTrans {
@@ -137,6 +143,7 @@ impl JbdTrans {
             buf_queue: VecDeque::new(),
             revoke_root: BTreeMap::new(),
             tbrec_list: Vec::new(),
+            data_buf: BTreeSet::new(),
         }
     }
 
@@ -150,6 +157,27 @@ impl JbdTrans {
         self.data_cnt += 1;
     }
 
+    /// Add a filesystem block to this transaction
+    ///
+    /// 提交时读取块的当前内容（通常在缓存中）写入日志
+    pub fn add_block(&mut self, lba: u64) {
+        self.add_buffer(JbdBuf::new(0, lba));
+    }
+
+    /// Add a file data block
+    ///
+    /// # Returns
+    ///
+    /// true if the block was newly added, false if it was already tracked
+    pub fn add_data_buffer(&mut self, lba: u64) -> bool {
+        self.data_buf.insert(lba)
+    }
+
+    /// Get number of tracked file data blocks
+    pub fn data_buffer_count(&self) -> usize {
+        self.data_buf.len()
+    }
+
     /// Add a revoke record
     ///
     /// # Parameters
@@ -255,6 +283,18 @@ mod tests {
         assert_eq!(trans.revoke_count(), 2);
     }
 
+    #[test]
+    fn test_add_data_buffer() {
+        let mut trans = JbdTrans::new(1, 100);
+
+        assert!(trans.add_data_buffer(700));
+        assert!(!trans.add_data_buffer(700));
+        assert!(trans.add_data_buffer(701));
+        assert_eq!(trans.data_buffer_count(), 2);
+        // 数据块不写入日志
+        assert_eq!(trans.buffer_count(), 0);
+    }
+
     #[test]
     fn test_is_revoked() {
         let mut trans = JbdTrans::new(1, 100);
@@ -63,29 +63,27 @@
 //! # 使用示例
 //!
 //! ```rust,ignore
-//! use lwext4_core::journal::{JbdFs, JbdJournal, JournalTransaction};
+//! use lwext4_core::journal::{JbdFs, JbdJournal};
 //!
 //! // 1. 初始化journal（mount时）
-//! let mut jbd_fs = JbdFs::get(&mut fs)?;
-//! jbd_fs.recover()?;  // 执行崩溃恢复
-//! let mut journal = JbdJournal::start(&mut jbd_fs)?;
+//! let mut jbd_fs = JbdFs::get(&mut bdev, &mut sb)?;
+//! jbd_fs.recover(&mut bdev, &mut sb)?;  // 执行崩溃恢复
+//! let mut journal = JbdJournal::start(&jbd_fs);
 //!
 //! // 2. 开始事务
-//! let mut trans = journal.new_transaction()?;
+//! let mut trans = journal.new_transaction();
 //!
 //! // 3. 执行修改
-//! let mut block = trans.get_block(100)?;
-//! block.with_data_mut(|data| {
+//! Block::get(&mut bdev, 100)?.with_data_mut(|data| {
 //!     data[0] = 0x42;
 //! })?;
-//! trans.mark_block_dirty(100)?;
+//! trans.add_block(100);
 //!
 //! // 4. 提交事务
-//! journal.commit_transaction(trans)?;
+//! journal.commit_transaction(&mut jbd_fs, &mut trans, &mut bdev, &mut sb)?;
 //!
-//! // 5. 停止journal（unmount时）
-//! journal.stop()?;
-//! jbd_fs.put()?;
+//! // 5. 写回journal superblock（unmount时）
+//! jbd_fs.put(&mut bdev, &mut sb)?;
 //! ```
 //!
 //! # 对应lwext4
@@ -117,7 +115,7 @@ mod checksum;
 // Re-exports
 pub use types::*;
 pub use jbd_fs::JbdFs;
-pub use jbd_journal::JbdJournal;
+pub use jbd_journal::{JbdJournal, JournalDataMode};
 pub use jbd_trans::JbdTrans;
 pub use jbd_buf::JbdBuf;
 
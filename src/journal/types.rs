@@ -37,6 +37,7 @@ pub const JBD_CHECKSUM_BYTES: usize = 8;
 /// 所有字段都是大端序（big-endian）
 #[repr(C, packed)]
 #[derive(Debug, Clone, Copy)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct jbd_bhdr {
     /// Magic number (0xC03B3998)
     pub magic: u32,
@@ -90,6 +91,7 @@ pub const JBD_CRC32_CHKSUM_SIZE: usize = 4;
 /// 这三个版本互斥。
 #[repr(C, packed)]
 #[derive(Debug, Clone, Copy)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct jbd_commit_header {
     /// Block header
     pub header: jbd_bhdr,
@@ -118,6 +120,7 @@ pub struct jbd_commit_header {
 /// 用于 FEATURE_INCOMPAT_CSUM_V3 特性
 #[repr(C, packed)]
 #[derive(Debug, Clone, Copy)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct jbd_block_tag3 {
     /// On-disk block number (low 32 bits)
     pub blocknr: u32,
@@ -136,6 +139,7 @@ pub struct jbd_block_tag3 {
 /// 用于标准JBD2和CSUM_V2
 #[repr(C, packed)]
 #[derive(Debug, Clone, Copy)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct jbd_block_tag {
     /// On-disk block number (low 32 bits)
     pub blocknr: u32,
@@ -166,6 +170,7 @@ pub const JBD_FLAG_LAST_TAG: u16 = 8;
 /// 对应 lwext4 的 `struct jbd_block_tail`
 #[repr(C, packed)]
 #[derive(Debug, Clone, Copy)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct jbd_block_tail {
     /// Checksum of the descriptor block
     pub checksum: u32,
@@ -180,6 +185,7 @@ pub struct jbd_block_tail {
 /// 对应 lwext4 的 `struct jbd_revoke_header`
 #[repr(C, packed)]
 #[derive(Debug, Clone, Copy)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct jbd_revoke_header {
     /// Block header
     pub header: jbd_bhdr,
@@ -192,6 +198,7 @@ pub struct jbd_revoke_header {
 /// 对应 lwext4 的 `struct jbd_revoke_tail`
 #[repr(C, packed)]
 #[derive(Debug, Clone, Copy)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct jbd_revoke_tail {
     /// Checksum of the revoke block
     pub checksum: u32,
@@ -237,6 +244,7 @@ pub struct jbd_revoke_tail {
 /// ```
 #[repr(C, packed)]
 #[derive(Debug, Clone, Copy)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct jbd_sb {
     /* 0x0000 */
     /// Block header
@@ -290,12 +298,14 @@ pub struct jbd_sb {
     /// Padding
     pub padding2: [u8; 3],
     /// Reserved padding (42 u32s = 168 bytes)
+    #[cfg_attr(feature = "serde", serde(with = "crate::disk::serde_array"))]
     pub padding: [u32; 42],
     /// CRC32C checksum of superblock
     pub checksum: u32,
 
     /* 0x0100 */
     /// IDs of all filesystems sharing the log
+    #[cfg_attr(feature = "serde", serde(with = "crate::disk::serde_array"))]
     pub users: [u8; JBD_USERS_SIZE],
 
     /* 0x0400 - Total size: 1024 bytes */
@@ -326,7 +336,7 @@ pub const JBD_FEATURE_INCOMPAT_CSUM_V2: u32 = 0x00000008;
 pub const JBD_FEATURE_INCOMPAT_CSUM_V3: u32 = 0x00000010;
 
 /// Known compatible features
-pub const JBD_KNOWN_COMPAT_FEATURES: u32 = 0;
+pub const JBD_KNOWN_COMPAT_FEATURES: u32 = JBD_FEATURE_COMPAT_CHECKSUM;
 /// Known read-only compatible features
 pub const JBD_KNOWN_ROCOMPAT_FEATURES: u32 = 0;
 /// Known incompatible features
@@ -378,6 +388,40 @@ impl jbd_sb {
     pub fn is_64bit(&self) -> bool {
         self.has_incompat_feature(JBD_FEATURE_INCOMPAT_64BIT)
     }
+
+    /// Check if journal metadata blocks carry crc32c checksums (v2 or v3)
+    pub fn has_csum_v2or3(&self) -> bool {
+        self.has_incompat_feature(JBD_FEATURE_INCOMPAT_CSUM_V2 | JBD_FEATURE_INCOMPAT_CSUM_V3)
+    }
+
+    /// Size of one block tag in a descriptor block
+    ///
+    /// Same as the kernel's `journal_tag_bytes()`: v3 uses [`jbd_block_tag3`],
+    /// otherwise [`jbd_block_tag`] plus 2 bytes for v2, without `blocknr_high`
+    /// unless the journal is 64-bit
+    pub fn tag_bytes(&self) -> usize {
+        if self.has_incompat_feature(JBD_FEATURE_INCOMPAT_CSUM_V3) {
+            return core::mem::size_of::<jbd_block_tag3>();
+        }
+        let mut size = core::mem::size_of::<jbd_block_tag>();
+        if self.has_incompat_feature(JBD_FEATURE_INCOMPAT_CSUM_V2) {
+            size += core::mem::size_of::<u16>();
+        }
+        if self.is_64bit() {
+            size
+        } else {
+            size - core::mem::size_of::<u32>()
+        }
+    }
+
+    /// Size of one revoke record (block number)
+    pub fn revoke_record_bytes(&self) -> usize {
+        if self.is_64bit() {
+            core::mem::size_of::<u64>()
+        } else {
+            core::mem::size_of::<u32>()
+        }
+    }
 }
 
 impl Default for jbd_sb {
@@ -493,6 +537,21 @@ mod tests {
         );
     }
 
+    #[test]
+    fn test_tag_bytes() {
+        let mut sb = jbd_sb::default();
+        assert_eq!(sb.tag_bytes(), 8);
+        assert_eq!(sb.revoke_record_bytes(), 4);
+
+        sb.feature_incompat = (JBD_FEATURE_INCOMPAT_64BIT | JBD_FEATURE_INCOMPAT_CSUM_V2).to_be();
+        assert_eq!(sb.tag_bytes(), 14);
+        assert_eq!(sb.revoke_record_bytes(), 8);
+
+        sb.feature_incompat = JBD_FEATURE_INCOMPAT_CSUM_V3.to_be();
+        assert!(sb.has_csum_v2or3());
+        assert_eq!(sb.tag_bytes(), 16);
+    }
+
     #[test]
     fn test_bhdr_endian() {
         let header = jbd_bhdr::new(JBD_DESCRIPTOR_BLOCK, 100);
@@ -39,14 +39,35 @@
 //! - [`block`] - 块设备抽象和 I/O 操作
 //! - [`consts`] - 常量定义
 //! - [`types`] - 数据结构定义
+//! - [`disk`] - 磁盘格式结构（供外部工具解析原始块）
+//! - [`parse`] - 目录项、extent 节点和 xattr 条目的边界检查解析（可作为 fuzz target）
 //! - [`superblock`] - Superblock 操作
 //! - [`c_api`] - C API 兼容层（可选）
+//!
+//! # Cargo features
+//!
+//! | feature       | 默认 | 内容                                               |
+//! |---------------|------|----------------------------------------------------|
+//! | `journal`     | ✅   | JBD2 日志模块                                       |
+//! | `xattr`       | ✅   | 扩展属性；关闭时 xattr API 返回 `Unsupported`        |
+//! | `htree-write` | ✅   | HTree 目录块分裂；关闭时叶子块满返回 `Unsupported`   |
+//! | `indirect`    | ✅   | 间接块映射；关闭时非 extent 文件返回 `Unsupported`   |
+//! | `std`         |      | 标准库支持，镜像文件设备 `FileBlockDevice`          |
+//! | `mmap`        |      | `FileBlockDevice` 的内存映射（隐含 `std`）           |
+//! | `c-api`       |      | C API 兼容层                                        |
+//! | `serde`       |      | 为 [`disk`] 中的磁盘结构派生 `Serialize`/`Deserialize` |
+//! | `testing`     |      | 故障注入块设备和 `crash_and_remount`，用于崩溃一致性测试 |
+//!
+//! 使用 `--no-default-features` 只编译 extent 文件和目录支持。
 
 #![no_std]
 #![deny(unsafe_op_in_unsafe_fn)]
 #![warn(missing_docs)]
 
 extern crate alloc;
+#[cfg(feature = "std")]
+#[macro_use]
+extern crate std;
 
 // ===== 核心模块 =====
 
@@ -62,6 +83,9 @@ pub mod consts;
 /// 数据结构定义
 pub mod types;
 
+/// 磁盘格式结构（供外部工具解析原始块）
+pub mod disk;
+
 /// Superblock 操作
 pub mod superblock;
 
@@ -75,6 +99,7 @@ pub mod block_group;
 pub mod extent;
 
 /// Indirect blocks 操作（传统 ext2/ext3 间接块寻址）
+#[cfg(feature = "indirect")]
 pub mod indirect;
 
 /// 目录操作
@@ -99,14 +124,28 @@ pub mod balloc;
 pub mod transaction;
 
 /// Journal (JBD2) 系统
+#[cfg(feature = "journal")]
 pub mod journal;
 
 /// Extended Attributes (xattr)
 pub mod xattr;
 
+/// 磁盘配额
+pub mod quota;
+
+/// 日志配置
+pub mod logging;
+
+/// 磁盘结构的边界检查解析
+pub mod parse;
+
 /// CRC32C 校验和计算
 pub(crate) mod crc;
 
+/// 崩溃一致性测试工具
+#[cfg(any(test, feature = "testing"))]
+pub mod testing;
+
 // ===== C API 兼容层（可选）=====
 
 /// C API 兼容层
@@ -120,15 +159,29 @@ pub mod c_api;
 // 错误处理
 pub use error::{Error, ErrorKind, Result};
 
+// 日志配置
+pub use logging::LogConfig;
+
+// 块号类型
+pub use types::{ByteOff, Lblk, Pblk};
+
 // 块设备
-pub use block::{BlockDevice, BlockDev, Block};
+pub use block::{
+    BlockDevice, BlockDev, Block, CountingObserver, FsObserver, FsOp, MemBlockDevice, MemoryOverlay,
+    NoopObserver, ObserverCounts, OpStats, OverlayDevice, OverlayStore, PartitionDevice, WriteHeatMap,
+};
+#[cfg(feature = "std")]
+pub use block::FileBlockDevice;
 
 // Superblock
-pub use superblock::{Superblock, read_superblock};
+pub use superblock::{ErrorRecord, ErrorState, FeatureReport, Superblock, read_superblock};
 
 // Inode
 pub use inode::{Inode, read_inode};
 
+// Inode allocation
+pub use ialloc::InodeAllocPolicy;
+
 // BlockGroup
 pub use block_group::{BlockGroup, read_block_group_desc, write_block_group_desc};
 
@@ -136,29 +189,42 @@ pub use block_group::{BlockGroup, read_block_group_desc, write_block_group_desc}
 pub use extent::ExtentTree;
 
 // Indirect blocks
+#[cfg(feature = "indirect")]
 pub use indirect::IndirectBlockMapper;
 
 // Dir
-pub use dir::{DirEntry, DirIterator, DirReader, PathLookup, read_dir, lookup_path, get_inode_ref_by_path};
+pub use dir::{DirEntry, DirIterator, DirOrder, DirReader, PathLookup, read_dir, read_dir_sorted, lookup_path, lookup_path_at, get_inode_ref_by_path};
 
 // FileSystem
 pub use fs::{
-    Ext4FileSystem, File, FileMetadata, FileType,
-    FileAttr, FsConfig, InodeType, StatFs, SystemHal,
-    InodeRef, BlockGroupRef,
+    Ext4FileSystem, File, FileMetadata, FileType, OpenOptions, SeekFrom,
+    CreatePolicy, FileAttr, FollowSymlink, FsConfig, GroupWrites, InodeType, StatFs, SystemHal,
+    InodeRef, BlockGroupRef, ExtentMapping, MappingFlags, copy_between, move_between, makedev, major, minor,
+    BadRange, ScrubIssue, ScrubProgress, ScrubReport,
+    SpaceEstimate, SpaceEstimateRequest, DEFAULT_COMMIT_INTERVAL, PageCacheStats, DefragReport, MAX_SYMLINK_FOLLOW, InodeHandle, FileHasher, Batch, DirStream, ReadDirOptions, FsckReason,
 };
+pub use fs::ops;
+pub use fs::export;
+pub use fs::import;
+#[cfg(feature = "sync")]
+pub use fs::SyncExt4FileSystem;
 
 // Cache
-pub use cache::{BlockCache, CacheBuffer, CacheFlags, CacheStats, DEFAULT_CACHE_SIZE};
+pub use cache::{BlockCache, CacheBuffer, CacheFlags, CachePolicy, CacheStats, WritebackConfig, DEFAULT_CACHE_SIZE};
 
 // Transaction
 pub use transaction::SimpleTransaction;
 
 // Journal
-pub use journal::{JbdFs, JbdJournal, JbdTrans, JbdBuf, JournalError};
+#[cfg(feature = "journal")]
+pub use journal::{JbdFs, JbdJournal, JbdTrans, JbdBuf, JournalDataMode, JournalError};
+
+// Quota
+pub use quota::{QuotaId, QuotaLimits, QuotaType, QuotaUsage};
 
 // Xattr
 pub use xattr::{list as xattr_list, get as xattr_get, set as xattr_set, remove as xattr_remove};
+pub use xattr::{iter as xattr_iter, get_size as xattr_get_size, XattrEntry, XattrNamespace};
 
 // C API（当启用时）
 #[cfg(feature = "c-api")]
@@ -166,3 +232,8 @@ pub use c_api::block::{
     ext4_blocks_get_direct, ext4_blocks_set_direct, ext4_block_readbytes,
     ext4_block_writebytes, ext4_block_cache_flush,
 };
+#[cfg(feature = "c-api")]
+pub use c_api::blockdev::{
+    ext4_blockdev, ext4_blockdev_iface, ext4_device_register, ext4_device_unregister,
+    ext4_device_unregister_all, CBlockDevice,
+};
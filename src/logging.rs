@@ -0,0 +1,242 @@
+//! 日志配置
+//!
+//! 本 crate 通过 `log` crate 输出日志。extent、块分配、日志（JBD2）和目录子系统的日志
+//! 使用固定的 target（见 [`Subsystem::target`]），宿主的 logger 可以按 target 过滤；
+//! 在无法配置 logger 的内核中，可以用 [`LogConfig`] 在本 crate 内部过滤：
+//! - 每个子系统单独的级别上限
+//! - 热路径日志（每次块分配、每次 extent 合并）的级别由 [`LogConfig::hot_path`] 决定，
+//!   默认为 `Trace`
+//! - 限流：每个周期内每个子系统最多输出 [`RateLimit::burst`] 条，超出的被丢弃并计数。
+//!   周期由 [`new_period`] 开始，[`Ext4FileSystem::on_timer_tick`](crate::Ext4FileSystem::on_timer_tick)
+//!   每次调用都会开始新的周期
+//!
+//! 配置是全局的，对所有挂载的文件系统生效。
+//!
+//! ```rust,ignore
+//! use lwext4_core::logging::{self, LogConfig, RateLimit};
+//! use log::LevelFilter;
+//!
+//! logging::set_log_config(&LogConfig {
+//!     extent: LevelFilter::Warn,
+//!     balloc: LevelFilter::Warn,
+//!     rate_limit: Some(RateLimit { burst: 20 }),
+//!     ..LogConfig::new()
+//! });
+//! ```
+
+use core::sync::atomic::{AtomicU32, AtomicU8, Ordering};
+use log::{Level, LevelFilter};
+
+/// 可以单独配置的子系统
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Subsystem {
+    /// extent 树
+    Extent,
+    /// 块分配
+    Balloc,
+    /// JBD2 日志
+    Journal,
+    /// 目录
+    Dir,
+}
+
+impl Subsystem {
+    /// 子系统数量
+    pub const COUNT: usize = 4;
+
+    /// 所有子系统
+    pub const ALL: [Subsystem; Self::COUNT] = [Self::Extent, Self::Balloc, Self::Journal, Self::Dir];
+
+    /// 日志的 target，是模块路径的前缀，按模块过滤的 logger 配置不受影响
+    pub const fn target(self) -> &'static str {
+        match self {
+            Self::Extent => "lwext4_core::extent",
+            Self::Balloc => "lwext4_core::balloc",
+            Self::Journal => "lwext4_core::journal",
+            Self::Dir => "lwext4_core::dir",
+        }
+    }
+
+    const fn index(self) -> usize {
+        self as usize
+    }
+}
+
+/// 限流参数
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct RateLimit {
+    /// 每个周期内每个子系统最多输出的日志条数，`Error` 级别的日志不受限制
+    pub burst: u32,
+}
+
+/// 日志配置，见 [模块文档](self)
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct LogConfig {
+    /// extent 树的级别上限
+    pub extent: LevelFilter,
+    /// 块分配的级别上限
+    pub balloc: LevelFilter,
+    /// JBD2 日志的级别上限
+    pub journal: LevelFilter,
+    /// 目录的级别上限
+    pub dir: LevelFilter,
+    /// 热路径日志的级别
+    pub hot_path: Level,
+    /// 限流，`None` 表示不限流
+    pub rate_limit: Option<RateLimit>,
+}
+
+impl LogConfig {
+    /// 默认配置：不在本 crate 内过滤，热路径日志为 `Trace`，不限流
+    pub const fn new() -> Self {
+        Self {
+            extent: LevelFilter::Trace,
+            balloc: LevelFilter::Trace,
+            journal: LevelFilter::Trace,
+            dir: LevelFilter::Trace,
+            hot_path: Level::Trace,
+            rate_limit: None,
+        }
+    }
+
+    /// 子系统的级别上限
+    pub fn level(&self, subsystem: Subsystem) -> LevelFilter {
+        match subsystem {
+            Subsystem::Extent => self.extent,
+            Subsystem::Balloc => self.balloc,
+            Subsystem::Journal => self.journal,
+            Subsystem::Dir => self.dir,
+        }
+    }
+}
+
+impl Default for LogConfig {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// 不限流时的 burst
+const UNLIMITED: u32 = u32::MAX;
+
+static LEVELS: [AtomicU8; Subsystem::COUNT] = [const { AtomicU8::new(LevelFilter::Trace as u8) }; Subsystem::COUNT];
+static HOT_PATH: AtomicU8 = AtomicU8::new(Level::Trace as u8);
+static BURST: AtomicU32 = AtomicU32::new(UNLIMITED);
+/// 本周期内各子系统已输出的条数
+static EMITTED: [AtomicU32; Subsystem::COUNT] = [const { AtomicU32::new(0) }; Subsystem::COUNT];
+/// 本周期内各子系统被限流丢弃的条数
+static SUPPRESSED: [AtomicU32; Subsystem::COUNT] = [const { AtomicU32::new(0) }; Subsystem::COUNT];
+
+/// 设置全局日志配置
+pub fn set_log_config(config: &LogConfig) {
+    for subsystem in Subsystem::ALL {
+        LEVELS[subsystem.index()].store(config.level(subsystem) as u8, Ordering::Relaxed);
+    }
+    HOT_PATH.store(config.hot_path as u8, Ordering::Relaxed);
+    BURST.store(config.rate_limit.map_or(UNLIMITED, |limit| limit.burst), Ordering::Relaxed);
+}
+
+/// 当前的全局日志配置
+pub fn log_config() -> LogConfig {
+    let level = |subsystem: Subsystem| level_filter_from_u8(LEVELS[subsystem.index()].load(Ordering::Relaxed));
+    let burst = BURST.load(Ordering::Relaxed);
+    LogConfig {
+        extent: level(Subsystem::Extent),
+        balloc: level(Subsystem::Balloc),
+        journal: level(Subsystem::Journal),
+        dir: level(Subsystem::Dir),
+        hot_path: hot_path_level(),
+        rate_limit: (burst != UNLIMITED).then_some(RateLimit { burst }),
+    }
+}
+
+/// 开始新的限流周期
+///
+/// 上一周期有日志被丢弃时，以 `Warn` 级别报告各子系统丢弃的条数
+pub fn new_period() {
+    for subsystem in Subsystem::ALL {
+        EMITTED[subsystem.index()].store(0, Ordering::Relaxed);
+        let dropped = SUPPRESSED[subsystem.index()].swap(0, Ordering::Relaxed);
+        if dropped > 0 {
+            log::warn!(target: subsystem.target(), "[LOG] {dropped} messages suppressed by rate limit");
+        }
+    }
+}
+
+/// 本周期内子系统被限流丢弃的日志条数
+pub fn suppressed(subsystem: Subsystem) -> u32 {
+    SUPPRESSED[subsystem.index()].load(Ordering::Relaxed)
+}
+
+/// 热路径日志的级别
+pub(crate) fn hot_path_level() -> Level {
+    level_from_u8(HOT_PATH.load(Ordering::Relaxed))
+}
+
+/// 判断一条日志是否输出，输出时返回 target
+///
+/// 先检查 `log` 的全局级别，logger 不接收的日志不计入限流
+pub(crate) fn admit(subsystem: Subsystem, level: Level) -> Option<&'static str> {
+    if level > log::max_level() || level > level_filter_from_u8(LEVELS[subsystem.index()].load(Ordering::Relaxed)) {
+        return None;
+    }
+    if level != Level::Error {
+        let burst = BURST.load(Ordering::Relaxed);
+        if burst != UNLIMITED && EMITTED[subsystem.index()].fetch_add(1, Ordering::Relaxed) >= burst {
+            SUPPRESSED[subsystem.index()].fetch_add(1, Ordering::Relaxed);
+            return None;
+        }
+    }
+    Some(subsystem.target())
+}
+
+fn level_filter_from_u8(value: u8) -> LevelFilter {
+    LevelFilter::iter().nth(value as usize).unwrap_or(LevelFilter::Trace)
+}
+
+fn level_from_u8(value: u8) -> Level {
+    Level::iter().nth((value as usize).saturating_sub(1)).unwrap_or(Level::Trace)
+}
+
+/// 按子系统配置输出日志
+///
+/// `ext4_log!(Extent, Debug, "...", args)` 以固定级别输出；
+/// `ext4_log!(Balloc, hot, "...", args)` 用于热路径，级别取 [`LogConfig::hot_path`]
+macro_rules! ext4_log {
+    ($subsystem:ident, hot, $($arg:tt)+) => {
+        $crate::logging::ext4_log!(@emit $subsystem, $crate::logging::hot_path_level(), $($arg)+)
+    };
+    (@emit $subsystem:ident, $level:expr, $($arg:tt)+) => {{
+        let level = $level;
+        if let Some(target) = $crate::logging::admit($crate::logging::Subsystem::$subsystem, level) {
+            log::log!(target: target, level, $($arg)+);
+        }
+    }};
+    ($subsystem:ident, $level:ident, $($arg:tt)+) => {
+        $crate::logging::ext4_log!(@emit $subsystem, log::Level::$level, $($arg)+)
+    };
+}
+pub(crate) use ext4_log;
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_level_encoding() {
+        for filter in LevelFilter::iter() {
+            assert_eq!(level_filter_from_u8(filter as u8), filter);
+        }
+        for level in Level::iter() {
+            assert_eq!(level_from_u8(level as u8), level);
+        }
+    }
+
+    #[test]
+    fn test_config_level() {
+        let config = LogConfig { balloc: LevelFilter::Warn, ..LogConfig::new() };
+        assert_eq!(config.level(Subsystem::Balloc), LevelFilter::Warn);
+        assert_eq!(config.level(Subsystem::Extent), LevelFilter::Trace);
+        assert!(Subsystem::ALL.iter().all(|s| s.target().starts_with("lwext4_core::")));
+    }
+}
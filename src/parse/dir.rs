@@ -0,0 +1,130 @@
+//! 线性目录块
+
+use alloc::vec::Vec;
+
+use crate::{
+    consts::EXT4_DIR_ENTRY_MIN_LEN,
+    error::{Error, ErrorKind, Result},
+};
+
+use super::{read_bytes, read_u16, read_u32};
+
+/// 目录块中的一个目录项，名称借用块数据
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct RawDirEntry<'a> {
+    /// 在块内的偏移
+    pub offset: usize,
+    /// inode 编号，0 表示空闲（已删除的项、校验和尾部）
+    pub inode: u32,
+    /// 目录项长度，包括到下一项之前的空闲空间
+    pub rec_len: u16,
+    /// 文件类型（`EXT4_DE_*`），没有 `filetype` 特性时为名称长度的高字节
+    pub file_type: u8,
+    /// 名称
+    pub name: &'a [u8],
+}
+
+/// 解析 `offset` 处的目录项
+///
+/// 与内核的 `ext4_check_dir_entry()` 相同，检查：
+/// - 偏移 4 字节对齐，8 字节头部在块内
+/// - `rec_len` 不小于 8、4 字节对齐且不超出块
+/// - 名称在 `rec_len` 之内
+pub fn dir_entry_at(block: &[u8], offset: usize) -> Result<RawDirEntry<'_>> {
+    if offset % 4 != 0 {
+        return Err(Error::new(ErrorKind::Corrupted, "Directory entry not 4-byte aligned"));
+    }
+    let header = read_bytes(block, offset, EXT4_DIR_ENTRY_MIN_LEN)
+        .map_err(|_| Error::new(ErrorKind::Corrupted, "Directory entry header extends beyond block"))?;
+    let inode = read_u32(header, 0)?;
+    let rec_len = read_u16(header, 4)?;
+    let name_len = header[6] as usize;
+    let file_type = header[7];
+
+    if (rec_len as usize) < EXT4_DIR_ENTRY_MIN_LEN || rec_len % 4 != 0 {
+        return Err(Error::new(ErrorKind::Corrupted, "Directory entry rec_len invalid"));
+    }
+    if rec_len as usize > block.len() - offset {
+        return Err(Error::new(ErrorKind::Corrupted, "Directory entry rec_len extends beyond block"));
+    }
+    if name_len > rec_len as usize - EXT4_DIR_ENTRY_MIN_LEN {
+        return Err(Error::new(ErrorKind::Corrupted, "Directory entry name_len too large"));
+    }
+
+    Ok(RawDirEntry {
+        offset,
+        inode,
+        rec_len,
+        file_type,
+        name: read_bytes(block, offset + EXT4_DIR_ENTRY_MIN_LEN, name_len)?,
+    })
+}
+
+/// 解析整个线性目录块
+///
+/// 返回所有目录项，包括 inode 为 0 的空闲项。目录项必须首尾相接并恰好覆盖整个块
+pub fn parse_dir_block(block: &[u8]) -> Result<Vec<RawDirEntry<'_>>> {
+    let mut entries = Vec::new();
+    let mut offset = 0;
+    while offset < block.len() {
+        let entry = dir_entry_at(block, offset)?;
+        offset += entry.rec_len as usize;
+        entries.push(entry);
+    }
+    Ok(entries)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use alloc::vec;
+
+    fn put_entry(block: &mut [u8], offset: usize, inode: u32, rec_len: u16, name: &[u8]) {
+        block[offset..offset + 4].copy_from_slice(&inode.to_le_bytes());
+        block[offset + 4..offset + 6].copy_from_slice(&rec_len.to_le_bytes());
+        block[offset + 6] = name.len() as u8;
+        block[offset + 7] = 1;
+        block[offset + 8..offset + 8 + name.len()].copy_from_slice(name);
+    }
+
+    #[test]
+    fn test_parse_dir_block() {
+        let mut block = vec![0u8; 64];
+        put_entry(&mut block, 0, 2, 12, b".");
+        put_entry(&mut block, 12, 2, 12, b"..");
+        put_entry(&mut block, 24, 0, 12, b"");
+        put_entry(&mut block, 36, 12, 28, b"hello");
+
+        let entries = parse_dir_block(&block).unwrap();
+        assert_eq!(entries.len(), 4);
+        assert_eq!((entries[1].offset, entries[1].name), (12, &b".."[..]));
+        assert_eq!(entries[2].inode, 0);
+        assert_eq!((entries[3].inode, entries[3].name), (12, &b"hello"[..]));
+    }
+
+    #[test]
+    fn test_malformed_dir_entries() {
+        let mut block = vec![0u8; 32];
+        // rec_len 为 0（全零块）
+        assert!(parse_dir_block(&block).is_err());
+        // rec_len 小于头部
+        put_entry(&mut block, 0, 2, 4, b"");
+        assert!(dir_entry_at(&block, 0).is_err());
+        // rec_len 超出块
+        put_entry(&mut block, 0, 2, 36, b"");
+        assert!(dir_entry_at(&block, 0).is_err());
+        // name_len 超出 rec_len
+        put_entry(&mut block, 0, 2, 12, b"abcde");
+        assert!(dir_entry_at(&block, 0).is_err());
+        // 头部不完整、偏移不对齐、偏移越界
+        assert!(dir_entry_at(&block, 28).is_err());
+        assert!(dir_entry_at(&block, 2).is_err());
+        assert!(dir_entry_at(&block, usize::MAX - 3).is_err());
+        // 最后一项没有到达块尾
+        put_entry(&mut block, 0, 2, 12, b"a");
+        put_entry(&mut block, 12, 3, 12, b"b");
+        assert!(parse_dir_block(&block).is_err());
+        assert!(parse_dir_block(&block[..24]).is_ok());
+        assert!(parse_dir_block(&[]).unwrap().is_empty());
+    }
+}
@@ -0,0 +1,201 @@
+//! extent 树节点
+
+use alloc::vec::Vec;
+
+use crate::{
+    consts::{EXT4_EXTENT_MAGIC, EXT4_EXTENT_MAX_DEPTH},
+    error::{Error, ErrorKind, Result},
+    extent::{ExtentRange, EXT_INIT_MAX_LEN},
+};
+
+use super::{read_u16, read_u32};
+
+/// header 和每个条目（extent 或索引）的大小
+const ENTRY_SIZE: usize = 12;
+
+/// 物理块号的位数
+const PBLK_BITS: u32 = 48;
+
+/// 已检查的 extent 节点头部
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ExtentHeader {
+    /// 有效条目数
+    pub entries: u16,
+    /// 节点能容纳的条目数
+    pub max: u16,
+    /// 节点在树中的高度，0 表示叶子
+    pub depth: u16,
+    /// 树的 generation（ext4 未使用）
+    pub generation: u32,
+}
+
+impl ExtentHeader {
+    /// 是否是叶子节点
+    pub fn is_leaf(&self) -> bool {
+        self.depth == 0
+    }
+}
+
+/// 索引节点中的一项
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ExtentIndex {
+    /// 子树覆盖的第一个逻辑块
+    pub logical_block: u32,
+    /// 子节点所在的物理块
+    pub leaf: u64,
+}
+
+/// 解析后的 extent 节点
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ExtentNode {
+    /// 叶子节点，按逻辑块号升序
+    Leaf(Vec<ExtentRange>),
+    /// 索引节点，按逻辑块号严格升序
+    Index(Vec<ExtentIndex>),
+}
+
+/// 解析并检查 extent 节点头部
+///
+/// `node` 是整个节点：inode 中的 60 字节根节点或一个 extent 块。检查：
+/// - 魔数
+/// - `max` 不为 0，且 `max` 个条目能放进 `node`
+/// - `entries` 不超过 `max`
+/// - 深度不超过 [`EXT4_EXTENT_MAX_DEPTH`]
+pub fn extent_header(node: &[u8]) -> Result<ExtentHeader> {
+    if read_u16(node, 0)? != EXT4_EXTENT_MAGIC {
+        return Err(Error::new(ErrorKind::Corrupted, "invalid extent magic number"));
+    }
+    let header = ExtentHeader {
+        entries: read_u16(node, 2)?,
+        max: read_u16(node, 4)?,
+        depth: read_u16(node, 6)?,
+        generation: read_u32(node, 8)?,
+    };
+
+    if header.max == 0 || (header.max as usize + 1) * ENTRY_SIZE > node.len() {
+        return Err(Error::new(ErrorKind::Corrupted, "invalid max entries"));
+    }
+    if header.entries > header.max {
+        return Err(Error::new(ErrorKind::Corrupted, "invalid entries count"));
+    }
+    if header.depth > EXT4_EXTENT_MAX_DEPTH as u16 {
+        return Err(Error::new(ErrorKind::Corrupted, "extent tree too deep"));
+    }
+    Ok(header)
+}
+
+/// 解析整个 extent 节点
+///
+/// 在 [`extent_header`] 的基础上，与内核的 `ext4_valid_extent_entries()` 相同检查条目：
+/// - 叶子：长度不为 0，逻辑范围不溢出，物理范围不超过 48 位，相互不重叠且升序
+/// - 索引：逻辑块号严格升序
+pub fn parse_extent_node(node: &[u8]) -> Result<ExtentNode> {
+    let header = extent_header(node)?;
+    let offsets = (1..=header.entries as usize).map(|i| i * ENTRY_SIZE);
+
+    if !header.is_leaf() {
+        let mut indexes: Vec<ExtentIndex> = Vec::with_capacity(header.entries as usize);
+        for offset in offsets {
+            let logical_block = read_u32(node, offset)?;
+            let leaf = read_u32(node, offset + 4)? as u64 | (read_u16(node, offset + 8)? as u64) << 32;
+            if indexes.last().is_some_and(|prev| prev.logical_block >= logical_block) {
+                return Err(Error::new(ErrorKind::Corrupted, "extent index entries out of order"));
+            }
+            indexes.push(ExtentIndex { logical_block, leaf });
+        }
+        return Ok(ExtentNode::Index(indexes));
+    }
+
+    let mut ranges: Vec<ExtentRange> = Vec::with_capacity(header.entries as usize);
+    let mut next_free = 0u64;
+    for offset in offsets {
+        let logical_start = read_u32(node, offset)?;
+        let raw_len = read_u16(node, offset + 4)?;
+        let physical_start = (read_u16(node, offset + 6)? as u64) << 32 | read_u32(node, offset + 8)? as u64;
+        let unwritten = raw_len > EXT_INIT_MAX_LEN;
+        let len = if unwritten { raw_len - EXT_INIT_MAX_LEN } else { raw_len } as u32;
+
+        let logical_end = logical_start as u64 + len as u64;
+        if len == 0 || logical_end > 1 << 32 || physical_start + len as u64 > 1 << PBLK_BITS {
+            return Err(Error::new(ErrorKind::Corrupted, "invalid extent range"));
+        }
+        if (logical_start as u64) < next_free {
+            return Err(Error::new(ErrorKind::Corrupted, "extents overlap or out of order"));
+        }
+        next_free = logical_end;
+        ranges.push(ExtentRange { logical_start, len, physical_start, unwritten });
+    }
+    Ok(ExtentNode::Leaf(ranges))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use alloc::vec;
+
+    fn node(entries: u16, max: u16, depth: u16, len: usize) -> Vec<u8> {
+        let mut node = vec![0u8; len];
+        node[0..2].copy_from_slice(&EXT4_EXTENT_MAGIC.to_le_bytes());
+        node[2..4].copy_from_slice(&entries.to_le_bytes());
+        node[4..6].copy_from_slice(&max.to_le_bytes());
+        node[6..8].copy_from_slice(&depth.to_le_bytes());
+        node
+    }
+
+    fn put_extent(node: &mut [u8], i: usize, lblk: u32, len: u16, pblk: u64) {
+        let off = (i + 1) * ENTRY_SIZE;
+        node[off..off + 4].copy_from_slice(&lblk.to_le_bytes());
+        node[off + 4..off + 6].copy_from_slice(&len.to_le_bytes());
+        node[off + 6..off + 8].copy_from_slice(&((pblk >> 32) as u16).to_le_bytes());
+        node[off + 8..off + 12].copy_from_slice(&(pblk as u32).to_le_bytes());
+    }
+
+    #[test]
+    fn test_extent_header() {
+        assert_eq!(extent_header(&node(4, 4, 1, 60)).unwrap().depth, 1);
+        assert!(extent_header(&[]).is_err());
+        assert!(extent_header(&node(0, 4, 0, 59)).is_err());
+        assert!(extent_header(&node(0, 0, 0, 60)).is_err());
+        assert!(extent_header(&node(5, 4, 0, 60)).is_err());
+        assert!(extent_header(&node(0, 4, EXT4_EXTENT_MAX_DEPTH as u16 + 1, 60)).is_err());
+        // 内核为校验和尾部留出 4 字节后的 max
+        assert!(extent_header(&node(0, 340, 0, 4096)).is_ok());
+        assert!(extent_header(&node(0, 0xFFFF, 0, 4096)).is_err());
+    }
+
+    #[test]
+    fn test_parse_extent_leaf() {
+        let mut leaf = node(2, 4, 0, 60);
+        put_extent(&mut leaf, 0, 0, 8, 1000);
+        put_extent(&mut leaf, 1, 8, EXT_INIT_MAX_LEN + 4, 0x1_0000_2000);
+        let ExtentNode::Leaf(ranges) = parse_extent_node(&leaf).unwrap() else { panic!() };
+        assert_eq!(ranges[0], ExtentRange { logical_start: 0, len: 8, physical_start: 1000, unwritten: false });
+        assert_eq!(ranges[1], ExtentRange { logical_start: 8, len: 4, physical_start: 0x1_0000_2000, unwritten: true });
+
+        // 重叠
+        put_extent(&mut leaf, 1, 7, 1, 2000);
+        assert!(parse_extent_node(&leaf).is_err());
+        // 长度为 0
+        put_extent(&mut leaf, 1, 8, 0, 2000);
+        assert!(parse_extent_node(&leaf).is_err());
+        // 逻辑范围溢出
+        put_extent(&mut leaf, 1, u32::MAX, 2, 2000);
+        assert!(parse_extent_node(&leaf).is_err());
+        // 物理范围超出 48 位
+        put_extent(&mut leaf, 1, 8, 2, (1 << PBLK_BITS) - 1);
+        assert!(parse_extent_node(&leaf).is_err());
+    }
+
+    #[test]
+    fn test_parse_extent_index() {
+        let mut index = node(2, 4, 1, 60);
+        put_extent(&mut index, 0, 0, 0, 0);
+        put_extent(&mut index, 1, 100, 0, 0);
+        index[ENTRY_SIZE + 4..ENTRY_SIZE + 8].copy_from_slice(&500u32.to_le_bytes());
+        let ExtentNode::Index(indexes) = parse_extent_node(&index).unwrap() else { panic!() };
+        assert_eq!(indexes[0], ExtentIndex { logical_block: 0, leaf: 500 });
+
+        put_extent(&mut index, 1, 0, 0, 0);
+        assert!(parse_extent_node(&index).is_err());
+    }
+}
@@ -0,0 +1,75 @@
+//! 磁盘结构的边界检查解析
+//!
+//! 目录块、extent 节点和 xattr 区域中的偏移和长度都直接来自磁盘，镜像损坏时
+//! 按这些值切片会越界 panic，在内核中就是整个系统崩溃。本模块只做带边界检查的读取，
+//! 越界、长度不一致或顺序错误都返回 `ErrorKind::Corrupted`。
+//!
+//! [`parse_dir_block`]、[`parse_extent_node`]、[`parse_xattr_block`] 和
+//! [`parse_xattr_ibody`] 是纯函数，对任意输入都不会 panic，可以直接作为 fuzz target：
+//!
+//! ```rust,ignore
+//! // fuzz/fuzz_targets/parse.rs
+//! #![no_main]
+//! use libfuzzer_sys::fuzz_target;
+//! use lwext4_core::parse;
+//!
+//! fuzz_target!(|data: &[u8]| {
+//!     let _ = parse::parse_dir_block(data);
+//!     let _ = parse::parse_extent_node(data);
+//!     let _ = parse::parse_xattr_block(data);
+//!     let _ = parse::parse_xattr_ibody(data);
+//! });
+//! ```
+
+mod dir;
+mod extent;
+mod xattr;
+
+pub use dir::{dir_entry_at, parse_dir_block, RawDirEntry};
+pub use extent::{extent_header, parse_extent_node, ExtentHeader, ExtentIndex, ExtentNode};
+pub use xattr::{parse_xattr_block, parse_xattr_entries, parse_xattr_ibody, xattr_entry_at, RawXattrEntry};
+
+use crate::error::{Error, ErrorKind, Result};
+
+/// 读取 `data[offset..offset + N]`
+fn read_array<const N: usize>(data: &[u8], offset: usize) -> Result<[u8; N]> {
+    data.get(offset..)
+        .and_then(|rest| rest.first_chunk::<N>())
+        .copied()
+        .ok_or(Error::new(ErrorKind::Corrupted, "on-disk structure out of bounds"))
+}
+
+/// 读取小端 u16
+pub(crate) fn read_u16(data: &[u8], offset: usize) -> Result<u16> {
+    read_array(data, offset).map(u16::from_le_bytes)
+}
+
+/// 读取小端 u32
+pub(crate) fn read_u32(data: &[u8], offset: usize) -> Result<u32> {
+    read_array(data, offset).map(u32::from_le_bytes)
+}
+
+/// 读取 `data[offset..offset + len]`
+pub(crate) fn read_bytes(data: &[u8], offset: usize, len: usize) -> Result<&[u8]> {
+    offset
+        .checked_add(len)
+        .and_then(|end| data.get(offset..end))
+        .ok_or(Error::new(ErrorKind::Corrupted, "on-disk structure out of bounds"))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_read_bounds() {
+        let data = [1, 2, 3, 4, 5];
+        assert_eq!(read_u16(&data, 3).unwrap(), 0x0504);
+        assert_eq!(read_u32(&data, 1).unwrap(), 0x0504_0302);
+        assert!(read_u16(&data, 4).is_err());
+        assert!(read_u32(&data, usize::MAX).is_err());
+        assert_eq!(read_bytes(&data, 5, 0).unwrap(), &[] as &[u8]);
+        assert!(read_bytes(&data, 1, usize::MAX).is_err());
+        assert!(read_bytes(&data, 6, 0).is_err());
+    }
+}
@@ -0,0 +1,195 @@
+//! 扩展属性条目
+
+use alloc::vec::Vec;
+
+use crate::{
+    consts::{EXT4_XATTR_MAGIC, EXT4_XATTR_ROUND},
+    error::{Error, ErrorKind, Result},
+};
+
+use super::{read_bytes, read_u16, read_u32};
+
+/// 条目固定部分的大小（`ext4_xattr_entry`）
+const ENTRY_SIZE: usize = 16;
+
+/// xattr 块头部的大小（`ext4_xattr_header`）
+const BLOCK_HEADER_SIZE: usize = 32;
+
+/// inode 内部 xattr 头部的大小（`ext4_xattr_ibody_header`）
+const IBODY_HEADER_SIZE: usize = 4;
+
+/// 一个扩展属性条目，名称和值借用所在区域的数据
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct RawXattrEntry<'a> {
+    /// 条目在区域内的偏移
+    pub offset: usize,
+    /// 命名空间索引（`EXT4_XATTR_INDEX_*`）
+    pub name_index: u8,
+    /// 名称（不含前缀）
+    pub name: &'a [u8],
+    /// 值的偏移（`e_value_offs`），值不在本区域内时无意义
+    pub value_offs: u16,
+    /// 值所在的 EA inode，0 表示值在本区域内
+    pub value_inum: u32,
+    /// 值的长度
+    pub value_size: u32,
+    /// 条目哈希
+    pub hash: u32,
+    /// 值在本区域内时为值的数据，否则为空
+    pub value: &'a [u8],
+}
+
+impl RawXattrEntry<'_> {
+    /// 条目（含名称和填充）占用的长度，对应 C 宏 `EXT4_XATTR_LEN`
+    pub fn entry_len(&self) -> usize {
+        (self.name.len() + EXT4_XATTR_ROUND as usize + ENTRY_SIZE) & !(EXT4_XATTR_ROUND as usize)
+    }
+}
+
+/// 解析 `offset` 处的条目，遇到结束标记（4 字节 0）或区域末尾时返回 `None`
+///
+/// 值的偏移相对于 `value_base`。检查条目、名称和本地的值都在 `region` 内
+pub fn xattr_entry_at(region: &[u8], offset: usize, value_base: usize) -> Result<Option<RawXattrEntry<'_>>> {
+    match read_u32(region, offset) {
+        Err(_) | Ok(0) => return Ok(None),
+        Ok(_) => {}
+    }
+    let fixed = read_bytes(region, offset, ENTRY_SIZE)
+        .map_err(|_| Error::new(ErrorKind::Corrupted, "xattr entry out of bounds"))?;
+    let name_len = fixed[0] as usize;
+    let value_offs = read_u16(fixed, 2)?;
+    let value_inum = read_u32(fixed, 4)?;
+    let value_size = read_u32(fixed, 8)?;
+
+    let name = read_bytes(region, offset + ENTRY_SIZE, name_len)
+        .map_err(|_| Error::new(ErrorKind::Corrupted, "xattr entry out of bounds"))?;
+    let value = if value_inum == 0 && value_size > 0 {
+        value_base
+            .checked_add(value_offs as usize)
+            .and_then(|start| read_bytes(region, start, value_size as usize).ok())
+            .ok_or(Error::new(ErrorKind::Corrupted, "xattr value out of bounds"))?
+    } else {
+        &[]
+    };
+
+    Ok(Some(RawXattrEntry {
+        offset,
+        name_index: fixed[1],
+        name,
+        value_offs,
+        value_inum,
+        value_size,
+        hash: read_u32(fixed, 12)?,
+        value,
+    }))
+}
+
+/// 解析从 `first_entry` 开始的所有条目，值的偏移相对于 `value_base`
+///
+/// 除 [`xattr_entry_at`] 的检查外，本地的值不能与条目区域重叠
+pub fn parse_xattr_entries(region: &[u8], first_entry: usize, value_base: usize) -> Result<Vec<RawXattrEntry<'_>>> {
+    let mut entries = Vec::new();
+    let mut offset = first_entry;
+    while let Some(entry) = xattr_entry_at(region, offset, value_base)? {
+        offset += entry.entry_len();
+        entries.push(entry);
+    }
+
+    if entries.iter().any(|e| !e.value.is_empty() && value_base + (e.value_offs as usize) < offset) {
+        return Err(Error::new(ErrorKind::Corrupted, "entry and value regions overlap"));
+    }
+    Ok(entries)
+}
+
+/// 解析独立的 xattr 块
+///
+/// 检查魔数和 `h_blocks`（只支持单块），值的偏移相对于块首
+pub fn parse_xattr_block(block: &[u8]) -> Result<Vec<RawXattrEntry<'_>>> {
+    if block.len() < BLOCK_HEADER_SIZE || read_u32(block, 0)? != EXT4_XATTR_MAGIC {
+        return Err(Error::new(ErrorKind::Corrupted, "invalid xattr block magic"));
+    }
+    if read_u32(block, 8)? != 1 {
+        return Err(Error::new(ErrorKind::Corrupted, "multi-block xattr not supported"));
+    }
+    parse_xattr_entries(block, BLOCK_HEADER_SIZE, 0)
+}
+
+/// 解析 inode 内部的 xattr 区域
+///
+/// `area` 从 `i_extra_isize` 之后的魔数开始，到 inode 末尾结束；
+/// 值的偏移相对于第一个条目
+pub fn parse_xattr_ibody(area: &[u8]) -> Result<Vec<RawXattrEntry<'_>>> {
+    if read_u32(area, 0).ok() != Some(EXT4_XATTR_MAGIC) {
+        return Err(Error::new(ErrorKind::Corrupted, "invalid ibody xattr magic"));
+    }
+    parse_xattr_entries(area, IBODY_HEADER_SIZE, IBODY_HEADER_SIZE)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use alloc::vec;
+
+    fn put_entry(data: &mut [u8], offset: usize, name: &[u8], value_offs: u16, value_size: u32) {
+        data[offset] = name.len() as u8;
+        data[offset + 1] = 1;
+        data[offset + 2..offset + 4].copy_from_slice(&value_offs.to_le_bytes());
+        data[offset + 8..offset + 12].copy_from_slice(&value_size.to_le_bytes());
+        data[offset + ENTRY_SIZE..offset + ENTRY_SIZE + name.len()].copy_from_slice(name);
+    }
+
+    fn block() -> Vec<u8> {
+        let mut block = vec![0u8; 256];
+        block[0..4].copy_from_slice(&EXT4_XATTR_MAGIC.to_le_bytes());
+        block[8..12].copy_from_slice(&1u32.to_le_bytes());
+        block
+    }
+
+    #[test]
+    fn test_parse_xattr_block() {
+        let mut data = block();
+        put_entry(&mut data, 32, b"comment", 248, 5);
+        data[248..253].copy_from_slice(b"hello");
+        put_entry(&mut data, 56, b"big", 0, 100000);
+        data[60..64].copy_from_slice(&42u32.to_le_bytes());
+
+        let entries = parse_xattr_block(&data).unwrap();
+        assert_eq!(entries.len(), 2);
+        assert_eq!((entries[0].name, entries[0].value), (&b"comment"[..], &b"hello"[..]));
+        assert_eq!(entries[0].entry_len(), 24);
+        assert_eq!((entries[1].value_inum, entries[1].value), (42, &[][..]));
+    }
+
+    #[test]
+    fn test_parse_xattr_ibody() {
+        let mut area = vec![0u8; 64];
+        area[0..4].copy_from_slice(&EXT4_XATTR_MAGIC.to_le_bytes());
+        put_entry(&mut area, 4, b"a", 56, 4);
+        let entries = parse_xattr_ibody(&area).unwrap();
+        assert_eq!(entries[0].value.as_ptr(), area[60..].as_ptr());
+        assert!(parse_xattr_ibody(&area[..2]).is_err());
+    }
+
+    #[test]
+    fn test_malformed_xattr_entries() {
+        let mut data = block();
+        // 值越界
+        put_entry(&mut data, 32, b"x", 250, 10);
+        assert!(parse_xattr_block(&data).is_err());
+        // 值与条目区域重叠
+        put_entry(&mut data, 32, b"x", 36, 4);
+        assert!(parse_xattr_block(&data).is_err());
+        // 名称越界
+        put_entry(&mut data, 32, b"x", 0, 0);
+        data[32] = 255;
+        assert!(parse_xattr_block(&data[..64]).is_err());
+        // 条目固定部分不完整
+        data[250] = 1;
+        assert!(xattr_entry_at(&data, 250, 0).is_err());
+        assert_eq!(xattr_entry_at(&data, usize::MAX, 0).unwrap(), None);
+        // 魔数和块数
+        assert!(parse_xattr_block(&data[..16]).is_err());
+        data[8] = 2;
+        assert!(parse_xattr_block(&data).is_err());
+    }
+}
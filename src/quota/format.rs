@@ -0,0 +1,299 @@
+//! 配额文件格式（vfsv1 / QFMT_VFS_V1）
+//!
+//! 配额文件按 1KB 分块：
+//!
+//! - 块 0：文件头（`v2_disk_dqheader`）和配额信息（`v2_disk_dqinfo`）
+//! - 块 1 起：4 层基数树，每层用 ID 的 8 位作为索引，每个树块是 256 个
+//!   `__le32` 块号；最后一层指向数据块
+//! - 数据块：16 字节头部（`qt_disk_dqdbheader`）后跟若干 72 字节的
+//!   `v2r1_disk_dqblk`，多个 ID 共享同一个数据块
+//!
+//! 对应内核 `fs/quota/quota_tree.c`、`fs/quota/quota_v2.c` 和 e2fsprogs `lib/support/quotaio_tree.c`。
+
+use crate::error::{Error, ErrorKind, Result};
+use alloc::{collections::BTreeMap, collections::BTreeSet, vec, vec::Vec};
+
+use super::{QuotaLimits, QuotaType, QuotaUsage};
+
+/// 配额文件的块大小
+pub(crate) const QT_BLKSIZE: usize = 1024;
+/// 树根所在的块
+const QT_TREEOFF: u32 = 1;
+/// 树的层数
+const QT_TREEDEPTH: u32 = 4;
+/// 每个树块中的引用数
+const QT_REFS: usize = QT_BLKSIZE / 4;
+/// 数据块头部大小
+const DQDB_HEADER_SIZE: usize = 16;
+/// 一个配额条目的大小（`v2r1_disk_dqblk`）
+const DQBLK_SIZE: usize = 72;
+/// 每个数据块能容纳的条目数
+const DQ_PER_BLOCK: usize = (QT_BLKSIZE - DQDB_HEADER_SIZE) / DQBLK_SIZE;
+/// 配额信息在文件中的偏移（紧跟文件头）
+const V2_DQINFOOFF: usize = 8;
+/// vfsv1 格式的版本号
+const V2_VERSION_R1: u32 = 1;
+/// 磁盘上的空间限额以 1KB 为单位
+const QUOTABLOCK_SIZE: u64 = 1024;
+/// 默认宽限期（7 天）
+const MAX_DQ_TIME: u32 = 604800;
+
+/// 各配额类型的文件头魔数
+fn magic(qtype: QuotaType) -> u32 {
+    match qtype {
+        QuotaType::User => 0xd9c0_1f11,
+        QuotaType::Group => 0xd9c0_1927,
+        QuotaType::Project => 0xd9c0_3f14,
+    }
+}
+
+/// 配额文件中的全局信息（`v2_disk_dqinfo`），只保留需要原样写回的字段
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) struct QuotaInfo {
+    /// 空间软限制的宽限期（秒）
+    pub(crate) bgrace: u32,
+    /// inode 软限制的宽限期（秒）
+    pub(crate) igrace: u32,
+    /// 标志
+    pub(crate) flags: u32,
+}
+
+impl Default for QuotaInfo {
+    fn default() -> Self {
+        Self { bgrace: MAX_DQ_TIME, igrace: MAX_DQ_TIME, flags: 0 }
+    }
+}
+
+fn le32(buf: &[u8], off: usize) -> u32 {
+    u32::from_le_bytes(buf[off..off + 4].try_into().unwrap())
+}
+
+fn le64(buf: &[u8], off: usize) -> u64 {
+    u64::from_le_bytes(buf[off..off + 8].try_into().unwrap())
+}
+
+fn put32(buf: &mut [u8], off: usize, v: u32) {
+    buf[off..off + 4].copy_from_slice(&v.to_le_bytes());
+}
+
+fn put64(buf: &mut [u8], off: usize, v: u64) {
+    buf[off..off + 8].copy_from_slice(&v.to_le_bytes());
+}
+
+/// 树中第 `depth` 层使用的索引
+fn tree_index(id: u32, depth: u32) -> usize {
+    ((id >> ((QT_TREEDEPTH - depth - 1) * 8)) & 0xff) as usize
+}
+
+/// 取出配额文件中的第 `blk` 块
+fn block(file: &[u8], blk: u32) -> Result<&[u8]> {
+    let start = blk as usize * QT_BLKSIZE;
+    file.get(start..start + QT_BLKSIZE)
+        .ok_or(Error::new(ErrorKind::Corrupted, "Quota tree reference beyond end of file"))
+}
+
+/// 解析配额文件
+///
+/// # 错误
+///
+/// - `ErrorKind::Corrupted` - 文件头不匹配或树中的块号越界
+pub(crate) fn parse(file: &[u8], qtype: QuotaType) -> Result<(QuotaInfo, BTreeMap<u32, QuotaUsage>)> {
+    let header = block(file, 0)?;
+    if le32(header, 0) != magic(qtype) || le32(header, 4) != V2_VERSION_R1 {
+        return Err(Error::new(ErrorKind::Corrupted, "Invalid quota file header"));
+    }
+    let info = QuotaInfo {
+        bgrace: le32(header, V2_DQINFOOFF),
+        igrace: le32(header, V2_DQINFOOFF + 4),
+        flags: le32(header, V2_DQINFOOFF + 8),
+    };
+
+    let mut data_blocks = BTreeSet::new();
+    collect_data_blocks(file, QT_TREEOFF, 0, &mut data_blocks)?;
+
+    let mut dquots = BTreeMap::new();
+    for blk in data_blocks {
+        let data = block(file, blk)?;
+        for entry in data[DQDB_HEADER_SIZE..].chunks_exact(DQBLK_SIZE).take(DQ_PER_BLOCK) {
+            if entry.iter().all(|&b| b == 0) {
+                continue;
+            }
+            dquots.insert(le32(entry, 0), decode_dqblk(entry));
+        }
+    }
+
+    Ok((info, dquots))
+}
+
+/// 遍历树，收集最后一层引用的数据块
+fn collect_data_blocks(file: &[u8], blk: u32, depth: u32, out: &mut BTreeSet<u32>) -> Result<()> {
+    let refs = block(file, blk)?;
+    for i in 0..QT_REFS {
+        let child = le32(refs, i * 4);
+        if child == 0 {
+            continue;
+        }
+        if child <= QT_TREEOFF {
+            return Err(Error::new(ErrorKind::Corrupted, "Invalid quota tree reference"));
+        }
+        if depth + 1 == QT_TREEDEPTH {
+            out.insert(child);
+        } else {
+            collect_data_blocks(file, child, depth + 1, out)?;
+        }
+    }
+    Ok(())
+}
+
+fn decode_dqblk(entry: &[u8]) -> QuotaUsage {
+    QuotaUsage {
+        limits: QuotaLimits {
+            inodes_hard: le64(entry, 8),
+            inodes_soft: le64(entry, 16),
+            space_hard: le64(entry, 32).saturating_mul(QUOTABLOCK_SIZE),
+            space_soft: le64(entry, 40).saturating_mul(QUOTABLOCK_SIZE),
+        },
+        inodes: le64(entry, 24),
+        space: le64(entry, 48),
+        space_grace_end: le64(entry, 56),
+        inode_grace_end: le64(entry, 64),
+    }
+}
+
+fn encode_dqblk(entry: &mut [u8], id: u32, dq: &QuotaUsage) {
+    put32(entry, 0, id);
+    put32(entry, 4, 0);
+    put64(entry, 8, dq.limits.inodes_hard);
+    put64(entry, 16, dq.limits.inodes_soft);
+    put64(entry, 24, dq.inodes);
+    put64(entry, 32, dq.limits.space_hard.div_ceil(QUOTABLOCK_SIZE));
+    put64(entry, 40, dq.limits.space_soft.div_ceil(QUOTABLOCK_SIZE));
+    put64(entry, 48, dq.space);
+    put64(entry, 56, dq.space_grace_end);
+    put64(entry, 64, dq.inode_grace_end);
+    // 全零的条目表示空闲，与内核 v2r1_mem2diskdqb 相同用 itime = 1 标记为已使用
+    if entry.iter().all(|&b| b == 0) {
+        put64(entry, 64, 1);
+    }
+}
+
+/// 生成完整的配额文件
+///
+/// 按 ID 顺序依次插入，树块和数据块按需顺序分配；最后一个未满的数据块
+/// 挂在空闲条目链表上，没有空闲块
+pub(crate) fn build(qtype: QuotaType, info: &QuotaInfo, dquots: &BTreeMap<u32, QuotaUsage>) -> Vec<u8> {
+    // 块 0（文件头）和块 1（树根）
+    let mut file = vec![0u8; 2 * QT_BLKSIZE];
+    // 当前未满的数据块及其条目数
+    let mut open_data: Option<(u32, usize)> = None;
+
+    for (&id, dq) in dquots {
+        let mut blk = QT_TREEOFF;
+        for depth in 0..QT_TREEDEPTH - 1 {
+            blk = get_or_alloc_ref(&mut file, blk, tree_index(id, depth));
+        }
+
+        let (data_blk, used) = match open_data {
+            Some((blk, used)) if used < DQ_PER_BLOCK => (blk, used),
+            _ => (alloc_block(&mut file), 0),
+        };
+        let leaf_off = blk as usize * QT_BLKSIZE + tree_index(id, QT_TREEDEPTH - 1) * 4;
+        put32(&mut file, leaf_off, data_blk);
+
+        let base = data_blk as usize * QT_BLKSIZE;
+        let entry_off = base + DQDB_HEADER_SIZE + used * DQBLK_SIZE;
+        encode_dqblk(&mut file[entry_off..entry_off + DQBLK_SIZE], id, dq);
+        file[base + 8..base + 10].copy_from_slice(&(used as u16 + 1).to_le_bytes());
+        open_data = Some((data_blk, used + 1));
+    }
+
+    let free_entry = match open_data {
+        Some((blk, used)) if used < DQ_PER_BLOCK => blk,
+        _ => 0,
+    };
+    let blocks = (file.len() / QT_BLKSIZE) as u32;
+
+    put32(&mut file, 0, magic(qtype));
+    put32(&mut file, 4, V2_VERSION_R1);
+    put32(&mut file, V2_DQINFOOFF, info.bgrace);
+    put32(&mut file, V2_DQINFOOFF + 4, info.igrace);
+    put32(&mut file, V2_DQINFOOFF + 8, info.flags);
+    put32(&mut file, V2_DQINFOOFF + 12, blocks);
+    put32(&mut file, V2_DQINFOOFF + 16, 0);
+    put32(&mut file, V2_DQINFOOFF + 20, free_entry);
+
+    file
+}
+
+/// 在文件末尾追加一个全零块，返回块号
+fn alloc_block(file: &mut Vec<u8>) -> u32 {
+    let blk = (file.len() / QT_BLKSIZE) as u32;
+    file.resize(file.len() + QT_BLKSIZE, 0);
+    blk
+}
+
+/// 读取树块 `blk` 的第 `index` 个引用，为空时分配新块并写入引用
+fn get_or_alloc_ref(file: &mut Vec<u8>, blk: u32, index: usize) -> u32 {
+    let off = blk as usize * QT_BLKSIZE + index * 4;
+    match le32(file, off) {
+        0 => {
+            let child = alloc_block(file);
+            put32(file, off, child);
+            child
+        }
+        child => child,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn usage(space: u64, inodes: u64) -> QuotaUsage {
+        QuotaUsage { space, inodes, ..Default::default() }
+    }
+
+    #[test]
+    fn test_build_parse_roundtrip() {
+        let mut dquots = BTreeMap::new();
+        dquots.insert(0, usage(8192, 2));
+        let mut limited = usage(4096, 1);
+        limited.limits = QuotaLimits { space_hard: 1 << 20, space_soft: 512 << 10, inodes_hard: 100, inodes_soft: 50 };
+        dquots.insert(1000, limited);
+        // 跨越多个数据块和多个树分支
+        for id in 0..40u32 {
+            dquots.insert(0x0102_0000 + id * 7, usage(id as u64 * 4096, id as u64));
+        }
+
+        let info = QuotaInfo { bgrace: 100, igrace: 200, flags: 0 };
+        let file = build(QuotaType::Group, &info, &dquots);
+        assert_eq!(file.len() % QT_BLKSIZE, 0);
+        assert_eq!(le32(&file, V2_DQINFOOFF + 12) as usize, file.len() / QT_BLKSIZE);
+
+        let (parsed_info, parsed) = parse(&file, QuotaType::Group).unwrap();
+        assert_eq!(parsed_info, info);
+        assert_eq!(parsed, dquots);
+
+        // 魔数按类型区分
+        assert!(parse(&file, QuotaType::User).is_err());
+    }
+
+    #[test]
+    fn test_zero_entry_marked_used() {
+        // ID 0 的全零条目必须能被读回
+        let mut dquots = BTreeMap::new();
+        dquots.insert(0, QuotaUsage::default());
+        let file = build(QuotaType::User, &QuotaInfo::default(), &dquots);
+        let (_, parsed) = parse(&file, QuotaType::User).unwrap();
+        assert_eq!(parsed.len(), 1);
+        assert_eq!(parsed[&0].space, 0);
+    }
+
+    #[test]
+    fn test_empty_file() {
+        let file = build(QuotaType::Project, &QuotaInfo::default(), &BTreeMap::new());
+        assert_eq!(file.len(), 2 * QT_BLKSIZE);
+        let (_, parsed) = parse(&file, QuotaType::Project).unwrap();
+        assert!(parsed.is_empty());
+    }
+}
@@ -0,0 +1,330 @@
+//! 磁盘配额
+//!
+//! 文件系统带有 `RO_COMPAT_QUOTA` 特性时，用户、组（以及带 `RO_COMPAT_PROJECT`
+//! 特性时的项目）配额保存在 superblock 中 `s_{usr,grp,prj}_quota_inum` 指向的
+//! 隐藏 inode 里，格式见 [`format`]。挂载时读入内存，之后：
+//!
+//! - inode 的 `i_blocks` 变化时，按变化量调整所属 uid/gid/项目的已用空间
+//! - 分配、释放 inode 时调整已用 inode 数
+//! - 修改属主时把用量从旧 ID 转移到新 ID
+//! - 刷新、提交和卸载时把修改过的配额文件整体写回
+//!
+//! 统计范围与 e2fsck 相同：根目录和编号不小于 `s_first_ino` 的 inode，
+//! 不含配额 inode 自身；已用空间按 `i_blocks` 计算，包括元数据块和 xattr 块。
+//!
+//! 只执行硬限制：分配会超过硬限制时返回 [`ErrorKind::QuotaExceeded`]
+//! （对应 `EDQUOT`）。软限制和宽限期只保存，不检查。
+
+pub(crate) mod format;
+
+use crate::error::{Error, ErrorKind, Result};
+use alloc::collections::BTreeMap;
+
+use format::QuotaInfo;
+
+/// 配额类型
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum QuotaType {
+    /// 用户配额（按 uid）
+    User,
+    /// 组配额（按 gid）
+    Group,
+    /// 项目配额（按项目 ID）
+    Project,
+}
+
+impl QuotaType {
+    /// 全部配额类型，顺序与 [`QuotaOwner`] 中的下标一致
+    pub(crate) const ALL: [QuotaType; 3] = [QuotaType::User, QuotaType::Group, QuotaType::Project];
+
+    const fn index(self) -> usize {
+        match self {
+            QuotaType::User => 0,
+            QuotaType::Group => 1,
+            QuotaType::Project => 2,
+        }
+    }
+}
+
+/// 配额 ID：配额类型和对应的 uid / gid / 项目 ID
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum QuotaId {
+    /// 用户
+    User(u32),
+    /// 组
+    Group(u32),
+    /// 项目
+    Project(u32),
+}
+
+impl QuotaId {
+    /// 配额类型
+    pub const fn quota_type(&self) -> QuotaType {
+        match self {
+            QuotaId::User(_) => QuotaType::User,
+            QuotaId::Group(_) => QuotaType::Group,
+            QuotaId::Project(_) => QuotaType::Project,
+        }
+    }
+
+    /// uid / gid / 项目 ID
+    pub const fn id(&self) -> u32 {
+        match *self {
+            QuotaId::User(id) | QuotaId::Group(id) | QuotaId::Project(id) => id,
+        }
+    }
+}
+
+/// 配额限制，0 表示不限制
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct QuotaLimits {
+    /// 空间硬限制（字节，磁盘上以 1KB 为单位保存，向上取整）
+    pub space_hard: u64,
+    /// 空间软限制（字节，不检查）
+    pub space_soft: u64,
+    /// inode 数硬限制
+    pub inodes_hard: u64,
+    /// inode 数软限制（不检查）
+    pub inodes_soft: u64,
+}
+
+/// 一个配额 ID 的用量和限制
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct QuotaUsage {
+    /// 已用空间（字节）
+    pub space: u64,
+    /// 已用 inode 数
+    pub inodes: u64,
+    /// 限制
+    pub limits: QuotaLimits,
+    /// 空间软限制宽限期的截止时间（Unix 时间戳，0 表示未超过软限制）
+    pub space_grace_end: u64,
+    /// inode 软限制宽限期的截止时间（Unix 时间戳，0 表示未超过软限制）
+    pub inode_grace_end: u64,
+}
+
+/// inode 所属的各类配额 ID（下标见 [`QuotaType::ALL`]）
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) struct QuotaOwner {
+    pub(crate) ids: [u32; 3],
+}
+
+impl QuotaOwner {
+    pub(crate) const fn new(uid: u32, gid: u32, projid: u32) -> Self {
+        Self { ids: [uid, gid, projid] }
+    }
+}
+
+/// 一种配额类型的内存副本
+#[derive(Debug)]
+pub(crate) struct QuotaTable {
+    /// 配额文件所在的 inode
+    pub(crate) inum: u32,
+    /// 文件头中的全局信息，原样写回
+    pub(crate) info: QuotaInfo,
+    /// 各 ID 的用量
+    pub(crate) dquots: BTreeMap<u32, QuotaUsage>,
+    /// 是否需要写回
+    pub(crate) dirty: bool,
+}
+
+impl QuotaTable {
+    pub(crate) fn new(inum: u32, info: QuotaInfo, dquots: BTreeMap<u32, QuotaUsage>) -> Self {
+        Self { inum, info, dquots, dirty: false }
+    }
+}
+
+/// 已挂载文件系统的配额状态，保存在 [`Superblock`](crate::Superblock) 中
+#[derive(Debug)]
+pub(crate) struct QuotaState {
+    /// 按 [`QuotaType::ALL`] 顺序，未启用的类型为 `None`
+    tables: [Option<QuotaTable>; 3],
+    /// `s_first_ino`，之前的保留 inode（根目录除外）不计入配额
+    first_ino: u32,
+}
+
+impl QuotaState {
+    pub(crate) fn new(first_ino: u32) -> Self {
+        Self { tables: [None, None, None], first_ino }
+    }
+
+    /// 启用一种配额类型
+    pub(crate) fn insert(&mut self, qtype: QuotaType, table: QuotaTable) {
+        self.tables[qtype.index()] = Some(table);
+    }
+
+    pub(crate) fn table(&self, qtype: QuotaType) -> Option<&QuotaTable> {
+        self.tables[qtype.index()].as_ref()
+    }
+
+    pub(crate) fn tables_mut(&mut self) -> impl Iterator<Item = (QuotaType, &mut QuotaTable)> {
+        QuotaType::ALL
+            .into_iter()
+            .zip(self.tables.iter_mut())
+            .filter_map(|(qtype, table)| table.as_mut().map(|t| (qtype, t)))
+    }
+
+    /// inode 是否计入配额
+    pub(crate) fn accounts(&self, inode_num: u32) -> bool {
+        if inode_num != crate::consts::EXT4_ROOT_INODE && inode_num < self.first_ino {
+            return false;
+        }
+        self.tables.iter().flatten().all(|t| t.inum != inode_num)
+    }
+
+    /// 查询用量，该类型未启用时返回 `None`；启用但没有记录的 ID 用量为 0
+    pub(crate) fn usage(&self, id: QuotaId) -> Option<QuotaUsage> {
+        let table = self.table(id.quota_type())?;
+        Some(table.dquots.get(&id.id()).copied().unwrap_or_default())
+    }
+
+    /// 设置限制
+    ///
+    /// # 错误
+    ///
+    /// - `ErrorKind::Unsupported` - 该配额类型未启用
+    pub(crate) fn set_limits(&mut self, id: QuotaId, limits: QuotaLimits) -> Result<()> {
+        let table = self.tables[id.quota_type().index()]
+            .as_mut()
+            .ok_or(Error::new(ErrorKind::Unsupported, "Quota type not enabled"))?;
+        table.dquots.entry(id.id()).or_default().limits = limits;
+        table.dirty = true;
+        Ok(())
+    }
+
+    /// 检查为 `owner` 再分配 `space` 字节和 `inodes` 个 inode 是否超过硬限制
+    ///
+    /// # 错误
+    ///
+    /// - `ErrorKind::QuotaExceeded` - 任一启用的配额类型超过硬限制
+    pub(crate) fn check(&self, owner: &QuotaOwner, space: u64, inodes: u64) -> Result<()> {
+        for (table, id) in self.tables.iter().zip(owner.ids) {
+            if let Some(table) = table {
+                check_hard_limits(table, id, space, inodes)?;
+            }
+        }
+        Ok(())
+    }
+
+    /// 调整 `owner` 的用量（不检查限制，结果在 0 处截断）
+    pub(crate) fn charge(&mut self, owner: &QuotaOwner, space: i64, inodes: i64) {
+        if space == 0 && inodes == 0 {
+            return;
+        }
+        for (table, id) in self.tables.iter_mut().zip(owner.ids) {
+            let Some(table) = table else {
+                continue;
+            };
+            let dq = table.dquots.entry(id).or_default();
+            dq.space = dq.space.saturating_add_signed(space);
+            dq.inodes = dq.inodes.saturating_add_signed(inodes);
+            table.dirty = true;
+        }
+    }
+
+    /// 把一个 inode 的用量从 `from` 转移到 `to`
+    ///
+    /// 只检查发生变化的 ID 的硬限制，失败时用量不变
+    pub(crate) fn transfer(&mut self, from: &QuotaOwner, to: &QuotaOwner, space: u64, inodes: u64) -> Result<()> {
+        let changed = |i: usize| from.ids[i] != to.ids[i];
+        for (i, table) in self.tables.iter().enumerate() {
+            if let Some(table) = table.as_ref().filter(|_| changed(i)) {
+                check_hard_limits(table, to.ids[i], space, inodes)?;
+            }
+        }
+
+        for (i, table) in self.tables.iter_mut().enumerate() {
+            let Some(table) = table.as_mut().filter(|_| changed(i)) else {
+                continue;
+            };
+            let old = table.dquots.entry(from.ids[i]).or_default();
+            old.space = old.space.saturating_sub(space);
+            old.inodes = old.inodes.saturating_sub(inodes);
+            let new = table.dquots.entry(to.ids[i]).or_default();
+            new.space = new.space.saturating_add(space);
+            new.inodes = new.inodes.saturating_add(inodes);
+            table.dirty = true;
+        }
+        Ok(())
+    }
+}
+
+/// 检查 `id` 再分配 `space` 字节和 `inodes` 个 inode 是否超过 `table` 中的硬限制
+fn check_hard_limits(table: &QuotaTable, id: u32, space: u64, inodes: u64) -> Result<()> {
+    let Some(dq) = table.dquots.get(&id) else {
+        return Ok(());
+    };
+    let limits = &dq.limits;
+    if space > 0 && limits.space_hard != 0 && dq.space.saturating_add(space) > limits.space_hard {
+        return Err(Error::new(ErrorKind::QuotaExceeded, "Disk space quota exceeded"));
+    }
+    if inodes > 0 && limits.inodes_hard != 0 && dq.inodes.saturating_add(inodes) > limits.inodes_hard {
+        return Err(Error::new(ErrorKind::QuotaExceeded, "Inode quota exceeded"));
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn state() -> QuotaState {
+        let mut state = QuotaState::new(11);
+        state.insert(QuotaType::User, QuotaTable::new(3, QuotaInfo::default(), BTreeMap::new()));
+        state.insert(QuotaType::Group, QuotaTable::new(4, QuotaInfo::default(), BTreeMap::new()));
+        state
+    }
+
+    #[test]
+    fn test_accounts() {
+        let state = state();
+        assert!(state.accounts(2));
+        assert!(!state.accounts(3));
+        assert!(!state.accounts(7));
+        assert!(state.accounts(11));
+    }
+
+    #[test]
+    fn test_charge_and_check() {
+        let mut state = state();
+        let owner = QuotaOwner::new(1000, 100, 0);
+        state.set_limits(QuotaId::User(1000), QuotaLimits { space_hard: 8192, inodes_hard: 2, ..Default::default() }).unwrap();
+
+        state.charge(&owner, 4096, 1);
+        assert!(state.check(&owner, 4096, 1).is_ok());
+        assert_eq!(state.check(&owner, 4097, 0).unwrap_err().kind(), ErrorKind::QuotaExceeded);
+        assert_eq!(state.check(&owner, 0, 2).unwrap_err().kind(), ErrorKind::QuotaExceeded);
+
+        // 组没有限制
+        let usage = state.usage(QuotaId::Group(100)).unwrap();
+        assert_eq!((usage.space, usage.inodes), (4096, 1));
+
+        // 项目配额未启用
+        assert!(state.usage(QuotaId::Project(0)).is_none());
+        assert!(state.set_limits(QuotaId::Project(0), QuotaLimits::default()).is_err());
+
+        // 释放时不会下溢
+        state.charge(&owner, -8192, -2);
+        assert_eq!(state.usage(QuotaId::User(1000)).unwrap().space, 0);
+    }
+
+    #[test]
+    fn test_transfer() {
+        let mut state = state();
+        let root = QuotaOwner::new(0, 0, 0);
+        let user = QuotaOwner::new(1000, 0, 0);
+        state.charge(&root, 8192, 1);
+        state.set_limits(QuotaId::User(1000), QuotaLimits { space_hard: 4096, ..Default::default() }).unwrap();
+
+        // 超过新属主的硬限制，用量不变
+        assert_eq!(state.transfer(&root, &user, 8192, 1).unwrap_err().kind(), ErrorKind::QuotaExceeded);
+        assert_eq!(state.usage(QuotaId::User(0)).unwrap().space, 8192);
+
+        state.set_limits(QuotaId::User(1000), QuotaLimits::default()).unwrap();
+        state.transfer(&root, &user, 8192, 1).unwrap();
+        assert_eq!(state.usage(QuotaId::User(0)).unwrap().space, 0);
+        assert_eq!(state.usage(QuotaId::User(1000)).unwrap().inodes, 1);
+        // gid 未变，组用量不变
+        assert_eq!(state.usage(QuotaId::Group(0)).unwrap().space, 8192);
+    }
+}
@@ -0,0 +1,143 @@
+//! superblock 中的错误记录
+//!
+//! 与内核的 `__ext4_error` 相同，记录第一次和最后一次错误的时间、位置和错误码，
+//! 以及错误总数（`s_error_count`）。dumpe2fs 显示为 "First error ..." / "Last error ..."，
+//! 设备返修后可以据此判断损坏发生在什么时候、哪个块。
+
+use core::fmt;
+
+use super::Superblock;
+
+/// 一次错误的记录
+#[derive(Clone, Copy, PartialEq, Eq)]
+pub struct ErrorRecord {
+    /// 发生时间（Unix 时间戳，秒），0 表示时间未知
+    pub time: u64,
+    /// 相关的 inode，0 表示未知
+    pub inode: u32,
+    /// 相关的块号，0 表示未知
+    pub block: u64,
+    /// 源码行号，0 表示未知
+    pub line: u32,
+    /// 错误码（`EXT4_ERR_*`）
+    pub errcode: u8,
+    func: [u8; 32],
+}
+
+impl ErrorRecord {
+    /// 创建错误记录，`func` 超过 32 字节的部分被截断
+    pub fn new(func: &str, time: u64, block: u64, errcode: u8) -> Self {
+        let mut record = Self { time, inode: 0, block, line: 0, errcode, func: [0; 32] };
+        let len = func.len().min(record.func.len());
+        record.func[..len].copy_from_slice(&func.as_bytes()[..len]);
+        record
+    }
+
+    /// 检测到错误的函数（本实现中为操作名称，见 [`FsOp::name`](crate::FsOp::name)）
+    pub fn func(&self) -> &str {
+        let len = self.func.iter().position(|&b| b == 0).unwrap_or(self.func.len());
+        core::str::from_utf8(&self.func[..len]).unwrap_or("")
+    }
+
+    fn is_empty(&self) -> bool {
+        self.time == 0 && self.func[0] == 0
+    }
+}
+
+impl fmt::Debug for ErrorRecord {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("ErrorRecord")
+            .field("time", &self.time)
+            .field("func", &self.func())
+            .field("inode", &self.inode)
+            .field("block", &self.block)
+            .field("line", &self.line)
+            .field("errcode", &self.errcode)
+            .finish()
+    }
+}
+
+/// superblock 中记录的错误，见 [`Superblock::error_state`]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ErrorState {
+    /// 错误总数
+    pub count: u32,
+    /// 第一次错误
+    pub first: Option<ErrorRecord>,
+    /// 最后一次错误
+    pub last: Option<ErrorRecord>,
+}
+
+impl Superblock {
+    /// 读取 superblock 中记录的错误
+    pub fn error_state(&self) -> ErrorState {
+        let sb = &self.inner;
+        let first = ErrorRecord {
+            time: u32::from_le(sb.first_error_time) as u64 | (sb.first_error_time_hi as u64) << 32,
+            inode: u32::from_le(sb.first_error_ino),
+            block: u64::from_le(sb.first_error_block),
+            line: u32::from_le(sb.first_error_line),
+            errcode: sb.first_error_errcode,
+            func: sb.first_error_func,
+        };
+        let last = ErrorRecord {
+            time: u32::from_le(sb.last_error_time) as u64 | (sb.last_error_time_hi as u64) << 32,
+            inode: u32::from_le(sb.last_error_ino),
+            block: u64::from_le(sb.last_error_block),
+            line: u32::from_le(sb.last_error_line),
+            errcode: sb.last_error_errcode,
+            func: sb.last_error_func,
+        };
+        ErrorState {
+            count: u32::from_le(sb.error_count),
+            first: (!first.is_empty()).then_some(first),
+            last: (!last.is_empty()).then_some(last),
+        }
+    }
+
+    /// 记录一次错误：错误数加一，更新最后一次错误，还没有第一次错误时同时记为第一次
+    ///
+    /// 只修改内存中的 superblock，调用者负责写回
+    pub fn record_error(&mut self, record: &ErrorRecord) {
+        let sb = &mut self.inner;
+        sb.error_count = u32::from_le(sb.error_count).saturating_add(1).to_le();
+        if sb.first_error_time == 0 && sb.first_error_func[0] == 0 {
+            sb.first_error_time = (record.time as u32).to_le();
+            sb.first_error_time_hi = (record.time >> 32) as u8;
+            sb.first_error_ino = record.inode.to_le();
+            sb.first_error_block = record.block.to_le();
+            sb.first_error_line = record.line.to_le();
+            sb.first_error_errcode = record.errcode;
+            sb.first_error_func = record.func;
+        }
+        sb.last_error_time = (record.time as u32).to_le();
+        sb.last_error_time_hi = (record.time >> 32) as u8;
+        sb.last_error_ino = record.inode.to_le();
+        sb.last_error_block = record.block.to_le();
+        sb.last_error_line = record.line.to_le();
+        sb.last_error_errcode = record.errcode;
+        sb.last_error_func = record.func;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{consts::EXT4_ERR_EFSCORRUPTED, types::ext4_sblock};
+
+    #[test]
+    fn test_record_error() {
+        let mut sb = Superblock::new(ext4_sblock::default());
+        assert_eq!(sb.error_state(), ErrorState { count: 0, first: None, last: None });
+
+        sb.record_error(&ErrorRecord::new("readdir", 0, 100, EXT4_ERR_EFSCORRUPTED));
+        sb.record_error(&ErrorRecord::new("a_very_long_operation_name_over_32_bytes", 5 << 32, 200, EXT4_ERR_EFSCORRUPTED));
+
+        let state = sb.error_state();
+        assert_eq!(state.count, 2);
+        let first = state.first.unwrap();
+        assert_eq!((first.func(), first.block), ("readdir", 100));
+        let last = state.last.unwrap();
+        assert_eq!((last.func(), last.time, last.block), ("a_very_long_operation_name_over_", 5 << 32, 200));
+    }
+}
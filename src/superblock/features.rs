@@ -0,0 +1,176 @@
+//! 挂载时的特性检查
+//!
+//! 对比 superblock 中的 incompat/ro_compat 特性位与本实现支持的特性
+//! （[`EXT4_FEATURE_INCOMPAT_SUPP`]、[`EXT4_FEATURE_RO_COMPAT_SUPP`]），
+//! 生成 [`FeatureReport`]。挂载时据此拒绝挂载或降级为只读。
+
+use core::fmt;
+
+use super::Superblock;
+use crate::consts::*;
+
+/// incompat 特性名称（与 e2fsprogs 一致）
+const INCOMPAT_NAMES: &[(u32, &str)] = &[
+    (EXT4_FEATURE_INCOMPAT_COMPRESSION, "compression"),
+    (EXT4_FEATURE_INCOMPAT_FILETYPE, "filetype"),
+    (EXT4_FEATURE_INCOMPAT_RECOVER, "needs_recovery"),
+    (EXT4_FEATURE_INCOMPAT_JOURNAL_DEV, "journal_dev"),
+    (EXT4_FEATURE_INCOMPAT_META_BG, "meta_bg"),
+    (EXT4_FEATURE_INCOMPAT_EXTENTS, "extent"),
+    (EXT4_FEATURE_INCOMPAT_64BIT, "64bit"),
+    (EXT4_FEATURE_INCOMPAT_MMP, "mmp"),
+    (EXT4_FEATURE_INCOMPAT_FLEX_BG, "flex_bg"),
+    (EXT4_FEATURE_INCOMPAT_EA_INODE, "ea_inode"),
+    (EXT4_FEATURE_INCOMPAT_DIRDATA, "dirdata"),
+    (EXT4_FEATURE_INCOMPAT_CSUM_SEED, "metadata_csum_seed"),
+    (EXT4_FEATURE_INCOMPAT_LARGEDIR, "large_dir"),
+    (EXT4_FEATURE_INCOMPAT_INLINE_DATA, "inline_data"),
+    (EXT4_FEATURE_INCOMPAT_ENCRYPT, "encrypt"),
+    (EXT4_FEATURE_INCOMPAT_CASEFOLD, "casefold"),
+];
+
+/// ro_compat 特性名称（与 e2fsprogs 一致）
+const RO_COMPAT_NAMES: &[(u32, &str)] = &[
+    (EXT4_FEATURE_RO_COMPAT_SPARSE_SUPER, "sparse_super"),
+    (EXT4_FEATURE_RO_COMPAT_LARGE_FILE, "large_file"),
+    (EXT4_FEATURE_RO_COMPAT_BTREE_DIR, "btree_dir"),
+    (EXT4_FEATURE_RO_COMPAT_HUGE_FILE, "huge_file"),
+    (EXT4_FEATURE_RO_COMPAT_GDT_CSUM, "uninit_bg"),
+    (EXT4_FEATURE_RO_COMPAT_DIR_NLINK, "dir_nlink"),
+    (EXT4_FEATURE_RO_COMPAT_EXTRA_ISIZE, "extra_isize"),
+    (EXT4_FEATURE_RO_COMPAT_HAS_SNAPSHOT, "snapshot"),
+    (EXT4_FEATURE_RO_COMPAT_QUOTA, "quota"),
+    (EXT4_FEATURE_RO_COMPAT_BIGALLOC, "bigalloc"),
+    (EXT4_FEATURE_RO_COMPAT_METADATA_CSUM, "metadata_csum"),
+    (EXT4_FEATURE_RO_COMPAT_READONLY, "read-only"),
+    (EXT4_FEATURE_RO_COMPAT_PROJECT, "project"),
+];
+
+/// 特性检查结果
+///
+/// 只记录本实现不支持的特性位：
+/// - incompat 位不理解就无法正确读取，挂载失败
+/// - ro_compat 位不影响读取，挂载降级为只读
+///
+/// `Display` 按 e2fsprogs 的名称列出这些特性，未知的位显示为十六进制
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct FeatureReport {
+    /// 不支持的 incompat 特性位
+    pub unsupported_incompat: u32,
+    /// 不支持的 ro_compat 特性位
+    pub unsupported_ro_compat: u32,
+}
+
+impl FeatureReport {
+    /// 是否可以挂载（没有不支持的 incompat 特性）
+    pub fn can_mount(&self) -> bool {
+        self.unsupported_incompat == 0
+    }
+
+    /// 是否可以读写挂载
+    pub fn can_write(&self) -> bool {
+        self.can_mount() && self.unsupported_ro_compat == 0
+    }
+
+    /// 不支持的 incompat 特性名称（未知的位不包含在内）
+    pub fn incompat_names(&self) -> impl Iterator<Item = &'static str> {
+        names(INCOMPAT_NAMES, self.unsupported_incompat)
+    }
+
+    /// 不支持的 ro_compat 特性名称（未知的位不包含在内）
+    pub fn ro_compat_names(&self) -> impl Iterator<Item = &'static str> {
+        names(RO_COMPAT_NAMES, self.unsupported_ro_compat)
+    }
+}
+
+fn names(table: &'static [(u32, &'static str)], bits: u32) -> impl Iterator<Item = &'static str> {
+    table.iter().filter(move |(bit, _)| bits & bit != 0).map(|&(_, name)| name)
+}
+
+/// 按名称写出一组特性位，未知的位合并为一个十六进制值
+fn write_features(f: &mut fmt::Formatter<'_>, table: &[(u32, &str)], bits: u32) -> fmt::Result {
+    let mut rest = bits;
+    let mut first = true;
+    for &(bit, name) in table {
+        if bits & bit != 0 {
+            write!(f, "{}{name}", if first { "" } else { ", " })?;
+            rest &= !bit;
+            first = false;
+        }
+    }
+    if rest != 0 {
+        write!(f, "{}{rest:#x}", if first { "" } else { ", " })?;
+    }
+    Ok(())
+}
+
+impl fmt::Display for FeatureReport {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        if self.can_write() {
+            return f.write_str("all features supported");
+        }
+        if self.unsupported_incompat != 0 {
+            f.write_str("unsupported incompat features: ")?;
+            write_features(f, INCOMPAT_NAMES, self.unsupported_incompat)?;
+            if self.unsupported_ro_compat != 0 {
+                f.write_str("; ")?;
+            }
+        }
+        if self.unsupported_ro_compat != 0 {
+            f.write_str("unsupported ro_compat features: ")?;
+            write_features(f, RO_COMPAT_NAMES, self.unsupported_ro_compat)?;
+        }
+        Ok(())
+    }
+}
+
+impl Superblock {
+    /// 检查 superblock 中本实现不支持的特性
+    ///
+    /// # 示例
+    ///
+    /// ```rust,ignore
+    /// let report = Superblock::load(&mut bdev)?.check_features();
+    /// if !report.can_mount() {
+    ///     println!("{report}"); // unsupported incompat features: mmp, inline_data
+    /// }
+    /// ```
+    pub fn check_features(&self) -> FeatureReport {
+        FeatureReport {
+            unsupported_incompat: u32::from_le(self.inner.feature_incompat) & !EXT4_FEATURE_INCOMPAT_SUPP,
+            unsupported_ro_compat: u32::from_le(self.inner.feature_ro_compat) & !EXT4_FEATURE_RO_COMPAT_SUPP,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::types::ext4_sblock;
+    use alloc::{format, vec::Vec};
+
+    #[test]
+    fn test_check_features() {
+        let mut sb = Superblock::new(ext4_sblock {
+            feature_incompat: (EXT4_FEATURE_INCOMPAT_FILETYPE | EXT4_FEATURE_INCOMPAT_EXTENTS).to_le(),
+            feature_ro_compat: EXT4_FEATURE_RO_COMPAT_METADATA_CSUM.to_le(),
+            ..Default::default()
+        });
+        let report = sb.check_features();
+        assert!(report.can_write());
+        assert_eq!(format!("{report}"), "all features supported");
+
+        sb.inner.feature_ro_compat |= EXT4_FEATURE_RO_COMPAT_READONLY.to_le();
+        let report = sb.check_features();
+        assert!(report.can_mount() && !report.can_write());
+        assert_eq!(report.ro_compat_names().collect::<Vec<_>>(), ["read-only"]);
+
+        sb.inner.feature_incompat |= (EXT4_FEATURE_INCOMPAT_MMP | EXT4_FEATURE_INCOMPAT_INLINE_DATA | 0x8000_0000).to_le();
+        let report = sb.check_features();
+        assert!(!report.can_mount());
+        assert_eq!(
+            format!("{report}"),
+            "unsupported incompat features: mmp, inline_data, 0x80000000; unsupported ro_compat features: read-only"
+        );
+    }
+}
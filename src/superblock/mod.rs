@@ -2,9 +2,13 @@
 //!
 //! 这个模块提供 ext4 superblock 的读取、验证、写入和更新功能。
 
+mod errors;
+mod features;
 mod read;
 mod write;
 pub mod checksum;
 
+pub use errors::{ErrorRecord, ErrorState};
+pub use features::*;
 pub use read::*;
 pub use write::*;
@@ -4,16 +4,19 @@ use crate::{
     block::{BlockDev, BlockDevice},
     consts::*,
     error::{Error, ErrorKind, Result},
+    quota::QuotaState,
     types::ext4_sblock,
 };
 use crate::consts::{
     EXT4_FEATURE_RO_COMPAT_METADATA_CSUM,
     EXT4_FEATURE_RO_COMPAT_SPARSE_SUPER,
+    EXT4_FEATURE_COMPAT_SPARSE_SUPER2,
     EXT4_FEATURE_INCOMPAT_META_BG,
     EXT4_MIN_BLOCK_GROUP_DESCRIPTOR_SIZE,
     EXT4_MAX_BLOCK_GROUP_DESCRIPTOR_SIZE,
 };
-use alloc::vec;
+use crate::block_group::GroupLockMap;
+use alloc::{boxed::Box, collections::{BTreeMap, BTreeSet}, sync::Arc, vec, vec::Vec};
 
 /// 从块设备读取 superblock
 ///
@@ -25,10 +28,14 @@ use alloc::vec;
 ///
 /// 成功返回 superblock 结构
 pub fn read_superblock<D: BlockDevice>(bdev: &mut BlockDev<D>) -> Result<ext4_sblock> {
+    read_superblock_at(bdev, EXT4_SUPERBLOCK_OFFSET)
+}
+
+/// 从指定字节偏移读取 superblock（主副本或备份）
+fn read_superblock_at<D: BlockDevice>(bdev: &mut BlockDev<D>, offset: u64) -> Result<ext4_sblock> {
     let mut sb_buf = vec![0u8; EXT4_SUPERBLOCK_SIZE];
 
-    // 读取 superblock（从偏移 1024 开始）
-    bdev.read_bytes(EXT4_SUPERBLOCK_OFFSET, &mut sb_buf)?;
+    bdev.read_bytes(offset, &mut sb_buf)?;
 
     // 解析 superblock
     let sb = unsafe {
@@ -49,18 +56,287 @@ pub fn read_superblock<D: BlockDevice>(bdev: &mut BlockDev<D>) -> Result<ext4_sb
 /// Superblock 包装器，提供高级操作
 pub struct Superblock {
     pub(super) inner: ext4_sblock,
+    /// 挂载时设置的分配上限（块号），不写入磁盘
+    pub(super) max_blocks: Option<u64>,
+    /// 延迟分配已预留、尚未实际分配的块数，不写入磁盘
+    pub(super) reserved_blocks: u64,
+    /// 挂载时设置的保留块比例（百分比），覆盖 `s_r_blocks_count`，不写入磁盘
+    pub(super) reserved_percent: Option<u8>,
+    /// 配额的内存副本（`RO_COMPAT_QUOTA`），挂载时加载
+    pub(super) quota: Option<Box<QuotaState>>,
+    /// 读取元数据时是否校验校验和（`FsConfig::verify_checksums`），不写入磁盘
+    pub(super) verify_checksums: bool,
+    /// 只读挂载（`FsConfig::read_only` 或遇到不支持的特性），不写入磁盘
+    pub(super) read_only: bool,
+    /// 分配器修改块组位图和描述符时使用的块组锁
+    pub(super) group_locks: Arc<GroupLockMap>,
+    /// 本次挂载见过的 EA inode（值哈希 -> inode 号），用于共享相同的大 xattr 值
+    pub(super) ea_inodes: BTreeMap<u32, Vec<u32>>,
+    /// 批量操作期间推迟重算叶子块校验和的目录，`None` 表示不推迟，不写入磁盘
+    pub(super) deferred_dir_csums: Option<BTreeSet<u32>>,
 }
 
 impl Superblock {
     /// 从 ext4_sblock 创建 Superblock（主要用于测试）
     pub fn new(inner: ext4_sblock) -> Self {
-        Self { inner }
+        Self {
+            inner,
+            max_blocks: None,
+            reserved_blocks: 0,
+            reserved_percent: None,
+            quota: None,
+            verify_checksums: false,
+            read_only: false,
+            group_locks: Arc::new(GroupLockMap::new()),
+            ea_inodes: BTreeMap::new(),
+            deferred_dir_csums: None,
+        }
     }
 
     /// 从块设备加载 superblock
     pub fn load<D: BlockDevice>(bdev: &mut BlockDev<D>) -> Result<Self> {
         let inner = read_superblock(bdev)?;
-        Ok(Self { inner })
+        Ok(Self::new(inner))
+    }
+
+    /// 从块设备加载 superblock，主副本损坏时使用备份
+    ///
+    /// 主 superblock 的魔数或校验和（启用 METADATA_CSUM 时）不正确时，
+    /// 与 e2fsck 一样依次按 1K 到 64K 的块大小尝试块组 1 中的备份。
+    /// 备份的位置取决于块大小和每组块数，这里假设每组块数为默认的 `8 * 块大小`。
+    ///
+    /// 使用备份时会把其中的块组号改为 0，下次写回 superblock 时主副本随之修复
+    ///
+    /// # 错误
+    ///
+    /// - `ErrorKind::Corrupted` - 主副本和备份都不可用，返回主副本的错误
+    /// - `ErrorKind::Io` - 设备读取失败
+    pub fn load_with_fallback<D: BlockDevice>(bdev: &mut BlockDev<D>) -> Result<Self> {
+        let primary_err = match Self::load_checked(bdev, EXT4_SUPERBLOCK_OFFSET) {
+            Ok(sb) => return Ok(sb),
+            Err(e) if e.kind() == ErrorKind::Corrupted => e,
+            Err(e) => return Err(e),
+        };
+
+        let device_size = bdev.total_blocks() * bdev.block_size() as u64;
+        for log_block_size in 0..=6 {
+            let block_size = 1024u64 << log_block_size;
+            let first_data_block = if block_size == 1024 { 1 } else { 0 };
+            let blocks_per_group = 8 * block_size;
+            let offset = (first_data_block + blocks_per_group) * block_size;
+            if offset + EXT4_SUPERBLOCK_SIZE as u64 > device_size {
+                break;
+            }
+
+            let mut sb = match Self::load_checked(bdev, offset) {
+                Ok(sb) => sb,
+                Err(e) if e.kind() == ErrorKind::Corrupted => continue,
+                Err(e) => return Err(e),
+            };
+            if sb.block_size() as u64 != block_size
+                || sb.blocks_per_group() as u64 != blocks_per_group
+            {
+                continue;
+            }
+
+            sb.inner.block_group_nr = 0;
+            super::checksum::set_checksum(&mut sb.inner);
+            return Ok(sb);
+        }
+
+        Err(primary_err)
+    }
+
+    /// 读取指定偏移处的 superblock，并检查魔数和校验和
+    fn load_checked<D: BlockDevice>(bdev: &mut BlockDev<D>, offset: u64) -> Result<Self> {
+        let sb = Self::new(read_superblock_at(bdev, offset)?);
+        if sb.has_metadata_csum() && !sb.verify_checksum() {
+            return Err(Error::with_block(
+                ErrorKind::Corrupted,
+                "Superblock checksum mismatch",
+                offset / sb.block_size() as u64,
+            ));
+        }
+        Ok(sb)
+    }
+
+    /// 设置块分配上限
+    ///
+    /// 设置后分配器不会返回 `>= max_blocks` 的块号，`None` 表示不限制。
+    /// 这只是本次挂载的运行时限制，不会写入磁盘。
+    pub fn set_max_blocks(&mut self, max_blocks: Option<u64>) {
+        self.max_blocks = max_blocks;
+    }
+
+    /// 获取挂载时设置的块分配上限
+    pub fn max_blocks(&self) -> Option<u64> {
+        self.max_blocks
+    }
+
+    /// 块组锁表
+    ///
+    /// 分配器持有 `Superblock` 的可变引用时仍要锁住块组，因此返回
+    /// `Arc` 的克隆，守卫不借用 `self`
+    pub fn group_locks(&self) -> Arc<GroupLockMap> {
+        self.group_locks.clone()
+    }
+
+    /// 获取延迟分配预留的块数
+    pub fn reserved_blocks(&self) -> u64 {
+        self.reserved_blocks
+    }
+
+    /// 设置延迟分配预留的块数
+    ///
+    /// 由 [`balloc::reserve_blocks`](crate::balloc::reserve_blocks) 等函数维护，
+    /// 只是运行时计数，不会写入磁盘
+    pub fn set_reserved_blocks(&mut self, count: u64) {
+        self.reserved_blocks = count;
+    }
+
+    /// 设置为特权属主保留的块比例（百分比）
+    ///
+    /// 覆盖 superblock 中的 `s_r_blocks_count`，`None` 恢复使用磁盘上的值。
+    /// 只是本次挂载的运行时设置，不会写入磁盘。
+    pub fn set_reserved_percent(&mut self, percent: Option<u8>) {
+        self.reserved_percent = percent;
+    }
+
+    /// 获取挂载时设置的保留块比例
+    pub fn reserved_percent(&self) -> Option<u8> {
+        self.reserved_percent
+    }
+
+    /// 为特权属主保留的块数
+    ///
+    /// 设置了保留比例时按可分配块数计算，否则取 `s_r_blocks_count`
+    pub fn root_reserved_blocks(&self) -> u64 {
+        match self.reserved_percent {
+            Some(percent) => self.alloc_limit() * percent as u64 / 100,
+            None => self.inner.r_blocks_count().min(self.alloc_limit()),
+        }
+    }
+
+    /// 属主是否可以使用保留块
+    ///
+    /// 与内核 `ext4_has_free_clusters()` 相同：root、`s_def_resuid`
+    /// 以及 `s_def_resgid`（非 0 时）组的属主可以使用保留块
+    pub fn can_use_reserved(&self, uid: u32, gid: u32) -> bool {
+        let resuid = u16::from_le(self.inner.def_resuid) as u32;
+        let resgid = u16::from_le(self.inner.def_resgid) as u32;
+        uid == 0 || uid == resuid || (resgid != 0 && gid == resgid)
+    }
+
+    /// 获取配额状态，未启用配额时为 `None`
+    pub(crate) fn quota(&self) -> Option<&QuotaState> {
+        self.quota.as_deref()
+    }
+
+    /// 获取可变的配额状态
+    pub(crate) fn quota_mut(&mut self) -> Option<&mut QuotaState> {
+        self.quota.as_deref_mut()
+    }
+
+    /// 设置配额状态
+    pub(crate) fn set_quota(&mut self, quota: Option<Box<QuotaState>>) {
+        self.quota = quota;
+    }
+
+    /// 值哈希为 `hash` 的已知 EA inode
+    ///
+    /// 只是本次挂载的索引，使用前需要确认 inode 的内容
+    pub(crate) fn ea_inode_candidates(&self, hash: u32) -> &[u32] {
+        self.ea_inodes.get(&hash).map_or(&[], Vec::as_slice)
+    }
+
+    /// 记录一个值哈希为 `hash` 的 EA inode
+    pub(crate) fn register_ea_inode(&mut self, hash: u32, inode_num: u32) {
+        let inodes = self.ea_inodes.entry(hash).or_default();
+        if !inodes.contains(&inode_num) {
+            inodes.push(inode_num);
+        }
+    }
+
+    /// EA inode 被释放时从索引中移除
+    pub(crate) fn forget_ea_inode(&mut self, hash: u32, inode_num: u32) {
+        if let Some(inodes) = self.ea_inodes.get_mut(&hash) {
+            inodes.retain(|&ino| ino != inode_num);
+            if inodes.is_empty() {
+                self.ea_inodes.remove(&hash);
+            }
+        }
+    }
+
+    /// 开始推迟目录叶子块的校验和，见 [`defer_dir_csum`](Self::defer_dir_csum)
+    pub(crate) fn begin_defer_dir_csums(&mut self) {
+        self.deferred_dir_csums.get_or_insert_with(BTreeSet::new);
+    }
+
+    /// 记录目录 `dir` 的叶子块被修改
+    ///
+    /// 正在推迟时返回 true，调用者不必更新校验和，推迟结束后由
+    /// [`take_deferred_dir_csums`](Self::take_deferred_dir_csums) 返回的目录统一重算
+    pub(crate) fn defer_dir_csum(&mut self, dir: u32) -> bool {
+        match self.deferred_dir_csums.as_mut() {
+            Some(dirs) => {
+                dirs.insert(dir);
+                true
+            }
+            None => false,
+        }
+    }
+
+    /// 目录 `dir` 的块中是否可能有过期的校验和
+    pub(crate) fn dir_csum_deferred(&self, dir: u32) -> bool {
+        self.deferred_dir_csums.as_ref().is_some_and(|dirs| dirs.contains(&dir))
+    }
+
+    /// 结束推迟，返回需要重算校验和的目录
+    pub(crate) fn take_deferred_dir_csums(&mut self) -> BTreeSet<u32> {
+        self.deferred_dir_csums.take().unwrap_or_default()
+    }
+
+    /// 设置读取元数据时是否校验校验和
+    pub fn set_verify_checksums(&mut self, verify: bool) {
+        self.verify_checksums = verify;
+    }
+
+    /// 读取元数据时是否需要校验校验和
+    ///
+    /// 只有挂载时打开了校验且文件系统启用了 `metadata_csum` 时才为 true
+    pub fn verify_checksums(&self) -> bool {
+        self.verify_checksums && self.has_metadata_csum()
+    }
+
+    /// 设置只读模式
+    ///
+    /// 只影响本次挂载。打开后分配器、目录写入、xattr 和日志提交等
+    /// 修改操作返回 `ErrorKind::ReadOnlyFs`
+    pub fn set_read_only(&mut self, read_only: bool) {
+        self.read_only = read_only;
+    }
+
+    /// 是否以只读方式挂载
+    pub fn is_read_only(&self) -> bool {
+        self.read_only
+    }
+
+    /// 只读挂载时返回 `ErrorKind::ReadOnlyFs`，供修改文件系统的操作在入口处调用
+    pub fn check_writable(&self) -> Result<()> {
+        if self.read_only {
+            return Err(Error::new(ErrorKind::ReadOnlyFs, "Filesystem is mounted read-only"));
+        }
+        Ok(())
+    }
+
+    /// 获取可分配块号的上界（不含）
+    ///
+    /// 取总块数与分配上限中较小的一个
+    pub fn alloc_limit(&self) -> u64 {
+        match self.max_blocks {
+            Some(max) => max.min(self.blocks_count()),
+            None => self.blocks_count(),
+        }
     }
 
     /// 获取内部 superblock 结构的引用
@@ -138,6 +414,16 @@ impl Superblock {
         (u32::from_le(self.inner.flags) & flag) != 0
     }
 
+    /// 获取文件名编码（`s_encoding`），只在启用 CASEFOLD 特性时有意义
+    pub fn encoding(&self) -> u16 {
+        u16::from_le(self.inner.encoding)
+    }
+
+    /// 获取文件名编码标志（`s_encoding_flags`）
+    pub fn encoding_flags(&self) -> u16 {
+        u16::from_le(self.inner.encoding_flags)
+    }
+
     /// 获取 hash seed（用于 HTree）
     pub fn hash_seed(&self) -> [u32; 4] {
         [
@@ -190,6 +476,26 @@ impl Superblock {
         &self.inner.uuid
     }
 
+    /// 获取元数据校验和的种子
+    ///
+    /// 对应内核的 `s_csum_seed`：启用 `CSUM_SEED` 特性时使用 superblock 中
+    /// 保存的种子，否则为 UUID 的 CRC32C
+    pub fn csum_seed(&self) -> u32 {
+        if self.has_incompat_feature(EXT4_FEATURE_INCOMPAT_CSUM_SEED) {
+            u32::from_le(self.inner.checksum_seed)
+        } else {
+            crate::crc::crc32c(&self.inner.uuid)
+        }
+    }
+
+    /// 获取 inode 相关元数据（inode、目录块、extent 块）的校验和种子
+    ///
+    /// 由 inode 号和 generation 追加到 [`csum_seed`](Self::csum_seed) 得到
+    pub fn inode_csum_seed(&self, inode: u32, generation: u32) -> u32 {
+        let csum = crate::crc::crc32c_append(self.csum_seed(), &inode.to_le_bytes());
+        crate::crc::crc32c_append(csum, &generation.to_le_bytes())
+    }
+
     /// 检查是否启用元数据校验和
     ///
     /// 对应 EXT4_FEATURE_RO_COMPAT_METADATA_CSUM 特性
@@ -203,6 +509,51 @@ impl Superblock {
         (u16::from_le(self.inner.state) & EXT4_VALID_FS) != 0
     }
 
+    /// 文件系统状态标志（`EXT4_SUPER_STATE_*`）
+    pub fn state(&self) -> u16 {
+        u16::from_le(self.inner.state)
+    }
+
+    /// 是否记录了错误（`EXT4_SUPER_STATE_ERROR`）
+    pub fn has_errors(&self) -> bool {
+        self.state() & EXT4_SUPER_STATE_ERROR != 0
+    }
+
+    /// 上次检查以来的挂载次数
+    pub fn mount_count(&self) -> u16 {
+        u16::from_le(self.inner.mnt_count)
+    }
+
+    /// 需要检查之前允许的最大挂载次数，0 或负数表示不按挂载次数检查
+    pub fn max_mount_count(&self) -> i16 {
+        u16::from_le(self.inner.max_mnt_count) as i16
+    }
+
+    /// 最后挂载时间（Unix 时间戳，秒）
+    pub fn mount_time(&self) -> u64 {
+        u32::from_le(self.inner.mtime) as u64 | (self.inner.mtime_hi as u64) << 32
+    }
+
+    /// 最后检查时间（Unix 时间戳，秒）
+    pub fn last_check_time(&self) -> u64 {
+        u32::from_le(self.inner.lastcheck) as u64 | (self.inner.lastcheck_hi as u64) << 32
+    }
+
+    /// 两次检查之间的最大间隔（秒），0 表示不按时间检查
+    pub fn check_interval(&self) -> u32 {
+        u32::from_le(self.inner.checkinterval)
+    }
+
+    /// 最后挂载的路径
+    pub fn last_mounted(&self) -> Option<&str> {
+        let len = self.inner.last_mounted
+            .iter()
+            .position(|&b| b == 0)
+            .unwrap_or(self.inner.last_mounted.len());
+
+        core::str::from_utf8(&self.inner.last_mounted[..len]).ok()
+    }
+
     /// 完整的 superblock 验证
     ///
     /// 对应 lwext4 的 `ext4_sb_check()`
@@ -390,7 +741,12 @@ impl Superblock {
 
     /// 判断超级块是否存在于指定的块组中
     ///
-    /// 对应 lwext4 的 `ext4_sb_is_super_in_bg()`
+    /// 对应 lwext4 的 `ext4_sb_is_super_in_bg()`（以及内核的 `ext4_bg_has_super()`）
+    ///
+    /// - 块组 0 总是包含超级块
+    /// - 启用 SPARSE_SUPER2 时只有 `s_backup_bgs` 中的块组有备份
+    /// - 启用 SPARSE_SUPER 时只有稀疏组有备份
+    /// - 否则每个块组都有备份
     ///
     /// # 参数
     ///
@@ -400,6 +756,15 @@ impl Superblock {
     ///
     /// 如果超级块存在于该块组返回 `true`
     pub fn has_super_in_bg(&self, group: u32) -> bool {
+        if group == 0 {
+            return true;
+        }
+
+        if self.has_compat_feature(EXT4_FEATURE_COMPAT_SPARSE_SUPER2) {
+            // 未使用的槽位为 0，不会与非 0 块组匹配
+            return self.backup_bgs().contains(&group);
+        }
+
         // 检查是否启用了稀疏超级块特性
         if self.has_ro_compat_feature(EXT4_FEATURE_RO_COMPAT_SPARSE_SUPER) {
             // 如果启用，只有稀疏组才有超级块
@@ -410,6 +775,41 @@ impl Superblock {
         }
     }
 
+    /// 获取保留的 GDT 块数（供在线扩容使用）
+    pub fn reserved_gdt_blocks(&self) -> u16 {
+        u16::from_le(self.inner.reserved_gdt_blocks)
+    }
+
+    /// 获取 SPARSE_SUPER2 指定的两个备份块组（`s_backup_bgs`）
+    ///
+    /// 值为 0 表示该槽位未使用
+    pub fn backup_bgs(&self) -> [u32; 2] {
+        [
+            u32::from_le(self.inner.backup_bgs[0]),
+            u32::from_le(self.inner.backup_bgs[1]),
+        ]
+    }
+
+    /// 列出所有包含备份超级块的块组（不含块组 0），按升序排列
+    ///
+    /// 写入备份和检查备份时都应以此为准，而不是自行假设备份位置。
+    pub fn backup_groups(&self) -> Vec<u32> {
+        let count = self.block_group_count();
+
+        if self.has_compat_feature(EXT4_FEATURE_COMPAT_SPARSE_SUPER2) {
+            let mut groups: Vec<u32> = self
+                .backup_bgs()
+                .into_iter()
+                .filter(|&g| g != 0 && g < count)
+                .collect();
+            groups.sort_unstable();
+            groups.dedup();
+            return groups;
+        }
+
+        (1..count).filter(|&g| self.has_super_in_bg(g)).collect()
+    }
+
     /// 计算指定块组的 GDT 块数（META_BG 模式）
     ///
     /// 对应 lwext4 的 `ext4_bg_num_gdb_meta()`
@@ -512,12 +912,14 @@ impl Superblock {
             num += self.num_gdb(block_group);
         }
 
-        // 转换为簇数
-        let log_cluster_size = u32::from_le(self.inner.log_cluster_size);
-        let cluster_ratio = 1u32 << log_cluster_size;
+        // 转换为簇数，s_log_cluster_size 与 s_log_block_size 一样以 1K 为单位，
+        // 未启用 BIGALLOC 时两者相等
+        let cluster_bits = u32::from_le(self.inner.log_cluster_size)
+            .saturating_sub(u32::from_le(self.inner.log_block_size));
+        let cluster_ratio = 1u32 << cluster_bits;
 
         // 向上取整
-        (num + cluster_ratio - 1) >> log_cluster_size
+        (num + cluster_ratio - 1) >> cluster_bits
     }
 }
 
@@ -573,6 +975,23 @@ mod tests {
         assert_eq!(sb.block_group_count(), 10);
     }
 
+    #[test]
+    fn test_read_only_features() {
+        let mut sb = Superblock::new(ext4_sblock {
+            feature_incompat: (EXT4_FEATURE_INCOMPAT_FILETYPE | EXT4_FEATURE_INCOMPAT_EXTENTS).to_le(),
+            feature_ro_compat: EXT4_FEATURE_RO_COMPAT_METADATA_CSUM.to_le(),
+            ..Default::default()
+        });
+        assert!(sb.check_features().can_write());
+        assert!(sb.check_writable().is_ok());
+
+        sb.inner.feature_ro_compat |= EXT4_FEATURE_RO_COMPAT_READONLY.to_le();
+        assert!(!sb.check_features().can_write());
+
+        sb.set_read_only(true);
+        assert_eq!(sb.check_writable().unwrap_err().kind(), ErrorKind::ReadOnlyFs);
+    }
+
     #[test]
     fn test_blocks_in_group_cnt() {
         let mut sb = ext4_sblock::default();
@@ -581,7 +1000,7 @@ mod tests {
         sb.blocks_count_lo = 950u32.to_le(); // 不能被 100 整除
         sb.blocks_per_group = 100u32.to_le();
 
-        let superblock = Superblock { inner: sb };
+        let superblock = Superblock::new(sb);
 
         // 总共 10 个块组（950 / 100 = 9 余 50）
         assert_eq!(superblock.block_group_count(), 10);
@@ -605,7 +1024,7 @@ mod tests {
         sb.inodes_count = 9050u32.to_le(); // 不能被 1000 整除
         sb.inodes_per_group = 1000u32.to_le();
 
-        let superblock = Superblock { inner: sb };
+        let superblock = Superblock::new(sb);
 
         // 总共 10 个块组
         assert_eq!(superblock.block_group_count(), 10);
@@ -618,4 +1037,33 @@ mod tests {
         // 最后一个块组只有 50 个 inode (9050 - 9000)
         assert_eq!(superblock.inodes_in_group_cnt(9), 50);
     }
+
+    #[test]
+    fn test_backup_groups() {
+        let mut sb = ext4_sblock {
+            magic: EXT4_SUPERBLOCK_MAGIC.to_le(),
+            log_block_size: 2u32.to_le(),
+            blocks_count_lo: 3000u32.to_le(),
+            blocks_per_group: 100u32.to_le(),
+            feature_ro_compat: EXT4_FEATURE_RO_COMPAT_SPARSE_SUPER.to_le(),
+            ..Default::default()
+        };
+
+        let superblock = Superblock::new(sb);
+        assert_eq!(superblock.backup_groups(), [1, 3, 5, 7, 9, 25, 27]);
+
+        // SPARSE_SUPER2：只有 s_backup_bgs 中的块组，忽略越界和未使用的槽位
+        sb.feature_compat = EXT4_FEATURE_COMPAT_SPARSE_SUPER2.to_le();
+        sb.backup_bgs = [1u32.to_le(), 29u32.to_le()];
+        let superblock = Superblock::new(sb);
+        assert_eq!(superblock.backup_groups(), [1, 29]);
+        assert!(superblock.has_super_in_bg(0));
+        assert!(superblock.has_super_in_bg(29));
+        assert!(!superblock.has_super_in_bg(3));
+
+        sb.backup_bgs = [0, 40u32.to_le()];
+        let superblock = Superblock::new(sb);
+        assert!(superblock.backup_groups().is_empty());
+        assert!(!superblock.has_super_in_bg(1));
+    }
 }
@@ -63,9 +63,11 @@ pub fn write_superblock<D: BlockDevice>(bdev: &mut BlockDev<D>, sb: &mut ext4_sb
 /// 根据 ext4 规范：
 /// - 主 superblock 总是在偏移 1024 字节处
 /// - 备份 superblock 在每个包含超级块的块组的起始位置
-/// - 块组是否包含超级块由 SPARSE_SUPER 特性决定
-///   - 未启用 SPARSE_SUPER：每个块组都有备份
+/// - 块组是否包含超级块由 SPARSE_SUPER / SPARSE_SUPER2 特性决定
+///   - 均未启用：每个块组都有备份
 ///   - 启用 SPARSE_SUPER：仅块组 0, 1, 以及 3/5/7 的幂次
+///   - 启用 SPARSE_SUPER2：仅 `s_backup_bgs` 指定的（至多）两个块组
+/// - 与 mke2fs 一致，备份的 `s_block_group_nr` 为其所在的块组号
 ///
 /// 这确保了文件系统的鲁棒性，即使主 superblock 损坏也能恢复
 pub fn write_superblock_with_backups<D: BlockDevice>(bdev: &mut BlockDev<D>, sb: &mut ext4_sblock) -> Result<()> {
@@ -84,30 +86,32 @@ pub fn write_superblock_with_backups<D: BlockDevice>(bdev: &mut BlockDev<D>, sb:
     bdev.write_bytes(EXT4_SUPERBLOCK_OFFSET, sb_bytes)?;
 
     // 2. 写入备份 superblock
-    // 创建临时 Superblock 包装器以使用 has_super_in_bg() 方法
+    // 创建临时 Superblock 包装器以使用 backup_groups() 方法
     let sb_wrapper = super::Superblock::new(*sb);
     let block_size = sb_wrapper.block_size() as u64;
-    let block_group_count = sb_wrapper.block_group_count();
 
-    // 遍历所有块组，写入包含 superblock 的块组
-    for bgid in 0..block_group_count {
-        // 块组 0 已经通过主 superblock 写入，跳过
-        if bgid == 0 {
-            continue;
-        }
-
-        // 检查此块组是否应该包含 superblock 备份
-        if sb_wrapper.has_super_in_bg(bgid) {
-            // 计算此块组的起始块号
-            let bg_start_block = sb_wrapper.first_data_block() as u64
-                + (bgid as u64) * sb_wrapper.blocks_per_group() as u64;
-
-            // superblock 在块组起始位置
-            let sb_offset = bg_start_block * block_size;
+    // 写入每个备份块组（块组 0 已经通过主 superblock 写入）
+    for bgid in sb_wrapper.backup_groups() {
+        // 计算此块组的起始块号
+        let bg_start_block = sb_wrapper.first_data_block() as u64
+            + (bgid as u64) * sb_wrapper.blocks_per_group() as u64;
+
+        // superblock 在块组起始位置
+        let sb_offset = bg_start_block * block_size;
+
+        // 备份中记录自己所在的块组号，校验和随之重新计算
+        let mut backup = *sb;
+        backup.block_group_nr = (bgid as u16).to_le();
+        super::checksum::set_checksum(&mut backup);
+        let backup_bytes = unsafe {
+            core::slice::from_raw_parts(
+                &backup as *const ext4_sblock as *const u8,
+                core::mem::size_of::<ext4_sblock>(),
+            )
+        };
 
-            // 写入备份 superblock
-            bdev.write_bytes(sb_offset, sb_bytes)?;
-        }
+        // 写入备份 superblock
+        bdev.write_bytes(sb_offset, backup_bytes)?;
     }
 
     Ok(())
@@ -142,7 +146,7 @@ impl super::Superblock {
     ///
     /// 在写入前会自动更新校验和（如果启用）
     ///
-    /// 根据 SPARSE_SUPER 特性写入备份 superblock 到正确的块组
+    /// 根据 SPARSE_SUPER / SPARSE_SUPER2 特性写入备份 superblock 到正确的块组
     ///
     /// # 参数
     ///
@@ -161,12 +165,16 @@ impl super::Superblock {
 
     /// 更新空闲块数
     ///
+    /// 未启用 64 位特性时只写低 32 位
+    ///
     /// # 参数
     ///
     /// * `count` - 新的空闲块数
     pub fn set_free_blocks_count(&mut self, count: u64) {
-        self.inner.free_blocks_count_lo = count as u32;
-        self.inner.free_blocks_count_hi = (count >> 32) as u32;
+        self.inner.free_blocks_count_lo = (count as u32).to_le();
+        if self.is_64bit() {
+            self.inner.free_blocks_count_hi = ((count >> 32) as u32).to_le();
+        }
     }
 
     /// 更新空闲 inode 数
@@ -178,6 +186,56 @@ impl super::Superblock {
         self.inner.free_inodes_count = count;
     }
 
+    /// 更新总块数
+    ///
+    /// 未启用 64 位特性时只写低 32 位
+    pub fn set_blocks_count(&mut self, count: u64) {
+        self.inner.blocks_count_lo = (count as u32).to_le();
+        if self.is_64bit() {
+            self.inner.blocks_count_hi = ((count >> 32) as u32).to_le();
+        }
+    }
+
+    /// 更新为 root 保留的块数（`s_r_blocks_count`）
+    ///
+    /// 未启用 64 位特性时只写低 32 位
+    pub fn set_r_blocks_count(&mut self, count: u64) {
+        self.inner.r_blocks_count_lo = (count as u32).to_le();
+        if self.is_64bit() {
+            self.inner.r_blocks_count_hi = ((count >> 32) as u32).to_le();
+        }
+    }
+
+    /// 更新总 inode 数
+    pub fn set_inodes_count(&mut self, count: u32) {
+        self.inner.inodes_count = count.to_le();
+    }
+
+    /// 更新保留的 GDT 块数
+    pub fn set_reserved_gdt_blocks(&mut self, count: u16) {
+        self.inner.reserved_gdt_blocks = count.to_le();
+    }
+
+    /// 设置 SPARSE_SUPER2 的备份块组（`s_backup_bgs`）
+    ///
+    /// 0 表示不使用该槽位。调整块组数量后需要同步更新，
+    /// 使第二个备份始终位于最后一个块组。
+    ///
+    /// # 参数
+    ///
+    /// * `groups` - 两个备份块组号
+    pub fn set_backup_bgs(&mut self, groups: [u32; 2]) {
+        self.inner.backup_bgs = [groups[0].to_le(), groups[1].to_le()];
+    }
+
+    /// 设置 ro_compat 特性位
+    ///
+    /// 只修改内存中的 superblock，调用者负责写回
+    pub fn set_ro_compat_feature(&mut self, feature: u32) {
+        let features = u32::from_le(self.inner.feature_ro_compat);
+        self.inner.feature_ro_compat = (features | feature).to_le();
+    }
+
     /// 增加空闲块数
     ///
     /// # 参数
@@ -222,7 +280,25 @@ impl super::Superblock {
     ///
     /// 每次挂载文件系统时调用
     pub fn inc_mount_count(&mut self) {
-        self.inner.mnt_count = self.inner.mnt_count.saturating_add(1);
+        self.inner.mnt_count = u16::from_le(self.inner.mnt_count).saturating_add(1).to_le();
+    }
+
+    /// 设置需要检查之前允许的最大挂载次数，-1 表示不按挂载次数检查
+    pub fn set_max_mount_count(&mut self, count: i16) {
+        self.inner.max_mnt_count = (count as u16).to_le();
+    }
+
+    /// 设置最后挂载时间（Unix 时间戳，秒）
+    pub fn set_mount_time(&mut self, secs: u64) {
+        self.inner.mtime = (secs as u32).to_le();
+        self.inner.mtime_hi = (secs >> 32) as u8;
+    }
+
+    /// 设置最后挂载的路径，超过 63 字节的部分被截断
+    pub fn set_last_mounted(&mut self, path: &str) {
+        let len = path.len().min(self.inner.last_mounted.len() - 1);
+        self.inner.last_mounted = [0; 64];
+        self.inner.last_mounted[..len].copy_from_slice(&path.as_bytes()[..len]);
     }
 
     /// 更新写入计数
@@ -253,7 +329,17 @@ impl super::Superblock {
     ///
     /// * `state` - 状态值（1 = 干净，2 = 有错误）
     pub fn set_state(&mut self, state: u16) {
-        self.inner.state = state;
+        self.inner.state = state.to_le();
+    }
+
+    /// 设置状态标志（`EXT4_SUPER_STATE_*`），保留其他标志
+    pub fn set_state_flags(&mut self, flags: u16) {
+        self.inner.state = (u16::from_le(self.inner.state) | flags).to_le();
+    }
+
+    /// 清除状态标志（`EXT4_SUPER_STATE_*`），保留其他标志
+    pub fn clear_state_flags(&mut self, flags: u16) {
+        self.inner.state = (u16::from_le(self.inner.state) & !flags).to_le();
     }
 
     /// 标记文件系统为干净
@@ -297,7 +383,7 @@ fn current_timestamp() -> u32 {
 mod tests {
     use super::*;
     use crate::block::{BlockDevice, BlockDev};
-    use crate::error::Result;
+    use crate::error::{ErrorKind, Result};
     use crate::superblock::Superblock;
 
     struct MockDevice {
@@ -362,7 +448,7 @@ mod tests {
         sb.free_blocks_count_hi = 0;
         sb.free_inodes_count = 500;
 
-        let mut superblock = Superblock { inner: sb };
+        let mut superblock = Superblock::new(sb);
 
         // 测试修改空闲块数
         assert_eq!(superblock.free_blocks_count(), 1000);
@@ -383,10 +469,55 @@ mod tests {
     }
 
     #[test]
-    fn test_superblock_state() {
-        let mut superblock = Superblock {
-            inner: ext4_sblock::default(),
+    fn test_load_with_fallback() {
+        // 1K 块、两个块组，备份位于块组 1 的起始块 8193
+        let total_blocks = 2200;
+        let device = MockDevice {
+            block_size: 4096,
+            sector_size: 512,
+            total_blocks,
+            storage: alloc::vec![0u8; total_blocks as usize * 4096],
+        };
+        let mut block_dev = BlockDev::new(device).unwrap();
+
+        let sb = ext4_sblock {
+            magic: EXT4_SUPERBLOCK_MAGIC.to_le(),
+            first_data_block: 1u32.to_le(),
+            blocks_per_group: 8192u32.to_le(),
+            blocks_count_lo: 16385u32.to_le(),
+            feature_ro_compat: (EXT4_FEATURE_RO_COMPAT_SPARSE_SUPER
+                | EXT4_FEATURE_RO_COMPAT_METADATA_CSUM)
+                .to_le(),
+            checksum_type: EXT4_CHECKSUM_CRC32C,
+            ..Default::default()
         };
+        let mut superblock = Superblock::new(sb);
+        superblock.write_with_backups(&mut block_dev).unwrap();
+
+        let mut backup = [0u8; 1024];
+        block_dev.read_bytes(8193 * 1024, &mut backup).unwrap();
+        assert_eq!(u16::from_le_bytes([backup[90], backup[91]]), 1);
+        assert!(Superblock::load(&mut block_dev).unwrap().verify_checksum());
+
+        // 破坏主 superblock 的校验和
+        block_dev.write_bytes(1024 + 0x78, b"x").unwrap();
+        let loaded = Superblock::load_with_fallback(&mut block_dev).unwrap();
+        assert_eq!(loaded.blocks_count(), 16385);
+        assert_eq!(loaded.inner().block_group_nr, 0);
+        assert!(loaded.verify_checksum());
+
+        // 主副本和备份都损坏时返回主副本的错误
+        block_dev.write_bytes(8193 * 1024 + 0x38, &[0, 0]).unwrap();
+        let Err(err) = Superblock::load_with_fallback(&mut block_dev) else {
+            panic!("corrupted superblock loaded");
+        };
+        assert_eq!(err.kind(), ErrorKind::Corrupted);
+        assert_eq!(err.block(), Some(1));
+    }
+
+    #[test]
+    fn test_superblock_state() {
+        let mut superblock = Superblock::new(ext4_sblock::default());
 
         superblock.mark_clean();
         assert_eq!(superblock.inner().state, EXT4_SUPER_STATE_VALID);
@@ -0,0 +1,427 @@
+//! 故障注入块设备
+//!
+//! [`FaultyBlockDevice`] 包装任意 [`BlockDevice`]，在第 N 次写入之后让写入失败、
+//! 静默丢弃或只写入一部分扇区（撕裂写），用来模拟写入过程中掉电。
+//!
+//! 还可以模拟设备的易失性写缓存（[`new_with_write_cache`](FaultyBlockDevice::new_with_write_cache)）：
+//! 写入先留在缓存中，直到 `flush`/`flush_barrier` 才到达底层设备；
+//! [`crash`](FaultyBlockDevice::crash) 丢弃尚未刷新的写入，
+//! [`crash_partial`](FaultyBlockDevice::crash_partial) 按种子随机保留其中一部分，
+//! 模拟设备乱序落盘。
+//!
+//! 所有行为都是确定的：同样的操作序列和种子总是得到同样的设备内容，
+//! 失败的用例可以直接重放。
+
+use crate::{
+    block::BlockDevice,
+    error::{Error, ErrorKind, Result},
+};
+use alloc::vec::Vec;
+
+/// 注入的故障
+///
+/// 写入次数按 [`BlockDevice::write_blocks`] 的调用次数计算，
+/// 从 [`FaultyBlockDevice::set_fault`] 开始计数
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Fault {
+    /// 前 `after` 次写入正常完成，之后的写入返回 `ErrorKind::Io`
+    FailWrites {
+        /// 正常完成的写入次数
+        after: u64,
+    },
+    /// 前 `after` 次写入正常完成，之后的写入报告成功但被丢弃（掉电后 CPU 仍在运行）
+    DropWrites {
+        /// 正常完成的写入次数
+        after: u64,
+    },
+    /// 前 `after` 次写入正常完成，下一次写入只写入前 `sectors` 个扇区，之后的写入被丢弃
+    TornWrite {
+        /// 正常完成的写入次数
+        after: u64,
+        /// 撕裂的那次写入实际写入的扇区数
+        sectors: u32,
+    },
+}
+
+impl Fault {
+    /// 正常完成的写入次数
+    fn after(&self) -> u64 {
+        match *self {
+            Fault::FailWrites { after } | Fault::DropWrites { after } | Fault::TornWrite { after, .. } => after,
+        }
+    }
+}
+
+/// 写缓存中尚未刷新的写入
+struct PendingWrite {
+    /// 起始扇区
+    lba: u64,
+    data: Vec<u8>,
+}
+
+/// 故障注入块设备
+///
+/// # 示例
+///
+/// ```rust,ignore
+/// use lwext4_core::testing::{crash_and_remount, Fault, FaultyBlockDevice};
+///
+/// let dev = FaultyBlockDevice::new_with_write_cache(image);
+/// let mut fs = Ext4FileSystem::mount(BlockDev::new_with_cache(dev, 256)?)?;
+/// fs.write_file("/a", b"committed")?;
+/// fs.fsync()?;
+///
+/// // 之后的第 3 次写入只写入一个扇区，然后掉电
+/// fs.block_device_mut().device_mut().set_fault(Some(Fault::TornWrite { after: 2, sectors: 1 }));
+/// fs.write_file("/a", b"lost")?;
+///
+/// let mut fs = crash_and_remount(fs, FsConfig::default())?;
+/// assert_eq!(fs.read_file("/a")?, b"committed");
+/// ```
+pub struct FaultyBlockDevice<B: BlockDevice> {
+    inner: B,
+    fault: Option<Fault>,
+    /// 设置故障以来的写入次数（包括被丢弃和失败的写入）
+    writes: u64,
+    /// 故障是否已经触发
+    tripped: bool,
+    /// 易失性写缓存，`None` 表示写入直接到达底层设备
+    pending: Option<Vec<PendingWrite>>,
+}
+
+impl<B: BlockDevice> FaultyBlockDevice<B> {
+    /// 创建没有写缓存的故障注入设备，写入直接到达底层设备
+    pub fn new(inner: B) -> Self {
+        Self {
+            inner,
+            fault: None,
+            writes: 0,
+            tripped: false,
+            pending: None,
+        }
+    }
+
+    /// 创建带易失性写缓存的故障注入设备
+    ///
+    /// 写入在 `flush`/`flush_barrier` 时才按顺序写到底层设备，读取能看到缓存中的写入
+    pub fn new_with_write_cache(inner: B) -> Self {
+        Self {
+            pending: Some(Vec::new()),
+            ..Self::new(inner)
+        }
+    }
+
+    /// 设置故障，`None` 取消；写入计数从 0 重新开始
+    pub fn set_fault(&mut self, fault: Option<Fault>) {
+        self.fault = fault;
+        self.writes = 0;
+        self.tripped = false;
+    }
+
+    /// 当前的故障
+    pub fn fault(&self) -> Option<Fault> {
+        self.fault
+    }
+
+    /// 设置故障以来的写入次数
+    pub fn writes(&self) -> u64 {
+        self.writes
+    }
+
+    /// 故障是否已经触发
+    pub fn is_tripped(&self) -> bool {
+        self.tripped
+    }
+
+    /// 写缓存中尚未刷新的写入次数
+    pub fn unflushed_writes(&self) -> usize {
+        self.pending.as_ref().map_or(0, Vec::len)
+    }
+
+    /// 模拟掉电后重新上电
+    ///
+    /// 丢弃写缓存中尚未刷新的写入，清除故障和写入计数。
+    /// 之前在设备之上的 `BlockDev` 和文件系统的内存状态都已失效，
+    /// 见 [`crash_and_remount`](super::crash_and_remount)。
+    pub fn crash(&mut self) {
+        if let Some(pending) = &mut self.pending {
+            pending.clear();
+        }
+        self.set_fault(None);
+    }
+
+    /// 模拟掉电，写缓存中的每次写入按 `seed` 决定是否已经落盘
+    ///
+    /// 保留的写入按原顺序写到底层设备，其余的丢弃，之后同 [`crash`](Self::crash)
+    pub fn crash_partial(&mut self, seed: u64) -> Result<()> {
+        let pending = self.pending.as_mut().map(core::mem::take).unwrap_or_default();
+        let mut rng = XorShift::new(seed);
+        for write in pending {
+            if rng.next() & 1 == 1 {
+                self.write_through(write.lba, &write.data)?;
+            }
+        }
+        self.crash();
+        Ok(())
+    }
+
+    /// 底层设备
+    pub fn inner(&self) -> &B {
+        &self.inner
+    }
+
+    /// 底层设备的可变引用，直接修改不经过故障注入和写缓存
+    pub fn inner_mut(&mut self) -> &mut B {
+        &mut self.inner
+    }
+
+    /// 取出底层设备，写缓存中尚未刷新的写入被丢弃
+    pub fn into_inner(self) -> B {
+        self.inner
+    }
+
+    /// 写到底层设备
+    fn write_through(&mut self, lba: u64, data: &[u8]) -> Result<()> {
+        let count = (data.len() / self.inner.sector_size() as usize) as u32;
+        self.inner.write_blocks(lba, count, data)?;
+        Ok(())
+    }
+
+    /// 把写缓存中的写入按顺序写到底层设备
+    fn flush_pending(&mut self) -> Result<()> {
+        let Some(pending) = self.pending.as_mut() else {
+            return Ok(());
+        };
+        for write in core::mem::take(pending) {
+            self.write_through(write.lba, &write.data)?;
+        }
+        Ok(())
+    }
+}
+
+impl<B: BlockDevice> BlockDevice for FaultyBlockDevice<B> {
+    fn block_size(&self) -> u32 {
+        self.inner.block_size()
+    }
+
+    fn sector_size(&self) -> u32 {
+        self.inner.sector_size()
+    }
+
+    fn total_blocks(&self) -> u64 {
+        self.inner.total_blocks()
+    }
+
+    fn read_blocks(&mut self, lba: u64, count: u32, buf: &mut [u8]) -> Result<usize> {
+        let n = self.inner.read_blocks(lba, count, buf)?;
+
+        // 写缓存中的写入比底层设备新，按写入顺序覆盖重叠的扇区
+        let ss = self.sector_size() as u64;
+        let end = lba + count as u64;
+        for write in self.pending.iter().flatten() {
+            let write_end = write.lba + write.data.len() as u64 / ss;
+            let (start, stop) = (write.lba.max(lba), write_end.min(end));
+            if start < stop {
+                let dst = ((start - lba) * ss) as usize..((stop - lba) * ss) as usize;
+                let src = ((start - write.lba) * ss) as usize..((stop - write.lba) * ss) as usize;
+                buf[dst].copy_from_slice(&write.data[src]);
+            }
+        }
+        Ok(n)
+    }
+
+    fn write_blocks(&mut self, lba: u64, count: u32, buf: &[u8]) -> Result<usize> {
+        let len = count as usize * self.sector_size() as usize;
+        if buf.len() < len {
+            return Err(Error::new(ErrorKind::InvalidInput, "Buffer too small for write"));
+        }
+
+        let index = self.writes;
+        self.writes += 1;
+        let sectors = match self.fault {
+            Some(fault) if index >= fault.after() => {
+                self.tripped = true;
+                match fault {
+                    Fault::FailWrites { .. } => {
+                        return Err(Error::new(ErrorKind::Io, "Injected write failure"));
+                    }
+                    Fault::DropWrites { .. } => 0,
+                    Fault::TornWrite { after, sectors } if index == after => sectors.min(count),
+                    Fault::TornWrite { .. } => 0,
+                }
+            }
+            _ => count,
+        };
+        if sectors == 0 {
+            return Ok(len);
+        }
+
+        let data = &buf[..sectors as usize * self.sector_size() as usize];
+        match &mut self.pending {
+            Some(pending) => pending.push(PendingWrite { lba, data: data.to_vec() }),
+            None => self.write_through(lba, data)?,
+        }
+        Ok(len)
+    }
+
+    fn flush(&mut self) -> Result<()> {
+        self.flush_pending()?;
+        self.inner.flush()
+    }
+
+    fn flush_barrier(&mut self) -> Result<()> {
+        self.flush_pending()?;
+        self.inner.flush_barrier()
+    }
+
+    fn is_read_only(&self) -> bool {
+        self.inner.is_read_only()
+    }
+
+    fn open(&mut self) -> Result<()> {
+        self.inner.open()
+    }
+
+    fn close(&mut self) -> Result<()> {
+        self.inner.close()
+    }
+}
+
+/// xorshift64 伪随机数，只用于可重放的故障注入
+struct XorShift(u64);
+
+impl XorShift {
+    fn new(seed: u64) -> Self {
+        // 0 是 xorshift 的不动点
+        Self(seed ^ 0x9e37_79b9_7f4a_7c15)
+    }
+
+    fn next(&mut self) -> u64 {
+        let mut x = self.0;
+        x ^= x << 13;
+        x ^= x >> 7;
+        x ^= x << 17;
+        self.0 = x;
+        x
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// 512 字节扇区、4K 块的内存设备
+    struct MemDevice {
+        storage: Vec<u8>,
+        barriers: usize,
+    }
+
+    impl MemDevice {
+        fn new(total_blocks: u64) -> Self {
+            Self { storage: alloc::vec![0u8; total_blocks as usize * 4096], barriers: 0 }
+        }
+    }
+
+    impl BlockDevice for MemDevice {
+        fn block_size(&self) -> u32 {
+            4096
+        }
+
+        fn sector_size(&self) -> u32 {
+            512
+        }
+
+        fn total_blocks(&self) -> u64 {
+            (self.storage.len() / 4096) as u64
+        }
+
+        fn read_blocks(&mut self, lba: u64, count: u32, buf: &mut [u8]) -> Result<usize> {
+            let start = lba as usize * 512;
+            let len = count as usize * 512;
+            buf[..len].copy_from_slice(&self.storage[start..start + len]);
+            Ok(len)
+        }
+
+        fn write_blocks(&mut self, lba: u64, count: u32, buf: &[u8]) -> Result<usize> {
+            let start = lba as usize * 512;
+            let len = count as usize * 512;
+            self.storage[start..start + len].copy_from_slice(&buf[..len]);
+            Ok(len)
+        }
+
+        fn flush_barrier(&mut self) -> Result<()> {
+            self.barriers += 1;
+            Ok(())
+        }
+    }
+
+    #[test]
+    fn test_fail_and_drop_writes() {
+        let mut dev = FaultyBlockDevice::new(MemDevice::new(4));
+        dev.set_fault(Some(Fault::FailWrites { after: 1 }));
+        dev.write_blocks(0, 1, &[1u8; 512]).unwrap();
+        assert!(!dev.is_tripped());
+        let err = dev.write_blocks(8, 1, &[2u8; 512]).unwrap_err();
+        assert_eq!(err.kind(), ErrorKind::Io);
+        assert!(dev.is_tripped());
+
+        dev.set_fault(Some(Fault::DropWrites { after: 0 }));
+        assert_eq!(dev.write_blocks(8, 1, &[3u8; 512]).unwrap(), 512);
+        assert_eq!(dev.writes(), 1);
+        assert_eq!(dev.inner().storage[0], 1);
+        assert_eq!(dev.inner().storage[8 * 512], 0);
+    }
+
+    #[test]
+    fn test_torn_write() {
+        let mut dev = FaultyBlockDevice::new(MemDevice::new(4));
+        dev.set_fault(Some(Fault::TornWrite { after: 1, sectors: 3 }));
+        dev.write_blocks(0, 8, &[1u8; 4096]).unwrap();
+        dev.write_blocks(8, 8, &[2u8; 4096]).unwrap();
+        dev.write_blocks(16, 8, &[3u8; 4096]).unwrap();
+
+        let storage = &dev.inner().storage;
+        assert!(storage[..4096].iter().all(|&b| b == 1));
+        assert!(storage[4096..4096 + 3 * 512].iter().all(|&b| b == 2));
+        assert!(storage[4096 + 3 * 512..].iter().all(|&b| b == 0));
+    }
+
+    #[test]
+    fn test_write_cache_crash() {
+        let mut dev = FaultyBlockDevice::new_with_write_cache(MemDevice::new(4));
+        dev.write_blocks(0, 8, &[1u8; 4096]).unwrap();
+        dev.flush_barrier().unwrap();
+        assert_eq!(dev.inner().barriers, 1);
+
+        dev.write_blocks(4, 8, &[2u8; 4096]).unwrap();
+        assert_eq!(dev.unflushed_writes(), 1);
+        // 读取能看到缓存中的写入
+        let mut buf = [0u8; 4096];
+        dev.read_blocks(0, 8, &mut buf).unwrap();
+        assert!(buf[..2048].iter().all(|&b| b == 1));
+        assert!(buf[2048..].iter().all(|&b| b == 2));
+
+        dev.crash();
+        assert_eq!(dev.unflushed_writes(), 0);
+        dev.read_blocks(0, 8, &mut buf).unwrap();
+        assert!(buf.iter().all(|&b| b == 1));
+        assert!(dev.inner().storage[4096..].iter().all(|&b| b == 0));
+    }
+
+    #[test]
+    fn test_crash_partial_is_deterministic() {
+        let run = |seed| {
+            let mut dev = FaultyBlockDevice::new_with_write_cache(MemDevice::new(4));
+            for i in 0..16u8 {
+                dev.write_blocks(i as u64, 1, &[i + 1; 512]).unwrap();
+            }
+            dev.crash_partial(seed).unwrap();
+            dev.into_inner().storage
+        };
+
+        let kept = run(7);
+        assert_eq!(kept, run(7));
+        let survivors = (0..16).filter(|&i| kept[i * 512] != 0).count();
+        assert!(survivors > 0 && survivors < 16);
+    }
+}
@@ -0,0 +1,270 @@
+//! 单元测试用的最小 ext4 镜像
+//!
+//! 4 KiB 块、单个块组，只启用 `dir_index`、`filetype`、`extents`、`sparse_super` 和 `large_file`，
+//! 根目录只有 `.` 和 `..`。可选地带一个 JBD2 日志（inode 8），或者启用 `metadata_csum`。
+//! 布局固定，测试可以直接按块号检查设备内容：
+//!
+//! | 块          | 内容             |
+//! |-------------|------------------|
+//! | 0           | superblock       |
+//! | 1           | 块组描述符表     |
+//! | 2           | 块位图           |
+//! | 3           | inode 位图       |
+//! | 4..12       | inode 表         |
+//! | 12          | 根目录           |
+//! | 16..16+256  | 日志（可选）     |
+
+use crate::{
+    block::{BlockDev, MemBlockDevice},
+    consts::*,
+    fs::Ext4FileSystem,
+    superblock::Superblock,
+    types::{ext4_extent, ext4_extent_header, ext4_group_desc, ext4_inode, ext4_sblock},
+};
+use alloc::vec::Vec;
+
+const BLOCK_SIZE: usize = 4096;
+/// 镜像总块数（8 MiB）
+pub(crate) const IMAGE_BLOCKS: u64 = 2048;
+const INODES: u32 = 128;
+const INODE_SIZE: usize = 256;
+const INODE_TABLE: u64 = 4;
+const ROOT_DIR_BLOCK: u64 = 12;
+/// 日志 inode
+const JOURNAL_INO: u32 = 8;
+/// 第一个非保留 inode
+const FIRST_INO: u32 = 11;
+/// 日志的第一个块
+pub(crate) const JOURNAL_START: u64 = 16;
+/// 日志块数
+pub(crate) const JOURNAL_BLOCKS: u32 = 256;
+/// CRC32C（`JBD2_CRC32C_CHKSUM`）
+#[cfg(feature = "journal")]
+const JBD_CRC32C_CHKSUM: u8 = 4;
+
+/// 没有日志的镜像
+pub(crate) fn image() -> MemBlockDevice {
+    build(None, false)
+}
+
+/// 启用 `metadata_csum` 的镜像，所有元数据都带有正确的校验和
+#[cfg(feature = "metadata-csum")]
+pub(crate) fn image_with_csum() -> MemBlockDevice {
+    build(None, true)
+}
+
+/// 带日志的镜像，`jbd_incompat` 是日志 superblock 额外的不兼容特性（例如校验和版本）
+#[cfg(feature = "journal")]
+pub(crate) fn image_with_journal(jbd_incompat: u32) -> MemBlockDevice {
+    build(Some(jbd_incompat), false)
+}
+
+/// 用 64 块缓存挂载
+pub(crate) fn mount(dev: MemBlockDevice) -> Ext4FileSystem<MemBlockDevice> {
+    Ext4FileSystem::mount(BlockDev::new_with_cache(dev, 64).unwrap()).unwrap()
+}
+
+fn build(journal: Option<u32>, csum: bool) -> MemBlockDevice {
+    let mut data = alloc::vec![0u8; IMAGE_BLOCKS as usize * BLOCK_SIZE];
+    let journal_blocks = if journal.is_some() { JOURNAL_BLOCKS as u64 } else { 0 };
+    let used_blocks = ROOT_DIR_BLOCK + 1 + journal_blocks;
+    let reserved_inodes = FIRST_INO - 1;
+
+    let mut sb = ext4_sblock {
+        inodes_count: INODES.to_le(),
+        blocks_count_lo: (IMAGE_BLOCKS as u32).to_le(),
+        free_blocks_count_lo: ((IMAGE_BLOCKS - used_blocks) as u32).to_le(),
+        free_inodes_count: (INODES - reserved_inodes).to_le(),
+        log_block_size: 2u32.to_le(),
+        log_cluster_size: 2u32.to_le(),
+        blocks_per_group: 32768u32.to_le(),
+        clusters_per_group: 32768u32.to_le(),
+        inodes_per_group: INODES.to_le(),
+        max_mnt_count: u16::MAX.to_le(),
+        magic: EXT4_SUPERBLOCK_MAGIC.to_le(),
+        state: 1u16.to_le(),
+        errors: 1u16.to_le(),
+        rev_level: 1u32.to_le(),
+        first_ino: FIRST_INO.to_le(),
+        inode_size: (INODE_SIZE as u16).to_le(),
+        feature_compat: EXT4_FEATURE_COMPAT_DIR_INDEX.to_le(),
+        feature_incompat: (EXT4_FEATURE_INCOMPAT_FILETYPE | EXT4_FEATURE_INCOMPAT_EXTENTS).to_le(),
+        feature_ro_compat: (EXT4_FEATURE_RO_COMPAT_SPARSE_SUPER | EXT4_FEATURE_RO_COMPAT_LARGE_FILE).to_le(),
+        uuid: *b"lwext4-core-test",
+        hash_seed: [0x1234_5678u32.to_le(); 4],
+        def_hash_version: 1,
+        min_extra_isize: 32u16.to_le(),
+        want_extra_isize: 32u16.to_le(),
+        ..Default::default()
+    };
+    if journal.is_some() {
+        sb.feature_compat |= EXT4_FEATURE_COMPAT_HAS_JOURNAL.to_le();
+        sb.journal_inum = JOURNAL_INO.to_le();
+    }
+    if csum {
+        sb.feature_ro_compat |= EXT4_FEATURE_RO_COMPAT_METADATA_CSUM.to_le();
+        sb.checksum_type = 1;
+    }
+    let superblock = Superblock::new(sb);
+
+    let mut desc = ext4_group_desc {
+        block_bitmap_lo: 2u32.to_le(),
+        inode_bitmap_lo: 3u32.to_le(),
+        inode_table_lo: (INODE_TABLE as u32).to_le(),
+        free_blocks_count_lo: ((IMAGE_BLOCKS - used_blocks) as u16).to_le(),
+        free_inodes_count_lo: ((INODES - reserved_inodes) as u16).to_le(),
+        used_dirs_count_lo: 1u16.to_le(),
+        ..Default::default()
+    };
+    // 位图：已用的块和 inode，以及块组末尾之后的填充位
+    let block_bitmap = &mut data[2 * BLOCK_SIZE..3 * BLOCK_SIZE];
+    set_bits(block_bitmap, 0..ROOT_DIR_BLOCK as usize + 1);
+    set_bits(block_bitmap, JOURNAL_START as usize..(JOURNAL_START + journal_blocks) as usize);
+    set_bits(block_bitmap, IMAGE_BLOCKS as usize..BLOCK_SIZE * 8);
+    let inode_bitmap = &mut data[3 * BLOCK_SIZE..4 * BLOCK_SIZE];
+    set_bits(inode_bitmap, 0..reserved_inodes as usize);
+    set_bits(inode_bitmap, INODES as usize..BLOCK_SIZE * 8);
+
+    let root = extent_inode(0o040755, 2, 1, ROOT_DIR_BLOCK);
+    write_inode(&mut data, EXT4_ROOT_INODE, &root);
+
+    let dir = &mut data[ROOT_DIR_BLOCK as usize * BLOCK_SIZE..][..BLOCK_SIZE];
+    write_dir_entry(dir, 0, EXT4_ROOT_INODE, 12, b".");
+    if csum {
+        // 块尾留给 ext4_dir_entry_tail
+        write_dir_entry(dir, 12, EXT4_ROOT_INODE, BLOCK_SIZE as u16 - 24, b"..");
+        dir[BLOCK_SIZE - 12..BLOCK_SIZE - 8].fill(0);
+        dir[BLOCK_SIZE - 8..BLOCK_SIZE - 6].copy_from_slice(&12u16.to_le_bytes());
+        dir[BLOCK_SIZE - 6] = 0;
+        dir[BLOCK_SIZE - 5] = 0xDE;
+        let seed = superblock.inode_csum_seed(EXT4_ROOT_INODE, 0);
+        crate::dir::checksum::set_leaf_csum(seed, dir, BLOCK_SIZE);
+    } else {
+        write_dir_entry(dir, 12, EXT4_ROOT_INODE, BLOCK_SIZE as u16 - 12, b"..");
+    }
+
+    if let Some(incompat) = journal {
+        let journal_inode = extent_inode(0o100600, 1, JOURNAL_BLOCKS, JOURNAL_START);
+        write_inode(&mut data, JOURNAL_INO, &journal_inode);
+        write_journal_sb(&mut data, &sb, incompat);
+    }
+
+    if csum {
+        let offset = INODE_TABLE as usize * BLOCK_SIZE + (EXT4_ROOT_INODE as usize - 1) * INODE_SIZE;
+        crate::inode::checksum::set_checksum_raw(&superblock, EXT4_ROOT_INODE, &mut data[offset..offset + INODE_SIZE]);
+        crate::balloc::checksum::set_bitmap_csum(&superblock, &mut desc, &data[2 * BLOCK_SIZE..3 * BLOCK_SIZE]);
+        crate::ialloc::set_bitmap_csum(&superblock, &mut desc, &data[3 * BLOCK_SIZE..4 * BLOCK_SIZE]);
+    }
+
+    // 没有 64bit 特性，描述符只有前 32 字节
+    let mut desc_bytes = struct_bytes(&desc);
+    crate::block_group::checksum::set_checksum(&superblock, 0, &mut desc_bytes[..32]);
+    data[BLOCK_SIZE..BLOCK_SIZE + 32].copy_from_slice(&desc_bytes[..32]);
+
+    crate::superblock::checksum::set_checksum(&mut sb);
+    write_struct(&mut data, 1024, &sb);
+
+    MemBlockDevice::from_vec(data).unwrap()
+}
+
+#[cfg(feature = "journal")]
+fn write_journal_sb(data: &mut [u8], sb: &ext4_sblock, incompat: u32) {
+    use crate::journal::{jbd_sb, JBD_FEATURE_INCOMPAT_REVOKE};
+
+    let mut jsb = jbd_sb {
+        maxlen: JOURNAL_BLOCKS.to_be(),
+        first: 1u32.to_be(),
+        sequence: 1u32.to_be(),
+        feature_incompat: (JBD_FEATURE_INCOMPAT_REVOKE | incompat).to_be(),
+        uuid: sb.uuid,
+        nr_users: 1u32.to_be(),
+        ..Default::default()
+    };
+    if jsb.has_csum_v2or3() {
+        jsb.checksum_type = JBD_CRC32C_CHKSUM;
+        jsb.checksum = crate::crc::crc32c(&struct_bytes(&jsb)).to_be();
+    }
+    write_struct(data, JOURNAL_START as usize * BLOCK_SIZE, &jsb);
+}
+
+#[cfg(not(feature = "journal"))]
+fn write_journal_sb(_data: &mut [u8], _sb: &ext4_sblock, _incompat: u32) {
+    unreachable!("journal images need the `journal` feature")
+}
+
+/// 使用单个 extent 映射 `[start, start + blocks)` 的 inode
+fn extent_inode(mode: u16, links: u16, blocks: u32, start: u64) -> ext4_inode {
+    let mut inode = ext4_inode {
+        mode: mode.to_le(),
+        size_lo: (blocks * BLOCK_SIZE as u32).to_le(),
+        links_count: links.to_le(),
+        blocks_count_lo: (blocks * (BLOCK_SIZE as u32 / 512)).to_le(),
+        flags: EXT4_INODE_FLAG_EXTENTS.to_le(),
+        extra_isize: 32u16.to_le(),
+        ..Default::default()
+    };
+    let header = ext4_extent_header {
+        magic: 0xF30Au16.to_le(),
+        entries: 1u16.to_le(),
+        max: 4u16.to_le(),
+        depth: 0,
+        generation: 0,
+    };
+    let extent = ext4_extent {
+        block: 0,
+        len: (blocks as u16).to_le(),
+        start_hi: ((start >> 32) as u16).to_le(),
+        start_lo: (start as u32).to_le(),
+    };
+    let mut root = [0u8; 60];
+    root[..12].copy_from_slice(&struct_bytes(&header));
+    root[12..24].copy_from_slice(&struct_bytes(&extent));
+    for (word, bytes) in inode.blocks.iter_mut().zip(root.chunks_exact(4)) {
+        *word = u32::from_ne_bytes(bytes.try_into().unwrap());
+    }
+    inode
+}
+
+fn write_inode(data: &mut [u8], ino: u32, inode: &ext4_inode) {
+    let offset = INODE_TABLE as usize * BLOCK_SIZE + (ino as usize - 1) * INODE_SIZE;
+    write_struct(data, offset, inode);
+}
+
+fn write_dir_entry(block: &mut [u8], offset: usize, ino: u32, rec_len: u16, name: &[u8]) {
+    block[offset..offset + 4].copy_from_slice(&ino.to_le_bytes());
+    block[offset + 4..offset + 6].copy_from_slice(&rec_len.to_le_bytes());
+    block[offset + 6] = name.len() as u8;
+    block[offset + 7] = EXT4_DE_DIR;
+    block[offset + 8..offset + 8 + name.len()].copy_from_slice(name);
+}
+
+fn set_bits(bitmap: &mut [u8], bits: core::ops::Range<usize>) {
+    for bit in bits {
+        bitmap[bit / 8] |= 1 << (bit % 8);
+    }
+}
+
+fn struct_bytes<T: Copy>(value: &T) -> Vec<u8> {
+    // SAFETY: 磁盘结构都是 repr(C) 的纯数据
+    unsafe { core::slice::from_raw_parts(value as *const T as *const u8, core::mem::size_of::<T>()) }.to_vec()
+}
+
+fn write_struct<T: Copy>(data: &mut [u8], offset: usize, value: &T) {
+    let bytes = struct_bytes(value);
+    data[offset..offset + bytes.len()].copy_from_slice(&bytes);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_image_mounts() {
+        let mut fs = mount(image());
+        crate::fs::ops::write(&mut fs, "/hello", b"hi").unwrap();
+        let dev = fs.unmount().unwrap().device().clone();
+
+        let mut fs = mount(dev);
+        assert_eq!(crate::fs::ops::read(&mut fs, "/hello").unwrap(), b"hi");
+    }
+}
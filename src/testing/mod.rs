@@ -0,0 +1,57 @@
+//! 崩溃一致性测试工具（`testing` 特性）
+//!
+//! - [`FaultyBlockDevice`]：在第 N 次写入之后让写入失败、丢弃或撕裂，
+//!   并可模拟设备的易失性写缓存
+//! - [`crash_and_remount`]：丢弃文件系统和块缓存的内存状态（不写回），
+//!   模拟掉电后重新挂载
+//!
+//! 典型的掉电测试：在每个可能的写入位置注入 [`Fault::DropWrites`]，执行同一组操作，
+//! 崩溃并重新挂载，然后检查已经 `fsync` 的数据仍然完整。
+//!
+//! ```rust,ignore
+//! for after in 0.. {
+//!     let dev = FaultyBlockDevice::new_with_write_cache(fresh_image());
+//!     let mut fs = Ext4FileSystem::mount(BlockDev::new_with_cache(dev, 256)?)?;
+//!     fs.block_device_mut().device_mut().set_fault(Some(Fault::DropWrites { after }));
+//!     run_workload(&mut fs)?;
+//!     let tripped = fs.block_device().device().is_tripped();
+//!
+//!     let mut fs = crash_and_remount(fs, FsConfig::default())?;
+//!     check_invariants(&mut fs)?;
+//!     if !tripped {
+//!         break; // 工作负载的写入次数少于 after，所有位置都已覆盖
+//!     }
+//! }
+//! ```
+
+mod faulty;
+#[cfg(test)]
+pub(crate) mod image;
+
+pub use faulty::{Fault, FaultyBlockDevice};
+
+use crate::{
+    block::BlockDevice,
+    error::Result,
+    fs::{Ext4FileSystem, FsConfig},
+};
+
+/// 模拟掉电并重新挂载
+///
+/// 不卸载文件系统：块缓存中的脏块、延迟分配和页缓存中的数据都被丢弃，
+/// 设备写缓存中尚未刷新的写入也被丢弃（见 [`FaultyBlockDevice::crash`]），
+/// 然后用 `config` 在同一设备上重新挂载。设备上的内容就是掉电那一刻已经落盘的内容。
+///
+/// # 错误
+///
+/// 同 [`Ext4FileSystem::mount_with_config`]，例如崩溃破坏了 superblock
+pub fn crash_and_remount<B: BlockDevice>(
+    fs: Ext4FileSystem<FaultyBlockDevice<B>>,
+    config: FsConfig,
+) -> Result<Ext4FileSystem<FaultyBlockDevice<B>>> {
+    // 不经过 unmount，直接取出块设备
+    let mut bdev = fs.bdev;
+    bdev.discard_cache();
+    bdev.device_mut().crash();
+    Ext4FileSystem::mount_with_config(bdev, config)
+}
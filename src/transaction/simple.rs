@@ -213,6 +213,8 @@ impl<'a, D: BlockDevice> SimpleTransaction<'a, D> {
         if self.state == TransactionState::Active {
             self.state = TransactionState::Aborted;
             self.dirty_blocks.clear();
+            // 回滚后缓存的块组描述符和位图校验状态不再可信
+            self.bdev.invalidate_meta_cache();
         }
     }
 
@@ -21,6 +21,7 @@ use crate::consts::*;
 /// 对应 ext4 磁盘格式中的 superblock (ext4_super_block)
 #[repr(C)]
 #[derive(Debug, Clone, Copy)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct ext4_sblock {
     pub inodes_count: u32,           // 0: 总 inode 数
     pub blocks_count_lo: u32,        // 4: 总块数（低32位）
@@ -58,6 +59,7 @@ pub struct ext4_sblock {
 
     pub uuid: [u8; 16],              // 104: 128位UUID
     pub volume_name: [u8; 16],       // 120: 卷名称
+    #[cfg_attr(feature = "serde", serde(with = "crate::disk::serde_array"))]
     pub last_mounted: [u8; 64],      // 136: 最后挂载路径
     pub algorithm_usage_bitmap: u32, // 200: 压缩算法位图
 
@@ -108,6 +110,7 @@ pub struct ext4_sblock {
     pub last_error_line: u32,        // 468: 最后错误行号
     pub last_error_block: u64,       // 472: 最后错误块号
     pub last_error_func: [u8; 32],   // 480: 最后错误函数
+    #[cfg_attr(feature = "serde", serde(with = "crate::disk::serde_array"))]
     pub mount_opts: [u8; 64],        // 512: 挂载选项
     pub usr_quota_inum: u32,         // 576: 用户配额inode
     pub grp_quota_inum: u32,         // 580: 组配额inode
@@ -118,7 +121,28 @@ pub struct ext4_sblock {
     pub lpf_ino: u32,                // 616: lost+found inode
     pub prj_quota_inum: u32,         // 620: 项目配额inode
     pub checksum_seed: u32,          // 624: 校验和种子
-    pub reserved: [u32; 98],         // 628: 保留字段
+    /// 最后写入时间（高8位）
+    pub wtime_hi: u8,                // 628
+    /// 最后挂载时间（高8位）
+    pub mtime_hi: u8,                // 629
+    /// 创建时间（高8位）
+    pub mkfs_time_hi: u8,            // 630
+    /// 最后检查时间（高8位）
+    pub lastcheck_hi: u8,            // 631
+    /// 第一次错误时间（高8位）
+    pub first_error_time_hi: u8,     // 632
+    /// 最后错误时间（高8位）
+    pub last_error_time_hi: u8,      // 633
+    /// 第一次错误的错误码
+    pub first_error_errcode: u8,     // 634
+    /// 最后错误的错误码
+    pub last_error_errcode: u8,      // 635
+    /// 文件名编码（casefold）
+    pub encoding: u16,               // 636
+    /// 文件名编码标志
+    pub encoding_flags: u16,         // 638
+    #[cfg_attr(feature = "serde", serde(with = "crate::disk::serde_array"))]
+    pub reserved: [u32; 95],         // 640: 保留字段
     pub checksum: u32,               // 1020: superblock校验和
 }
 
@@ -144,23 +168,43 @@ impl ext4_sblock {
         }
     }
 
+    /// 是否启用 64 位特性，未启用时 `*_hi` 字段没有意义
+    fn is_64bit(&self) -> bool {
+        u32::from_le(self.feature_incompat) & EXT4_FEATURE_INCOMPAT_64BIT != 0
+    }
+
+    /// 合并高低 32 位，只有启用 64 位特性时才使用高 32 位
+    fn blocks_lo_hi(&self, lo: u32, hi: u32) -> u64 {
+        let mut v = u32::from_le(lo) as u64;
+        if self.is_64bit() {
+            v |= (u32::from_le(hi) as u64) << 32;
+        }
+        v
+    }
+
     /// 获取总块数（合并高低32位）
     pub fn blocks_count(&self) -> u64 {
-        (u32::from_le(self.blocks_count_lo) as u64)
-            | ((u32::from_le(self.blocks_count_hi) as u64) << 32)
+        self.blocks_lo_hi(self.blocks_count_lo, self.blocks_count_hi)
     }
 
     /// 获取空闲块数（合并高低32位）
     pub fn free_blocks_count(&self) -> u64 {
-        (u32::from_le(self.free_blocks_count_lo) as u64)
-            | ((u32::from_le(self.free_blocks_count_hi) as u64) << 32)
+        self.blocks_lo_hi(self.free_blocks_count_lo, self.free_blocks_count_hi)
+    }
+
+    /// 获取保留块数（合并高低32位）
+    pub fn r_blocks_count(&self) -> u64 {
+        self.blocks_lo_hi(self.r_blocks_count_lo, self.r_blocks_count_hi)
     }
 
     /// 计算块组数量
+    ///
+    /// 块组从 `first_data_block` 开始划分，与内核 `ext4_fill_super()` 相同
     pub fn block_group_count(&self) -> u32 {
         let blocks_count = self.blocks_count();
+        let first_data_block = u32::from_le(self.first_data_block) as u64;
         let blocks_per_group = u32::from_le(self.blocks_per_group) as u64;
-        ((blocks_count + blocks_per_group - 1) / blocks_per_group) as u32
+        blocks_count.saturating_sub(first_data_block).div_ceil(blocks_per_group) as u32
     }
 
     /// 验证魔数
@@ -174,6 +218,7 @@ impl ext4_sblock {
 /// 对应 ext4 磁盘格式中的 inode (ext4_inode)
 #[repr(C)]
 #[derive(Debug, Clone, Copy)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct ext4_inode {
     pub mode: u16,                   // 0: 文件模式
     pub uid: u16,                    // 2: 所有者 uid（低16位）
@@ -251,6 +296,7 @@ impl ext4_inode {
 /// 对应 ext4 磁盘格式中的目录项 (ext4_dir_entry_2)
 #[repr(C, packed)]
 #[derive(Debug, Clone, Copy)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct ext4_dir_entry {
     pub inode: u32,                  // inode 编号
     pub rec_len: u16,                // 记录长度
@@ -271,6 +317,7 @@ pub type ext4_dir_en = ext4_dir_entry;
 /// 对应 ext4 磁盘格式中的 ext4_dir_idx_climit
 #[repr(C, packed)]
 #[derive(Debug, Clone, Copy)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct ext4_dir_idx_climit {
     pub limit: u16,                  // 最大条目数
     pub count: u16,                  // 当前条目数
@@ -309,6 +356,7 @@ impl ext4_dir_idx_climit {
 /// 对应 ext4 磁盘格式中的 ext4_fake_dir_entry
 #[repr(C, packed)]
 #[derive(Debug, Clone, Copy)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct ext4_dir_idx_dot_en {
     pub inode: u32,                  // inode 编号
     pub entry_len: u16,              // 记录长度
@@ -340,6 +388,7 @@ impl ext4_dir_idx_dot_en {
 /// 对应 ext4 磁盘格式中的 ext4_dir_idx_root_info
 #[repr(C, packed)]
 #[derive(Debug, Clone, Copy)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct ext4_dir_idx_rinfo {
     pub reserved_zero: u32,          // 保留字段，必须为 0
     pub hash_version: u8,            // 哈希版本
@@ -391,6 +440,7 @@ impl ext4_dir_idx_rinfo {
 /// 对应 ext4 磁盘格式中的 ext4_dir_idx_entry
 #[repr(C, packed)]
 #[derive(Debug, Clone, Copy)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct ext4_dir_idx_entry {
     pub hash: u32,                   // 哈希值
     pub block: u32,                  // 块号
@@ -430,6 +480,7 @@ impl ext4_dir_idx_entry {
 /// 包含 "." 和 ".." 目录项以及根信息和索引条目
 #[repr(C, packed)]
 #[derive(Debug, Clone, Copy)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct ext4_dir_idx_root {
     pub dots: [ext4_dir_idx_dot_en; 2], // "." 和 ".." 目录项
     pub info: ext4_dir_idx_rinfo,    // 根信息
@@ -447,6 +498,7 @@ impl Default for ext4_dir_idx_root {
 /// 对应 ext4 磁盘格式中的 ext4_dir_idx_node
 #[repr(C, packed)]
 #[derive(Debug, Clone, Copy)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct ext4_dir_idx_node {
     pub fake: ext4_fake_dir_entry,   // 假目录项
     pub entries: [ext4_dir_idx_entry; 0], // 索引条目数组（变长）
@@ -457,6 +509,7 @@ pub struct ext4_dir_idx_node {
 /// 对应 ext4 磁盘格式中的 ext4_fake_dir_entry
 #[repr(C, packed)]
 #[derive(Debug, Clone, Copy)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct ext4_fake_dir_entry {
     pub inode: u32,                  // inode 编号（通常为 0）
     pub entry_len: u16,              // 记录长度
@@ -481,6 +534,7 @@ impl Default for ext4_dir_idx_node {
 /// 对应 ext4 磁盘格式中的 ext4_dir_idx_tail
 #[repr(C, packed)]
 #[derive(Debug, Clone, Copy)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct ext4_dir_idx_tail {
     pub reserved: u32,               // 保留字段
     pub checksum: u32,               // 校验和
@@ -509,6 +563,7 @@ impl ext4_dir_idx_tail {
 /// 对应 ext4 磁盘格式中的 ext4_dir_entry_tail
 #[repr(C, packed)]
 #[derive(Debug, Clone, Copy)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct ext4_dir_entry_tail {
     pub reserved_zero1: u32,         // 保留字段 1
     pub rec_len: u16,                // 记录长度（通常为 12）
@@ -550,6 +605,7 @@ impl ext4_dir_entry_tail {
 /// 对应 ext4 磁盘格式中的块组描述符 (ext4_group_desc)
 #[repr(C)]
 #[derive(Debug, Clone, Copy)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct ext4_group_desc {
     pub block_bitmap_lo: u32,        // 块位图块号（低32位）
     pub inode_bitmap_lo: u32,        // inode位图块号（低32位）
@@ -614,6 +670,7 @@ impl ext4_group_desc {
 /// 位于每个 extent 树节点的开头
 #[repr(C)]
 #[derive(Debug, Clone, Copy)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 #[allow(non_camel_case_types)]
 pub struct ext4_extent_header {
     pub magic: u16,      // 魔数 0xF30A
@@ -662,6 +719,7 @@ impl ext4_extent_header {
 /// 描述一段连续的物理块
 #[repr(C)]
 #[derive(Debug, Clone, Copy)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 #[allow(non_camel_case_types)]
 pub struct ext4_extent {
     pub block: u32,    // 逻辑块号（文件内偏移）
@@ -713,6 +771,7 @@ impl ext4_extent {
 /// 指向下一层的 extent 树节点
 #[repr(C)]
 #[derive(Debug, Clone, Copy)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 #[allow(non_camel_case_types)]
 pub struct ext4_extent_idx {
     pub block: u32,   // 逻辑块号（覆盖范围的起始）
@@ -760,6 +819,7 @@ impl ext4_extent_idx {
 /// 对应 ext4 磁盘格式中的 ext4_extent_tail
 #[repr(C)]
 #[derive(Debug, Clone, Copy)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 #[allow(non_camel_case_types)]
 pub struct ext4_extent_tail {
     /// CRC32C 校验和：crc32c(uuid + inum + extent_block)
@@ -782,6 +842,7 @@ impl Default for ext4_extent_tail {
 /// 位于独立 xattr 块的开头
 #[repr(C)]
 #[derive(Debug, Clone, Copy)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 #[allow(non_camel_case_types)]
 pub struct ext4_xattr_header {
     pub h_magic: u32,       // 魔数：EXT4_XATTR_MAGIC (0xEA020000)
@@ -804,6 +865,7 @@ impl Default for ext4_xattr_header {
 /// 位于 inode 的额外空间开头
 #[repr(C)]
 #[derive(Debug, Clone, Copy)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 #[allow(non_camel_case_types)]
 pub struct ext4_xattr_ibody_header {
     pub h_magic: u32,       // 魔数：EXT4_XATTR_MAGIC
@@ -821,6 +883,7 @@ impl Default for ext4_xattr_ibody_header {
 /// 描述一个扩展属性的元数据
 #[repr(C)]
 #[derive(Debug, Clone, Copy)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 #[allow(non_camel_case_types)]
 pub struct ext4_xattr_entry {
     pub e_name_len: u8,     // 名称长度
@@ -863,3 +926,155 @@ impl ext4_xattr_entry {
         u32::from_le(self.e_hash)
     }
 }
+
+//=============================================================================
+// 块号与偏移类型
+//=============================================================================
+//
+// 逻辑块号、物理块号和字节偏移在磁盘格式中都是普通整数，混用时编译器
+// 无法发现错误（例如把逻辑块号当作物理块号读写）。API 中使用下面的
+// 新类型区分它们：
+//
+// - `Lblk` 与 `u32`、`Pblk` 与 `u64`、`ByteOff` 与 `u64` 之间通过 `From` 互转
+// - `Lblk` 与 `Pblk` 之间没有转换，只能经过 extent 树或间接块映射
+// - 块号与字节偏移之间的换算需要显式给出块大小
+
+/// 文件内的逻辑块号
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash, Default)]
+#[repr(transparent)]
+pub struct Lblk(pub u32);
+
+/// 文件系统物理块号（以文件系统块为单位，从设备起始处计数）
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash, Default)]
+#[repr(transparent)]
+pub struct Pblk(pub u64);
+
+/// 字节偏移（文件内偏移或设备上的偏移）
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash, Default)]
+#[repr(transparent)]
+pub struct ByteOff(pub u64);
+
+impl Lblk {
+    /// 该逻辑块在文件中的起始字节偏移
+    pub fn to_byte_off(self, block_size: u32) -> ByteOff {
+        ByteOff(self.0 as u64 * block_size as u64)
+    }
+
+    /// 向后偏移 `n` 个块，溢出时返回 `None`
+    pub fn checked_add(self, n: u32) -> Option<Lblk> {
+        self.0.checked_add(n).map(Lblk)
+    }
+}
+
+impl Pblk {
+    /// 该物理块在设备上的起始字节偏移
+    pub fn to_byte_off(self, block_size: u32) -> ByteOff {
+        ByteOff(self.0 * block_size as u64)
+    }
+
+    /// 向后偏移 `n` 个块，溢出时返回 `None`
+    pub fn checked_add(self, n: u64) -> Option<Pblk> {
+        self.0.checked_add(n).map(Pblk)
+    }
+}
+
+impl ByteOff {
+    /// 偏移所在的逻辑块号
+    ///
+    /// 超出 32 位逻辑块范围时截断，调用方应先检查文件大小上限
+    pub fn lblk(self, block_size: u32) -> Lblk {
+        Lblk((self.0 / block_size as u64) as u32)
+    }
+
+    /// 偏移所在的物理块号（偏移为设备上的字节偏移时使用）
+    pub fn pblk(self, block_size: u32) -> Pblk {
+        Pblk(self.0 / block_size as u64)
+    }
+
+    /// 偏移在所在块内的位置
+    pub fn in_block(self, block_size: u32) -> usize {
+        (self.0 % block_size as u64) as usize
+    }
+}
+
+impl From<u32> for Lblk {
+    fn from(block: u32) -> Self {
+        Lblk(block)
+    }
+}
+
+impl From<Lblk> for u32 {
+    fn from(block: Lblk) -> Self {
+        block.0
+    }
+}
+
+impl From<u64> for Pblk {
+    fn from(block: u64) -> Self {
+        Pblk(block)
+    }
+}
+
+impl From<Pblk> for u64 {
+    fn from(block: Pblk) -> Self {
+        block.0
+    }
+}
+
+impl From<u64> for ByteOff {
+    fn from(offset: u64) -> Self {
+        ByteOff(offset)
+    }
+}
+
+impl From<ByteOff> for u64 {
+    fn from(offset: ByteOff) -> Self {
+        offset.0
+    }
+}
+
+impl core::ops::Add<u32> for Lblk {
+    type Output = Lblk;
+
+    fn add(self, n: u32) -> Lblk {
+        Lblk(self.0 + n)
+    }
+}
+
+impl core::ops::Add<u64> for Pblk {
+    type Output = Pblk;
+
+    fn add(self, n: u64) -> Pblk {
+        Pblk(self.0 + n)
+    }
+}
+
+impl core::ops::AddAssign<u32> for Lblk {
+    fn add_assign(&mut self, n: u32) {
+        self.0 += n;
+    }
+}
+
+impl core::ops::AddAssign<u64> for Pblk {
+    fn add_assign(&mut self, n: u64) {
+        self.0 += n;
+    }
+}
+
+impl core::fmt::Display for Lblk {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        write!(f, "lblk {}", self.0)
+    }
+}
+
+impl core::fmt::Display for Pblk {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        write!(f, "pblk {}", self.0)
+    }
+}
+
+impl core::fmt::LowerHex for Pblk {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        core::fmt::LowerHex::fmt(&self.0, f)
+    }
+}
@@ -40,12 +40,22 @@
 use crate::{
     Result, Error, ErrorKind,
     block::{Block, BlockDevice},
+    consts::EXT4_XATTR_SIZE_MAX,
     fs::InodeRef,
     superblock::Superblock,
     balloc,
+    types::{ext4_xattr_header, Pblk},
+};
+use alloc::{string::String, vec::Vec};
+use core::mem::size_of;
+
+use super::{
+    block, ea_inode, hash, prefix, write,
+    ea_inode::EaRefs,
+    entry::{XattrEntry, XattrNamespace},
+    ibody::{read_ibody_records, write_ibody_records},
+    write::XattrRecord,
 };
-
-use super::prefix;
 
 /// 列出所有扩展属性
 ///
@@ -161,6 +171,77 @@ pub fn list<D: BlockDevice>(
     Ok(written)
 }
 
+/// 遍历所有扩展属性
+///
+/// 与 [`list`] 的顺序相同：先是 inode 内部的属性，然后是 xattr 块中的属性。
+/// 没有用户可见前缀的命名空间（如加密上下文）被跳过。
+/// 所有条目一次读出，迭代期间不再访问 inode
+///
+/// # 示例
+///
+/// ```ignore
+/// for entry in xattr::iter(&mut inode_ref)? {
+///     println!("{} ({} bytes)", entry.name, entry.value_size);
+/// }
+/// ```
+pub fn iter<D: BlockDevice>(inode_ref: &mut InodeRef<D>) -> Result<impl Iterator<Item = XattrEntry>> {
+    let records = read_all_records(inode_ref)?;
+    Ok(records.into_iter().filter_map(|record| {
+        let namespace = XattrNamespace::from_name_index(record.name_index)?;
+        let (prefix, _) = prefix::get_xattr_name_prefix(record.name_index)?;
+        let mut name = String::from(prefix);
+        name.push_str(&String::from_utf8_lossy(&record.name));
+        Some(XattrEntry {
+            name,
+            namespace,
+            value_size: record_value_size(&record) as u32,
+        })
+    }))
+}
+
+/// 遍历指定命名空间中的扩展属性
+///
+/// 与 [`iter`] 相同，只保留 `namespace` 中的条目
+pub fn iter_namespace<D: BlockDevice>(
+    inode_ref: &mut InodeRef<D>,
+    namespace: XattrNamespace,
+) -> Result<impl Iterator<Item = XattrEntry>> {
+    Ok(iter(inode_ref)?.filter(move |entry| entry.namespace == namespace))
+}
+
+/// 获取扩展属性值的长度
+///
+/// 只读取条目，不复制值；值存放在 EA inode 中时也不读取该 inode。
+/// 可以先用它确定 [`get`] 需要的缓冲区大小
+///
+/// # 错误
+///
+/// - `ErrorKind::InvalidInput` - 属性名无效
+/// - `ErrorKind::NotFound` - 属性不存在
+pub fn get_size<D: BlockDevice>(inode_ref: &mut InodeRef<D>, name: &str) -> Result<usize> {
+    use super::prefix::extract_xattr_name;
+    let (name_index, name_str, _name_len) = extract_xattr_name(name)
+        .ok_or_else(|| Error::new(ErrorKind::InvalidInput, "invalid xattr name"))?;
+
+    read_all_records(inode_ref)?
+        .iter()
+        .find(|record| record.matches(name_index, name_str.as_bytes()))
+        .map(record_value_size)
+        .ok_or_else(|| Error::new(ErrorKind::NotFound, "xattr not found"))
+}
+
+/// 读取 inode 内部和 xattr 块中的所有属性
+fn read_all_records<D: BlockDevice>(inode_ref: &mut InodeRef<D>) -> Result<Vec<XattrRecord>> {
+    let mut records = read_ibody_records(inode_ref)?.unwrap_or_default();
+    records.extend(read_block_records(inode_ref)?);
+    Ok(records)
+}
+
+/// 属性值的长度（值在 EA inode 中时取条目记录的长度）
+fn record_value_size(record: &XattrRecord) -> usize {
+    record.ea.map_or(record.value.len(), |ea| ea.size as usize)
+}
+
 /// 获取扩展属性值
 ///
 /// 对应 lwext4 的 `ext4_xattr_get()`
@@ -202,11 +283,22 @@ pub fn get<D: BlockDevice>(
     let (name_index, name_str, _name_len) = extract_xattr_name(name)
         .ok_or_else(|| Error::new(ErrorKind::InvalidInput, "invalid xattr name"))?;
 
-    let name_bytes = name_str.as_bytes();
+    get_indexed(inode_ref, name_index, name_str.as_bytes(), buffer)
+}
 
+/// 按命名空间索引和不含前缀的名称获取扩展属性值
+///
+/// 用于没有用户可见前缀的命名空间（如 fscrypt 的加密上下文），
+/// 语义与 [`get`] 相同
+pub(crate) fn get_indexed<D: BlockDevice>(
+    inode_ref: &mut InodeRef<D>,
+    name_index: u8,
+    name_bytes: &[u8],
+    buffer: &mut [u8],
+) -> Result<usize> {
     // 2. 先在 inode 内部查找
     use super::ibody::find_ibody_entry;
-    if let Some((_entry_offset, value_offset, value_size)) =
+    if let Some((entry_offset, value_offset, value_size)) =
         find_ibody_entry(inode_ref, name_index, name_bytes)?
     {
         // 在 inode 内部找到了
@@ -215,6 +307,12 @@ pub fn get<D: BlockDevice>(
             return Err(Error::new(ErrorKind::InvalidInput, "buffer too small"));
         }
 
+        // 值存放在 EA inode 中
+        let value_inum = inode_ref.with_inode_raw_data(|inode_data| entry_value_inum(inode_data, entry_offset))?;
+        if value_inum != 0 {
+            return ea_inode::read_value(inode_ref, value_inum, value_size, buffer);
+        }
+
         // 从 inode 数据中读取 value
         return inode_ref.with_inode_raw_data(|inode_data| {
             let value_end = value_offset + value_len;
@@ -235,13 +333,15 @@ pub fn get<D: BlockDevice>(
 
     // 使用 Block 访问 xattr block
     let mut block = Block::get(inode_ref.bdev_mut(), xattr_block_addr)?;
-    block.with_data(|block_data| {
+    let (value_len, value_inum) = block.with_data(|block_data| {
         // 在 block 中查找（不依赖 find_block_entry 避免借用问题）
         use super::search::XattrSearch;
         let first_entry_offset = core::mem::size_of::<crate::types::ext4_xattr_header>();
         let mut search = XattrSearch::new(block_data, first_entry_offset);
+        // 块哈希有效说明块由本库或内核按规范顺序写入，可以有序查找
+        search.sorted = block_data[HEADER_HASH_OFFSET..HEADER_HASH_OFFSET + 4] != [0; 4];
 
-        if let Some((_entry_offset, value_offset, value_size)) =
+        if let Some((entry_offset, value_offset, value_size)) =
             search.find_entry(name_index, name_bytes)
         {
             let value_len = value_size as usize;
@@ -249,17 +349,38 @@ pub fn get<D: BlockDevice>(
                 return Err(Error::new(ErrorKind::InvalidInput, "buffer too small"));
             }
 
+            let value_inum = entry_value_inum(block_data, entry_offset);
+            if value_inum != 0 {
+                return Ok((value_size, value_inum));
+            }
+
             let value_end = value_offset + value_len;
             if value_end > block_data.len() {
                 return Err(Error::new(ErrorKind::Io, "value out of bounds"));
             }
 
             buffer[..value_len].copy_from_slice(&block_data[value_offset..value_end]);
-            Ok(value_len)
+            Ok((value_size, 0))
         } else {
             Err(Error::new(ErrorKind::NotFound, "xattr not found"))
         }
-    })?
+    })??;
+    drop(block);
+
+    if value_inum != 0 {
+        return ea_inode::read_value(inode_ref, value_inum, value_len, buffer);
+    }
+    Ok(value_len as usize)
+}
+
+/// 读取 entry 的 `e_value_inum`（值所在的 EA inode，0 表示值在本地）
+fn entry_value_inum(data: &[u8], entry_offset: usize) -> u32 {
+    u32::from_le_bytes([
+        data[entry_offset + 4],
+        data[entry_offset + 5],
+        data[entry_offset + 6],
+        data[entry_offset + 7],
+    ])
 }
 
 /// 设置扩展属性
@@ -278,17 +399,20 @@ pub fn get<D: BlockDevice>(
 ///
 /// # 实现说明
 ///
-/// 这是最复杂的函数，完全对应 C 实现的逻辑：
+/// 每个属性只存放在一个位置（inode 内部或 xattr 块）：
 ///
-/// 1. 解析属性名称
-/// 2. 尝试在 inode 内部设置
-///    - 如果成功，标记 inode 为脏并返回
-/// 3. 如果 inode 内部空间不足，尝试在 xattr 块中设置
+/// 1. 解析属性名称，读取 inode 内部和 xattr 块中的全部属性
+/// 2. 从两处都移除同名属性
+/// 3. 优先放入 inode 内部；放不下时放入 xattr 块
 ///    - 如果没有 xattr 块，分配新块
 ///    - 如果块引用计数 > 1，执行 COW（分离共享块）
-///    - 在块中设置属性
-///    - 标记 block 为脏
-/// 4. 如果在块中设置成功，尝试从 inode 内部删除该属性（迁移）
+/// 4. 写回发生变化的位置，块变空时释放
+///
+/// 两处都按规范顺序整体重写，同一组属性总是得到相同的磁盘布局。
+/// 先写可能失败的 xattr 块，失败时 inode 内部保持不变。
+///
+/// 启用 EA_INODE 特性时，空 xattr 块也放不下的值，或两处都放不下的值，
+/// 存入独立的 EA inode，entry 只保存引用。
 ///
 /// 注意：修改会自动标记为脏
 pub fn set<D: BlockDevice>(
@@ -296,31 +420,103 @@ pub fn set<D: BlockDevice>(
     name: &str,
     value: &[u8],
 ) -> Result<()> {
+    inode_ref.sb().check_writable()?;
     // 1. 解析属性名称
     use super::prefix::extract_xattr_name;
     let (name_index, name_str, _name_len) = extract_xattr_name(name)
         .ok_or_else(|| Error::new(ErrorKind::InvalidInput, "invalid xattr name"))?;
 
     let name_bytes = name_str.as_bytes();
+    if value.len() > EXT4_XATTR_SIZE_MAX {
+        return Err(Error::new(ErrorKind::InvalidInput, "xattr value too large"));
+    }
 
-    // 2. 尝试在 inode 内部设置
-    use super::ibody::{set_ibody_entry, initialize_ibody_xattr};
+    // 2. 读取两处的属性并移除同名项
+    let mut stored = Stored::read(inode_ref)?;
+    let in_ibody = stored
+        .ibody
+        .as_mut()
+        .is_some_and(|records| remove_record(records, name_index, name_bytes));
+    let in_block = remove_record(&mut stored.block, name_index, name_bytes);
+
+    let mut record = XattrRecord {
+        name_index,
+        name: name_bytes.to_vec(),
+        value: value.to_vec(),
+        ea: None,
+    };
 
-    // 先确保 ibody xattr 已初始化
-    initialize_ibody_xattr(inode_ref)?;
+    // 空 xattr 块也放不下的值直接存入 EA inode
+    let block_size = inode_ref.superblock().block_size() as usize;
+    let ea_enabled = ea_inode::enabled(inode_ref.superblock());
+    if ea_enabled
+        && size_of::<ext4_xattr_header>() + write::records_space(core::slice::from_ref(&record)) > block_size
+    {
+        ea_inode::store_value(inode_ref, &mut record)?;
+    }
 
-    let set_in_ibody = set_ibody_entry(inode_ref, name_index, name_bytes, Some(value))?;
-    if set_in_ibody {
-        return Ok(());
+    let mut refs = EaRefs::default();
+    let mut placed = place_record(inode_ref, &mut stored, &record, in_ibody, in_block, &mut refs);
+
+    // 两处都放不下时改为存入 EA inode 再试一次
+    if ea_enabled && record.ea.is_none() && !record.value.is_empty() && matches!(placed, Ok(false)) {
+        ea_inode::store_value(inode_ref, &mut record)?;
+        placed = place_record(inode_ref, &mut stored, &record, in_ibody, in_block, &mut refs);
+    }
+
+    // 已经写回的位置的引用变化总要生效
+    refs.apply(inode_ref)?;
+    let result = match placed {
+        Ok(true) => Ok(()),
+        Ok(false) => Err(Error::new(ErrorKind::NoSpace, "no space for xattr entry")),
+        Err(e) => Err(e),
+    };
+    if let (Err(_), Some(ea)) = (&result, record.ea) {
+        ea_inode::release_if_unused(inode_ref, ea.inum)?;
+    }
+    result
+}
+
+/// 放入新记录并写回发生变化的位置
+///
+/// 优先放入 inode 内部，空间不足时使用 xattr block。
+/// 两处都放不下时返回 `false`，此时没有写入任何数据，`stored` 保持不变
+fn place_record<D: BlockDevice>(
+    inode_ref: &mut InodeRef<D>,
+    stored: &mut Stored,
+    record: &XattrRecord,
+    in_ibody: bool,
+    in_block: bool,
+    refs: &mut EaRefs,
+) -> Result<bool> {
+    // 3. 优先放入 inode 内部
+    if let Some(records) = stored.ibody.as_mut() {
+        records.push(record.clone());
+        if write_ibody(inode_ref, records, &stored.old_ibody, refs)? {
+            if in_block {
+                write_block_records(inode_ref, &mut stored.block, &stored.old_block, refs)?;
+            }
+            return Ok(true);
+        }
+        records.pop();
     }
 
-    // 3. inode 内部空间不足，使用 xattr block
-    set_in_block(inode_ref, name_index, name_bytes, value)?;
+    // 4. inode 内部空间不足，使用 xattr block
+    let block_size = inode_ref.superblock().block_size() as usize;
+    stored.block.push(record.clone());
+    if size_of::<ext4_xattr_header>() + write::records_space(&stored.block) > block_size {
+        stored.block.pop();
+        return Ok(false);
+    }
+    write_block_records(inode_ref, &mut stored.block, &stored.old_block, refs)?;
 
-    // 4. 如果在 block 中设置成功，尝试从 inode 内部删除该属性（优化空间）
-    let _ = set_ibody_entry(inode_ref, name_index, name_bytes, None);
+    if in_ibody {
+        if let Some(records) = stored.ibody.as_mut() {
+            write_ibody(inode_ref, records, &stored.old_ibody, refs)?;
+        }
+    }
 
-    Ok(())
+    Ok(true)
 }
 
 /// 删除扩展属性
@@ -339,8 +535,8 @@ pub fn set<D: BlockDevice>(
 /// # 实现说明
 ///
 /// 1. 解析属性名称
-/// 2. 尝试在 inode 内部删除
-/// 3. 如果未找到，尝试在 xattr 块中删除
+/// 2. 从 inode 内部和 xattr 块中移除该属性
+/// 3. 两处都不存在时返回 NotFound
 /// 4. 如果块为空，释放块
 ///
 /// 注意：修改会自动标记为脏
@@ -348,6 +544,7 @@ pub fn remove<D: BlockDevice>(
     inode_ref: &mut InodeRef<D>,
     name: &str,
 ) -> Result<()> {
+    inode_ref.sb().check_writable()?;
     // 1. 解析属性名称
     use super::prefix::extract_xattr_name;
     let (name_index, name_str, _name_len) = extract_xattr_name(name)
@@ -355,292 +552,244 @@ pub fn remove<D: BlockDevice>(
 
     let name_bytes = name_str.as_bytes();
 
-    // 2. 尝试在 inode 内部删除
-    use super::ibody::set_ibody_entry;
-    let removed_from_ibody = set_ibody_entry(inode_ref, name_index, name_bytes, None)?;
-    if removed_from_ibody {
-        return Ok(());
-    }
+    // 2. 从两处移除
+    let mut stored = Stored::read(inode_ref)?;
+    let in_ibody = stored
+        .ibody
+        .as_mut()
+        .is_some_and(|records| remove_record(records, name_index, name_bytes));
+    let in_block = remove_record(&mut stored.block, name_index, name_bytes);
 
-    // 3. 在 xattr block 中删除
-    let xattr_block_addr = inode_ref.get_xattr_block_addr()?;
-    if xattr_block_addr == 0 {
-        // 没有 xattr block，属性不存在
+    if !in_ibody && !in_block {
         return Err(Error::new(ErrorKind::NotFound, "xattr not found"));
     }
 
-    remove_from_block(inode_ref, name_index, name_bytes)?;
-
-    Ok(())
+    // 3. 写回发生变化的位置，最后释放不再被引用的 EA inode
+    let mut refs = EaRefs::default();
+    let mut result = Ok(());
+    if in_block {
+        result = write_block_records(inode_ref, &mut stored.block, &stored.old_block, &mut refs);
+    }
+    if in_ibody && result.is_ok() {
+        if let Some(records) = stored.ibody.as_mut() {
+            result = write_ibody(inode_ref, records, &stored.old_ibody, &mut refs).map(|_| ());
+        }
+    }
+    refs.apply(inode_ref)?;
+    result
 }
 
-/// 在 xattr block 中设置属性（内部辅助函数）
-///
-/// 对应 lwext4 的 `ext4_xattr_block_set()`
+/// 释放 inode 的所有扩展属性（删除 inode 时调用）
 ///
-/// 实现逻辑：
-/// 1. 如果没有 xattr block，分配新块
-/// 2. 如果有 block 且 h_refcount > 1，执行 COW
-/// 3. 在 block 中设置 entry
-/// 4. 更新哈希和校验和
-fn set_in_block<D: BlockDevice>(
-    inode_ref: &mut InodeRef<D>,
-    name_index: u8,
-    name: &[u8],
-    value: &[u8],
-) -> Result<()> {
-    use super::{block, write};
-    use crate::balloc;
-
-    let xattr_block_addr = inode_ref.get_xattr_block_addr()?;
-    let block_size = inode_ref.superblock().block_size() as usize;
-
-    // 情况 1: 没有 xattr block，需要分配新块
-    if xattr_block_addr == 0 {
-        // 分配新块
-        let goal = 0; // TODO: 可以优化为 inode 附近的块
-        let mut allocator = balloc::BlockAllocator::new();
-        let (bdev, sb) = inode_ref.bdev_and_sb_mut();
-        let new_block_addr = allocator.alloc_block(bdev, sb, goal)?;
+/// 放弃 xattr 块（共享块只减少引用计数）并清除 `i_file_acl`，
+/// 减少 inode 内部和块中引用的 EA inode 的引用计数。
+/// inode 内部的条目不会改写，inode 本身随后被释放
+pub(crate) fn release_all<D: BlockDevice>(inode_ref: &mut InodeRef<D>) -> Result<()> {
+    let mut refs = EaRefs::default();
+    if let Some(records) = read_ibody_records(inode_ref)? {
+        refs.replace(&records, &[]);
+    }
 
-        if new_block_addr == 0 {
-            return Err(Error::new(ErrorKind::NoSpace, "failed to allocate xattr block"));
-        }
+    let block = read_block_records(inode_ref)?;
+    write_block_records(inode_ref, &mut [], &block, &mut refs)?;
 
-        // 更新 inode 的 file_acl
-        inode_ref.set_xattr_block_addr(new_block_addr)?;
+    refs.apply(inode_ref)
+}
 
-        // 初始化并设置新块
-        let mut block_handle = Block::get(inode_ref.bdev_mut(), new_block_addr)?;
-        block_handle.with_data_mut(|block_data| {
-            // 初始化 xattr block
-            block::initialize_block(block_data)?;
-
-            // 在块中设置 entry
-            let first_offset = core::mem::size_of::<crate::types::ext4_xattr_header>();
-            write::set_entry_in_memory(
-                block_data,
-                first_offset,
-                block_size,
-                name_index,
-                name,
-                Some(value),
-                false,
-            )?;
-
-            // TODO: 计算并设置哈希和校验和
-            // hash::compute_and_set_hashes(block_data)?;
-            // hash::set_block_checksum(sb, block_num, header, block_data)?;
-
-            Ok::<(), Error>(())
-        })?;
-    } else {
-        // 情况 2: 已有 xattr block
-        // 先检查引用计数
-        let refcount = {
-            let mut block_handle = Block::get(inode_ref.bdev_mut(), xattr_block_addr)?;
-            block_handle.with_data(|data| block::get_refcount(data))??
-        };
-
-        // 如果引用计数 > 1，需要 COW
-        let target_block_addr = if refcount > 1 {
-            // 分配新块
-            let goal = xattr_block_addr;
-            let mut allocator = balloc::BlockAllocator::new();
-            let (bdev, sb) = inode_ref.bdev_and_sb_mut();
-            let new_block_addr = allocator.alloc_block(bdev, sb, goal)?;
-
-            if new_block_addr == 0 {
-                return Err(Error::new(ErrorKind::NoSpace, "failed to allocate xattr block for COW"));
-            }
+/// 修改前两处存储的属性
+///
+/// `ibody` 和 `block` 是修改中的副本，`old_*` 保留原始内容，
+/// 写回时用来计算 EA inode 引用的变化
+struct Stored {
+    ibody: Option<Vec<XattrRecord>>,
+    block: Vec<XattrRecord>,
+    old_ibody: Vec<XattrRecord>,
+    old_block: Vec<XattrRecord>,
+}
 
-            // 复制旧块到新块，并调整引用计数
-            // 先复制数据
-            {
-                let mut old_block = Block::get(inode_ref.bdev_mut(), xattr_block_addr)?;
-                let data_copy: alloc::vec::Vec<u8> = old_block.with_data(|old_data| {
-                    old_data[..block_size].to_vec()
-                })?;
-
-                drop(old_block);
-
-                let mut new_block = Block::get(inode_ref.bdev_mut(), new_block_addr)?;
-                new_block.with_data_mut(|new_data| {
-                    new_data[..block_size].copy_from_slice(&data_copy);
-                    // 设置新块的引用计数为 1
-                    block::set_refcount(new_data, 1)?;
-                    Ok::<(), Error>(())
-                })?;
-            }
+impl Stored {
+    fn read<D: BlockDevice>(inode_ref: &mut InodeRef<D>) -> Result<Self> {
+        let ibody = read_ibody_records(inode_ref)?;
+        let block = read_block_records(inode_ref)?;
+        Ok(Self {
+            old_ibody: ibody.clone().unwrap_or_default(),
+            old_block: block.clone(),
+            ibody,
+            block,
+        })
+    }
+}
 
-            // 减少旧块的引用计数
-            {
-                let mut old_block = Block::get(inode_ref.bdev_mut(), xattr_block_addr)?;
-                old_block.with_data_mut(|data| {
-                    block::dec_refcount(data)?;
-                    Ok::<(), Error>(())
-                })?;
-            }
+/// 写回 inode 内部的属性，成功时记录 EA inode 引用的变化
+fn write_ibody<D: BlockDevice>(
+    inode_ref: &mut InodeRef<D>,
+    records: &mut [XattrRecord],
+    old: &[XattrRecord],
+    refs: &mut EaRefs,
+) -> Result<bool> {
+    let written = write_ibody_records(inode_ref, records)?;
+    if written {
+        refs.replace(old, records);
+    }
+    Ok(written)
+}
 
-            // 更新 inode 的 file_acl
-            inode_ref.set_xattr_block_addr(new_block_addr)?;
+/// 从列表中移除所有同名属性，返回是否存在
+fn remove_record(records: &mut Vec<XattrRecord>, name_index: u8, name: &[u8]) -> bool {
+    let before = records.len();
+    records.retain(|r| !r.matches(name_index, name));
+    records.len() != before
+}
 
-            new_block_addr
-        } else {
-            xattr_block_addr
-        };
-
-        // 在目标块中设置 entry
-        let mut block_handle = Block::get(inode_ref.bdev_mut(), target_block_addr)?;
-        block_handle.with_data_mut(|block_data| {
-            let first_offset = core::mem::size_of::<crate::types::ext4_xattr_header>();
-
-            // 设置 entry
-            write::set_entry_in_memory(
-                block_data,
-                first_offset,
-                block_size,
-                name_index,
-                name,
-                Some(value),
-                false,
-            )?;
-
-            // TODO: 更新哈希和校验和
-            // hash::compute_and_set_hashes(block_data)?;
-            // hash::set_block_checksum(sb, block_num, header, block_data)?;
-
-            Ok::<(), Error>(())
-        })?;
+/// 读取 xattr block 中的所有属性（没有块时为空）
+fn read_block_records<D: BlockDevice>(inode_ref: &mut InodeRef<D>) -> Result<Vec<XattrRecord>> {
+    let xattr_block_addr = inode_ref.get_xattr_block_addr()?;
+    if xattr_block_addr == 0 {
+        return Ok(Vec::new());
     }
 
-    Ok(())
+    let (bdev, sb) = inode_ref.bdev_and_sb_mut();
+    let mut block_handle = Block::get(bdev, xattr_block_addr)?;
+    block_handle.with_data(|block_data| {
+        block::validate_block(sb, block_data).map_err(|_| {
+            Error::new(ErrorKind::Corrupted, "invalid xattr block")
+        })?;
+        write::read_records(block_data, size_of::<ext4_xattr_header>(), 0)
+    })?
 }
 
-/// 从 xattr block 中删除属性（内部辅助函数）
+/// 用 `records` 替换 xattr block 的内容（内部辅助函数）
 ///
-/// 对应 lwext4 的 `ext4_xattr_block_remove()`
+/// 对应 lwext4 的 `ext4_xattr_block_set()`
 ///
 /// 实现逻辑：
-/// 1. 如果 h_refcount > 1，执行 COW
-/// 2. 在 block 中删除 entry
-/// 3. 检查 block 是否为空，如果是则释放
-/// 4. 更新哈希和校验和
-fn remove_from_block<D: BlockDevice>(
+/// 1. `records` 为空时释放块（共享块只减少引用计数）
+/// 2. 如果没有 xattr block，分配新块
+/// 3. 如果有 block 且 h_refcount > 1，执行 COW
+/// 4. 按规范顺序写入所有 entry，更新哈希和校验和
+///
+/// xattr 块计入 inode 的 i_blocks。空间不足时返回 NoSpace，不修改任何数据。
+///
+/// `old` 是块原来的内容，写入成功后把 EA inode 引用的变化记入 `refs`：
+/// 共享块保留原有的引用，COW 得到的新块引用其中所有的 EA inode
+fn write_block_records<D: BlockDevice>(
     inode_ref: &mut InodeRef<D>,
-    name_index: u8,
-    name: &[u8],
+    records: &mut [XattrRecord],
+    old: &[XattrRecord],
+    refs: &mut EaRefs,
 ) -> Result<()> {
-    use super::{block, write};
-    use crate::balloc;
-
     let xattr_block_addr = inode_ref.get_xattr_block_addr()?;
     let block_size = inode_ref.superblock().block_size() as usize;
 
-    // 检查引用计数
-    let refcount = {
+    let refcount = if xattr_block_addr != 0 {
         let mut block_handle = Block::get(inode_ref.bdev_mut(), xattr_block_addr)?;
         block_handle.with_data(|data| block::get_refcount(data))??
+    } else {
+        0
     };
 
-    // 如果引用计数 > 1，需要 COW
-    let target_block_addr = if refcount > 1 {
-        // 分配新块
+    // 1. 没有属性了：释放块
+    if records.is_empty() {
+        if xattr_block_addr != 0 {
+            release_block(inode_ref, xattr_block_addr, refcount)?;
+            inode_ref.set_xattr_block_addr(0)?;
+            inode_ref.sub_blocks(1)?;
+            refs.charge(old, records);
+            if refcount <= 1 {
+                refs.reference(old, records);
+            }
+        }
+        return Ok(());
+    }
+
+    if size_of::<ext4_xattr_header>() + write::records_space(records) > block_size {
+        return Err(Error::new(ErrorKind::NoSpace, "no space for xattr entry"));
+    }
+
+    // 2/3. 没有块或块被共享时，写入新分配的块
+    let target_block_addr = if xattr_block_addr != 0 && refcount <= 1 {
+        xattr_block_addr
+    } else {
         let goal = xattr_block_addr;
         let mut allocator = balloc::BlockAllocator::new();
         let (bdev, sb) = inode_ref.bdev_and_sb_mut();
-        let new_block_addr = allocator.alloc_block(bdev, sb, goal)?;
+        let Pblk(new_block_addr) = allocator.alloc_block(bdev, sb, goal)?;
 
         if new_block_addr == 0 {
-            return Err(Error::new(ErrorKind::NoSpace, "failed to allocate xattr block for COW"));
+            return Err(Error::new(ErrorKind::NoSpace, "failed to allocate xattr block"));
         }
 
-        // 复制旧块到新块，并调整引用计数
-        // 先复制数据
-        {
-            let mut old_block = Block::get(inode_ref.bdev_mut(), xattr_block_addr)?;
-            let data_copy: alloc::vec::Vec<u8> = old_block.with_data(|old_data| {
-                old_data[..block_size].to_vec()
-            })?;
-
-            drop(old_block);
-
-            let mut new_block = Block::get(inode_ref.bdev_mut(), new_block_addr)?;
-            new_block.with_data_mut(|new_data| {
-                new_data[..block_size].copy_from_slice(&data_copy);
-                // 设置新块的引用计数为 1
-                block::set_refcount(new_data, 1)?;
-                Ok::<(), Error>(())
-            })?;
-        }
+        let mut new_block = Block::get_noread(inode_ref.bdev_mut(), new_block_addr)?;
+        new_block.with_data_mut(block::initialize_block)??;
+        drop(new_block);
 
-        // 减少旧块的引用计数
-        {
-            let mut old_block = Block::get(inode_ref.bdev_mut(), xattr_block_addr)?;
-            old_block.with_data_mut(|data| {
-                block::dec_refcount(data)?;
-                Ok::<(), Error>(())
-            })?;
+        if xattr_block_addr != 0 {
+            // COW：旧块仍被其他 inode 使用，只减少引用计数
+            release_block(inode_ref, xattr_block_addr, refcount)?;
+        } else {
+            inode_ref.add_blocks(1)?;
         }
-
-        // 更新 inode 的 file_acl
         inode_ref.set_xattr_block_addr(new_block_addr)?;
 
         new_block_addr
-    } else {
-        xattr_block_addr
     };
 
-    // 在目标块中删除 entry
-    let mut block_handle = Block::get(inode_ref.bdev_mut(), target_block_addr)?;
-    let block_is_empty = block_handle.with_data_mut(|block_data| {
-        let first_offset = core::mem::size_of::<crate::types::ext4_xattr_header>();
-
-        // 删除 entry（传入 None 作为 value）
-        write::set_entry_in_memory(
-            block_data,
-            first_offset,
-            block_size,
-            name_index,
-            name,
-            None, // None 表示删除
-            false,
+    // 4. 写入 entry、块哈希和校验和
+    let (bdev, sb) = inode_ref.bdev_and_sb_mut();
+    let mut block_handle = Block::get(bdev, target_block_addr)?;
+    block_handle.with_data_mut(|block_data| {
+        let block_hash = write::write_records(
+            &mut block_data[..block_size],
+            size_of::<ext4_xattr_header>(),
+            0,
+            records,
+            true,
         )?;
+        block_data[HEADER_HASH_OFFSET..HEADER_HASH_OFFSET + 4].copy_from_slice(&block_hash.to_le_bytes());
 
-        // TODO: 更新哈希和校验和
-        // hash::compute_and_set_hashes(block_data)?;
-        // hash::set_block_checksum(sb, block_num, header, block_data)?;
-
-        // 检查 block 是否为空（只剩 header）
-        let is_empty = {
-            let first_entry_offset = first_offset;
-            if first_entry_offset + 4 > block_data.len() {
-                true
-            } else {
-                let first_u32 = u32::from_le_bytes([
-                    block_data[first_entry_offset],
-                    block_data[first_entry_offset + 1],
-                    block_data[first_entry_offset + 2],
-                    block_data[first_entry_offset + 3],
-                ]);
-                first_u32 == 0 // 如果第一个 entry 就是终止符，说明为空
-            }
-        };
+        if sb.has_metadata_csum() {
+            block_data[HEADER_CSUM_OFFSET..HEADER_CSUM_OFFSET + 4].fill(0);
+            let csum = hash::compute_block_checksum(sb, target_block_addr, &block_data[..block_size]);
+            block_data[HEADER_CSUM_OFFSET..HEADER_CSUM_OFFSET + 4].copy_from_slice(&csum.to_le_bytes());
+        }
 
-        Ok::<bool, Error>(is_empty)
+        Ok::<(), Error>(())
     })??;
 
-    // 如果 block 为空，释放它
-    if block_is_empty {
-        drop(block_handle); // 释放 Block 的借用
-        let (bdev, sb) = inode_ref.bdev_and_sb_mut();
-        balloc::free_block(bdev, sb, target_block_addr)?;
-        inode_ref.set_xattr_block_addr(0)?;
+    refs.charge(old, records);
+    if target_block_addr == xattr_block_addr {
+        refs.reference(old, records);
+    } else {
+        refs.reference(&[], records);
     }
 
     Ok(())
 }
 
+/// `ext4_xattr_header::h_hash` 的偏移
+const HEADER_HASH_OFFSET: usize = 12;
+
+/// `ext4_xattr_header::h_checksum` 的偏移
+const HEADER_CSUM_OFFSET: usize = 16;
+
+/// 放弃 inode 对 xattr 块的引用
+///
+/// 共享块只减少引用计数，否则释放块
+fn release_block<D: BlockDevice>(
+    inode_ref: &mut InodeRef<D>,
+    block_addr: u64,
+    refcount: u32,
+) -> Result<()> {
+    if refcount > 1 {
+        let mut block_handle = Block::get(inode_ref.bdev_mut(), block_addr)?;
+        block_handle.with_data_mut(block::dec_refcount)??;
+        return Ok(());
+    }
+
+    let (bdev, sb) = inode_ref.bdev_and_sb_mut();
+    balloc::free_block(bdev, sb, block_addr)
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -5,6 +5,7 @@
 use crate::{
     consts::*,
     error::{Error, ErrorKind, Result},
+    parse,
     superblock::Superblock,
     types::{ext4_xattr_entry, ext4_xattr_header},
 };
@@ -68,94 +69,8 @@ pub fn validate_block(sb: &Superblock, block_data: &[u8]) -> Result<()> {
         return Err(Error::new(ErrorKind::InvalidInput, "block data too small"));
     }
 
-    // 读取 header
-    let header = get_block_header(block_data)?;
-
-    // 检查魔数
-    if u32::from_le(header.h_magic) != EXT4_XATTR_MAGIC {
-        return Err(Error::new(ErrorKind::InvalidInput, "invalid xattr block magic"));
-    }
-
-    // 检查块数（当前只支持单块）
-    if u32::from_le(header.h_blocks) != 1 {
-        return Err(Error::new(ErrorKind::InvalidInput, "multi-block xattr not supported"));
-    }
-
-    // 验证所有 entry
-    let base = 0;
-    let end = block_size;
-    let mut min_offs = end - base;
-
-    let first_entry_offset = get_first_entry_offset();
-    let mut entry_offset = first_entry_offset;
-
-    loop {
-        // 检查是否到达末尾
-        if entry_offset + 4 > end {
-            break;
-        }
-
-        // 检查是否是最后一个 entry
-        let first_u32 = u32::from_le_bytes([
-            block_data[entry_offset],
-            block_data[entry_offset + 1],
-            block_data[entry_offset + 2],
-            block_data[entry_offset + 3],
-        ]);
-
-        if first_u32 == 0 {
-            break;
-        }
-
-        // 读取 entry
-        if entry_offset + size_of::<ext4_xattr_entry>() > end {
-            return Err(Error::new(ErrorKind::InvalidInput, "entry out of bounds"));
-        }
-
-        let entry_bytes = &block_data[entry_offset..entry_offset + size_of::<ext4_xattr_entry>()];
-        let entry = unsafe {
-            core::ptr::read(entry_bytes.as_ptr() as *const ext4_xattr_entry)
-        };
-
-        let value_size = entry.value_size();
-        let value_offs = u16::from_le(entry.e_value_offs) as usize;
-
-        // 检查：如果 value_size 为 0，value_offs 也应该为 0
-        if value_size == 0 && value_offs != 0 {
-            return Err(Error::new(ErrorKind::InvalidInput, "invalid value offset"));
-        }
-
-        // 检查 value 是否在范围内
-        if value_size > 0 {
-            if base + value_offs + value_size as usize > end {
-                return Err(Error::new(ErrorKind::InvalidInput, "value out of bounds"));
-            }
-
-            // 更新最小值偏移
-            if value_offs < min_offs {
-                min_offs = value_offs;
-            }
-        }
-
-        // 检查下一个 entry 的位置
-        let name_len = entry.e_name_len as usize;
-        let entry_len = ((name_len + EXT4_XATTR_ROUND as usize + size_of::<ext4_xattr_entry>())
-            & !(EXT4_XATTR_ROUND as usize));
-
-        let next_offset = entry_offset + entry_len;
-
-        // 确保下一个 entry 的位置加上结束标记不会超出边界
-        if next_offset + 4 > end {
-            return Err(Error::new(ErrorKind::InvalidInput, "next entry out of bounds"));
-        }
-
-        entry_offset = next_offset;
-    }
-
-    // 检查 entry 区域和 value 区域是否有重叠
-    if entry_offset > min_offs {
-        return Err(Error::new(ErrorKind::InvalidInput, "entry and value regions overlap"));
-    }
+    parse::parse_xattr_block(&block_data[..block_size])
+        .map_err(|e| Error::new(ErrorKind::InvalidInput, e.message()))?;
 
     Ok(())
 }
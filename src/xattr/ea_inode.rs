@@ -0,0 +1,431 @@
+//! 存放在独立 inode 中的 xattr 值（EA_INODE）
+//!
+//! 启用 `EXT4_FEATURE_INCOMPAT_EA_INODE` 后，放不进 inode 内部和 xattr 块的值
+//! 写入一个带 `EXT4_INODE_FLAG_EA_INODE` 标志的普通文件 inode，entry 的
+//! `e_value_inum` 指向它，`e_value_offs` 为 0。磁盘格式与内核相同：
+//!
+//! - 值的哈希（见 [`compute_ea_value_hash`](super::hash::compute_ea_value_hash)）保存在 `i_atime`
+//! - 引用它的 entry 数量是 EA inode 的引用计数，高 32 位保存在 `i_ctime`，
+//!   低 32 位保存在 `i_version`（`osd1`）
+//! - EA inode 不在任何目录中，链接计数固定为 1，引用计数归零时释放
+//!
+//! 内容相同的值共享同一个 EA inode：superblock 上按值哈希记录本次挂载见过的
+//! EA inode，创建前先比较候选 inode 的内容。
+
+use crate::{
+    block::{Block, BlockDev, BlockDevice},
+    consts::*,
+    error::{Error, ErrorKind, Result},
+    fs::InodeRef,
+    ialloc,
+    superblock::Superblock,
+    types::ext4_inode,
+};
+use alloc::vec::Vec;
+
+use super::{hash, write::{EaValue, XattrRecord}};
+
+/// 文件系统是否启用了 EA_INODE 特性
+pub(super) fn enabled(sb: &Superblock) -> bool {
+    sb.has_incompat_feature(EXT4_FEATURE_INCOMPAT_EA_INODE)
+}
+
+/// 把记录的值移入 EA inode
+///
+/// 复用内容相同的已知 EA inode，否则创建新的。新 inode 的引用计数为 0，
+/// 由写入 entry 的位置通过 [`EaRefs`] 增加引用；放置失败时调用
+/// [`release_if_unused`] 释放
+pub(super) fn store_value<D: BlockDevice>(
+    inode_ref: &mut InodeRef<D>,
+    record: &mut XattrRecord,
+) -> Result<()> {
+    let (uid, gid) = inode_ref.with_inode(|inode| {
+        let uid = u16::from_le(inode.uid) as u32 | (u16::from_le(inode.uid_high) as u32) << 16;
+        let gid = u16::from_le(inode.gid) as u32 | (u16::from_le(inode.gid_high) as u32) << 16;
+        (uid, gid)
+    })?;
+
+    let (bdev, sb) = inode_ref.bdev_and_sb_mut();
+    let value = &record.value;
+    let value_hash = hash::compute_ea_value_hash(sb, value);
+
+    let mut inum = None;
+    for candidate in sb.ea_inode_candidates(value_hash).to_vec() {
+        if holds_value(bdev, sb, candidate, value_hash, value)? {
+            inum = Some(candidate);
+            break;
+        }
+    }
+    let inum = match inum {
+        Some(inum) => inum,
+        None => create(bdev, sb, uid, gid, value_hash, value)?,
+    };
+
+    record.ea = Some(EaValue {
+        inum,
+        size: value.len() as u32,
+        entry_hash: hash::compute_ea_entry_hash(&record.name, value_hash),
+    });
+    record.value = Vec::new();
+    Ok(())
+}
+
+/// 从 EA inode 读取值到 `buffer`
+///
+/// # 错误
+///
+/// - `ErrorKind::InvalidInput` - 缓冲区太小
+/// - `ErrorKind::Corrupted` - inode 不是 EA inode，或大小、值哈希与 entry 不符
+pub(super) fn read_value<D: BlockDevice>(
+    inode_ref: &mut InodeRef<D>,
+    inum: u32,
+    size: u32,
+    buffer: &mut [u8],
+) -> Result<usize> {
+    let size = size as usize;
+    if buffer.len() < size {
+        return Err(Error::new(ErrorKind::InvalidInput, "buffer too small"));
+    }
+
+    let (bdev, sb) = inode_ref.bdev_and_sb_mut();
+    let value_hash = {
+        let mut ea_ref = InodeRef::get(bdev, sb, inum)?;
+        check_ea_inode(&mut ea_ref)?;
+        if ea_ref.size()? != size as u64 {
+            return Err(Error::new(ErrorKind::Corrupted, "EA inode size does not match xattr entry"));
+        }
+        if ea_ref.read_extent_file(0, &mut buffer[..size])? != size {
+            return Err(Error::new(ErrorKind::Corrupted, "short read from EA inode"));
+        }
+        ea_ref.with_inode(|inode| u32::from_le(inode.atime))?
+    };
+
+    if hash::compute_ea_value_hash(sb, &buffer[..size]) != value_hash {
+        return Err(Error::new(ErrorKind::Corrupted, "EA inode value hash mismatch"));
+    }
+    sb.register_ea_inode(value_hash, inum);
+    Ok(size)
+}
+
+/// 释放引用计数为 0 的 EA inode（新建后没能放置的值）
+pub(super) fn release_if_unused<D: BlockDevice>(inode_ref: &mut InodeRef<D>, inum: u32) -> Result<()> {
+    let (bdev, sb) = inode_ref.bdev_and_sb_mut();
+    let refs = {
+        let mut ea_ref = InodeRef::get(bdev, sb, inum)?;
+        ea_ref.with_inode(get_refcount)?
+    };
+    if refs == 0 {
+        free(bdev, sb, inum)?;
+    }
+    Ok(())
+}
+
+/// 一次 xattr 修改中 EA inode 引用的变化
+///
+/// 每个存储位置（inode 内部或 xattr 块）写回后记录新旧内容的差异，
+/// 全部写完后由 [`apply`](Self::apply) 先增加后减少引用，
+/// 值在两个位置之间移动时 EA inode 不会被提前释放。
+///
+/// 与内核和 e2fsck 相同，拥有属性的 inode 的 `i_blocks` 包含它能看到的每个
+/// EA 值占用的块数，这部分随 inode 看到的条目变化，与 EA inode 的引用计数分开记录
+#[derive(Debug, Default)]
+pub(super) struct EaRefs {
+    inc: Vec<u32>,
+    dec: Vec<u32>,
+    charged: Vec<u32>,
+    uncharged: Vec<u32>,
+}
+
+impl EaRefs {
+    /// inode 独占的位置的内容从 `old` 变为 `new`
+    pub(super) fn replace(&mut self, old: &[XattrRecord], new: &[XattrRecord]) {
+        self.reference(old, new);
+        self.charge(old, new);
+    }
+
+    /// 位置对 EA inode 的引用从 `old` 变为 `new`
+    ///
+    /// COW 得到的新块以空列表为 `old`，放弃共享块时不改变引用
+    pub(super) fn reference(&mut self, old: &[XattrRecord], new: &[XattrRecord]) {
+        let (added, removed) = diff(old, new);
+        self.inc.extend(added.iter().map(|ea| ea.inum));
+        self.dec.extend(removed.iter().map(|ea| ea.inum));
+    }
+
+    /// inode 看到的 EA 值从 `old` 变为 `new`，调整计入 `i_blocks` 的块数
+    pub(super) fn charge(&mut self, old: &[XattrRecord], new: &[XattrRecord]) {
+        let (added, removed) = diff(old, new);
+        self.charged.extend(added.iter().map(|ea| ea.size));
+        self.uncharged.extend(removed.iter().map(|ea| ea.size));
+    }
+
+    /// 更新 inode 的 `i_blocks` 和 EA inode 的引用计数，归零的 EA inode 被释放
+    pub(super) fn apply<D: BlockDevice>(self, inode_ref: &mut InodeRef<D>) -> Result<()> {
+        let block_size = inode_ref.superblock().block_size();
+        let blocks = |sizes: &[u32]| sizes.iter().map(|size| size.div_ceil(block_size)).sum::<u32>();
+        let (add, sub) = (blocks(&self.charged), blocks(&self.uncharged));
+        if add > sub {
+            inode_ref.add_blocks(add - sub)?;
+        } else if sub > add {
+            inode_ref.sub_blocks(sub - add)?;
+        }
+
+        let (bdev, sb) = inode_ref.bdev_and_sb_mut();
+        for &inum in &self.inc {
+            adjust_refcount(bdev, sb, inum, 1)?;
+        }
+        for &inum in &self.dec {
+            adjust_refcount(bdev, sb, inum, -1)?;
+        }
+        Ok(())
+    }
+}
+
+/// 比较两组条目中的 EA 引用，返回 (新增, 移除)
+///
+/// 按多重集合比较：同一 EA inode 被两个条目引用时计两次
+fn diff(old: &[XattrRecord], new: &[XattrRecord]) -> (Vec<EaValue>, Vec<EaValue>) {
+    let mut removed: Vec<EaValue> = old.iter().filter_map(|r| r.ea).collect();
+    let mut added = Vec::new();
+    for ea in new.iter().filter_map(|r| r.ea) {
+        match removed.iter().position(|old| old.inum == ea.inum) {
+            Some(pos) => {
+                removed.swap_remove(pos);
+            }
+            None => added.push(ea),
+        }
+    }
+    (added, removed)
+}
+
+/// 读取 EA inode 的引用计数
+fn get_refcount(inode: &ext4_inode) -> u64 {
+    (u32::from_le(inode.ctime) as u64) << 32 | u32::from_le(inode.osd1) as u64
+}
+
+/// 设置 EA inode 的引用计数
+fn set_refcount(inode: &mut ext4_inode, refs: u64) {
+    inode.ctime = ((refs >> 32) as u32).to_le();
+    inode.osd1 = (refs as u32).to_le();
+}
+
+/// 确认 inode 带有 EA_INODE 标志
+fn check_ea_inode<D: BlockDevice>(ea_ref: &mut InodeRef<D>) -> Result<()> {
+    let flags = ea_ref.with_inode(|inode| u32::from_le(inode.flags))?;
+    if flags & EXT4_INODE_FLAG_EA_INODE == 0 {
+        return Err(Error::new(ErrorKind::Corrupted, "xattr entry references a non-EA inode"));
+    }
+    Ok(())
+}
+
+/// 调整引用计数，减到 0 时释放 EA inode
+fn adjust_refcount<D: BlockDevice>(
+    bdev: &mut BlockDev<D>,
+    sb: &mut Superblock,
+    inum: u32,
+    delta: i64,
+) -> Result<()> {
+    let refs = {
+        let mut ea_ref = InodeRef::get(bdev, sb, inum)?;
+        check_ea_inode(&mut ea_ref)?;
+        let refs = ea_ref.with_inode_mut(|inode| {
+            let refs = (get_refcount(inode) as i64).saturating_add(delta).max(0) as u64;
+            set_refcount(inode, refs);
+            refs
+        })?;
+        ea_ref.mark_dirty()?;
+        refs
+    };
+
+    if delta < 0 && refs == 0 {
+        free(bdev, sb, inum)?;
+    }
+    Ok(())
+}
+
+/// 候选 EA inode 是否保存着同样的值
+///
+/// 索引只是提示：inode 可能已被释放或重用，因此逐项确认标志、链接计数、
+/// 大小、值哈希和内容
+fn holds_value<D: BlockDevice>(
+    bdev: &mut BlockDev<D>,
+    sb: &mut Superblock,
+    inum: u32,
+    value_hash: u32,
+    value: &[u8],
+) -> Result<bool> {
+    let mut ea_ref = InodeRef::get(bdev, sb, inum)?;
+    let (flags, links, hash) = ea_ref.with_inode(|inode| {
+        (u32::from_le(inode.flags), u16::from_le(inode.links_count), u32::from_le(inode.atime))
+    })?;
+    if flags & EXT4_INODE_FLAG_EA_INODE == 0
+        || links != 1
+        || hash != value_hash
+        || ea_ref.size()? != value.len() as u64
+    {
+        return Ok(false);
+    }
+
+    let mut stored = alloc::vec![0u8; value.len()];
+    let read = ea_ref.read_extent_file(0, &mut stored)?;
+    Ok(read == value.len() && stored == value)
+}
+
+/// 分配并写入一个新的 EA inode（引用计数为 0）
+fn create<D: BlockDevice>(
+    bdev: &mut BlockDev<D>,
+    sb: &mut Superblock,
+    uid: u32,
+    gid: u32,
+    value_hash: u32,
+    value: &[u8],
+) -> Result<u32> {
+    let inum = ialloc::alloc_inode(bdev, sb, false)?;
+    if let Err(e) = init(bdev, sb, inum, uid, gid, value_hash, value) {
+        free(bdev, sb, inum)?;
+        return Err(e);
+    }
+    sb.register_ea_inode(value_hash, inum);
+    Ok(inum)
+}
+
+/// 初始化 EA inode 并写入值
+///
+/// 与内核 `ext4_xattr_inode_create()` 相同：`S_IFREG | 0600`，属主取自
+/// 拥有该属性的 inode，启用 extent 时使用 extent 树
+fn init<D: BlockDevice>(
+    bdev: &mut BlockDev<D>,
+    sb: &mut Superblock,
+    inum: u32,
+    uid: u32,
+    gid: u32,
+    value_hash: u32,
+    value: &[u8],
+) -> Result<()> {
+    let inode_size = sb.inode_size() as usize;
+    let block_size = sb.block_size() as usize;
+    let use_extents = sb.has_extents();
+
+    let mut ea_ref = InodeRef::get(bdev, sb, inum)?;
+
+    // 清除残留内容，保留 generation（inode 校验和种子的一部分）
+    ea_ref.with_inode_raw_data_mut(|data| {
+        let generation = [data[100], data[101], data[102], data[103]];
+        data[..inode_size].fill(0);
+        data[100..104].copy_from_slice(&generation);
+        if inode_size > EXT4_GOOD_OLD_INODE_SIZE {
+            let extra_isize = 32.min(inode_size - EXT4_GOOD_OLD_INODE_SIZE) as u16;
+            data[EXT4_GOOD_OLD_INODE_SIZE..EXT4_GOOD_OLD_INODE_SIZE + 2]
+                .copy_from_slice(&extra_isize.to_le_bytes());
+        }
+    })?;
+
+    ea_ref.with_inode_mut(|inode| {
+        inode.mode = (EXT4_INODE_MODE_FILE | 0o600).to_le();
+        inode.uid = (uid as u16).to_le();
+        inode.uid_high = ((uid >> 16) as u16).to_le();
+        inode.gid = (gid as u16).to_le();
+        inode.gid_high = ((gid >> 16) as u16).to_le();
+        inode.links_count = 1u16.to_le();
+        inode.atime = value_hash.to_le();
+        let mut flags = EXT4_INODE_FLAG_EA_INODE;
+        if use_extents {
+            flags |= EXT4_INODE_FLAG_EXTENTS;
+        }
+        inode.flags = flags.to_le();
+    })?;
+    if use_extents {
+        crate::extent::tree_init(&mut ea_ref)?;
+    }
+    ea_ref.set_size(value.len() as u64)?;
+    ea_ref.mark_dirty()?;
+
+    // 写入值，最后一块的剩余部分清零
+    let total_blocks = value.len().div_ceil(block_size) as u32;
+    let mut lblk = 0u32;
+    while lblk < total_blocks {
+        let (pblk, count) = ea_ref.get_inode_dblk_run(lblk, total_blocks - lblk, true)?;
+        for i in 0..count {
+            let start = (lblk + i) as usize * block_size;
+            let chunk = &value[start..(start + block_size).min(value.len())];
+            let mut block = Block::get_noread(ea_ref.bdev_mut(), pblk + i as u64)?;
+            block.with_data_mut(|data| {
+                data[..chunk.len()].copy_from_slice(chunk);
+                data[chunk.len()..].fill(0);
+            })?;
+        }
+        lblk += count;
+    }
+
+    Ok(())
+}
+
+/// 释放 EA inode 的数据块和 inode 本身
+fn free<D: BlockDevice>(bdev: &mut BlockDev<D>, sb: &mut Superblock, inum: u32) -> Result<()> {
+    let value_hash = {
+        let mut ea_ref = InodeRef::get(bdev, sb, inum)?;
+        let size = ea_ref.size()?;
+        let block_size = ea_ref.superblock().block_size() as u64;
+        if size > 0 {
+            ea_ref.remove_space(0, ((size - 1) / block_size) as u32)?;
+        }
+        ea_ref.set_size(0)?;
+        ea_ref.set_blocks_count(0)?;
+        let value_hash = ea_ref.with_inode_mut(|inode| {
+            inode.links_count = 0;
+            u32::from_le(inode.atime)
+        })?;
+        ea_ref.mark_dirty()?;
+        value_hash
+    };
+
+    ialloc::free_inode(bdev, sb, inum, false)?;
+    sb.forget_ea_inode(value_hash, inum);
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn ea_record(inum: u32) -> XattrRecord {
+        XattrRecord {
+            name_index: 1,
+            name: alloc::vec![b'a'],
+            value: Vec::new(),
+            ea: Some(EaValue { inum, size: inum * 100, entry_hash: 0 }),
+        }
+    }
+
+    #[test]
+    fn test_ea_refs_replace() {
+        let mut refs = EaRefs::default();
+        // 12 保留，13 被移除，14 新增；同一 inode 的两个引用分别计数
+        refs.replace(&[ea_record(12), ea_record(13)], &[ea_record(12), ea_record(14), ea_record(14)]);
+        assert_eq!(refs.inc, [14, 14]);
+        assert_eq!(refs.dec, [13]);
+        assert_eq!(refs.charged, [1400, 1400]);
+        assert_eq!(refs.uncharged, [1300]);
+    }
+
+    #[test]
+    fn test_ea_refs_shared_block() {
+        let mut refs = EaRefs::default();
+        // COW：新块引用所有 EA inode，inode 看到的值只多了 13
+        refs.reference(&[], &[ea_record(12), ea_record(13)]);
+        refs.charge(&[ea_record(12)], &[ea_record(12), ea_record(13)]);
+        assert_eq!(refs.inc, [12, 13]);
+        assert!(refs.dec.is_empty());
+        assert_eq!(refs.charged, [1300]);
+        assert!(refs.uncharged.is_empty());
+    }
+
+    #[test]
+    fn test_refcount_fields() {
+        let mut inode = ext4_inode::default();
+        set_refcount(&mut inode, 0x1_0000_0002);
+        assert_eq!(u32::from_le(inode.ctime), 1);
+        assert_eq!(u32::from_le(inode.osd1), 2);
+        assert_eq!(get_refcount(&inode), 0x1_0000_0002);
+    }
+}
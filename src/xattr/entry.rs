@@ -0,0 +1,68 @@
+//! 扩展属性条目和命名空间
+//!
+//! [`iter`](super::iter) 返回的条目类型。与 `xattr` feature 无关，
+//! 关闭时占位实现使用相同的类型。
+
+use crate::consts::*;
+use alloc::string::String;
+
+/// 扩展属性的命名空间
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum XattrNamespace {
+    /// `user.*`
+    User,
+    /// `system.*`（包括 POSIX ACL 和 richacl）
+    System,
+    /// `security.*`（如 SELinux 标签）
+    Security,
+    /// `trusted.*`
+    Trusted,
+}
+
+impl XattrNamespace {
+    /// 磁盘上命名空间索引（`e_name_index`）所属的命名空间
+    ///
+    /// 没有用户可见前缀的索引（如 Lustre、加密上下文）返回 `None`
+    pub fn from_name_index(name_index: u8) -> Option<Self> {
+        match name_index {
+            EXT4_XATTR_INDEX_USER => Some(Self::User),
+            EXT4_XATTR_INDEX_POSIX_ACL_ACCESS
+            | EXT4_XATTR_INDEX_POSIX_ACL_DEFAULT
+            | EXT4_XATTR_INDEX_SYSTEM
+            | EXT4_XATTR_INDEX_RICHACL => Some(Self::System),
+            EXT4_XATTR_INDEX_SECURITY => Some(Self::Security),
+            EXT4_XATTR_INDEX_TRUSTED => Some(Self::Trusted),
+            _ => None,
+        }
+    }
+}
+
+/// 一个扩展属性（不含值）
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct XattrEntry {
+    /// 完整名称，含命名空间前缀（如 `user.comment`）
+    ///
+    /// 磁盘上不是 UTF-8 的名称按 `String::from_utf8_lossy` 转换
+    pub name: String,
+    /// 命名空间
+    pub namespace: XattrNamespace,
+    /// 值的长度（字节）
+    pub value_size: u32,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_namespace_from_name_index() {
+        assert_eq!(XattrNamespace::from_name_index(EXT4_XATTR_INDEX_USER), Some(XattrNamespace::User));
+        assert_eq!(
+            XattrNamespace::from_name_index(EXT4_XATTR_INDEX_POSIX_ACL_DEFAULT),
+            Some(XattrNamespace::System)
+        );
+        assert_eq!(XattrNamespace::from_name_index(EXT4_XATTR_INDEX_SECURITY), Some(XattrNamespace::Security));
+        assert_eq!(XattrNamespace::from_name_index(EXT4_XATTR_INDEX_TRUSTED), Some(XattrNamespace::Trusted));
+        assert_eq!(XattrNamespace::from_name_index(EXT4_XATTR_INDEX_ENCRYPTION), None);
+    }
+}
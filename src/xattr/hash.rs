@@ -14,7 +14,7 @@ use crate::{
 /// 对应 lwext4 的 `ext4_xattr_compute_hash()`
 ///
 /// 哈希算法：
-/// 1. 对名称的每个字节执行滚动哈希
+/// 1. 对名称的每个字节执行滚动哈希（移位后异或）
 /// 2. 对值的每个 4 字节执行滚动哈希（如果值存在）
 ///
 /// # 参数
@@ -37,8 +37,8 @@ pub fn compute_entry_hash(
     for &byte in name.iter().take(entry.e_name_len as usize) {
         hash = hash
             .wrapping_shl(NAME_HASH_SHIFT)
-            .wrapping_add(hash.wrapping_shr(32 - NAME_HASH_SHIFT))
-            .wrapping_add(byte as u32);
+            ^ hash.wrapping_shr(32 - NAME_HASH_SHIFT)
+            ^ byte as u32;
     }
 
     // 如果有值且值不在外部块（e_value_block == 0），对值计算哈希
@@ -62,8 +62,8 @@ pub fn compute_entry_hash(
 
                     hash = hash
                         .wrapping_shl(VALUE_HASH_SHIFT)
-                        .wrapping_add(hash.wrapping_shr(32 - VALUE_HASH_SHIFT))
-                        .wrapping_add(val);
+                        ^ hash.wrapping_shr(32 - VALUE_HASH_SHIFT)
+                        ^ val;
                 }
             }
         }
@@ -150,8 +150,8 @@ pub fn rehash_block(
             // 滚动哈希
             block_hash = block_hash
                 .wrapping_shl(BLOCK_HASH_SHIFT)
-                .wrapping_add(block_hash.wrapping_shr(32 - BLOCK_HASH_SHIFT))
-                .wrapping_add(e_hash);
+                ^ block_hash.wrapping_shr(32 - BLOCK_HASH_SHIFT)
+                ^ e_hash;
         }
 
         // 移动到下一个 entry
@@ -168,6 +168,48 @@ pub fn rehash_block(
     block_hash
 }
 
+/// 计算值存放在 EA inode 中的 entry 的哈希
+///
+/// 对应内核 `ext4_xattr_hash_entry()` 处理 `e_value_inum` 的分支：
+/// 名称哈希之后混入 EA inode 中保存的值哈希（见 [`compute_ea_value_hash`]）
+pub fn compute_ea_entry_hash(name: &[u8], value_hash: u32) -> u32 {
+    let mut hash: u32 = 0;
+    for &byte in name {
+        hash = hash
+            .wrapping_shl(NAME_HASH_SHIFT)
+            ^ hash.wrapping_shr(32 - NAME_HASH_SHIFT)
+            ^ byte as u32;
+    }
+
+    hash.wrapping_shl(VALUE_HASH_SHIFT) ^ hash.wrapping_shr(32 - VALUE_HASH_SHIFT) ^ value_hash
+}
+
+/// 计算 EA inode 中值的哈希（保存在 EA inode 的 `i_atime`）
+///
+/// 对应内核 `ext4_xattr_inode_hash()`：以 `s_csum_seed` 为初值的 CRC32C。
+/// 启用 EA_INODE 时内核总会计算该种子，与是否启用 METADATA_CSUM 无关
+pub fn compute_ea_value_hash(sb: &Superblock, value: &[u8]) -> u32 {
+    crate::crc::crc32c_append(sb.csum_seed(), value)
+}
+
+/// 由各 entry 的哈希计算块哈希
+///
+/// 对应 lwext4 `ext4_xattr_rehash()` 的第二步。任何 entry 的哈希为 0 时
+/// 块哈希为 0，表示该块不参与共享
+pub fn compute_block_hash(entry_hashes: &[u32]) -> u32 {
+    let mut block_hash: u32 = 0;
+    for &hash in entry_hashes {
+        if hash == 0 {
+            return 0;
+        }
+        block_hash = block_hash
+            .wrapping_shl(BLOCK_HASH_SHIFT)
+            ^ block_hash.wrapping_shr(32 - BLOCK_HASH_SHIFT)
+            ^ hash;
+    }
+    block_hash
+}
+
 /// 计算 xattr block 的 CRC32C 校验和
 ///
 /// 对应 lwext4 的 `ext4_xattr_block_checksum()`
@@ -284,4 +326,31 @@ mod tests {
         // 空名称的哈希应该是 0
         assert_eq!(hash, 0);
     }
+
+    #[test]
+    fn test_compute_entry_hash_matches_kernel() {
+        // 内核 ext4_xattr_hash_entry("a", "one")：
+        // 名称: 0x61；值 "one\0" 按小端为 0x00656e6f
+        // (0x61 << 16) ^ 0x00656e6f = 0x00046e6f
+        let entry = ext4_xattr_entry {
+            e_name_len: 1,
+            e_value_size: 3u32.to_le(),
+            ..Default::default()
+        };
+        assert_eq!(compute_entry_hash(&entry, b"a", Some(b"one\0")), 0x0004_6e6f);
+    }
+
+    #[test]
+    fn test_compute_ea_entry_hash() {
+        // 名称 "a" 的哈希 0x61 旋转 16 位后异或值哈希
+        assert_eq!(compute_ea_entry_hash(b"a", 5), 0x0061_0005);
+        assert_eq!(compute_ea_entry_hash(b"", 0xdead_beef), 0xdead_beef);
+    }
+
+    #[test]
+    fn test_compute_block_hash() {
+        assert_eq!(compute_block_hash(&[]), 0);
+        assert_eq!(compute_block_hash(&[1, 0, 2]), 0);
+        assert_eq!(compute_block_hash(&[1, 2]), (1 << 16) ^ 2);
+    }
 }
@@ -35,11 +35,16 @@ use crate::{
     error::{Error, ErrorKind, Result},
     block::BlockDevice,
     fs::InodeRef,
+    parse,
     types::{ext4_xattr_entry, ext4_xattr_ibody_header},
 };
+use alloc::vec::Vec;
 use core::mem::size_of;
 
-use super::search::XattrSearch;
+use super::{
+    search::XattrSearch,
+    write::{read_records, records_space, write_records, XattrRecord},
+};
 
 /// 获取 inode 内部 xattr header 的偏移
 ///
@@ -110,7 +115,9 @@ pub fn validate_ibody_xattr<D: BlockDevice>(
             if u32::from_le(header.h_magic) != EXT4_XATTR_MAGIC {
                 Err(Error::new(ErrorKind::Io, "invalid ibody xattr magic"))
             } else {
-                Ok(())
+                // 验证所有 entry 和 value 都在 inode 内
+                let area = &inode_data[header_offset..inode_size.min(inode_data.len())];
+                parse::parse_xattr_ibody(area).map(|_| ())
             }
         }
     })?
@@ -149,16 +156,19 @@ pub fn find_ibody_entry<D: BlockDevice>(
         None => return Ok(None), // 没有 extra_isize
     };
 
-    // 验证 xattr 数据
-    validate_ibody_xattr(inode_ref)?;
+    // 未初始化（没有魔数）时视为没有属性
+    if validate_ibody_xattr(inode_ref).is_err() {
+        return Ok(None);
+    }
 
     // 使用 with_inode_raw_data 访问原始数据并搜索
     inode_ref.with_inode_raw_data(|inode_data| {
         // 获取第一个 entry 的偏移
         let first_entry_offset = get_first_entry_offset(header_offset);
 
-        // 使用 XattrSearch 查找
+        // 使用 XattrSearch 查找，inode 内部的 value 偏移相对于第一个 entry
         let mut search = XattrSearch::new(inode_data, first_entry_offset);
+        search.base = first_entry_offset;
         search.find_entry(name_index, name)
     })
 }
@@ -267,149 +277,77 @@ pub fn list_ibody_xattr<D: BlockDevice>(
     })?
 }
 
-/// 在 inode 内部设置 xattr
-///
-/// # 参数
-///
-/// * `inode_ref` - inode 引用（可变）
-/// * `name_index` - 命名空间索引
-/// * `name` - 属性名称（不含前缀）
-/// * `value` - 属性值（None 表示删除）
+/// 读取 inode 内部的所有 xattr
 ///
 /// # 返回
 ///
-/// 成功返回 true，空间不足返回 false
-///
-/// # 实现说明
-///
-/// 对应 lwext4 中在 inode 内部设置 xattr 的逻辑：
-/// 1. 检查空间是否足够
-/// 2. 如果足够，执行设置操作
-/// 3. 标记 inode 为脏（通过 InodeRef 自动处理）
-pub fn set_ibody_entry<D: BlockDevice>(
+/// - `Some(records)` - inode 有 xattr 空间（尚未初始化时为空列表）
+/// - `None` - inode 没有额外空间可以存放 xattr
+pub fn read_ibody_records<D: BlockDevice>(
     inode_ref: &mut InodeRef<D>,
-    name_index: u8,
-    name: &[u8],
-    value: Option<&[u8]>,
-) -> Result<bool> {
-    // 获取 header 偏移
+) -> Result<Option<Vec<XattrRecord>>> {
     let header_offset = match get_ibody_header_offset(inode_ref)? {
         Some(offset) => offset,
-        None => return Ok(false), // 没有 extra_isize，无法设置
+        None => return Ok(None),
     };
 
     let inode_size = inode_ref.superblock().inode_size() as usize;
+    let first_entry_offset = get_first_entry_offset(header_offset);
+    if first_entry_offset + size_of::<u32>() > inode_size {
+        return Ok(None);
+    }
 
-    // 使用 with_inode_raw_data_mut 修改数据
-    inode_ref.with_inode_raw_data_mut(|inode_data| -> Result<bool> {
-        let first_entry_offset = get_first_entry_offset(header_offset);
-
-        // 使用 XattrSearch 查找现有 entry
-        let mut search = XattrSearch::new(inode_data, first_entry_offset);
-        let found = search.find_entry(name_index, name);
-
-        // 调用内部设置函数
-        set_entry_impl(
-            inode_data,
-            first_entry_offset,
-            inode_size,
-            name_index,
-            name,
-            value,
-            found,
-        )
+    inode_ref.with_inode_raw_data(|inode_data| {
+        if !has_ibody_magic(inode_data, header_offset) {
+            return Ok(Some(Vec::new()));
+        }
+        read_records(inode_data, first_entry_offset, first_entry_offset).map(Some)
     })?
 }
 
-/// 内部实现：在给定的数据缓冲区中设置 xattr entry
-///
-/// 这是一个简单的适配器，调用 `write::set_entry_in_memory` 来完成实际的修改工作。
-///
-/// 对应 C 实现的 ext4_xattr_set_entry
-///
-/// # 参数
+/// 用 `records` 替换 inode 内部的所有 xattr
 ///
-/// * `data` - xattr 数据缓冲区（可变）
-/// * `first_entry_offset` - 第一个 entry 的偏移
-/// * `data_end` - 数据区结束偏移
-/// * `name_index` - 命名空间索引
-/// * `name` - 属性名称（不含前缀）
-/// * `value` - 属性值（None 表示删除）
-/// * `_found` - 查找结果（未使用，set_entry_in_memory 会自己查找）
+/// 按规范顺序写入，必要时写入 header 魔数。
 ///
 /// # 返回
 ///
-/// * Ok(true) - 成功设置
-/// * Ok(false) - 空间不足
-/// * Err(_) - 其他错误
-fn set_entry_impl(
-    data: &mut [u8],
-    first_entry_offset: usize,
-    data_end: usize,
-    name_index: u8,
-    name: &[u8],
-    value: Option<&[u8]>,
-    _found: Option<(usize, usize, u32)>,
+/// - `true` - 写入成功
+/// - `false` - inode 没有 xattr 空间或空间不足，inode 未被修改
+pub fn write_ibody_records<D: BlockDevice>(
+    inode_ref: &mut InodeRef<D>,
+    records: &mut [XattrRecord],
 ) -> Result<bool> {
-    // 直接调用 write.rs 中的完整实现
-    use super::write::set_entry_in_memory;
-
-    match set_entry_in_memory(
-        data,
-        first_entry_offset,
-        data_end,
-        name_index,
-        name,
-        value,
-        false,  // dry_run = false，执行实际修改
-    ) {
-        Ok(()) => Ok(true),  // 成功
-        Err(e) if e.kind() == ErrorKind::NoSpace => Ok(false),  // 空间不足
-        Err(e) => Err(e),  // 其他错误
-    }
-}
-
-/// 初始化 inode 内部 xattr 区域
-///
-/// 对应 lwext4 的 `ext4_xattr_ibody_initialize()`
-///
-/// # 参数
-///
-/// * `inode_ref` - inode 引用（可变）
-///
-/// # 返回
-///
-/// 成功返回 Ok(())
-pub fn initialize_ibody_xattr<D: BlockDevice>(
-    inode_ref: &mut InodeRef<D>
-) -> Result<()> {
     let header_offset = match get_ibody_header_offset(inode_ref)? {
         Some(offset) => offset,
-        None => return Ok(()), // 没有 extra_isize，无需初始化
+        None => return Ok(false),
     };
 
     let inode_size = inode_ref.superblock().inode_size() as usize;
+    let first_entry_offset = get_first_entry_offset(header_offset);
+    if first_entry_offset + records_space(records) > inode_size {
+        return Ok(false);
+    }
+
+    // 没有属性且从未初始化时不必弄脏 inode
+    let initialized = inode_ref.with_inode_raw_data(|inode_data| has_ibody_magic(inode_data, header_offset))?;
+    if records.is_empty() && !initialized {
+        return Ok(true);
+    }
 
-    // 使用 with_inode_raw_data_mut 修改数据（自动标记为脏）
     inode_ref.with_inode_raw_data_mut(|inode_data| {
-        // 计算 xattr 区域的范围
-        // xattr 区域从 header_offset 开始，到 inode 末尾结束
-        let xattr_area_start = header_offset;
-        let xattr_area_end = inode_size;
-
-        if xattr_area_start < xattr_area_end {
-            // 清零 xattr 区域
-            let xattr_area = &mut inode_data[xattr_area_start..xattr_area_end];
-            for byte in xattr_area.iter_mut() {
-                *byte = 0;
-            }
+        let area = &mut inode_data[..inode_size];
+        area[header_offset..first_entry_offset].copy_from_slice(&EXT4_XATTR_MAGIC.to_le_bytes());
+        write_records(area, first_entry_offset, first_entry_offset, records, false)
+    })??;
 
-            // 设置 header 魔数
-            let header_bytes = &mut inode_data[header_offset..header_offset + size_of::<ext4_xattr_ibody_header>()];
-            let magic_bytes = EXT4_XATTR_MAGIC.to_le_bytes();
-            header_bytes[0..4].copy_from_slice(&magic_bytes);
-        }
-    })
+    Ok(true)
+}
+
+/// inode 内部 xattr header 的魔数是否有效
+fn has_ibody_magic(inode_data: &[u8], header_offset: usize) -> bool {
+    inode_data
+        .get(header_offset..header_offset + size_of::<ext4_xattr_ibody_header>())
+        .is_some_and(|magic| magic == EXT4_XATTR_MAGIC.to_le_bytes())
 }
 
 #[cfg(test)]
@@ -16,12 +16,16 @@
 //! 1. **Inode 内部** - 存储在 inode 的额外空间中（快速访问）
 //! 2. **独立块** - 存储在独立的 xattr 块中（通过 inode->file_acl 指向）
 //!
+//! 启用 EA_INODE 特性时，超过一个块的值（最大 64KB）存放在专用的 EA inode 中，
+//! 内容相同的值共享同一个 EA inode 并按引用计数释放。
+//!
 //! # 功能特性
 //!
 //! - ✅ 命名空间前缀解析
 //! - ✅ inode 内部 xattr 操作
 //! - ✅ xattr 块操作
 //! - ✅ 块共享和引用计数（COW）
+//! - ✅ 大属性值存放在独立 inode（EA_INODE）
 //! - ✅ 哈希计算
 //! - ✅ CRC32C 校验和
 //!
@@ -34,6 +38,11 @@
 //! let mut list = Vec::new();
 //! xattr::list(&inode_ref, &mut list)?;
 //!
+//! // 遍历 user 命名空间中的属性
+//! for entry in xattr::iter_namespace(&mut inode_ref, XattrNamespace::User)? {
+//!     println!("{}: {} bytes", entry.name, entry.value_size);
+//! }
+//!
 //! // 获取属性值
 //! let mut buf = vec![0u8; 256];
 //! let len = xattr::get(&inode_ref, "user.comment", &mut buf)?;
@@ -53,17 +62,39 @@
 //! - ✅ inode 内部操作（ibody.rs）- 100% 完成 + 2个测试
 //! - ✅ 块操作（block.rs）- 100% 完成 + 3个测试
 //! - ✅ 写操作逻辑（write.rs）- 100% 完成 + 5个测试
-//! - ✅ 公共 API（api.rs）- 完整实现（list/get/set/remove）
+//! - ✅ EA inode（ea_inode.rs）- 大值存储、引用计数和去重 + 3个测试
+//! - ✅ 公共 API（api.rs）- 完整实现（list/iter/get/get_size/set/remove）
 //!
 //! **总体完成度**: 100% (核心功能完整)
 
+mod entry;
+#[cfg(feature = "xattr")]
 mod prefix;
+#[cfg(feature = "xattr")]
 mod search;
+#[cfg(feature = "xattr")]
 mod hash;
+#[cfg(feature = "xattr")]
 mod ibody;
+#[cfg(feature = "xattr")]
 mod block;
+#[cfg(feature = "xattr")]
 mod write;
+#[cfg(feature = "xattr")]
+mod ea_inode;
+#[cfg(feature = "xattr")]
 mod api;
+#[cfg(not(feature = "xattr"))]
+mod stub;
 
-pub use api::{list, get, set, remove};
+pub use entry::{XattrEntry, XattrNamespace};
+#[cfg(feature = "xattr")]
+pub use api::{list, get, get_size, iter, iter_namespace, set, remove};
+#[cfg(feature = "xattr")]
+pub(crate) use api::{get_indexed, release_all};
+#[cfg(feature = "xattr")]
 pub use prefix::{extract_xattr_name, get_xattr_name_prefix};
+#[cfg(not(feature = "xattr"))]
+pub use stub::{list, get, get_size, iter, iter_namespace, set, remove};
+#[cfg(not(feature = "xattr"))]
+pub(crate) use stub::{get_indexed, release_all};
@@ -6,7 +6,9 @@ use crate::{
     consts::*,
     types::ext4_xattr_entry,
 };
-use core::mem::size_of;
+use core::{cmp::Ordering, mem::size_of};
+
+use super::write::compare_key;
 
 /// xattr 搜索上下文
 ///
@@ -18,7 +20,7 @@ pub struct XattrSearch<'a> {
     /// 第一个 entry
     pub first: usize,
 
-    /// buffer 起始地址（相对偏移）
+    /// value 偏移的基准（xattr 块为 0，inode 内部为第一个 entry 的偏移）
     pub base: usize,
 
     /// buffer 结束地址（相对偏移）
@@ -30,6 +32,9 @@ pub struct XattrSearch<'a> {
     /// 是否未找到
     pub not_found: bool,
 
+    /// 条目是否按规范顺序排列（见 [`compare_key`]），为真时查找可以提前结束
+    pub sorted: bool,
+
     /// xattr 数据引用
     data: &'a [u8],
 }
@@ -52,6 +57,7 @@ impl<'a> XattrSearch<'a> {
             end: data.len(),
             here: None,
             not_found: true,
+            sorted: false,
             data,
         }
     }
@@ -98,6 +104,13 @@ impl<'a> XattrSearch<'a> {
 
             let entry_name = &self.data[name_offset..name_offset + entry_name_len];
 
+            // 有序时越过目标位置即可确定不存在
+            if self.sorted
+                && compare_key(entry.e_name_index, entry_name, name_index, name) == Ordering::Greater
+            {
+                break;
+            }
+
             // 比较 name_index 和 name
             if entry_name_len == name.len()
                 && entry.e_name_index == name_index
@@ -109,7 +122,7 @@ impl<'a> XattrSearch<'a> {
 
                 let value_size = entry.value_size();
                 let value_offset = if value_size > 0 {
-                    u16::from_le(entry.e_value_offs) as usize
+                    self.base + u16::from_le(entry.e_value_offs) as usize
                 } else {
                     0
                 };
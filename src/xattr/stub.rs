@@ -0,0 +1,74 @@
+//! 关闭 `xattr` feature 时的占位实现
+//!
+//! 保留与完整实现相同的函数签名，除删除 inode 时的清理外，所有操作都返回
+//! `ErrorKind::Unsupported`。
+
+use crate::{
+    block::BlockDevice,
+    error::{Error, ErrorKind, Result},
+    fs::InodeRef,
+};
+
+use super::entry::{XattrEntry, XattrNamespace};
+
+const DISABLED: Error = Error::new(
+    ErrorKind::Unsupported,
+    "Extended attribute support is disabled (enable the `xattr` feature)",
+);
+
+/// 列出扩展属性（未启用）
+pub fn list<D: BlockDevice>(_inode_ref: &mut InodeRef<D>, _buffer: &mut [u8]) -> Result<usize> {
+    Err(DISABLED)
+}
+
+/// 遍历扩展属性（未启用）
+pub fn iter<D: BlockDevice>(_inode_ref: &mut InodeRef<D>) -> Result<impl Iterator<Item = XattrEntry>> {
+    Err::<core::iter::Empty<XattrEntry>, _>(DISABLED)
+}
+
+/// 遍历指定命名空间中的扩展属性（未启用）
+pub fn iter_namespace<D: BlockDevice>(
+    _inode_ref: &mut InodeRef<D>,
+    _namespace: XattrNamespace,
+) -> Result<impl Iterator<Item = XattrEntry>> {
+    Err::<core::iter::Empty<XattrEntry>, _>(DISABLED)
+}
+
+/// 获取扩展属性值的长度（未启用）
+pub fn get_size<D: BlockDevice>(_inode_ref: &mut InodeRef<D>, _name: &str) -> Result<usize> {
+    Err(DISABLED)
+}
+
+/// 获取扩展属性（未启用）
+pub fn get<D: BlockDevice>(
+    _inode_ref: &mut InodeRef<D>,
+    _name: &str,
+    _buffer: &mut [u8],
+) -> Result<usize> {
+    Err(DISABLED)
+}
+
+/// 按命名空间索引获取扩展属性（未启用）
+pub(crate) fn get_indexed<D: BlockDevice>(
+    _inode_ref: &mut InodeRef<D>,
+    _name_index: u8,
+    _name_bytes: &[u8],
+    _buffer: &mut [u8],
+) -> Result<usize> {
+    Err(DISABLED)
+}
+
+/// 设置扩展属性（未启用）
+pub fn set<D: BlockDevice>(_inode_ref: &mut InodeRef<D>, _name: &str, _value: &[u8]) -> Result<()> {
+    Err(DISABLED)
+}
+
+/// 删除扩展属性（未启用）
+pub fn remove<D: BlockDevice>(_inode_ref: &mut InodeRef<D>, _name: &str) -> Result<()> {
+    Err(DISABLED)
+}
+
+/// 释放 inode 的所有扩展属性（未启用时什么也不做）
+pub(crate) fn release_all<D: BlockDevice>(_inode_ref: &mut InodeRef<D>) -> Result<()> {
+    Ok(())
+}
@@ -5,11 +5,16 @@
 use crate::{
     consts::*,
     error::{Error, ErrorKind, Result},
+    parse,
     types::ext4_xattr_entry,
 };
-use core::mem::size_of;
+use alloc::vec::Vec;
+use core::{cmp::Ordering, mem::size_of};
 
-use super::search::XattrSearch;
+use super::{
+    hash::{compute_block_hash, compute_entry_hash},
+    search::XattrSearch,
+};
 
 /// 计算 entry 的总大小（包括名称和对齐）
 ///
@@ -332,6 +337,173 @@ pub fn set_entry_in_memory(
     Ok(())
 }
 
+/// 一个完整的扩展属性（名称和值）
+///
+/// 写入时先把存储区解析为记录列表，修改后再按规范顺序整体写回，
+/// 因此同一组属性总是序列化为相同的字节
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct XattrRecord {
+    /// 命名空间索引
+    pub name_index: u8,
+    /// 属性名称（不含前缀）
+    pub name: Vec<u8>,
+    /// 属性值（值存放在 EA inode 中时为空）
+    pub value: Vec<u8>,
+    /// 值存放在独立 inode 中时的引用
+    pub ea: Option<EaValue>,
+}
+
+/// 存放在 EA inode 中的值
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct EaValue {
+    /// 存放值的 inode 号（`e_value_inum`）
+    pub inum: u32,
+    /// 值的长度
+    pub size: u32,
+    /// entry 哈希（名称哈希混合值哈希），见 `hash::compute_ea_entry_hash`
+    pub entry_hash: u32,
+}
+
+impl XattrRecord {
+    /// 是否是指定的属性
+    pub fn matches(&self, name_index: u8, name: &[u8]) -> bool {
+        self.name_index == name_index && self.name == name
+    }
+
+    /// 占用的字节数（entry 头、名称和对齐后的 value）
+    ///
+    /// 值在 EA inode 中时只占 entry 头和名称
+    fn space(&self) -> usize {
+        match self.ea {
+            Some(_) => entry_len(self.name.len()),
+            None => entry_len(self.name.len()) + value_size(self.value.len()),
+        }
+    }
+}
+
+/// 条目的规范顺序
+///
+/// 与内核 xattr 块中的顺序一致：先按命名空间索引，再按名称长度，最后按名称字节
+pub fn compare_key(a_index: u8, a_name: &[u8], b_index: u8, b_name: &[u8]) -> Ordering {
+    a_index
+        .cmp(&b_index)
+        .then(a_name.len().cmp(&b_name.len()))
+        .then_with(|| a_name.cmp(b_name))
+}
+
+/// 存放 `records` 需要的字节数（含 4 字节结束标记）
+pub fn records_space(records: &[XattrRecord]) -> usize {
+    records.iter().map(XattrRecord::space).sum::<usize>() + size_of::<u32>()
+}
+
+/// 解析数据区中的所有条目
+///
+/// # 参数
+///
+/// * `data` - xattr 存储区（inode 原始数据或整个 xattr 块）
+/// * `first_offset` - 第一个 entry 的偏移
+/// * `value_base` - `e_value_offs` 的基准偏移（块为 0，inode 内部为第一个 entry）
+///
+/// # 错误
+///
+/// - `ErrorKind::Corrupted` - entry 或 value 越界，或 value 与 entry 区域重叠
+///
+/// value 存放在独立 inode 中（EA_INODE）的条目只记录引用，不读取值
+pub fn read_records(data: &[u8], first_offset: usize, value_base: usize) -> Result<Vec<XattrRecord>> {
+    let entries = parse::parse_xattr_entries(data, first_offset, value_base)?;
+    Ok(entries
+        .into_iter()
+        .map(|entry| XattrRecord {
+            name_index: entry.name_index,
+            name: entry.name.to_vec(),
+            value: entry.value.to_vec(),
+            ea: (entry.value_inum != 0).then_some(EaValue {
+                inum: entry.value_inum,
+                size: entry.value_size,
+                entry_hash: entry.hash,
+            }),
+        })
+        .collect())
+}
+
+/// 按规范顺序写入所有条目
+///
+/// entry 从 `first_offset` 向后排列，value 按 entry 顺序从数据区末尾向前排列，
+/// `first_offset` 之后的其他字节全部清零。`hashed` 为真时（xattr 块）计算每个
+/// entry 的 `e_hash`，否则与内核的 inode 内部条目一样写 0。
+/// 值在 EA inode 中的条目总是写入记录的 entry 哈希，e2fsck 用它确认引用。
+///
+/// # 返回
+///
+/// 块哈希（写入 header 的 `h_hash`）
+///
+/// # 错误
+///
+/// - `ErrorKind::NoSpace` - 空间不足，此时数据区不会被修改
+pub fn write_records(
+    data: &mut [u8],
+    first_offset: usize,
+    value_base: usize,
+    records: &mut [XattrRecord],
+    hashed: bool,
+) -> Result<u32> {
+    if first_offset + records_space(records) > data.len() {
+        return Err(Error::new(ErrorKind::NoSpace, "no space for xattr entry"));
+    }
+
+    records.sort_by(|a, b| compare_key(a.name_index, &a.name, b.name_index, &b.name));
+    data[first_offset..].fill(0);
+
+    let mut hashes = Vec::with_capacity(records.len());
+    let mut offset = first_offset;
+    let mut value_end = data.len();
+
+    for record in records.iter() {
+        let mut entry = ext4_xattr_entry {
+            e_name_len: record.name.len() as u8,
+            e_name_index: record.name_index,
+            e_value_offs: 0,
+            e_value_block: 0,
+            e_value_size: (record.value.len() as u32).to_le(),
+            e_hash: 0,
+        };
+
+        if let Some(ea) = record.ea {
+            entry.e_value_block = ea.inum.to_le();
+            entry.e_value_size = ea.size.to_le();
+            entry.e_hash = ea.entry_hash.to_le();
+            if hashed {
+                hashes.push(ea.entry_hash);
+            }
+        } else if !record.value.is_empty() {
+            value_end -= value_size(record.value.len());
+            data[value_end..value_end + record.value.len()].copy_from_slice(&record.value);
+            entry.e_value_offs = ((value_end - value_base) as u16).to_le();
+        }
+
+        if hashed && record.ea.is_none() {
+            let value = &data[value_end..value_end + value_size(record.value.len())];
+            let hash = compute_entry_hash(&entry, &record.name, Some(value));
+            entry.e_hash = hash.to_le();
+            hashes.push(hash);
+        }
+
+        let name_start = offset + size_of::<ext4_xattr_entry>();
+        data[offset] = entry.e_name_len;
+        data[offset + 1] = entry.e_name_index;
+        // 字段已经是小端编码
+        data[offset + 2..offset + 4].copy_from_slice(&entry.e_value_offs.to_ne_bytes());
+        data[offset + 4..offset + 8].copy_from_slice(&entry.e_value_block.to_ne_bytes());
+        data[offset + 8..offset + 12].copy_from_slice(&entry.e_value_size.to_ne_bytes());
+        data[offset + 12..offset + 16].copy_from_slice(&entry.e_hash.to_ne_bytes());
+        data[name_start..name_start + record.name.len()].copy_from_slice(&record.name);
+
+        offset += entry_len(record.name.len());
+    }
+
+    Ok(compute_block_hash(&hashes))
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -425,4 +597,69 @@ mod tests {
 
         assert!(result.is_ok());
     }
+
+    fn record(name_index: u8, name: &[u8], value: &[u8]) -> XattrRecord {
+        XattrRecord {
+            name_index,
+            name: name.to_vec(),
+            value: value.to_vec(),
+            ea: None,
+        }
+    }
+
+    #[test]
+    fn test_write_records_roundtrip() {
+        let mut data = vec![0xffu8; 256];
+        let mut records = vec![
+            record(1, b"bb", b"two"),
+            record(6, b"a", b""),
+            record(1, b"a", b"one"),
+        ];
+
+        write_records(&mut data, 32, 32, &mut records, false).unwrap();
+
+        // 按 (索引, 名称长度, 名称) 排序，值相对于 value_base
+        let read = read_records(&data, 32, 32).unwrap();
+        assert_eq!(read, [record(1, b"a", b"one"), record(1, b"bb", b"two"), record(6, b"a", b"")]);
+        assert_eq!(&data[..32], &[0xffu8; 32]);
+    }
+
+    #[test]
+    fn test_write_records_deterministic() {
+        let mut first = vec![0u8; 128];
+        let mut second = vec![0u8; 128];
+        let mut a = vec![record(1, b"x", b"1"), record(1, b"y", b"22")];
+        let mut b = vec![record(1, b"y", b"22"), record(1, b"x", b"1")];
+
+        let hash_a = write_records(&mut first, 32, 0, &mut a, true).unwrap();
+        let hash_b = write_records(&mut second, 32, 0, &mut b, true).unwrap();
+
+        assert_eq!(first, second);
+        assert_eq!(hash_a, hash_b);
+        assert_ne!(hash_a, 0);
+    }
+
+    #[test]
+    fn test_write_records_ea_inode() {
+        let mut data = vec![0u8; 128];
+        let ea = EaValue { inum: 12, size: 70000, entry_hash: 0x1234_5678 };
+        let mut records = vec![XattrRecord { ea: Some(ea), ..record(6, b"selinux", b"") }];
+
+        // EA 条目不占 value 空间，即使不计算哈希也写入 entry 哈希
+        write_records(&mut data, 32, 32, &mut records, false).unwrap();
+        assert_eq!(&data[32 + 4..32 + 8], &12u32.to_le_bytes());
+        assert_eq!(&data[32 + 12..32 + 16], &0x1234_5678u32.to_le_bytes());
+        assert_eq!(&data[32 + 2..32 + 4], &[0, 0]);
+        assert_eq!(read_records(&data, 32, 32).unwrap(), records);
+    }
+
+    #[test]
+    fn test_write_records_no_space() {
+        let mut data = vec![7u8; 64];
+        let mut records = vec![record(1, b"big", &[0u8; 64])];
+
+        let err = write_records(&mut data, 0, 0, &mut records, false).unwrap_err();
+        assert_eq!(err.kind(), ErrorKind::NoSpace);
+        assert_eq!(data, vec![7u8; 64]);
+    }
 }